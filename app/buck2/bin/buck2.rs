@@ -119,6 +119,7 @@ fn main(init: fbinit::FacebookInit) -> ! {
     buck2_configured::init_late_bindings();
     buck2_query_impls::init_late_bindings();
     buck2_interpreter_for_build::init_late_bindings();
+    buck2_server::init_late_bindings();
     buck2_server_commands::init_late_bindings();
     buck2_test::init_late_bindings();
     buck2_external_cells::init_late_bindings();
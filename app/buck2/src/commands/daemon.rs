@@ -250,6 +250,7 @@ impl DaemonCommand {
             Self::daemonize(stdout, stderr)?;
 
             fs_util::write(&pid_path, format!("{}", process::id()))?;
+            daemon_dir.claim_ownership(BuckVersion::get().unique_id())?;
 
             let pid = process::id();
             let process_info = DaemonProcessInfo {
@@ -268,6 +269,7 @@ impl DaemonCommand {
             (listener, process_info, endpoint)
         } else {
             fs_util::write(&pid_path, format!("{}", process::id()))?;
+            daemon_dir.claim_ownership(BuckVersion::get().unique_id())?;
 
             if !in_process {
                 Self::redirect_output(stdout, stderr)?;
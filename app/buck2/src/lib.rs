@@ -141,6 +141,11 @@ struct BeforeSubcommandOptions {
     // NO_BUCKD=1 for buck1.
     no_buckd: bool,
 
+    /// Fail the command instead of restarting the daemon when it does not match this client
+    /// (e.g. it runs a different buck2 version or was started with a different config).
+    #[clap(env("BUCK2_NO_AUTO_RESTART"), long, global(true))]
+    no_auto_restart: bool,
+
     /// Print buck wrapper help.
     #[clap(skip)] // @oss-enable
     // @oss-disable: #[clap(long)]
@@ -343,6 +348,7 @@ impl CommandKind {
             runtime: &runtime,
             oncall: common_opts.oncall,
             client_metadata: common_opts.client_metadata,
+            no_auto_restart: common_opts.no_auto_restart,
         };
 
         match self {
@@ -10,6 +10,7 @@
 use anyhow::Context;
 use buck2_client::commands::kill::kill_command_impl;
 use buck2_client_ctx::daemon::client::connect::buckd_startup_timeout;
+use buck2_client_ctx::daemon::client::kill::GRACEFUL_SHUTDOWN_TIMEOUT;
 use buck2_client_ctx::daemon::client::BuckdLifecycleLock;
 use buck2_client_ctx::startup_deadline::StartupDeadline;
 use buck2_common::invocation_paths::InvocationPaths;
@@ -38,7 +39,12 @@ pub(crate) fn start_in_process_daemon(
         .await
         .with_context(|| "Error locking buckd lifecycle.lock")?;
 
-        kill_command_impl(&lifecycle_lock, "A command with `--no-buckd` is invoked").await
+        kill_command_impl(
+            &lifecycle_lock,
+            "A command with `--no-buckd` is invoked",
+            GRACEFUL_SHUTDOWN_TIMEOUT,
+        )
+        .await
     })?;
 
     let daemon_startup_config = daemon_startup_config.clone();
@@ -16,6 +16,7 @@ use buck2_execute::artifact_value::ArtifactValue;
 use buck2_execute::directory::INTERNER;
 use buck2_execute::entry::build_entry_from_disk;
 use buck2_execute::materialize::materializer::CopiedArtifact;
+use buck2_execute::materialize::physical_roots::PhysicalRoots;
 use dupe::Dupe;
 
 /// Declares a copy materialization to copy the output BuildArtifact to the
@@ -47,11 +48,12 @@ pub(crate) async fn declare_copy_from_offline_cache(
         .fs()
         .resolve_offline_output_cache_path(output.get_path());
 
+    let roots = PhysicalRoots::unchecked(ctx.fs().fs(), ctx.fs().buck_out_path_resolver().root());
     let (value, _hashing_time) = build_entry_from_disk(
         ctx.fs().fs().resolve(&offline_cache_path),
         FileDigestConfig::build(ctx.digest_config().cas_digest_config()),
         ctx.blocking_executor(),
-        ctx.fs().fs().root(),
+        &roots,
     )
     .await?;
 
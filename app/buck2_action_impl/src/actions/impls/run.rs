@@ -172,6 +172,7 @@ pub(crate) struct UnregisteredRunAction {
     pub(crate) allow_dep_file_cache_upload: bool,
     pub(crate) force_full_hybrid_if_capable: bool,
     pub(crate) unique_input_inodes: bool,
+    pub(crate) streamed_inputs: bool,
     pub(crate) remote_execution_dependencies: Vec<RemoteExecutorDependency>,
 }
 
@@ -261,6 +262,9 @@ impl RunAction {
                     ValueAsCommandLineLike::unpack_value_err(v)?.0,
                 ));
             }
+            // The order of environment variables is never significant, so expand them (and visit
+            // their artifacts) in key order rather than in the order the rule built the dict in.
+            res.sort_by_key(|(k, _)| *k);
             res
         };
         let worker: NoneOr<&WorkerInfo> = values.worker()?.typed;
@@ -652,6 +656,7 @@ impl IncrementalActionExecutable for RunAction {
             .with_local_environment_inheritance(EnvironmentInheritance::local_command_exclusions())
             .with_force_full_hybrid_if_capable(self.inner.force_full_hybrid_if_capable)
             .with_unique_input_inodes(self.inner.unique_input_inodes)
+            .with_streamed_inputs(self.inner.streamed_inputs)
             .with_remote_execution_dependencies(self.inner.remote_execution_dependencies.clone());
 
         let (mut dep_file_bundle, req) = if let Some(visitor) = dep_file_visitor {
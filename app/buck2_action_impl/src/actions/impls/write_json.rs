@@ -23,6 +23,7 @@ use buck2_build_api::actions::execute::action_executor::ActionOutputs;
 use buck2_build_api::actions::execute::error::ExecuteError;
 use buck2_build_api::actions::impls::json;
 use buck2_build_api::actions::impls::json::validate_json;
+use buck2_build_api::actions::impls::json::JsonDictOrder;
 use buck2_build_api::actions::Action;
 use buck2_build_api::actions::ActionExecutable;
 use buck2_build_api::actions::ActionExecutionCtx;
@@ -36,7 +37,9 @@ use buck2_build_api::interpreter::rule_defs::cmd_args::CommandLineArtifactVisito
 use buck2_build_api::interpreter::rule_defs::cmd_args::CommandLineBuilder;
 use buck2_build_api::interpreter::rule_defs::cmd_args::CommandLineContext;
 use buck2_build_api::interpreter::rule_defs::cmd_args::WriteToFileMacroVisitor;
+use buck2_core::buck2_env;
 use buck2_core::category::Category;
+use buck2_core::soft_error;
 use buck2_execute::artifact::fs::ExecutorFs;
 use buck2_execute::execute::command_executor::ActionExecutionTimingData;
 use buck2_execute::materialize::materializer::WriteRequest;
@@ -71,15 +74,32 @@ enum WriteJsonActionValidationError {
     TooManyOutputs,
 }
 
+#[derive(Debug, buck2_error::Error)]
+#[error(
+    "`write_json` action `{identifier}` of `{owner}` writes different JSON when its dicts are \
+    built in another order, so its digest depends on dict insertion order. \
+    Remove `sort_keys = False` unless the consumer relies on that order"
+)]
+#[buck2(input)]
+struct OrderDependentWriteJson {
+    owner: String,
+    identifier: String,
+}
+
 #[derive(Allocative, Debug)]
 pub(crate) struct UnregisteredWriteJsonAction {
     pretty: bool,
     absolute: bool,
+    sort_keys: bool,
 }
 
 impl UnregisteredWriteJsonAction {
-    pub(crate) fn new(pretty: bool, absolute: bool) -> Self {
-        Self { pretty, absolute }
+    pub(crate) fn new(pretty: bool, absolute: bool, sort_keys: bool) -> Self {
+        Self {
+            pretty,
+            absolute,
+            sort_keys,
+        }
     }
 
     pub(crate) fn cli<'v>(
@@ -100,6 +120,11 @@ impl UnregisteredAction for UnregisteredWriteJsonAction {
     ) -> anyhow::Result<Box<dyn Action>> {
         let contents = starlark_data.expect("module data to be present");
         let action = WriteJsonAction::new(contents, inputs, outputs, *self)?;
+        if buck2_env!("BUCK2_DETECT_ORDER_DEPENDENT_ACTIONS", bool)? {
+            if let Some(e) = action.order_dependence()? {
+                soft_error!("order_dependent_action", e.into())?;
+            }
+        }
         Ok(Box::new(action))
     }
 }
@@ -141,6 +166,29 @@ impl WriteJsonAction {
         })
     }
 
+    fn dict_order(&self) -> JsonDictOrder {
+        if self.inner.sort_keys {
+            JsonDictOrder::Sorted
+        } else {
+            JsonDictOrder::Insertion
+        }
+    }
+
+    /// Debugging aid for actions that defeat caching across daemons: check whether the digest of
+    /// the contents changes when its dicts are built in another order. Only possible when the rule
+    /// opted out of sorted keys.
+    fn order_dependence(&self) -> anyhow::Result<Option<OrderDependentWriteJson>> {
+        if self.dict_order() == JsonDictOrder::Sorted
+            || !json::depends_on_dict_order(self.contents.value(), self.inner.pretty)?
+        {
+            return Ok(None);
+        }
+        Ok(Some(OrderDependentWriteJson {
+            owner: self.output.get_path().owner().to_string(),
+            identifier: self.output.get_path().path().to_string(),
+        }))
+    }
+
     fn get_contents(&self, fs: &ExecutorFs) -> anyhow::Result<Vec<u8>> {
         let mut writer = Vec::new();
         json::write_json(
@@ -149,6 +197,7 @@ impl WriteJsonAction {
             &mut writer,
             self.inner.pretty,
             self.inner.absolute,
+            self.dict_order(),
         )?;
         Ok(writer)
     }
@@ -192,6 +241,7 @@ impl Action for WriteJsonAction {
                 Err(e) => format!("ERROR: constructing contents ({})", e)
             },
             "absolute".to_owned() => self.inner.absolute.to_string(),
+            "sort_keys".to_owned() => self.inner.sort_keys.to_string(),
         }
     }
 }
@@ -305,3 +355,57 @@ impl<'v, V: ValueLike<'v>> CommandLineArgLike for WriteJsonCommandLineArgGen<V>
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use buck2_artifact::artifact::artifact_type::testing::BuildArtifactTestingExt;
+    use buck2_artifact::artifact::build_artifact::BuildArtifact;
+    use buck2_artifact::deferred::id::DeferredId;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+    use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+    use indexmap::indexset;
+    use indexmap::IndexSet;
+    use starlark::values::dict::AllocDict;
+    use starlark::values::OwnedFrozenValue;
+
+    use crate::actions::impls::write_json::UnregisteredWriteJsonAction;
+    use crate::actions::impls::write_json::WriteJsonAction;
+
+    fn action(contents: OwnedFrozenValue, sort_keys: bool) -> WriteJsonAction {
+        let output = BuildArtifact::testing_new(
+            ConfiguredTargetLabel::testing_parse("cell//pkg:foo", ConfigurationData::testing_new()),
+            ForwardRelativePathBuf::unchecked_new("foo.json".to_owned()),
+            DeferredId::testing_new(0),
+        );
+        WriteJsonAction::new(
+            contents,
+            IndexSet::new(),
+            indexset![output],
+            UnregisteredWriteJsonAction::new(false, false, sort_keys),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_order_dependent_action_is_detected() {
+        let contents = OwnedFrozenValue::alloc(AllocDict([("b", 1), ("a", 2)]));
+        let e = action(contents, false).order_dependence().unwrap().unwrap();
+        assert_eq!("cell//pkg:foo", e.owner.split(' ').next().unwrap());
+        assert_eq!("foo.json", e.identifier);
+    }
+
+    #[test]
+    fn test_sorted_action_is_not_order_dependent() {
+        let contents = OwnedFrozenValue::alloc(AllocDict([("b", 1), ("a", 2)]));
+        assert!(action(contents, true).order_dependence().unwrap().is_none());
+
+        let contents = OwnedFrozenValue::alloc(AllocDict([("a", 1)]));
+        assert!(
+            action(contents, false)
+                .order_dependence()
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -16,8 +16,10 @@ use buck2_build_api::interpreter::rule_defs::artifact::starlark_declared_artifac
 use buck2_build_api::interpreter::rule_defs::context::AnalysisActions;
 use buck2_common::cas_digest::CasDigest;
 use buck2_core::execution_types::executor_config::RemoteExecutorUseCase;
+use buck2_events::dispatch::console_warning;
 use buck2_execute::execute::request::OutputType;
 use buck2_execute::materialize::http::Checksum;
+use buck2_http::url::normalize_url;
 use chrono::TimeZone;
 use chrono::Utc;
 use indexmap::indexset;
@@ -41,6 +43,16 @@ enum CasArtifactError {
     TreeAndDirectory,
 }
 
+/// Validate and normalize a URL when the download action is declared, so that malformed URLs
+/// fail during analysis and equivalent spellings of the same URL produce the same action.
+fn normalize_download_url(url: &str) -> anyhow::Result<Arc<str>> {
+    let url = normalize_url(url)?;
+    if let Some(warning) = url.credentials_warning() {
+        console_warning(warning);
+    }
+    Ok(Arc::from(url.into_string()))
+}
+
 #[starlark_module]
 pub(crate) fn analysis_actions_methods_download(methods: &mut MethodsBuilder) {
     /// Downloads a URL to an output (filename as string or output artifact). The file at the URL
@@ -59,6 +71,12 @@ pub(crate) fn analysis_actions_methods_download(methods: &mut MethodsBuilder) {
         #[starlark(require = named, default = false)] is_deferrable: bool,
         eval: &mut Evaluator<'v, '_, '_>,
     ) -> anyhow::Result<ValueTyped<'v, StarlarkDeclaredArtifact>> {
+        let url = normalize_download_url(url)?;
+        let vpnless_url = vpnless_url
+            .into_option()
+            .map(normalize_download_url)
+            .transpose()?;
+
         let mut this = this.state();
         let (declaration, output_artifact) =
             this.get_or_declare_output(eval, output, OutputType::File)?;
@@ -70,8 +88,8 @@ pub(crate) fn analysis_actions_methods_download(methods: &mut MethodsBuilder) {
             indexset![output_artifact],
             UnregisteredDownloadFileAction::new(
                 checksum,
-                Arc::from(url),
-                vpnless_url.into_option().map(Arc::from),
+                url,
+                vpnless_url,
                 is_executable,
                 is_deferrable,
             ),
@@ -143,3 +161,40 @@ pub(crate) fn analysis_actions_methods_download(methods: &mut MethodsBuilder) {
         Ok(output_value.into_declared_artifact(AssociatedArtifacts::new()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use buck2_execute::materialize::http::Checksum;
+
+    use crate::actions::impls::download_file::UnregisteredDownloadFileAction;
+    use crate::context::download::normalize_download_url;
+
+    /// Everything a download action is declared with, for two URLs given the same checksum.
+    fn declared_action(url: &str) -> anyhow::Result<String> {
+        let action = UnregisteredDownloadFileAction::new(
+            Checksum::new(Some("da39a3ee5e6b4b0d3255bfef95601890afd80709"), None)?,
+            normalize_download_url(url)?,
+            None,
+            false,
+            false,
+        );
+        Ok(format!("{:?}", action))
+    }
+
+    #[test]
+    fn test_equivalent_urls_declare_identical_actions() -> anyhow::Result<()> {
+        assert_eq!(
+            declared_action("https://host/x")?,
+            declared_action("HTTPS://Host:443/x")?
+        );
+        assert_eq!(
+            declared_action("http://host/x")?,
+            declared_action("HTTP://HOST:80/x")?
+        );
+        assert_ne!(
+            declared_action("https://host/x")?,
+            declared_action("http://host:443/x")?
+        );
+        Ok(())
+    }
+}
@@ -117,6 +117,11 @@ pub(crate) fn analysis_actions_methods_run(methods: &mut MethodsBuilder) {
     ///   Each dependency is dictionary with the following keys:
     ///     * `smc_tier`: name of the SMC tier to call by RE Scheduler.
     ///     * `id`: name of the dependency.
+    /// * `streamed_inputs`: experimental. When the action runs locally, large inputs that would be
+    ///   downloaded from the CAS may be exposed as named pipes that stream their content, instead
+    ///   of being materialized. Only set this for commands that read each of their inputs once,
+    ///   from start to end: if an input is opened again or not read to the end, it is materialized
+    ///   and the command runs again.
     ///
     /// When actions execute, they'll do so from the root of the repository. As they execute,
     /// actions have exclusive access to their output directory.
@@ -152,6 +157,7 @@ pub(crate) fn analysis_actions_methods_run(methods: &mut MethodsBuilder) {
             Either<ValueOf<'v, &'v WorkerRunInfo<'v>>, ValueOf<'v, &'v RunInfo<'v>>>,
         >,
         #[starlark(require = named, default = false)] unique_input_inodes: bool,
+        #[starlark(require = named, default = false)] streamed_inputs: bool,
         #[starlark(require = named)] error_handler: Option<StarlarkCallable<'v>>,
         eval: &mut Evaluator<'v, '_, '_>,
         #[starlark(require = named, default=UnpackList::default())]
@@ -339,6 +345,7 @@ pub(crate) fn analysis_actions_methods_run(methods: &mut MethodsBuilder) {
             allow_dep_file_cache_upload,
             force_full_hybrid_if_capable,
             unique_input_inodes,
+            streamed_inputs,
             remote_execution_dependencies: re_dependencies,
         };
         this.state().register_action(
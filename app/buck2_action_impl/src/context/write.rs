@@ -69,6 +69,10 @@ pub(crate) fn analysis_actions_methods_write(methods: &mut MethodsBuilder) {
     ///   rendering artifact paths. You generally shouldn't use this if you plan to use this action
     ///   as the input for anything else, as this would effectively result in losing all shared
     ///   caching. (defaults to `False`)
+    /// * `sort_keys` (optional): write the entries of dictionaries sorted by key, so the output
+    ///   (and the digest of any action reading it) doesn't depend on the order the dictionaries were
+    ///   built in. Set it to `False` only if the consumer relies on insertion order. (defaults to
+    ///   `True`)
     fn write_json<'v>(
         this: &AnalysisActions<'v>,
         #[starlark(require = pos)] output: OutputArtifactArg<'v>,
@@ -76,6 +80,7 @@ pub(crate) fn analysis_actions_methods_write(methods: &mut MethodsBuilder) {
         #[starlark(require = named, default = false)] with_inputs: bool,
         #[starlark(require = named, default = false)] pretty: bool,
         #[starlark(require = named, default = false)] absolute: bool,
+        #[starlark(require = named, default = true)] sort_keys: bool,
         eval: &mut Evaluator<'v, '_, '_>,
     ) -> anyhow::Result<impl AllocValue<'v>> {
         let mut this = this.state();
@@ -86,7 +91,7 @@ pub(crate) fn analysis_actions_methods_write(methods: &mut MethodsBuilder) {
         this.register_action(
             IndexSet::new(),
             indexset![output_artifact],
-            UnregisteredWriteJsonAction::new(pretty, absolute),
+            UnregisteredWriteJsonAction::new(pretty, absolute, sort_keys),
             Some(content),
             None,
         )?;
@@ -219,3 +219,49 @@ fn declare_output_require_bound() -> anyhow::Result<()> {
         ),
     })
 }
+
+#[test]
+fn download_file_duplicate_scheme() -> anyhow::Result<()> {
+    let content = indoc!(
+        r#"
+         def test(c):
+             return c.actions.download_file(
+                 "out",
+                 "https://https://example.com/foo",
+                 sha1 = "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+             )
+         "#
+    );
+
+    let expect = "contains a second scheme";
+    run_ctx_test(content, |ret| match ret {
+        Err(e) if e.to_string().contains(expect) => Ok(()),
+        _ => panic!(
+            "Expected a specific failure containing `{}`, got {:?}",
+            expect, ret
+        ),
+    })
+}
+
+#[test]
+fn download_file_unsupported_scheme() -> anyhow::Result<()> {
+    let content = indoc!(
+        r#"
+         def test(c):
+             return c.actions.download_file(
+                 "out",
+                 "ftp://example.com/foo",
+                 sha1 = "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+             )
+         "#
+    );
+
+    let expect = "unsupported scheme `ftp`";
+    run_ctx_test(content, |ret| match ret {
+        Err(e) if e.to_string().contains(expect) => Ok(()),
+        _ => panic!(
+            "Expected a specific failure containing `{}`, got {:?}",
+            expect, ret
+        ),
+    })
+}
@@ -21,6 +21,7 @@ use buck2_build_api::analysis::calculation::EVAL_ANALYSIS_QUERY;
 use buck2_build_api::analysis::calculation::RULE_ANALYSIS_CALCULATION;
 use buck2_build_api::analysis::AnalysisResult;
 use buck2_build_api::keep_going::KeepGoing;
+use buck2_common::target_progress::HasTargetProgress;
 use buck2_core::configuration::compatibility::MaybeCompatible;
 use buck2_core::provider::label::ConfiguredProvidersLabel;
 use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
@@ -104,9 +105,15 @@ impl RuleAnalsysisCalculationImpl for RuleAnalysisCalculationInstance {
             }
         }
 
-        ctx.compute(&AnalysisKey(target.dupe()))
+        let res = ctx
+            .compute(&AnalysisKey(target.dupe()))
             .await?
-            .map_err(anyhow::Error::from)
+            .map_err(anyhow::Error::from)?;
+        // Counted here rather than in `compute` so that cached analysis results count too.
+        if let Some(target_progress) = ctx.per_transaction_data().get_target_progress() {
+            target_progress.target_analyzed(target);
+        }
+        Ok(res)
     }
 }
 
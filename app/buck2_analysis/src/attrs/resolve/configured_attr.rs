@@ -23,7 +23,6 @@ use buck2_node::attrs::attr_type::dep::DepAttrType;
 use buck2_node::attrs::attr_type::source::SourceAttrType;
 use buck2_node::attrs::attr_type::split_transition_dep::SplitTransitionDepAttrType;
 use buck2_node::attrs::configured_attr::ConfiguredAttr;
-use buck2_node::visibility::VisibilityPatternList;
 use buck2_node::visibility::VisibilitySpecification;
 use buck2_node::visibility::WithinViewSpecification;
 use dupe::Dupe;
@@ -225,12 +224,9 @@ impl ConfiguredAttrExt for ConfiguredAttr {
             ConfiguredAttr::None => Value::new_none(),
             ConfiguredAttr::OneOf(box l, _) => l.to_value(pkg, heap)?,
             ConfiguredAttr::Visibility(VisibilitySpecification(specs))
-            | ConfiguredAttr::WithinView(WithinViewSpecification(specs)) => match specs {
-                VisibilityPatternList::Public => heap.alloc(AllocList(["PUBLIC"])),
-                VisibilityPatternList::List(specs) => {
-                    heap.alloc(AllocList(specs.iter().map(|s| s.to_string())))
-                }
-            },
+            | ConfiguredAttr::WithinView(WithinViewSpecification(specs)) => {
+                heap.alloc(AllocList(specs.to_strings()))
+            }
             ConfiguredAttr::ExplicitConfiguredDep(d) => heap.alloc(
                 StarlarkConfiguredProvidersLabel::new(d.as_ref().label.clone()),
             ),
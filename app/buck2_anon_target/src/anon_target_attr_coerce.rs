@@ -137,6 +137,7 @@ impl AnonTargetAttrTypeCoerce for AttrType {
                     Ok(AnonTargetAttr::PromiseArtifact(PromiseArtifactAttr {
                         id: promise_artifact.artifact.id.as_ref().clone(),
                         short_path: promise_artifact.short_path.clone(),
+                        declaration_location: promise_artifact.declaration_location.clone(),
                     }))
                 } else if let Some(artifact_like) = ValueAsArtifactLike::unpack_value(value) {
                     let artifact = artifact_like.0.get_bound_artifact()?;
@@ -17,8 +17,8 @@ use buck2_analysis::attrs::resolve::attr_type::dep::DepAttrTypeExt;
 use buck2_analysis::attrs::resolve::ctx::AttrResolutionContext;
 use buck2_artifact::artifact::artifact_type::Artifact;
 use buck2_build_api::analysis::calculation::RuleAnalysisCalculation;
+use buck2_build_api::artifact_groups::promise::check_short_path;
 use buck2_build_api::artifact_groups::promise::PromiseArtifact;
-use buck2_build_api::artifact_groups::promise::PromiseArtifactResolveError;
 use buck2_build_api::interpreter::rule_defs::artifact::starlark_artifact::StarlarkArtifact;
 use buck2_build_api::interpreter::rule_defs::artifact::starlark_promise_artifact::StarlarkPromiseArtifact;
 use buck2_build_api::interpreter::rule_defs::provider::collection::FrozenProviderCollectionValue;
@@ -135,20 +135,11 @@ impl AnonTargetAttrResolution for AnonTargetAttr {
                     .unwrap();
 
                 // Assert the short path, since we have the real artifact now
-                if let Some(expected_short_path) = &promise_artifact_attr.short_path {
-                    artifact.get_path().with_short_path(|artifact_short_path| {
-                        if artifact_short_path != expected_short_path {
-                            Err(anyhow::Error::from(
-                                PromiseArtifactResolveError::ShortPathMismatch(
-                                    expected_short_path.clone(),
-                                    artifact_short_path.to_string(),
-                                ),
-                            ))
-                        } else {
-                            Ok(())
-                        }
-                    })?;
-                }
+                check_short_path(
+                    artifact,
+                    &promise_artifact_attr.short_path,
+                    &promise_artifact_attr.declaration_location,
+                )?;
 
                 let fulfilled = OnceLock::new();
                 fulfilled.set(artifact.clone()).unwrap();
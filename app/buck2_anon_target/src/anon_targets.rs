@@ -24,12 +24,12 @@ use buck2_build_api::analysis::anon_targets_registry::AnonTargetsRegistryDyn;
 use buck2_build_api::analysis::anon_targets_registry::ANON_TARGET_REGISTRY_NEW;
 use buck2_build_api::analysis::registry::AnalysisRegistry;
 use buck2_build_api::analysis::AnalysisResult;
+use buck2_build_api::artifact_groups::promise::promised_artifact_from_value;
 use buck2_build_api::artifact_groups::promise::PromiseArtifact;
 use buck2_build_api::artifact_groups::promise::PromiseArtifactId;
 use buck2_build_api::artifact_groups::promise::PromiseArtifactResolveError;
 use buck2_build_api::deferred::calculation::EVAL_ANON_TARGET;
 use buck2_build_api::deferred::calculation::GET_PROMISED_ARTIFACT;
-use buck2_build_api::interpreter::rule_defs::artifact::starlark_artifact_like::ValueAsArtifactLike;
 use buck2_build_api::interpreter::rule_defs::context::AnalysisContext;
 use buck2_build_api::interpreter::rule_defs::plugins::AnalysisPlugins;
 use buck2_build_api::interpreter::rule_defs::provider::collection::FrozenProviderCollectionValue;
@@ -435,17 +435,8 @@ impl AnonTargetKey {
             let promise_id =
                 PromiseArtifactId::new(BaseDeferredKey::AnonTarget(self.0.clone()), id);
 
-            match ValueAsArtifactLike::unpack_value(artifact) {
-                Some(artifact) => {
-                    fulfilled_artifact_mappings
-                        .insert(promise_id.clone(), artifact.0.get_bound_artifact()?);
-                }
-                None => {
-                    return Err(
-                        PromiseArtifactResolveError::NotAnArtifact(artifact.to_repr()).into(),
-                    );
-                }
-            }
+            fulfilled_artifact_mappings
+                .insert(promise_id, promised_artifact_from_value(artifact, &None)?);
         }
 
         Ok(fulfilled_artifact_mappings)
@@ -575,7 +566,7 @@ impl<'v> AnonTargetsRegistryDyn<'v> for AnonTargetsRegistry<'v> {
         self
     }
 
-    fn consumer_analysis_artifacts(&self) -> Vec<PromiseArtifact> {
+    fn consumer_analysis_artifacts(&self) -> Vec<(PromiseArtifact, Option<FileSpan>)> {
         self.promise_artifact_registry.consumer_analysis_artifacts()
     }
 
@@ -9,6 +9,8 @@
 
 use std::fmt;
 use std::fmt::Debug;
+use std::hash::Hash;
+use std::hash::Hasher;
 use std::sync::Arc;
 use std::sync::OnceLock;
 
@@ -47,9 +49,11 @@ impl PromiseArtifactRegistry {
     /// target analysis. These promised artifacts are the ones that will have their short paths
     /// asserted. During promise resolution, we use the promised artifact's owner (the anon target
     /// key) to look up the owner's analysis results via DICE (which will be blocking) to ensure
-    /// that any dependent anon target analyses are finished first.
-    pub(crate) fn consumer_analysis_artifacts(&self) -> Vec<PromiseArtifact> {
-        self.artifacts.map(|e| e.artifact.clone())
+    /// that any dependent anon target analyses are finished first. Each artifact is returned
+    /// together with the location it was registered at, for error reporting.
+    pub(crate) fn consumer_analysis_artifacts(&self) -> Vec<(PromiseArtifact, Option<FileSpan>)> {
+        self.artifacts
+            .map(|e| (e.artifact.clone(), e.location.clone()))
     }
 
     pub(crate) fn register(
@@ -72,10 +76,28 @@ impl PromiseArtifactRegistry {
 // During resolve, we look up the analysis of the target that produced the promise artifact,
 // assert short paths, and produce a new `StarlarkPromiseArtifact` with the `OnceLock` resolved.
 #[allow(unused)]
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Allocative)]
+#[derive(Debug, Clone, Allocative)]
 pub(crate) struct PromiseArtifactAttr {
     pub(crate) id: PromiseArtifactId,
     pub(crate) short_path: Option<ForwardRelativePathBuf>,
+    /// Where the promise was declared, for error reporting. It is not part of the attr's
+    /// identity, so the same promise passed in from different places is the same anon target.
+    pub(crate) declaration_location: Option<FileSpan>,
+}
+
+impl PartialEq for PromiseArtifactAttr {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.short_path == other.short_path
+    }
+}
+
+impl Eq for PromiseArtifactAttr {}
+
+impl Hash for PromiseArtifactAttr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.short_path.hash(state);
+    }
 }
 
 impl fmt::Display for PromiseArtifactAttr {
@@ -7,8 +7,13 @@
  * of this source tree.
  */
 
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
 use allocative::Allocative;
 use buck2_core::base_deferred_key::BaseDeferredKey;
+use buck2_data::action_key_owner::BaseDeferredKeyProto;
 use buck2_data::ToProtoMessage;
 use dupe::Dupe;
 
@@ -43,6 +48,207 @@ impl ActionKey {
     pub fn owner(&self) -> &BaseDeferredKey {
         self.deferred_key().owner()
     }
+
+    /// A deterministic textual form of this key, see `StableActionKey`.
+    pub fn to_string_stable(&self) -> String {
+        StableActionKey::new(self).to_string()
+    }
+
+    /// Parse the output of `to_string_stable`.
+    pub fn parse_stable(s: &str) -> anyhow::Result<StableActionKey> {
+        s.parse()
+    }
+}
+
+#[derive(Debug, buck2_error::Error)]
+enum StableActionKeyError {
+    #[error("Invalid stable action key `{0}`, expected `<target|anon|bxl> <owner>... <key>`")]
+    Invalid(String),
+}
+
+/// A human-readable form of an `ActionKey` that can be printed (e.g. by aquery) and later parsed
+/// back to look the action up in an event log.
+///
+/// It is derived from the same data as the `ActionKey` proto, so it matches protos from the
+/// event log exactly. The format is space separated, starting with the owner kind and ending with
+/// the action key suffix (the chain of deferred ids, the last of which is the action's own id):
+///
+/// * `target <package>:<name> <configuration> [<exec configuration>] <key>`
+/// * `anon <package>:<name> <exec configuration> <hash> <key>`
+/// * `bxl <bxl path>:<function> <key>`
+///
+/// For target label owners this identifies the action exactly. Anon targets are identified by
+/// their attribute hash, and BXL owners only by their function, without the arguments, so for
+/// those the key is best-effort: distinct owners may produce the same stable key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StableActionKey {
+    owner: StableActionKeyOwner,
+    key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum StableActionKeyOwner {
+    TargetLabel {
+        label: String,
+        configuration: String,
+        exec_configuration: Option<String>,
+    },
+    AnonTarget {
+        label: String,
+        exec_configuration: String,
+        hash: String,
+    },
+    BxlLabel {
+        function: String,
+    },
+}
+
+fn label_from_proto(label: &Option<buck2_data::TargetLabel>) -> String {
+    match label {
+        Some(label) => format!("{}:{}", label.package, label.name),
+        None => String::new(),
+    }
+}
+
+fn configuration_from_proto(cfg: &Option<buck2_data::Configuration>) -> String {
+    cfg.as_ref()
+        .map(|c| c.full_name.clone())
+        .unwrap_or_default()
+}
+
+impl StableActionKeyOwner {
+    fn from_target_label(label: &buck2_data::ConfiguredTargetLabel) -> Self {
+        StableActionKeyOwner::TargetLabel {
+            label: label_from_proto(&label.label),
+            configuration: configuration_from_proto(&label.configuration),
+            exec_configuration: label
+                .execution_configuration
+                .as_ref()
+                .map(|c| c.full_name.clone()),
+        }
+    }
+
+    fn from_anon_target(anon: &buck2_data::AnonTarget) -> Self {
+        StableActionKeyOwner::AnonTarget {
+            label: label_from_proto(&anon.name),
+            exec_configuration: configuration_from_proto(&anon.execution_configuration),
+            hash: anon.hash.clone(),
+        }
+    }
+
+    fn from_bxl_key(bxl: &buck2_data::BxlFunctionKey) -> Self {
+        StableActionKeyOwner::BxlLabel {
+            function: match &bxl.label {
+                Some(label) => format!("{}:{}", label.bxl_path, label.name),
+                None => String::new(),
+            },
+        }
+    }
+}
+
+impl StableActionKey {
+    pub fn new(key: &ActionKey) -> Self {
+        let owner = match key.owner().to_proto() {
+            BaseDeferredKeyProto::TargetLabel(t) => StableActionKeyOwner::from_target_label(&t),
+            BaseDeferredKeyProto::AnonTarget(a) => StableActionKeyOwner::from_anon_target(&a),
+            BaseDeferredKeyProto::BxlKey(b) => StableActionKeyOwner::from_bxl_key(&b),
+        };
+        StableActionKey {
+            owner,
+            key: key.deferred_key().action_key(),
+        }
+    }
+
+    /// Build the stable key for an `ActionKey` proto from the event log, if it has an owner.
+    pub fn from_proto(proto: &buck2_data::ActionKey) -> Option<Self> {
+        use buck2_data::action_key::Owner;
+
+        let owner = match proto.owner.as_ref()? {
+            Owner::TargetLabel(t) | Owner::TestTargetLabel(t) | Owner::LocalResourceSetup(t) => {
+                StableActionKeyOwner::from_target_label(t)
+            }
+            Owner::AnonTarget(a) => StableActionKeyOwner::from_anon_target(a),
+            Owner::BxlKey(b) => StableActionKeyOwner::from_bxl_key(b),
+        };
+        Some(StableActionKey {
+            owner,
+            key: proto.key.clone(),
+        })
+    }
+
+    /// Whether an `ActionKey` proto from the event log refers to this action.
+    pub fn matches_proto(&self, proto: &buck2_data::ActionKey) -> bool {
+        StableActionKey::from_proto(proto).as_ref() == Some(self)
+    }
+}
+
+impl Display for StableActionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.owner {
+            StableActionKeyOwner::TargetLabel {
+                label,
+                configuration,
+                exec_configuration,
+            } => {
+                write!(f, "target {} {}", label, configuration)?;
+                if let Some(exec_configuration) = exec_configuration {
+                    write!(f, " {}", exec_configuration)?;
+                }
+            }
+            StableActionKeyOwner::AnonTarget {
+                label,
+                exec_configuration,
+                hash,
+            } => write!(f, "anon {} {} {}", label, exec_configuration, hash)?,
+            StableActionKeyOwner::BxlLabel { function } => write!(f, "bxl {}", function)?,
+        }
+        write!(f, " {}", self.key)
+    }
+}
+
+impl FromStr for StableActionKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || StableActionKeyError::Invalid(s.to_owned());
+
+        let (kind, rest) = s.split_once(' ').ok_or_else(invalid)?;
+        let (owner, key) = rest.rsplit_once(' ').ok_or_else(invalid)?;
+        if key.is_empty() || !key.split('_').all(|id| id.parse::<u32>().is_ok()) {
+            return Err(invalid().into());
+        }
+
+        let parts: Vec<&str> = owner.split(' ').collect();
+        let owner = match (kind, parts.as_slice()) {
+            ("target", [label, configuration]) => StableActionKeyOwner::TargetLabel {
+                label: (*label).to_owned(),
+                configuration: (*configuration).to_owned(),
+                exec_configuration: None,
+            },
+            ("target", [label, configuration, exec_configuration]) => {
+                StableActionKeyOwner::TargetLabel {
+                    label: (*label).to_owned(),
+                    configuration: (*configuration).to_owned(),
+                    exec_configuration: Some((*exec_configuration).to_owned()),
+                }
+            }
+            ("anon", [label, exec_configuration, hash]) => StableActionKeyOwner::AnonTarget {
+                label: (*label).to_owned(),
+                exec_configuration: (*exec_configuration).to_owned(),
+                hash: (*hash).to_owned(),
+            },
+            // The BXL path is not split further, so it may contain spaces.
+            ("bxl", _) if owner.contains(':') => StableActionKeyOwner::BxlLabel {
+                function: owner.to_owned(),
+            },
+            _ => return Err(invalid().into()),
+        };
+
+        Ok(StableActionKey {
+            owner,
+            key: key.to_owned(),
+        })
+    }
 }
 
 impl ToProtoMessage for ActionKey {
@@ -56,3 +262,173 @@ impl ToProtoMessage for ActionKey {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::sync::Arc;
+
+    use allocative::Allocative;
+    use buck2_core::base_deferred_key::BaseDeferredKey;
+    use buck2_core::base_deferred_key::BaseDeferredKeyDyn;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::execution_types::execution::ExecutionPlatformResolution;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+    use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+    use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+    use buck2_data::action_key_owner::BaseDeferredKeyProto;
+    use buck2_data::ToProtoMessage;
+    use cmp_any::PartialEqAny;
+    use dupe::Dupe;
+
+    use crate::actions::key::ActionKey;
+    use crate::actions::key::StableActionKey;
+    use crate::deferred::id::DeferredId;
+    use crate::deferred::key::DeferredKey;
+
+    #[derive(Debug, derive_more::Display, Allocative, PartialEq)]
+    enum TestingOwner {
+        #[display(fmt = "anon")]
+        Anon,
+        #[display(fmt = "bxl")]
+        Bxl,
+    }
+
+    impl BaseDeferredKeyDyn for TestingOwner {
+        fn eq_token(&self) -> PartialEqAny {
+            PartialEqAny::new(self)
+        }
+
+        fn hash(&self) -> u64 {
+            match self {
+                TestingOwner::Anon => 0,
+                TestingOwner::Bxl => 1,
+            }
+        }
+
+        fn make_hashed_path(
+            &self,
+            _base: &ProjectRelativePath,
+            _prefix: &ForwardRelativePath,
+            _action_key: Option<&str>,
+            _path: &ForwardRelativePath,
+        ) -> ProjectRelativePathBuf {
+            unimplemented!()
+        }
+
+        fn configured_label(&self) -> Option<ConfiguredTargetLabel> {
+            None
+        }
+
+        fn to_proto(&self) -> BaseDeferredKeyProto {
+            match self {
+                TestingOwner::Anon => BaseDeferredKeyProto::AnonTarget(buck2_data::AnonTarget {
+                    name: Some(buck2_data::TargetLabel {
+                        package: "root//foo".to_owned(),
+                        name: "anon_rule".to_owned(),
+                    }),
+                    execution_configuration: Some(ConfigurationData::testing_new().as_proto()),
+                    hash: "1234abcd".to_owned(),
+                }),
+                TestingOwner::Bxl => BaseDeferredKeyProto::BxlKey(buck2_data::BxlFunctionKey {
+                    label: Some(buck2_data::BxlFunctionLabel {
+                        bxl_path: "root//bar/my script.bxl".to_owned(),
+                        name: "main".to_owned(),
+                    }),
+                }),
+            }
+        }
+
+        fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+            self
+        }
+
+        fn execution_platform_resolution(&self) -> &ExecutionPlatformResolution {
+            unimplemented!()
+        }
+    }
+
+    fn action_key(owner: BaseDeferredKey) -> ActionKey {
+        let base = DeferredKey::Base(owner, DeferredId::testing_new(3));
+        ActionKey::unchecked_new(DeferredKey::Deferred(
+            Arc::new(base),
+            DeferredId::testing_new(7),
+        ))
+    }
+
+    fn assert_round_trips(key: &ActionKey, expected: &str) {
+        let stable = key.to_string_stable();
+        assert_eq!(expected, stable);
+        let parsed = ActionKey::parse_stable(&stable).unwrap();
+        assert_eq!(StableActionKey::new(key), parsed);
+        assert!(parsed.matches_proto(&key.as_proto()));
+        assert_eq!(stable, parsed.to_string());
+    }
+
+    #[test]
+    fn test_stable_target_label() {
+        let label =
+            ConfiguredTargetLabel::testing_parse("root//foo:bar", ConfigurationData::testing_new());
+        let key = action_key(BaseDeferredKey::TargetLabel(label.dupe()));
+        let cfg = ConfigurationData::testing_new();
+        assert_round_trips(
+            &key,
+            &format!("target root//foo:bar {} 3_7", cfg.full_name()),
+        );
+
+        let exec = label.with_exec_cfg(ConfigurationData::testing_new());
+        let key = action_key(BaseDeferredKey::TargetLabel(exec));
+        assert_round_trips(
+            &key,
+            &format!(
+                "target root//foo:bar {} {} 3_7",
+                cfg.full_name(),
+                cfg.full_name()
+            ),
+        );
+
+        // A freshly built key is equal to the parsed one, a different action is not.
+        let parsed = ActionKey::parse_stable(&key.to_string_stable()).unwrap();
+        let other = ActionKey::unchecked_new(DeferredKey::Base(
+            key.owner().dupe(),
+            DeferredId::testing_new(7),
+        ));
+        assert_ne!(StableActionKey::new(&other), parsed);
+        assert!(!parsed.matches_proto(&other.as_proto()));
+    }
+
+    #[test]
+    fn test_stable_anon_target() {
+        let key = action_key(BaseDeferredKey::AnonTarget(Arc::new(TestingOwner::Anon)));
+        assert_round_trips(
+            &key,
+            &format!(
+                "anon root//foo:anon_rule {} 1234abcd 3_7",
+                ConfigurationData::testing_new().full_name()
+            ),
+        );
+    }
+
+    #[test]
+    fn test_stable_bxl() {
+        let key = action_key(BaseDeferredKey::BxlLabel(Arc::new(TestingOwner::Bxl)));
+        assert_round_trips(&key, "bxl root//bar/my script.bxl:main 3_7");
+    }
+
+    #[test]
+    fn test_parse_stable_invalid() {
+        for s in [
+            "",
+            "target",
+            "target root//foo:bar",
+            "target root//foo:bar cfg not_an_id",
+            "target root//foo:bar a b c 1",
+            "anon root//foo:bar cfg 1",
+            "bxl nocolon 1",
+            "unknown root//foo:bar cfg 1",
+        ] {
+            assert!(ActionKey::parse_stable(s).is_err(), "{}", s);
+        }
+    }
+}
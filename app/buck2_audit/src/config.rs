@@ -119,6 +119,11 @@ pub struct AuditConfigCommand {
     )]
     pub value_style: ValueStyle,
 
+    /// With JSON output, print for each key an object with its value, where it was set, and the
+    /// values it overrode, instead of just its value.
+    #[clap(long)]
+    pub provenance: bool,
+
     /// config section/key specs of the form `section` or `section.key`.
     /// If any specs are provided, only values matching a spec will be printed
     /// (section headers will be printed only for sections with a key matching the spec).
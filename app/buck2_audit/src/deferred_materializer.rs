@@ -47,6 +47,23 @@ pub enum DeferredMaterializerSubcommand {
         count: usize,
     },
     FlushAccessTimes,
+    /// Print the status of artifacts, including why materializing them failed, if it did.
+    Status {
+        /// Project-relative paths of the artifacts.
+        #[clap(required = true)]
+        paths: Vec<String>,
+    },
+    /// Re-hash materialized artifacts and report those that don't match what the materializer
+    /// believes is on disk.
+    Verify {
+        /// Project-relative paths to check. All materialized artifacts are checked if omitted.
+        #[clap()]
+        paths: Vec<String>,
+        /// Remove the mismatched artifacts, so that they are materialized again the next time
+        /// they are needed.
+        #[clap(long)]
+        invalidate: bool,
+    },
 }
 
 #[async_trait]
@@ -28,6 +28,7 @@ use crate::analysis_queries::AuditAnalysisQueriesCommand;
 use crate::cell::AuditCellCommand;
 use crate::config::AuditConfigCommand;
 use crate::configurations::AuditConfigurationsCommand;
+use crate::defaults::AuditDefaultsCommand;
 use crate::deferred_materializer::DeferredMaterializerCommand;
 use crate::dep_files::AuditDepFilesCommand;
 use crate::execution_platform_resolution::AuditExecutionPlatformResolutionCommand;
@@ -46,6 +47,7 @@ pub mod cell;
 pub mod classpath;
 pub mod config;
 pub mod configurations;
+pub mod defaults;
 pub mod deferred_materializer;
 pub mod dep_files;
 pub mod execution_platform_resolution;
@@ -79,6 +81,7 @@ pub enum AuditCommand {
     Output(AuditOutputCommand),
     Parse(AuditParseCommand),
     PackageValues(PackageValuesCommand),
+    Defaults(AuditDefaultsCommand),
 }
 
 /// `buck2 audit` subcommands have a somewhat unique approach to make it really easy to
@@ -114,6 +117,7 @@ impl AuditCommand {
             AuditCommand::Output(cmd) => cmd,
             AuditCommand::Parse(cmd) => cmd,
             AuditCommand::PackageValues(cmd) => cmd,
+            AuditCommand::Defaults(cmd) => cmd,
         }
     }
 }
@@ -66,6 +66,28 @@ fn print_location(
     Ok(())
 }
 
+fn location_json(location: &LegacyBuckConfigLocation) -> serde_json::Value {
+    match location {
+        LegacyBuckConfigLocation::File(path, line) => {
+            json!({"type": "file", "path": path, "line": line})
+        }
+        LegacyBuckConfigLocation::CommandLineArgument => json!({"type": "command_line"}),
+    }
+}
+
+fn provenance_json(value: &LegacyBuckConfigValue) -> serde_json::Value {
+    let overridden = value
+        .overridden()
+        .map(|v| json!({"raw_value": v.raw_value(), "source": location_json(&v.location())}))
+        .collect::<Vec<_>>();
+    json!({
+        "value": value.as_str(),
+        "raw_value": value.raw_value(),
+        "source": location_json(&value.location()),
+        "overridden": overridden,
+    })
+}
+
 fn print_value(
     writer: &mut impl Write,
     key: &str,
@@ -212,7 +234,12 @@ impl ServerAuditSubcommand for AuditConfigCommand {
                                         if self.all_cells && !spec.contains("//") {
                                             spec = format!("{cell}//{spec}");
                                         }
-                                        json_output.insert(spec, value.as_str().to_owned());
+                                        let value = if self.provenance {
+                                            provenance_json(&value)
+                                        } else {
+                                            json!(value.as_str())
+                                        };
+                                        json_output.insert(spec, value);
                                     }
                                     OutputFormat::Simple => {
                                         if self.all_cells && !printed_cell {
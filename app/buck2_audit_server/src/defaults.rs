@@ -0,0 +1,49 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::io::Write;
+
+use async_trait::async_trait;
+use buck2_audit::defaults::AuditDefaultsCommand;
+use buck2_build_api::audit_defaults::audit_defaults;
+use buck2_common::dice::cells::HasCellResolver;
+use buck2_server_ctx::ctx::ServerCommandContextTrait;
+use buck2_server_ctx::ctx::ServerCommandDiceContext;
+use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
+
+use crate::ServerAuditSubcommand;
+
+#[async_trait]
+impl ServerAuditSubcommand for AuditDefaultsCommand {
+    async fn server_execute(
+        &self,
+        server_ctx: &dyn ServerCommandContextTrait,
+        mut stdout: PartialResultDispatcher<buck2_cli_proto::StdoutBytes>,
+        _client_server_ctx: buck2_cli_proto::ClientContext,
+    ) -> anyhow::Result<()> {
+        server_ctx
+            .with_dice_ctx(|server_ctx, mut dice_ctx| async move {
+                let cell_resolver = dice_ctx.get_cell_resolver().await?;
+                let attributes = audit_defaults(
+                    &self.rule_type,
+                    server_ctx.working_dir(),
+                    &cell_resolver,
+                    &mut dice_ctx,
+                )
+                .await?;
+
+                let mut stdout = stdout.as_writer();
+                serde_json::to_writer_pretty(&mut stdout, &attributes)?;
+                // Because serde does not write a trailing newline.
+                writeln!(stdout)?;
+                Ok(())
+            })
+            .await
+    }
+}
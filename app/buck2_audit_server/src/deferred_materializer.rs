@@ -14,6 +14,7 @@ use async_trait::async_trait;
 use buck2_audit::deferred_materializer::DeferredMaterializerCommand;
 use buck2_audit::deferred_materializer::DeferredMaterializerSubcommand;
 use buck2_cli_proto::ClientContext;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
 use futures::stream::StreamExt;
@@ -99,6 +100,53 @@ impl ServerAuditSubcommand for DeferredMaterializerCommand {
 
                 write!(stdout, "{}", text)?;
             }
+            DeferredMaterializerSubcommand::Status { ref paths } => {
+                let paths = paths
+                    .iter()
+                    .map(|p| ProjectRelativePath::new(p).map(|p| p.to_owned()))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let statuses = deferred_materializer
+                    .materialization_status(paths)
+                    .await
+                    .context("Failed to get materialization status")?;
+
+                for (path, status) in statuses {
+                    match status {
+                        Some(status) => writeln!(stdout, "{}\t{}", path, status)?,
+                        None => writeln!(stdout, "{}\tnot declared", path)?,
+                    }
+                }
+            }
+            DeferredMaterializerSubcommand::Verify {
+                ref paths,
+                invalidate,
+            } => {
+                let paths = paths
+                    .iter()
+                    .map(|p| ProjectRelativePath::new(p).map(|p| p.to_owned()))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let report = deferred_materializer
+                    .verify(paths, invalidate)
+                    .await
+                    .context("Failed to verify")?;
+
+                for mismatch in &report.mismatches {
+                    writeln!(
+                        stdout,
+                        "{}\texpected {}, found {}",
+                        mismatch.path, mismatch.expected, mismatch.found
+                    )?;
+                }
+
+                let mut stderr = server_ctx.stderr()?;
+                writeln!(
+                    &mut stderr,
+                    "checked: {}, mismatches: {}, invalidated: {}",
+                    report.checked,
+                    report.mismatches.len(),
+                    report.invalidated.len()
+                )?;
+            }
         }
 
         anyhow::Ok(())
@@ -25,6 +25,7 @@ mod classpath;
 mod common;
 mod config;
 mod configurations;
+mod defaults;
 pub mod deferred_materializer;
 mod dep_files;
 mod execution_platform_resolution;
@@ -98,6 +99,7 @@ impl AuditCommandExt for AuditCommand {
             AuditCommand::Output(cmd) => cmd,
             AuditCommand::Parse(cmd) => cmd,
             AuditCommand::PackageValues(cmd) => cmd,
+            AuditCommand::Defaults(cmd) => cmd,
         }
     }
 }
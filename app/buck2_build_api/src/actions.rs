@@ -295,6 +295,23 @@ pub enum ActionErrors {
         "Analysis produced multiple actions with category `{0}` and at least one of them had no identifier. Add an identifier to these actions to disambiguate them"
     )]
     ActionCategoryDuplicateSingleton(Category),
+    #[error(
+        "Output `{path}` declared by `{owner}` contains `{component}`, which is a reserved file name on Windows"
+    )]
+    WindowsReservedOutputName {
+        path: ForwardRelativePathBuf,
+        owner: String,
+        component: String,
+    },
+    #[error(
+        "Output `{path}` declared by `{owner}` would have a path of {len} characters, which exceeds the Windows limit of {limit}. Use shorter output or target names"
+    )]
+    WindowsOutputPathTooLong {
+        path: ForwardRelativePathBuf,
+        owner: String,
+        len: usize,
+        limit: usize,
+    },
 }
 
 #[derive(Derivative, Debug, Display, Allocative)]
@@ -54,6 +54,8 @@ use crate::actions::execute::action_executor::HasActionExecutor;
 use crate::actions::key::ActionKeyExt;
 use crate::actions::RegisteredAction;
 use crate::artifact_groups::calculation::ensure_artifact_group_staged;
+use crate::build::cache_attribution::ActionOutcome;
+use crate::build::cache_attribution::HasCacheAttribution;
 use crate::deferred::calculation::DeferredCalculation;
 use crate::keep_going::KeepGoing;
 use crate::starlark::values::type_repr::StarlarkTypeRepr;
@@ -251,6 +253,18 @@ async fn build_action_no_redirect(
             }
         };
 
+        if let (Some(cache_attribution), Some(execution_kind)) = (
+            ctx.per_transaction_data().get_cache_attribution(),
+            execution_kind,
+        ) {
+            cache_attribution.record(
+                action.key(),
+                action.category().as_str(),
+                ActionOutcome::from_execution_kind(execution_kind),
+                wall_time,
+            );
+        }
+
         let outputs = action_result
             .as_ref()
             .map(|outputs| {
@@ -403,9 +417,20 @@ impl ActionCalculation for DiceComputations<'_> {
         // build_action is called for every action key. We don't use `async fn` to ensure that it has minimal cost.
         // We don't currently consume this in buck_e2e but it's good to log for debugging purposes.
         debug!("build_action {}", action_key);
-        self.compute(&BuildKey(action_key))
+        let outputs = self
+            .compute(&BuildKey(action_key.dupe()))
             .map(|v| v?.map_err(anyhow::Error::from))
-            .await
+            .await?;
+        // Actions executed in this command are recorded when they are computed. Record the ones
+        // that were already up to date here, so that warm builds are attributed too.
+        if let Some(cache_attribution) = self.per_transaction_data().get_cache_attribution() {
+            if !cache_attribution.contains(&action_key) {
+                let cache_attribution = cache_attribution.dupe();
+                let action = self.get_action(&action_key).await?;
+                cache_attribution.record_up_to_date(action.key(), action.category().as_str());
+            }
+        }
+        Ok(outputs)
     }
 
     async fn build_artifact(&mut self, artifact: &BuildArtifact) -> anyhow::Result<ActionOutputs> {
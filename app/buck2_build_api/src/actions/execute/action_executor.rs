@@ -31,6 +31,7 @@ use buck2_execute::digest_config::HasDigestConfig;
 use buck2_execute::execute::action_digest_and_blobs::ActionDigestAndBlobs;
 use buck2_execute::execute::blocking::BlockingExecutor;
 use buck2_execute::execute::blocking::HasBlockingExecutor;
+use buck2_execute::execute::blocking::IoTag;
 use buck2_execute::execute::cache_uploader::CacheUploadInfo;
 use buck2_execute::execute::cache_uploader::CacheUploadResult;
 use buck2_execute::execute::cache_uploader::DepFileEntry;
@@ -529,6 +530,7 @@ impl ActionExecutionCtx for BuckActionExecutionContext<'_> {
             self.executor
                 .blocking_executor
                 .execute_io(
+                    IoTag::Other,
                     Box::new(CleanOutputPaths {
                         paths: output_paths,
                     }),
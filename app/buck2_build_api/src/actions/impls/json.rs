@@ -10,6 +10,7 @@
 use std::io::sink;
 use std::io::Write;
 
+use allocative::Allocative;
 use anyhow::Context;
 use buck2_artifact::artifact::artifact_type::Artifact;
 use buck2_execute::artifact::artifact_dyn::ArtifactDyn;
@@ -47,11 +48,23 @@ use crate::interpreter::rule_defs::provider::ProviderLike;
 use crate::interpreter::rule_defs::provider::ValueAsProviderLike;
 use crate::interpreter::rule_defs::transitive_set::TransitiveSetJsonProjection;
 
+/// The order in which `write_json` writes the entries of dicts.
+#[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq, Allocative)]
+pub enum JsonDictOrder {
+    /// Sorted by key, so the output doesn't depend on the order the dicts were built in.
+    Sorted,
+    /// Insertion order, for rules that rely on it.
+    Insertion,
+    /// Reverse insertion order. Used to detect outputs that depend on insertion order.
+    Reversed,
+}
+
 /// A wrapper with a Serialize instance so we can pass down the necessary context.
 pub struct SerializeValue<'a, 'v> {
     pub value: Value<'v>,
     pub fs: Option<&'a ExecutorFs<'a>>,
     pub absolute: bool,
+    pub dict_order: JsonDictOrder,
 }
 
 impl<'a, 'v> SerializeValue<'a, 'v> {
@@ -60,7 +73,21 @@ impl<'a, 'v> SerializeValue<'a, 'v> {
             value: x,
             fs: self.fs,
             absolute: self.absolute,
+            dict_order: self.dict_order,
+        }
+    }
+
+    fn dict_entries(&self, dict: &DictRef<'v>) -> Vec<(Value<'v>, Value<'v>)> {
+        let mut entries: Vec<_> = dict.iter().collect();
+        match self.dict_order {
+            JsonDictOrder::Sorted => entries.sort_by(|(a, _), (b, _)| {
+                // Keys which are not strings are rejected by serde_json, so their order is moot.
+                a.unpack_str().cmp(&b.unpack_str())
+            }),
+            JsonDictOrder::Insertion => {}
+            JsonDictOrder::Reversed => entries.reverse(),
         }
+        entries
     }
 }
 
@@ -177,7 +204,8 @@ impl<'a, 'v> Serialize for SerializeValue<'a, 'v> {
             JsonUnpack::List(x) => serializer.collect_seq(x.iter().map(|v| self.with_value(v))),
             JsonUnpack::Tuple(x) => serializer.collect_seq(x.iter().map(|v| self.with_value(v))),
             JsonUnpack::Dict(x) => serializer.collect_map(
-                x.iter()
+                self.dict_entries(&x)
+                    .into_iter()
                     .map(|(k, v)| (self.with_value(k), self.with_value(v))),
             ),
             JsonUnpack::Struct(x) => {
@@ -202,7 +230,8 @@ impl<'a, 'v> Serialize for SerializeValue<'a, 'v> {
             JsonUnpack::Artifact(x) => {
                 match self.fs {
                     None => {
-                        // Invariant: If fs == None, then the writer = sink().
+                        // Invariant: If fs == None, then the writer = sink(), or the output is
+                        // only compared with another one written the same way.
                         // Therefore, in the None branch, we only care about getting Serde errors,
                         // so pass something of the right type, but don't worry about the value.
                         serializer.serialize_str("")
@@ -270,7 +299,7 @@ fn is_singleton_cmdargs(x: Value) -> bool {
 }
 
 pub fn validate_json(x: Value) -> anyhow::Result<()> {
-    write_json(x, None, &mut sink(), false, false)
+    write_json(x, None, &mut sink(), false, false, JsonDictOrder::Insertion)
 }
 
 pub fn write_json(
@@ -279,11 +308,13 @@ pub fn write_json(
     mut writer: &mut dyn Write,
     pretty: bool,
     absolute: bool,
+    dict_order: JsonDictOrder,
 ) -> anyhow::Result<()> {
     let value = SerializeValue {
         value: x,
         fs,
         absolute,
+        dict_order,
     };
     (|| {
         if pretty {
@@ -299,6 +330,31 @@ pub fn write_json(
     .context("Error converting to JSON for `write_json`")
 }
 
+/// Whether the JSON written for `x` in insertion order changes when its dicts are written in
+/// reverse order, i.e. whether it (and so the digest of the action writing it) depends on the order
+/// the dicts were built in. Artifact paths are not resolved, they don't depend on that order.
+pub fn depends_on_dict_order(x: Value, pretty: bool) -> anyhow::Result<bool> {
+    let mut insertion = Vec::new();
+    write_json(
+        x,
+        None,
+        &mut insertion,
+        pretty,
+        false,
+        JsonDictOrder::Insertion,
+    )?;
+    let mut reversed = Vec::new();
+    write_json(
+        x,
+        None,
+        &mut reversed,
+        pretty,
+        false,
+        JsonDictOrder::Reversed,
+    )?;
+    Ok(insertion != reversed)
+}
+
 pub fn visit_json_artifacts(
     v: Value,
     visitor: &mut dyn CommandLineArtifactVisitor,
@@ -369,3 +425,74 @@ pub fn visit_json_artifacts(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use buck2_common::file_ops::FileDigest;
+    use buck2_execute::digest_config::DigestConfig;
+    use starlark::values::dict::AllocDict;
+    use starlark::values::Heap;
+
+    use crate::actions::impls::json::depends_on_dict_order;
+    use crate::actions::impls::json::write_json;
+    use crate::actions::impls::json::JsonDictOrder;
+
+    fn to_json(heap: &Heap, entries: &[(&str, i32)], dict_order: JsonDictOrder) -> String {
+        let value = heap.alloc(AllocDict(entries.iter().copied()));
+        let mut out = Vec::new();
+        write_json(value, None, &mut out, false, false, dict_order).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_sorted_dicts_do_not_depend_on_insertion_order() {
+        let heap = Heap::new();
+        let a = to_json(&heap, &[("b", 1), ("a", 2)], JsonDictOrder::Sorted);
+        let b = to_json(&heap, &[("a", 2), ("b", 1)], JsonDictOrder::Sorted);
+        assert_eq!(r#"{"a":2,"b":1}"#, a);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_insertion_order_is_kept_on_request() {
+        let heap = Heap::new();
+        assert_eq!(
+            r#"{"b":1,"a":2}"#,
+            to_json(&heap, &[("b", 1), ("a", 2)], JsonDictOrder::Insertion)
+        );
+    }
+
+    #[test]
+    fn test_depends_on_dict_order() {
+        let heap = Heap::new();
+        let order_dependent = heap.alloc(AllocDict([("b", 1), ("a", 2)]));
+        assert!(depends_on_dict_order(order_dependent, false).unwrap());
+
+        let single_entry = heap.alloc(AllocDict([("a", heap.alloc(AllocDict([("b", 1)])))]));
+        assert!(!depends_on_dict_order(single_entry, false).unwrap());
+    }
+
+    #[test]
+    fn test_sorted_keys_give_identical_digests() {
+        let digest_config = DigestConfig::testing_default();
+        let digest = |entries: &[(&str, i32)], dict_order| {
+            let heap = Heap::new();
+            let nested = heap.alloc(AllocDict(entries.iter().copied()));
+            let value = heap.alloc(AllocDict([("deps", nested), ("name", heap.alloc("x"))]));
+            let mut out = Vec::new();
+            write_json(value, None, &mut out, true, false, dict_order).unwrap();
+            FileDigest::from_content(&out, digest_config.cas_digest_config())
+        };
+
+        let ab = [("a", 1), ("b", 2)];
+        let ba = [("b", 2), ("a", 1)];
+        assert_eq!(
+            digest(&ab, JsonDictOrder::Sorted),
+            digest(&ba, JsonDictOrder::Sorted)
+        );
+        assert_ne!(
+            digest(&ab, JsonDictOrder::Insertion),
+            digest(&ba, JsonDictOrder::Insertion)
+        );
+    }
+}
@@ -17,6 +17,7 @@ use buck2_artifact::artifact::artifact_type::DeclaredArtifact;
 use buck2_artifact::artifact::artifact_type::OutputArtifact;
 use buck2_artifact::deferred::id::DeferredId;
 use buck2_core::base_deferred_key::BaseDeferredKey;
+use buck2_core::buck2_env;
 use buck2_core::category::Category;
 use buck2_core::directory;
 use buck2_core::directory::Directory;
@@ -27,8 +28,13 @@ use buck2_core::directory::DirectoryIterator;
 use buck2_core::directory::NoDigest;
 use buck2_core::execution_types::execution::ExecutionPlatformResolution;
 use buck2_core::fs::buck_out_path::BuckOutPath;
+use buck2_core::fs::buck_out_path::BuckOutPathResolver;
 use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
 use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::windows_path::find_windows_reserved_component;
+use buck2_core::fs::windows_path::projected_path_len;
+use buck2_core::fs::windows_path::WINDOWS_MAX_PATH;
 use buck2_execute::execute::request::OutputType;
 use dupe::Dupe;
 use indexmap::IndexSet;
@@ -44,6 +50,48 @@ use crate::artifact_groups::ArtifactGroup;
 use crate::deferred::types::DeferredRegistry;
 use crate::deferred::types::ReservedTrivialDeferredData;
 
+/// Outputs which can't be materialized on Windows fail much later, during the build, with
+/// unhelpful errors. When building on or for Windows, this check can be enabled with
+/// `BUCK2_CHECK_WINDOWS_OUTPUT_PATHS` to reject them when they are declared instead.
+///
+/// The projected length is computed relative to buck-out, so the length of the absolute path of
+/// buck-out on the machines which will run the build is configurable.
+fn check_windows_output_path(out_path: &BuckOutPath) -> anyhow::Result<()> {
+    if !buck2_env!("BUCK2_CHECK_WINDOWS_OUTPUT_PATHS", bool)? {
+        return Ok(());
+    }
+    let buck_out_len = buck2_env!(
+        "BUCK2_WINDOWS_BUCK_OUT_PATH_LEN",
+        type = usize,
+        default = 40
+    )?;
+
+    if let Some(component) = find_windows_reserved_component(out_path.path().as_str()) {
+        return Err(ActionErrors::WindowsReservedOutputName {
+            path: out_path.path().to_owned(),
+            owner: out_path.owner().to_string(),
+            component: component.to_owned(),
+        }
+        .into());
+    }
+
+    let resolved =
+        BuckOutPathResolver::new(ProjectRelativePath::empty().to_buf()).resolve_gen(out_path);
+    let len = projected_path_len(buck_out_len, resolved.as_str());
+    // `MAX_PATH` includes the terminating NUL.
+    if len >= WINDOWS_MAX_PATH {
+        return Err(ActionErrors::WindowsOutputPathTooLong {
+            path: out_path.path().to_owned(),
+            owner: out_path.owner().to_string(),
+            len,
+            limit: WINDOWS_MAX_PATH - 1,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
 /// The actions registry for a particular analysis of a rule implementation
 #[derive(Allocative)]
 pub struct ActionsRegistry {
@@ -163,6 +211,7 @@ impl ActionsRegistry {
         self.claim_output_path(&path, declaration_location)?;
         let out_path =
             BuckOutPath::with_action_key(self.owner.dupe(), path, self.action_key.dupe());
+        check_windows_output_path(&out_path)?;
         let declared = DeclaredArtifact::new(out_path, output_type, hidden);
         if !self.artifacts.insert(declared.dupe()) {
             panic!("not expected duplicate artifact after output path was successfully claimed");
@@ -14,6 +14,7 @@ use allocative::Allocative;
 use buck2_core::execution_types::execution::ExecutionPlatformResolution;
 use buck2_util::late_binding::LateBinding;
 use starlark::any::AnyLifetime;
+use starlark::codemap::FileSpan;
 use starlark::values::Trace;
 use starlark::values::Value;
 
@@ -32,6 +33,6 @@ pub trait AnonTargetsRegistryDyn<'v>:
 {
     fn as_any_mut(&mut self) -> &mut dyn AnyLifetime<'v>;
     fn take_promises(&mut self) -> Option<Box<dyn AnonPromisesDyn<'v>>>;
-    fn consumer_analysis_artifacts(&self) -> Vec<PromiseArtifact>;
+    fn consumer_analysis_artifacts(&self) -> Vec<(PromiseArtifact, Option<FileSpan>)>;
     fn assert_no_promises(&self) -> anyhow::Result<()>;
 }
@@ -280,7 +280,7 @@ impl<'v> AnalysisRegistry<'v> {
         self.anon_targets.take_promises()
     }
 
-    pub fn consumer_analysis_artifacts(&self) -> Vec<PromiseArtifact> {
+    pub fn consumer_analysis_artifacts(&self) -> Vec<(PromiseArtifact, Option<FileSpan>)> {
         self.anon_targets.consumer_analysis_artifacts()
     }
 
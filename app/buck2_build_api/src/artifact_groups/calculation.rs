@@ -23,6 +23,7 @@ use buck2_artifact::artifact::source_artifact::SourceArtifact;
 use buck2_common::dice::file_ops::DiceFileComputations;
 use buck2_common::file_ops::PathMetadata;
 use buck2_common::file_ops::PathMetadataOrRedirection;
+use buck2_common::file_ops::RawPathMetadata;
 use buck2_core::cells::cell_path::CellPath;
 use buck2_core::directory::DirectoryData;
 use buck2_error::BuckErrorContext;
@@ -282,7 +283,7 @@ async fn dir_artifact_value(
     // memory use. This diff introduces an intermediate DICE key `DirArtifactValueKey` for
     // getting the artifact value of a source directory. Every BuildKey
     // using that directory now only depends on one DirArtifactValueKey, and that DirArtifactValueKey
-    // depends on the PathMetadataKey of every member of the directory.
+    // depends on the listing of the directory with the metadata of every member.
     #[derive(Clone, Dupe, Display, Debug, Eq, Hash, PartialEq, Allocative)]
     #[display(fmt = "dir_artifact_value({})", .0)]
     struct DirArtifactValueKey(Arc<CellPath>);
@@ -296,9 +297,9 @@ async fn dir_artifact_value(
             ctx: &mut DiceComputations,
             _cancellation: &CancellationContext,
         ) -> Self::Value {
-            let files = DiceFileComputations::read_dir(ctx, self.0.as_ref().as_ref())
-                .await?
-                .included;
+            // Fetch the metadata of all the entries at once rather than one at a time.
+            let files =
+                DiceFileComputations::read_dir_with_metadata(ctx, self.0.as_ref().as_ref()).await?;
 
             let entries = ctx
                 .try_compute_join(files.iter(), |ctx, x| {
@@ -306,9 +307,12 @@ async fn dir_artifact_value(
                         // TODO(scottcao): This current creates a `DirArtifactValueKey` for each subdir of a source directory.
                         // Instead, this should be 1 key for the entire top-level directory since there's almost
                         // no chance of getting cache hit with a sub-directory.
-                        let value =
-                            path_artifact_value(ctx, Arc::new(self.0.as_ref().join(&x.file_name)))
-                                .await?;
+                        let value = metadata_artifact_value(
+                            ctx,
+                            Arc::new(self.0.as_ref().join(&x.file_name)),
+                            x.metadata.clone(),
+                        )
+                        .await?;
                         anyhow::Ok((x.file_name.clone(), value))
                     }
                     .boxed()
@@ -340,6 +344,15 @@ async fn path_artifact_value(
     cell_path: Arc<CellPath>,
 ) -> anyhow::Result<ActionDirectoryEntry<ActionSharedDirectory>> {
     let raw = DiceFileComputations::read_path_metadata(ctx, cell_path.as_ref().as_ref()).await?;
+    metadata_artifact_value(ctx, cell_path, raw).await
+}
+
+/// The artifact value of the source at `cell_path`, whose metadata is `raw`.
+async fn metadata_artifact_value(
+    ctx: &mut DiceComputations<'_>,
+    cell_path: Arc<CellPath>,
+    raw: RawPathMetadata,
+) -> anyhow::Result<ActionDirectoryEntry<ActionSharedDirectory>> {
     match PathMetadataOrRedirection::from(raw) {
         PathMetadataOrRedirection::PathMetadata(meta) => match meta {
             PathMetadata::ExternalSymlink(symlink) => Ok(ActionDirectoryEntry::Leaf(
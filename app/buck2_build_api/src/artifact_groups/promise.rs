@@ -15,9 +15,13 @@ use std::sync::OnceLock;
 use allocative::Allocative;
 use buck2_artifact::artifact::artifact_type::Artifact;
 use buck2_core::base_deferred_key::BaseDeferredKey;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
 use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
 use dupe::Dupe;
 use starlark::codemap::FileSpan;
+use starlark::values::Value;
+
+use crate::interpreter::rule_defs::artifact::starlark_artifact_like::ValueAsArtifactLike;
 
 #[derive(Debug, buck2_error::Error)]
 pub enum PromiseArtifactResolveError {
@@ -34,13 +38,19 @@ pub enum PromiseArtifactResolveError {
     #[error("Artifact promise resolved to artifact with associated artifacts, this isn't allowed")]
     HasAssociatedArtifacts,
     #[error(
-        "Artifact promise resolved to a source artifact, this isn't allowed: an artifact promise must resolve to a build artifact"
+        "Artifact promise{} resolved to source artifact `{1}`, this isn't allowed: an artifact promise must resolve to a build artifact",
+        maybe_declared_at(_0)
     )]
-    SourceArtifact,
+    SourceArtifactNotAllowed(Option<FileSpan>, String),
     #[error(
-        "assert_short_path() was called with `short_path = {0}`, but it did not match the artifact's actual short path: `{1}`"
+        "assert_short_path() was called with `short_path = {declared}`, but it did not match the artifact's actual short path: `{actual}`{}",
+        maybe_declared_at(location)
     )]
-    ShortPathMismatch(ForwardRelativePathBuf, String),
+    ShortPathMismatch {
+        declared: ForwardRelativePathBuf,
+        actual: String,
+        location: Option<FileSpan>,
+    },
     #[error("Internal error: analysis result did not contain promise with ID ({0})")]
     NotFoundInAnalysis(PromiseArtifactId),
     #[error(
@@ -56,6 +66,58 @@ fn maybe_declared_at(location: &Option<FileSpan>) -> String {
     }
 }
 
+/// Get the artifact that a Starlark promise used for a promise artifact resolved to. The value
+/// may be any artifact-like value, including another (already resolved) promise artifact, but
+/// it must be bound to a build artifact: promise artifacts can't point at sources.
+pub fn promised_artifact_from_value(
+    value: Value,
+    location: &Option<FileSpan>,
+) -> anyhow::Result<Artifact> {
+    let artifact = match ValueAsArtifactLike::unpack_value(value) {
+        Some(artifact) => artifact.0.get_bound_artifact()?,
+        None => return Err(PromiseArtifactResolveError::NotAnArtifact(value.to_repr()).into()),
+    };
+    if artifact.is_source() {
+        return Err(PromiseArtifactResolveError::SourceArtifactNotAllowed(
+            location.clone(),
+            value.to_repr(),
+        )
+        .into());
+    }
+    Ok(artifact)
+}
+
+/// Check that the artifact a promise resolved to has the short path declared for the promise,
+/// if any. `location` is where the promise was registered.
+pub fn check_short_path(
+    artifact: &Artifact,
+    declared: &Option<ForwardRelativePathBuf>,
+    location: &Option<FileSpan>,
+) -> anyhow::Result<()> {
+    if let Some(declared) = declared {
+        artifact
+            .get_path()
+            .with_short_path(|actual| check_short_path_matches(declared, actual, location))?;
+    }
+    Ok(())
+}
+
+fn check_short_path_matches(
+    declared: &ForwardRelativePathBuf,
+    actual: &ForwardRelativePath,
+    location: &Option<FileSpan>,
+) -> Result<(), PromiseArtifactResolveError> {
+    if actual != declared {
+        Err(PromiseArtifactResolveError::ShortPathMismatch {
+            declared: declared.clone(),
+            actual: actual.to_string(),
+            location: location.clone(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
 /// A PromiseArtifact is used to assert that a starlark promise will resolve to an artifact. While the analysis
 /// that declares the promise is running, the promise may not yet be resolved. In that case, operations that require
 /// the underlying artifact will fail. Once that analysis is complete, all promises will be resolved and this will
@@ -112,25 +174,17 @@ impl PromiseArtifact {
         &self,
         artifact: Artifact,
         expected_short_path: &Option<ForwardRelativePathBuf>,
+        location: &Option<FileSpan>,
     ) -> anyhow::Result<()> {
         let bound = artifact;
         if bound.is_source() {
-            return Err(PromiseArtifactResolveError::SourceArtifact.into());
-        }
-        if let Some(expected_short_path) = expected_short_path {
-            bound.get_path().with_short_path(|artifact_short_path| {
-                if artifact_short_path != expected_short_path {
-                    Err(anyhow::Error::from(
-                        PromiseArtifactResolveError::ShortPathMismatch(
-                            expected_short_path.clone(),
-                            artifact_short_path.to_string(),
-                        ),
-                    ))
-                } else {
-                    Ok(())
-                }
-            })?;
+            return Err(PromiseArtifactResolveError::SourceArtifactNotAllowed(
+                location.clone(),
+                bound.to_string(),
+            )
+            .into());
         }
+        check_short_path(&bound, expected_short_path, location)?;
         match self.artifact.set(bound) {
             Ok(_) => Ok(()),
             Err(_) => Err(PromiseArtifactResolveError::AlreadyResolved.into()),
@@ -173,3 +227,90 @@ impl PartialEq for PromiseArtifact {
 }
 
 impl Eq for PromiseArtifact {}
+
+#[cfg(test)]
+mod tests {
+    use buck2_artifact::artifact::artifact_type::testing::BuildArtifactTestingExt;
+    use buck2_artifact::artifact::artifact_type::Artifact;
+    use buck2_artifact::artifact::build_artifact::BuildArtifact;
+    use buck2_artifact::deferred::id::DeferredId;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+    use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+    use starlark::codemap::FileSpan;
+
+    use crate::artifact_groups::promise::check_short_path;
+    use crate::artifact_groups::promise::check_short_path_matches;
+    use crate::artifact_groups::promise::PromiseArtifactResolveError;
+
+    #[test]
+    fn test_check_short_path_matches() {
+        let declared = ForwardRelativePathBuf::unchecked_new("foo/bar.txt".to_owned());
+        assert!(
+            check_short_path_matches(
+                &declared,
+                ForwardRelativePath::new("foo/bar.txt").unwrap(),
+                &None
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_check_short_path_mismatch() {
+        let declared = ForwardRelativePathBuf::unchecked_new("foo/bar.txt".to_owned());
+        let location = FileSpan::new("pkg/BUCK".to_owned(), "anon_rule()".to_owned());
+        let err = check_short_path_matches(
+            &declared,
+            ForwardRelativePath::new("foo/baz.txt").unwrap(),
+            &Some(location.clone()),
+        )
+        .unwrap_err();
+        match &err {
+            PromiseArtifactResolveError::ShortPathMismatch {
+                declared: d,
+                actual,
+                location: l,
+            } => {
+                assert_eq!(&declared, d);
+                assert_eq!("foo/baz.txt", actual);
+                assert_eq!(Some(&location), l.as_ref());
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert!(
+            err.to_string().contains("(declared at pkg/BUCK:"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_check_short_path_not_declared() {
+        let target =
+            ConfiguredTargetLabel::testing_parse("cell//pkg:foo", ConfigurationData::testing_new());
+        let artifact = Artifact::from(BuildArtifact::testing_new(
+            target,
+            ForwardRelativePathBuf::unchecked_new("foo/bar.txt".to_owned()),
+            DeferredId::testing_new(0),
+        ));
+        let location = Some(FileSpan::new(
+            "pkg/BUCK".to_owned(),
+            "anon_rule()".to_owned(),
+        ));
+
+        // Without a declared short path, any artifact is accepted.
+        assert!(check_short_path(&artifact, &None, &location).is_ok());
+
+        let declared = Some(ForwardRelativePathBuf::unchecked_new(
+            "foo/baz.txt".to_owned(),
+        ));
+        let err = check_short_path(&artifact, &declared, &location).unwrap_err();
+        assert!(
+            err.to_string().contains("(declared at pkg/BUCK:"),
+            "{}",
+            err
+        );
+    }
+}
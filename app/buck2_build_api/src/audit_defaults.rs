@@ -0,0 +1,72 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use buck2_core::cells::build_file_cell::BuildFileCell;
+use buck2_core::cells::CellResolver;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_interpreter::parse_import::parse_bzl_path_with_config;
+use buck2_interpreter::parse_import::ParseImportOptions;
+use buck2_interpreter::parse_import::RelativeImports;
+use buck2_node::attrs::describe::AttributeDescription;
+use buck2_node::rule::RULE_DEFINITION_CALCULATION;
+use buck2_node::rule_type::StarlarkRuleType;
+use dice::DiceComputations;
+use starlark_map::small_map::SmallMap;
+
+#[derive(Debug, buck2_error::Error)]
+enum AuditDefaultsError {
+    #[error("Rule type must be of the form `cell//path:file.bzl:rule_name`, got `{0}`")]
+    InvalidRuleType(String),
+}
+
+/// Parse a rule type like `cell//path:file.bzl:rule_name`, as accepted on the command line.
+pub fn parse_rule_type(
+    rule_type: &str,
+    working_dir: &ProjectRelativePath,
+    cell_resolver: &CellResolver,
+) -> anyhow::Result<StarlarkRuleType> {
+    let (import_path, name) = rule_type
+        .rsplit_once(':')
+        .filter(|(_, name)| !name.is_empty())
+        .ok_or_else(|| AuditDefaultsError::InvalidRuleType(rule_type.to_owned()))?;
+
+    let current_cell_path = cell_resolver.get_cell_path(working_dir)?;
+    let cell_alias_resolver = cell_resolver.get_cwd_cell_alias_resolver(working_dir)?;
+    let import_path = parse_bzl_path_with_config(
+        cell_alias_resolver,
+        import_path,
+        &ParseImportOptions {
+            relative_import_option: RelativeImports::Allow {
+                current_dir: &current_cell_path,
+            },
+            allow_missing_at_symbol: true,
+        },
+        BuildFileCell::new(current_cell_path.cell()),
+    )?;
+
+    Ok(StarlarkRuleType {
+        import_path,
+        name: name.to_owned(),
+    })
+}
+
+/// Describe the attributes of a rule, with their default values.
+pub async fn audit_defaults(
+    rule_type: &str,
+    working_dir: &ProjectRelativePath,
+    cell_resolver: &CellResolver,
+    dice_ctx: &mut DiceComputations<'_>,
+) -> anyhow::Result<SmallMap<String, AttributeDescription>> {
+    let rule_type = parse_rule_type(rule_type, working_dir, cell_resolver)?;
+    let rule = RULE_DEFINITION_CALCULATION
+        .get()?
+        .rule_definition(dice_ctx, &rule_type)
+        .await?;
+    rule.attributes.describe()
+}
@@ -60,6 +60,7 @@ use crate::keep_going::KeepGoing;
 
 mod action_error;
 pub mod build_report;
+pub mod cache_attribution;
 mod graph_size;
 /// The types of provider to build on the configured providers label
 #[derive(Debug, Clone, Dupe, Allocative)]
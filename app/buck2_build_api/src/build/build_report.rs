@@ -46,6 +46,8 @@ use serde::Serialize;
 use starlark_map::small_set::SmallSet;
 
 use crate::build::action_error::BuildReportActionError;
+use crate::build::cache_attribution::CacheAttributionCollector;
+use crate::build::cache_attribution::TargetCacheAttribution;
 use crate::build::BuildProviderType;
 use crate::build::ConfiguredBuildTargetResult;
 
@@ -75,6 +77,10 @@ pub struct BuildReport {
     project_root: AbsNormPathBuf,
     truncated: bool,
     strings: BTreeMap<String, String>,
+    /// Action cache hits and misses by the target owning the actions. Only filled when requested,
+    /// because it can be large.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_attribution: Option<BTreeMap<String, TargetCacheAttribution>>,
 }
 
 /// The fields that stored in the unconfigured `BuildReportEntry` for buck1 backcompat.
@@ -188,6 +194,7 @@ impl<'a> BuildReportCollector<'a> {
         include_package_project_relative_paths: bool,
         configured: &BTreeMap<ConfiguredProvidersLabel, Option<ConfiguredBuildTargetResult>>,
         other_errors: &BTreeMap<Option<ProvidersLabel>, Vec<buck2_error::Error>>,
+        cache_attribution: Option<&CacheAttributionCollector>,
     ) -> BuildReport {
         let mut this: BuildReportCollector<'_> = Self {
             artifact_fs,
@@ -246,6 +253,7 @@ impl<'a> BuildReportCollector<'a> {
             // Setting this to false since we don't currently truncate buck2's build report.
             truncated: false,
             strings: this.strings,
+            cache_attribution: cache_attribution.map(|c| c.report()),
         }
     }
 
@@ -543,6 +551,7 @@ pub fn generate_build_report(
     trace_id: &TraceId,
     configured: &BTreeMap<ConfiguredProvidersLabel, Option<ConfiguredBuildTargetResult>>,
     other_errors: &BTreeMap<Option<ProvidersLabel>, Vec<buck2_error::Error>>,
+    cache_attribution: Option<&CacheAttributionCollector>,
 ) -> Result<Option<String>, buck2_error::Error> {
     let build_report = BuildReportCollector::convert(
         trace_id,
@@ -555,6 +564,7 @@ pub fn generate_build_report(
         opts.unstable_include_package_project_relative_paths,
         configured,
         other_errors,
+        cache_attribution,
     );
 
     let mut serialized_build_report = None;
@@ -0,0 +1,305 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Attribution of action cache hits and misses to the targets owning the actions, for the build
+//! report.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use buck2_artifact::actions::key::ActionKey;
+use buck2_core::base_deferred_key::BaseDeferredKey;
+use dashmap::DashMap;
+use dice::UserComputationData;
+use dupe::Dupe;
+use serde::Serialize;
+
+/// How the result of an action was obtained.
+#[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq)]
+pub enum ActionOutcome {
+    /// A command was executed locally (including via a worker).
+    Local,
+    /// A command was executed remotely.
+    Remote,
+    /// The result was served from an action cache (including dep file caches).
+    ActionCache,
+    /// No command had to run, e.g. the action was a `write` or the local dep file cache showed
+    /// the outputs were up to date.
+    Skipped,
+}
+
+impl ActionOutcome {
+    pub fn from_execution_kind(kind: buck2_data::ActionExecutionKind) -> Self {
+        use buck2_data::ActionExecutionKind;
+
+        match kind {
+            ActionExecutionKind::Local | ActionExecutionKind::LocalWorker => ActionOutcome::Local,
+            ActionExecutionKind::Remote => ActionOutcome::Remote,
+            ActionExecutionKind::ActionCache | ActionExecutionKind::RemoteDepFileCache => {
+                ActionOutcome::ActionCache
+            }
+            ActionExecutionKind::LocalDepFile
+            | ActionExecutionKind::Simple
+            | ActionExecutionKind::Deferred
+            | ActionExecutionKind::NotSet => ActionOutcome::Skipped,
+        }
+    }
+
+    /// Whether the action had to run a command because its result wasn't cached.
+    pub fn is_miss(self) -> bool {
+        matches!(self, ActionOutcome::Local | ActionOutcome::Remote)
+    }
+}
+
+/// DO NOT UPDATE WITHOUT UPDATING `docs/users/build_observability/build_report.md`!
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct ActionOutcomeCounts {
+    local: u64,
+    remote: u64,
+    action_cache: u64,
+    skipped: u64,
+    /// Execution wall time of the actions which missed the cache, in milliseconds.
+    miss_wall_time_ms: u64,
+}
+
+impl ActionOutcomeCounts {
+    fn record(&mut self, outcome: ActionOutcome, wall_time: Option<Duration>) {
+        match outcome {
+            ActionOutcome::Local => self.local += 1,
+            ActionOutcome::Remote => self.remote += 1,
+            ActionOutcome::ActionCache => self.action_cache += 1,
+            ActionOutcome::Skipped => self.skipped += 1,
+        }
+        if outcome.is_miss() {
+            self.miss_wall_time_ms += wall_time.map_or(0, |t| t.as_millis() as u64);
+        }
+    }
+}
+
+/// DO NOT UPDATE WITHOUT UPDATING `docs/users/build_observability/build_report.md`!
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct TargetCacheAttribution {
+    #[serde(flatten)]
+    total: ActionOutcomeCounts,
+    categories: BTreeMap<String, ActionOutcomeCounts>,
+}
+
+#[derive(Debug, Clone)]
+struct RecordedAction {
+    category: String,
+    outcome: ActionOutcome,
+    wall_time: Option<Duration>,
+}
+
+/// Aggregates the outcome of every action built in a command by the owner of the action.
+///
+/// Actions are recorded by key, so an action is counted once however many times its outputs are
+/// requested.
+#[derive(Default)]
+pub struct CacheAttributionCollector {
+    actions: DashMap<ActionKey, RecordedAction>,
+}
+
+impl CacheAttributionCollector {
+    /// Record an action which was executed in this command.
+    pub fn record(
+        &self,
+        key: &ActionKey,
+        category: &str,
+        outcome: ActionOutcome,
+        wall_time: Option<Duration>,
+    ) {
+        self.actions.insert(
+            key.dupe(),
+            RecordedAction {
+                category: category.to_owned(),
+                outcome,
+                wall_time,
+            },
+        );
+    }
+
+    pub fn contains(&self, key: &ActionKey) -> bool {
+        self.actions.contains_key(key)
+    }
+
+    /// Record an action whose outputs were requested in this command. Unless it was executed in
+    /// this command, its outputs were already up to date (e.g. on a warm build).
+    pub fn record_up_to_date(&self, key: &ActionKey, category: &str) {
+        self.actions
+            .entry(key.dupe())
+            .or_insert_with(|| RecordedAction {
+                category: category.to_owned(),
+                outcome: ActionOutcome::Skipped,
+                wall_time: None,
+            });
+    }
+
+    /// The attribution keyed by the owner of the actions. Configured targets, anon targets and
+    /// BXL functions are all keyed by how they are displayed.
+    pub fn report(&self) -> BTreeMap<String, TargetCacheAttribution> {
+        let mut targets = HashMap::<BaseDeferredKey, TargetCacheAttribution>::new();
+        for entry in self.actions.iter() {
+            let action = entry.value();
+            let target = targets.entry(entry.key().owner().dupe()).or_default();
+            target.total.record(action.outcome, action.wall_time);
+            target
+                .categories
+                .entry(action.category.clone())
+                .or_default()
+                .record(action.outcome, action.wall_time);
+        }
+        targets
+            .into_iter()
+            .map(|(owner, target)| (owner.to_string(), target))
+            .collect()
+    }
+}
+
+pub trait SetCacheAttribution {
+    fn set_cache_attribution(&mut self, collector: Arc<CacheAttributionCollector>);
+}
+
+impl SetCacheAttribution for UserComputationData {
+    fn set_cache_attribution(&mut self, collector: Arc<CacheAttributionCollector>) {
+        self.data.set(collector);
+    }
+}
+
+pub trait HasCacheAttribution {
+    /// Only set when the cache attribution was requested for the build report.
+    fn get_cache_attribution(&self) -> Option<&Arc<CacheAttributionCollector>>;
+}
+
+impl HasCacheAttribution for UserComputationData {
+    fn get_cache_attribution(&self) -> Option<&Arc<CacheAttributionCollector>> {
+        self.data.get::<Arc<CacheAttributionCollector>>().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use buck2_artifact::actions::key::ActionKey;
+    use buck2_artifact::deferred::id::DeferredId;
+    use buck2_artifact::deferred::key::DeferredKey;
+    use buck2_core::base_deferred_key::BaseDeferredKey;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::target::label::label::TargetLabel;
+
+    use crate::build::cache_attribution::ActionOutcome;
+    use crate::build::cache_attribution::ActionOutcomeCounts;
+    use crate::build::cache_attribution::CacheAttributionCollector;
+
+    fn owner(name: &str) -> BaseDeferredKey {
+        BaseDeferredKey::TargetLabel(
+            TargetLabel::testing_parse(name).configure(ConfigurationData::testing_new()),
+        )
+    }
+
+    fn action(name: &str, id: u32) -> ActionKey {
+        ActionKey::unchecked_new(DeferredKey::Base(owner(name), DeferredId::testing_new(id)))
+    }
+
+    #[test]
+    fn test_outcome_from_execution_kind() {
+        use buck2_data::ActionExecutionKind;
+
+        assert_eq!(
+            ActionOutcome::Local,
+            ActionOutcome::from_execution_kind(ActionExecutionKind::LocalWorker)
+        );
+        assert_eq!(
+            ActionOutcome::ActionCache,
+            ActionOutcome::from_execution_kind(ActionExecutionKind::RemoteDepFileCache)
+        );
+        assert_eq!(
+            ActionOutcome::Skipped,
+            ActionOutcome::from_execution_kind(ActionExecutionKind::LocalDepFile)
+        );
+    }
+
+    #[test]
+    fn test_rollup() {
+        let collector = CacheAttributionCollector::default();
+        let secs = |s| Some(Duration::from_secs(s));
+
+        let foo = |id| action("root//:foo", id);
+        collector.record(&foo(0), "cxx_compile", ActionOutcome::Local, secs(2));
+        collector.record(&foo(1), "cxx_compile", ActionOutcome::Remote, secs(3));
+        collector.record(&foo(2), "cxx_compile", ActionOutcome::ActionCache, secs(7));
+        collector.record(&foo(3), "cxx_link", ActionOutcome::Local, secs(5));
+        collector.record(&foo(4), "write", ActionOutcome::Skipped, None);
+        collector.record(
+            &action("root//:bar", 0),
+            "cxx_compile",
+            ActionOutcome::ActionCache,
+            secs(1),
+        );
+        // Requesting the outputs of executed actions doesn't count them again.
+        collector.record_up_to_date(&foo(0), "cxx_compile");
+        collector.record_up_to_date(&foo(3), "cxx_link");
+
+        let report = collector.report();
+        assert_eq!(2, report.len());
+
+        let foo = &report[&owner("root//:foo").to_string()];
+        assert_eq!(
+            ActionOutcomeCounts {
+                local: 2,
+                remote: 1,
+                action_cache: 1,
+                skipped: 1,
+                // Cache hits don't count towards the miss time.
+                miss_wall_time_ms: 10_000,
+            },
+            foo.total
+        );
+        assert_eq!(
+            ActionOutcomeCounts {
+                local: 1,
+                remote: 1,
+                action_cache: 1,
+                skipped: 0,
+                miss_wall_time_ms: 5_000,
+            },
+            foo.categories["cxx_compile"]
+        );
+        assert_eq!(
+            vec!["cxx_compile", "cxx_link", "write"],
+            foo.categories.keys().collect::<Vec<_>>()
+        );
+
+        let bar = &report[&owner("root//:bar").to_string()];
+        assert_eq!(1, bar.total.action_cache);
+        assert_eq!(0, bar.total.miss_wall_time_ms);
+    }
+
+    #[test]
+    fn test_up_to_date() {
+        let collector = CacheAttributionCollector::default();
+        let foo = action("root//:foo", 0);
+        collector.record_up_to_date(&foo, "cxx_link");
+        collector.record_up_to_date(&foo, "cxx_link");
+
+        let report = collector.report();
+        let foo = &report[&owner("root//:foo").to_string()];
+        assert_eq!(
+            ActionOutcomeCounts {
+                skipped: 1,
+                ..Default::default()
+            },
+            foo.total
+        );
+        assert_eq!(1, foo.categories["cxx_link"].skipped);
+    }
+}
@@ -9,6 +9,7 @@
 
 use std::sync::Arc;
 
+use anyhow::Context;
 use buck2_common::dice::cells::SetCellResolver;
 use buck2_common::dice::data::SetIoProvider;
 use buck2_common::io::IoProvider;
@@ -60,10 +61,24 @@ pub async fn configure_dice_for_buck(
         Ok,
     )?;
 
+    let max_cache_weight = root_config
+        .map(|c| {
+            c.parse::<usize>(BuckconfigKeyRef {
+                section: "buck2",
+                property: "dice_max_cache_weight",
+            })
+        })
+        .transpose()?
+        .flatten();
+
     let mut dice = match which_dice {
         WhichDice::Legacy => Dice::builder(),
         WhichDice::Modern => Dice::modern(),
     };
+    if let Some(max_cache_weight) = max_cache_weight {
+        dice.max_cache_weight(max_cache_weight)
+            .context("Invalid `buck2.dice_max_cache_weight`")?;
+    }
     dice.set_io_provider(io);
     dice.set_digest_config(digest_config);
 
@@ -71,7 +86,7 @@ pub async fn configure_dice_for_buck(
     let mut dice_ctx = dice.updater();
     dice_ctx.set_none_cell_resolver()?;
     dice_ctx.set_none_legacy_configs()?;
-    dice_ctx.set_none_legacy_config_overrides()?;
+    dice_ctx.set_none_legacy_config_overlay()?;
     dice_ctx.commit().await;
 
     Ok(dice)
@@ -8,6 +8,7 @@
  */
 
 use buck2_common::global_cfg_options::GlobalCfgOptions;
+use buck2_core::buck2_env;
 use buck2_core::configuration::compatibility::IncompatiblePlatformReason;
 use buck2_core::configuration::compatibility::MaybeCompatible;
 use buck2_core::package::PackageLabel;
@@ -25,9 +26,23 @@ use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use dice::DiceComputations;
 use dupe::Dupe;
 use futures::FutureExt;
+use futures::StreamExt;
 use gazebo::prelude::VecExt;
 use starlark_map::small_set::SmallSet;
 
+/// How many target patterns, or top-level targets, to resolve concurrently when resolving
+/// command line or query literals.
+///
+/// This is bounded so that a command with many patterns doesn't start loading all the packages at once.
+pub fn pattern_resolution_concurrency() -> anyhow::Result<usize> {
+    Ok(buck2_env!(
+        "BUCK2_PATTERN_RESOLUTION_CONCURRENCY",
+        type = usize,
+        default = 200
+    )?
+    .max(1))
+}
+
 // Returns a tuple of compatible and incompatible targets.
 fn split_compatible_incompatible(
     targets: impl IntoIterator<Item = anyhow::Result<MaybeCompatible<ConfiguredTargetNode>>>,
@@ -87,9 +102,20 @@ pub async fn get_maybe_compatible_targets<'a>(
         }
     }
 
-    Ok(futures::future::join_all(ctx.compute_many(by_package_fns))
-        .await
-        .into_iter())
+    // Configure targets concurrently, but bounded, and gather the results in the order of the
+    // loaded targets regardless of which configuration finishes first.
+    let mut results: Vec<(usize, _)> = futures::stream::iter(
+        ctx.compute_many(by_package_fns)
+            .into_iter()
+            .enumerate()
+            .map(|(index, fut)| fut.map(move |result| (index, result))),
+    )
+    .buffer_unordered(pattern_resolution_concurrency()?)
+    .collect()
+    .await;
+    results.sort_by_key(|(index, _)| *index);
+
+    Ok(results.into_iter().map(|(_, result)| result))
 }
 
 /// Converts target nodes to a set of compatible configured target nodes.
@@ -22,6 +22,8 @@ pub mod unpack_artifact;
 use std::fmt::Debug;
 
 use buck2_core::base_deferred_key::BaseDeferredKey;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use buck2_execute::execute::request::OutputType;
 
 #[derive(Debug, buck2_error::Error)]
 pub(crate) enum ArtifactError {
@@ -45,4 +47,25 @@ pub(crate) enum ArtifactError {
         only declared artifacts can be used as an output"
     )]
     PromiseArtifactAsOutput { artifact_repr: String },
+    #[error(
+        "attempted to project `{path}` out of artifact {artifact_repr}, but it was declared as a \
+        file. Only directory artifacts can be projected."
+    )]
+    ProjectFromFile { artifact_repr: String, path: String },
+}
+
+/// Projecting a sub-path only makes sense for directories, so reject artifacts known to be files.
+pub(crate) fn check_projectable(
+    output_type: OutputType,
+    artifact_repr: impl FnOnce() -> String,
+    path: &ForwardRelativePath,
+) -> anyhow::Result<()> {
+    if output_type == OutputType::File && !path.is_empty() {
+        return Err(ArtifactError::ProjectFromFile {
+            artifact_repr: artifact_repr(),
+            path: path.to_string(),
+        }
+        .into());
+    }
+    Ok(())
 }
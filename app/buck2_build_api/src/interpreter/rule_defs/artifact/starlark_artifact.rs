@@ -36,6 +36,7 @@ use starlark::values::Value;
 
 use crate::artifact_groups::ArtifactGroup;
 use crate::interpreter::rule_defs::artifact::associated::AssociatedArtifacts;
+use crate::interpreter::rule_defs::artifact::check_projectable;
 use crate::interpreter::rule_defs::artifact::methods::artifact_methods;
 use crate::interpreter::rule_defs::artifact::methods::EitherStarlarkArtifact;
 use crate::interpreter::rule_defs::artifact::starlark_artifact_like::ArtifactFingerprint;
@@ -205,6 +206,9 @@ impl StarlarkArtifactLike for StarlarkArtifact {
         hide_prefix: bool,
     ) -> anyhow::Result<EitherStarlarkArtifact> {
         let path = ForwardRelativePath::new(path)?;
+        if let BaseArtifactKind::Build(b) = self.artifact.as_parts().0 {
+            check_projectable(b.output_type(), || self.to_string(), path)?;
+        }
         Ok(EitherStarlarkArtifact::Artifact(StarlarkArtifact {
             artifact: self.artifact.dupe().project(path, hide_prefix),
             associated_artifacts: self.associated_artifacts.dupe(),
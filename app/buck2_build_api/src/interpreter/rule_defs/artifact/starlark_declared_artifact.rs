@@ -45,6 +45,7 @@ use starlark::values::ValueTyped;
 
 use crate::artifact_groups::ArtifactGroup;
 use crate::interpreter::rule_defs::artifact::associated::AssociatedArtifacts;
+use crate::interpreter::rule_defs::artifact::check_projectable;
 use crate::interpreter::rule_defs::artifact::methods::artifact_methods;
 use crate::interpreter::rule_defs::artifact::methods::EitherStarlarkArtifact;
 use crate::interpreter::rule_defs::artifact::starlark_artifact::StarlarkArtifact;
@@ -208,6 +209,7 @@ impl StarlarkArtifactLike for StarlarkDeclaredArtifact {
         hide_prefix: bool,
     ) -> anyhow::Result<EitherStarlarkArtifact> {
         let path = ForwardRelativePath::new(path)?;
+        check_projectable(self.artifact.output_type(), || self.to_string(), path)?;
         // Not sure if this.declaration_location is or the project() call is more appropriate here.
         Ok(EitherStarlarkArtifact::DeclaredArtifact(
             StarlarkDeclaredArtifact {
@@ -36,6 +36,7 @@ use starlark::environment::Methods;
 use starlark::environment::MethodsBuilder;
 use starlark::environment::MethodsStatic;
 use starlark::typing::Ty;
+use starlark::values::dict::DictRef;
 use starlark::values::list::ListRef;
 use starlark::values::list::UnpackList;
 use starlark::values::starlark_value;
@@ -548,7 +549,7 @@ impl<'v> StarlarkCmdArgs<'v> {
 
     pub fn try_from_value(value: Value<'v>) -> anyhow::Result<Self> {
         let mut builder = Self::new();
-        builder.0.get_mut().add_value(value)?;
+        builder.0.get_mut().add_value(value, true)?;
         Ok(builder)
     }
 }
@@ -557,17 +558,29 @@ impl<'v> StarlarkCmdArgs<'v> {
 enum StarlarkCommandLineValueUnpack<'v> {
     // This should be `list[Self]`, but we cannot express it.
     List(&'v ListRef<'v>),
+    // This should be `dict[Self, Self]`, but we cannot express it.
+    Dict(DictRef<'v>),
     CommandLineArg(CommandLineArg<'v>),
 }
 
 impl<'v> StarlarkCommandLineData<'v> {
-    fn add_value(&mut self, value: Value<'v>) -> anyhow::Result<()> {
-        self.add_value_typed(StarlarkCommandLineValueUnpack::unpack_value_err(value)?)
+    fn add_value(&mut self, value: Value<'v>, sort_keys: bool) -> anyhow::Result<()> {
+        self.add_value_typed(
+            StarlarkCommandLineValueUnpack::unpack_value_err(value)?,
+            sort_keys,
+        )
     }
 
-    fn add_value_typed(&mut self, value: StarlarkCommandLineValueUnpack<'v>) -> anyhow::Result<()> {
+    fn add_value_typed(
+        &mut self,
+        value: StarlarkCommandLineValueUnpack<'v>,
+        sort_keys: bool,
+    ) -> anyhow::Result<()> {
         match value {
-            StarlarkCommandLineValueUnpack::List(values) => self.add_values(values.content())?,
+            StarlarkCommandLineValueUnpack::List(values) => {
+                self.add_values(values.content(), sort_keys)?
+            }
+            StarlarkCommandLineValueUnpack::Dict(dict) => self.add_dict(&dict, sort_keys)?,
             StarlarkCommandLineValueUnpack::CommandLineArg(value) => self.items.push(value),
         }
         Ok(())
@@ -575,11 +588,26 @@ impl<'v> StarlarkCommandLineData<'v> {
 
     /// Check the types of a list of values, and modify `data` accordingly
     ///
-    /// The values must be one of: CommandLineArgLike or a list thereof.
-    fn add_values(&mut self, values: &[Value<'v>]) -> anyhow::Result<()> {
+    /// The values must be one of: CommandLineArgLike, or a list or dict thereof.
+    fn add_values(&mut self, values: &[Value<'v>], sort_keys: bool) -> anyhow::Result<()> {
         self.items.reserve(values.len());
         for value in values {
-            self.add_value(*value)?
+            self.add_value(*value, sort_keys)?
+        }
+        Ok(())
+    }
+
+    /// Add each key of the dict followed by its value. Unless `sort_keys` is unset, the entries
+    /// are added in key order rather than in the order the dict was built in, so the command line
+    /// doesn't depend on it.
+    fn add_dict(&mut self, dict: &DictRef<'v>, sort_keys: bool) -> anyhow::Result<()> {
+        let mut entries: Vec<_> = dict.iter().collect();
+        if sort_keys {
+            entries.sort_by_cached_key(|(k, _)| k.to_str());
+        }
+        for (k, v) in entries {
+            self.add_value(k, sort_keys)?;
+            self.add_value(v, sort_keys)?;
         }
         Ok(())
     }
@@ -652,7 +680,7 @@ fn cmd_args_methods(builder: &mut MethodsBuilder) {
         mut this: StarlarkCommandLineMut<'v>,
         #[starlark(args)] args: UnpackTuple<Value<'v>>,
     ) -> anyhow::Result<StarlarkCommandLineMut<'v>> {
-        this.borrow.add_values(&args.items)?;
+        this.borrow.add_values(&args.items, true)?;
         Ok(this)
     }
 
@@ -809,6 +837,7 @@ pub fn register_cmd_args(builder: &mut GlobalsBuilder) {
     /// * `parent` - for all the artifacts use their parent directory.
     /// * `relative_to` - make all artifact paths relative to a given location.
     /// * `replace_regex` - replaces arguments with a regular expression.
+    /// * `sort_keys` - if `False`, dicts are added in insertion order rather than sorted by key.
     ///
     /// ## `ignore_artifacts`
     ///
@@ -881,6 +910,17 @@ pub fn register_cmd_args(builder: &mut GlobalsBuilder) {
     ///
     /// Replaces all parts matching pattern regular expression (or regular expressions)
     /// in each argument with replacement strings.
+    ///
+    /// # `sort_keys`
+    ///
+    /// A dict among the arguments adds each of its keys followed by its value. The entries are
+    /// added sorted by key, so the command line, and the digest of the action running it, doesn't
+    /// depend on the order the dict was built in. Pass `sort_keys = False` to keep insertion order
+    /// if the tool relies on it.
+    ///
+    /// ```python
+    /// cmd_args({"--input": src, "--output": out.as_output()})
+    /// ```
     fn cmd_args<'v>(
         #[starlark(args)] args: UnpackTuple<StarlarkCommandLineValueUnpack<'v>>,
         hidden: Option<Value<'v>>,
@@ -899,6 +939,7 @@ pub fn register_cmd_args(builder: &mut GlobalsBuilder) {
             (CmdArgsRegex<'v>, StringValue<'v>),
             UnpackList<(CmdArgsRegex<'v>, StringValue<'v>)>,
         >,
+        #[starlark(default = true)] sort_keys: bool,
     ) -> anyhow::Result<StarlarkCmdArgs<'v>> {
         let quote = quote.try_map(QuoteStyle::parse)?;
         let mut builder = StarlarkCommandLineData::default();
@@ -937,7 +978,7 @@ pub fn register_cmd_args(builder: &mut GlobalsBuilder) {
             builder.options_mut().replacements = Some(Box::new(replace_regex));
         }
         for v in args.items {
-            builder.add_value_typed(v)?;
+            builder.add_value_typed(v, sort_keys)?;
         }
         if let Some(hidden) = hidden {
             builder.add_hidden(&[hidden])?;
@@ -118,10 +118,10 @@ impl<'v> AnalysisActions<'v> {
             )
         };
 
-        for consumer_artifact in consumer_analysis_artifacts {
+        for (consumer_artifact, location) in consumer_analysis_artifacts {
             let artifact = (GET_PROMISED_ARTIFACT.get()?)(&consumer_artifact, dice).await?;
             let short_path = short_path_assertions.get(consumer_artifact.id()).cloned();
-            consumer_artifact.resolve(artifact.clone(), &short_path)?;
+            consumer_artifact.resolve(artifact.clone(), &short_path, &location)?;
         }
         Ok(())
     }
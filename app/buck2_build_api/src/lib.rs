@@ -24,6 +24,7 @@ pub mod analysis;
 pub mod artifact_groups;
 pub mod attrs;
 pub mod audit_cell;
+pub mod audit_defaults;
 pub mod audit_dep_files;
 pub mod audit_output;
 pub mod build;
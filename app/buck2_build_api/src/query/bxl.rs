@@ -42,6 +42,12 @@ pub trait BxlCqueryFunctions: Send {
         from: &TargetSet<ConfiguredTargetNode>,
         to: &TargetSet<ConfiguredTargetNode>,
     ) -> anyhow::Result<TargetSet<ConfiguredTargetNode>>;
+    async fn shortestpath(
+        &self,
+        dice: &mut DiceComputations<'_>,
+        from: &TargetSet<ConfiguredTargetNode>,
+        to: &TargetSet<ConfiguredTargetNode>,
+    ) -> anyhow::Result<TargetSet<ConfiguredTargetNode>>;
     async fn owner(
         &self,
         dice: &mut DiceComputations<'_>,
@@ -88,6 +94,12 @@ pub trait BxlUqueryFunctions: Send {
         from: &TargetSet<TargetNode>,
         to: &TargetSet<TargetNode>,
     ) -> anyhow::Result<TargetSet<TargetNode>>;
+    async fn shortestpath(
+        &self,
+        dice: &mut DiceComputations<'_>,
+        from: &TargetSet<TargetNode>,
+        to: &TargetSet<TargetNode>,
+    ) -> anyhow::Result<TargetSet<TargetNode>>;
     async fn deps(
         &self,
         dice: &mut DiceComputations<'_>,
@@ -133,6 +145,12 @@ pub trait BxlAqueryFunctions: Send {
         from: &TargetSet<ActionQueryNode>,
         to: &TargetSet<ActionQueryNode>,
     ) -> anyhow::Result<TargetSet<ActionQueryNode>>;
+    async fn shortestpath(
+        &self,
+        dice: &mut DiceComputations<'_>,
+        from: &TargetSet<ActionQueryNode>,
+        to: &TargetSet<ActionQueryNode>,
+    ) -> anyhow::Result<TargetSet<ActionQueryNode>>;
     async fn deps(
         &self,
         dice: &mut DiceComputations<'_>,
@@ -172,6 +190,11 @@ pub trait BxlAqueryFunctions: Send {
         dice: &mut DiceComputations<'_>,
         targets: &TargetSet<ActionQueryNode>,
     ) -> anyhow::Result<TargetSet<ActionQueryNode>>;
+    async fn all_inputs(
+        &self,
+        dice: &mut DiceComputations<'_>,
+        targets: &TargetSet<ActionQueryNode>,
+    ) -> anyhow::Result<TargetSet<ActionQueryNode>>;
 }
 
 pub static NEW_BXL_CQUERY_FUNCTIONS: LateBinding<
@@ -53,6 +53,7 @@ use buck2_interpreter_for_build::interpreter::configuror::BuildInterpreterConfig
 use buck2_interpreter_for_build::interpreter::dice_calculation_delegate::testing::EvalImportKey;
 use buck2_interpreter_for_build::interpreter::interpreter_setup::setup_interpreter_basic;
 use buck2_interpreter_for_build::interpreter::testing::Tester;
+use buck2_interpreter_for_build::nodes::validate_visibility::ValidateVisibility;
 use buck2_interpreter_for_build::rule::register_rule_function;
 use dice::testing::DiceBuilder;
 use dice::UserComputationData;
@@ -176,6 +177,7 @@ async fn test_analysis_calculation() -> anyhow::Result<()> {
             None,
             false,
             false,
+            ValidateVisibility::Off,
             None,
             Arc::new(ConcurrentTargetLabelInterner::default()),
         )?,
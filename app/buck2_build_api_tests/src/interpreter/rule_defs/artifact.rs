@@ -260,8 +260,33 @@ fn project_declared_artifact() -> buck2_error::Result<()> {
                 assert_eq("<output artifact for out/qux.so>", repr(unbound.as_output()))
                 assert_eq("qux.so", unbound.basename)
                 assert_eq(".so", unbound.extension)
+
+                nested = declared_artifact("out").project("lib").project("qux.so")
+                assert_eq("<build artifact out/lib/qux.so>", repr(nested))
+                assert_eq("out/lib/qux.so", nested.short_path)
+                assert_eq("qux.so", nested.basename)
+                assert_eq(".so", nested.extension)
+                assert_eq(nested, declared_artifact("out").project("lib/qux.so"))
+                assert_eq(False, nested == declared_artifact("out").project("lib/qux.a"))
+
+                bound = bound_artifact("//foo:bar", "baz").project("include").project("quz.h")
+                assert_eq("baz/include/quz.h", bound.short_path)
+                assert_eq("quz.h", bound.basename)
+                assert_eq(".h", bound.extension)
             "#
         ))?;
+
+    let project_file = indoc!(
+        r#"
+            def test():
+                declared_file_artifact("out.txt").project("foo")
+            "#
+    );
+    expect_error(
+        tester.run_starlark_bzl_test(project_file),
+        project_file,
+        "Only directory artifacts can be projected",
+    );
     Ok(())
 }
 
@@ -314,6 +339,7 @@ fn stringifies_for_command_line() -> buck2_error::Result<()> {
                 assert_eq("foo/bar/baz/file1", stringify_for_cli(a2))
                 assert_eq_ignore_hash("buck-out/v2/gen/root/<HASH>/foo/__bar__/baz/quz.cpp", stringify_for_cli(a3))
                 assert_eq("foo/bar/baz/file2", stringify_for_cli(a4))
+                assert_eq_ignore_hash("buck-out/v2/gen/root/<HASH>/foo/__bar__/baz/include/quz.h", stringify_for_cli(bound_artifact("//foo:bar", "baz").project("include/quz.h")))
             "#
         ))
 }
@@ -353,3 +379,59 @@ fn bound_artifact_with_associated_artifacts() -> buck2_error::Result<()> {
             "#
     ))
 }
+
+#[test]
+fn promised_artifact() -> buck2_error::Result<()> {
+    let mut tester = Tester::new()?;
+    tester.additional_globals(buck2_build_api::interpreter::rule_defs::register_rule_defs);
+    tester.additional_globals(artifactory);
+    tester.run_starlark_bzl_test(indoc!(
+        r#"
+            def test():
+                a1 = promised_artifact(bound_artifact("//foo:bar", "baz/quz.h"))
+                assert_eq("baz/quz.h", a1.short_path)
+                assert_eq(False, a1.is_source)
+
+                a2 = promised_artifact(promise_artifact(bound_artifact("//foo:bar", "baz/file1")))
+                assert_eq("baz/file1", a2.short_path)
+                assert_eq(False, a2.is_source)
+            "#
+    ))?;
+
+    let source = indoc!(
+        r#"
+            def test():
+                promised_artifact(source_artifact("foo/bar", "baz/quz.h"))
+            "#
+    );
+    expect_error(
+        tester.run_starlark_bzl_test(source),
+        source,
+        "resolved to source artifact `<source foo/bar/baz/quz.h>`",
+    );
+
+    let chained_source = indoc!(
+        r#"
+            def test():
+                promised_artifact(promise_artifact(source_artifact("foo/bar", "baz/quz.h")))
+            "#
+    );
+    expect_error(
+        tester.run_starlark_bzl_test(chained_source),
+        chained_source,
+        "must resolve to a build artifact",
+    );
+
+    let not_an_artifact = indoc!(
+        r#"
+            def test():
+                promised_artifact("foo")
+            "#
+    );
+    expect_error(
+        tester.run_starlark_bzl_test(not_an_artifact),
+        not_an_artifact,
+        "was not an artifact",
+    );
+    Ok(())
+}
@@ -7,13 +7,20 @@
  * of this source tree.
  */
 
-use buck2_artifact::artifact::artifact_type::testing::BuildArtifactTestingExt;
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use buck2_artifact::actions::key::ActionKey;
 use buck2_artifact::artifact::artifact_type::Artifact;
 use buck2_artifact::artifact::build_artifact::BuildArtifact;
 use buck2_artifact::artifact::source_artifact::SourceArtifact;
 use buck2_artifact::deferred::id::DeferredId;
+use buck2_artifact::deferred::key::DeferredKey;
 use buck2_build_api::actions::registry::ActionsRegistry;
 use buck2_build_api::analysis::registry::AnalysisRegistry;
+use buck2_build_api::artifact_groups::promise::promised_artifact_from_value;
+use buck2_build_api::artifact_groups::promise::PromiseArtifact;
+use buck2_build_api::artifact_groups::promise::PromiseArtifactId;
 use buck2_build_api::artifact_groups::ArtifactGroup;
 use buck2_build_api::deferred::types::BaseKey;
 use buck2_build_api::deferred::types::DeferredRegistry;
@@ -22,6 +29,7 @@ use buck2_build_api::interpreter::rule_defs::artifact::output_artifact_like::Out
 use buck2_build_api::interpreter::rule_defs::artifact::starlark_artifact::StarlarkArtifact;
 use buck2_build_api::interpreter::rule_defs::artifact::starlark_artifact_like::ValueAsArtifactLike;
 use buck2_build_api::interpreter::rule_defs::artifact::starlark_declared_artifact::StarlarkDeclaredArtifact;
+use buck2_build_api::interpreter::rule_defs::artifact::starlark_promise_artifact::StarlarkPromiseArtifact;
 use buck2_build_api::interpreter::rule_defs::artifact::unpack_artifact::UnpackArtifactOrDeclaredArtifact;
 use buck2_build_api::interpreter::rule_defs::cmd_args::DefaultCommandLineContext;
 use buck2_core::base_deferred_key::BaseDeferredKey;
@@ -31,6 +39,7 @@ use buck2_core::configuration::data::ConfigurationData;
 use buck2_core::execution_types::execution::ExecutionPlatformResolution;
 use buck2_core::execution_types::executor_config::PathSeparatorKind;
 use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_core::fs::buck_out_path::BuckOutPath;
 use buck2_core::fs::buck_out_path::BuckOutPathResolver;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
@@ -100,17 +109,66 @@ pub(crate) fn artifactory(builder: &mut GlobalsBuilder) {
     ) -> anyhow::Result<StarlarkArtifact> {
         let target_label = get_label(eval, target)?;
         let id = DeferredId::testing_new(0);
-        let artifact = Artifact::from(BuildArtifact::testing_new(
-            target_label,
-            ForwardRelativePathBuf::try_from(path.to_owned()).unwrap(),
-            id,
+        let artifact = Artifact::from(BuildArtifact::new(
+            BuckOutPath::new(
+                BaseDeferredKey::TargetLabel(target_label.dupe()),
+                ForwardRelativePathBuf::try_from(path.to_owned()).unwrap(),
+            ),
+            ActionKey::unchecked_new(DeferredKey::Base(
+                BaseDeferredKey::TargetLabel(target_label),
+                id,
+            )),
+            OutputType::FileOrDirectory,
         ));
         Ok(StarlarkArtifact::new(artifact))
     }
 
+    fn promise_artifact<'v>(
+        artifact: ValueAsArtifactLike<'v>,
+        eval: &mut Evaluator,
+    ) -> anyhow::Result<StarlarkPromiseArtifact> {
+        let target_label = get_label(eval, "//foo:bar")?;
+        let id = PromiseArtifactId::new(BaseDeferredKey::TargetLabel(target_label), 0);
+        let resolved = OnceLock::new();
+        resolved.set(artifact.0.get_bound_artifact()?).unwrap();
+        Ok(StarlarkPromiseArtifact::new(
+            None,
+            PromiseArtifact::new(Arc::new(resolved), Arc::new(id)),
+            None,
+        ))
+    }
+
+    fn promised_artifact<'v>(value: Value<'v>) -> anyhow::Result<StarlarkArtifact> {
+        Ok(StarlarkArtifact::new(promised_artifact_from_value(
+            value, &None,
+        )?))
+    }
+
     fn declared_artifact(
         path: &str,
         eval: &mut Evaluator,
+    ) -> anyhow::Result<StarlarkDeclaredArtifact> {
+        let target_label = get_label(eval, "//foo:bar")?;
+        let mut registry = ActionsRegistry::new(
+            BaseDeferredKey::TargetLabel(target_label),
+            ExecutionPlatformResolution::unspecified(),
+        );
+        let artifact = registry.declare_artifact(
+            None,
+            ForwardRelativePathBuf::try_from(path.to_owned()).unwrap(),
+            OutputType::FileOrDirectory,
+            None,
+        )?;
+        Ok(StarlarkDeclaredArtifact::new(
+            None,
+            artifact,
+            AssociatedArtifacts::new(),
+        ))
+    }
+
+    fn declared_file_artifact(
+        path: &str,
+        eval: &mut Evaluator,
     ) -> anyhow::Result<StarlarkDeclaredArtifact> {
         let target_label = get_label(eval, "//foo:bar")?;
         let mut registry = ActionsRegistry::new(
@@ -146,7 +204,7 @@ pub(crate) fn artifactory(builder: &mut GlobalsBuilder) {
         let artifact = registry.declare_artifact(
             None,
             ForwardRelativePathBuf::try_from(path.to_owned()).unwrap(),
-            OutputType::File,
+            OutputType::FileOrDirectory,
             None,
         )?;
         let outputs = indexset![artifact.as_output()];
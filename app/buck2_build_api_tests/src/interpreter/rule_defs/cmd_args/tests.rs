@@ -713,6 +713,28 @@ fn test_concat() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_dict_args() -> anyhow::Result<()> {
+    let mut tester = tester()?;
+    let contents = indoc!(
+        r#"
+        def test():
+            a = cmd_args({"--b": "1", "--a": ["2", "3"]})
+            b = cmd_args({"--a": ["2", "3"], "--b": "1"})
+            assert_eq(["--a", "2", "3", "--b", "1"], get_args(a))
+            assert_eq(get_args(a), get_args(b))
+
+            # Nested dicts are sorted too.
+            assert_eq(["x", "--a", "2", "--b", "1"], get_args(cmd_args(["x", {"--b": "1", "--a": "2"}])))
+
+            # Rules which rely on insertion order can opt out.
+            assert_eq(["--b", "1", "--a", "2"], get_args(cmd_args({"--b": "1", "--a": "2"}, sort_keys = False)))
+        "#
+    );
+    tester.run_starlark_bzl_test(contents)?;
+    Ok(())
+}
+
 #[test]
 fn test_replace_regex() -> anyhow::Result<()> {
     let mut tester = tester()?;
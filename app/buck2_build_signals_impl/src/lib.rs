@@ -53,6 +53,7 @@ use buck2_interpreter_for_build::interpreter::calculation::InterpreterResultsKey
 use buck2_node::nodes::eval_result::EvaluationResult;
 use derive_more::From;
 use dice::ActivationData;
+use dice::ActivationTiming;
 use dice::ActivationTracker;
 use dupe::Dupe;
 use gazebo::prelude::SliceExt;
@@ -239,6 +240,7 @@ impl ActivationTracker for BuildSignalSender {
         key: &dyn Any,
         deps: &mut dyn Iterator<Item = &dyn Any>,
         activation_data: ActivationData,
+        _timing: ActivationTiming,
     ) {
         let key = match NodeKey::from_any(key) {
             Some(key) => key,
@@ -7,6 +7,8 @@
  * of this source tree.
  */
 
+use std::rc::Rc;
+
 use allocative::Allocative;
 use buck2_build_api::actions::query::ActionQueryNode;
 use buck2_build_api::query::bxl::BxlAqueryFunctions;
@@ -15,7 +17,9 @@ use buck2_build_api::query::oneshot::QUERY_FRONTEND;
 use buck2_common::global_cfg_options::GlobalCfgOptions;
 use buck2_core::configuration::compatibility::IncompatiblePlatformReason;
 use buck2_core::provider::label::ConfiguredProvidersLabel;
+use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
 use buck2_core::target::label::label::TargetLabel;
+use buck2_interpreter::types::target_label::StarlarkConfiguredTargetLabel;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::functions::helpers::CapturedExpr;
@@ -23,6 +27,7 @@ use derivative::Derivative;
 use derive_more::Display;
 use dice::DiceComputations;
 use dupe::Dupe;
+use either::Either;
 use futures::FutureExt;
 use gazebo::prelude::OptionExt;
 use starlark::any::ProvidesStaticType;
@@ -31,9 +36,11 @@ use starlark::environment::MethodsBuilder;
 use starlark::environment::MethodsStatic;
 use starlark::eval::Evaluator;
 use starlark::starlark_module;
+use starlark::values::list::AllocList;
 use starlark::values::list::UnpackList;
 use starlark::values::none::NoneOr;
 use starlark::values::starlark_value;
+use starlark::values::structs::AllocStruct;
 use starlark::values::type_repr::StarlarkTypeRepr;
 use starlark::values::AllocValue;
 use starlark::values::Heap;
@@ -45,11 +52,11 @@ use starlark::values::Value;
 use starlark::StarlarkDocs;
 
 use crate::bxl::starlark_defs::context::BxlContext;
-use crate::bxl::starlark_defs::context::BxlContextNoDice;
 use crate::bxl::starlark_defs::nodes::action::StarlarkActionQueryNode;
 use crate::bxl::starlark_defs::providers_expr::ConfiguredProvidersExprArg;
 use crate::bxl::starlark_defs::providers_expr::ProvidersExpr;
 use crate::bxl::starlark_defs::query_util::parse_query_evaluation_result;
+use crate::bxl::starlark_defs::query_util::QueryEnvCache;
 use crate::bxl::starlark_defs::target_list_expr::ConfiguredTargetListExprArg;
 use crate::bxl::starlark_defs::target_list_expr::TargetListExpr;
 use crate::bxl::starlark_defs::targetset::StarlarkTargetSet;
@@ -76,6 +83,9 @@ pub(crate) struct StarlarkAQueryCtx<'v> {
     #[derivative(Debug = "ignore")]
     // Overrides the GlobalCfgOptions in the BxlContext
     global_cfg_options_override: GlobalCfgOptions,
+    #[trace(unsafe_ignore)]
+    #[derivative(Debug = "ignore")]
+    env: QueryEnvCache<dyn BxlAqueryFunctions>,
 }
 
 #[starlark_value(type = "aqueryctx", StarlarkTypeRepr, UnpackValue)]
@@ -112,21 +122,27 @@ impl<'v> StarlarkAQueryCtx<'v> {
                 target_platform,
                 cli_modifiers: vec![].into(),
             },
+            env: QueryEnvCache::new(),
         })
     }
 }
 
 pub(crate) async fn get_aquery_env(
-    ctx: &BxlContextNoDice<'_>,
-    global_cfg_options_override: &GlobalCfgOptions,
-) -> anyhow::Result<Box<dyn BxlAqueryFunctions>> {
-    (NEW_BXL_AQUERY_FUNCTIONS.get()?)(
-        global_cfg_options_override.clone(),
-        ctx.project_root().dupe(),
-        ctx.cell_name(),
-        ctx.cell_resolver().dupe(),
-    )
-    .await
+    this: &StarlarkAQueryCtx<'_>,
+    dice: &DiceComputations<'_>,
+) -> anyhow::Result<Rc<dyn BxlAqueryFunctions>> {
+    let ctx = &this.ctx.data;
+    this.env
+        .get_or_init(dice, || async {
+            (NEW_BXL_AQUERY_FUNCTIONS.get()?)(
+                this.global_cfg_options_override.clone(),
+                ctx.project_root().dupe(),
+                ctx.cell_name(),
+                ctx.cell_resolver().dupe(),
+            )
+            .await
+        })
+        .await
 }
 
 #[derive(StarlarkTypeRepr, UnpackValue)]
@@ -147,12 +163,33 @@ async fn unpack_action_nodes<'v>(
     dice: &mut DiceComputations<'_>,
     expr: UnpackActionNodes<'v>,
 ) -> anyhow::Result<TargetSet<ActionQueryNode>> {
-    let aquery_env = get_aquery_env(&this.ctx.data, &this.global_cfg_options_override).await?;
+    let (incompatible_targets, result) =
+        unpack_action_nodes_and_incompatible(this, dice, expr).await?;
+
+    if !incompatible_targets.is_empty() {
+        this.ctx.data.print_to_error_stream(
+            IncompatiblePlatformReason::skipping_message_for_multiple(incompatible_targets.iter()),
+        )?;
+    }
+
+    Ok(result)
+}
+
+// Like `unpack_action_nodes`, but returns the targets skipped as incompatible instead of printing
+// them to BXL's stderr.
+async fn unpack_action_nodes_and_incompatible<'v>(
+    this: &StarlarkAQueryCtx<'v>,
+    dice: &mut DiceComputations<'_>,
+    expr: UnpackActionNodes<'v>,
+) -> anyhow::Result<(Vec<ConfiguredTargetLabel>, TargetSet<ActionQueryNode>)> {
+    let aquery_env = get_aquery_env(this, dice).await?;
     let providers = match expr {
         UnpackActionNodes::ActionQueryNodes(action_nodes) => {
-            return Ok(action_nodes.into_iter().map(|v| v.0).collect());
+            return Ok((Vec::new(), action_nodes.into_iter().map(|v| v.0).collect()));
+        }
+        UnpackActionNodes::ActionQueryNodesSet(action_nodes) => {
+            return Ok((Vec::new(), action_nodes.0.clone()));
         }
-        UnpackActionNodes::ActionQueryNodesSet(action_nodes) => return Ok(action_nodes.0.clone()),
         UnpackActionNodes::ConfiguredProviders(arg) => {
             ProvidersExpr::<ConfiguredProvidersLabel>::unpack(
                 arg,
@@ -178,84 +215,154 @@ async fn unpack_action_nodes<'v>(
         }
     };
 
-    let (incompatible_targets, result) = aquery_env.get_target_set(dice, providers).await?;
+    aquery_env.get_target_set(dice, providers).await
+}
 
-    if !incompatible_targets.is_empty() {
-        this.ctx.data.print_to_error_stream(
-            IncompatiblePlatformReason::skipping_message_for_multiple(incompatible_targets.iter()),
-        )?;
+// Like `unpack_action_nodes`, but when `keep_incompatible` is set the skipped targets are
+// returned to the caller instead of being reported on BXL's stderr.
+async fn unpack_action_nodes_keep_incompatible<'v>(
+    this: &StarlarkAQueryCtx<'v>,
+    dice: &mut DiceComputations<'_>,
+    expr: UnpackActionNodes<'v>,
+    keep_incompatible: bool,
+) -> anyhow::Result<(Vec<ConfiguredTargetLabel>, TargetSet<ActionQueryNode>)> {
+    if keep_incompatible {
+        unpack_action_nodes_and_incompatible(this, dice, expr).await
+    } else {
+        Ok((Vec::new(), unpack_action_nodes(this, dice, expr).await?))
     }
+}
 
-    Ok(result)
+/// The result of a query that takes `keep_incompatible`: the target set, or with
+/// `keep_incompatible` a struct with the resulting `target_set` and the `incompatible_targets`
+/// that were skipped.
+type AqueryResultWithIncompatible<'v> =
+    Either<StarlarkTargetSet<ActionQueryNode>, AllocStruct<[(&'static str, Value<'v>); 2]>>;
+
+fn with_incompatible<'v>(
+    heap: &'v Heap,
+    keep_incompatible: bool,
+    incompatible_targets: Vec<ConfiguredTargetLabel>,
+    result: TargetSet<ActionQueryNode>,
+) -> AqueryResultWithIncompatible<'v> {
+    let target_set = StarlarkTargetSet::from(result);
+    if !keep_incompatible {
+        return Either::Left(target_set);
+    }
+    Either::Right(AllocStruct([
+        ("target_set", heap.alloc(target_set)),
+        (
+            "incompatible_targets",
+            heap.alloc(AllocList(
+                incompatible_targets
+                    .into_iter()
+                    .map(StarlarkConfiguredTargetLabel::new),
+            )),
+        ),
+    ]))
 }
 /// The context for performing `aquery` operations in bxl. The functions offered on this ctx are
 /// the same behaviour as the query functions available within aquery command.
 ///
+/// All queries of a bxl evaluation run against the same snapshot of the repo: files changed
+/// while the script runs are only seen by the next evaluation.
+///
 /// Query results are `target_set`s of `action_query_node`s, which supports iteration,
 /// indexing, `len()`, set addition/subtraction, and `equals()`.
 #[starlark_module]
 fn aquery_methods(builder: &mut MethodsBuilder) {
     /// The deps query for finding the transitive closure of dependencies.
+    ///
+    /// Incompatible targets are handled as in `all_actions`, see `keep_incompatible` there.
     fn deps<'v>(
         this: &StarlarkAQueryCtx<'v>,
         // TODO(nga): parameters should be either positional or named, not both.
         universe: UnpackActionNodes<'v>,
         #[starlark(default = NoneOr::None)] depth: NoneOr<i32>,
         #[starlark(default = NoneOr::None)] filter: NoneOr<&'v str>,
-    ) -> anyhow::Result<StarlarkTargetSet<ActionQueryNode>> {
-        this.ctx
-            .via_dice(|dice, ctx| {
-                dice.via(|dice| {
-                    async {
-                        let filter = filter
-                            .into_option()
-                            .try_map(buck2_query_parser::parse_expr)?;
+        #[starlark(require = named, default = false)] keep_incompatible: bool,
+        heap: &'v Heap,
+    ) -> anyhow::Result<AqueryResultWithIncompatible<'v>> {
+        let (incompatible_targets, result) = this.ctx.via_dice(|dice, _| {
+            dice.via(|dice| {
+                async {
+                    let filter = filter
+                        .into_option()
+                        .try_map(buck2_query_parser::parse_expr)?;
 
-                        let universe = unpack_action_nodes(this, dice, universe).await?;
+                    let (incompatible_targets, universe) = unpack_action_nodes_keep_incompatible(
+                        this,
+                        dice,
+                        universe,
+                        keep_incompatible,
+                    )
+                    .await?;
 
-                        let aquery_env =
-                            get_aquery_env(ctx, &this.global_cfg_options_override).await?;
-                        aquery_env
-                            .deps(
-                                dice,
-                                &universe,
-                                depth.into_option(),
-                                filter
-                                    .as_ref()
-                                    .map(|span| CapturedExpr { expr: span })
-                                    .as_ref(),
-                            )
-                            .await
-                    }
-                    .boxed_local()
-                })
+                    let aquery_env = get_aquery_env(this, dice).await?;
+                    let result = aquery_env
+                        .deps(
+                            dice,
+                            &universe,
+                            depth.into_option(),
+                            filter
+                                .as_ref()
+                                .map(|span| CapturedExpr { expr: span })
+                                .as_ref(),
+                        )
+                        .await?;
+                    Ok((incompatible_targets, result))
+                }
+                .boxed_local()
             })
-            .map(StarlarkTargetSet::from)
+        })?;
+        Ok(with_incompatible(
+            heap,
+            keep_incompatible,
+            incompatible_targets,
+            result,
+        ))
     }
 
     /// Obtain all the actions declared within the analysis of a given target.
     ///
     /// This operation only makes sense on a target literal (it is a simple passthrough when passed
     /// an action).
+    ///
+    /// Targets that are incompatible with their configuration are skipped, and reported on stderr.
+    /// Pass `keep_incompatible = True` to instead get back a struct with the resulting
+    /// `target_set` and the skipped `incompatible_targets`.
     fn all_actions<'v>(
         this: &StarlarkAQueryCtx<'v>,
         // TODO(nga): parameters should be either positional or named, not both.
         targets: UnpackActionNodes<'v>,
-    ) -> anyhow::Result<StarlarkTargetSet<ActionQueryNode>> {
-        this.ctx
-            .via_dice(|dice, ctx| {
-                dice.via(|dice| {
-                    async {
-                        let targets = unpack_action_nodes(this, dice, targets).await?;
-                        get_aquery_env(ctx, &this.global_cfg_options_override)
-                            .await?
-                            .all_actions(dice, &targets)
-                            .await
-                    }
-                    .boxed_local()
-                })
+        #[starlark(require = named, default = false)] keep_incompatible: bool,
+        heap: &'v Heap,
+    ) -> anyhow::Result<AqueryResultWithIncompatible<'v>> {
+        let (incompatible_targets, result) = this.ctx.via_dice(|dice, _| {
+            dice.via(|dice| {
+                async {
+                    let (incompatible_targets, targets) = unpack_action_nodes_keep_incompatible(
+                        this,
+                        dice,
+                        targets,
+                        keep_incompatible,
+                    )
+                    .await?;
+                    let result = get_aquery_env(this, dice)
+                        .await?
+                        .all_actions(dice, &targets)
+                        .await?;
+                    Ok((incompatible_targets, result))
+                }
+                .boxed_local()
             })
-            .map(StarlarkTargetSet::from)
+        })?;
+        Ok(with_incompatible(
+            heap,
+            keep_incompatible,
+            incompatible_targets,
+            result,
+        ))
     }
 
     /// Obtain the actions for all the outputs provided by the `DefaultInfo` for the targets passed
@@ -263,48 +370,116 @@ fn aquery_methods(builder: &mut MethodsBuilder) {
     ///
     /// This operation only makes sense on a target literal (it does nothing if passed something
     /// else).
+    ///
+    /// Incompatible targets are handled as in `all_actions`, see `keep_incompatible` there.
     fn all_outputs<'v>(
         this: &StarlarkAQueryCtx<'v>,
         // TODO(nga): parameters should be either positional or named, not both.
         targets: UnpackActionNodes<'v>,
-    ) -> anyhow::Result<StarlarkTargetSet<ActionQueryNode>> {
-        this.ctx
-            .via_dice(|dice, ctx| {
-                dice.via(|dice| {
-                    async {
-                        let targets = unpack_action_nodes(this, dice, targets).await?;
+        #[starlark(require = named, default = false)] keep_incompatible: bool,
+        heap: &'v Heap,
+    ) -> anyhow::Result<AqueryResultWithIncompatible<'v>> {
+        let (incompatible_targets, result) = this.ctx.via_dice(|dice, _| {
+            dice.via(|dice| {
+                async {
+                    let (incompatible_targets, targets) = unpack_action_nodes_keep_incompatible(
+                        this,
+                        dice,
+                        targets,
+                        keep_incompatible,
+                    )
+                    .await?;
+                    let result = get_aquery_env(this, dice)
+                        .await?
+                        .all_outputs(dice, &targets)
+                        .await?;
+                    Ok((incompatible_targets, result))
+                }
+                .boxed_local()
+            })
+        })?;
+        Ok(with_incompatible(
+            heap,
+            keep_incompatible,
+            incompatible_targets,
+            result,
+        ))
+    }
 
-                        get_aquery_env(ctx, &this.global_cfg_options_override)
-                            .await?
-                            .all_outputs(dice, &targets)
-                            .await
-                    }
-                    .boxed_local()
-                })
+    /// Obtain the actions producing the inputs of the given actions, including inputs reached
+    /// through transitive sets.
+    ///
+    /// Source inputs are not produced by any action, so they are not included. This operation
+    /// only makes sense on actions (target literals are ignored).
+    ///
+    /// Incompatible targets are handled as in `all_actions`, see `keep_incompatible` there.
+    fn all_inputs<'v>(
+        this: &StarlarkAQueryCtx<'v>,
+        targets: UnpackActionNodes<'v>,
+        #[starlark(require = named, default = false)] keep_incompatible: bool,
+        heap: &'v Heap,
+    ) -> anyhow::Result<AqueryResultWithIncompatible<'v>> {
+        let (incompatible_targets, result) = this.ctx.via_dice(|dice, _| {
+            dice.via(|dice| {
+                async {
+                    let (incompatible_targets, targets) = unpack_action_nodes_keep_incompatible(
+                        this,
+                        dice,
+                        targets,
+                        keep_incompatible,
+                    )
+                    .await?;
+                    let result = get_aquery_env(this, dice)
+                        .await?
+                        .all_inputs(dice, &targets)
+                        .await?;
+                    Ok((incompatible_targets, result))
+                }
+                .boxed_local()
             })
-            .map(StarlarkTargetSet::from)
+        })?;
+        Ok(with_incompatible(
+            heap,
+            keep_incompatible,
+            incompatible_targets,
+            result,
+        ))
     }
 
     /// The attrfilter query for rule attribute filtering.
+    ///
+    /// Incompatible targets are handled as in `all_actions`, see `keep_incompatible` there.
     fn attrfilter<'v>(
         this: &StarlarkAQueryCtx<'v>,
         // TODO(nga): parameters should be either positional or named, not both.
         attr: &str,
         value: &str,
         targets: UnpackActionNodes<'v>,
-    ) -> anyhow::Result<StarlarkTargetSet<ActionQueryNode>> {
-        this.ctx.via_dice(|dice, _| {
+        #[starlark(require = named, default = false)] keep_incompatible: bool,
+        heap: &'v Heap,
+    ) -> anyhow::Result<AqueryResultWithIncompatible<'v>> {
+        let (incompatible_targets, result) = this.ctx.via_dice(|dice, _| {
             dice.via(|dice| {
                 async {
-                    let targets = unpack_action_nodes(this, dice, targets).await?;
-
-                    targets
-                        .attrfilter(attr, &|v| Ok(v == value))
-                        .map(StarlarkTargetSet::from)
+                    let (incompatible_targets, targets) = unpack_action_nodes_keep_incompatible(
+                        this,
+                        dice,
+                        targets,
+                        keep_incompatible,
+                    )
+                    .await?;
+                    let result = targets.attrfilter(attr, &|v| Ok(v == value))?;
+                    Ok((incompatible_targets, result))
                 }
                 .boxed_local()
             })
-        })
+        })?;
+        Ok(with_incompatible(
+            heap,
+            keep_incompatible,
+            incompatible_targets,
+            result,
+        ))
     }
 
     /// Evaluates some general query string. `query_args` can be a target_set of unconfigured nodes, or
@@ -10,6 +10,7 @@
 use allocative::Allocative;
 use anyhow::Context as _;
 use buck2_build_api::audit_cell::audit_cell;
+use buck2_build_api::audit_defaults::audit_defaults;
 use buck2_build_api::audit_output::audit_output;
 use buck2_build_api::audit_output::AuditOutputResult;
 use buck2_common::global_cfg_options::GlobalCfgOptions;
@@ -193,4 +194,29 @@ fn audit_methods(builder: &mut MethodsBuilder) {
             result.into_iter().map(|(k, v)| (k, v.to_string())),
         ))
     }
+
+    /// Describe the attributes of a rule, given its rule type like `cell//path:file.bzl:rule_name`.
+    ///
+    /// Returns a dict of attribute name to a dict with the attribute `type`, whether it is
+    /// `configurable` or `default_only`, its `doc` if any, and its `default` if the attribute is not
+    /// required. Defaults are rendered the same way as attribute values in `uquery` output, so a `select`
+    /// default is a dict with `__type` set to `selector`.
+    ///
+    /// This is the same information as `buck2 audit defaults` prints.
+    ///
+    /// Sample usage:
+    /// ```text
+    /// def _impl_audit_rule_defaults(ctx):
+    ///     result = ctx.audit().rule_defaults("prelude//rules.bzl:genrule")
+    ///     ctx.output.print(result["visibility"]["default"])
+    /// ```
+    fn rule_defaults<'v>(
+        this: &StarlarkAuditCtx<'v>,
+        #[starlark(require = pos)] rule_type: &str,
+    ) -> anyhow::Result<serde_json::Value> {
+        let attributes = this.ctx.async_ctx.borrow_mut().via(|ctx| {
+            audit_defaults(rule_type, &this.working_dir, &this.cell_resolver, ctx).boxed_local()
+        })?;
+        Ok(serde_json::to_value(attributes)?)
+    }
 }
@@ -97,7 +97,7 @@ impl BxlDiceComputations for BxlSafeDiceComputations<'_, '_> {
 
                 match select(fut, liveness).await {
                     Either::Left((res, _)) => res,
-                    Either::Right(((), _)) => Err(ViaError::Cancelled.into()),
+                    Either::Right((_reason, _)) => Err(ViaError::Cancelled.into()),
                 }
             };
 
@@ -7,6 +7,8 @@
  * of this source tree.
  */
 
+use std::rc::Rc;
+
 use allocative::Allocative;
 use buck2_build_api::query::bxl::BxlCqueryFunctions;
 use buck2_build_api::query::bxl::NEW_BXL_CQUERY_FUNCTIONS;
@@ -14,6 +16,7 @@ use buck2_build_api::query::oneshot::CqueryOwnerBehavior;
 use buck2_build_api::query::oneshot::QUERY_FRONTEND;
 use buck2_common::global_cfg_options::GlobalCfgOptions;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
+use buck2_query::query::syntax::simple::eval::file_set::FileGlob;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::functions::helpers::CapturedExpr;
 use derivative::Derivative;
@@ -40,10 +43,10 @@ use starlark::values::Value;
 use starlark::StarlarkDocs;
 
 use crate::bxl::starlark_defs::context::BxlContext;
-use crate::bxl::starlark_defs::context::BxlContextNoDice;
 use crate::bxl::starlark_defs::file_set::FileSetExpr;
 use crate::bxl::starlark_defs::file_set::StarlarkFileSet;
 use crate::bxl::starlark_defs::query_util::parse_query_evaluation_result;
+use crate::bxl::starlark_defs::query_util::QueryEnvCache;
 use crate::bxl::starlark_defs::target_list_expr::filter_incompatible;
 use crate::bxl::starlark_defs::target_list_expr::ConfiguredTargetListExprArg;
 use crate::bxl::starlark_defs::target_list_expr::TargetListExpr;
@@ -71,6 +74,9 @@ pub(crate) struct StarlarkCQueryCtx<'v> {
     #[derivative(Debug = "ignore")]
     // Overrides the GlobalCfgOptions in the BxlContext
     global_cfg_options_override: GlobalCfgOptions,
+    #[trace(unsafe_ignore)]
+    #[derivative(Debug = "ignore")]
+    env: QueryEnvCache<dyn BxlCqueryFunctions>,
 }
 
 #[starlark_value(type = "cqueryctx", StarlarkTypeRepr, UnpackValue)]
@@ -88,16 +94,21 @@ impl<'v> AllocValue<'v> for StarlarkCQueryCtx<'v> {
 }
 
 pub(crate) async fn get_cquery_env(
-    ctx: &BxlContextNoDice<'_>,
-    global_cfg_options_override: &GlobalCfgOptions,
-) -> anyhow::Result<Box<dyn BxlCqueryFunctions>> {
-    (NEW_BXL_CQUERY_FUNCTIONS.get()?)(
-        global_cfg_options_override.clone(),
-        ctx.project_root().dupe(),
-        ctx.cell_name(),
-        ctx.cell_resolver().dupe(),
-    )
-    .await
+    this: &StarlarkCQueryCtx<'_>,
+    dice: &DiceComputations<'_>,
+) -> anyhow::Result<Rc<dyn BxlCqueryFunctions>> {
+    let ctx = &this.ctx.data;
+    this.env
+        .get_or_init(dice, || async {
+            (NEW_BXL_CQUERY_FUNCTIONS.get()?)(
+                this.global_cfg_options_override.clone(),
+                ctx.project_root().dupe(),
+                ctx.cell_name(),
+                ctx.cell_resolver().dupe(),
+            )
+            .await
+        })
+        .await
 }
 
 async fn unpack_targets<'v>(
@@ -139,6 +150,7 @@ impl<'v> StarlarkCQueryCtx<'v> {
                 target_platform,
                 cli_modifiers: vec![].into(),
             },
+            env: QueryEnvCache::new(),
         })
     }
 }
@@ -146,6 +158,9 @@ impl<'v> StarlarkCQueryCtx<'v> {
 /// The context for performing `cquery` operations in bxl. The functions offered on this ctx are
 /// the same behaviour as the query functions available within cquery command.
 ///
+/// All queries of a bxl evaluation run against the same snapshot of the repo: files changed
+/// while the script runs are only seen by the next evaluation.
+///
 /// Query results are `target_set`s of `target_node`s, which supports iteration,
 /// indexing, `len()`, set addition/subtraction, and `equals()`.
 #[starlark_module]
@@ -156,12 +171,12 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
         from: ConfiguredTargetListExprArg<'v>,
         to: ConfiguredTargetListExprArg<'v>,
     ) -> anyhow::Result<StarlarkTargetSet<ConfiguredTargetNode>> {
-        this.ctx.via_dice(move |dice, ctx| {
+        this.ctx.via_dice(move |dice, _| {
             dice.via(|dice| {
                 async move {
                     let from = unpack_targets(this, dice, from).await?;
                     let to = unpack_targets(this, dice, to).await?;
-                    get_cquery_env(ctx, &this.global_cfg_options_override)
+                    get_cquery_env(this, dice)
                         .await?
                         .allpaths(dice, &from, &to)
                         .await
@@ -178,12 +193,12 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
         from: ConfiguredTargetListExprArg<'v>,
         to: ConfiguredTargetListExprArg<'v>,
     ) -> anyhow::Result<StarlarkTargetSet<ConfiguredTargetNode>> {
-        this.ctx.via_dice(|dice, ctx| {
+        this.ctx.via_dice(|dice, _| {
             dice.via(|dice| {
                 async {
                     let from = unpack_targets(this, dice, from).await?;
                     let to = unpack_targets(this, dice, to).await?;
-                    get_cquery_env(ctx, &this.global_cfg_options_override)
+                    get_cquery_env(this, dice)
                         .await?
                         .somepath(dice, &from, &to)
                         .await
@@ -194,6 +209,29 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
         })
     }
 
+    /// The shortestpath query, which returns the nodes of one shortest dependency chain, in order,
+    /// starting at a target in `from` and ending at a target in `to`.
+    fn shortestpath<'v>(
+        this: &StarlarkCQueryCtx<'v>,
+        from: ConfiguredTargetListExprArg<'v>,
+        to: ConfiguredTargetListExprArg<'v>,
+    ) -> anyhow::Result<StarlarkTargetSet<ConfiguredTargetNode>> {
+        this.ctx.via_dice(|dice, _| {
+            dice.via(|dice| {
+                async {
+                    let from = unpack_targets(this, dice, from).await?;
+                    let to = unpack_targets(this, dice, to).await?;
+                    get_cquery_env(this, dice)
+                        .await?
+                        .shortestpath(dice, &from, &to)
+                        .await
+                        .map(StarlarkTargetSet::from)
+                }
+                .boxed_local()
+            })
+        })
+    }
+
     /// The attrfilter query for rule attribute filtering.
     fn attrfilter<'v>(
         this: &StarlarkCQueryCtx<'v>,
@@ -292,7 +330,7 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
                             None => None,
                         };
 
-                        get_cquery_env(ctx, &this.global_cfg_options_override)
+                        get_cquery_env(this, dice)
                             .await?
                             .owner(dice, files.get(ctx).await?.as_ref(), universe.as_ref())
                             .await
@@ -318,7 +356,7 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
         #[starlark(default = NoneOr::None)] filter: NoneOr<&'v str>,
     ) -> anyhow::Result<StarlarkTargetSet<ConfiguredTargetNode>> {
         this.ctx
-            .via_dice(|dice, ctx| {
+            .via_dice(|dice, _| {
                 dice.via(|dice| {
                     async {
                         let filter = filter
@@ -327,7 +365,7 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
 
                         let targets = unpack_targets(this, dice, universe).await?;
 
-                        get_cquery_env(ctx, &this.global_cfg_options_override)
+                        get_cquery_env(this, dice)
                             .await?
                             .deps(
                                 dice,
@@ -375,20 +413,35 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
 
     /// The inputs query for finding input files.
     ///
+    /// If `glob` is given, only the input files whose path within their cell matches it are
+    /// returned, e.g. `**/*.proto`. The glob follows the same rules as `glob()` in build files,
+    /// in particular it is case insensitive.
+    ///
     /// Sample usage:
     /// ```text
     /// def _impl_inputs(ctx):
     ///     result = ctx.cquery().inputs("root//bin:the_binary")
     ///     ctx.output.print(result)
+    ///     protos = ctx.cquery().inputs("root//bin:the_binary", glob = "**/*.proto")
+    ///     ctx.output.print(protos)
     /// ```
     fn inputs<'v>(
         this: &StarlarkCQueryCtx<'v>,
         targets: ConfiguredTargetListExprArg<'v>,
+        #[starlark(default = NoneOr::None)] glob: NoneOr<&'v str>,
     ) -> anyhow::Result<StarlarkFileSet> {
+        let glob = glob.into_option().try_map(FileGlob::new)?;
         this.ctx
             .via_dice(|dice, _| {
                 dice.via(|dice| {
-                    async { unpack_targets(this, dice, targets).await?.inputs() }.boxed_local()
+                    async {
+                        let targets = unpack_targets(this, dice, targets).await?;
+                        match &glob {
+                            Some(glob) => targets.inputs_matching(glob),
+                            None => targets.inputs(),
+                        }
+                    }
+                    .boxed_local()
                 })
             })
             .map(StarlarkFileSet::from)
@@ -400,11 +453,11 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
         targets: ConfiguredTargetListExprArg<'v>,
     ) -> anyhow::Result<StarlarkTargetSet<ConfiguredTargetNode>> {
         this.ctx
-            .via_dice(|dice, ctx| {
+            .via_dice(|dice, _| {
                 dice.via(|dice| {
                     async {
                         let targets = unpack_targets(this, dice, targets).await?;
-                        get_cquery_env(ctx, &this.global_cfg_options_override)
+                        get_cquery_env(this, dice)
                             .await?
                             .testsof(dice, &targets)
                             .await
@@ -426,11 +479,10 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
                 dice.via(|dice| {
                     async {
                         let targets = unpack_targets(this, dice, targets).await?;
-                        let maybe_compatibles =
-                            get_cquery_env(ctx, &this.global_cfg_options_override)
-                                .await?
-                                .testsof_with_default_target_platform(dice, &targets)
-                                .await?;
+                        let maybe_compatibles = get_cquery_env(this, dice)
+                            .await?
+                            .testsof_with_default_target_platform(dice, &targets)
+                            .await?;
 
                         filter_incompatible(maybe_compatibles, ctx)
                     }
@@ -455,12 +507,12 @@ fn cquery_methods(builder: &mut MethodsBuilder) {
         depth: Option<i32>,
     ) -> anyhow::Result<StarlarkTargetSet<ConfiguredTargetNode>> {
         this.ctx
-            .via_dice(|dice, ctx| {
+            .via_dice(|dice, _| {
                 dice.via(|dice| {
                     async {
                         let universe = unpack_targets(this, dice, universe).await?;
                         let targets = unpack_targets(this, dice, from).await?;
-                        get_cquery_env(ctx, &this.global_cfg_options_override)
+                        get_cquery_env(this, dice)
                             .await?
                             .rdeps(dice, &universe, &targets, depth)
                             .await
@@ -26,7 +26,6 @@ use buck2_node::attrs::coerced_attr::CoercedAttr;
 use buck2_node::attrs::display::AttrDisplayWithContext;
 use buck2_node::attrs::fmt_context::AttrFmtContext;
 use buck2_node::attrs::serialize::AttrSerializeWithContext;
-use buck2_node::visibility::VisibilityPatternList;
 use buck2_node::visibility::VisibilitySpecification;
 use buck2_node::visibility::WithinViewSpecification;
 use derive_more::From;
@@ -199,12 +198,9 @@ impl CoercedAttrExt for CoercedAttr {
             CoercedAttr::None => Value::new_none(),
             CoercedAttr::OneOf(l, _) => l.as_ref().to_value(pkg, heap)?,
             CoercedAttr::Visibility(VisibilitySpecification(specs))
-            | CoercedAttr::WithinView(WithinViewSpecification(specs)) => match specs {
-                VisibilityPatternList::Public => heap.alloc(AllocList(["PUBLIC"])),
-                VisibilityPatternList::List(specs) => {
-                    heap.alloc(AllocList(specs.iter().map(|s| s.to_string())))
-                }
-            },
+            | CoercedAttr::WithinView(WithinViewSpecification(specs)) => {
+                heap.alloc(AllocList(specs.to_strings()))
+            }
             CoercedAttr::ExplicitConfiguredDep(d) => heap.alloc(
                 // TODO(@wendyy) - this needs better support
                 StarlarkProvidersLabel::new(d.as_ref().label.clone()),
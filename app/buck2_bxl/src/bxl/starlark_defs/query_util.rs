@@ -7,9 +7,16 @@
  * of this source tree.
  */
 
+use std::cell::RefCell;
+use std::future::Future;
+use std::rc::Rc;
+
+use buck2_error::internal_error;
 use buck2_interpreter::error::BuckStarlarkError;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationValue;
+use dice::DiceComputations;
+use dice::DiceEquality;
 use starlark::eval::Evaluator;
 use starlark::values::dict::Dict;
 use starlark::values::Value;
@@ -53,3 +60,115 @@ pub(crate) fn parse_query_evaluation_result<'v, T: NodeLike>(
         )),
     })
 }
+
+/// The query environment of a query ctx (`ctx.cquery()` etc.), created by its first query and
+/// reused by the following ones.
+///
+/// A BXL evaluation runs against a single DICE version, so all queries of a ctx see the same
+/// universe even if files change while the script runs; those changes are only visible to the
+/// next evaluation. Using the environment at another version is a bug, and fails the query rather
+/// than mixing results from both.
+pub(crate) struct QueryEnvCache<T: ?Sized> {
+    env: RefCell<Option<(DiceEquality, Rc<T>)>>,
+}
+
+impl<T: ?Sized> QueryEnvCache<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            env: RefCell::new(None),
+        }
+    }
+
+    pub(crate) async fn get_or_init<F: Future<Output = anyhow::Result<Box<T>>>>(
+        &self,
+        dice: &DiceComputations<'_>,
+        init: impl FnOnce() -> F,
+    ) -> anyhow::Result<Rc<T>> {
+        let version = dice.equality_token();
+        if let Some((env_version, env)) = &*self.env.borrow() {
+            if *env_version != version {
+                return Err(internal_error!(
+                    "Query environment created at DICE version `{}` used at version `{}`",
+                    env_version,
+                    version
+                )
+                .into());
+            }
+            return Ok(env.clone());
+        }
+
+        let env: Rc<T> = Rc::from(init().await?);
+        *self.env.borrow_mut() = Some((version, env.clone()));
+        Ok(env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use allocative::Allocative;
+    use async_trait::async_trait;
+    use derive_more::Display;
+    use dice::DetectCycles;
+    use dice::Dice;
+    use dice::InjectedKey;
+    use dupe::Dupe;
+
+    use crate::bxl::starlark_defs::query_util::QueryEnvCache;
+
+    #[derive(Clone, Dupe, Debug, Display, Eq, PartialEq, Hash, Allocative)]
+    struct Universe;
+
+    #[async_trait]
+    impl InjectedKey for Universe {
+        type Value = u32;
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_env_is_pinned_to_dice_version() -> anyhow::Result<()> {
+        let dice = Dice::builder().build(DetectCycles::Enabled);
+        let mut updater = dice.updater();
+        updater.changed_to(vec![(Universe, 1)])?;
+        let mut evaluation = updater.commit().await;
+
+        let inits = &Cell::new(0);
+        let init = move || async move {
+            inits.set(inits.get() + 1);
+            anyhow::Ok(Box::new(inits.get()))
+        };
+
+        let cache = QueryEnvCache::new();
+        let env = cache.get_or_init(&evaluation, init).await?;
+        assert_eq!(1, evaluation.compute(&Universe).await?);
+
+        // Files change while the script is running.
+        let mut updater = dice.updater();
+        updater.changed_to(vec![(Universe, 2)])?;
+        let mut next_evaluation = updater.commit().await;
+
+        // Queries of the running evaluation still see the universe they started with.
+        assert!(Rc::ptr_eq(
+            &env,
+            &cache.get_or_init(&evaluation, init).await?
+        ));
+        assert_eq!(1, inits.get());
+        assert_eq!(1, evaluation.compute(&Universe).await?);
+
+        // Reusing the environment at another version is refused.
+        assert!(cache.get_or_init(&next_evaluation, init).await.is_err());
+
+        // The next evaluation sees the new universe.
+        let next_cache = QueryEnvCache::new();
+        next_cache.get_or_init(&next_evaluation, init).await?;
+        assert_eq!(2, inits.get());
+        assert_eq!(2, next_evaluation.compute(&Universe).await?);
+
+        Ok(())
+    }
+}
@@ -8,12 +8,14 @@
  */
 
 use std::borrow::Cow;
+use std::rc::Rc;
 
 use allocative::Allocative;
 use buck2_build_api::query::bxl::BxlUqueryFunctions;
 use buck2_build_api::query::bxl::NEW_BXL_UQUERY_FUNCTIONS;
 use buck2_build_api::query::oneshot::QUERY_FRONTEND;
 use buck2_node::nodes::unconfigured::TargetNode;
+use buck2_query::query::syntax::simple::eval::file_set::FileGlob;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::functions::helpers::CapturedExpr;
 use derivative::Derivative;
@@ -44,9 +46,9 @@ use starlark::StarlarkDocs;
 use super::file_set::StarlarkFileSet;
 use super::target_list_expr::TargetListExpr;
 use crate::bxl::starlark_defs::context::BxlContext;
-use crate::bxl::starlark_defs::context::BxlContextNoDice;
 use crate::bxl::starlark_defs::file_set::FileSetExpr;
 use crate::bxl::starlark_defs::query_util::parse_query_evaluation_result;
+use crate::bxl::starlark_defs::query_util::QueryEnvCache;
 use crate::bxl::starlark_defs::target_list_expr::TargetListExprArg;
 use crate::bxl::starlark_defs::targetset::StarlarkTargetSet;
 
@@ -67,6 +69,9 @@ pub(crate) struct StarlarkUQueryCtx<'v> {
     #[trace(unsafe_ignore)]
     #[derivative(Debug = "ignore")]
     ctx: &'v BxlContext<'v>,
+    #[trace(unsafe_ignore)]
+    #[derivative(Debug = "ignore")]
+    env: QueryEnvCache<dyn BxlUqueryFunctions>,
 }
 
 #[starlark_value(type = "uqueryctx", StarlarkTypeRepr, UnpackValue)]
@@ -77,15 +82,21 @@ impl<'v> StarlarkValue<'v> for StarlarkUQueryCtx<'v> {
     }
 }
 
-pub(crate) async fn get_uquery_env<'v>(
-    ctx: &BxlContextNoDice<'v>,
-) -> anyhow::Result<Box<dyn BxlUqueryFunctions>> {
-    (NEW_BXL_UQUERY_FUNCTIONS.get()?)(
-        ctx.project_root().dupe(),
-        ctx.cell_name(),
-        ctx.cell_resolver().dupe(),
-    )
-    .await
+pub(crate) async fn get_uquery_env(
+    this: &StarlarkUQueryCtx<'_>,
+    dice: &DiceComputations<'_>,
+) -> anyhow::Result<Rc<dyn BxlUqueryFunctions>> {
+    let ctx = &this.ctx.data;
+    this.env
+        .get_or_init(dice, || async {
+            (NEW_BXL_UQUERY_FUNCTIONS.get()?)(
+                ctx.project_root().dupe(),
+                ctx.cell_name(),
+                ctx.cell_resolver().dupe(),
+            )
+            .await
+        })
+        .await
 }
 
 impl<'v> AllocValue<'v> for StarlarkUQueryCtx<'v> {
@@ -96,7 +107,10 @@ impl<'v> AllocValue<'v> for StarlarkUQueryCtx<'v> {
 
 impl<'v> StarlarkUQueryCtx<'v> {
     pub(crate) fn new(ctx: &'v BxlContext<'v>) -> anyhow::Result<Self> {
-        Ok(Self { ctx })
+        Ok(Self {
+            ctx,
+            env: QueryEnvCache::new(),
+        })
     }
 }
 
@@ -113,6 +127,9 @@ async fn unpack_targets<'c, 'v>(
 
 /// The context for performing `uquery` operations in bxl. The functions offered on this ctx are
 /// the same behaviour as the query functions available within uquery command.
+///
+/// All queries of a bxl evaluation run against the same snapshot of the repo: files changed
+/// while the script runs are only seen by the next evaluation.
 #[starlark_module]
 fn uquery_methods(builder: &mut MethodsBuilder) {
     /// The `allpaths` query for computing all dependency paths.
@@ -121,12 +138,12 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
         from: TargetListExprArg<'v>,
         to: TargetListExprArg<'v>,
     ) -> anyhow::Result<StarlarkTargetSet<TargetNode>> {
-        this.ctx.via_dice(|dice, ctx| {
+        this.ctx.via_dice(|dice, _| {
             dice.via(|dice| {
                 async {
                     let from = unpack_targets(this, dice, from).await?;
                     let to = unpack_targets(this, dice, to).await?;
-                    get_uquery_env(ctx)
+                    get_uquery_env(this, dice)
                         .await?
                         .allpaths(dice, &from, &to)
                         .await
@@ -143,12 +160,12 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
         from: TargetListExprArg<'v>,
         to: TargetListExprArg<'v>,
     ) -> anyhow::Result<StarlarkTargetSet<TargetNode>> {
-        this.ctx.via_dice(|dice, ctx| {
+        this.ctx.via_dice(|dice, _| {
             dice.via(|dice| {
                 async {
                     let from = unpack_targets(this, dice, from).await?;
                     let to = unpack_targets(this, dice, to).await?;
-                    get_uquery_env(ctx)
+                    get_uquery_env(this, dice)
                         .await?
                         .somepath(dice, &from, &to)
                         .await
@@ -159,6 +176,29 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
         })
     }
 
+    /// The shortestpath query, which returns the nodes of one shortest dependency chain, in order,
+    /// starting at a target in `from` and ending at a target in `to`.
+    fn shortestpath<'v>(
+        this: &StarlarkUQueryCtx<'v>,
+        from: TargetListExprArg<'v>,
+        to: TargetListExprArg<'v>,
+    ) -> anyhow::Result<StarlarkTargetSet<TargetNode>> {
+        this.ctx.via_dice(|dice, _| {
+            dice.via(|dice| {
+                async {
+                    let from = unpack_targets(this, dice, from).await?;
+                    let to = unpack_targets(this, dice, to).await?;
+                    get_uquery_env(this, dice)
+                        .await?
+                        .shortestpath(dice, &from, &to)
+                        .await
+                        .map(StarlarkTargetSet::from)
+                }
+                .boxed_local()
+            })
+        })
+    }
+
     /// The attrfilter query for rule attribute filtering.
     fn attrfilter<'v>(
         this: &StarlarkUQueryCtx<'v>,
@@ -181,22 +221,33 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
 
     /// The inputs query for finding input files.
     ///
+    /// If `glob` is given, only the input files whose path within their cell matches it are
+    /// returned, e.g. `**/*.proto`. The glob follows the same rules as `glob()` in build files,
+    /// in particular it is case insensitive.
+    ///
     /// Sample usage:
     /// ```text
     /// def _impl_inputs(ctx):
     ///     result = ctx.uquery().inputs("root//bin:the_binary")
     ///     ctx.output.print(result)
+    ///     protos = ctx.uquery().inputs("root//bin:the_binary", glob = "**/*.proto")
+    ///     ctx.output.print(protos)
     /// ```
     fn inputs<'v>(
         this: &StarlarkUQueryCtx<'v>,
         targets: TargetListExprArg<'v>,
+        #[starlark(default = NoneOr::None)] glob: NoneOr<&'v str>,
     ) -> anyhow::Result<StarlarkFileSet> {
+        let glob = glob.into_option().try_map(FileGlob::new)?;
         this.ctx
             .via_dice(|dice, _| {
                 dice.via(|dice| {
                     async {
                         let targets = unpack_targets(this, dice, targets).await?;
-                        targets.inputs()
+                        match &glob {
+                            Some(glob) => targets.inputs_matching(glob),
+                            None => targets.inputs(),
+                        }
                     }
                     .boxed_local()
                 })
@@ -243,7 +294,7 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
         #[starlark(default = NoneOr::None)] filter: NoneOr<&'v str>,
     ) -> anyhow::Result<StarlarkTargetSet<TargetNode>> {
         this.ctx
-            .via_dice(|dice, ctx| {
+            .via_dice(|dice, _| {
                 dice.via(|dice| {
                     async {
                         let filter = filter
@@ -252,7 +303,7 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
 
                         let targets = unpack_targets(this, dice, universe).await?;
 
-                        get_uquery_env(ctx)
+                        get_uquery_env(this, dice)
                             .await?
                             .deps(
                                 dice,
@@ -286,13 +337,13 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
         depth: Option<i32>,
     ) -> anyhow::Result<StarlarkTargetSet<TargetNode>> {
         this.ctx
-            .via_dice(|dice, ctx| {
+            .via_dice(|dice, _| {
                 dice.via(|dice| {
                     async {
                         let universe = unpack_targets(this, dice, universe).await?;
                         let targets = unpack_targets(this, dice, from).await?;
 
-                        get_uquery_env(ctx)
+                        get_uquery_env(this, dice)
                             .await?
                             .rdeps(dice, &universe, &targets, depth)
                             .await
@@ -342,11 +393,14 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
         targets: TargetListExprArg<'v>,
     ) -> anyhow::Result<StarlarkTargetSet<TargetNode>> {
         this.ctx
-            .via_dice(|dice, ctx| {
+            .via_dice(|dice, _| {
                 dice.via(|dice| {
                     async {
                         let targets = unpack_targets(this, dice, targets).await?;
-                        get_uquery_env(ctx).await?.testsof(dice, &targets).await
+                        get_uquery_env(this, dice)
+                            .await?
+                            .testsof(dice, &targets)
+                            .await
                     }
                     .boxed_local()
                 })
@@ -396,10 +450,10 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
         files: FileSetExpr,
     ) -> anyhow::Result<StarlarkTargetSet<TargetNode>> {
         this.ctx
-            .via_dice(|dice, ctx| {
+            .via_dice(|dice, _| {
                 dice.via(|dice| {
                     async {
-                        get_uquery_env(ctx)
+                        get_uquery_env(this, dice)
                             .await?
                             .owner(dice, (files.get(&this.ctx.data).await?).as_ref())
                             .await
@@ -425,10 +479,10 @@ fn uquery_methods(builder: &mut MethodsBuilder) {
         files: FileSetExpr,
     ) -> anyhow::Result<StarlarkTargetSet<TargetNode>> {
         this.ctx
-            .via_dice(|dice, ctx| {
+            .via_dice(|dice, _| {
                 dice.via(|dice| {
                     async {
-                        get_uquery_env(ctx)
+                        get_uquery_env(this, dice)
                             .await?
                             .targets_in_buildfile(dice, (files.get(&this.ctx.data).await?).as_ref())
                             .await
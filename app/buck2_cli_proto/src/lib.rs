@@ -17,6 +17,7 @@ use crate::BuckDaemonProtoError::MissingClientContext;
 
 pub mod new_generic;
 pub mod protobuf_util;
+pub mod stream_integrity;
 
 tonic::include_proto!("buck.daemon");
 
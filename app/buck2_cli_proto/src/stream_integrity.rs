@@ -0,0 +1,337 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Detects events lost or corrupted between the daemon and the client.
+//!
+//! The daemon stamps every event and partial result it sends for a command with a sequence number,
+//! and every so often sends a checkpoint with the last sequence number and the CRC of all the
+//! messages sent so far. The client checks that the sequence numbers have no gaps, and that the
+//! CRC of what it received matches the checkpoints.
+//!
+//! Both sides compute the CRC for every message, so it doesn't cover whole payloads, which would
+//! mean serializing every message once more. It covers what identifies a message instead: its
+//! sequence number and kind, and the header of events or the size of partial results.
+
+use buck2_data::buck_event::Data;
+use buck2_data::event_stream_integrity_violation::Kind;
+use buck2_data::EventStreamIntegrityViolation;
+
+use crate::command_progress::Progress;
+use crate::partial_result::PartialResult;
+use crate::CommandProgress;
+use crate::StreamCheckpoint;
+
+/// How many messages the daemon sends between two checkpoints.
+const CHECKPOINT_INTERVAL: u64 = 1000;
+
+/// Adds `progress`, sent with `sequence`, to `crc`, if it is one of the messages that are
+/// stamped. Returns whether it is.
+fn update_crc(crc: &mut crc32fast::Hasher, sequence: u64, progress: &Progress) -> bool {
+    match progress {
+        Progress::Event(event) => {
+            crc.update(&sequence.to_le_bytes());
+            let kind: u8 = match &event.data {
+                None => 0,
+                Some(Data::SpanStart(_)) => 1,
+                Some(Data::SpanEnd(_)) => 2,
+                Some(Data::Instant(_)) => 3,
+                Some(Data::Record(_)) => 4,
+            };
+            crc.update(&[kind]);
+            if let Some(timestamp) = &event.timestamp {
+                crc.update(&timestamp.seconds.to_le_bytes());
+                crc.update(&timestamp.nanos.to_le_bytes());
+            }
+            crc.update(event.trace_id.as_bytes());
+            crc.update(&event.span_id.to_le_bytes());
+            crc.update(&event.parent_id.to_le_bytes());
+        }
+        Progress::PartialResult(result) => {
+            crc.update(&sequence.to_le_bytes());
+            let (kind, len): (u8, usize) = match &result.partial_result {
+                None => (5, 0),
+                Some(PartialResult::StdoutBytes(x)) => (6, x.data.len()),
+                Some(PartialResult::LspMessage(x)) => (7, x.lsp_json.len()),
+                Some(PartialResult::SubscriptionResponseWrapper(_)) => (8, 0),
+                Some(PartialResult::DapMessage(x)) => (9, x.dap_json.len()),
+            };
+            crc.update(&[kind]);
+            crc.update(&(len as u64).to_le_bytes());
+        }
+        Progress::Result(_) | Progress::Checkpoint(_) => return false,
+    }
+    true
+}
+
+/// Daemon side: stamps the messages of the stream of a command.
+#[derive(Default)]
+pub struct StreamStamper {
+    last_sequence: u64,
+    crc: crc32fast::Hasher,
+}
+
+impl StreamStamper {
+    /// Stamps `progress` with its sequence number. Returns a checkpoint to send right after it
+    /// when one is due.
+    pub fn stamp(&mut self, progress: &mut CommandProgress) -> Option<CommandProgress> {
+        let stamped = match &progress.progress {
+            Some(p) => update_crc(&mut self.crc, self.last_sequence + 1, p),
+            None => false,
+        };
+        if !stamped {
+            return None;
+        }
+
+        self.last_sequence += 1;
+        progress.sequence = self.last_sequence;
+
+        if self.last_sequence % CHECKPOINT_INTERVAL == 0 {
+            Some(self.checkpoint())
+        } else {
+            None
+        }
+    }
+
+    /// A checkpoint covering all the messages stamped so far. Must be sent before the command
+    /// result, so that the client can check the end of the stream.
+    pub fn checkpoint(&self) -> CommandProgress {
+        CommandProgress {
+            progress: Some(Progress::Checkpoint(StreamCheckpoint {
+                last_sequence: self.last_sequence,
+                crc: self.crc.clone().finalize(),
+            })),
+            sequence: 0,
+        }
+    }
+}
+
+/// Client side: checks the messages of the stream of a command as they are received.
+#[derive(Default)]
+pub struct StreamVerifier {
+    /// Highest sequence number received.
+    last_sequence: u64,
+    /// CRC of the payloads received, resynchronized to the daemon's at every checkpoint.
+    crc: crc32fast::Hasher,
+    /// Sequence number of the last checkpoint.
+    last_checkpoint: u64,
+    /// Whether a violation was already reported since the last checkpoint, in which case the
+    /// CRC is expected to mismatch.
+    violation_since_checkpoint: bool,
+}
+
+impl StreamVerifier {
+    /// Checks the next message received on the stream.
+    pub fn verify(&mut self, progress: &CommandProgress) -> Option<EventStreamIntegrityViolation> {
+        let violation = match progress.progress.as_ref()? {
+            Progress::Checkpoint(checkpoint) => self.verify_checkpoint(checkpoint),
+            // Not stamped, e.g. injected out of band by the daemon.
+            _ if progress.sequence == 0 => None,
+            p => {
+                if !update_crc(&mut self.crc, progress.sequence, p) {
+                    return None;
+                }
+                self.verify_sequence(progress.sequence)
+            }
+        };
+        if violation.is_some() {
+            self.violation_since_checkpoint = true;
+        }
+        violation
+    }
+
+    fn verify_sequence(&mut self, sequence: u64) -> Option<EventStreamIntegrityViolation> {
+        let expected = self.last_sequence + 1;
+        if sequence < expected {
+            return Some(violation(Kind::Reordered, sequence, sequence));
+        }
+        self.last_sequence = sequence;
+        if sequence > expected {
+            Some(violation(Kind::Gap, expected, sequence - 1))
+        } else {
+            None
+        }
+    }
+
+    fn verify_checkpoint(
+        &mut self,
+        checkpoint: &StreamCheckpoint,
+    ) -> Option<EventStreamIntegrityViolation> {
+        if checkpoint.last_sequence < self.last_sequence {
+            // The checkpoint was overtaken by later messages, the CRC can't be compared.
+            return Some(violation(
+                Kind::Reordered,
+                checkpoint.last_sequence + 1,
+                self.last_sequence,
+            ));
+        }
+
+        let res = if checkpoint.last_sequence > self.last_sequence {
+            // The last messages before the checkpoint were lost.
+            Some(violation(
+                Kind::Gap,
+                self.last_sequence + 1,
+                checkpoint.last_sequence,
+            ))
+        } else if !self.violation_since_checkpoint && self.crc.clone().finalize() != checkpoint.crc
+        {
+            Some(violation(
+                Kind::ChecksumMismatch,
+                self.last_checkpoint + 1,
+                checkpoint.last_sequence,
+            ))
+        } else {
+            None
+        };
+
+        // Start over from the daemon's state, so that each violation is only reported once.
+        self.last_sequence = checkpoint.last_sequence;
+        self.last_checkpoint = checkpoint.last_sequence;
+        self.crc = crc32fast::Hasher::new_with_initial(checkpoint.crc);
+        self.violation_since_checkpoint = false;
+
+        res
+    }
+}
+
+fn violation(kind: Kind, first_sequence: u64, last_sequence: u64) -> EventStreamIntegrityViolation {
+    EventStreamIntegrityViolation {
+        kind: kind as i32,
+        first_sequence,
+        last_sequence,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_data::event_stream_integrity_violation::Kind;
+    use buck2_data::EventStreamIntegrityViolation;
+
+    use crate::command_progress::Progress;
+    use crate::stream_integrity::violation;
+    use crate::stream_integrity::StreamStamper;
+    use crate::stream_integrity::StreamVerifier;
+    use crate::CommandProgress;
+
+    /// The messages the daemon sends for a command with `count` events, as stamped by the daemon.
+    fn daemon_stream(count: u64) -> Vec<CommandProgress> {
+        let mut stamper = StreamStamper::default();
+        let mut messages = Vec::new();
+        for i in 0..count {
+            let mut event = CommandProgress {
+                progress: Some(Progress::Event(Box::new(buck2_data::BuckEvent {
+                    trace_id: "trace".to_owned(),
+                    span_id: i,
+                    ..Default::default()
+                }))),
+                sequence: 0,
+            };
+            let checkpoint = stamper.stamp(&mut event);
+            messages.push(event);
+            messages.extend(checkpoint);
+        }
+        messages.push(stamper.checkpoint());
+        messages
+    }
+
+    fn position(messages: &[CommandProgress], sequence: u64) -> usize {
+        messages
+            .iter()
+            .position(|m| m.sequence == sequence)
+            .unwrap()
+    }
+
+    fn drop_event(mut messages: Vec<CommandProgress>, sequence: u64) -> Vec<CommandProgress> {
+        messages.remove(position(&messages, sequence));
+        messages
+    }
+
+    fn corrupt_event(mut messages: Vec<CommandProgress>, sequence: u64) -> Vec<CommandProgress> {
+        let i = position(&messages, sequence);
+        match &mut messages[i].progress {
+            Some(Progress::Event(event)) => event.trace_id = "corrupted".to_owned(),
+            _ => unreachable!(),
+        }
+        messages
+    }
+
+    fn verify_stream(messages: &[CommandProgress]) -> Vec<EventStreamIntegrityViolation> {
+        let mut verifier = StreamVerifier::default();
+        messages.iter().filter_map(|m| verifier.verify(m)).collect()
+    }
+
+    #[test]
+    fn test_clean_stream() {
+        assert_eq!(
+            Vec::<EventStreamIntegrityViolation>::new(),
+            verify_stream(&[])
+        );
+        assert_eq!(
+            Vec::<EventStreamIntegrityViolation>::new(),
+            verify_stream(&daemon_stream(10))
+        );
+        assert_eq!(
+            Vec::<EventStreamIntegrityViolation>::new(),
+            verify_stream(&daemon_stream(2500))
+        );
+    }
+
+    #[test]
+    fn test_dropped_event() {
+        assert_eq!(
+            vec![violation(Kind::Gap, 1500, 1500)],
+            verify_stream(&drop_event(daemon_stream(2500), 1500))
+        );
+        // Dropping events right before a checkpoint is detected by the checkpoint.
+        assert_eq!(
+            vec![violation(Kind::Gap, 2499, 2500)],
+            verify_stream(&drop_event(drop_event(daemon_stream(2500), 2500), 2499))
+        );
+    }
+
+    #[test]
+    fn test_corrupted_event() {
+        assert_eq!(
+            vec![violation(Kind::ChecksumMismatch, 1, 1000)],
+            verify_stream(&corrupt_event(daemon_stream(2500), 700))
+        );
+        assert_eq!(
+            vec![violation(Kind::ChecksumMismatch, 2001, 2500)],
+            verify_stream(&corrupt_event(daemon_stream(2500), 2100))
+        );
+    }
+
+    #[test]
+    fn test_reordered_events() {
+        let mut messages = daemon_stream(20);
+        messages.swap(position(&messages, 10), position(&messages, 11));
+        assert_eq!(
+            vec![
+                violation(Kind::Gap, 10, 10),
+                violation(Kind::Reordered, 10, 10)
+            ],
+            verify_stream(&messages)
+        );
+    }
+
+    #[test]
+    fn test_unstamped_messages_are_ignored() {
+        let mut messages = daemon_stream(20);
+        messages.insert(
+            5,
+            CommandProgress {
+                progress: Some(Progress::Event(Box::default())),
+                sequence: 0,
+            },
+        );
+        assert_eq!(
+            Vec::<EventStreamIntegrityViolation>::new(),
+            verify_stream(&messages)
+        );
+    }
+}
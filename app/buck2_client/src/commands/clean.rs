@@ -15,6 +15,7 @@ use anyhow::Context;
 use buck2_client_ctx::client_ctx::ClientCommandContext;
 use buck2_client_ctx::common::target_cfg::TargetCfgUnusedOptions;
 use buck2_client_ctx::common::CommonCommandOptions;
+use buck2_client_ctx::daemon::client::kill::GRACEFUL_SHUTDOWN_TIMEOUT;
 use buck2_client_ctx::daemon::client::BuckdLifecycleLock;
 use buck2_client_ctx::exit_result::ExitResult;
 use buck2_client_ctx::final_console::FinalConsole;
@@ -69,6 +70,12 @@ the specified duration, without killing the daemon",
     #[clap(long = "tracked-only", requires = "stale")]
     tracked_only: bool,
 
+    /// Compact the materializer state db to release the space left behind by deleted artifacts,
+    /// without killing the daemon. This blocks materializations while it runs. When combined with
+    /// `--stale`, compacts after cleaning.
+    #[clap(long = "compact-db", conflicts_with = "dry_run")]
+    compact_db: bool,
+
     /// Command doesn't need these flags, but they are used in mode files, so we need to keep them.
     #[clap(flatten)]
     _target_cfg: TargetCfgUnusedOptions,
@@ -79,12 +86,14 @@ the specified duration, without killing the daemon",
 
 impl CleanCommand {
     pub fn exec(self, matches: &clap::ArgMatches, ctx: ClientCommandContext<'_>) -> ExitResult {
-        if let Some(keep_since_arg) = parse_clean_stale_args(self.stale, self.keep_since_time)? {
+        let keep_since_arg = parse_clean_stale_args(self.stale, self.keep_since_time)?;
+        if keep_since_arg.is_some() || self.compact_db {
             let cmd = CleanStaleCommand {
                 common_opts: self.common_opts,
                 keep_since_arg,
                 dry_run: self.dry_run,
                 tracked_only: self.tracked_only,
+                compact_db: self.compact_db,
             };
             return cmd.exec(matches, ctx);
         }
@@ -110,7 +119,12 @@ impl CleanCommand {
                 .await
                 .with_context(|| "Error locking buckd lifecycle.lock")?;
 
-                kill_command_impl(&lifecycle_lock, "`buck2 clean` was invoked").await?;
+                kill_command_impl(
+                    &lifecycle_lock,
+                    "`buck2 clean` was invoked",
+                    GRACEFUL_SHUTDOWN_TIMEOUT,
+                )
+                .await?;
 
                 clean(buck_out_dir, daemon_dir, console, Some(&lifecycle_lock)).await
             },
@@ -35,9 +35,11 @@ use humantime;
 /// This is just so that it can be used as a StreamingCommand, which CleanCommand should not be.
 pub struct CleanStaleCommand {
     pub(crate) common_opts: CommonCommandOptions,
-    pub keep_since_arg: KeepSinceArg,
+    /// Nothing is cleaned if unset, which is useful to only compact the db.
+    pub keep_since_arg: Option<KeepSinceArg>,
     pub dry_run: bool,
     pub tracked_only: bool,
+    pub compact_db: bool,
 }
 
 /// Specifies the maximum age of artifacts to keep
@@ -91,6 +93,14 @@ fn format_result_stats(stats: buck2_data::CleanStaleStats) -> String {
     output
 }
 
+fn format_compaction(compaction: buck2_data::SqliteVacuum) -> String {
+    format!(
+        "Compacted materializer state db from {} to {}",
+        bytesize::to_string(compaction.size_before_bytes, true),
+        bytesize::to_string(compaction.size_after_bytes, true),
+    )
+}
+
 #[async_trait]
 impl StreamingCommand for CleanStaleCommand {
     const COMMAND_NAME: &'static str = "clean-stale";
@@ -102,7 +112,8 @@ impl StreamingCommand for CleanStaleCommand {
         ctx: &mut ClientCommandContext<'_>,
     ) -> ExitResult {
         let keep_since_time = match self.keep_since_arg {
-            KeepSinceArg::Duration(duration) => {
+            None => None,
+            Some(KeepSinceArg::Duration(duration)) => {
                 let keep_since_time: DateTime<Utc> = Utc::now()
                     .checked_sub_signed(duration)
                     .context("Duration underflow")?;
@@ -114,12 +125,13 @@ impl StreamingCommand for CleanStaleCommand {
                 )?;
                 // Round up to next second since timestamp below is rounded down
                 // (this way clean --stale=0s immediately after a build deletes the result)
-                keep_since_time + chrono::Duration::seconds(1)
+                Some(keep_since_time + chrono::Duration::seconds(1))
             }
-            KeepSinceArg::Time(timestamp) => Utc
-                .timestamp_opt(timestamp, 0)
-                .single()
-                .context("Invalid timestamp")?,
+            Some(KeepSinceArg::Time(timestamp)) => Some(
+                Utc.timestamp_opt(timestamp, 0)
+                    .single()
+                    .context("Invalid timestamp")?,
+            ),
         };
 
         let context = ctx.client_context(matches, &self)?;
@@ -128,9 +140,10 @@ impl StreamingCommand for CleanStaleCommand {
             .clean_stale(
                 CleanStaleRequest {
                     context: Some(context),
-                    keep_since_time: keep_since_time.timestamp(),
+                    keep_since_time: keep_since_time.map(|t| t.timestamp()),
                     dry_run: self.dry_run,
                     tracked_only: self.tracked_only,
+                    compact_db: self.compact_db,
                 },
                 ctx.stdin()
                     .console_interaction_stream(&self.common_opts.console_opts),
@@ -144,6 +157,9 @@ impl StreamingCommand for CleanStaleCommand {
         if let Some(stats) = response.stats {
             buck2_client_ctx::eprintln!("{}", format_result_stats(stats))?;
         }
+        if let Some(compaction) = response.compaction {
+            buck2_client_ctx::eprintln!("{}", format_compaction(compaction))?;
+        }
         ExitResult::success()
     }
 
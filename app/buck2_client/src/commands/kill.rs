@@ -19,6 +19,7 @@ use buck2_client_ctx::exit_result::ExitResult;
 use buck2_client_ctx::startup_deadline::StartupDeadline;
 use buck2_common::argv::Argv;
 use buck2_common::argv::SanitizedArgv;
+use buck2_wrapper_common::kill::ProcessExit;
 
 /// Kill the buck daemon.
 ///
@@ -31,6 +32,10 @@ use buck2_common::argv::SanitizedArgv;
 pub struct KillCommand {
     #[clap(flatten)]
     pub(crate) event_log_opts: CommonEventLogOptions,
+
+    /// Wait `<timeout>` seconds for the daemon to shut down before killing it forcefully
+    #[clap(long, default_value = "10")]
+    timeout: u64,
 }
 
 impl KillCommand {
@@ -45,7 +50,12 @@ impl KillCommand {
             .await
             .with_context(|| "Error locking buckd lifecycle.lock")?;
 
-            kill_command_impl(&lifecycle_lock, "`buck kill` was invoked").await
+            kill_command_impl(
+                &lifecycle_lock,
+                "`buck kill` was invoked",
+                Duration::from_secs(self.timeout),
+            )
+            .await
         })
     }
 
@@ -57,6 +67,7 @@ impl KillCommand {
 pub async fn kill_command_impl(
     lifecycle_lock: &BuckdLifecycleLock,
     reason: &str,
+    timeout: Duration,
 ) -> anyhow::Result<()> {
     let process = match BuckdProcessInfo::load(lifecycle_lock.daemon_dir()) {
         Ok(p) => p,
@@ -72,10 +83,10 @@ pub async fn kill_command_impl(
     })
     .await;
 
-    let pid = match buckd {
+    let exit = match buckd {
         Ok(Ok(mut buckd)) => {
             buck2_client_ctx::eprintln!("killing buckd server")?;
-            Some(buckd.kill(reason).await?)
+            Some(buckd.kill_with_timeout(reason, timeout).await?)
         }
         Ok(Err(e)) => {
             // No time out: we just errored out. This is likely indicative that there is no
@@ -107,13 +118,22 @@ pub async fn kill_command_impl(
             // This means the socket is probably open. We can reasonably got and kill this
             // process if both the PID and the port exist.
             buck2_client_ctx::eprintln!("killing unresponsive buckd server")?;
-            process.hard_kill().await?;
-            Some(process.pid()?)
+            Some(process.hard_kill().await?)
         }
     };
 
-    if let Some(pid) = pid {
-        buck2_client_ctx::eprintln!("Buck2 daemon pid {} has exited", pid)?;
+    match exit {
+        Some(ProcessExit::Exited) => {
+            buck2_client_ctx::eprintln!("Buck2 daemon pid {} has exited", process.pid()?)?;
+        }
+        Some(ProcessExit::Terminated) => {
+            buck2_client_ctx::eprintln!("Buck2 daemon pid {} was terminated", process.pid()?)?;
+        }
+        Some(ProcessExit::Killed) => {
+            buck2_client_ctx::eprintln!("Buck2 daemon pid {} was killed", process.pid()?)?;
+        }
+        // Reported as an error by the kill itself.
+        Some(ProcessExit::StillRunning { .. }) | None => {}
     }
 
     Ok(())
@@ -13,19 +13,36 @@ use buck2_client_ctx::exit_result::ExitResult;
 use buck2_common::argv::Argv;
 use buck2_common::argv::SanitizedArgv;
 use buck2_wrapper_common::is_buck2::WhoIsAsking;
+use buck2_wrapper_common::KillallOptions;
 
 #[derive(Debug, clap::Parser)]
 #[clap(about = "Kill all buck2 processes on the machine")]
 pub struct KillallCommand {
     #[clap(flatten)]
     pub(crate) event_log_opts: CommonEventLogOptions,
+
+    /// Only print the buck2 processes which would be killed
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Print one JSON record per process to stdout
+    #[clap(long)]
+    json: bool,
 }
 
 impl KillallCommand {
     pub fn exec(self, _matches: &clap::ArgMatches, ctx: ClientCommandContext<'_>) -> ExitResult {
         ctx.instant_command("killall", &self.event_log_opts, |_ctx| async move {
-            buck2_wrapper_common::killall(WhoIsAsking::Buck2, |s| {
-                let _ignored = buck2_client_ctx::eprintln!("{}", s);
+            let options = KillallOptions {
+                dry_run: self.dry_run,
+                json: self.json,
+            };
+            buck2_wrapper_common::killall_with_options(WhoIsAsking::Buck2, options, |s| {
+                let _ignored = if self.json {
+                    buck2_client_ctx::println!("{}", s)
+                } else {
+                    buck2_client_ctx::eprintln!("{}", s)
+                };
             })
             .then_some(())
             .ok_or(anyhow::anyhow!("Killall command failed"))
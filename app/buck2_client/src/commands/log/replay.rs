@@ -64,7 +64,7 @@ impl ReplayCommand {
 
                 let console = get_console_with_root(
                     invocation.trace_id,
-                    console_opts.console_type,
+                    console_opts.console_type()?,
                     ctx.verbosity,
                     true,
                     speed,
@@ -83,7 +83,7 @@ impl StreamingCommand for LspCommand {
         // This should only be communicated with by an IDE, so disable anything other
         // than the simple console
         static SIMPLE_CONSOLE: Lazy<CommonConsoleOptions> = Lazy::new(|| CommonConsoleOptions {
-            console_type: ConsoleType::Simple,
+            console_type: Some(ConsoleType::Simple),
             ui: vec![],
             no_interactive_console: true,
         });
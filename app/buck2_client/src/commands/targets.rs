@@ -39,6 +39,10 @@ enum TargetsError {
     /// Clap should report it, but if we missed something, this is a fallback.
     #[error("Flags are mutually exclusive")]
     IncompatibleArguments,
+    #[error(
+        "`--output-format=proto` cannot be used with `--imports`, target hashes, package values or outputs"
+    )]
+    ProtoIncompatibleArguments,
 }
 
 // Use non-camel case so the possible values match buck1's
@@ -51,6 +55,16 @@ enum TargetHashFileMode {
     None,
 }
 
+#[derive(Debug, clap::ValueEnum, Clone, Dupe)]
+#[clap(rename_all = "snake_case")]
+enum TargetsOutputFormat {
+    Text,
+    Json,
+    JsonLines,
+    Stats,
+    Proto,
+}
+
 #[derive(Debug, clap::ValueEnum, Clone, Dupe)]
 enum TargetHashGraphType {
     None,
@@ -90,6 +104,15 @@ pub struct TargetsCommand {
     #[clap(long)]
     stats: bool,
 
+    /// Output format. `proto` writes a header followed by one message per target, each prefixed
+    /// with its length, as packages are loaded. See `targets.proto` in `buck2_data` for the schema.
+    #[clap(
+        long,
+        value_enum,
+        conflicts_with_all = &["json", "json_lines", "stats", "resolve_alias"]
+    )]
+    output_format: Option<TargetsOutputFormat>,
+
     /// Print the fully-qualified build target for the specified aliases
     #[clap(long, alias = "resolvealias")]
     resolve_alias: bool,
@@ -200,6 +223,34 @@ pub struct TargetsCommand {
 impl TargetsCommand {
     #[allow(clippy::if_same_then_else)]
     fn output_format(&self) -> anyhow::Result<OutputFormat> {
+        if let Some(output_format) = &self.output_format {
+            if self.json || self.json_lines || self.stats {
+                return Err(TargetsError::IncompatibleArguments.into());
+            }
+            return match output_format {
+                TargetsOutputFormat::Text | TargetsOutputFormat::Stats
+                    if !self.attributes.get()?.is_empty() =>
+                {
+                    Err(TargetsError::IncompatibleArguments.into())
+                }
+                TargetsOutputFormat::Text => Ok(OutputFormat::Text),
+                TargetsOutputFormat::Json => Ok(OutputFormat::Json),
+                TargetsOutputFormat::JsonLines => Ok(OutputFormat::JsonLines),
+                TargetsOutputFormat::Stats => Ok(OutputFormat::Stats),
+                TargetsOutputFormat::Proto => {
+                    if self.imports
+                        || self.show_target_hash
+                        || self.show_unconfigured_target_hash
+                        || self.package_values
+                        || !self.package_values_regex.is_empty()
+                        || self.show_output.format().is_some()
+                    {
+                        return Err(TargetsError::ProtoIncompatibleArguments.into());
+                    }
+                    Ok(OutputFormat::Proto)
+                }
+            };
+        }
         if self.json {
             if self.json_lines || self.stats {
                 return Err(TargetsError::IncompatibleArguments.into());
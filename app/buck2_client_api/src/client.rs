@@ -0,0 +1,362 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Connecting to the daemon and running requests.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use buck2_cli_proto::config_override::ConfigType;
+use buck2_cli_proto::ClientContext;
+use buck2_cli_proto::ConfigOverride;
+use buck2_client_ctx::command_outcome::CommandOutcome;
+use buck2_client_ctx::daemon::client::connect::establish_connection_existing;
+use buck2_client_ctx::daemon::client::connect::BootstrapBuckdClient;
+use buck2_client_ctx::subscribers::subscribers::EventSubscribers;
+use buck2_common::daemon_dir::DaemonDir;
+use buck2_common::invocation_paths::InvocationPaths;
+use buck2_common::invocation_roots::find_invocation_roots;
+use buck2_core::fs::paths::file_name::FileNameBuf;
+use buck2_error::ErrorTag;
+use buck2_wrapper_common::invocation_id::TraceId;
+
+use crate::events::EventHandler;
+use crate::events::ForwardEvents;
+use crate::request::private::Connection;
+use crate::request::CommandOutput;
+use crate::request::DaemonRequest;
+
+#[derive(Debug, buck2_error::Error)]
+#[non_exhaustive]
+pub enum ClientError {
+    #[error("Command failed:\n{}", _0.join("\n"))]
+    CommandFailed(Vec<String>),
+    #[error(
+        "Failed to start the buck2 daemon with `{}` (exit code {code:?}):\n{stderr}",
+        binary.display()
+    )]
+    DaemonStartFailed {
+        binary: PathBuf,
+        code: Option<i32>,
+        stderr: String,
+    },
+    #[error("Working directory is not UTF-8: `{}`", _0.display())]
+    WorkingDirNotUtf8(PathBuf),
+}
+
+/// What to do when no daemon is running.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum DaemonStartup {
+    /// Fail to connect.
+    ExistingOnly,
+    /// Start a daemon by running `server` with the given `buck2` binary. The daemon is started by
+    /// the binary rather than by this library so that it matches the version of the binary, and
+    /// is shared with the commands run by the user.
+    StartWith(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ClientOptions {
+    /// The directory the requests are run from, like the current directory of a `buck2` command.
+    pub working_dir: PathBuf,
+    /// Same as `buck2 --isolation-dir`.
+    pub isolation_dir: String,
+    /// Config overrides in the form `section.key=value`, same as `buck2 --config`.
+    pub config_overrides: Vec<String>,
+    pub oncall: Option<String>,
+    pub startup: DaemonStartup,
+}
+
+impl ClientOptions {
+    pub fn new(working_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            working_dir: working_dir.into(),
+            isolation_dir: "v2".to_owned(),
+            config_overrides: Vec::new(),
+            oncall: None,
+            startup: DaemonStartup::StartWith(PathBuf::from("buck2")),
+        }
+    }
+}
+
+/// A connection to the daemon, which is reused by all the requests.
+pub struct Buck2Client {
+    options: ClientOptions,
+    daemon_dir: DaemonDir,
+    /// Dropped when the daemon goes away, so that the next request connects to its replacement.
+    connection: Option<BootstrapBuckdClient>,
+}
+
+impl Buck2Client {
+    pub async fn connect(options: ClientOptions) -> anyhow::Result<Self> {
+        let paths = InvocationPaths {
+            roots: find_invocation_roots(&options.working_dir)?,
+            isolation: FileNameBuf::try_from(options.isolation_dir.clone())
+                .context("isolation dir must be a directory name")?,
+        };
+        let mut client = Self::new(options, paths.daemon_dir()?);
+        client.connection().await?;
+        Ok(client)
+    }
+
+    pub(crate) fn new(options: ClientOptions, daemon_dir: DaemonDir) -> Self {
+        Self {
+            options,
+            daemon_dir,
+            connection: None,
+        }
+    }
+
+    async fn connection(&mut self) -> anyhow::Result<BootstrapBuckdClient> {
+        if let Some(connection) = &self.connection {
+            return Ok(connection.clone());
+        }
+
+        let connection = match establish_connection_existing(&self.daemon_dir).await {
+            Ok(connection) => connection,
+            Err(e) => match &self.options.startup {
+                DaemonStartup::ExistingOnly => return Err(e),
+                DaemonStartup::StartWith(binary) => {
+                    self.start_daemon(binary).await?;
+                    establish_connection_existing(&self.daemon_dir).await?
+                }
+            },
+        };
+        self.connection = Some(connection.clone());
+        Ok(connection)
+    }
+
+    async fn start_daemon(&self, binary: &Path) -> anyhow::Result<()> {
+        let output = tokio::process::Command::new(binary)
+            // The daemon is much slower when started with backtraces enabled, and tools are
+            // often run with them enabled by IDEs.
+            .env_remove("RUST_BACKTRACE")
+            .env_remove("RUST_LIB_BACKTRACE")
+            .arg("--isolation-dir")
+            .arg(&self.options.isolation_dir)
+            .arg("server")
+            .current_dir(&self.options.working_dir)
+            .output()
+            .await
+            .with_context(|| format!("Error running `{}`", binary.display()))?;
+        if !output.status.success() {
+            return Err(ClientError::DaemonStartFailed {
+                binary: binary.to_path_buf(),
+                code: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn client_context(&self, command_name: &str) -> anyhow::Result<ClientContext> {
+        Ok(ClientContext {
+            working_dir: self
+                .options
+                .working_dir
+                .to_str()
+                .ok_or_else(|| ClientError::WorkingDirNotUtf8(self.options.working_dir.clone()))?
+                .to_owned(),
+            config_overrides: self
+                .options
+                .config_overrides
+                .iter()
+                .map(|config_override| ConfigOverride {
+                    config_override: config_override.clone(),
+                    config_type: ConfigType::Value as i32,
+                })
+                .collect(),
+            oncall: self.options.oncall.clone().unwrap_or_default(),
+            trace_id: TraceId::new().to_string(),
+            command_name: command_name.to_owned(),
+            ..Default::default()
+        })
+    }
+
+    /// Run a request, passing its events to `events`.
+    ///
+    /// If the daemon goes away while running an idempotent request (e.g. because it was
+    /// restarted by another command), the request is sent again once to the new daemon.
+    pub async fn run<R: DaemonRequest>(
+        &mut self,
+        mut request: R,
+        events: &mut dyn EventHandler,
+    ) -> anyhow::Result<CommandOutput<R::Response>> {
+        request.set_context(self.client_context(R::COMMAND_NAME)?);
+
+        match self.run_once(&request, events).await {
+            // The connection is dropped when the daemon went away.
+            Err(e) if R::IDEMPOTENT && self.connection.is_none() => {
+                tracing::debug!("Daemon went away, retrying request: {:#}", e);
+                self.run_once(&request, events).await
+            }
+            res => res,
+        }
+    }
+
+    async fn run_once<R: DaemonRequest>(
+        &mut self,
+        request: &R,
+        events: &mut dyn EventHandler,
+    ) -> anyhow::Result<CommandOutput<R::Response>> {
+        let connection = self.connection().await?;
+        let mut errors = Vec::new();
+        let mut stdout = Vec::new();
+
+        let outcome = {
+            let mut connector =
+                connection.with_subscribers(EventSubscribers::new(vec![Box::new(ForwardEvents {
+                    handler: events,
+                    errors: &mut errors,
+                })]));
+            request
+                .send(&mut Connection(connector.with_flushing()), &mut stdout)
+                .await
+        };
+
+        match outcome {
+            Ok(CommandOutcome::Success(response)) => Ok(CommandOutput { response, stdout }),
+            Ok(CommandOutcome::Failure(_)) => Err(ClientError::CommandFailed(errors).into()),
+            Err(e) => {
+                let e = buck2_error::Error::from(e);
+                if is_daemon_gone(&e) {
+                    self.connection = None;
+                }
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// Whether the request failed because the connection to the daemon was lost.
+fn is_daemon_gone(error: &buck2_error::Error) -> bool {
+    error.tags().iter().any(|t| {
+        matches!(
+            t,
+            ErrorTag::ClientGrpc | ErrorTag::InterruptedByDaemonShutdown
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use buck2_cli_proto::BuildRequest;
+    use buck2_cli_proto::UqueryRequest;
+    use buck2_data::BuckEvent;
+
+    use crate::client::Buck2Client;
+    use crate::client::ClientOptions;
+    use crate::client::DaemonStartup;
+    use crate::events::IgnoreEvents;
+    use crate::mock_daemon::MockDaemons;
+
+    fn client(daemons: &Arc<MockDaemons>) -> Buck2Client {
+        let mut options = ClientOptions::new(std::env::temp_dir());
+        options.startup = DaemonStartup::ExistingOnly;
+        Buck2Client::new(options, daemons.daemon_dir.clone())
+    }
+
+    fn uquery(query: &str) -> UqueryRequest {
+        UqueryRequest {
+            query: query.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect() -> anyhow::Result<()> {
+        let daemons = MockDaemons::start().await?;
+        let mut client = client(&daemons);
+
+        client.connection().await?;
+        client.connection().await?;
+
+        assert_eq!(1, daemons.started.load(Ordering::SeqCst));
+        assert!(daemons.commands().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_connect_without_daemon() -> anyhow::Result<()> {
+        let daemons = MockDaemons::start().await?;
+        std::fs::remove_file(daemons.daemon_dir.buckd_info())?;
+
+        assert!(client(&daemons).connection().await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query() -> anyhow::Result<()> {
+        let daemons = MockDaemons::start().await?;
+        let mut client = client(&daemons);
+
+        let mut events = Vec::new();
+        let output = client
+            .run(uquery("deps(//:foo)"), &mut |event: &BuckEvent| {
+                events.push(event.clone())
+            })
+            .await?;
+
+        assert_eq!(b"deps(//:foo)\n", output.stdout.as_slice());
+        assert_eq!(vec!["uquery"], daemons.commands());
+        // The event which isn't about progress is filtered out.
+        assert_eq!(1, events.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_request_retried_on_restart() -> anyhow::Result<()> {
+        let daemons = MockDaemons::start().await?;
+        let mut client = client(&daemons);
+        client.connection().await?;
+
+        daemons
+            .restart_on_next_request
+            .store(true, Ordering::SeqCst);
+        let output = client.run(uquery("//:foo"), &mut IgnoreEvents).await?;
+
+        assert_eq!(b"//:foo\n", output.stdout.as_slice());
+        assert_eq!(2, daemons.started.load(Ordering::SeqCst));
+        assert_eq!(vec!["uquery", "uquery"], daemons.commands());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_build_not_retried_on_restart() -> anyhow::Result<()> {
+        let daemons = MockDaemons::start().await?;
+        let mut client = client(&daemons);
+        client.connection().await?;
+
+        daemons
+            .restart_on_next_request
+            .store(true, Ordering::SeqCst);
+        assert!(
+            client
+                .run(BuildRequest::default(), &mut IgnoreEvents)
+                .await
+                .is_err()
+        );
+        assert_eq!(vec!["build"], daemons.commands());
+
+        // The next request goes to the new daemon.
+        client
+            .run(BuildRequest::default(), &mut IgnoreEvents)
+            .await?;
+        assert_eq!(2, daemons.started.load(Ordering::SeqCst));
+        assert_eq!(vec!["build", "build"], daemons.commands());
+        Ok(())
+    }
+}
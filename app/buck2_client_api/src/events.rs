@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Events sent by the daemon while running a request.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use buck2_client_ctx::subscribers::subscriber::EventSubscriber;
+use buck2_data::buck_event;
+use buck2_data::instant_event;
+use buck2_data::span_end_event;
+use buck2_data::span_start_event;
+use buck2_data::BuckEvent;
+
+/// Receives the events of a request, e.g. to report progress.
+pub trait EventHandler: Send {
+    /// Which events are passed to `handle_event`. Defaults to [`is_progress_event`].
+    fn wants(&self, event: &BuckEvent) -> bool {
+        is_progress_event(event)
+    }
+
+    fn handle_event(&mut self, event: &BuckEvent);
+}
+
+/// Drops all events.
+pub struct IgnoreEvents;
+
+impl EventHandler for IgnoreEvents {
+    fn wants(&self, _event: &BuckEvent) -> bool {
+        false
+    }
+
+    fn handle_event(&mut self, _event: &BuckEvent) {}
+}
+
+impl<F: FnMut(&BuckEvent) + Send> EventHandler for F {
+    fn handle_event(&mut self, event: &BuckEvent) {
+        self(event)
+    }
+}
+
+/// The events a tool would use to report progress: start and end of the command, of loading
+/// packages, of analysis and of actions, as well as periodic snapshots and console messages.
+pub fn is_progress_event(event: &BuckEvent) -> bool {
+    match &event.data {
+        Some(buck_event::Data::SpanStart(start)) => matches!(
+            start.data,
+            Some(
+                span_start_event::Data::Command(_)
+                    | span_start_event::Data::Load(_)
+                    | span_start_event::Data::Analysis(_)
+                    | span_start_event::Data::ActionExecution(_)
+            )
+        ),
+        Some(buck_event::Data::SpanEnd(end)) => matches!(
+            end.data,
+            Some(
+                span_end_event::Data::Command(_)
+                    | span_end_event::Data::Load(_)
+                    | span_end_event::Data::Analysis(_)
+                    | span_end_event::Data::ActionExecution(_)
+            )
+        ),
+        Some(buck_event::Data::Instant(instant)) => matches!(
+            instant.data,
+            Some(instant_event::Data::Snapshot(_) | instant_event::Data::ConsoleMessage(_))
+        ),
+        _ => false,
+    }
+}
+
+/// Forwards the events of a request to an `EventHandler`, and keeps the error messages of a
+/// failed command, which are otherwise only printed by the console.
+pub(crate) struct ForwardEvents<'a> {
+    pub(crate) handler: &'a mut dyn EventHandler,
+    pub(crate) errors: &'a mut Vec<String>,
+}
+
+#[async_trait]
+impl<'a> EventSubscriber for ForwardEvents<'a> {
+    async fn handle_events(
+        &mut self,
+        events: &[Arc<buck2_events::BuckEvent>],
+    ) -> anyhow::Result<()> {
+        for event in events {
+            if self.handler.wants(event.event()) {
+                self.handler.handle_event(event.event());
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_command_result(
+        &mut self,
+        result: &buck2_cli_proto::CommandResult,
+    ) -> anyhow::Result<()> {
+        if let Some(buck2_cli_proto::command_result::Result::Error(error)) = &result.result {
+            self.errors
+                .extend(error.errors.iter().map(|e| e.message.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_data::buck_event;
+    use buck2_data::instant_event;
+    use buck2_data::span_start_event;
+    use buck2_data::BuckEvent;
+
+    use crate::events::is_progress_event;
+
+    fn event(data: buck_event::Data) -> BuckEvent {
+        BuckEvent {
+            data: Some(data),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_progress_events() {
+        assert!(is_progress_event(&event(buck_event::Data::SpanStart(
+            buck2_data::SpanStartEvent {
+                data: Some(span_start_event::Data::Analysis(Default::default())),
+            }
+        ))));
+        assert!(is_progress_event(&event(buck_event::Data::Instant(
+            buck2_data::InstantEvent {
+                data: Some(instant_event::Data::ConsoleMessage(Default::default())),
+            }
+        ))));
+        assert!(!is_progress_event(&event(buck_event::Data::SpanStart(
+            buck2_data::SpanStartEvent {
+                data: Some(span_start_event::Data::FileWatcher(Default::default())),
+            }
+        ))));
+        assert!(!is_progress_event(&event(buck_event::Data::Instant(
+            buck2_data::InstantEvent {
+                data: Some(instant_event::Data::BuildGraphInfo(Default::default())),
+            }
+        ))));
+    }
+}
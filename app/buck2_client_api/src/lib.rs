@@ -0,0 +1,39 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+#![feature(error_generic_member_access)]
+
+//! Library for tools which drive the buck2 daemon directly instead of running the `buck2` binary
+//! and parsing its output.
+//!
+//! Requests and responses are the daemon's protobuf messages (e.g. `CqueryRequest`), so no
+//! output format needs to be parsed, and a single connection is reused across requests.
+//! Nothing is printed: the daemon's events are passed to an [`EventHandler`], filtered to the
+//! ones reporting progress by default.
+//!
+//! This crate is used by tools outside of buck2, so changes to its public API should be
+//! backwards compatible.
+
+pub mod client;
+pub mod events;
+#[cfg(test)]
+mod mock_daemon;
+pub mod request;
+
+pub use buck2_cli_proto as proto;
+pub use buck2_data as data;
+
+pub use crate::client::Buck2Client;
+pub use crate::client::ClientError;
+pub use crate::client::ClientOptions;
+pub use crate::client::DaemonStartup;
+pub use crate::events::EventHandler;
+pub use crate::events::IgnoreEvents;
+pub use crate::request::CommandOutput;
+pub use crate::request::DaemonRequest;
@@ -0,0 +1,278 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! An in-process daemon with canned responses, to test the client without running buck2.
+
+use std::fs::File;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use buck2_cli_proto::daemon_api_server::DaemonApi;
+use buck2_cli_proto::daemon_api_server::DaemonApiServer;
+use buck2_cli_proto::*;
+use buck2_common::daemon_dir::DaemonDir;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_data::buck_event;
+use buck2_data::instant_event;
+use futures::future::BoxFuture;
+use futures::Stream;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tonic::Streaming;
+
+type ResponseStream =
+    Pin<Box<dyn Stream<Item = Result<MultiCommandProgress, Status>> + Send + Sync>>;
+
+/// The state shared by all the daemons started in a daemon dir.
+pub(crate) struct MockDaemons {
+    _tempdir: tempfile::TempDir,
+    pub(crate) daemon_dir: DaemonDir,
+    /// The number of daemons started. The last one is the one in `buckd.info`.
+    pub(crate) started: AtomicUsize,
+    /// The command names of the requests received by all the daemons.
+    pub(crate) commands: Mutex<Vec<String>>,
+    /// Make the next request shut down the daemon and start a new one, like a daemon killed by a
+    /// command with different constraints.
+    pub(crate) restart_on_next_request: AtomicBool,
+}
+
+impl MockDaemons {
+    /// Create a daemon dir, and start a daemon in it.
+    pub(crate) async fn start() -> anyhow::Result<Arc<Self>> {
+        let tempdir = tempfile::tempdir()?;
+        let daemon_dir = DaemonDir {
+            path: AbsNormPathBuf::try_from(tempdir.path().to_owned())?,
+        };
+        File::create(daemon_dir.buckd_stdout())?;
+        File::create(daemon_dir.buckd_stderr())?;
+
+        let daemons = Arc::new(MockDaemons {
+            _tempdir: tempdir,
+            daemon_dir,
+            started: AtomicUsize::new(0),
+            commands: Mutex::new(Vec::new()),
+            restart_on_next_request: AtomicBool::new(false),
+        });
+        MockDaemon::start(daemons.clone()).await?;
+        Ok(daemons)
+    }
+
+    pub(crate) fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+struct MockDaemon {
+    daemons: Arc<MockDaemons>,
+}
+
+impl MockDaemon {
+    async fn start(daemons: Arc<MockDaemons>) -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let info = DaemonProcessInfo {
+            pid: std::process::id() as i64,
+            endpoint: format!("tcp:{}", listener.local_addr()?.port()),
+            version: "mock".to_owned(),
+            auth_token: "mock".to_owned(),
+        };
+
+        daemons.started.fetch_add(1, Ordering::SeqCst);
+        let daemon_dir = daemons.daemon_dir.clone();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(DaemonApiServer::new(MockDaemon { daemons }))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        serde_json::to_writer(File::create(daemon_dir.buckd_info())?, &info)?;
+        Ok(())
+    }
+
+    /// Respond to a streaming request with a progress event, an event which isn't about progress,
+    /// some stdout and the result.
+    async fn respond(
+        &self,
+        context: Option<&ClientContext>,
+        result: command_result::Result,
+        stdout: &str,
+    ) -> Result<Response<ResponseStream>, Status> {
+        self.daemons
+            .commands
+            .lock()
+            .unwrap()
+            .push(context.map(|c| c.command_name.clone()).unwrap_or_default());
+
+        if self
+            .daemons
+            .restart_on_next_request
+            .swap(false, Ordering::SeqCst)
+        {
+            MockDaemon::start(self.daemons.clone())
+                .await
+                .map_err(|e| Status::internal(format!("{:#}", e)))?;
+            return Err(Status::unavailable("daemon is shutting down"));
+        }
+
+        let messages = vec![
+            event(instant_event::Data::ConsoleMessage(
+                buck2_data::ConsoleMessage {
+                    message: "running".to_owned(),
+                },
+            )),
+            event(instant_event::Data::BuildGraphInfo(Default::default())),
+            command_progress::Progress::PartialResult(Box::new(PartialResult {
+                partial_result: Some(partial_result::PartialResult::StdoutBytes(StdoutBytes {
+                    data: stdout.as_bytes().to_vec(),
+                })),
+            })),
+            command_progress::Progress::Result(Box::new(CommandResult {
+                result: Some(result),
+            })),
+        ];
+        let progress = MultiCommandProgress {
+            messages: messages
+                .into_iter()
+                .map(|progress| CommandProgress {
+                    progress: Some(progress),
+                    sequence: 0,
+                })
+                .collect(),
+        };
+        Ok(Response::new(Box::pin(futures::stream::iter([Ok::<
+            _,
+            Status,
+        >(
+            progress
+        )]))))
+    }
+}
+
+fn event(data: instant_event::Data) -> command_progress::Progress {
+    command_progress::Progress::Event(Box::new(buck2_data::BuckEvent {
+        timestamp: Some(SystemTime::now().into()),
+        data: Some(buck_event::Data::Instant(buck2_data::InstantEvent {
+            data: Some(data),
+        })),
+        ..Default::default()
+    }))
+}
+
+/// Implement methods of the `#[async_trait]` trait `DaemonApi` as returning `unimplemented`.
+/// This is what `#[async_trait]` would expand `async fn` to, which can't be used here because
+/// the attribute does not see inside macros.
+macro_rules! unimplemented_methods {
+    ($($method: ident($req: ty) -> $res: ty;)*) => {
+        $(
+            fn $method<'life0, 'async_trait>(
+                &'life0 self,
+                _req: Request<$req>,
+            ) -> BoxFuture<'async_trait, Result<Response<$res>, Status>>
+            where
+                'life0: 'async_trait,
+                Self: 'async_trait,
+            {
+                Box::pin(futures::future::ready(Err(Status::unimplemented(stringify!(
+                    $method
+                )))))
+            }
+        )*
+    };
+}
+
+macro_rules! unimplemented_streaming_methods {
+    ($($method: ident($req: ty) -> $stream: ident;)*) => {
+        $(
+            type $stream = ResponseStream;
+        )*
+        unimplemented_methods!($($method($req) -> ResponseStream;)*);
+    };
+}
+
+#[async_trait]
+impl DaemonApi for MockDaemon {
+    async fn status(
+        &self,
+        _req: Request<StatusRequest>,
+    ) -> Result<Response<CommandResult>, Status> {
+        Ok(Response::new(CommandResult {
+            result: Some(command_result::Result::StatusResponse(StatusResponse {
+                daemon_constraints: Some(DaemonConstraints {
+                    version: "mock".to_owned(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })),
+        }))
+    }
+
+    type UqueryStream = ResponseStream;
+    async fn uquery(
+        &self,
+        req: Request<UqueryRequest>,
+    ) -> Result<Response<ResponseStream>, Status> {
+        let req = req.into_inner();
+        self.respond(
+            req.context.as_ref(),
+            UqueryResponse {}.into(),
+            &format!("{}\n", req.query),
+        )
+        .await
+    }
+
+    type BuildStream = ResponseStream;
+    async fn build(&self, req: Request<BuildRequest>) -> Result<Response<ResponseStream>, Status> {
+        let req = req.into_inner();
+        self.respond(req.context.as_ref(), BuildResponse::default().into(), "")
+            .await
+    }
+
+    unimplemented_methods! {
+        kill(KillRequest) -> CommandResult;
+        ping(PingRequest) -> CommandResult;
+        flush_dep_files(FlushDepFilesRequest) -> CommandResult;
+        unstable_crash(UnstableCrashRequest) -> CommandResult;
+        segfault(SegfaultRequest) -> SegfaultResponse;
+        unstable_heap_dump(UnstableHeapDumpRequest) -> UnstableHeapDumpResponse;
+        unstable_allocator_stats(UnstableAllocatorStatsRequest) -> UnstableAllocatorStatsResponse;
+        unstable_dice_dump(UnstableDiceDumpRequest) -> UnstableDiceDumpResponse;
+        set_log_filter(SetLogFilterRequest) -> SetLogFilterResponse;
+    }
+
+    unimplemented_streaming_methods! {
+        bxl(BxlRequest) -> BxlStream;
+        test(TestRequest) -> TestStream;
+        targets(TargetsRequest) -> TargetsStream;
+        targets_show_outputs(TargetsRequest) -> TargetsShowOutputsStream;
+        ctargets(ConfiguredTargetsRequest) -> CtargetsStream;
+        aquery(AqueryRequest) -> AqueryStream;
+        cquery(CqueryRequest) -> CqueryStream;
+        audit(GenericRequest) -> AuditStream;
+        starlark(GenericRequest) -> StarlarkStream;
+        unstable_docs(UnstableDocsRequest) -> UnstableDocsStream;
+        install(InstallRequest) -> InstallStream;
+        clean_stale(CleanStaleRequest) -> CleanStaleStream;
+        file_status(FileStatusRequest) -> FileStatusStream;
+        profile2(ProfileRequest) -> Profile2Stream;
+        new_generic_impl(NewGenericRequestMessage) -> NewGenericImplStream;
+        allocative(AllocativeRequest) -> AllocativeStream;
+        trace_io(TraceIoRequest) -> TraceIoStream;
+        lsp(Streaming<StreamingRequest>) -> LspStream;
+        subscription(Streaming<StreamingRequest>) -> SubscriptionStream;
+        dap(Streaming<StreamingRequest>) -> DapStream;
+    }
+}
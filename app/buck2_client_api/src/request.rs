@@ -0,0 +1,155 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Requests which can be sent to the daemon with [`Buck2Client::run`](crate::Buck2Client::run).
+
+use async_trait::async_trait;
+use buck2_cli_proto::BuildRequest;
+use buck2_cli_proto::BuildResponse;
+use buck2_cli_proto::ClientContext;
+use buck2_cli_proto::CqueryRequest;
+use buck2_cli_proto::CqueryResponse;
+use buck2_cli_proto::TargetsRequest;
+use buck2_cli_proto::TargetsResponse;
+use buck2_cli_proto::UqueryRequest;
+use buck2_cli_proto::UqueryResponse;
+use buck2_client_ctx::command_outcome::CommandOutcome;
+use buck2_client_ctx::daemon::client::NoPartialResultHandler;
+use buck2_client_ctx::events_ctx::PartialResultCtx;
+use buck2_client_ctx::events_ctx::PartialResultHandler;
+
+/// The result of a successful request.
+#[derive(Debug)]
+pub struct CommandOutput<R> {
+    pub response: R,
+    /// What the command would have printed to stdout, e.g. the query results.
+    pub stdout: Vec<u8>,
+}
+
+/// A request the daemon can run. Implemented for the daemon's protobuf request messages.
+#[async_trait]
+pub trait DaemonRequest: Clone + Send + Sync + 'static {
+    type Response: Send + 'static;
+
+    /// Whether the request can be sent again if the daemon went away while running it. Builds
+    /// are not, because they may have written outputs and run actions with side effects.
+    const IDEMPOTENT: bool;
+
+    /// Set the client context, which is filled in by the client before sending the request.
+    fn set_context(&mut self, context: ClientContext);
+
+    /// The name of the command reported to the daemon, e.g. in its logs.
+    #[doc(hidden)]
+    const COMMAND_NAME: &'static str;
+
+    /// Not part of the API: `Connection` can't be named outside of this crate, so this trait
+    /// can't be implemented elsewhere.
+    #[doc(hidden)]
+    async fn send(
+        &self,
+        connection: &mut private::Connection<'_, '_>,
+        stdout: &mut Vec<u8>,
+    ) -> anyhow::Result<CommandOutcome<Self::Response>>;
+}
+
+pub(crate) mod private {
+    use buck2_client_ctx::daemon::client::FlushingBuckdClient;
+
+    pub struct Connection<'a, 'b>(pub(crate) FlushingBuckdClient<'a, 'b>);
+}
+
+/// Collects the output written to stdout by the daemon, rather than printing it.
+struct CaptureStdout<'a>(&'a mut Vec<u8>);
+
+#[async_trait]
+impl<'a> PartialResultHandler for CaptureStdout<'a> {
+    type PartialResult = buck2_cli_proto::StdoutBytes;
+
+    async fn handle_partial_result(
+        &mut self,
+        _ctx: PartialResultCtx<'_, '_>,
+        partial_res: Self::PartialResult,
+    ) -> anyhow::Result<()> {
+        self.0.extend_from_slice(&partial_res.data);
+        Ok(())
+    }
+}
+
+macro_rules! daemon_request {
+    ($req: ident, $res: ident, $method: ident, idempotent = $idempotent: literal, stdout) => {
+        #[async_trait]
+        impl DaemonRequest for $req {
+            type Response = $res;
+            const IDEMPOTENT: bool = $idempotent;
+            const COMMAND_NAME: &'static str = stringify!($method);
+
+            fn set_context(&mut self, context: ClientContext) {
+                self.context = Some(context);
+            }
+
+            async fn send(
+                &self,
+                connection: &mut private::Connection<'_, '_>,
+                stdout: &mut Vec<u8>,
+            ) -> anyhow::Result<CommandOutcome<$res>> {
+                connection
+                    .0
+                    .$method(self.clone(), None, &mut CaptureStdout(stdout))
+                    .await
+            }
+        }
+    };
+
+    ($req: ident, $res: ident, $method: ident, idempotent = $idempotent: literal) => {
+        #[async_trait]
+        impl DaemonRequest for $req {
+            type Response = $res;
+            const IDEMPOTENT: bool = $idempotent;
+            const COMMAND_NAME: &'static str = stringify!($method);
+
+            fn set_context(&mut self, context: ClientContext) {
+                self.context = Some(context);
+            }
+
+            async fn send(
+                &self,
+                connection: &mut private::Connection<'_, '_>,
+                _stdout: &mut Vec<u8>,
+            ) -> anyhow::Result<CommandOutcome<$res>> {
+                connection
+                    .0
+                    .$method(self.clone(), None, &mut NoPartialResultHandler)
+                    .await
+            }
+        }
+    };
+}
+
+daemon_request!(
+    TargetsRequest,
+    TargetsResponse,
+    targets,
+    idempotent = true,
+    stdout
+);
+daemon_request!(
+    UqueryRequest,
+    UqueryResponse,
+    uquery,
+    idempotent = true,
+    stdout
+);
+daemon_request!(
+    CqueryRequest,
+    CqueryResponse,
+    cquery,
+    idempotent = true,
+    stdout
+);
+daemon_request!(BuildRequest, BuildResponse, build, idempotent = false);
@@ -12,9 +12,12 @@ use std::future::Future;
 use anyhow::Context as _;
 use buck2_cli_proto::client_context::HostArchOverride as GrpcHostArchOverride;
 use buck2_cli_proto::client_context::HostPlatformOverride as GrpcHostPlatformOverride;
+use buck2_cli_proto::client_context::PreemptibleWhen as GrpcPreemptibleWhen;
+use buck2_cli_proto::client_context::ValidateVisibility as GrpcValidateVisibility;
 use buck2_cli_proto::ClientContext;
 use buck2_common::argv::Argv;
 use buck2_common::invocation_paths::InvocationPaths;
+use buck2_common::invocation_scratch_dir::InvocationScratchDir;
 use buck2_core::error::buck2_hard_error_env;
 use buck2_core::fs::working_dir::WorkingDir;
 use buck2_event_observer::verbosity::Verbosity;
@@ -27,6 +30,8 @@ use crate::client_metadata::ClientMetadata;
 use crate::common::CommonEventLogOptions;
 use crate::common::HostArchOverride;
 use crate::common::HostPlatformOverride;
+use crate::common::PreemptibleWhen;
+use crate::common::ValidateVisibility;
 use crate::daemon::client::connect::BuckdConnectOptions;
 use crate::daemon::client::BuckdClientConnector;
 use crate::exit_result::ExitResult;
@@ -55,6 +60,8 @@ pub struct ClientCommandContext<'a> {
     pub runtime: &'a Runtime,
     pub oncall: Option<String>,
     pub client_metadata: Vec<ClientMetadata>,
+    /// Fail instead of restarting a daemon which does not match this client.
+    pub no_auto_restart: bool,
 }
 
 impl<'a> ClientCommandContext<'a> {
@@ -143,9 +150,22 @@ impl<'a> ClientCommandContext<'a> {
             disable_starlark_types: starlark_opts.disable_starlark_types,
             unstable_typecheck: starlark_opts.unstable_typecheck,
             skip_targets_with_duplicate_names: starlark_opts.skip_targets_with_duplicate_names,
+            validate_visibility: match starlark_opts.validate_visibility {
+                None => GrpcValidateVisibility::Unset,
+                Some(ValidateVisibility::Off) => GrpcValidateVisibility::Off,
+                Some(ValidateVisibility::Warn) => GrpcValidateVisibility::Warn,
+                Some(ValidateVisibility::Strict) => GrpcValidateVisibility::Strict,
+            }
+            .into(),
             reuse_current_config: config_opts.reuse_current_config,
             sanitized_argv: cmd.sanitize_argv(self.argv.clone()).argv,
             exit_when_different_state: config_opts.exit_when_different_state,
+            preemptible: match config_opts.preemptible {
+                None => GrpcPreemptibleWhen::Never,
+                Some(PreemptibleWhen::OnDifferentState) => GrpcPreemptibleWhen::OnDifferentState,
+                Some(PreemptibleWhen::Always) => GrpcPreemptibleWhen::Always,
+            }
+            .into(),
             argfiles: self
                 .immediate_config
                 .trace()
@@ -184,6 +204,7 @@ impl<'a> ClientCommandContext<'a> {
             unstable_typecheck: false,
             target_call_stacks: false,
             skip_targets_with_duplicate_names: false,
+            validate_visibility: Default::default(),
             trace_id: format!("{}", self.trace_id),
             reuse_current_config: false,
             daemon_uuid,
@@ -192,6 +213,7 @@ impl<'a> ClientCommandContext<'a> {
             buck2_hard_error: buck2_hard_error_env()?.unwrap_or_default().to_owned(),
             command_name: command_name.to_owned(),
             exit_when_different_state: false,
+            preemptible: Default::default(),
             client_metadata: self
                 .client_metadata
                 .iter()
@@ -204,6 +226,14 @@ impl<'a> ClientCommandContext<'a> {
         &self.async_cleanup
     }
 
+    /// Scratch dir for this invocation. It is removed when dropped, or at the latest when the
+    /// client exits, including when the command is interrupted.
+    pub fn invocation_scratch_dir(&self) -> anyhow::Result<InvocationScratchDir> {
+        let dir = self.paths()?.invocation_scratch_dir(&self.trace_id);
+        dir.register_cleanup(&self.async_cleanup);
+        Ok(dir)
+    }
+
     pub fn allow_vpnless(&self) -> anyhow::Result<bool> {
         Ok(self.immediate_config.daemon_startup_config()?.allow_vpnless)
     }
@@ -73,11 +73,48 @@ pub enum HostArchOverride {
     X86_64,
 }
 
+#[derive(
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Dupe,
+    Copy,
+    clap::ValueEnum
+)]
+pub enum PreemptibleWhen {
+    /// Preempt when a newer command requires a different state.
+    OnDifferentState,
+    /// Preempt when any newer command starts.
+    Always,
+}
+
+#[derive(
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Dupe,
+    Copy,
+    clap::ValueEnum
+)]
+pub enum ValidateVisibility {
+    /// Don't validate visibility.
+    Off,
+    /// Report visibility problems as warnings.
+    Warn,
+    /// Fail on the first visibility problem.
+    Strict,
+}
+
 /// Defines options related to commands that involves a streaming daemon command.
 #[derive(Debug, clap::Parser, serde::Serialize, serde::Deserialize, Default)]
 #[clap(next_help_heading = "Event Log Options")]
 pub struct CommonEventLogOptions {
-    /// Write events to this log file
+    /// Write events to this log file, in addition to the event log in buck-out.
+    /// The format is inferred from the extension (e.g. `.json-lines`, `.json-lines.gz`,
+    /// `.json-lines.zst` or `.pb.zst`) and defaults to gzipped JSON lines. Missing parent
+    /// directories are created
     #[clap(value_name = "PATH", long = EVENT_LOG)]
     pub event_log: Option<PathArg>,
 
@@ -93,6 +130,15 @@ pub struct CommonEventLogOptions {
     /// regarding the stability of the format.
     #[clap(long, value_name = "PATH")]
     pub(crate) unstable_write_invocation_record: Option<PathArg>,
+
+    /// Write a machine-readable stream of high-level build status (targets started and finished,
+    /// action failures and the final outcome) to this file, as one JSON object per line.
+    #[clap(long, value_name = "PATH", conflicts_with = "status_fd")]
+    pub(crate) status_file: Option<PathArg>,
+
+    /// Like `--status-file`, but write to this already open file descriptor. Unix only.
+    #[clap(long, value_name = "FD")]
+    pub(crate) status_fd: Option<i32>,
 }
 
 impl CommonEventLogOptions {
@@ -102,6 +148,8 @@ impl CommonEventLogOptions {
             no_event_log: false,
             write_build_id: None,
             unstable_write_invocation_record: None,
+            status_file: None,
+            status_fd: None,
         };
         &DEFAULT
     }
@@ -155,6 +203,18 @@ pub struct CommonBuildConfigurationOptions {
     /// Used for exiting a concurrent command when a different state is detected.
     #[clap(long)]
     pub exit_when_different_state: bool,
+
+    /// Allow this command to be cancelled by a newer command on the same daemon, either when
+    /// the newer command requires a different state (the default), or always.
+    #[clap(
+        long,
+        value_name = "WHEN",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "on-different-state"
+    )]
+    pub preemptible: Option<PreemptibleWhen>,
 }
 
 impl CommonBuildConfigurationOptions {
@@ -256,6 +316,7 @@ impl CommonBuildConfigurationOptions {
             fake_xcode_version: None,
             reuse_current_config: false,
             exit_when_different_state: false,
+            preemptible: None,
         };
         &DEFAULT
     }
@@ -289,6 +350,20 @@ pub struct CommonStarlarkOptions {
     /// This is a hack for TD. Do not use this option.
     #[clap(long, hide = true)]
     pub(crate) skip_targets_with_duplicate_names: bool,
+
+    /// Check `visibility` and `within_view` of the evaluated targets for patterns referring to
+    /// unknown cells and for dependencies outside `within_view`, including default dependencies.
+    /// Problems are reported as warnings (the default), or fail the command with `strict`.
+    /// Defaults to `buck2.validate_visibility` from the root buckconfig.
+    #[clap(
+        long,
+        value_name = "MODE",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "warn"
+    )]
+    pub(crate) validate_visibility: Option<ValidateVisibility>,
 }
 
 impl CommonStarlarkOptions {
@@ -298,6 +373,7 @@ impl CommonStarlarkOptions {
             unstable_typecheck: false,
             target_call_stacks: false,
             skip_targets_with_duplicate_names: false,
+            validate_visibility: None,
         };
         &DEFAULT
     }
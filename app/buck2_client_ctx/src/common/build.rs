@@ -23,6 +23,9 @@ pub struct BuildReportOption {
 
     /// Include package relative paths in the output.
     include_package_project_relative_paths: bool,
+
+    /// Include cache hits and misses by target and action category.
+    include_cache_attribution: bool,
 }
 
 impl FromStr for BuildReportOption {
@@ -30,20 +33,24 @@ impl FromStr for BuildReportOption {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut fill_out_failures = false;
         let mut include_package_project_relative_paths = false;
+        let mut include_cache_attribution = false;
 
         if s.to_lowercase() == "fill-out-failures" {
             fill_out_failures = true;
         } else if s.to_lowercase() == "package-project-relative-paths" {
             include_package_project_relative_paths = true;
+        } else if s.to_lowercase() == "cache-attribution" {
+            include_cache_attribution = true;
         } else {
             warn!(
-                "Incorrect syntax for build report option. Got: `{}` but expected one of `fill-out-failures, package-project-relative-paths`",
+                "Incorrect syntax for build report option. Got: `{}` but expected one of `fill-out-failures, package-project-relative-paths, cache-attribution`",
                 s.to_owned()
             )
         }
         Ok(BuildReportOption {
             fill_out_failures,
             include_package_project_relative_paths,
+            include_cache_attribution,
         })
     }
 }
@@ -67,6 +74,10 @@ pub struct CommonBuildOptions {
     ///
     /// `package-project-relative-paths`:
     /// emit the project-relative path of packages for the targets that were built.
+    ///
+    /// `cache-attribution`:
+    /// emit the number of actions which ran locally, ran remotely, were served from cache, or
+    /// were skipped, by target and action category.
     #[clap(
         long = "build-report-options",
         requires = "build_report",
@@ -189,6 +200,10 @@ impl CommonBuildOptions {
             .build_report_options
             .iter()
             .any(|option| option.include_package_project_relative_paths);
+        let unstable_include_cache_attribution = self
+            .build_report_options
+            .iter()
+            .any(|option| option.include_cache_attribution);
         let concurrency = self
             .num_threads
             .map(|num| buck2_cli_proto::Concurrency { concurrency: num });
@@ -221,6 +236,7 @@ impl CommonBuildOptions {
             materialize_failed_inputs: self.materialize_failed_inputs,
             unstable_include_failures_build_report,
             unstable_include_package_project_relative_paths,
+            unstable_include_cache_attribution,
         }
     }
 }
@@ -7,7 +7,9 @@
  * of this source tree.
  */
 
+use buck2_core::buck2_env;
 use clap::builder::FalseyValueParser;
+use clap::ValueEnum;
 use dupe::Dupe;
 use termwiz::istty::IsTty;
 
@@ -31,6 +33,8 @@ pub enum ConsoleType {
     Super,
     Auto,
     None,
+    /// Like `none`, and also skips subscribers which are not needed to record the command.
+    Quiet,
 }
 
 #[derive(
@@ -56,16 +60,15 @@ pub enum UiOptions {
 #[derive(Debug, clap::Parser, serde::Serialize, serde::Deserialize)]
 #[clap(next_help_heading = "Console Options")]
 pub struct CommonConsoleOptions {
+    /// Which console to use for this command. Defaults to `$BUCK2_CONSOLE`, or `auto`.
     #[clap(
         long = "console",
-        help = "Which console to use for this command",
-        default_value = "auto",
         ignore_case = true,
         env = "BUCK_CONSOLE",
         value_name = "super|simple|...",
         value_enum
     )]
-    pub console_type: ConsoleType,
+    pub console_type: Option<ConsoleType>,
 
     /// Configure additional superconsole ui components.
     ///
@@ -96,7 +99,7 @@ pub struct CommonConsoleOptions {
 impl Default for CommonConsoleOptions {
     fn default() -> Self {
         Self {
-            console_type: ConsoleType::Auto,
+            console_type: None,
             ui: Vec::new(),
             no_interactive_console: false,
         }
@@ -106,7 +109,7 @@ impl Default for CommonConsoleOptions {
 impl CommonConsoleOptions {
     pub fn default_ref() -> &'static Self {
         static OPTS: CommonConsoleOptions = CommonConsoleOptions {
-            console_type: ConsoleType::Auto,
+            console_type: None,
             ui: vec![],
             no_interactive_console: false,
         };
@@ -115,7 +118,7 @@ impl CommonConsoleOptions {
 
     pub fn simple_ref() -> &'static Self {
         static OPTS: CommonConsoleOptions = CommonConsoleOptions {
-            console_type: ConsoleType::Simple,
+            console_type: Some(ConsoleType::Simple),
             ui: vec![],
             no_interactive_console: false,
         };
@@ -124,20 +127,36 @@ impl CommonConsoleOptions {
 
     pub fn none_ref() -> &'static Self {
         static OPTS: CommonConsoleOptions = CommonConsoleOptions {
-            console_type: ConsoleType::None,
+            console_type: Some(ConsoleType::None),
             ui: vec![],
             no_interactive_console: false,
         };
         &OPTS
     }
 
+    /// Console type from `--console`, then `$BUCK2_CONSOLE`, then `auto`.
+    pub fn console_type(&self) -> anyhow::Result<ConsoleType> {
+        let env = buck2_env!(
+            "BUCK2_CONSOLE",
+            type=ConsoleType,
+            converter=|s| ConsoleType::from_str(s, true).map_err(anyhow::Error::msg)
+        )?;
+        Ok(self.console_type_with_env(env.copied()))
+    }
+
+    fn console_type_with_env(&self, env: Option<ConsoleType>) -> ConsoleType {
+        self.console_type.or(env).unwrap_or(ConsoleType::Auto)
+    }
+
     pub fn final_console(&self) -> FinalConsole {
-        let is_tty = match self.console_type {
+        // An invalid `$BUCK2_CONSOLE` is reported when the console is created.
+        let console_type = self.console_type().unwrap_or(ConsoleType::Auto);
+        let is_tty = match console_type {
             ConsoleType::Auto | ConsoleType::Simple => std::io::stderr().is_tty(),
             ConsoleType::Super => true,
             ConsoleType::SimpleNoTty => false,
             ConsoleType::SimpleTty => true,
-            ConsoleType::None => false,
+            ConsoleType::None | ConsoleType::Quiet => false,
         };
         if is_tty {
             FinalConsole::new_with_tty()
@@ -159,3 +178,36 @@ impl CommonConsoleOptions {
         config
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::common::ui::CommonConsoleOptions;
+    use crate::common::ui::ConsoleType;
+
+    fn console_type(args: &[&str], env: Option<ConsoleType>) -> ConsoleType {
+        let opts = CommonConsoleOptions::try_parse_from(
+            std::iter::once("test").chain(args.iter().copied()),
+        )
+        .unwrap();
+        opts.console_type_with_env(env)
+    }
+
+    #[test]
+    fn test_console_type_precedence() {
+        assert!(matches!(console_type(&[], None), ConsoleType::Auto));
+        assert!(matches!(
+            console_type(&[], Some(ConsoleType::Quiet)),
+            ConsoleType::Quiet
+        ));
+        assert!(matches!(
+            console_type(&["--console", "simple"], Some(ConsoleType::Quiet)),
+            ConsoleType::Simple
+        ));
+        assert!(matches!(
+            console_type(&["--console=QUIET"], None),
+            ConsoleType::Quiet
+        ));
+    }
+}
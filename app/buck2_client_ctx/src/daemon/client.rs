@@ -11,12 +11,14 @@ use std::fs::create_dir_all;
 use std::fs::File;
 use std::mem;
 use std::time::Duration;
+use std::time::SystemTime;
 
 use anyhow::Context;
 use async_trait::async_trait;
 use buck2_cli_proto::daemon_api_client::*;
 use buck2_cli_proto::new_generic::NewGenericRequest;
 use buck2_cli_proto::new_generic::NewGenericResponse;
+use buck2_cli_proto::stream_integrity::StreamVerifier;
 use buck2_cli_proto::*;
 use buck2_common::daemon_dir::DaemonDir;
 use buck2_core::fs::fs_util;
@@ -38,6 +40,7 @@ use tonic::Status;
 use crate::command_outcome::CommandOutcome;
 use crate::console_interaction_stream::ConsoleInteractionStream;
 use crate::daemon::client::connect::BuckAddAuthTokenInterceptor;
+use crate::daemon::client::diagnostics::DisconnectDiagnostics;
 use crate::events_ctx::EventsCtx;
 use crate::events_ctx::FileTailers;
 use crate::events_ctx::PartialResultCtx;
@@ -45,6 +48,7 @@ use crate::events_ctx::PartialResultHandler;
 use crate::subscribers::observer::ErrorObserver;
 
 pub mod connect;
+mod diagnostics;
 pub mod kill;
 
 use crate::startup_deadline::StartupDeadline;
@@ -147,6 +151,8 @@ pub struct BuckdClient<'a> {
     client: DaemonApiClient<InterceptedService<Channel, BuckAddAuthTokenInterceptor>>,
     constraints: buck2_cli_proto::DaemonConstraints,
     daemon_dir: DaemonDir,
+    /// Set when the client knows where to write diagnostics if the daemon disconnects.
+    disconnect_diagnostics: Option<DisconnectDiagnostics>,
     // TODO(brasselsprouts): events_ctx should own tailers
     tailers: Option<FileTailers>,
     pub(crate) events_ctx: EventsCtx<'a>,
@@ -195,26 +201,90 @@ fn grpc_to_stream(
         .map_ok(|e| stream::iter(e.messages.into_iter().map(anyhow::Ok)))
         .try_flatten();
 
-    stream::unfold(stream, |mut stream| async {
-        let msg = match stream.try_next().await {
-            Ok(Some(msg)) => msg,
-            Ok(None) => return None,
-            Err(e) => return Some((Err(e), stream)),
-        };
-        let value = match msg.progress {
-            Some(command_progress::Progress::Event(e)) => Some(Ok(StreamValue::Event(e))),
-            Some(command_progress::Progress::Result(res)) => Some(Ok(StreamValue::Result(res))),
-            Some(command_progress::Progress::PartialResult(res)) => {
-                Some(Ok(StreamValue::PartialResult(res)))
-            }
-            None => Some(Err(GrpcToStreamError::EmptyCommandProgress.into())),
-        };
+    let integrity = StreamIntegrity {
+        verifier: StreamVerifier::default(),
+        trace_id: None,
+        violation: None,
+    };
 
-        value.map(|v| (v, stream))
-    })
+    stream::unfold(
+        (stream, integrity),
+        |(mut stream, mut integrity)| async move {
+            loop {
+                if let Some(violation) = integrity.violation.take() {
+                    return Some((Ok(StreamValue::Event(violation)), (stream, integrity)));
+                }
+
+                let msg = match stream.try_next().await {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => return None,
+                    Err(e) => return Some((Err(e), (stream, integrity))),
+                };
+                integrity.verify(&msg);
+
+                let value = match msg.progress {
+                    Some(command_progress::Progress::Event(e)) => Ok(StreamValue::Event(e)),
+                    Some(command_progress::Progress::Result(res)) => Ok(StreamValue::Result(res)),
+                    Some(command_progress::Progress::PartialResult(res)) => {
+                        Ok(StreamValue::PartialResult(res))
+                    }
+                    Some(command_progress::Progress::Checkpoint(_)) => continue,
+                    None => Err(GrpcToStreamError::EmptyCommandProgress.into()),
+                };
+
+                return Some((value, (stream, integrity)));
+            }
+        },
+    )
     .right_stream()
 }
 
+/// Checks the integrity of the event stream, and reports the violations as events so that
+/// they end up in the event log and the invocation record.
+struct StreamIntegrity {
+    verifier: StreamVerifier,
+    /// Trace id of the command, taken from the events received.
+    trace_id: Option<String>,
+    /// Violation to report before the next message.
+    violation: Option<Box<buck2_data::BuckEvent>>,
+}
+
+impl StreamIntegrity {
+    fn verify(&mut self, msg: &CommandProgress) {
+        if let Some(command_progress::Progress::Event(e)) = &msg.progress {
+            if self.trace_id.is_none() {
+                self.trace_id = Some(e.trace_id.clone());
+            }
+        }
+
+        let Some(violation) = self.verifier.verify(msg) else {
+            return;
+        };
+        tracing::warn!(
+            kind = ?violation.kind(),
+            first_sequence = violation.first_sequence,
+            last_sequence = violation.last_sequence,
+            "Events from the daemon were lost or corrupted"
+        );
+        // Without a trace id, this can't be turned into an event: this only happens if all the
+        // events were lost, which is visible anyway.
+        if let Some(trace_id) = &self.trace_id {
+            self.violation = Some(Box::new(buck2_data::BuckEvent {
+                timestamp: Some(SystemTime::now().into()),
+                trace_id: trace_id.clone(),
+                span_id: 0,
+                parent_id: 0,
+                data: Some(
+                    buck2_data::InstantEvent {
+                        data: Some(violation.into()),
+                    }
+                    .into(),
+                ),
+            }));
+        }
+    }
+}
+
 impl<'a> BuckdClient<'a> {
     fn open_tailers(&mut self) -> anyhow::Result<()> {
         let tailers = FileTailers::new(&self.daemon_dir)?;
@@ -253,14 +323,19 @@ impl<'a> BuckdClient<'a> {
             .context("Error dispatching request");
         let stream = grpc_to_stream(response);
         pin_mut!(stream);
-        events_ctx
+        let outcome = events_ctx
             .unpack_stream(
                 partial_result_handler,
                 stream,
                 self.tailers.take(),
                 console_interaction,
             )
-            .await
+            .await;
+
+        match (outcome, &self.disconnect_diagnostics) {
+            (Err(e), Some(diagnostics)) => Err(diagnostics.report_if_disconnected(e).await),
+            (outcome, _) => outcome,
+        }
     }
 
     pub async fn status(&mut self, snapshot: bool) -> anyhow::Result<StatusResponse> {
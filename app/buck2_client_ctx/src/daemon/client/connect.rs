@@ -31,10 +31,12 @@ use buck2_data::DaemonWasStartedReason;
 use buck2_util::process::async_background_command;
 use buck2_util::truncate::truncate;
 use buck2_wrapper_common::kill::process_exists;
+use buck2_wrapper_common::kill::ProcessExit;
 use buck2_wrapper_common::pid::Pid;
 use dupe::Dupe;
 use futures::future::try_join3;
 use futures::FutureExt;
+use itertools::Itertools;
 use tokio::io::AsyncReadExt;
 use tokio::time::timeout;
 use tonic::codegen::InterceptedService;
@@ -45,6 +47,7 @@ use tonic::Request;
 use tonic::Status;
 
 use crate::command_outcome::CommandOutcome;
+use crate::daemon::client::diagnostics::DisconnectDiagnostics;
 use crate::daemon::client::kill;
 use crate::daemon::client::kill::hard_kill_until;
 use crate::daemon::client::BuckdClient;
@@ -70,40 +73,88 @@ pub struct DaemonConstraintsRequest {
     pub reject_daemon: Option<String>,
     pub reject_materializer_state: Option<String>,
     pub daemon_startup_config: DaemonStartupConfig,
+    /// Fail instead of restarting the daemon if it does not satisfy the constraints.
+    pub no_auto_restart: bool,
 }
 
 #[derive(Debug, derive_more::Display)]
 pub(crate) enum ConstraintUnsatisfiedReason {
-    #[display(fmt = "Version mismatch")]
-    Version,
-    #[display(fmt = "User version mismatch")]
-    UserVersion,
-    #[display(fmt = "Startup config mismatch")]
-    StartupConfig,
+    #[display(fmt = "Version mismatch (client: `{}`, daemon: `{}`)", client, daemon)]
+    Version { client: String, daemon: String },
+    #[display(
+        fmt = "User version mismatch (client: {}, daemon: {})",
+        "display_user_version(client)",
+        "display_user_version(daemon)"
+    )]
+    UserVersion {
+        client: Option<String>,
+        daemon: Option<String>,
+    },
+    #[display(fmt = "Startup config mismatch ({})", _0)]
+    StartupConfig(StartupConfigMismatch),
     #[display(fmt = "Reject daemon id")]
     RejectDaemonId,
-    #[display(fmt = "Trace IO mismatch")]
-    TraceIo,
+    #[display(
+        fmt = "Trace IO mismatch (daemon has trace IO {})",
+        "if *daemon_enabled { \"enabled\" } else { \"disabled\" }"
+    )]
+    TraceIo { daemon_enabled: bool },
     #[display(fmt = "Materializer state identity mismatch")]
     MaterializerStateIdentity,
 }
 
+#[derive(Debug, derive_more::Display)]
+pub(crate) enum StartupConfigMismatch {
+    #[display(fmt = "daemon did not report a valid startup config")]
+    Invalid,
+    #[display(
+        fmt = "different {}",
+        "_0.iter().map(|f| format!(\"`{}`\", f)).join(\", \")"
+    )]
+    Fields(Vec<String>),
+}
+
+fn display_user_version(user_version: &Option<String>) -> String {
+    match user_version {
+        Some(v) => format!("`{}`", v),
+        None => "none".to_owned(),
+    }
+}
+
+impl StartupConfigMismatch {
+    /// Names of the top-level fields which differ between the two configs.
+    fn compare(client: &DaemonStartupConfig, daemon: &DaemonStartupConfig) -> Self {
+        let (Ok(serde_json::Value::Object(client)), Ok(serde_json::Value::Object(daemon))) =
+            (serde_json::to_value(client), serde_json::to_value(daemon))
+        else {
+            return StartupConfigMismatch::Fields(Vec::new());
+        };
+        StartupConfigMismatch::Fields(
+            client
+                .iter()
+                .filter(|(field, value)| daemon.get(field.as_str()) != Some(*value))
+                .map(|(field, _)| field.clone())
+                .collect(),
+        )
+    }
+}
+
 impl ConstraintUnsatisfiedReason {
     pub(crate) fn to_daemon_was_started_reason(&self) -> buck2_data::DaemonWasStartedReason {
         match self {
-            ConstraintUnsatisfiedReason::Version => {
+            ConstraintUnsatisfiedReason::Version { .. } => {
                 buck2_data::DaemonWasStartedReason::ConstraintMismatchVersion
             }
-            ConstraintUnsatisfiedReason::UserVersion => {
+            ConstraintUnsatisfiedReason::UserVersion { .. } => {
                 buck2_data::DaemonWasStartedReason::ConstraintMismatchUserVersion
             }
-            ConstraintUnsatisfiedReason::StartupConfig => {
+            ConstraintUnsatisfiedReason::StartupConfig(..) => {
                 buck2_data::DaemonWasStartedReason::ConstraintMismatchStartupConfig
             }
             ConstraintUnsatisfiedReason::RejectDaemonId => {
                 buck2_data::DaemonWasStartedReason::ConstraintRejectDaemonId
             }
-            ConstraintUnsatisfiedReason::TraceIo => {
+            ConstraintUnsatisfiedReason::TraceIo { .. } => {
                 buck2_data::DaemonWasStartedReason::ConstraintMismatchTraceIo
             }
             ConstraintUnsatisfiedReason::MaterializerStateIdentity => {
@@ -125,6 +176,7 @@ impl DaemonConstraintsRequest {
             reject_daemon: None,
             reject_materializer_state: None,
             daemon_startup_config: immediate_config.daemon_startup_config()?.clone(),
+            no_auto_restart: false,
         })
     }
 
@@ -137,11 +189,17 @@ impl DaemonConstraintsRequest {
         daemon: &buck2_cli_proto::DaemonConstraints,
     ) -> Result<(), ConstraintUnsatisfiedReason> {
         if self.version != daemon.version {
-            return Err(ConstraintUnsatisfiedReason::Version);
+            return Err(ConstraintUnsatisfiedReason::Version {
+                client: self.version.clone(),
+                daemon: daemon.version.clone(),
+            });
         }
 
         if self.user_version != daemon.user_version {
-            return Err(ConstraintUnsatisfiedReason::UserVersion);
+            return Err(ConstraintUnsatisfiedReason::UserVersion {
+                client: self.user_version.clone(),
+                daemon: daemon.user_version.clone(),
+            });
         }
 
         let server_daemon_startup_config = daemon.daemon_startup_config.as_ref().and_then(|c| {
@@ -152,8 +210,18 @@ impl DaemonConstraintsRequest {
             server.ok()
         });
 
-        if Some(&self.daemon_startup_config) != server_daemon_startup_config.as_ref() {
-            return Err(ConstraintUnsatisfiedReason::StartupConfig);
+        match &server_daemon_startup_config {
+            None => {
+                return Err(ConstraintUnsatisfiedReason::StartupConfig(
+                    StartupConfigMismatch::Invalid,
+                ));
+            }
+            Some(server) if *server != self.daemon_startup_config => {
+                return Err(ConstraintUnsatisfiedReason::StartupConfig(
+                    StartupConfigMismatch::compare(&self.daemon_startup_config, server),
+                ));
+            }
+            Some(_) => {}
         }
 
         if let Some(r) = &self.reject_daemon {
@@ -171,11 +239,10 @@ impl DaemonConstraintsRequest {
         };
 
         match (self.desired_trace_io_state, extra.trace_io_enabled) {
-            (DesiredTraceIoState::Enabled, false) => {
-                return Err(ConstraintUnsatisfiedReason::TraceIo);
-            }
-            (DesiredTraceIoState::Disabled, true) => {
-                return Err(ConstraintUnsatisfiedReason::TraceIo);
+            (DesiredTraceIoState::Enabled, false) | (DesiredTraceIoState::Disabled, true) => {
+                return Err(ConstraintUnsatisfiedReason::TraceIo {
+                    daemon_enabled: extra.trace_io_enabled,
+                });
             }
             _ => {}
         }
@@ -518,6 +585,7 @@ impl BuckdChannel {
             daemon_dir,
             client,
             constraints,
+            paths: None,
         })
     }
 }
@@ -530,6 +598,8 @@ pub struct BootstrapBuckdClient {
     client: DaemonApiClient<InterceptedService<Channel, BuckAddAuthTokenInterceptor>>,
     /// The constraints for the daemon we're connected to.
     constraints: buck2_cli_proto::DaemonConstraints,
+    /// Paths of the invocation, used to write diagnostics if the daemon disconnects.
+    paths: Option<InvocationPaths>,
 }
 
 impl BootstrapBuckdClient {
@@ -563,6 +633,10 @@ impl BootstrapBuckdClient {
                 establish_connection(paths, constraints, event_subscribers).await
             }
         }
+        .map(|client| BootstrapBuckdClient {
+            paths: Some(paths.clone()),
+            ..client
+        })
         .with_context(|| daemon_connect_error(paths))
         .context(error_message)
     }
@@ -573,6 +647,9 @@ impl BootstrapBuckdClient {
     ) -> BuckdClientConnector<'a> {
         BuckdClientConnector {
             client: BuckdClient {
+                disconnect_diagnostics: self.paths.as_ref().map(|paths| {
+                    DisconnectDiagnostics::new(paths, self.daemon_dir.clone(), self.info.pid)
+                }),
                 daemon_dir: self.daemon_dir,
                 client: self.client,
                 constraints: self.constraints,
@@ -583,10 +660,20 @@ impl BootstrapBuckdClient {
     }
 
     pub async fn kill(&mut self, reason: &str) -> anyhow::Result<Pid> {
-        kill::kill(&mut self.client, &self.info, reason).await?;
+        self.kill_with_timeout(reason, kill::GRACEFUL_SHUTDOWN_TIMEOUT)
+            .await?;
         Pid::from_i64(self.info.pid)
     }
 
+    /// Kills the daemon, giving it `timeout` to shut down by itself. Returns how it exited.
+    pub async fn kill_with_timeout(
+        &mut self,
+        reason: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<ProcessExit> {
+        kill::kill(&mut self.client, &self.info, reason, timeout).await
+    }
+
     async fn kill_for_constraints_mismatch(&mut self) -> anyhow::Result<Pid> {
         self.kill("client expected different buckd constraints")
             .await
@@ -725,6 +812,10 @@ async fn establish_connection_inner(
         })
         .await?;
 
+    // Clears the owner left behind by a daemon that crashed, and refuses to touch the daemon dir
+    // if its recorded owner is some unrelated process.
+    daemon_dir.check_owner()?;
+
     // Even if we didn't connect before, it's possible that we just raced with another invocation
     // starting the server, so we try to connect again while holding the lock.
     let daemon_was_started_reason = {
@@ -738,6 +829,12 @@ async fn establish_connection_inner(
                             Err(reason) => reason,
                         };
 
+                        if constraints.no_auto_restart {
+                            return Err(
+                                BuckdConnectError::BuckDaemonConstraintMismatch { reason }.into()
+                            );
+                        }
+
                         event_subscribers
                             .eprintln(&format!(
                                 "buck2 daemon constraint mismatch: {reason}; killing daemon..."
@@ -986,7 +1083,7 @@ impl<'a> BuckdProcessInfo<'a> {
         })
     }
 
-    pub async fn hard_kill(&self) -> anyhow::Result<()> {
+    pub async fn hard_kill(&self) -> anyhow::Result<ProcessExit> {
         kill::hard_kill(&self.info).await
     }
 
@@ -1030,6 +1127,10 @@ enum BuckdConnectError {
         stdout: String,
         stderr: String,
     },
+    #[error(
+        "buck2 daemon constraint mismatch: {reason}; not restarting it because of `--no-auto-restart`"
+    )]
+    BuckDaemonConstraintMismatch { reason: ConstraintUnsatisfiedReason },
     #[error(
         "during buck daemon startup, the started process did not match constraints ({reason}).\nexpected: {expected:?}\nactual: {actual:?}"
     )]
@@ -1084,6 +1185,7 @@ mod tests {
             reject_daemon: None,
             reject_materializer_state: None,
             daemon_startup_config: DaemonStartupConfig::testing_empty(),
+            no_auto_restart: false,
         }
     }
 
@@ -1108,6 +1210,54 @@ mod tests {
         assert!(req.satisfied(&daemon).is_err());
     }
 
+    #[test]
+    fn test_constraints_mismatch_details() {
+        let daemon = constraints(true);
+
+        let mut req = request(DesiredTraceIoState::Enabled);
+        req.version = "other".to_owned();
+        assert_eq!(
+            "Version mismatch (client: `other`, daemon: `version`)",
+            req.satisfied(&daemon).unwrap_err().to_string()
+        );
+
+        let mut req = request(DesiredTraceIoState::Enabled);
+        req.user_version = None;
+        assert_eq!(
+            "User version mismatch (client: none, daemon: `test`)",
+            req.satisfied(&daemon).unwrap_err().to_string()
+        );
+
+        let mut req = request(DesiredTraceIoState::Enabled);
+        req.daemon_startup_config.paranoid = true;
+        req.daemon_startup_config.daemon_buster = Some("1".to_owned());
+        assert_eq!(
+            "Startup config mismatch (different `daemon_buster`, `paranoid`)",
+            req.satisfied(&daemon).unwrap_err().to_string()
+        );
+
+        let req = request(DesiredTraceIoState::Disabled);
+        assert!(matches!(
+            req.satisfied(&daemon),
+            Err(ConstraintUnsatisfiedReason::TraceIo {
+                daemon_enabled: true
+            })
+        ));
+    }
+
+    #[test]
+    fn test_constraints_invalid_startup_config() {
+        let req = request(DesiredTraceIoState::Enabled);
+        let mut daemon = constraints(true);
+        daemon.daemon_startup_config = None;
+        assert!(matches!(
+            req.satisfied(&daemon),
+            Err(ConstraintUnsatisfiedReason::StartupConfig(
+                StartupConfigMismatch::Invalid
+            ))
+        ));
+    }
+
     #[test]
     fn test_trace_io_is_enabled() {
         let c = request(DesiredTraceIoState::Enabled);
@@ -1126,6 +1276,7 @@ mod tests {
             reject_daemon: None,
             reject_materializer_state: None,
             daemon_startup_config: DaemonStartupConfig::testing_empty(),
+            no_auto_restart: false,
         };
 
         let daemon = buck2_cli_proto::DaemonConstraints {
@@ -1154,6 +1305,7 @@ mod tests {
             reject_daemon: None,
             reject_materializer_state: None,
             daemon_startup_config: DaemonStartupConfig::testing_empty(),
+            no_auto_restart: false,
         };
 
         let daemon = buck2_cli_proto::DaemonConstraints {
@@ -1185,6 +1337,7 @@ mod tests {
             reject_daemon: None,
             reject_materializer_state: None,
             daemon_startup_config: DaemonStartupConfig::testing_empty(),
+            no_auto_restart: false,
         };
 
         let daemon = buck2_cli_proto::DaemonConstraints {
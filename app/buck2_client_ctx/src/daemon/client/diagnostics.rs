@@ -0,0 +1,518 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Diagnostics collected when the daemon disconnects in the middle of a command.
+//!
+//! Without them, all the user gets is a transport error, which is not actionable.
+
+use std::fmt;
+use std::fmt::Write;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use anyhow::Context;
+use buck2_common::daemon_dir::DaemonDir;
+use buck2_common::invocation_paths::InvocationPaths;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::abs_path::AbsPath;
+use buck2_core::fs::paths::abs_path::AbsPathBuf;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use buck2_data::error::ErrorTag;
+use buck2_event_log::file_names::get_local_logs;
+use buck2_wrapper_common::kill::get_sysinfo_status;
+use buck2_wrapper_common::pid::Pid;
+
+use crate::subscribers::classify_server_stderr::classify_server_stderr;
+
+/// Upper bound on the time spent collecting diagnostics before returning the original error.
+const COLLECTION_TIMEOUT: Duration = Duration::from_secs(5);
+#[cfg(target_os = "linux")]
+const KERNEL_LOG_TIMEOUT: Duration = Duration::from_secs(1);
+/// Only this much of the end of the daemon stderr is read.
+const STDERR_TAIL_BYTES: u64 = 64 * 1024;
+const STDERR_TAIL_LINES: usize = 200;
+#[cfg(target_os = "linux")]
+const KERNEL_LOG_TAIL_LINES: usize = 100;
+/// Larger event logs are not copied into the bundle, since copying them could take too long.
+const MAX_EVENT_LOG_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The most likely reason for the daemon to disconnect, as far as we can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DisconnectCause {
+    OomKilled,
+    Panicked,
+    StackOverflow,
+    Segfault,
+    AllocatorAssert,
+    StillRunning,
+}
+
+impl fmt::Display for DisconnectCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            DisconnectCause::OomKilled => "daemon appears to have been OOM-killed",
+            DisconnectCause::Panicked => "daemon appears to have panicked",
+            DisconnectCause::StackOverflow => "daemon appears to have overflowed its stack",
+            DisconnectCause::Segfault => "daemon appears to have crashed with a segfault",
+            DisconnectCause::AllocatorAssert => {
+                "daemon appears to have aborted on a memory allocator assertion"
+            }
+            DisconnectCause::StillRunning => {
+                "daemon is still running, so the connection was lost but the daemon did not die"
+            }
+        };
+        f.write_str(message)
+    }
+}
+
+/// Everything we found out about the daemon after it disconnected.
+#[derive(Debug, Default)]
+struct DisconnectEvidence {
+    pid: Option<i64>,
+    /// Last lines of the daemon stderr.
+    stderr_tail: Option<String>,
+    /// Status of the daemon process, `None` if it does not exist anymore.
+    process_status: Option<String>,
+    /// OOM killer messages from the kernel log, `None` if the kernel log could not be read.
+    oom_kernel_log: Option<Vec<String>>,
+    /// Most recent event log, which is the one of this command unless commands ran concurrently.
+    event_log: Option<AbsPathBuf>,
+    /// What could not be collected, and why.
+    collection_errors: Vec<String>,
+}
+
+fn mentions_oom_kill_of(line: &str, pid: i64) -> bool {
+    // The kernel reports kills like this:
+    // ```
+    // Out of memory: Killed process 1234 (buck2d) total-vm:...
+    // oom-kill:constraint=CONSTRAINT_NONE,...,task=buck2d,pid=1234,uid=1000
+    // ```
+    line.contains(&format!("Killed process {} ", pid)) || line.contains(&format!(",pid={},", pid))
+}
+
+#[cfg(target_os = "linux")]
+fn is_oom_line(line: &str) -> bool {
+    line.contains("Out of memory")
+        || line.contains("out of memory")
+        || line.contains("oom-kill")
+        || line.contains("oom_reaper")
+}
+
+fn classify_disconnect(evidence: &DisconnectEvidence) -> Option<DisconnectCause> {
+    if let (Some(pid), Some(oom_log)) = (evidence.pid, &evidence.oom_kernel_log) {
+        if oom_log.iter().any(|line| mentions_oom_kill_of(line, pid)) {
+            return Some(DisconnectCause::OomKilled);
+        }
+    }
+
+    if let Some(stderr) = &evidence.stderr_tail {
+        match classify_server_stderr(stderr) {
+            ErrorTag::ServerPanicked => return Some(DisconnectCause::Panicked),
+            ErrorTag::ServerStackOverflow => return Some(DisconnectCause::StackOverflow),
+            ErrorTag::ServerSegv => return Some(DisconnectCause::Segfault),
+            ErrorTag::ServerJemallocAssert => return Some(DisconnectCause::AllocatorAssert),
+            _ => {}
+        }
+    }
+
+    if evidence.process_status.is_some() {
+        return Some(DisconnectCause::StillRunning);
+    }
+
+    None
+}
+
+/// Read the last lines of a file, without reading all of it.
+fn read_tail(path: &AbsPath, max_bytes: u64, max_lines: usize) -> anyhow::Result<String> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Error opening `{}`", path.display()))?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(max_bytes)))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    let contents = String::from_utf8_lossy(&bytes);
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut tail = lines[lines.len().saturating_sub(max_lines)..].join("\n");
+    tail.push('\n');
+    Ok(tail)
+}
+
+#[cfg(target_os = "linux")]
+async fn read_oom_kernel_log() -> anyhow::Result<Option<Vec<String>>> {
+    let output = tokio::time::timeout(
+        KERNEL_LOG_TIMEOUT,
+        tokio::process::Command::new("dmesg")
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .context("Timed out reading the kernel log")?
+    .context("Error running `dmesg`")?;
+    if !output.status.success() {
+        // Typically because the kernel log is restricted to root.
+        return Err(anyhow::anyhow!(
+            "`dmesg` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let log = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<String> = log
+        .lines()
+        .filter(|line| is_oom_line(line))
+        .map(|line| line.to_owned())
+        .collect();
+    let skip = lines.len().saturating_sub(KERNEL_LOG_TAIL_LINES);
+    Ok(Some(lines.into_iter().skip(skip).collect()))
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn read_oom_kernel_log() -> anyhow::Result<Option<Vec<String>>> {
+    Ok(None)
+}
+
+/// Write the bundle into `dir`, which is created.
+fn write_bundle(
+    dir: &AbsNormPath,
+    mut evidence: DisconnectEvidence,
+    cause: Option<DisconnectCause>,
+) -> anyhow::Result<()> {
+    fs_util::create_dir_all(dir)?;
+
+    if let Some(stderr) = &evidence.stderr_tail {
+        fs_util::write(
+            dir.join(FileName::unchecked_new("daemon_stderr.txt")),
+            stderr,
+        )?;
+    }
+
+    if let Some(oom_log) = evidence
+        .oom_kernel_log
+        .as_ref()
+        .filter(|log| !log.is_empty())
+    {
+        let mut contents = oom_log.join("\n");
+        contents.push('\n');
+        fs_util::write(
+            dir.join(FileName::unchecked_new("kernel_oom.txt")),
+            contents,
+        )?;
+    }
+
+    if let Some(event_log) = &evidence.event_log {
+        let copied: anyhow::Result<()> = try {
+            let size = fs_util::metadata(event_log)?.len();
+            if size > MAX_EVENT_LOG_BYTES {
+                Err(anyhow::anyhow!("too large to copy ({} bytes)", size))?;
+            }
+            let file_name = event_log
+                .file_name()
+                .context("Event log has no file name")?
+                .to_str()
+                .context("Event log file name is not UTF-8")?;
+            fs_util::copy(event_log, dir.join(FileName::new(file_name)?))?;
+        };
+        if let Err(e) = copied {
+            evidence.collection_errors.push(format!(
+                "event log `{}`: {:#}",
+                event_log.display(),
+                e
+            ));
+        }
+    }
+
+    let mut summary = String::new();
+    match cause {
+        Some(cause) => writeln!(summary, "likely cause: {}", cause)?,
+        None => writeln!(summary, "likely cause: unknown")?,
+    }
+    match evidence.pid {
+        Some(pid) => writeln!(summary, "daemon pid: {}", pid)?,
+        None => writeln!(summary, "daemon pid: unknown")?,
+    }
+    match &evidence.process_status {
+        Some(status) => writeln!(summary, "daemon process status: {}", status)?,
+        None => writeln!(summary, "daemon process status: not running")?,
+    }
+    if let Some(event_log) = &evidence.event_log {
+        writeln!(summary, "event log: {}", event_log.display())?;
+    }
+    for error in &evidence.collection_errors {
+        writeln!(summary, "not collected: {}", error)?;
+    }
+    fs_util::write(dir.join(FileName::unchecked_new("summary.txt")), summary)?;
+
+    Ok(())
+}
+
+/// Collects a diagnostics bundle when the daemon disconnects in the middle of a command, and tells
+/// the user where to find it.
+pub(crate) struct DisconnectDiagnostics {
+    daemon_dir: DaemonDir,
+    pid: i64,
+    log_dir: AbsNormPathBuf,
+    diagnostics_dir: AbsNormPathBuf,
+}
+
+impl DisconnectDiagnostics {
+    pub(crate) fn new(paths: &InvocationPaths, daemon_dir: DaemonDir, pid: i64) -> Self {
+        Self {
+            daemon_dir,
+            pid,
+            log_dir: paths.log_dir(),
+            diagnostics_dir: paths
+                .buck_out_path()
+                .join(ForwardRelativePath::unchecked_new("diagnostics")),
+        }
+    }
+
+    /// Given the error a command failed with, collect diagnostics if the failure looks like the
+    /// daemon went away. This is best effort and strictly time bounded: the original error is
+    /// always returned unchanged.
+    pub(crate) async fn report_if_disconnected(&self, error: anyhow::Error) -> anyhow::Error {
+        let error = buck2_error::Error::from(error);
+        if !error.tags().contains(&ErrorTag::ClientGrpc) {
+            return error.into();
+        }
+
+        match tokio::time::timeout(COLLECTION_TIMEOUT, self.collect()).await {
+            Ok(Ok((bundle, cause))) => {
+                let cause = match cause {
+                    Some(cause) => format!(" ({})", cause),
+                    None => String::new(),
+                };
+                let _ignored = crate::eprintln!(
+                    "Buck2 daemon disconnected unexpectedly{}. Diagnostics were saved to `{}`",
+                    cause,
+                    bundle.display()
+                );
+            }
+            Ok(Err(e)) => tracing::warn!("Error collecting daemon diagnostics: {:#}", e),
+            Err(_) => tracing::warn!("Timed out collecting daemon diagnostics"),
+        }
+
+        error.into()
+    }
+
+    /// Filesystem access happens on blocking threads, so that the collection timeout still fires
+    /// if a read or the copy of the event log hangs.
+    async fn collect(&self) -> anyhow::Result<(AbsNormPathBuf, Option<DisconnectCause>)> {
+        let evidence = self.gather().await;
+        let cause = classify_disconnect(&evidence);
+
+        let bundle = self.diagnostics_dir.join(FileName::new(&format!(
+            "daemon-disconnect-{}",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ))?);
+        let dir = bundle.clone();
+        tokio::task::spawn_blocking(move || write_bundle(&dir, evidence, cause)).await??;
+        Ok((bundle, cause))
+    }
+
+    async fn gather(&self) -> DisconnectEvidence {
+        let mut evidence = DisconnectEvidence {
+            pid: Some(self.pid),
+            ..Default::default()
+        };
+
+        let stderr = self.daemon_dir.buckd_stderr();
+        match tokio::task::spawn_blocking(move || {
+            read_tail(&stderr, STDERR_TAIL_BYTES, STDERR_TAIL_LINES)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|tail| tail)
+        {
+            Ok(tail) => evidence.stderr_tail = Some(tail),
+            Err(e) => evidence
+                .collection_errors
+                .push(format!("daemon stderr: {:#}", e)),
+        }
+
+        evidence.process_status = Pid::from_i64(self.pid)
+            .ok()
+            .and_then(get_sysinfo_status)
+            .map(|status| status.to_string());
+
+        match read_oom_kernel_log().await {
+            Ok(oom_log) => evidence.oom_kernel_log = oom_log,
+            Err(e) => evidence
+                .collection_errors
+                .push(format!("kernel log: {:#}", e)),
+        }
+
+        let log_dir = self.log_dir.clone();
+        match tokio::task::spawn_blocking(move || get_local_logs(&log_dir))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|logs| logs)
+        {
+            Ok(logs) => {
+                evidence.event_log = logs.last().map(|log| log.path().to_owned());
+            }
+            Err(e) => evidence
+                .collection_errors
+                .push(format!("event log: {:#}", e)),
+        }
+
+        evidence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+    use buck2_core::fs::paths::abs_path::AbsPathBuf;
+
+    use crate::daemon::client::diagnostics::classify_disconnect;
+    use crate::daemon::client::diagnostics::write_bundle;
+    use crate::daemon::client::diagnostics::DisconnectCause;
+    use crate::daemon::client::diagnostics::DisconnectEvidence;
+
+    fn base_evidence() -> DisconnectEvidence {
+        DisconnectEvidence {
+            pid: Some(1234),
+            stderr_tail: Some("starting daemon\n".to_owned()),
+            process_status: None,
+            oom_kernel_log: Some(Vec::new()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_classify_oom_kill() {
+        let evidence = DisconnectEvidence {
+            oom_kernel_log: Some(vec![
+                "[123.4] buck2d invoked oom-killer: gfp_mask=0x100cca".to_owned(),
+                "[123.5] Out of memory: Killed process 1234 (buck2d) total-vm:123kB".to_owned(),
+            ]),
+            ..base_evidence()
+        };
+        assert_eq!(
+            Some(DisconnectCause::OomKilled),
+            classify_disconnect(&evidence)
+        );
+
+        let evidence = DisconnectEvidence {
+            oom_kernel_log: Some(vec![
+                "oom-kill:constraint=CONSTRAINT_NONE,task=buck2d,pid=1234,uid=1000".to_owned(),
+            ]),
+            ..base_evidence()
+        };
+        assert_eq!(
+            Some(DisconnectCause::OomKilled),
+            classify_disconnect(&evidence)
+        );
+    }
+
+    #[test]
+    fn test_classify_oom_kill_of_other_process() {
+        let evidence = DisconnectEvidence {
+            oom_kernel_log: Some(vec![
+                "Out of memory: Killed process 12345 (rustc) total-vm:123kB".to_owned(),
+                "oom-kill:constraint=CONSTRAINT_NONE,task=rustc,pid=12345,uid=1000".to_owned(),
+            ]),
+            ..base_evidence()
+        };
+        assert_eq!(None, classify_disconnect(&evidence));
+    }
+
+    #[test]
+    fn test_classify_from_stderr() {
+        let evidence = DisconnectEvidence {
+            stderr_tail: Some("thread 'buck2-rt' panicked at 'oops', src/lib.rs:1:1\n".to_owned()),
+            ..base_evidence()
+        };
+        assert_eq!(
+            Some(DisconnectCause::Panicked),
+            classify_disconnect(&evidence)
+        );
+
+        let evidence = DisconnectEvidence {
+            stderr_tail: Some("thread 'buck2-dm' has overflowed its stack\n".to_owned()),
+            ..base_evidence()
+        };
+        assert_eq!(
+            Some(DisconnectCause::StackOverflow),
+            classify_disconnect(&evidence)
+        );
+    }
+
+    #[test]
+    fn test_classify_without_evidence() {
+        assert_eq!(None, classify_disconnect(&base_evidence()));
+        assert_eq!(None, classify_disconnect(&DisconnectEvidence::default()));
+
+        let evidence = DisconnectEvidence {
+            process_status: Some("Sleep".to_owned()),
+            ..base_evidence()
+        };
+        assert_eq!(
+            Some(DisconnectCause::StillRunning),
+            classify_disconnect(&evidence)
+        );
+    }
+
+    #[test]
+    fn test_bundle_layout() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let event_log = temp_dir.path().join("20240101-trace.pb.zst");
+        std::fs::write(&event_log, "events")?;
+        let bundle = AbsNormPathBuf::try_from(temp_dir.path().join("bundle"))?;
+
+        let evidence = DisconnectEvidence {
+            oom_kernel_log: Some(vec![
+                "Out of memory: Killed process 1234 (buck2d)".to_owned(),
+            ]),
+            event_log: Some(AbsPathBuf::try_from(event_log)?),
+            collection_errors: vec!["kernel log: permission denied".to_owned()],
+            ..base_evidence()
+        };
+        let cause = classify_disconnect(&evidence);
+        write_bundle(&bundle, evidence, cause)?;
+
+        let read = |name: &str| std::fs::read_to_string(bundle.as_path().join(name));
+        assert_eq!("starting daemon\n", read("daemon_stderr.txt")?);
+        assert_eq!(
+            "Out of memory: Killed process 1234 (buck2d)\n",
+            read("kernel_oom.txt")?
+        );
+        assert_eq!("events", read("20240101-trace.pb.zst")?);
+
+        let summary = read("summary.txt")?;
+        assert!(summary.starts_with(
+            "likely cause: daemon appears to have been OOM-killed\n\
+             daemon pid: 1234\n\
+             daemon process status: not running\n"
+        ));
+        assert!(summary.contains("not collected: kernel log: permission denied\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_layout_without_evidence() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let bundle = AbsNormPathBuf::try_from(temp_dir.path().join("bundle"))?;
+
+        write_bundle(&bundle, DisconnectEvidence::default(), None)?;
+
+        let mut files: Vec<_> = std::fs::read_dir(bundle.as_path())?
+            .map(|entry| Ok(entry?.file_name().into_string().unwrap()))
+            .collect::<anyhow::Result<_>>()?;
+        files.sort();
+        assert_eq!(vec!["summary.txt"], files);
+        Ok(())
+    }
+}
@@ -13,6 +13,7 @@ use std::time::Instant;
 use buck2_cli_proto::daemon_api_client::*;
 use buck2_cli_proto::*;
 use buck2_wrapper_common::kill;
+use buck2_wrapper_common::kill::ProcessExit;
 use buck2_wrapper_common::pid::Pid;
 use sysinfo::PidExt;
 use sysinfo::ProcessExt;
@@ -32,16 +33,23 @@ enum KillError {
     DidNotDie(Pid, Duration, String),
 }
 
-const GRACEFUL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(4);
+/// How long the daemon is given to shut down by itself by default.
+pub const GRACEFUL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(4);
 /// Kill request does not wait for the process to exit.
 const KILL_REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long the daemon is given to exit after `TERM`, before it is sent `KILL`.
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(2);
 const FORCE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Asks the daemon to shut down, and terminates it if it doesn't exit within `graceful_timeout`,
+/// first with `TERM` so it can flush its logs, then with `KILL`.
+/// Fails if the daemon still runs after being killed.
 pub(crate) async fn kill(
     client: &mut DaemonApiClient<InterceptedService<Channel, BuckAddAuthTokenInterceptor>>,
     info: &DaemonProcessInfo,
     reason: &str,
-) -> anyhow::Result<()> {
+    graceful_timeout: Duration,
+) -> anyhow::Result<ProcessExit> {
     let pid = Pid::from_i64(info.pid)?;
     let callers = get_callers_for_kill();
 
@@ -49,28 +57,15 @@ pub(crate) async fn kill(
 
     let request_fut = client.kill(Request::new(KillRequest {
         reason: reason.to_owned(),
-        timeout: Some(GRACEFUL_SHUTDOWN_TIMEOUT.try_into()?),
+        timeout: Some(graceful_timeout.try_into()?),
         callers,
     }));
-    let time_to_kill = GRACEFUL_SHUTDOWN_TIMEOUT + FORCE_SHUTDOWN_TIMEOUT;
     let time_req_sent = Instant::now();
     // First we send a Kill request
-    match tokio::time::timeout(KILL_REQUEST_TIMEOUT, request_fut).await {
+    let graceful_timeout = match tokio::time::timeout(KILL_REQUEST_TIMEOUT, request_fut).await {
         Ok(inner_result) => {
             match inner_result {
-                Ok(_) => loop {
-                    if !kill::process_exists(pid)? {
-                        return Ok(());
-                    }
-                    if time_req_sent.elapsed() > GRACEFUL_SHUTDOWN_TIMEOUT {
-                        crate::eprintln!(
-                            "Timed out waiting for graceful shutdown of buck2 daemon pid {}",
-                            pid
-                        )?;
-                        break;
-                    }
-                    tokio::time::sleep(Duration::from_millis(100)).await;
-                },
+                Ok(_) => graceful_timeout.saturating_sub(time_req_sent.elapsed()),
                 Err(e) => {
                     // The kill request can fail if the server is in a bad state and we cannot
                     // authenticate to it.
@@ -79,6 +74,7 @@ pub(crate) async fn kill(
                         pid,
                         e
                     )?;
+                    Duration::ZERO
                 }
             }
         }
@@ -88,29 +84,45 @@ pub(crate) async fn kill(
                 "Timed out requesting graceful shutdown of buck2 daemon pid {}",
                 pid
             )?;
+            Duration::ZERO
         }
     };
 
-    hard_kill_impl(pid, time_req_sent, time_to_kill).await
+    let exit = tokio::task::spawn_blocking(move || {
+        kill::wait_for_exit_or_kill(
+            pid,
+            graceful_timeout,
+            TERMINATE_TIMEOUT,
+            FORCE_SHUTDOWN_TIMEOUT,
+        )
+    })
+    .await??;
+    if matches!(exit, ProcessExit::Terminated | ProcessExit::Killed) && !graceful_timeout.is_zero()
+    {
+        crate::eprintln!(
+            "Timed out waiting for graceful shutdown of buck2 daemon pid {}",
+            pid
+        )?;
+    }
+    check_exited(pid, FORCE_SHUTDOWN_TIMEOUT, exit)
 }
 
-pub(crate) async fn hard_kill(info: &DaemonProcessInfo) -> anyhow::Result<()> {
+pub(crate) async fn hard_kill(info: &DaemonProcessInfo) -> anyhow::Result<ProcessExit> {
     let pid = Pid::from_i64(info.pid)?;
 
-    hard_kill_impl(pid, Instant::now(), FORCE_SHUTDOWN_TIMEOUT).await
+    hard_kill_impl(pid, FORCE_SHUTDOWN_TIMEOUT).await
 }
 
 pub(crate) async fn hard_kill_until(
     info: &DaemonProcessInfo,
     deadline: Instant,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<ProcessExit> {
     let pid = Pid::from_i64(info.pid)?;
 
-    let now = Instant::now();
-    hard_kill_impl(pid, now, deadline.saturating_duration_since(now)).await
+    hard_kill_impl(pid, deadline.saturating_duration_since(Instant::now())).await
 }
 
-async fn hard_kill_impl(pid: Pid, start_at: Instant, deadline: Duration) -> anyhow::Result<()> {
+async fn hard_kill_impl(pid: Pid, timeout: Duration) -> anyhow::Result<ProcessExit> {
     tracing::info!(
         "Killing PID {} with status {}",
         pid,
@@ -120,25 +132,17 @@ async fn hard_kill_impl(pid: Pid, start_at: Instant, deadline: Duration) -> anyh
             .unwrap_or("<unknown>")
     );
 
-    let Some(handle) = kill::kill(pid)? else {
-        return Ok(());
-    };
-    let timestamp_after_kill = Instant::now();
-    while start_at.elapsed() < deadline {
-        if handle.has_exited()? {
-            return Ok(());
-        }
-        tokio::time::sleep(Duration::from_millis(100)).await;
-    }
+    let exit = tokio::task::spawn_blocking(move || kill::kill_and_wait(pid, timeout)).await??;
+    check_exited(pid, timeout, exit)
+}
 
-    // Last chance: we do logging this time.
-    let status = kill::get_sysinfo_status(pid).map(|s| s.to_string());
-    let status = status.unwrap_or_else(|| "<unknown>".to_owned());
-    if handle.has_exited()? {
-        return Ok(());
+fn check_exited(pid: Pid, timeout: Duration, exit: ProcessExit) -> anyhow::Result<ProcessExit> {
+    match exit {
+        ProcessExit::StillRunning { status } => {
+            Err(KillError::DidNotDie(pid, timeout, status).into())
+        }
+        exit => Ok(exit),
     }
-
-    Err(KillError::DidNotDie(pid, timestamp_after_kill.elapsed(), status).into())
 }
 
 fn get_callers_for_kill() -> Vec<String> {
@@ -31,6 +31,8 @@ pub struct ExecArgs {
 /// - Uncategorized Error : 1
 /// - Infra Error         : 2
 /// - User Error          : 3
+/// - Daemon Is Busy      : 4
+/// - Preempted           : 5
 /// - Signal Interruption : 129-192 (128 + signal number)
 ///
 /// We can easily turn a anyhow::Result (or anyhow::Error, or even a message) into a ExitResult,
@@ -105,6 +107,8 @@ impl ExitResult {
             && io_error.0.kind() == io::ErrorKind::BrokenPipe
         {
             ExitCode::BrokenPipe
+        } else if buck2_error::find_tags(&err).contains(&buck2_error::ErrorTag::InternalError) {
+            ExitCode::InfraError
         } else {
             ExitCode::UnknownFailure
         };
@@ -146,6 +150,11 @@ impl ExitResult {
         let mut has_infra = false;
         let mut has_user = false;
         for e in errors {
+            if e.tags
+                .contains(&(buck2_data::error::ErrorTag::PreemptedByNewerInvocation as i32))
+            {
+                return Self::status(ExitCode::Preempted);
+            }
             if e.tags
                 .contains(&(buck2_data::error::ErrorTag::DaemonIsBusy as i32))
             {
@@ -274,6 +283,8 @@ pub enum ExitCode {
     InfraError,
     UserError,
     DaemonIsBusy,
+    /// The command was cancelled in favour of a newer command.
+    Preempted,
     ConnectError,
     SignalInterrupt,
     BrokenPipe,
@@ -291,6 +302,7 @@ impl ExitCode {
             InfraError => 2,
             UserError => 3,
             DaemonIsBusy => 4,
+            Preempted => 5,
             ConnectError => 11,
             BrokenPipe => 130,
             SignalInterrupt => 141,
@@ -338,3 +350,96 @@ fn execv(args: ExecArgs) -> ! {
     ));
     ExitResult::err(err).report()
 }
+
+#[cfg(test)]
+mod tests {
+    use buck2_data::error::ErrorTag;
+    use buck2_data::error::ErrorTier;
+    use futures::future::Either;
+
+    use crate::exit_result::ExitResult;
+    use crate::exit_result::ExitResultVariant;
+    use crate::signal_handler::exit_on_interrupt;
+
+    fn status_code(res: ExitResult) -> u8 {
+        match res.variant {
+            ExitResultVariant::Status(code) => code.exit_code(),
+            _ => panic!("expected a status"),
+        }
+    }
+
+    fn exit_code_for(errors: &[buck2_data::ErrorReport]) -> u8 {
+        status_code(ExitResult::from_errors(errors))
+    }
+
+    fn report(tier: ErrorTier, tags: &[ErrorTag]) -> buck2_data::ErrorReport {
+        buck2_data::ErrorReport {
+            tier: Some(tier as i32),
+            tags: tags.iter().map(|t| *t as i32).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_preempted_exit_code() {
+        assert_eq!(
+            5,
+            exit_code_for(&[report(
+                ErrorTier::Input,
+                &[ErrorTag::PreemptedByNewerInvocation]
+            )])
+        );
+        // Preemption wins over other errors reported by the command.
+        assert_eq!(
+            5,
+            exit_code_for(&[
+                report(ErrorTier::Tier0, &[]),
+                report(ErrorTier::Input, &[ErrorTag::PreemptedByNewerInvocation]),
+            ])
+        );
+        assert_eq!(
+            4,
+            exit_code_for(&[report(ErrorTier::Input, &[ErrorTag::DaemonIsBusy])])
+        );
+        assert_eq!(3, exit_code_for(&[report(ErrorTier::Input, &[])]));
+    }
+
+    #[test]
+    fn test_internal_error_exit_code() {
+        fn err_exit_code(err: anyhow::Error) -> u8 {
+            match ExitResult::err(err).variant {
+                ExitResultVariant::StatusWithErr(code, _) => code.exit_code(),
+                _ => panic!("expected an error"),
+            }
+        }
+
+        let internal: anyhow::Error = buck2_error::Error::from(anyhow::anyhow!("bug"))
+            .tag([ErrorTag::InternalError])
+            .into();
+        assert_eq!(2, err_exit_code(internal.context("context")));
+        assert_eq!(1, err_exit_code(anyhow::anyhow!("unknown")));
+    }
+
+    #[tokio::test]
+    async fn test_second_interrupt_skips_cleanup() {
+        let cleanup = futures::future::pending::<()>();
+        let interrupt = exit_on_interrupt(futures::future::ready(Ok(())));
+        futures::pin_mut!(cleanup);
+        futures::pin_mut!(interrupt);
+
+        match futures::future::select(cleanup, interrupt).await {
+            Either::Right((Some(res), _)) => assert_eq!(141, status_code(res)),
+            _ => panic!("expected the interrupt to win"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interrupt_listener_failure_does_not_exit() {
+        let res = exit_on_interrupt(futures::future::ready(Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "no signals",
+        ))))
+        .await;
+        assert!(res.is_none());
+    }
+}
@@ -7,10 +7,15 @@
  * of this source tree.
  */
 
+use std::io;
+
 use futures::future;
 use futures::future::Either;
 use futures::Future;
 
+use crate::exit_result::ExitCode;
+use crate::exit_result::ExitResult;
+
 /// A simple SIGINT handler that lets `work` and ctrl+c future race. When ctrl+c
 /// is hit, it allows the `work` future and the other clean-up implementations
 /// such as AsyncCleanupContext to be dropped.
@@ -25,3 +30,53 @@ pub async fn with_simple_sigint_handler<F: Future>(work: F) -> Option<F::Output>
         Either::Right((_, _)) => None,
     }
 }
+
+/// After a first ctrl+c started a graceful teardown, listen for another interrupt (ctrl+c, or
+/// SIGTERM on unix) and exit immediately if one arrives, skipping whatever clean-up is still
+/// running. The listener is spawned on the current runtime, which keeps being driven while we
+/// wait for the `AsyncCleanupContext` jobs.
+pub fn spawn_hard_exit_on_interrupt() {
+    tokio::spawn(async {
+        if let Some(res) = exit_on_interrupt(interrupt_signal()).await {
+            res.report()
+        }
+    });
+}
+
+/// Waits for `interrupt`, then returns the `ExitResult` to exit with right away. Returns `None`
+/// if we could not listen for the interrupt.
+pub(crate) async fn exit_on_interrupt(
+    interrupt: impl Future<Output = io::Result<()>>,
+) -> Option<ExitResult> {
+    match interrupt.await {
+        Ok(()) => {
+            let _ignored =
+                crate::eprintln!("Interrupted again, exiting without waiting for clean-up");
+            Some(ExitResult::status(ExitCode::SignalInterrupt))
+        }
+        Err(e) => {
+            tracing::warn!("Failed to listen for interrupts during clean-up: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Resolves on ctrl+c, or SIGTERM on unix.
+async fn interrupt_signal() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::signal;
+        use tokio::signal::unix::SignalKind;
+
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res,
+            _ = sigterm.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
@@ -17,6 +17,7 @@ use dupe::Dupe;
 
 use crate::client_ctx::ClientCommandContext;
 use crate::common::ui::CommonConsoleOptions;
+use crate::common::ui::ConsoleType;
 use crate::common::CommonBuildConfigurationOptions;
 use crate::common::CommonEventLogOptions;
 use crate::common::CommonStarlarkOptions;
@@ -28,12 +29,14 @@ use crate::daemon::client::BuckdClientConnector;
 use crate::exit_result::ExitCode;
 use crate::exit_result::ExitResult;
 use crate::path_arg::PathArg;
+use crate::signal_handler::spawn_hard_exit_on_interrupt;
 use crate::signal_handler::with_simple_sigint_handler;
 use crate::subscribers::get::get_console_with_root;
 use crate::subscribers::get::try_get_build_graph_stats;
 use crate::subscribers::get::try_get_build_id_writer;
 use crate::subscribers::get::try_get_event_log_subscriber;
 use crate::subscribers::get::try_get_re_log_subscriber;
+use crate::subscribers::get::try_get_status_writer;
 use crate::subscribers::recorder::try_get_invocation_recorder;
 use crate::subscribers::subscriber::EventSubscriber;
 use crate::subscribers::subscribers::EventSubscribers;
@@ -43,8 +46,10 @@ fn default_subscribers<'a, T: StreamingCommand>(
     ctx: &ClientCommandContext<'a>,
 ) -> anyhow::Result<EventSubscribers<'a>> {
     let console_opts = cmd.console_opts();
+    let console_type = console_opts.console_type()?;
     let mut subscribers = vec![];
-    let expect_spans = cmd.should_expect_spans();
+    // The quiet console does not show "Waiting for daemon..." either.
+    let expect_spans = cmd.should_expect_spans() && !matches!(console_type, ConsoleType::Quiet);
 
     // Need this to get information from one subscriber (event_log)
     // and log it in another (invocation_recorder)
@@ -52,7 +57,7 @@ fn default_subscribers<'a, T: StreamingCommand>(
 
     subscribers.push(get_console_with_root(
         ctx.trace_id.dupe(),
-        console_opts.console_type,
+        console_type,
         ctx.verbosity,
         expect_spans,
         None,
@@ -64,8 +69,10 @@ fn default_subscribers<'a, T: StreamingCommand>(
     {
         subscribers.push(event_log)
     }
-    if let Some(re_log) = try_get_re_log_subscriber(ctx)? {
-        subscribers.push(re_log)
+    if !matches!(console_type, ConsoleType::Quiet) {
+        if let Some(re_log) = try_get_re_log_subscriber(ctx)? {
+            subscribers.push(re_log)
+        }
     }
     if let Some(build_id_writer) = try_get_build_id_writer(cmd.event_log_opts(), ctx)? {
         subscribers.push(build_id_writer)
@@ -73,6 +80,9 @@ fn default_subscribers<'a, T: StreamingCommand>(
     if let Some(build_graph_stats) = try_get_build_graph_stats(cmd, ctx)? {
         subscribers.push(build_graph_stats)
     }
+    if let Some(status_writer) = try_get_status_writer(cmd, ctx)? {
+        subscribers.push(status_writer)
+    }
     let recorder = try_get_invocation_recorder(
         ctx,
         cmd.event_log_opts(),
@@ -158,6 +168,13 @@ impl<T: StreamingCommand> BuckSubcommand for T {
     /// Handles all of the business of setting up a runtime, server, and subscribers.
     fn exec(self, matches: &clap::ArgMatches, ctx: ClientCommandContext<'_>) -> ExitResult {
         ctx.with_runtime(|mut ctx| async move {
+            if let Ok(paths) = ctx.paths() {
+                // Invocations that crashed leave their scratch dirs behind.
+                if let Err(e) = paths.remove_old_invocation_scratch_dirs() {
+                    tracing::debug!("Error removing old invocation scratch dirs: {:#}", e);
+                }
+            }
+
             let work = async {
                 let constraints = if T::existing_only() {
                     BuckdConnectConstraints::ExistingOnly
@@ -165,6 +182,7 @@ impl<T: StreamingCommand> BuckSubcommand for T {
                     let mut req =
                         DaemonConstraintsRequest::new(ctx.immediate_config, T::trace_io(&self))?;
                     ctx.restarter.apply_to_constraints(&mut req);
+                    req.no_auto_restart = ctx.no_auto_restart;
                     BuckdConnectConstraints::Constraints(req)
                 };
 
@@ -201,9 +219,15 @@ impl<T: StreamingCommand> BuckSubcommand for T {
                 command_result
             };
 
-            with_simple_sigint_handler(work)
-                .await
-                .unwrap_or_else(|| ExitResult::status(ExitCode::SignalInterrupt))
+            match with_simple_sigint_handler(work).await {
+                Some(res) => res,
+                None => {
+                    // Dropping `work` can leave a lot of clean-up behind (e.g. tearing down remote
+                    // connections). Let users skip it by hitting ctrl+c again.
+                    spawn_hard_exit_on_interrupt();
+                    ExitResult::status(ExitCode::SignalInterrupt)
+                }
+            }
         })
     }
 }
@@ -17,6 +17,7 @@ pub(crate) mod observer;
 pub mod re_log;
 pub mod recorder;
 pub(crate) mod simpleconsole;
+pub(crate) mod status_writer;
 pub mod stdout_stderr_forwarder;
 pub mod subscriber;
 pub mod subscriber_unpack;
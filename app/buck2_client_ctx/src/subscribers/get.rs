@@ -10,6 +10,7 @@
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 
+use anyhow::Context;
 use buck2_event_observer::event_observer::NoopEventObserverExtra;
 use buck2_event_observer::verbosity::Verbosity;
 use buck2_wrapper_common::invocation_id::TraceId;
@@ -25,6 +26,7 @@ use crate::subscribers::errorconsole::ErrorConsole;
 use crate::subscribers::event_log::EventLog;
 use crate::subscribers::re_log::ReLog;
 use crate::subscribers::simpleconsole::SimpleConsole;
+use crate::subscribers::status_writer::StatusWriter;
 use crate::subscribers::subscriber::EventSubscriber;
 use crate::subscribers::subscriber_unpack::UnpackingEventSubscriberAsEventSubscriber;
 use crate::subscribers::superconsole::StatefulSuperConsole;
@@ -82,9 +84,9 @@ pub fn get_console_with_root(
                 ))),
             }
         }
-        ConsoleType::None => Ok(Box::new(UnpackingEventSubscriberAsEventSubscriber(
-            ErrorConsole,
-        ))),
+        ConsoleType::None | ConsoleType::Quiet => Ok(Box::new(
+            UnpackingEventSubscriberAsEventSubscriber(ErrorConsole),
+        )),
     }
 }
 
@@ -143,6 +145,52 @@ pub(crate) fn try_get_build_id_writer<'a>(
     }
 }
 
+#[derive(Debug, buck2_error::Error)]
+#[buck2(input)]
+enum StatusFdError {
+    #[error("`--status-fd` is only supported on unix")]
+    Unsupported,
+    #[error("`--status-fd` must be a file descriptor other than stdin, stdout or stderr, got {0}")]
+    InvalidFd(i32),
+}
+
+pub(crate) fn try_get_status_writer<'a, T: StreamingCommand>(
+    cmd: &T,
+    ctx: &ClientCommandContext<'a>,
+) -> anyhow::Result<Option<Box<dyn EventSubscriber + 'a>>> {
+    let opts = cmd.event_log_opts();
+    let file = if let Some(path) = opts.status_file.as_ref() {
+        let path = path.resolve(&ctx.working_dir);
+        std::fs::File::create(&path)
+            .with_context(|| format!("Error creating status file `{}`", path.display()))?
+    } else if let Some(fd) = opts.status_fd {
+        #[cfg(unix)]
+        {
+            use std::os::fd::BorrowedFd;
+
+            if fd <= 2 {
+                return Err(StatusFdError::InvalidFd(fd).into());
+            }
+            // Write to a duplicate, so the fd the user handed us is left open and never closed
+            // twice, whatever else in the process owns it.
+            // SAFETY: the fd is only borrowed for the duration of the `dup`, which fails cleanly
+            // if it isn't open.
+            let fd = unsafe { BorrowedFd::borrow_raw(fd) }
+                .try_clone_to_owned()
+                .with_context(|| format!("Error duplicating `--status-fd` {}", fd))?;
+            std::fs::File::from(fd)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = fd;
+            return Err(StatusFdError::Unsupported.into());
+        }
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(Box::new(StatusWriter::new(file, T::COMMAND_NAME))))
+}
+
 pub(crate) fn try_get_build_graph_stats<'a, T: StreamingCommand>(
     cmd: &T,
     ctx: &ClientCommandContext<'a>,
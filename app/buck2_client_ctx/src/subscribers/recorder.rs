@@ -97,8 +97,7 @@ pub(crate) struct InvocationRecorder<'a> {
     test_info: Option<String>,
     eligible_for_full_hybrid: bool,
     max_event_client_delay: Option<Duration>,
-    max_malloc_bytes_active: Option<u64>,
-    max_malloc_bytes_allocated: Option<u64>,
+    daemon_resource_stats: DaemonResourceStats,
     run_command_failure_count: u64,
     event_count: u64,
     time_to_first_action_execution: Option<Duration>,
@@ -144,6 +143,44 @@ pub(crate) struct InvocationRecorder<'a> {
     server_stderr: String,
     target_rule_type_names: Vec<String>,
     new_configs_used: bool,
+    event_stream_integrity_violations: Vec<buck2_data::EventStreamIntegrityViolation>,
+}
+
+/// Daemon resource usage aggregated over the snapshots of an invocation. Snapshots are not
+/// guaranteed to arrive in the order they were taken, so none of this depends on the order they
+/// are added in.
+#[derive(Default, Debug, PartialEq, Eq)]
+struct DaemonResourceStats {
+    peak_rss_bytes: Option<u64>,
+    max_malloc_bytes_active: Option<u64>,
+    max_malloc_bytes_allocated: Option<u64>,
+    /// Page fault counts are cumulative, so the lowest is from the earliest snapshot.
+    min_major_page_faults: Option<u64>,
+    max_major_page_faults: Option<u64>,
+}
+
+impl DaemonResourceStats {
+    fn add(&mut self, snapshot: &buck2_data::Snapshot) {
+        self.peak_rss_bytes = cmp::max(self.peak_rss_bytes, snapshot.buck2_rss);
+        self.max_malloc_bytes_active =
+            cmp::max(self.max_malloc_bytes_active, snapshot.malloc_bytes_active);
+        self.max_malloc_bytes_allocated = cmp::max(
+            self.max_malloc_bytes_allocated,
+            snapshot.malloc_bytes_allocated,
+        );
+        if let Some(faults) = snapshot.buck2_major_page_faults {
+            self.min_major_page_faults = Some(cmp::min(
+                self.min_major_page_faults.unwrap_or(faults),
+                faults,
+            ));
+            self.max_major_page_faults = cmp::max(self.max_major_page_faults, Some(faults));
+        }
+    }
+
+    /// Major page faults between the earliest and the latest snapshot.
+    fn major_page_faults(&self) -> Option<u64> {
+        Some(self.max_major_page_faults? - self.min_major_page_faults?)
+    }
 }
 
 impl<'a> InvocationRecorder<'a> {
@@ -196,8 +233,7 @@ impl<'a> InvocationRecorder<'a> {
             test_info: None,
             eligible_for_full_hybrid: false,
             max_event_client_delay: None,
-            max_malloc_bytes_active: None,
-            max_malloc_bytes_allocated: None,
+            daemon_resource_stats: DaemonResourceStats::default(),
             run_command_failure_count: 0,
             event_count: 0,
             time_to_first_action_execution: None,
@@ -241,6 +277,7 @@ impl<'a> InvocationRecorder<'a> {
             server_stderr: String::new(),
             target_rule_type_names: Vec::new(),
             new_configs_used: false,
+            event_stream_integrity_violations: Vec::new(),
         }
     }
 
@@ -357,6 +394,12 @@ impl<'a> InvocationRecorder<'a> {
         let mut metadata = Self::default_metadata();
         metadata.strings.extend(std::mem::take(&mut self.metadata));
 
+        let resource_usage = self
+            .command_end
+            .as_ref()
+            .and_then(|command| command.resource_usage.clone())
+            .unwrap_or_default();
+
         let record = buck2_data::InvocationRecord {
             command_name: Some(self.command_name.to_owned()),
             command_end: self.command_end.take(),
@@ -395,8 +438,8 @@ impl<'a> InvocationRecorder<'a> {
             max_event_client_delay_ms: self
                 .max_event_client_delay
                 .and_then(|d| u64::try_from(d.as_millis()).ok()),
-            max_malloc_bytes_active: self.max_malloc_bytes_active.take(),
-            max_malloc_bytes_allocated: self.max_malloc_bytes_allocated.take(),
+            max_malloc_bytes_active: self.daemon_resource_stats.max_malloc_bytes_active,
+            max_malloc_bytes_allocated: self.daemon_resource_stats.max_malloc_bytes_allocated,
             run_command_failure_count: Some(self.run_command_failure_count),
             event_count: Some(self.event_count),
             time_to_first_action_execution_ms: self
@@ -467,6 +510,19 @@ impl<'a> InvocationRecorder<'a> {
             best_error_tag: best_error_tag.map(|t| t.to_owned()),
             target_rule_type_names: std::mem::take(&mut self.target_rule_type_names),
             new_configs_used: Some(self.new_configs_used),
+            event_stream_integrity_violations: std::mem::take(
+                &mut self.event_stream_integrity_violations,
+            ),
+            peak_rss_bytes: self.daemon_resource_stats.peak_rss_bytes,
+            major_page_faults: self.daemon_resource_stats.major_page_faults(),
+            command_user_cpu_us: resource_usage.user_cpu_us,
+            command_system_cpu_us: resource_usage.system_cpu_us,
+            command_io_read_bytes: resource_usage.io_read_bytes,
+            command_io_write_bytes: resource_usage.io_write_bytes,
+            command_concurrency_overlap_fraction: resource_usage
+                .wall_time
+                .is_some()
+                .then_some(resource_usage.concurrency_overlap_fraction),
         };
 
         let event = BuckEvent::new(
@@ -851,12 +907,7 @@ impl<'a> InvocationRecorder<'a> {
         update: &buck2_data::Snapshot,
         _event: &BuckEvent,
     ) -> anyhow::Result<()> {
-        self.max_malloc_bytes_active =
-            cmp::max(self.max_malloc_bytes_active, update.malloc_bytes_active);
-        self.max_malloc_bytes_allocated = cmp::max(
-            self.max_malloc_bytes_allocated,
-            update.malloc_bytes_allocated,
-        );
+        self.daemon_resource_stats.add(update);
         if self.first_snapshot.is_none() {
             self.first_snapshot = Some(update.clone());
         } else {
@@ -1093,6 +1144,11 @@ impl<'a> InvocationRecorder<'a> {
                         self.new_configs_used = conf.new_configs_used;
                         Ok(())
                     }
+                    buck2_data::instant_event::Data::EventStreamIntegrityViolation(violation) => {
+                        self.event_stream_integrity_violations
+                            .push(violation.clone());
+                        Ok(())
+                    }
                     _ => Ok(()),
                 }
             }
@@ -1330,7 +1386,14 @@ fn truncate_stderr(stderr: &str) -> &str {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    use buck2_events::BuckEvent;
+    use buck2_wrapper_common::invocation_id::TraceId;
+
     use crate::subscribers::recorder::truncate_stderr;
+    use crate::subscribers::recorder::DaemonResourceStats;
 
     #[test]
     fn test_truncate_stderr() {
@@ -1343,4 +1406,75 @@ mod tests {
         let truncated = truncate_stderr(&stderr);
         assert_eq!(truncated.len(), 19_999);
     }
+
+    fn snapshot_event(
+        seconds: u64,
+        rss: Option<u64>,
+        allocated: Option<u64>,
+        major_page_faults: Option<u64>,
+    ) -> BuckEvent {
+        BuckEvent::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(seconds),
+            TraceId::new(),
+            None,
+            None,
+            buck2_data::InstantEvent {
+                data: Some(
+                    buck2_data::Snapshot {
+                        buck2_rss: rss,
+                        malloc_bytes_allocated: allocated,
+                        buck2_major_page_faults: major_page_faults,
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+            }
+            .into(),
+        )
+    }
+
+    fn aggregate(events: &[BuckEvent]) -> DaemonResourceStats {
+        let mut stats = DaemonResourceStats::default();
+        for event in events {
+            if let buck2_data::buck_event::Data::Instant(buck2_data::InstantEvent {
+                data: Some(buck2_data::instant_event::Data::Snapshot(snapshot)),
+            }) = event.data()
+            {
+                stats.add(snapshot);
+            }
+        }
+        stats
+    }
+
+    #[test]
+    fn test_daemon_resource_stats() {
+        // Out of order: the snapshot taken at 2s arrives last.
+        let events = [
+            snapshot_event(1, Some(100), Some(10), Some(5)),
+            snapshot_event(3, Some(300), Some(20), Some(12)),
+            snapshot_event(4, None, None, None),
+            snapshot_event(2, Some(400), Some(15), Some(8)),
+        ];
+        let stats = aggregate(&events);
+        assert_eq!(Some(400), stats.peak_rss_bytes);
+        assert_eq!(Some(20), stats.max_malloc_bytes_allocated);
+        assert_eq!(None, stats.max_malloc_bytes_active);
+        assert_eq!(Some(7), stats.major_page_faults());
+
+        let mut reversed = events;
+        reversed.reverse();
+        assert_eq!(stats, aggregate(&reversed));
+    }
+
+    #[test]
+    fn test_daemon_resource_stats_without_snapshots() {
+        let stats = aggregate(&[]);
+        assert_eq!(DaemonResourceStats::default(), stats);
+        assert_eq!(None, stats.major_page_faults());
+
+        // With a single snapshot there is nothing to take a delta against.
+        let stats = aggregate(&[snapshot_event(1, Some(100), None, Some(5))]);
+        assert_eq!(Some(100), stats.peak_rss_bytes);
+        assert_eq!(Some(0), stats.major_page_faults());
+    }
 }
@@ -489,49 +489,55 @@ where
         if self.verbosity.print_status() && self.last_print_time.elapsed() > KEEPALIVE_TIME_LIMIT {
             let mut show_stats = self.expect_spans;
 
-            let mut roots = self.observer().spans().iter_roots();
-            let sample_event = roots.next();
-            match sample_event {
-                Some(sample_event) => {
-                    let child = match sample_event.children().next() {
-                        Some(c) => Cow::Owned(format!(
-                            " [{}]",
+            if let Some(waiting) = self.observer().concurrent_command().render() {
+                // We are blocked on another command, so our own spans don't say much.
+                echo!("{}", waiting)?;
+                show_stats = self.verbosity.always_print_stats_in_status();
+            } else {
+                let mut roots = self.observer().spans().iter_roots();
+                let sample_event = roots.next();
+                match sample_event {
+                    Some(sample_event) => {
+                        let child = match sample_event.children().next() {
+                            Some(c) => Cow::Owned(format!(
+                                " [{}]",
+                                display::display_event(
+                                    &c.info().event,
+                                    TargetDisplayOptions::for_log()
+                                )?
+                            )),
+                            None => Cow::Borrowed(""),
+                        };
+
+                        let remaining = match roots.len() {
+                            0 => String::new(),
+                            x => format!(", and {x} other actions"),
+                        };
+                        echo!(
+                            "Waiting on {}{}{}",
                             display::display_event(
-                                &c.info().event,
+                                &sample_event.info().event,
                                 TargetDisplayOptions::for_log()
-                            )?
-                        )),
-                        None => Cow::Borrowed(""),
-                    };
-
-                    let remaining = match roots.len() {
-                        0 => String::new(),
-                        x => format!(", and {x} other actions"),
-                    };
-                    echo!(
-                        "Waiting on {}{}{}",
-                        display::display_event(
-                            &sample_event.info().event,
-                            TargetDisplayOptions::for_log()
-                        )?,
-                        child,
-                        remaining
-                    )?;
-
-                    show_stats = self.verbosity.always_print_stats_in_status();
-                }
-                None => {
-                    if self.expect_spans {
-                        echo!(
-                            "Waiting on buck2 daemon {}...",
-                            self.observer.session_info().trace_id
+                            )?,
+                            child,
+                            remaining
                         )?;
+
+                        show_stats = self.verbosity.always_print_stats_in_status();
+                    }
+                    None => {
+                        if self.expect_spans {
+                            echo!(
+                                "Waiting on buck2 daemon {}...",
+                                self.observer.session_info().trace_id
+                            )?;
+                        }
                     }
                 }
+                // roots must be dropped here because it mutably borrows `self`
+                // and doesn't get dropped until the end of this scope otherwise.
+                std::mem::drop(roots);
             }
-            // roots must be dropped here because it mutably borrows `self`
-            // and doesn't get dropped until the end of this scope otherwise.
-            std::mem::drop(roots);
 
             if show_stats {
                 self.print_stats_while_waiting()?;
@@ -0,0 +1,365 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Machine-readable build status, written as newline-delimited JSON for `--status-file` and
+//! `--status-fd`.
+//!
+//! Unlike the event log, this only contains a few high-level events and its format is stable:
+//! every line carries the schema version in its `v` field.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use buck2_event_observer::display::display_action_identity;
+use buck2_event_observer::display::display_analysis_target;
+use buck2_event_observer::display::display_anon_target;
+use buck2_event_observer::display::display_configured_target_label;
+use buck2_event_observer::display::get_action_error_reason;
+use buck2_event_observer::display::sanitize_output_colors;
+use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_events::BuckEvent;
+use tokio::io::AsyncWriteExt;
+
+use crate::subscribers::subscriber::EventSubscriber;
+
+/// Version of the schema of [`StatusLine`]. Must be bumped on incompatible changes.
+pub const STATUS_VERSION: u32 = 1;
+
+/// Only the end of the stderr of failed actions is included, since that is usually where the
+/// error is.
+const MAX_STDERR_LINES: usize = 20;
+
+/// One line of the status stream.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StatusLine {
+    pub v: u32,
+    #[serde(flatten)]
+    pub event: StatusEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StatusEvent {
+    CommandStarted {
+        command: String,
+        trace_id: String,
+    },
+    /// Analysis of the target started.
+    TargetStarted {
+        target: String,
+    },
+    /// Analysis of the target finished.
+    TargetFinished {
+        target: String,
+    },
+    ActionFailed {
+        action: String,
+        reason: String,
+        /// The last lines of the stderr of the failed command, if any.
+        stderr: Option<String>,
+    },
+    CommandFinished {
+        success: bool,
+    },
+}
+
+/// Converts the events the status stream cares about, and skips everything else, including
+/// events that are missing fields we need.
+fn status_event(command: &str, event: &BuckEvent) -> Option<StatusEvent> {
+    use buck2_data::buck_event::Data;
+    use buck2_data::instant_event;
+    use buck2_data::span_end_event;
+    use buck2_data::span_start_event;
+
+    let opts = TargetDisplayOptions::for_log();
+    match event.data() {
+        Data::SpanStart(start) => match start.data.as_ref()? {
+            span_start_event::Data::Command(_) => Some(StatusEvent::CommandStarted {
+                command: command.to_owned(),
+                trace_id: event.event().trace_id.clone(),
+            }),
+            span_start_event::Data::Analysis(analysis) => Some(StatusEvent::TargetStarted {
+                target: display_analysis_target(analysis.target.as_ref()?, opts).ok()?,
+            }),
+            _ => None,
+        },
+        Data::SpanEnd(end) => match end.data.as_ref()? {
+            span_end_event::Data::Command(command) => Some(StatusEvent::CommandFinished {
+                success: command.is_success,
+            }),
+            span_end_event::Data::Analysis(analysis) => {
+                use buck2_data::analysis_end::Target;
+                let target = match analysis.target.as_ref()? {
+                    Target::StandardTarget(ctl) => display_configured_target_label(ctl, opts),
+                    Target::AnonTarget(anon) => display_anon_target(anon),
+                };
+                Some(StatusEvent::TargetFinished {
+                    target: target.ok()?,
+                })
+            }
+            _ => None,
+        },
+        Data::Instant(instant) => match instant.data.as_ref()? {
+            instant_event::Data::ActionError(error) => Some(StatusEvent::ActionFailed {
+                action: display_action_identity(error.key.as_ref(), error.name.as_ref(), opts)
+                    .ok()?,
+                reason: get_action_error_reason(error).ok()?,
+                stderr: error
+                    .last_command
+                    .as_ref()
+                    .and_then(|c| c.details.as_ref())
+                    .and_then(|d| stderr_snippet(&d.stderr)),
+            }),
+            _ => None,
+        },
+        Data::Record(_) => None,
+    }
+}
+
+fn stderr_snippet(stderr: &str) -> Option<String> {
+    if stderr.is_empty() {
+        return None;
+    }
+    let stderr = sanitize_output_colors(stderr.as_bytes());
+    let lines = stderr.lines().collect::<Vec<_>>();
+    Some(lines[lines.len().saturating_sub(MAX_STDERR_LINES)..].join("\n"))
+}
+
+/// Writes the [`StatusLine`]s for the events of a command to a file.
+pub(crate) struct StatusWriter {
+    file: tokio::fs::File,
+    command: &'static str,
+}
+
+impl StatusWriter {
+    pub(crate) fn new(file: std::fs::File, command: &'static str) -> Self {
+        Self {
+            file: tokio::fs::File::from_std(file),
+            command,
+        }
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for StatusWriter {
+    async fn handle_events(&mut self, events: &[Arc<BuckEvent>]) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        for event in events {
+            if let Some(event) = status_event(self.command, event) {
+                let line = StatusLine {
+                    v: STATUS_VERSION,
+                    event,
+                };
+                serde_json::to_writer(&mut buf, &line)?;
+                buf.push(b'\n');
+            }
+        }
+        if !buf.is_empty() {
+            self.file
+                .write_all(&buf)
+                .await
+                .context("Error writing build status")?;
+            self.file
+                .flush()
+                .await
+                .context("Error writing build status")?;
+        }
+        Ok(())
+    }
+
+    async fn exit(&mut self) -> anyhow::Result<()> {
+        self.file
+            .flush()
+            .await
+            .context("Error writing build status")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use buck2_data::ActionName;
+    use buck2_data::ConfiguredTargetLabel;
+    use buck2_data::TargetLabel;
+    use buck2_wrapper_common::invocation_id::TraceId;
+
+    use super::*;
+
+    fn event(data: buck2_data::buck_event::Data) -> BuckEvent {
+        BuckEvent::new(SystemTime::now(), TraceId::new(), None, None, data)
+    }
+
+    fn target() -> ConfiguredTargetLabel {
+        ConfiguredTargetLabel {
+            label: Some(TargetLabel {
+                package: "root//foo".to_owned(),
+                name: "bar".to_owned(),
+            }),
+            configuration: Some(buck2_data::Configuration {
+                full_name: "cfg".to_owned(),
+            }),
+            execution_configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let lines = [
+            StatusEvent::CommandStarted {
+                command: "build".to_owned(),
+                trace_id: "trace".to_owned(),
+            },
+            StatusEvent::TargetStarted {
+                target: "root//foo:bar".to_owned(),
+            },
+            StatusEvent::TargetFinished {
+                target: "root//foo:bar".to_owned(),
+            },
+            StatusEvent::ActionFailed {
+                action: "root//foo:bar (cxx_compile bar.cpp)".to_owned(),
+                reason: "failed".to_owned(),
+                stderr: Some("error".to_owned()),
+            },
+            StatusEvent::CommandFinished { success: false },
+        ];
+        for event in lines {
+            let line = StatusLine {
+                v: STATUS_VERSION,
+                event,
+            };
+            let json = serde_json::to_string(&line)?;
+            assert_eq!(line, serde_json::from_str(&json)?);
+        }
+
+        // The format is stable.
+        assert_eq!(
+            r#"{"v":1,"event":"command_finished","success":true}"#,
+            serde_json::to_string(&StatusLine {
+                v: 1,
+                event: StatusEvent::CommandFinished { success: true },
+            })?
+        );
+        assert_eq!(
+            StatusLine {
+                v: 1,
+                event: StatusEvent::TargetStarted {
+                    target: "root//foo:bar".to_owned()
+                },
+            },
+            serde_json::from_str(r#"{"v":1,"event":"target_started","target":"root//foo:bar"}"#)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_status_event() {
+        let start = event(
+            buck2_data::SpanStartEvent {
+                data: Some(
+                    buck2_data::AnalysisStart {
+                        target: Some(buck2_data::analysis_start::Target::StandardTarget(target())),
+                        rule: "rule".to_owned(),
+                    }
+                    .into(),
+                ),
+            }
+            .into(),
+        );
+        assert_eq!(
+            Some(StatusEvent::TargetStarted {
+                target: "root//foo:bar (cfg)".to_owned()
+            }),
+            status_event("build", &start)
+        );
+
+        let error = event(
+            buck2_data::InstantEvent {
+                data: Some(
+                    buck2_data::ActionError {
+                        key: Some(buck2_data::ActionKey {
+                            owner: Some(buck2_data::action_key::Owner::TargetLabel(target())),
+                            ..Default::default()
+                        }),
+                        name: Some(ActionName {
+                            category: "cxx_compile".to_owned(),
+                            identifier: "bar.cpp".to_owned(),
+                        }),
+                        error: Some(buck2_data::action_error::Error::Unknown(
+                            "it broke".to_owned(),
+                        )),
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+            }
+            .into(),
+        );
+        assert_eq!(
+            Some(StatusEvent::ActionFailed {
+                action: "root//foo:bar (cfg) (cxx_compile bar.cpp)".to_owned(),
+                reason: "it broke".to_owned(),
+                stderr: None,
+            }),
+            status_event("build", &error)
+        );
+    }
+
+    #[test]
+    fn test_unexpected_events_are_skipped() {
+        let events = [
+            // Not something we report.
+            event(
+                buck2_data::InstantEvent {
+                    data: Some(
+                        buck2_data::ConsoleMessage {
+                            message: "hello".to_owned(),
+                        }
+                        .into(),
+                    ),
+                }
+                .into(),
+            ),
+            // Missing data.
+            event(buck2_data::SpanStartEvent { data: None }.into()),
+            // Missing the target.
+            event(
+                buck2_data::SpanEndEvent {
+                    data: Some(buck2_data::AnalysisEnd::default().into()),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            // Missing the action key.
+            event(
+                buck2_data::InstantEvent {
+                    data: Some(buck2_data::ActionError::default().into()),
+                }
+                .into(),
+            ),
+        ];
+        for event in events {
+            assert_eq!(None, status_event("build", &event));
+        }
+    }
+
+    #[test]
+    fn test_stderr_snippet() {
+        assert_eq!(None, stderr_snippet(""));
+        let stderr = (0..30)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let snippet = stderr_snippet(&stderr).unwrap();
+        assert!(snippet.starts_with("10\n"), "{}", snippet);
+        assert!(snippet.ends_with("\n29"), "{}", snippet);
+    }
+}
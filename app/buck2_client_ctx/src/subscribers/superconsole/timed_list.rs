@@ -122,6 +122,30 @@ impl<'c> TimedListBody<'c> {
         let display_platform = config.display_platform;
         let info = root.info();
 
+        if let Some(buck2_data::span_start_event::Data::DiceBlockConcurrentCommand(..)) = info
+            .event
+            .span_start_event()
+            .and_then(|span| span.data.as_ref())
+        {
+            // Show what the daemon is busy with rather than only the command we wait for.
+            if let Some(waiting) = self
+                .state
+                .simple_console
+                .observer()
+                .concurrent_command()
+                .render()
+            {
+                let elapsed = Instant::now() - info.start;
+                return Ok(vec![TimedRow::text(
+                    0,
+                    waiting,
+                    fmt_duration::fmt_duration(elapsed, time_speed.speed()),
+                    elapsed.mul_f64(time_speed.speed()),
+                    self.cutoffs,
+                )?]);
+            }
+        }
+
         let mut it = root.children();
 
         match it.next() {
@@ -225,7 +249,13 @@ impl<'s> Component for CountComponent<'s> {
                 let remaining = HumanizedCount::new(remaining);
                 let total = HumanizedCount::new(total);
 
-                let contents = if action_stats.log_stats() {
+                let mut contents = observer
+                    .two_snapshots()
+                    .target_progress()
+                    .and_then(analysis_summary)
+                    .unwrap_or_default();
+
+                contents += &if action_stats.log_stats() {
                     let mut actions_summary = format!(
                         "Remaining: {}/{}. Cache hits: {}%. ",
                         remaining,
@@ -264,6 +294,28 @@ impl<'s> Component for CountComponent<'s> {
     }
 }
 
+/// Summary of analysis progress, e.g. `Analyzed: 12.4K/~18.2K (31 packages pending). `.
+///
+/// The total is only an estimate (marked with `~`) while some packages have not finished
+/// evaluating.
+fn analysis_summary(progress: &buck2_data::TargetProgress) -> Option<String> {
+    if progress.targets_discovered == 0 {
+        return None;
+    }
+    let analyzed = HumanizedCount::new(progress.targets_analyzed);
+    let discovered = HumanizedCount::new(progress.targets_discovered);
+    Some(if progress.packages_pending > 0 {
+        format!(
+            "Analyzed: {}/~{} ({} packages pending). ",
+            analyzed,
+            discovered,
+            HumanizedCount::new(progress.packages_pending)
+        )
+    } else {
+        format!("Analyzed: {}/{}. ", analyzed, discovered)
+    })
+}
+
 /// Wrapper component for Header + Count
 struct TimedListHeader<'s> {
     state: &'s SuperConsoleState,
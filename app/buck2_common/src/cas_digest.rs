@@ -97,6 +97,18 @@ impl RawDigest {
         hex::decode_to_slice(data, &mut blake3).map_err(CasDigestParseError::InvalidBlake3)?;
         Ok(RawDigest::Blake3Keyed(blake3))
     }
+
+    pub fn parse_for_kind(
+        kind: DigestAlgorithmKind,
+        data: &[u8],
+    ) -> Result<Self, CasDigestParseError> {
+        match kind {
+            DigestAlgorithmKind::Sha1 => Self::parse_sha1(data),
+            DigestAlgorithmKind::Sha256 => Self::parse_sha256(data),
+            DigestAlgorithmKind::Blake3 => Self::parse_blake3(data),
+            DigestAlgorithmKind::Blake3Keyed => Self::parse_blake3_keyed(data),
+        }
+    }
 }
 
 impl fmt::Display for RawDigest {
@@ -171,7 +183,7 @@ pub enum DigestAlgorithm {
 }
 
 impl DigestAlgorithm {
-    fn kind(self) -> DigestAlgorithmKind {
+    pub fn kind(self) -> DigestAlgorithmKind {
         match self {
             Self::Sha1 => DigestAlgorithmKind::Sha1,
             Self::Sha256 => DigestAlgorithmKind::Sha256,
@@ -244,6 +256,14 @@ impl CasDigestConfig {
         )
     }
 
+    /// The enabled algorithm of the given kind, if any.
+    pub fn algorithm_for_kind(self, kind: DigestAlgorithmKind) -> Option<DigestAlgorithm> {
+        [self.inner.digest160, self.inner.digest256]
+            .into_iter()
+            .flatten()
+            .find(|algo| algo.kind() == kind)
+    }
+
     /// Access the config for source files. Note that there is no method to go back to the
     /// non-source config.
     pub fn source_files_config(self) -> Self {
@@ -564,20 +584,59 @@ impl<Kind: CasDigestKind> CasDigest<Kind> {
         TinyDigest { of: self }
     }
 
+    /// Display this digest as `ALGORITHM:HASH:SIZE`, which `parse_digest` accepts regardless of
+    /// how the config disambiguates hashes of the same length.
+    pub fn display_with_algorithm(&self) -> impl fmt::Display + '_ {
+        struct WithAlgorithm<'a>(&'a CasDigestData);
+
+        impl fmt::Display for WithAlgorithm<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}:{}", self.0.raw_digest().algorithm(), self.0)
+            }
+        }
+
+        WithAlgorithm(&self.data)
+    }
+
+    /// Parse a digest in the `HASH:SIZE` form, where the algorithm is inferred from the length of
+    /// the hash, or in the `ALGORITHM:HASH:SIZE` form produced by `display_with_algorithm`. In
+    /// both cases the algorithm must be enabled in `config`.
     pub fn parse_digest(
         s: &str,
         config: CasDigestConfig,
     ) -> Result<(Self, DigestAlgorithm), CasDigestParseError> {
-        let (digest, size) = s
-            .split_once(':')
-            .ok_or(CasDigestParseError::MissingSizeSeparator)?;
-
-        let (digest, algo) = CasDigest::<Kind>::parse_digest_without_size(digest, config)?;
-        let size = size.parse().map_err(CasDigestParseError::InvalidSize)?;
+        let (digest, size) = split_size(s)?;
+
+        let (digest, algo) = match split_algorithm(digest)? {
+            (Some(kind), digest) => {
+                let algo = config
+                    .algorithm_for_kind(kind)
+                    .ok_or(CasDigestParseError::AlgorithmNotEnabled(kind))?;
+                (RawDigest::parse_for_kind(kind, digest.as_bytes())?, algo)
+            }
+            (None, digest) => CasDigest::<Kind>::parse_digest_without_size(digest, config)?,
+        };
 
         Ok((Self::new(digest, size), algo))
     }
 
+    /// Like `parse_digest`, but an explicit algorithm prefix is accepted even if that algorithm is
+    /// not enabled in `config`. This is for digests produced elsewhere (e.g. in action results),
+    /// which we need to understand but will never compute ourselves.
+    pub fn parse_foreign_digest(
+        s: &str,
+        config: CasDigestConfig,
+    ) -> Result<(Self, DigestAlgorithmKind), CasDigestParseError> {
+        let (digest, size) = split_size(s)?;
+
+        let digest = match split_algorithm(digest)? {
+            (Some(kind), digest) => RawDigest::parse_for_kind(kind, digest.as_bytes())?,
+            (None, digest) => CasDigest::<Kind>::parse_digest_without_size(digest, config)?.0,
+        };
+
+        Ok((Self::new(digest, size), digest.algorithm()))
+    }
+
     pub fn parse_digest_without_size(
         data: &str,
         config: CasDigestConfig,
@@ -660,6 +719,31 @@ impl<Kind: CasDigestKind> CasDigest<Kind> {
     }
 }
 
+/// Split `HASH:SIZE` (possibly with an algorithm prefix) into the hash and the size.
+fn split_size(s: &str) -> Result<(&str, u64), CasDigestParseError> {
+    let (digest, size) = s
+        .rsplit_once(':')
+        .ok_or(CasDigestParseError::MissingSizeSeparator)?;
+    let size = size.parse().map_err(CasDigestParseError::InvalidSize)?;
+    Ok((digest, size))
+}
+
+/// Split the optional `ALGORITHM:` prefix off a hash.
+fn split_algorithm(
+    digest: &str,
+) -> Result<(Option<DigestAlgorithmKind>, &str), CasDigestParseError> {
+    match digest.split_once(':') {
+        Some((kind, digest)) => Ok((
+            Some(
+                kind.parse()
+                    .map_err(CasDigestParseError::InvalidAlgorithm)?,
+            ),
+            digest,
+        )),
+        None => Ok((None, digest)),
+    }
+}
+
 pub trait CasDigestKind: Sized + 'static {
     /// This needs to be a concrete implementation since we share the empty instance in a static
     /// but we can't have static generics.
@@ -691,6 +775,12 @@ pub enum CasDigestParseError {
 
     #[error("The size part of the CAS digest is invalid")]
     InvalidSize(#[source] std::num::ParseIntError),
+
+    #[error("The algorithm prefix of the CAS digest is invalid")]
+    InvalidAlgorithm(#[source] InvalidDigestAlgorithmKind),
+
+    #[error("The CAS digest uses `{}`, which is not enabled", .0)]
+    AlgorithmNotEnabled(DigestAlgorithmKind),
 }
 
 /// A digest to interact with RE. This, despite the name, can be a file or a directory. We track
@@ -1061,6 +1151,83 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_display_with_algorithm_roundtrip() {
+        let content = &b"foo"[..];
+
+        for (config, expected) in [
+            (
+                testing::sha1(),
+                "SHA1:0beec7b5ea3f0fdbc95d0dd47f3c5bc275da8a33:3",
+            ),
+            (
+                testing::sha256(),
+                "SHA256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae:3",
+            ),
+            (
+                testing::blake3(),
+                "BLAKE3:04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e9:3",
+            ),
+        ] {
+            let digest = CasDigest::<FileDigestKind>::from_content(content, config);
+            assert_eq!(digest.display_with_algorithm().to_string(), expected);
+
+            let (parsed, algo) =
+                CasDigest::<FileDigestKind>::parse_digest(expected, config).unwrap();
+            assert_eq!(parsed, digest);
+            assert_eq!(algo, config.preferred_algorithm());
+        }
+
+        let config = testing::blake3_keyed();
+        let digest = CasDigest::<FileDigestKind>::from_content(content, config);
+        let displayed = digest.display_with_algorithm().to_string();
+        assert!(displayed.starts_with("BLAKE3-KEYED:"), "{}", displayed);
+        let (parsed, algo) = CasDigest::<FileDigestKind>::parse_digest(&displayed, config).unwrap();
+        assert_eq!(parsed, digest);
+        assert_eq!(algo.kind(), DigestAlgorithmKind::Blake3Keyed);
+    }
+
+    #[test]
+    fn test_parse_sha256_unchanged() {
+        let s = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae:3";
+
+        let (digest, algo) =
+            CasDigest::<FileDigestKind>::parse_digest(s, testing::sha256()).unwrap();
+        assert_eq!(algo, DigestAlgorithm::Sha256);
+        assert_eq!(digest.raw_digest().algorithm(), DigestAlgorithmKind::Sha256);
+        assert_eq!(digest.to_string(), s);
+
+        let prefixed = format!("SHA256:{}", s);
+        assert_eq!(
+            CasDigest::<FileDigestKind>::parse_digest(&prefixed, testing::sha256())
+                .unwrap()
+                .0,
+            digest
+        );
+    }
+
+    #[test]
+    fn test_parse_digest_algorithm_not_enabled() {
+        let s = "BLAKE3-KEYED:04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e9:3";
+
+        assert!(matches!(
+            CasDigest::<FileDigestKind>::parse_digest(s, testing::sha1_sha256()),
+            Err(CasDigestParseError::AlgorithmNotEnabled(
+                DigestAlgorithmKind::Blake3Keyed
+            ))
+        ));
+
+        let (digest, kind) =
+            CasDigest::<FileDigestKind>::parse_foreign_digest(s, testing::sha1_sha256()).unwrap();
+        assert_eq!(kind, DigestAlgorithmKind::Blake3Keyed);
+        assert_eq!(digest.display_with_algorithm().to_string(), s);
+
+        assert!(matches!(
+            CasDigest::<FileDigestKind>::parse_foreign_digest("MD5:00:3", testing::sha1()),
+            Err(CasDigestParseError::InvalidAlgorithm(..))
+        ));
+    }
+
     #[test]
     fn test_digest_algorithm_kind_roundtrip() {
         for v in [
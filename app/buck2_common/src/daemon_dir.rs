@@ -7,8 +7,35 @@
  * of this source tree.
  */
 
+use std::fmt;
+use std::path::Path;
+
+use anyhow::Context;
+use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::paths::file_name::FileName;
+use buck2_wrapper_common::is_buck2::is_buck2_exe;
+use buck2_wrapper_common::is_buck2::WhoIsAsking;
+use buck2_wrapper_common::kill::process_exists;
+use buck2_wrapper_common::kill::process_info;
+use buck2_wrapper_common::kill::process_start_time;
+use buck2_wrapper_common::kill::ProcessInfo;
+use buck2_wrapper_common::pid::Pid;
+
+#[derive(Debug, buck2_error::Error)]
+pub enum DaemonDirOwnerError {
+    #[error("Daemon dir `{0}` is owned by another running buck2 daemon ({1})")]
+    InUse(AbsNormPathBuf, DaemonDirOwner),
+    #[error(
+        "Daemon dir `{path}` is owned by pid {pid}, which is not a buck2 daemon \
+        (command line: `{command_line}`). If that process is unrelated to buck2, delete `{path}`"
+    )]
+    NotADaemon {
+        path: AbsNormPathBuf,
+        pid: u32,
+        command_line: String,
+    },
+}
 
 /// `~/.buck/buckd/repo-path` directory.
 #[derive(Debug, Clone, derive_more::Display)]
@@ -37,4 +64,271 @@ impl DaemonDir {
     pub fn buckd_pid(&self) -> AbsNormPathBuf {
         self.path.join(FileName::new("buckd.pid").unwrap())
     }
+
+    /// Path to `buckd.owner` file, see [`DaemonDirOwner`].
+    pub fn buckd_owner(&self) -> AbsNormPathBuf {
+        self.path.join(FileName::new("buckd.owner").unwrap())
+    }
+
+    /// Returns the daemon that owns this directory, if any. A stale owner file, left behind by a
+    /// daemon that is no longer running, is deleted. Fails if the recorded pid belongs to a
+    /// process that is clearly not a buck2 daemon.
+    pub fn check_owner(&self) -> anyhow::Result<Option<DaemonDirOwner>> {
+        let path = self.buckd_owner();
+        let owner = match fs_util::read_to_string_if_exists(&path)? {
+            Some(owner) => owner,
+            None => return Ok(None),
+        };
+        // An owner file we can't parse was not written by a daemon we can check, so treat it as
+        // stale.
+        let owner = match serde_json::from_str::<DaemonDirOwner>(&owner) {
+            Ok(owner) => owner,
+            Err(_) => return self.remove_stale_owner(),
+        };
+        let process = Pid::from_u32(owner.pid).ok().and_then(process_info);
+        if !owner.is_alive(process.as_ref()) {
+            return self.remove_stale_owner();
+        }
+        if owner.pid != std::process::id() {
+            if let Some(process) = process {
+                if is_clearly_not_daemon(&process.command_line) {
+                    return Err(DaemonDirOwnerError::NotADaemon {
+                        path,
+                        pid: owner.pid,
+                        command_line: process.command_line.join(" "),
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(Some(owner))
+    }
+
+    fn remove_stale_owner(&self) -> anyhow::Result<Option<DaemonDirOwner>> {
+        let path = self.buckd_owner();
+        tracing::debug!("Removing stale daemon dir owner file `{}`", path);
+        fs_util::remove_file(&path)?;
+        Ok(None)
+    }
+
+    /// Records the current process as the owner of this directory. Called by the daemon on
+    /// startup. Fails if another running daemon owns the directory.
+    pub fn claim_ownership(&self, version: &str) -> anyhow::Result<()> {
+        if let Some(owner) = self.check_owner()? {
+            if owner.pid != std::process::id() {
+                return Err(DaemonDirOwnerError::InUse(self.path.clone(), owner).into());
+            }
+        }
+        let owner = DaemonDirOwner::for_current_process(version.to_owned());
+        fs_util::write(self.buckd_owner(), serde_json::to_string(&owner)?)
+            .with_context(|| format!("Error writing daemon dir owner file in `{}`", self))?;
+        Ok(())
+    }
+}
+
+/// Contents of the `buckd.owner` file: identifies the daemon that owns the daemon dir, so that
+/// a crashed daemon's files can be told apart from those of one that is still running.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DaemonDirOwner {
+    pub pid: u32,
+    /// In seconds. Distinguishes the owner from another process that reused its pid.
+    pub start_time: Option<u64>,
+    pub version: String,
+}
+
+impl fmt::Display for DaemonDirOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pid {}, version {}", self.pid, self.version)
+    }
+}
+
+impl DaemonDirOwner {
+    fn for_current_process(version: String) -> Self {
+        let pid = std::process::id();
+        let start_time = Pid::from_u32(pid)
+            .ok()
+            .and_then(process_start_time)
+            .map(|t| t.as_secs());
+        Self {
+            pid,
+            start_time,
+            version,
+        }
+    }
+
+    /// Whether the owner is still running. We only consider it dead if we can prove it: either
+    /// its pid is gone, or the process with its pid was started at a different time.
+    fn is_alive(&self, process: Option<&ProcessInfo>) -> bool {
+        let pid = match Pid::from_u32(self.pid) {
+            Ok(pid) => pid,
+            Err(_) => return false,
+        };
+        match process_exists(pid) {
+            Ok(false) => return false,
+            Ok(true) | Err(_) => {}
+        }
+        match (self.start_time, process.and_then(|p| p.start_time)) {
+            (Some(recorded), Some(actual)) => recorded == actual.as_secs(),
+            _ => true,
+        }
+    }
+}
+
+/// Whether `command_line` is clearly not that of a buck2 daemon. Often we can't tell: the command
+/// line is empty when it can't be read, and buck2 may be installed under another name. Those
+/// owners are treated as daemons, so that the usual kill and restart logic deals with them.
+fn is_clearly_not_daemon(command_line: &[String]) -> bool {
+    match command_line.split_first() {
+        Some((exe, args)) => {
+            !is_buck2_exe(Path::new(exe), WhoIsAsking::Buck2) && !args.iter().any(|a| a == "daemon")
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use buck2_core::fs::fs_util;
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+
+    use crate::daemon_dir::is_clearly_not_daemon;
+    use crate::daemon_dir::DaemonDir;
+    use crate::daemon_dir::DaemonDirOwner;
+    use crate::daemon_dir::DaemonDirOwnerError;
+
+    fn daemon_dir(tempdir: &tempfile::TempDir) -> DaemonDir {
+        DaemonDir {
+            path: AbsNormPathBuf::new(tempdir.path().to_owned()).unwrap(),
+        }
+    }
+
+    fn record_owner(daemon_dir: &DaemonDir, owner: &DaemonDirOwner) {
+        fs_util::write(
+            daemon_dir.buckd_owner(),
+            serde_json::to_string(owner).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_claim_ownership() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let daemon_dir = daemon_dir(&tempdir);
+        assert_eq!(None, daemon_dir.check_owner()?);
+
+        daemon_dir.claim_ownership("v1")?;
+        let owner = daemon_dir.check_owner()?.unwrap();
+        assert_eq!(DaemonDirOwner::for_current_process("v1".to_owned()), owner);
+
+        // Claiming again from the same process is fine.
+        daemon_dir.claim_ownership("v2")?;
+        assert_eq!("v2", daemon_dir.check_owner()?.unwrap().version);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_owner_is_removed() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let daemon_dir = daemon_dir(&tempdir);
+
+        let current = DaemonDirOwner::for_current_process("v1".to_owned());
+        let mut stale_owners = vec![
+            // Our pid, but reused by us after the owner died.
+            DaemonDirOwner {
+                start_time: Some(current.start_time.unwrap() + 1),
+                ..current.clone()
+            },
+        ];
+        if cfg!(unix) {
+            let mut child = std::process::Command::new("true").spawn()?;
+            let pid = child.id();
+            child.wait()?;
+            stale_owners.push(DaemonDirOwner {
+                pid,
+                start_time: None,
+                version: "v1".to_owned(),
+            });
+        }
+
+        for stale in stale_owners {
+            record_owner(&daemon_dir, &stale);
+            assert_eq!(None, daemon_dir.check_owner()?);
+            assert!(!fs_util::try_exists(daemon_dir.buckd_owner())?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_clearly_not_daemon() {
+        let command_line = |args: &[&str]| args.iter().map(|a| (*a).to_owned()).collect::<Vec<_>>();
+
+        assert!(is_clearly_not_daemon(&command_line(&["sh", "-c", "true"])));
+        // Unreadable.
+        assert!(!is_clearly_not_daemon(&[]));
+        assert!(!is_clearly_not_daemon(&command_line(&[
+            "/usr/bin/buck2",
+            "--isolation-dir",
+            "v2",
+            "daemon"
+        ])));
+        // Installed under another name.
+        assert!(!is_clearly_not_daemon(&command_line(&[
+            "/opt/bin/buck-next",
+            "--isolation-dir",
+            "v2",
+            "daemon"
+        ])));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_live_owner() -> anyhow::Result<()> {
+        use std::os::unix::process::CommandExt;
+
+        let tempdir = tempfile::tempdir()?;
+        let daemon_dir = daemon_dir(&tempdir);
+
+        let spawn = |arg0: &str, subcommand: &str| {
+            std::process::Command::new("sh")
+                .arg0(arg0)
+                .args(["-c", "sleep 100; true", "buck2", subcommand])
+                .spawn()
+        };
+        let owner = |child: &std::process::Child| DaemonDirOwner {
+            pid: child.id(),
+            start_time: None,
+            version: "v1".to_owned(),
+        };
+
+        let mut daemon = spawn("buck2", "daemon")?;
+        record_owner(&daemon_dir, &owner(&daemon));
+        let res = daemon_dir.check_owner();
+        let claim = daemon_dir.claim_ownership("v2");
+        daemon.kill()?;
+        daemon.wait()?;
+        assert_eq!(Some(owner(&daemon)), res?);
+        assert_matches!(
+            claim.unwrap_err().downcast_ref::<DaemonDirOwnerError>(),
+            Some(DaemonDirOwnerError::InUse(_, o)) => {
+                assert_eq!(*o, owner(&daemon));
+            }
+        );
+
+        let mut other = spawn("sh", "build")?;
+        record_owner(&daemon_dir, &owner(&other));
+        let res = daemon_dir.check_owner();
+        other.kill()?;
+        other.wait()?;
+        assert_matches!(
+            res.unwrap_err().downcast_ref::<DaemonDirOwnerError>(),
+            Some(DaemonDirOwnerError::NotADaemon { pid, command_line, .. }) => {
+                assert_eq!(*pid, other.id());
+                assert!(command_line.starts_with("sh -c"), "{}", command_line);
+            }
+        );
+        // The owner file is left alone for the user to look at.
+        assert!(fs_util::try_exists(daemon_dir.buckd_owner())?);
+        Ok(())
+    }
 }
@@ -28,9 +28,12 @@ use dice::LinearRecomputeDiceComputations;
 use dupe::Dupe;
 
 use crate::dice::file_ops::delegate::get_delegated_file_ops;
+use crate::external_symlink::HasExternalSymlinkPolicy;
+use crate::file_ops::DirEntryWithMetadata;
 use crate::file_ops::FileOps;
 use crate::file_ops::FileOpsError;
 use crate::file_ops::RawPathMetadata;
+use crate::file_ops::RawSymlink;
 use crate::file_ops::ReadDirOutput;
 use crate::legacy_configs::buildfiles::HasBuildfiles;
 
@@ -70,6 +73,20 @@ impl DiceFileComputations {
         .map_err(anyhow::Error::from)
     }
 
+    /// Filters out ignored paths. Equivalent to `read_dir` followed by `read_path_metadata` of
+    /// every entry, but reads everything at once when the underlying IO allows it.
+    pub async fn read_dir_with_metadata(
+        ctx: &mut DiceComputations<'_>,
+        path: CellPathRef<'_>,
+    ) -> anyhow::Result<Arc<[DirEntryWithMetadata]>> {
+        ctx.compute(&ReadDirWithMetadataKey {
+            path: path.to_owned(),
+            check_ignores: CheckIgnores::Yes,
+        })
+        .await?
+        .map_err(anyhow::Error::from)
+    }
+
     /// Does not check if the path is ignored
     ///
     /// TODO(cjhopman): error on ignored paths, maybe.
@@ -143,6 +160,7 @@ pub(crate) enum CheckIgnores {
 pub struct FileChangeTracker {
     files_to_dirty: HashSet<ReadFileKey>,
     dirs_to_dirty: HashSet<ReadDirKey>,
+    dirs_with_metadata_to_dirty: HashSet<ReadDirWithMetadataKey>,
     paths_to_dirty: HashSet<PathMetadataKey>,
 }
 
@@ -151,6 +169,7 @@ impl FileChangeTracker {
         Self {
             files_to_dirty: Default::default(),
             dirs_to_dirty: Default::default(),
+            dirs_with_metadata_to_dirty: Default::default(),
             paths_to_dirty: Default::default(),
         }
     }
@@ -158,18 +177,24 @@ impl FileChangeTracker {
     pub fn write_to_dice(self, ctx: &mut DiceTransactionUpdater) -> anyhow::Result<()> {
         ctx.changed(self.files_to_dirty)?;
         ctx.changed(self.dirs_to_dirty)?;
+        ctx.changed(self.dirs_with_metadata_to_dirty)?;
         ctx.changed(self.paths_to_dirty)?;
 
         Ok(())
     }
 
     fn file_contents_modify(&mut self, path: CellPath) {
+        if let Some(parent) = path.parent() {
+            // The metadata of the file is part of its parent's listing with metadata.
+            self.insert_dir_with_metadata_keys(parent.to_owned());
+        }
         self.files_to_dirty
             .insert(ReadFileKey(Arc::new(path.clone())));
         self.paths_to_dirty.insert(PathMetadataKey(path));
     }
 
     fn insert_dir_keys(&mut self, path: CellPath) {
+        self.insert_dir_with_metadata_keys(path.clone());
         self.dirs_to_dirty.insert(ReadDirKey {
             path: path.clone(),
             check_ignores: CheckIgnores::No,
@@ -180,6 +205,19 @@ impl FileChangeTracker {
         });
     }
 
+    fn insert_dir_with_metadata_keys(&mut self, path: CellPath) {
+        self.dirs_with_metadata_to_dirty
+            .insert(ReadDirWithMetadataKey {
+                path: path.clone(),
+                check_ignores: CheckIgnores::No,
+            });
+        self.dirs_with_metadata_to_dirty
+            .insert(ReadDirWithMetadataKey {
+                path,
+                check_ignores: CheckIgnores::Yes,
+            });
+    }
+
     pub fn file_added_or_removed(&mut self, path: CellPath) {
         let parent = path.parent();
 
@@ -283,6 +321,57 @@ impl Key for ReadDirKey {
     }
 }
 
+#[derive(Clone, Display, Debug, Eq, Hash, PartialEq, Allocative)]
+#[display(fmt = "{}", path)]
+struct ReadDirWithMetadataKey {
+    path: CellPath,
+    check_ignores: CheckIgnores,
+}
+
+#[async_trait]
+impl Key for ReadDirWithMetadataKey {
+    type Value = buck2_error::Result<Arc<[DirEntryWithMetadata]>>;
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        let entries = get_delegated_file_ops(ctx, self.path.cell(), self.check_ignores)
+            .await?
+            .read_dir_with_metadata(self.path.as_ref().path())
+            .await?;
+
+        // Apply the same checks and dependencies as `PathMetadataKey` does for each entry.
+        for e in &entries {
+            if let RawPathMetadata::Symlink {
+                at: ref path,
+                ref to,
+            } = e.metadata
+            {
+                if let RawSymlink::External(external) = to {
+                    if let Some(policy) = ctx.get_external_symlink_policy(path.cell()).await? {
+                        policy.check(path, external)?;
+                    }
+                }
+                ctx.compute(&ReadFileKey(path.dupe())).await?;
+            }
+        }
+
+        Ok(entries.into())
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        match (x, y) {
+            (Ok(x), Ok(y)) => x == y,
+            _ => false,
+        }
+    }
+
+    fn validity(x: &Self::Value) -> bool {
+        x.is_ok()
+    }
+}
+
 #[derive(Clone, Display, Debug, Eq, Hash, PartialEq, Allocative)]
 struct PathMetadataKey(CellPath);
 
@@ -302,8 +391,13 @@ impl Key for PathMetadataKey {
         match res {
             Some(RawPathMetadata::Symlink {
                 at: ref path,
-                to: _,
+                ref to,
             }) => {
+                if let RawSymlink::External(external) = to {
+                    if let Some(policy) = ctx.get_external_symlink_policy(path.cell()).await? {
+                        policy.check(path, external)?;
+                    }
+                }
                 ctx.compute(&ReadFileKey(path.dupe())).await?;
             }
             _ => (),
@@ -344,6 +438,13 @@ impl FileOps for DiceFileOps<'_, '_> {
         DiceFileComputations::read_path_metadata_if_exists(&mut self.0.get(), path).await
     }
 
+    async fn read_dir_with_metadata(
+        &self,
+        path: CellPathRef<'async_trait>,
+    ) -> anyhow::Result<Arc<[DirEntryWithMetadata]>> {
+        DiceFileComputations::read_dir_with_metadata(&mut self.0.get(), path).await
+    }
+
     async fn is_ignored(&self, path: CellPathRef<'async_trait>) -> anyhow::Result<bool> {
         DiceFileComputations::is_ignored(&mut self.0.get(), path).await
     }
@@ -34,6 +34,7 @@ use crate::dice::file_ops::delegate::keys::FileOpsKey;
 use crate::dice::file_ops::delegate::keys::FileOpsValue;
 use crate::dice::file_ops::CheckIgnores;
 use crate::external_cells::EXTERNAL_CELLS_IMPL;
+use crate::file_ops::DirEntryWithMetadata;
 use crate::file_ops::RawDirEntry;
 use crate::file_ops::RawPathMetadata;
 use crate::file_ops::ReadDirOutput;
@@ -83,6 +84,32 @@ pub trait FileOpsDelegate: Send + Sync {
         path: &'async_trait CellRelativePath,
     ) -> anyhow::Result<Option<RawPathMetadata>>;
 
+    /// Return the list of entries along with their metadata, sorted.
+    ///
+    /// The default implementation reads the metadata of each entry separately.
+    async fn read_dir_with_metadata(
+        &self,
+        path: &'async_trait CellRelativePath,
+    ) -> anyhow::Result<Vec<DirEntryWithMetadata>> {
+        let entries = self.read_dir(path).await?;
+        let entries = futures::future::try_join_all(
+            entries
+                .into_iter()
+                .filter_map(|e| FileNameBuf::try_from_or_get_back(e.file_name).ok())
+                .map(|file_name| async move {
+                    let metadata = self
+                        .read_path_metadata_if_exists(&path.join(&file_name))
+                        .await?;
+                    anyhow::Ok(metadata.map(|metadata| DirEntryWithMetadata {
+                        file_name,
+                        metadata,
+                    }))
+                }),
+        )
+        .await?;
+        Ok(entries.into_iter().flatten().collect())
+    }
+
     fn eq_token(&self) -> PartialEqAny;
 }
 
@@ -156,6 +183,26 @@ impl FileOpsDelegate for IoFileOpsDelegate {
             .transpose()
     }
 
+    async fn read_dir_with_metadata(
+        &self,
+        path: &'async_trait CellRelativePath,
+    ) -> anyhow::Result<Vec<DirEntryWithMetadata>> {
+        let project_path = self.resolve(path);
+        let mut entries = self
+            .io_provider()
+            .read_dir_with_metadata(project_path)
+            .await
+            .with_context(|| format!("Error listing dir `{}`", path))?;
+
+        // Make sure entries are deterministic, since read_dir isn't.
+        entries.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+        entries
+            .into_iter()
+            .map(|e| e.try_map(|path| Ok(Arc::new(self.get_cell_path(&path)?))))
+            .collect()
+    }
+
     fn eq_token(&self) -> PartialEqAny {
         PartialEqAny::new(self)
     }
@@ -250,6 +297,24 @@ impl FileOpsDelegateWithIgnores {
         }
     }
 
+    /// Whether the entry `file_name` of the directory `dir` is ignored.
+    fn is_entry_ignored(&self, dir: &CellRelativePath, file_name: &str) -> bool {
+        let mut cell_relative_path_buf;
+        let cell_relative_path: &str = if dir.is_empty() {
+            file_name
+        } else {
+            cell_relative_path_buf =
+                String::with_capacity(dir.as_str().len() + 1 + file_name.len());
+            cell_relative_path_buf.push_str(dir.as_str());
+            cell_relative_path_buf.push('/');
+            cell_relative_path_buf.push_str(file_name);
+            &cell_relative_path_buf
+        };
+
+        let cell_relative_path = UncheckedCellRelativePath::unchecked_new(cell_relative_path);
+        self.check_ignores(cell_relative_path).is_ignored()
+    }
+
     pub async fn read_file_if_exists(
         &self,
         path: &CellRelativePath,
@@ -266,24 +331,6 @@ impl FileOpsDelegateWithIgnores {
 
         let entries = self.delegate.read_dir(path).await?;
 
-        let is_ignored = |file_name: &str| {
-            let mut cell_relative_path_buf;
-            let cell_relative_path: &str = if path.is_empty() {
-                file_name
-            } else {
-                cell_relative_path_buf =
-                    String::with_capacity(path.as_str().len() + 1 + file_name.len());
-                cell_relative_path_buf.push_str(path.as_str());
-                cell_relative_path_buf.push('/');
-                cell_relative_path_buf.push_str(file_name);
-                &cell_relative_path_buf
-            };
-
-            let cell_relative_path = UncheckedCellRelativePath::unchecked_new(cell_relative_path);
-            let is_ignored = self.check_ignores(cell_relative_path).is_ignored();
-            anyhow::Ok(is_ignored)
-        };
-
         // Filter out any entries that are ignored.
         let mut included_entries = Vec::new();
         for e in entries {
@@ -292,7 +339,7 @@ impl FileOpsDelegateWithIgnores {
                 file_name,
             } = e;
 
-            if !is_ignored(&file_name)? {
+            if !self.is_entry_ignored(path, &file_name) {
                 let file_name = match FileNameBuf::try_from_or_get_back(file_name) {
                     Ok(file_name) => file_name,
                     Err(file_name) => {
@@ -322,6 +369,21 @@ impl FileOpsDelegateWithIgnores {
         self.delegate.read_path_metadata_if_exists(path).await
     }
 
+    /// Return the list of entries along with their metadata, sorted. Ignored entries are
+    /// filtered out, as in `read_dir`.
+    pub async fn read_dir_with_metadata(
+        &self,
+        path: &CellRelativePath,
+    ) -> anyhow::Result<Vec<DirEntryWithMetadata>> {
+        self.check_ignores(UncheckedCellRelativePath::new(path))
+            .into_result()
+            .with_context(|| format!("Error checking whether dir `{}` is ignored", path))?;
+
+        let mut entries = self.delegate.read_dir_with_metadata(path).await?;
+        entries.retain(|e| !self.is_entry_ignored(path, e.file_name.as_str()));
+        Ok(entries)
+    }
+
     pub async fn is_ignored(&self, path: &CellRelativePath) -> anyhow::Result<bool> {
         Ok(self
             .check_ignores(UncheckedCellRelativePath::new(path))
@@ -13,10 +13,19 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use allocative::Allocative;
+use async_trait::async_trait;
+use buck2_core::cells::name::CellName;
 use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
 use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+use buck2_futures::cancellation::CancellationContext;
+use derive_more::Display;
+use dice::DiceComputations;
+use dice::Key;
 use dupe::Dupe;
 
+use crate::legacy_configs::dice::HasLegacyConfigs;
+use crate::legacy_configs::key::BuckconfigKeyRef;
+
 type Utf8Path = String;
 
 /// Represents a path containing a symlink that resolves to an external path.
@@ -119,3 +128,348 @@ impl ExternalSymlink {
         }
     }
 }
+
+#[derive(Debug, buck2_error::Error)]
+pub enum ExternalSymlinkPolicyError {
+    #[error(
+        "Symlink `{at}` points to `{target}`, which is outside of the repository and not under any \
+        prefix allowed by `project.external_symlink_allowlist` (nearest allowed prefix: {nearest})"
+    )]
+    #[buck2(input)]
+    NotAllowed {
+        at: String,
+        target: String,
+        nearest: String,
+    },
+    #[error("Prefixes in `project.external_symlink_allowlist` must be absolute paths, got `{0}`")]
+    #[buck2(input)]
+    PrefixNotAbsolute(String),
+}
+
+/// How external symlink targets are compared against allowed prefixes.
+#[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq, Allocative)]
+pub struct ExternalSymlinkPathMatching {
+    /// Whether paths differing only in case are different paths.
+    pub case_sensitive: bool,
+    /// Whether `\` is a path separator in addition to `/`.
+    pub backslash_separator: bool,
+}
+
+impl ExternalSymlinkPathMatching {
+    /// Matching rules of the filesystems commonly used on the current platform.
+    pub fn current_platform() -> Self {
+        Self {
+            case_sensitive: !cfg!(any(windows, target_os = "macos")),
+            backslash_separator: cfg!(windows),
+        }
+    }
+
+    /// Normalized components of `path`: repeated and trailing separators and `.` are dropped,
+    /// `..` is applied lexically, and case is folded if matching is case insensitive.
+    fn components(self, path: &str) -> Vec<String> {
+        let mut components = Vec::new();
+        for component in path.split(|c| c == '/' || (self.backslash_separator && c == '\\')) {
+            match component {
+                "" | "." => {}
+                ".." => {
+                    components.pop();
+                }
+                c if self.case_sensitive => components.push(c.to_owned()),
+                c => components.push(c.to_lowercase()),
+            }
+        }
+        components
+    }
+
+    /// Whether `path` is `prefix` itself or is inside of it. This compares whole components, so
+    /// `/usr/local/tool` is not a prefix of `/usr/local/toolchains`.
+    pub fn is_under(self, prefix: &str, path: &str) -> bool {
+        self.components(path).starts_with(&self.components(prefix))
+    }
+
+    /// How many leading components `a` and `b` have in common.
+    fn common_components(self, a: &str, b: &str) -> usize {
+        self.components(a)
+            .iter()
+            .zip(self.components(b).iter())
+            .take_while(|(a, b)| a == b)
+            .count()
+    }
+}
+
+/// Which external symlinks are allowed, as configured by `project.external_symlink_allowlist`.
+/// When that isn't set, all external symlinks are allowed.
+#[derive(Debug, PartialEq, Eq, Allocative)]
+pub struct ExternalSymlinkPolicy {
+    allowed_prefixes: Vec<String>,
+    matching: ExternalSymlinkPathMatching,
+}
+
+impl ExternalSymlinkPolicy {
+    pub fn new(allowed_prefixes: Vec<String>, matching: ExternalSymlinkPathMatching) -> Self {
+        Self {
+            allowed_prefixes,
+            matching,
+        }
+    }
+
+    /// Parse the comma-separated list of absolute prefixes from buckconfig.
+    pub fn from_buckconfig(value: &str) -> anyhow::Result<Self> {
+        let mut allowed_prefixes = Vec::new();
+        for prefix in value.split(',') {
+            let prefix = prefix.trim();
+            if prefix.is_empty() {
+                continue;
+            }
+            if !Path::new(prefix).is_absolute() {
+                return Err(
+                    ExternalSymlinkPolicyError::PrefixNotAbsolute(prefix.to_owned()).into(),
+                );
+            }
+            allowed_prefixes.push(prefix.to_owned());
+        }
+        Ok(Self::new(
+            allowed_prefixes,
+            ExternalSymlinkPathMatching::current_platform(),
+        ))
+    }
+
+    /// Check the external symlink found at `at`, returning an error listing the nearest allowed
+    /// prefix if it points outside of all of them.
+    pub fn check(
+        &self,
+        at: &dyn fmt::Display,
+        symlink: &ExternalSymlink,
+    ) -> Result<(), ExternalSymlinkPolicyError> {
+        let target = symlink.to_path_buf();
+        let target = target.to_string_lossy();
+        if self
+            .allowed_prefixes
+            .iter()
+            .any(|prefix| self.matching.is_under(prefix, &target))
+        {
+            return Ok(());
+        }
+
+        Err(ExternalSymlinkPolicyError::NotAllowed {
+            at: at.to_string(),
+            target: target.into_owned(),
+            nearest: match self.nearest_allowed_prefix(&target) {
+                Some(prefix) => format!("`{}`", prefix),
+                None => "none configured".to_owned(),
+            },
+        })
+    }
+
+    /// The allowed prefix sharing the most leading components with `path`, preferring the first
+    /// one configured on ties.
+    fn nearest_allowed_prefix(&self, path: &str) -> Option<&str> {
+        let mut nearest: Option<(&str, usize)> = None;
+        for prefix in &self.allowed_prefixes {
+            let common = self.matching.common_components(prefix, path);
+            if nearest.map_or(true, |(_, best)| common > best) {
+                nearest = Some((prefix, common));
+            }
+        }
+        nearest.map(|(prefix, _)| prefix)
+    }
+}
+
+#[derive(Clone, Dupe, Display, Debug, Eq, Hash, PartialEq, Allocative)]
+#[display(fmt = "{:?}", self)]
+struct ExternalSymlinkPolicyKey(CellName);
+
+#[async_trait]
+impl Key for ExternalSymlinkPolicyKey {
+    type Value = buck2_error::Result<Option<Arc<ExternalSymlinkPolicy>>>;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        let s = ctx
+            .get_legacy_config_property(
+                self.0,
+                BuckconfigKeyRef {
+                    section: "project",
+                    property: "external_symlink_allowlist",
+                },
+            )
+            .await?;
+        match s {
+            Some(s) => Ok(Some(Arc::new(ExternalSymlinkPolicy::from_buckconfig(&s)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn validity(x: &Self::Value) -> bool {
+        x.is_ok()
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        match (x, y) {
+            (Ok(x), Ok(y)) => x == y,
+            _ => false,
+        }
+    }
+}
+
+#[async_trait]
+pub trait HasExternalSymlinkPolicy {
+    /// The external symlink policy of the cell, or `None` if all external symlinks are allowed.
+    async fn get_external_symlink_policy(
+        &mut self,
+        cell: CellName,
+    ) -> buck2_error::Result<Option<Arc<ExternalSymlinkPolicy>>>;
+}
+
+#[async_trait]
+impl HasExternalSymlinkPolicy for DiceComputations<'_> {
+    async fn get_external_symlink_policy(
+        &mut self,
+        cell: CellName,
+    ) -> buck2_error::Result<Option<Arc<ExternalSymlinkPolicy>>> {
+        self.compute(&ExternalSymlinkPolicyKey(cell)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIX: ExternalSymlinkPathMatching = ExternalSymlinkPathMatching {
+        case_sensitive: true,
+        backslash_separator: false,
+    };
+
+    const WINDOWS: ExternalSymlinkPathMatching = ExternalSymlinkPathMatching {
+        case_sensitive: false,
+        backslash_separator: true,
+    };
+
+    fn policy(prefixes: &[&str], matching: ExternalSymlinkPathMatching) -> ExternalSymlinkPolicy {
+        ExternalSymlinkPolicy::new(prefixes.iter().map(|p| (*p).to_owned()).collect(), matching)
+    }
+
+    fn symlink(target: &str, remaining: Option<&str>) -> ExternalSymlink {
+        ExternalSymlink::new(
+            PathBuf::from(target),
+            remaining.map(|r| ForwardRelativePathBuf::new(r.to_owned()).unwrap()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_is_under() {
+        assert!(UNIX.is_under("/usr/local/toolchains", "/usr/local/toolchains"));
+        assert!(UNIX.is_under("/usr/local/toolchains", "/usr/local/toolchains/clang/bin"));
+        assert!(UNIX.is_under("/", "/usr"));
+        assert!(!UNIX.is_under("/usr/local/toolchains", "/usr/local"));
+        assert!(!UNIX.is_under("/usr/local/tool", "/usr/local/toolchains"));
+        assert!(!UNIX.is_under("/usr/local/toolchains", "/opt/toolchains"));
+    }
+
+    #[test]
+    fn test_is_under_normalizes() {
+        // Trailing and repeated separators.
+        assert!(UNIX.is_under("/usr/local/toolchains/", "/usr/local/toolchains/clang"));
+        assert!(UNIX.is_under("/usr/local/toolchains", "/usr//local/toolchains/clang/"));
+        // `.` and `..` components.
+        assert!(UNIX.is_under("/usr/./local/toolchains", "/usr/local/toolchains/./clang"));
+        assert!(UNIX.is_under("/usr/local/toolchains", "/usr/local/x/../toolchains/clang"));
+        assert!(!UNIX.is_under(
+            "/usr/local/toolchains",
+            "/usr/local/toolchains/../../../etc/passwd"
+        ));
+    }
+
+    #[test]
+    fn test_is_under_case_sensitivity() {
+        assert!(!UNIX.is_under("/usr/Local/Toolchains", "/usr/local/toolchains/clang"));
+        assert!(WINDOWS.is_under("C:\\Toolchains", "c:\\toolchains\\clang"));
+        assert!(WINDOWS.is_under("C:/Toolchains/", "c:\\TOOLCHAINS\\clang"));
+        assert!(!WINDOWS.is_under("C:\\Toolchains", "D:\\Toolchains\\clang"));
+    }
+
+    #[test]
+    fn test_backslash_is_not_a_separator_on_unix() {
+        assert!(!UNIX.is_under("/opt/a", "/opt/a\\b"));
+        assert!(UNIX.is_under("/opt/a\\b", "/opt/a\\b/c"));
+    }
+
+    #[test]
+    fn test_check_allowed() {
+        let policy = policy(&["/usr/local/toolchains", "/opt/sdk/"], UNIX);
+        assert!(
+            policy
+                .check(&"foo/cc", &symlink("/usr/local/toolchains/clang", None))
+                .is_ok()
+        );
+        assert!(
+            policy
+                .check(&"foo/sdk", &symlink("/opt/sdk", Some("include/a.h")))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_check_uses_remaining_path() {
+        let policy = policy(&["/usr/local/toolchains"], UNIX);
+        // The symlink itself points at `/usr/local`, but what's accessed through it is allowed.
+        assert!(
+            policy
+                .check(
+                    &"foo/local",
+                    &symlink("/usr/local", Some("toolchains/clang"))
+                )
+                .is_ok()
+        );
+        assert!(
+            policy
+                .check(&"foo/local", &symlink("/usr/local", Some("bin/clang")))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_check_not_allowed() {
+        let policy = policy(&["/opt/sdk", "/usr/local/toolchains", "/usr/share"], UNIX);
+        match policy.check(&"foo/bar", &symlink("/usr/local/bin/clang", None)) {
+            Err(ExternalSymlinkPolicyError::NotAllowed {
+                at,
+                target,
+                nearest,
+            }) => {
+                assert_eq!("foo/bar", at);
+                assert_eq!("/usr/local/bin/clang", target);
+                assert_eq!("`/usr/local/toolchains`", nearest);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_check_nothing_allowed() {
+        let policy = policy(&[], UNIX);
+        match policy.check(&"foo/bar", &symlink("/usr/local/bin/clang", None)) {
+            Err(ExternalSymlinkPolicyError::NotAllowed { nearest, .. }) => {
+                assert_eq!("none configured", nearest);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_buckconfig() {
+        let policy =
+            ExternalSymlinkPolicy::from_buckconfig(" /usr/local/toolchains ,, /opt/sdk/,").unwrap();
+        assert_eq!(
+            vec!["/usr/local/toolchains".to_owned(), "/opt/sdk/".to_owned()],
+            policy.allowed_prefixes
+        );
+
+        assert!(ExternalSymlinkPolicy::from_buckconfig("/opt/sdk,usr/local").is_err());
+    }
+}
@@ -104,6 +104,32 @@ pub struct ReadDirOutput {
     pub included: Arc<[SimpleDirEntry]>,
 }
 
+/// A directory entry along with the metadata of the path it names.
+#[derive(Clone, Eq, PartialEq, Debug, Allocative)]
+pub struct DirEntryWithMetadata<T = Arc<CellPath>> {
+    pub file_name: FileNameBuf,
+    pub metadata: RawPathMetadata<T>,
+}
+
+impl<T> DirEntryWithMetadata<T> {
+    pub fn map<O>(self, f: impl Fn(T) -> O) -> DirEntryWithMetadata<O> {
+        DirEntryWithMetadata {
+            file_name: self.file_name,
+            metadata: self.metadata.map(f),
+        }
+    }
+
+    pub fn try_map<O, E>(
+        self,
+        f: impl Fn(T) -> Result<O, E>,
+    ) -> Result<DirEntryWithMetadata<O>, E> {
+        Ok(DirEntryWithMetadata {
+            file_name: self.file_name,
+            metadata: self.metadata.try_map(f)?,
+        })
+    }
+}
+
 impl ReadDirOutput {
     /// Is the file name in the directory listing. Ignores files that were explicitly ignored.
     pub fn contains(&self, file_name: &FileName) -> bool {
@@ -349,6 +375,29 @@ pub trait FileOps: Send + Sync {
         path: CellPathRef<'async_trait>,
     ) -> anyhow::Result<Option<RawPathMetadata>>;
 
+    /// Return the entries of a directory along with their metadata, sorted. Ignored entries are
+    /// filtered out, as in `read_dir`.
+    ///
+    /// The default implementation reads the metadata of each entry separately.
+    async fn read_dir_with_metadata(
+        &self,
+        path: CellPathRef<'async_trait>,
+    ) -> anyhow::Result<Arc<[DirEntryWithMetadata]>> {
+        let entries = self.read_dir(path).await?.included;
+        let mut out = Vec::with_capacity(entries.len());
+        for e in &*entries {
+            let child = path.join(&e.file_name);
+            // The entry may have been removed since the directory was listed.
+            if let Some(metadata) = self.read_path_metadata_if_exists(child.as_ref()).await? {
+                out.push(DirEntryWithMetadata {
+                    file_name: e.file_name.clone(),
+                    metadata,
+                });
+            }
+        }
+        Ok(out.into())
+    }
+
     fn eq_token(&self) -> PartialEqAny;
 
     async fn buildfiles<'a>(&self, cell: CellName) -> anyhow::Result<Arc<[FileNameBuf]>>;
@@ -410,6 +459,7 @@ pub mod testing {
     use crate::file_ops::ReadDirOutput;
     use crate::file_ops::SimpleDirEntry;
     use crate::file_ops::TrackedFileDigest;
+    use crate::ignores::file_ignores::CellFileIgnores;
 
     enum TestFileOpsEntry {
         File(String /*data*/, FileMetadata),
@@ -508,8 +558,17 @@ pub mod testing {
         }
 
         pub fn mock_in_cell(&self, cell: CellName, builder: DiceBuilder) -> DiceBuilder {
+            self.mock_in_cell_with_ignores(cell, None, builder)
+        }
+
+        pub fn mock_in_cell_with_ignores(
+            &self,
+            cell: CellName,
+            ignores: Option<Arc<CellFileIgnores>>,
+            builder: DiceBuilder,
+        ) -> DiceBuilder {
             let data = Ok(FileOpsValue(FileOpsDelegateWithIgnores::new(
-                None,
+                ignores,
                 Arc::new(TestCellFileOps(
                     cell,
                     Self {
@@ -574,7 +633,7 @@ pub mod testing {
                         at: Arc::new(path.to_owned()),
                         to: RawSymlink::External(sym.dupe()),
                     }),
-                    _ => Err(anyhow::anyhow!("couldn't get metadata for {:?}", path)),
+                    TestFileOpsEntry::Directory(..) => Ok(RawPathMetadata::Directory),
                 }
                 .map(Some)
             })
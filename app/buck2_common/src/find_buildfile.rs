@@ -7,21 +7,171 @@
  * of this source tree.
  */
 
+use buck2_core::cells::cell_path::CellPath;
+use buck2_core::cells::cell_path::CellPathRef;
 use buck2_core::fs::paths::file_name::FileName;
 use buck2_core::fs::paths::file_name::FileNameBuf;
 
 use crate::file_ops::SimpleDirEntry;
 
+#[derive(Debug, buck2_error::Error)]
+enum FindBuildfileError {
+    #[error(
+        "Package directory `{dir}` contains more than one buildfile: `{first}` and `{second}`. \
+        Remove one of them, or configure `buildfile.name_v2` for the cell so that only one is used"
+    )]
+    #[buck2(input)]
+    Ambiguous {
+        dir: CellPath,
+        first: FileNameBuf,
+        second: FileNameBuf,
+    },
+}
+
+/// Whether one of the buildfiles is the `.v2` variant of the other. Having both is how cells
+/// migrate from buck1 (which reads `FOO`) to buck2 (which prefers `FOO.v2`), so it's not an
+/// ambiguity.
+fn is_v2_variant(a: &FileName, b: &FileName) -> bool {
+    let (a, b) = (a.as_str(), b.as_str());
+    a.strip_suffix(".v2") == Some(b) || b.strip_suffix(".v2") == Some(a)
+}
+
+/// Finds the buildfile of the directory `dir`, given its listing.
+///
+/// `buildfile_candidates` is the cell's list of buildfile names (see
+/// [`HasBuildfiles`](crate::legacy_configs::buildfiles::HasBuildfiles)), in priority order: the
+/// first one that exists is the buildfile. It is an error for the directory to contain any other
+/// candidate, unless it's just the `.v2` variant of the buildfile (or the other way around).
 pub fn find_buildfile<'a>(
+    dir: CellPathRef<'_>,
     buildfile_candidates: &'a [FileNameBuf],
     dir_listing: &[SimpleDirEntry],
-) -> Option<&'a FileName> {
-    for candidate in buildfile_candidates {
-        for entry in dir_listing {
-            if entry.file_name == *candidate {
-                return Some(candidate.as_ref());
-            }
+) -> buck2_error::Result<Option<&'a FileName>> {
+    let exists = |candidate: &FileName| dir_listing.iter().any(|e| e.file_name == *candidate);
+
+    let mut candidates = buildfile_candidates.iter().map(|c| c.as_ref());
+    let Some(buildfile) = candidates.by_ref().find(|c| exists(c)) else {
+        return Ok(None);
+    };
+    if let Some(other) = candidates.find(|c| exists(c) && !is_v2_variant(buildfile, c)) {
+        return Err(FindBuildfileError::Ambiguous {
+            dir: dir.to_owned(),
+            first: buildfile.to_owned(),
+            second: other.to_owned(),
         }
+        .into());
+    }
+    Ok(Some(buildfile))
+}
+
+/// Whether the directory contains any of the buildfile candidates, i.e. whether it is a package.
+///
+/// Unlike [`find_buildfile`], this doesn't check for ambiguity. That's for the listing of the
+/// package itself to report.
+pub fn has_buildfile(buildfile_candidates: &[FileNameBuf], dir_listing: &[SimpleDirEntry]) -> bool {
+    buildfile_candidates
+        .iter()
+        .any(|candidate| dir_listing.iter().any(|e| e.file_name == *candidate))
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::cells::cell_path::CellPathRef;
+    use buck2_core::fs::paths::file_name::FileName;
+    use buck2_core::fs::paths::file_name::FileNameBuf;
+
+    use crate::file_ops::FileType;
+    use crate::file_ops::SimpleDirEntry;
+    use crate::find_buildfile::find_buildfile;
+    use crate::find_buildfile::has_buildfile;
+
+    fn names(names: &[&str]) -> Vec<FileNameBuf> {
+        names
+            .iter()
+            .map(|n| FileNameBuf::unchecked_new(*n))
+            .collect()
+    }
+
+    fn listing(files: &[&str]) -> Vec<SimpleDirEntry> {
+        files
+            .iter()
+            .map(|f| SimpleDirEntry {
+                file_name: FileNameBuf::unchecked_new(*f),
+                file_type: FileType::File,
+            })
+            .collect()
+    }
+
+    fn find<'a>(
+        candidates: &'a [FileNameBuf],
+        files: &[&str],
+    ) -> buck2_error::Result<Option<&'a FileName>> {
+        find_buildfile(
+            CellPathRef::testing_new("root//foo"),
+            candidates,
+            &listing(files),
+        )
+    }
+
+    #[test]
+    fn test_priority_order() -> buck2_error::Result<()> {
+        let candidates = names(&["BUCK.v2", "BUCK"]);
+        assert_eq!(None, find(&candidates, &["foo.rs"])?);
+        assert_eq!(
+            Some(FileName::unchecked_new("BUCK")),
+            find(&candidates, &["BUCK", "foo.rs"])?
+        );
+        // The `.v2` variant wins when both exist.
+        assert_eq!(
+            Some(FileName::unchecked_new("BUCK.v2")),
+            find(&candidates, &["BUCK", "BUCK.v2"])?
+        );
+
+        // The cell's order is what matters, not the order of the listing.
+        let candidates = names(&["TARGETS.v2", "TARGETS", "BUCK.v2"]);
+        assert_eq!(
+            Some(FileName::unchecked_new("TARGETS.v2")),
+            find(&candidates, &["TARGETS", "TARGETS.v2"])?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        let candidates = names(&["TARGETS.v2", "TARGETS", "BUCK.v2", "BUCK"]);
+        let err = find(&candidates, &["BUCK", "TARGETS"]).unwrap_err();
+        let err = format!("{:#}", err);
+        assert!(err.contains("`root//foo`"), "{}", err);
+        assert!(err.contains("`TARGETS` and `BUCK`"), "{}", err);
+
+        let err = find(&candidates, &["BUCK.v2", "TARGETS", "TARGETS.v2"]).unwrap_err();
+        let err = format!("{:#}", err);
+        assert!(err.contains("`TARGETS.v2` and `BUCK.v2`"), "{}", err);
+    }
+
+    #[test]
+    fn test_has_buildfile() {
+        let candidates = names(&["TARGETS.v2", "TARGETS", "BUCK.v2", "BUCK"]);
+        assert!(!has_buildfile(&candidates, &listing(&["foo.rs"])));
+        assert!(has_buildfile(&candidates, &listing(&["BUCK", "foo.rs"])));
+        // Ambiguous directories are still packages.
+        assert!(has_buildfile(&candidates, &listing(&["BUCK", "TARGETS"])));
+    }
+
+    #[test]
+    fn test_only_new_name() -> buck2_error::Result<()> {
+        // A cell that has finished migrating, i.e. `buildfile.name_v2 = BUCK.v2`.
+        let candidates = names(&["BUCK.v2"]);
+        assert_eq!(
+            Some(FileName::unchecked_new("BUCK.v2")),
+            find(&candidates, &["BUCK.v2"])?
+        );
+        // Leftover buck1 buildfiles are neither packages nor ambiguous.
+        assert_eq!(None, find(&candidates, &["BUCK"])?);
+        assert_eq!(
+            Some(FileName::unchecked_new("BUCK.v2")),
+            find(&candidates, &["BUCK", "BUCK.v2"])?
+        );
+        Ok(())
     }
-    None
 }
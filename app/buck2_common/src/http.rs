@@ -7,9 +7,22 @@
  * of this source tree.
  */
 
+use std::sync::Arc;
+
+use buck2_events::dispatch::EventDispatcher;
+use buck2_http::DownloadProgress;
 use buck2_http::HttpClient;
+use buck2_http::HttpError;
 use dice::UserComputationData;
 use dupe::Dupe;
+use futures::StreamExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+use crate::cas_digest::CasDigest;
+use crate::cas_digest::CasDigestConfig;
+use crate::cas_digest::CasDigestKind;
+use crate::cas_digest::DigestAlgorithmKind;
 
 /// Dice implementations so we can pass along the HttpClient to various subsystems
 /// that need to use it (Materializer, RunActions, etc).
@@ -35,3 +48,233 @@ impl SetHttpClient for UserComputationData {
         self.data.set(client);
     }
 }
+
+/// Emits the progress of the downloads of an `HttpClient` as `HttpDownloadProgress` events.
+pub struct EventDispatcherDownloadProgress {
+    dispatcher: EventDispatcher,
+}
+
+impl EventDispatcherDownloadProgress {
+    pub fn new(dispatcher: EventDispatcher) -> Arc<Self> {
+        Arc::new(Self { dispatcher })
+    }
+}
+
+impl DownloadProgress for EventDispatcherDownloadProgress {
+    fn progress(&self, uri: &str, downloaded_bytes: u64, total_bytes: Option<u64>) {
+        self.dispatcher
+            .instant_event(buck2_data::HttpDownloadProgress {
+                url: uri.to_owned(),
+                downloaded_bytes,
+                total_bytes,
+            });
+    }
+}
+
+#[derive(Debug, buck2_error::Error)]
+pub enum FetchAndVerifyError {
+    #[error(transparent)]
+    Http(HttpError),
+    #[error("Cannot verify a {algorithm} digest, the digest config is `{config}`")]
+    UnsupportedAlgorithm {
+        algorithm: DigestAlgorithmKind,
+        config: CasDigestConfig,
+    },
+    #[error(
+        "Invalid digest for `{uri}`: expected `{expected}`, got `{got}` after downloading {downloaded_bytes} bytes"
+    )]
+    #[buck2(input)]
+    DigestMismatch {
+        uri: String,
+        expected: String,
+        got: String,
+        downloaded_bytes: u64,
+    },
+}
+
+impl From<HttpError> for FetchAndVerifyError {
+    fn from(e: HttpError) -> Self {
+        Self::Http(e)
+    }
+}
+
+/// Stream the body of `uri` into `writer` while computing its digest, and check that it is
+/// `expected`. The digest is computed with the algorithm of `expected`, which must be enabled in
+/// `config`.
+///
+/// On error, `writer` may have received part or all of the body, so callers writing to a file
+/// should delete it.
+pub async fn fetch_and_verify<Kind: CasDigestKind>(
+    client: &HttpClient,
+    uri: &str,
+    writer: &mut (dyn AsyncWrite + Unpin + Send),
+    expected: &CasDigest<Kind>,
+    config: CasDigestConfig,
+) -> Result<(), FetchAndVerifyError> {
+    let algorithm_kind = expected.raw_digest().algorithm();
+    let algorithm = [config.digest160(), config.digest256()]
+        .into_iter()
+        .flatten()
+        .find(|algorithm| algorithm.kind() == algorithm_kind)
+        .ok_or(FetchAndVerifyError::UnsupportedAlgorithm {
+            algorithm: algorithm_kind,
+            config,
+        })?;
+    let mut digester = CasDigest::<Kind>::digester_for_algorithm(algorithm);
+
+    let mut body = client.get(uri).await?.into_body();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|source| HttpError::ReadBody {
+            uri: uri.to_owned(),
+            received: digester.bytes_read(),
+            source,
+        })?;
+        writer
+            .write_all(&chunk)
+            .await
+            .map_err(|source| HttpError::WriteBody {
+                uri: uri.to_owned(),
+                source,
+            })?;
+        digester.update(&chunk);
+    }
+    writer
+        .flush()
+        .await
+        .map_err(|source| HttpError::WriteBody {
+            uri: uri.to_owned(),
+            source,
+        })?;
+
+    let digest = digester.finalize();
+    if &digest != expected {
+        return Err(FetchAndVerifyError::DigestMismatch {
+            uri: uri.to_owned(),
+            expected: expected.to_string(),
+            got: digest.to_string(),
+            downloaded_bytes: digest.size(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use buck2_events::dispatch::EventDispatcher;
+    use buck2_http::testing::MockFailure;
+    use buck2_http::testing::MockHttpClient;
+    use buck2_http::HttpError;
+    use buck2_wrapper_common::invocation_id::TraceId;
+
+    use crate::cas_digest::testing;
+    use crate::cas_digest::DigestAlgorithm;
+    use crate::file_ops::FileDigest;
+    use crate::http::fetch_and_verify;
+    use crate::http::EventDispatcherDownloadProgress;
+    use crate::http::FetchAndVerifyError;
+
+    #[tokio::test]
+    async fn test_progress_events() -> anyhow::Result<()> {
+        let body = vec![7; 10 * 1024 * 1024];
+        let mock = MockHttpClient::builder()
+            .expect_get("https://example.com/big", 200, &body)
+            .build();
+        let (mut source, sink) = buck2_events::create_source_sink_pair();
+        let client =
+            mock.client()
+                .with_download_progress(EventDispatcherDownloadProgress::new(
+                    EventDispatcher::new(TraceId::null(), sink),
+                ));
+
+        client
+            .get_to_writer("https://example.com/big", &mut Vec::new())
+            .await?;
+
+        let mut reports = Vec::new();
+        while let Some(event) = source.try_receive() {
+            if let buck2_data::buck_event::Data::Instant(instant) =
+                event.unpack_buck().unwrap().data()
+            {
+                if let Some(buck2_data::instant_event::Data::HttpDownloadProgress(progress)) =
+                    &instant.data
+                {
+                    reports.push(progress.clone());
+                }
+            }
+        }
+        assert!(!reports.is_empty());
+        let last = reports.last().unwrap();
+        assert_eq!("https://example.com/big", last.url);
+        assert_eq!(body.len() as u64, last.downloaded_bytes);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_and_verify() -> anyhow::Result<()> {
+        let mock = MockHttpClient::builder()
+            .expect_get("https://example.com/x", 200, b"foobar")
+            .fail_call(3, MockFailure::BrokenBody)
+            .build();
+        let client = mock.client();
+        let config = testing::sha1_sha256();
+
+        for algorithm in [DigestAlgorithm::Sha1, DigestAlgorithm::Sha256] {
+            let expected = FileDigest::from_content_for_algorithm(b"foobar", algorithm);
+            let mut out = Vec::new();
+            fetch_and_verify(
+                &client,
+                "https://example.com/x",
+                &mut out,
+                &expected,
+                config,
+            )
+            .await?;
+            assert_eq!(b"foobar", out.as_slice());
+        }
+
+        let expected = FileDigest::from_content_for_algorithm(b"foobaz", DigestAlgorithm::Sha1);
+        assert_matches!(
+            fetch_and_verify(
+                &client,
+                "https://example.com/x",
+                &mut Vec::new(),
+                &expected,
+                config
+            )
+            .await,
+            Err(FetchAndVerifyError::DigestMismatch {
+                downloaded_bytes: 6,
+                ..
+            })
+        );
+
+        // The 4th call has its body cut short.
+        assert_matches!(
+            fetch_and_verify(
+                &client,
+                "https://example.com/x",
+                &mut Vec::new(),
+                &expected,
+                config
+            )
+            .await,
+            Err(FetchAndVerifyError::Http(HttpError::ReadBody { .. }))
+        );
+
+        let expected = FileDigest::from_content_for_algorithm(b"foobar", DigestAlgorithm::Blake3);
+        assert_matches!(
+            fetch_and_verify(
+                &client,
+                "https://example.com/x",
+                &mut Vec::new(),
+                &expected,
+                config
+            )
+            .await,
+            Err(FetchAndVerifyError::UnsupportedAlgorithm { .. })
+        );
+        assert_eq!(4, mock.requests().len());
+        Ok(())
+    }
+}
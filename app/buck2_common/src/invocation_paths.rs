@@ -21,9 +21,13 @@ use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_wrapper_common::invocation_id::TraceId;
 
 use crate::daemon_dir::DaemonDir;
 use crate::invocation_roots::InvocationRoots;
+use crate::invocation_scratch_dir::remove_old_scratch_dirs;
+use crate::invocation_scratch_dir::InvocationScratchDir;
+use crate::invocation_scratch_dir::MAX_SCRATCH_DIR_AGE;
 
 #[derive(Clone, Allocative)]
 pub struct InvocationPaths {
@@ -99,6 +103,25 @@ impl InvocationPaths {
             .join(ForwardRelativePath::unchecked_new("tmp"))
     }
 
+    /// Directory containing the scratch dirs of all invocations.
+    pub fn invocation_scratch_root(&self) -> AbsNormPathBuf {
+        self.tmp_dir()
+            .join(ForwardRelativePath::unchecked_new("invocations"))
+    }
+
+    /// Scratch dir of the invocation `trace_id`, see [`InvocationScratchDir`].
+    pub fn invocation_scratch_dir(&self, trace_id: &TraceId) -> InvocationScratchDir {
+        InvocationScratchDir::new(
+            self.invocation_scratch_root()
+                .join(FileNameBuf::unchecked_new(trace_id.to_string())),
+        )
+    }
+
+    /// Removes scratch dirs left behind by invocations that did not clean up after themselves.
+    pub fn remove_old_invocation_scratch_dirs(&self) -> anyhow::Result<()> {
+        remove_old_scratch_dirs(&self.invocation_scratch_root(), MAX_SCRATCH_DIR_AGE)
+    }
+
     pub fn re_logs_dir(&self) -> AbsNormPathBuf {
         self.buck_out_path()
             .join(ForwardRelativePath::unchecked_new("re_logs"))
@@ -256,6 +279,15 @@ mod tests {
             "/my/project/buck-out/isolation/dice_dump"
         };
         assert_eq!(paths.dice_dump_dir().as_os_str(), OsStr::new(expected_path));
+        let expected_path = if cfg!(windows) {
+            "C:\\my\\project\\buck-out\\isolation\\tmp\\invocations"
+        } else {
+            "/my/project/buck-out/isolation/tmp/invocations"
+        };
+        assert_eq!(
+            paths.invocation_scratch_root().as_os_str(),
+            OsStr::new(expected_path)
+        );
 
         assert_eq!(
             paths.cache_dir(),
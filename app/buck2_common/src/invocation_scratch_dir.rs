@@ -0,0 +1,220 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Scratch directories scoped to a single invocation.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use buck2_util::cleanup_ctx::AsyncCleanupContext;
+use futures::FutureExt;
+
+/// Scratch dirs are normally removed when their invocation ends. Those left behind by invocations
+/// that crashed are removed once they are this old.
+pub const MAX_SCRATCH_DIR_AGE: Duration = Duration::from_secs(3 * 86400);
+
+/// A directory for the temporary files of one invocation, created on first use.
+///
+/// It is removed on drop, and also on async cleanup if [`register_cleanup`] was called, so that
+/// it is removed even if the invocation is interrupted while it is still referenced.
+///
+/// [`register_cleanup`]: InvocationScratchDir::register_cleanup
+pub struct InvocationScratchDir {
+    inner: Arc<ScratchDirInner>,
+}
+
+struct ScratchDirInner {
+    path: AbsNormPathBuf,
+    /// Whether the directory was created (and not removed since).
+    created: Mutex<bool>,
+}
+
+impl ScratchDirInner {
+    fn remove(&self) {
+        let mut created = self.created.lock().unwrap();
+        if *created {
+            if let Err(e) = fs_util::remove_all(&self.path) {
+                tracing::warn!(
+                    "Error removing invocation scratch dir `{}`: {:#}",
+                    self.path,
+                    e
+                );
+            }
+            *created = false;
+        }
+    }
+}
+
+impl InvocationScratchDir {
+    pub(crate) fn new(path: AbsNormPathBuf) -> Self {
+        Self {
+            inner: Arc::new(ScratchDirInner {
+                path,
+                created: Mutex::new(false),
+            }),
+        }
+    }
+
+    /// Path to the directory, which may not exist yet.
+    pub fn path(&self) -> &AbsNormPath {
+        &self.inner.path
+    }
+
+    /// The directory, created if needed.
+    pub fn dir(&self) -> anyhow::Result<&AbsNormPath> {
+        let mut created = self.inner.created.lock().unwrap();
+        if !*created {
+            fs_util::create_dir_all(&self.inner.path)?;
+            *created = true;
+        }
+        Ok(&self.inner.path)
+    }
+
+    /// Path to `path` within the directory, which is created if needed. Only the directory
+    /// itself is created, not the parents of `path`.
+    pub fn sub_path(&self, path: &ForwardRelativePath) -> anyhow::Result<AbsNormPathBuf> {
+        Ok(self.dir()?.join(path))
+    }
+
+    /// Also remove the directory when `ctx` runs its cleanup.
+    pub fn register_cleanup(&self, ctx: &AsyncCleanupContext<'_>) {
+        let inner = self.inner.clone();
+        ctx.register(
+            "removing invocation scratch dir",
+            async move { inner.remove() }.boxed(),
+        );
+    }
+}
+
+impl Drop for InvocationScratchDir {
+    fn drop(&mut self) {
+        self.inner.remove();
+    }
+}
+
+/// Removes the scratch dirs in `root` that were last modified more than `max_age` ago.
+pub fn remove_old_scratch_dirs(root: &AbsNormPath, max_age: Duration) -> anyhow::Result<()> {
+    let entries = match fs_util::read_dir_if_exists(root)? {
+        Some(entries) => entries,
+        None => return Ok(()),
+    };
+
+    let now = SystemTime::now();
+    for entry in entries {
+        let entry = entry?;
+        let timestamp = match entry.metadata().and_then(|m| m.modified()) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // Possible if removed concurrently.
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+            Ok(timestamp) => timestamp,
+        };
+        if now.duration_since(timestamp).unwrap_or_default() > max_age {
+            fs_util::remove_all(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use buck2_core::fs::fs_util;
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+    use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+    use buck2_util::cleanup_ctx::AsyncCleanupContextGuard;
+
+    use crate::invocation_scratch_dir::remove_old_scratch_dirs;
+    use crate::invocation_scratch_dir::InvocationScratchDir;
+
+    fn scratch_dir(tempdir: &tempfile::TempDir, name: &str) -> InvocationScratchDir {
+        InvocationScratchDir::new(
+            AbsNormPathBuf::new(tempdir.path().to_owned())
+                .unwrap()
+                .join(ForwardRelativePath::unchecked_new(name)),
+        )
+    }
+
+    #[test]
+    fn test_cleanup() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let runtime = tokio::runtime::Runtime::new()?;
+
+        let dir = scratch_dir(&tempdir, "trace");
+        // Nothing is created until it is used.
+        assert!(!fs_util::try_exists(dir.path())?);
+        let file = dir.sub_path(ForwardRelativePath::unchecked_new("file"))?;
+        fs_util::write(&file, "hello")?;
+
+        let guard = AsyncCleanupContextGuard::new(&runtime);
+        dir.register_cleanup(guard.ctx());
+        drop(guard);
+        assert!(!fs_util::try_exists(dir.path())?);
+
+        // Removing it again on drop is fine.
+        drop(dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_on_drop() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let dir = scratch_dir(&tempdir, "trace");
+        let path = dir.dir()?.to_buf();
+        fs_util::write(
+            path.join(ForwardRelativePath::unchecked_new("file")),
+            "hello",
+        )?;
+
+        drop(dir);
+        assert!(!fs_util::try_exists(&path)?);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_old_scratch_dirs() -> anyhow::Result<()> {
+        use std::time::SystemTime;
+
+        let tempdir = tempfile::tempdir()?;
+        let root = AbsNormPathBuf::new(tempdir.path().to_owned())?;
+        let day = Duration::from_secs(86400);
+
+        let dir = |name: &str, age: Duration| -> anyhow::Result<AbsNormPathBuf> {
+            let path = root.join(ForwardRelativePath::unchecked_new(name));
+            fs_util::create_dir_all(&path)?;
+            std::fs::File::open(&path)?.set_modified(SystemTime::now() - age)?;
+            Ok(path)
+        };
+        let fresh = dir("fresh", Duration::ZERO)?;
+        let recent = dir("recent", 2 * day)?;
+        let crashed = dir("crashed", 4 * day)?;
+
+        remove_old_scratch_dirs(&root, 3 * day)?;
+        assert!(fs_util::try_exists(&fresh)?);
+        assert!(fs_util::try_exists(&recent)?);
+        assert!(!fs_util::try_exists(&crashed)?);
+
+        // A missing root is fine.
+        remove_old_scratch_dirs(
+            &root.join(ForwardRelativePath::unchecked_new("missing")),
+            day,
+        )?;
+        Ok(())
+    }
+}
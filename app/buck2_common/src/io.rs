@@ -12,11 +12,13 @@ pub mod trace;
 
 use allocative::Allocative;
 use async_trait::async_trait;
+use buck2_core::fs::paths::file_name::FileNameBuf;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_error::BuckErrorContext;
 use buck2_error::ErrorTag;
 
+use crate::file_ops::DirEntryWithMetadata;
 use crate::file_ops::RawDirEntry;
 use crate::file_ops::RawPathMetadata;
 
@@ -35,6 +37,36 @@ pub trait IoProvider: Allocative + Send + Sync {
         path: ProjectRelativePathBuf,
     ) -> anyhow::Result<Option<RawPathMetadata<ProjectRelativePathBuf>>>;
 
+    /// List a directory and read the metadata of each of its entries, in no particular order.
+    /// Entries that are not valid file names, or that disappear between the listing and reading
+    /// their metadata, are omitted.
+    ///
+    /// The default implementation issues a separate metadata read per entry. Providers that can
+    /// do this in fewer round trips should override it.
+    async fn read_dir_with_metadata_impl(
+        &self,
+        path: ProjectRelativePathBuf,
+    ) -> anyhow::Result<Vec<DirEntryWithMetadata<ProjectRelativePathBuf>>> {
+        let entries = self.read_dir_impl(path.clone()).await?;
+        let entries = futures::future::try_join_all(
+            entries
+                .into_iter()
+                .filter_map(|e| FileNameBuf::try_from_or_get_back(e.file_name).ok())
+                .map(|file_name| {
+                    let child = path.join(&file_name);
+                    async move {
+                        let metadata = self.read_path_metadata_if_exists_impl(child).await?;
+                        anyhow::Ok(metadata.map(|metadata| DirEntryWithMetadata {
+                            file_name,
+                            metadata,
+                        }))
+                    }
+                }),
+        )
+        .await?;
+        Ok(entries.into_iter().flatten().collect())
+    }
+
     /// Request that this I/O provider be up to date with whatever I/O operations the user might
     /// have done until this point.
     async fn settle(&self) -> anyhow::Result<()>;
@@ -71,4 +103,13 @@ impl<'a> dyn IoProvider + 'a {
             .await
             .tag(ErrorTag::IoSource)
     }
+
+    pub async fn read_dir_with_metadata(
+        &self,
+        path: ProjectRelativePathBuf,
+    ) -> anyhow::Result<Vec<DirEntryWithMetadata<ProjectRelativePathBuf>>> {
+        self.read_dir_with_metadata_impl(path)
+            .await
+            .tag(ErrorTag::IoSource)
+    }
 }
@@ -31,6 +31,7 @@ use tokio::sync::Semaphore;
 
 use crate::cas_digest::CasDigestConfig;
 use crate::external_symlink::ExternalSymlink;
+use crate::file_ops::DirEntryWithMetadata;
 use crate::file_ops::FileDigest;
 use crate::file_ops::FileDigestConfig;
 use crate::file_ops::FileMetadata;
@@ -171,6 +172,31 @@ impl IoProvider for FsIoProvider {
         .await?
     }
 
+    async fn read_dir_with_metadata_impl(
+        &self,
+        path: ProjectRelativePathBuf,
+    ) -> anyhow::Result<Vec<DirEntryWithMetadata<ProjectRelativePathBuf>>> {
+        // Same limit as `read_dir_impl`, for the same reasons.
+        static SEMAPHORE: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(400));
+        let _permit = SEMAPHORE.acquire().await.unwrap();
+
+        let fs = self.fs.dupe();
+        let path = path.into_forward_relative_path_buf();
+        let file_digest_config = FileDigestConfig::source(self.cas_digest_config);
+
+        tokio::task::spawn_blocking(move || {
+            let entries = read_dir_with_metadata(fs.root(), &path, file_digest_config)?;
+            anyhow::Ok(
+                entries
+                    .into_iter()
+                    .map(|e| e.map(ProjectRelativePathBuf::from))
+                    .collect(),
+            )
+        })
+        .await?
+        .context("Error listing directory")
+    }
+
     async fn settle(&self) -> anyhow::Result<()> {
         Ok(())
     }
@@ -259,6 +285,67 @@ fn read_path_metadata<P: AsRef<AbsPath>>(
     Ok(Some(meta))
 }
 
+/// List `relpath` and read the metadata of each entry in the same pass. This returns the same
+/// entries as calling `read_path_metadata` on each child, without re-resolving the parent path
+/// for every one of them.
+fn read_dir_with_metadata<P: AsRef<AbsPath>>(
+    root: P,
+    relpath: &ForwardRelativePath,
+    file_digest_config: FileDigestConfig,
+) -> anyhow::Result<Vec<DirEntryWithMetadata<ForwardRelativePathBuf>>> {
+    let root = root.as_ref();
+
+    // If the directory is reached through a symlink, the metadata of every entry is a redirection
+    // through that symlink, which `read_path_metadata` knows how to produce.
+    let through_symlink = !relpath.is_empty()
+        && matches!(
+            read_path_metadata(root, relpath, file_digest_config)?,
+            Some(RawPathMetadata::Symlink { .. })
+        );
+
+    let dir_entries = fs_util::read_dir(root.join(relpath.as_path()))
+        .map_err(IoError::categorize_for_source_file)?;
+
+    let mut entries = Vec::new();
+
+    for entry in dir_entries {
+        let e = entry.context("Error accessing directory entry")?;
+        let file_name = e.file_name();
+        let file_name = file_name
+            .to_str()
+            .ok_or_else(|| ReadDirError::NotUtf8(file_name.clone()))?;
+        let Ok(file_name) = FileName::new(file_name) else {
+            continue;
+        };
+
+        let path = relpath.join(file_name);
+        let metadata = if through_symlink {
+            read_path_metadata(root, &path, file_digest_config)?
+        } else {
+            let abspath = root.join(path.as_path());
+            let curr = PathAndAbsPath { path, abspath };
+            match ExactPathMetadata::from_exact_path(&curr)? {
+                ExactPathMetadata::DoesNotExist => None,
+                ExactPathMetadata::Symlink(symlink) => {
+                    Some(symlink.to_raw_path_metadata(curr, None)?)
+                }
+                ExactPathMetadata::FileOrDirectory(meta) => {
+                    Some(convert_metadata(&curr, meta, file_digest_config)?)
+                }
+            }
+        };
+
+        if let Some(metadata) = metadata {
+            entries.push(DirEntryWithMetadata {
+                file_name: file_name.to_owned(),
+                metadata,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
 fn convert_metadata(
     path: &PathAndAbsPath,
     meta: std::fs::Metadata,
@@ -399,6 +486,7 @@ mod tests {
 
     use assert_matches::assert_matches;
     use buck2_core::fs::paths::abs_path::AbsPath;
+    use buck2_core::fs::project::ProjectRootTemp;
     use tempfile::TempDir;
 
     use super::*;
@@ -554,4 +642,119 @@ mod tests {
 
         Ok(())
     }
+
+    /// Forwards to an `FsIoProvider`, but uses the default `read_dir_with_metadata_impl`.
+    #[derive(Allocative)]
+    struct FallbackIoProvider(FsIoProvider);
+
+    #[async_trait]
+    impl IoProvider for FallbackIoProvider {
+        async fn read_file_if_exists_impl(
+            &self,
+            path: ProjectRelativePathBuf,
+        ) -> anyhow::Result<Option<String>> {
+            self.0.read_file_if_exists_impl(path).await
+        }
+
+        async fn read_dir_impl(
+            &self,
+            path: ProjectRelativePathBuf,
+        ) -> anyhow::Result<Vec<RawDirEntry>> {
+            self.0.read_dir_impl(path).await
+        }
+
+        async fn read_path_metadata_if_exists_impl(
+            &self,
+            path: ProjectRelativePathBuf,
+        ) -> anyhow::Result<Option<RawPathMetadata<ProjectRelativePathBuf>>> {
+            self.0.read_path_metadata_if_exists_impl(path).await
+        }
+
+        async fn settle(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn name(&self) -> &'static str {
+            "fallback"
+        }
+
+        async fn eden_version(&self) -> anyhow::Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn project_root(&self) -> &ProjectRoot {
+            self.0.project_root()
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    async fn read_dir_with_metadata_both_ways(
+        fs: &FsIoProvider,
+        path: &str,
+    ) -> anyhow::Result<Vec<DirEntryWithMetadata<ProjectRelativePathBuf>>> {
+        let path = ProjectRelativePathBuf::unchecked_new(path.to_owned());
+        let fallback = FallbackIoProvider(fs.dupe());
+
+        let mut batched = fs.read_dir_with_metadata_impl(path.clone()).await?;
+        let mut expected = fallback.read_dir_with_metadata_impl(path).await?;
+        batched.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        expected.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+        assert_eq!(batched, expected);
+        Ok(batched)
+    }
+
+    #[tokio::test]
+    async fn test_read_dir_with_metadata_matches_fallback() -> anyhow::Result<()> {
+        let t = ProjectRootTemp::new()?;
+        let root: &AbsPath = t.path().root();
+        let fs = FsIoProvider::new(t.path().dupe(), CasDigestConfig::testing_default());
+
+        fs_util::create_dir_all(root.join("d/sub"))?;
+        fs_util::write(root.join("d/file"), "xx")?;
+        fs_util::write(root.join("d/sub/nested"), "yy")?;
+        unix::fs::symlink("sub", root.join("d/rel"))?;
+        unix::fs::symlink("../outside", root.join("d/up"))?;
+        unix::fs::symlink("/does/not/exist", root.join("d/abs"))?;
+        unix::fs::symlink("d", root.join("link_to_d"))?;
+
+        let entries = read_dir_with_metadata_both_ways(&fs, "d").await?;
+        let names = entries
+            .iter()
+            .map(|e| e.file_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["abs", "file", "rel", "sub", "up"]);
+        assert_matches!(
+            entries[0].metadata,
+            RawPathMetadata::Symlink {
+                to: RawSymlink::External(..),
+                ..
+            }
+        );
+        assert_matches!(entries[1].metadata, RawPathMetadata::File(..));
+        assert_matches!(entries[2].metadata, RawPathMetadata::Symlink { to: RawSymlink::Relative(ref r), .. } => {
+            assert_eq!(r.as_str(), "d/sub");
+        });
+        assert_matches!(entries[3].metadata, RawPathMetadata::Directory);
+        assert_matches!(entries[4].metadata, RawPathMetadata::Symlink { to: RawSymlink::Relative(ref r), .. } => {
+            assert_eq!(r.as_str(), "outside");
+        });
+
+        // Listing through a symlinked directory redirects every entry through the symlink.
+        let entries = read_dir_with_metadata_both_ways(&fs, "link_to_d").await?;
+        assert_eq!(entries.len(), 5);
+        for e in &entries {
+            assert_matches!(e.metadata, RawPathMetadata::Symlink { to: RawSymlink::Relative(ref r), .. } => {
+                assert_eq!(r.as_str(), format!("d/{}", e.file_name));
+            });
+        }
+
+        read_dir_with_metadata_both_ways(&fs, "d/sub").await?;
+        read_dir_with_metadata_both_ways(&fs, "").await?;
+
+        Ok(())
+    }
 }
@@ -9,98 +9,30 @@
 
 use std::time::Duration;
 
-#[allow(unused_variables)]
-pub async fn try_terminate_process_gracefully(pid: i32, timeout: Duration) -> anyhow::Result<()> {
-    #[cfg(unix)]
-    {
-        unix::try_terminate_process_gracefully(pid, timeout).await
-    }
-
-    #[cfg(not(unix))]
-    {
-        Err(anyhow::anyhow!(
-            "Graceful process termination is not implemented for non-unix target family."
-        ))
-    }
-}
+use buck2_wrapper_common::kill;
+use buck2_wrapper_common::kill::GracefulKill;
+use buck2_wrapper_common::pid::Pid;
+use tracing::warn;
 
-#[cfg(unix)]
-mod unix {
-    use std::ops::ControlFlow;
-    use std::time::Duration;
-    use std::time::Instant;
-
-    use nix::sys::signal::Signal;
-    use tracing::info;
-    use tracing::warn;
-
-    pub(crate) async fn try_terminate_process_gracefully(
-        pid: i32,
-        timeout: Duration,
-    ) -> anyhow::Result<()> {
-        let pid = nix::unistd::Pid::from_raw(pid);
-        match send_signal_and_wait_for_shutdown(Signal::SIGTERM, pid, Some(timeout)).await {
-            ControlFlow::Continue(_) => {}
-            ControlFlow::Break(StoppedWaiting::Success) => {
-                return Ok(());
-            }
-            ControlFlow::Break(StoppedWaiting::UnexpectedError(e)) => {
-                return Err(e);
-            }
-        }
+/// Send `TERM` to the process and `KILL` if it does not exit within `timeout`, then wait
+/// for the process to exit. On Windows, the process is always terminated forcefully.
+pub async fn try_terminate_process_gracefully(pid: i32, timeout: Duration) -> anyhow::Result<()> {
+    let pid = Pid::from_i64(pid.into())?;
+    let handle =
+        match tokio::task::spawn_blocking(move || kill::kill_graceful(pid, timeout)).await?? {
+            GracefulKill::NotRunning | GracefulKill::Terminated => return Ok(()),
+            GracefulKill::Killed(handle) => handle,
+        };
+    if cfg!(unix) {
         warn!(
-            "Failed to gracefully terminate process `{}`, sending SIGKILL.",
+            "Failed to gracefully terminate process `{}`, sent SIGKILL.",
             pid,
         );
-        match send_signal_and_wait_for_shutdown(Signal::SIGKILL, pid, None).await {
-            ControlFlow::Continue(_) => {
-                unreachable!("Not expected when there is no timeout specified.")
-            }
-            ControlFlow::Break(StoppedWaiting::Success) => Ok(()),
-            ControlFlow::Break(StoppedWaiting::UnexpectedError(e)) => Err(e),
-        }
-    }
-
-    enum StoppedWaiting {
-        Success,
-        UnexpectedError(anyhow::Error),
     }
-
-    fn check_result(pid: nix::unistd::Pid, result: nix::Result<()>) -> ControlFlow<StoppedWaiting> {
-        match result {
-            // Signal is sent successfully, now we need to wait for a process to terminate.
-            Ok(_) => ControlFlow::Continue(()),
-            // There is no such process, our desired outcome.
-            Err(nix::errno::Errno::ESRCH) => ControlFlow::Break(StoppedWaiting::Success),
-            Err(e) => ControlFlow::Break(StoppedWaiting::UnexpectedError(anyhow::anyhow!(
-                "Unexpected error while waiting for process `{}` to terminate (`{}`)",
-                pid,
-                e
-            ))),
-        }
-    }
-
-    async fn send_signal_and_wait_for_shutdown(
-        signal: nix::sys::signal::Signal,
-        pid: nix::unistd::Pid,
-        timeout: Option<Duration>,
-    ) -> ControlFlow<StoppedWaiting> {
-        let start = Instant::now();
-        info!("Sending signal `{}` to process `{}`.", signal, pid);
-        // Actually send a signal.
-        check_result(pid, nix::sys::signal::kill(pid, signal))?;
-        loop {
-            if let Some(timeout) = timeout {
-                if start.elapsed() > timeout {
-                    break;
-                }
-            }
-            tokio::time::sleep(Duration::from_millis(100)).await;
-            // Just check the process, signal already sent.
-            check_result(pid, nix::sys::signal::kill(pid, None))?;
-        }
-        ControlFlow::Continue(())
+    while !handle.has_exited()? {
+        tokio::time::sleep(Duration::from_millis(100)).await;
     }
+    Ok(())
 }
 
 #[cfg(unix)]
@@ -16,11 +16,14 @@ pub mod cells;
 pub mod dice;
 pub mod init;
 pub mod key;
+pub mod overlay;
 mod parser;
 pub(crate) mod path;
+pub mod section_group;
 pub mod view;
 
 use std::cell::OnceCell;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
@@ -44,6 +47,9 @@ use gazebo::prelude::*;
 use starlark_map::sorted_map::SortedMap;
 
 use crate::legacy_configs::cells::BuckConfigBasedCells;
+use crate::legacy_configs::overlay::LegacyConfigOverlay;
+use crate::legacy_configs::parser::resolve_layers;
+use crate::legacy_configs::parser::ConfigLayer;
 use crate::legacy_configs::parser::LegacyConfigParser;
 use crate::legacy_configs::view::LegacyBuckConfigView;
 
@@ -66,8 +72,8 @@ impl LegacyBuckConfigs {
 struct MainConfigFile {
     path: AbsNormPathBuf,
 
-    /// if a main config file is in project or global
-    owned_by_project: bool,
+    /// Whether this file is in the project, or global.
+    layer: ConfigLayer,
 }
 
 #[derive(Clone, Dupe, Debug, Allocative)]
@@ -76,6 +82,9 @@ pub struct LegacyBuckConfig(Arc<ConfigData>);
 #[derive(Debug, Allocative)]
 struct ConfigData {
     values: SortedMap<String, LegacyBuckConfigSection>,
+    /// Environment variables read by `$(env)` references, with the values they had when the
+    /// config was resolved (`None` if unset).
+    env_dependencies: SortedMap<String, Option<String>>,
 }
 
 #[derive(Clone, Debug, Allocative)]
@@ -308,11 +317,20 @@ fn parse_config_arg(raw_arg: &str) -> anyhow::Result<ParsedConfigArg> {
     })
 }
 
-#[derive(Debug, Allocative)]
+#[derive(Clone, Debug, Allocative)]
 struct ConfigValue {
     raw_value: String,
     resolved_value: ResolvedValue,
     source: Location,
+    /// The values previously set for this key that this one overrides, oldest first.
+    overridden: Vec<OverriddenConfigValue>,
+}
+
+/// A value that was set for a key, and then overridden by a later assignment.
+#[derive(Clone, Debug, Allocative)]
+struct OverriddenConfigValue {
+    raw_value: String,
+    source: Location,
 }
 
 #[derive(Debug, Default, Allocative)]
@@ -326,6 +344,7 @@ impl ConfigValue {
             raw_value: value,
             resolved_value: ResolvedValue::Unknown,
             source: Location::File(source),
+            overridden: Vec::new(),
         }
     }
 
@@ -334,9 +353,22 @@ impl ConfigValue {
             raw_value,
             resolved_value: ResolvedValue::Unknown,
             source: Location::CommandLineArgument,
+            overridden: Vec::new(),
         }
     }
 
+    /// This value, recorded as overriding `previous` and everything `previous` overrode.
+    fn overriding(mut self, previous: ConfigValue) -> Self {
+        let mut overridden = previous.overridden;
+        overridden.push(OverriddenConfigValue {
+            raw_value: previous.raw_value,
+            source: previous.source,
+        });
+        overridden.append(&mut self.overridden);
+        self.overridden = overridden;
+        self
+    }
+
     fn raw_value(&self) -> &str {
         &self.raw_value
     }
@@ -367,6 +399,11 @@ pub trait ConfigParserFileOps: Send + Sync {
     async fn file_exists(&mut self, path: &AbsNormPath) -> bool;
 
     async fn read_dir(&mut self, path: &AbsNormPath) -> anyhow::Result<Vec<ConfigDirEntry>>;
+
+    /// The value of the environment variable `var`, for `$(env)` references.
+    fn env_var(&self, var: &str) -> Option<String> {
+        std::env::var(var).ok()
+    }
 }
 
 #[derive(buck2_error::Error, Debug)]
@@ -473,6 +510,14 @@ impl<'a> LegacyBuckConfigValue<'a> {
         }
     }
 
+    /// The values set for this key before this one and overridden by it, oldest first.
+    pub fn overridden(&self) -> impl Iterator<Item = LegacyBuckConfigOverriddenValue<'a>> {
+        self.value
+            .overridden
+            .iter()
+            .map(|value| LegacyBuckConfigOverriddenValue { value })
+    }
+
     pub fn location_stack(&self) -> Vec<LegacyBuckConfigLocation> {
         let mut res = Vec::new();
         let mut location = Some(&self.value.source);
@@ -495,10 +540,26 @@ impl<'a> LegacyBuckConfigValue<'a> {
     }
 }
 
+/// A value that was set for a key in a config, but overridden by a later one.
+pub struct LegacyBuckConfigOverriddenValue<'a> {
+    value: &'a OverriddenConfigValue,
+}
+
+impl<'a> LegacyBuckConfigOverriddenValue<'a> {
+    pub fn raw_value(&self) -> &'a str {
+        &self.value.raw_value
+    }
+
+    pub fn location(&self) -> LegacyBuckConfigLocation<'a> {
+        self.value.source.as_legacy_buck_config_location()
+    }
+}
+
 impl LegacyBuckConfig {
     pub fn empty() -> Self {
         Self(Arc::new(ConfigData {
             values: SortedMap::new(),
+            env_dependencies: SortedMap::new(),
         }))
     }
 
@@ -591,39 +652,30 @@ impl LegacyBuckConfig {
         resolved_args.into_try_map(|x| x)
     }
 
+    /// Parses the config files of a cell, without the values passed on the command line, which
+    /// are applied with `with_overlay`.
     async fn parse_with_file_ops_with_includes(
         main_config_files: &[MainConfigFile],
-        cell_path: AbsNormPathBuf,
         file_ops: &mut dyn ConfigParserFileOps,
-        config_args: &[ResolvedLegacyConfigArg],
         follow_includes: bool,
     ) -> anyhow::Result<Self> {
-        let mut parser = LegacyConfigParser::new();
+        let mut parsers: BTreeMap<ConfigLayer, LegacyConfigParser> = BTreeMap::new();
         for main_config_file in main_config_files {
-            parser
+            parsers
+                .entry(main_config_file.layer)
+                .or_insert_with(LegacyConfigParser::new)
                 .parse_file(&main_config_file.path, None, follow_includes, file_ops)
                 .await?;
         }
 
-        for config_arg in config_args {
-            match config_arg {
-                ResolvedLegacyConfigArg::Flag(config_value) => {
-                    parser.apply_config_arg(config_value, cell_path.clone())?
-                }
-                ResolvedLegacyConfigArg::File(file_path) => {
-                    parser
-                        .parse_file(
-                            file_path,
-                            Some(Location::CommandLineArgument),
-                            follow_includes,
-                            file_ops,
-                        )
-                        .await?
-                }
-            };
-        }
-
-        parser.finish()
+        let file_ops = &*file_ops;
+        resolve_layers(
+            parsers
+                .into_iter()
+                .map(|(layer, parser)| (layer, parser.finish()))
+                .collect(),
+            &|var| file_ops.env_var(var),
+        )
     }
 }
 
@@ -636,24 +688,19 @@ struct BuckConfigParseOptions {
 fn push_all_files_from_a_directory<'a>(
     buckconfig_paths: &'a mut Vec<MainConfigFile>,
     folder_path: &'a AbsNormPath,
-    owned_by_project: bool,
+    layer: ConfigLayer,
     file_ops: &'a mut dyn ConfigParserFileOps,
 ) -> BoxFuture<'a, anyhow::Result<()>> {
     async move {
         for entry in file_ops.read_dir(folder_path).await? {
             let entry_path = folder_path.join(&entry.name);
             if entry.is_dir {
-                push_all_files_from_a_directory(
-                    buckconfig_paths,
-                    &entry_path,
-                    owned_by_project,
-                    file_ops,
-                )
-                .await?;
+                push_all_files_from_a_directory(buckconfig_paths, &entry_path, layer, file_ops)
+                    .await?;
             } else {
                 buckconfig_paths.push(MainConfigFile {
                     path: entry_path,
-                    owned_by_project,
+                    layer,
                 });
             }
         }
@@ -666,6 +713,9 @@ fn push_all_files_from_a_directory<'a>(
 pub mod testing {
     use std::cmp::min;
 
+    use buck2_core::cells::cell_root_path::CellRootPath;
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+
     use super::*;
     use crate::legacy_configs::cells::create_project_filesystem;
 
@@ -678,7 +728,24 @@ pub mod testing {
         path: &str,
         config_args: &[LegacyConfigCmdArg],
     ) -> anyhow::Result<LegacyBuckConfig> {
-        let mut file_ops = TestConfigParserFileOps::new(data)?;
+        parse_with_file_ops(TestConfigParserFileOps::new(data)?, path, config_args)
+    }
+
+    /// Like `parse`, with `$(env)` references looked up in `env` rather than the process
+    /// environment.
+    pub fn parse_with_env(
+        data: &[(&str, &str)],
+        path: &str,
+        env: &[(&str, &str)],
+    ) -> anyhow::Result<LegacyBuckConfig> {
+        parse_with_file_ops(TestConfigParserFileOps::new(data)?.with_env(env), path, &[])
+    }
+
+    fn parse_with_file_ops(
+        mut file_ops: TestConfigParserFileOps,
+        path: &str,
+        config_args: &[LegacyConfigCmdArg],
+    ) -> anyhow::Result<LegacyBuckConfig> {
         #[cfg(not(windows))]
         let path = &AbsNormPathBuf::from(path.into())?;
         // Need to add some disk drive on Windows to make path absolute.
@@ -693,20 +760,49 @@ pub mod testing {
         };
         let processed_config_args =
             LegacyBuckConfig::process_config_args(config_args, &cell_resolution, &mut file_ops)?;
-        futures::executor::block_on(LegacyBuckConfig::parse_with_file_ops_with_includes(
-            &[MainConfigFile {
-                path: path.to_buf(),
-                owned_by_project: true,
-            }],
-            path.clone(),
+        let overlay = futures::executor::block_on(LegacyConfigOverlay::new(
+            &processed_config_args,
+            &project_fs,
             &mut file_ops,
+            true,
+        ))?;
+        let config =
+            futures::executor::block_on(LegacyBuckConfig::parse_with_file_ops_with_includes(
+                &[MainConfigFile {
+                    path: path.to_buf(),
+                    layer: ConfigLayer::Repo,
+                }],
+                &mut file_ops,
+                true,
+            ))?;
+        config.with_overlay(&overlay, CellRootPath::new(ProjectRelativePath::empty()))
+    }
+
+    /// The overlay of a command passing `config_args`, which may refer to files in `data`.
+    pub fn parse_overlay(
+        data: &[(&str, &str)],
+        config_args: &[LegacyConfigCmdArg],
+    ) -> anyhow::Result<LegacyConfigOverlay> {
+        let mut file_ops = TestConfigParserFileOps::new(data)?;
+        let project_fs = create_project_filesystem();
+        let cell_resolution = CellResolutionState {
+            project_filesystem: &project_fs,
+            cwd: project_fs.root(),
+            cell_resolver: OnceCell::new(),
+        };
+        let processed_config_args =
+            LegacyBuckConfig::process_config_args(config_args, &cell_resolution, &mut file_ops)?;
+        futures::executor::block_on(LegacyConfigOverlay::new(
             &processed_config_args,
+            &project_fs,
+            &mut file_ops,
             true,
         ))
     }
 
     pub struct TestConfigParserFileOps {
         data: HashMap<AbsNormPathBuf, String>,
+        env: HashMap<String, String>,
     }
 
     impl TestConfigParserFileOps {
@@ -720,7 +816,20 @@ pub mod testing {
                 let file_path = format!("C:{}", file);
                 holder_data.insert(AbsNormPathBuf::from(file_path)?, (*content).to_owned());
             }
-            Ok(TestConfigParserFileOps { data: holder_data })
+            Ok(TestConfigParserFileOps {
+                data: holder_data,
+                env: HashMap::new(),
+            })
+        }
+
+        /// Sets the environment `$(env)` references are looked up in. Without it, the
+        /// environment is empty.
+        pub fn with_env(mut self, env: &[(&str, &str)]) -> Self {
+            self.env = env
+                .iter()
+                .map(|(var, value)| ((*var).to_owned(), (*value).to_owned()))
+                .collect();
+            self
         }
     }
 
@@ -761,6 +870,10 @@ pub mod testing {
             // say are always empty in tests
             Ok(Vec::new())
         }
+
+        fn env_var(&self, var: &str) -> Option<String> {
+            self.env.get(var).cloned()
+        }
     }
 }
 
@@ -772,6 +885,7 @@ mod tests {
 
     use super::testing::*;
     use super::*;
+    use crate::legacy_configs::init::DaemonStartupConfig;
     use crate::legacy_configs::key::BuckconfigKeyRef;
 
     pub(crate) fn assert_config_value(
@@ -990,6 +1104,116 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_env_references() -> anyhow::Result<()> {
+        let config = parse_with_env(
+            &[(
+                "/config",
+                indoc!(
+                    r#"
+            [x]
+                set = <$(env BUCK2_TEST_CONFIG_ENV_SET)>
+                set_with_default = $(env BUCK2_TEST_CONFIG_ENV_SET fallback)
+                unset_with_default = $(env BUCK2_TEST_CONFIG_ENV_UNSET fallback value)
+                unset_with_empty_default = <$(env BUCK2_TEST_CONFIG_ENV_UNSET )>
+                mixed = $(config x.set)/$(env BUCK2_TEST_CONFIG_ENV_UNSET d)
+                macro = $(location //foo:bar)
+                no_name = $(env)
+                bad_name = $(env 1FOO)
+        "#
+                ),
+            )],
+            "/config",
+            &[("BUCK2_TEST_CONFIG_ENV_SET", "from_env")],
+        )?;
+
+        assert_config_value(&config, "x", "set", "<from_env>");
+        assert_config_value(&config, "x", "set_with_default", "from_env");
+        assert_config_value(&config, "x", "unset_with_default", "fallback value");
+        assert_config_value(&config, "x", "unset_with_empty_default", "<>");
+        assert_config_value(&config, "x", "mixed", "<from_env>/d");
+        assert_config_value(&config, "x", "macro", "$(location //foo:bar)");
+        assert_config_value(&config, "x", "no_name", "$(env)");
+        assert_config_value(&config, "x", "bad_name", "$(env 1FOO)");
+
+        assert_eq!(
+            vec![
+                ("BUCK2_TEST_CONFIG_ENV_SET", Some("from_env")),
+                ("BUCK2_TEST_CONFIG_ENV_UNSET", None),
+            ],
+            config.env_dependencies().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_reference_missing() -> anyhow::Result<()> {
+        let res = parse(
+            &[(
+                "/config",
+                indoc!(
+                    r#"
+            [x]
+                a = $(env BUCK2_TEST_CONFIG_ENV_MISSING)
+        "#
+                ),
+            )],
+            "/config",
+        );
+
+        match res {
+            Ok(_) => panic!("Expected failure."),
+            Err(e) => {
+                let message = format!("{:#}", e);
+                for expected in ["BUCK2_TEST_CONFIG_ENV_MISSING", "`x.a`", "/config:2"] {
+                    assert!(
+                        message.contains(expected),
+                        "Expected error to contain \"{}\", but was `{}`",
+                        expected,
+                        message
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_reference_changes_startup_config() -> anyhow::Result<()> {
+        let data = [(
+            "/config",
+            indoc!(
+                r#"
+            [buck2]
+                daemon_buster = $(env BUCK2_TEST_CONFIG_ENV_CHANGES default)
+        "#
+            ),
+        )];
+
+        let parse = |env: &[(&str, &str)]| parse_with_env(&data, "/config", env);
+        let unset = parse(&[])?;
+        let set = parse(&[("BUCK2_TEST_CONFIG_ENV_CHANGES", "1")])?;
+        let set_again = parse(&[("BUCK2_TEST_CONFIG_ENV_CHANGES", "1")])?;
+        let changed = parse(&[("BUCK2_TEST_CONFIG_ENV_CHANGES", "2")])?;
+
+        assert!(!unset.compare(&set));
+        assert!(set.compare(&set_again));
+        assert!(!set.compare(&changed));
+
+        let startup_config = DaemonStartupConfig::new;
+        assert_ne!(startup_config(&unset)?, startup_config(&set)?);
+        assert_eq!(startup_config(&set)?, startup_config(&set_again)?);
+        assert_ne!(startup_config(&set)?, startup_config(&changed)?);
+        assert_eq!(
+            Some(&Some(blake3::hash(b"2").to_hex().to_string())),
+            startup_config(&changed)?
+                .config_env
+                .get("BUCK2_TEST_CONFIG_ENV_CHANGES")
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_includes() -> anyhow::Result<()> {
         let config = parse(
@@ -1213,6 +1437,7 @@ mod tests {
                         r#"
             [apple]
                 key = value1
+                other_key = value1
         "#
                     ),
                 ),
@@ -1222,6 +1447,7 @@ mod tests {
                         r#"
             [apple]
                 key = value2
+                other_key = value2
         "#
                     ),
                 ),
@@ -1230,19 +1456,141 @@ mod tests {
             &config_args,
         )?;
 
-        assert_config_value(&config, "apple", "key", "value2");
+        // `--config` takes precedence over `--config-file`, even if it comes first.
+        assert_config_value(&config, "apple", "key", "value3");
+        assert_config_value(&config, "apple", "other_key", "value2");
 
         let apple_section = config.get_section("apple").unwrap();
-        let key_value = apple_section.get("key").unwrap();
+        let key_value = apple_section.get("other_key").unwrap();
         #[cfg(not(windows))]
-        let expected_path = LegacyBuckConfigLocation::File("/cli-config", 2);
+        let expected_path = LegacyBuckConfigLocation::File("/cli-config", 3);
         #[cfg(windows)]
-        let expected_path = LegacyBuckConfigLocation::File("C:/cli-config", 2);
+        let expected_path = LegacyBuckConfigLocation::File("C:/cli-config", 3);
         assert_eq!(key_value.location(), expected_path);
 
         Ok(())
     }
 
+    #[test]
+    fn test_provenance_cli_overrides_file() -> anyhow::Result<()> {
+        let config = parse_with_config_args(
+            &[(
+                "/config",
+                indoc!(
+                    r#"
+            [apple]
+                key = value1
+                key = value2
+                other_key = value1
+        "#
+                ),
+            )],
+            "/config",
+            &[LegacyConfigCmdArg::flag("apple.key=value3")?],
+        )?;
+
+        let value = config
+            .value_with_provenance(BuckconfigKeyRef {
+                section: "apple",
+                property: "key",
+            })
+            .unwrap();
+        assert_eq!(value.as_str(), "value3");
+        assert_eq!(
+            value.location(),
+            LegacyBuckConfigLocation::CommandLineArgument
+        );
+        assert_eq!(
+            value
+                .overridden()
+                .map(|v| (v.raw_value(), v.location()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("value1", LegacyBuckConfigLocation::File("/config", 2)),
+                ("value2", LegacyBuckConfigLocation::File("/config", 3)),
+            ]
+        );
+
+        let other = config
+            .value_with_provenance(BuckconfigKeyRef {
+                section: "apple",
+                property: "other_key",
+            })
+            .unwrap();
+        assert_eq!(
+            other.location(),
+            LegacyBuckConfigLocation::File("/config", 4)
+        );
+        assert_eq!(other.overridden().count(), 0);
+
+        Ok(())
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_provenance_files_override_in_order() -> anyhow::Result<()> {
+        let config = parse_with_config_args(
+            &[
+                (
+                    "/config",
+                    indoc!(
+                        r#"
+            [apple]
+                key = value1
+        "#
+                    ),
+                ),
+                (
+                    "/first",
+                    indoc!(
+                        r#"
+            [apple]
+                key = value2
+        "#
+                    ),
+                ),
+                (
+                    "/second",
+                    indoc!(
+                        r#"
+            [apple]
+                key = value3
+        "#
+                    ),
+                ),
+            ],
+            "/config",
+            &[
+                LegacyConfigCmdArg::file("/first")?,
+                LegacyConfigCmdArg::file("/second")?,
+            ],
+        )?;
+
+        let value = config
+            .value_with_provenance(BuckconfigKeyRef {
+                section: "apple",
+                property: "key",
+            })
+            .unwrap();
+        assert_eq!(value.as_str(), "value3");
+        assert_eq!(
+            value.location(),
+            LegacyBuckConfigLocation::File("/second", 2)
+        );
+        assert_eq!(
+            value
+                .overridden()
+                .map(|v| (v.raw_value(), v.location()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("value1", LegacyBuckConfigLocation::File("/config", 2)),
+                ("value2", LegacyBuckConfigLocation::File("/first", 2)),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_config_args_cell_in_value() -> anyhow::Result<()> {
         let config_args = vec![LegacyConfigCmdArg::flag("apple.key=foo//value1")?];
@@ -1272,14 +1620,14 @@ mod tests {
             futures::executor::block_on(push_all_files_from_a_directory(
                 &mut v,
                 dir,
-                false,
+                ConfigLayer::External,
                 &mut DefaultConfigParserFileOps {},
             ))?;
             assert_eq!(
                 v,
                 vec![MainConfigFile {
                     path: file.to_owned(),
-                    owned_by_project: false,
+                    layer: ConfigLayer::External,
                 }]
             );
 
@@ -1295,7 +1643,7 @@ mod tests {
             futures::executor::block_on(push_all_files_from_a_directory(
                 &mut v,
                 dir,
-                false,
+                ConfigLayer::External,
                 &mut DefaultConfigParserFileOps {},
             ))?;
             assert_eq!(v, vec![]);
@@ -1313,7 +1661,7 @@ mod tests {
             futures::executor::block_on(push_all_files_from_a_directory(
                 &mut v,
                 dir,
-                false,
+                ConfigLayer::External,
                 &mut DefaultConfigParserFileOps {},
             ))?;
             assert_eq!(v, vec![]);
@@ -1331,7 +1679,7 @@ mod tests {
             futures::executor::block_on(push_all_files_from_a_directory(
                 &mut v,
                 AbsNormPath::new(dir)?,
-                false,
+                ConfigLayer::External,
                 &mut DefaultConfigParserFileOps {},
             ))?;
             assert_eq!(v, vec![]);
@@ -1348,7 +1696,7 @@ mod tests {
             futures::executor::block_on(push_all_files_from_a_directory(
                 &mut v,
                 file,
-                false,
+                ConfigLayer::External,
                 &mut DefaultConfigParserFileOps {},
             ))?;
             assert_eq!(v, vec![]);
@@ -1372,14 +1720,14 @@ mod tests {
             futures::executor::block_on(push_all_files_from_a_directory(
                 &mut v,
                 dir,
-                false,
+                ConfigLayer::External,
                 &mut DefaultConfigParserFileOps {},
             ))?;
             assert_eq!(
                 v,
                 vec![MainConfigFile {
                     path: file.to_owned(),
-                    owned_by_project: false,
+                    layer: ConfigLayer::External,
                 }]
             );
 
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::collections::BTreeSet;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -115,6 +116,12 @@ impl LegacyBuckConfig {
         self.get_config_value(key).map(|s| s.as_str())
     }
 
+    /// The value of `key`, which also knows where it was set and which values it overrode.
+    pub fn value_with_provenance(&self, key: BuckconfigKeyRef) -> Option<LegacyBuckConfigValue> {
+        self.get_config_value(key)
+            .map(|value| LegacyBuckConfigValue { value })
+    }
+
     /// Iterate all entries.
     pub fn iter(&self) -> impl Iterator<Item = (&str, impl IntoIterator<Item = (&str, &str)>)> {
         self.0.values.iter().map(|(section, section_values)| {
@@ -212,6 +219,15 @@ impl LegacyBuckConfig {
         self.0.values.get(section)
     }
 
+    /// Environment variables read by `$(env)` references in this config, with the values they
+    /// had when it was resolved (`None` if unset).
+    pub fn env_dependencies(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.0
+            .env_dependencies
+            .iter()
+            .map(|(var, value)| (var.as_str(), value.as_deref()))
+    }
+
     /// configs are equal if the data they resolve in is equal, regardless of the origin of the config
     pub(crate) fn compare(&self, other: &Self) -> bool {
         eq_chain!(
@@ -225,4 +241,26 @@ impl LegacyBuckConfig {
             })
         )
     }
+
+    /// Sections that are present in only one of the configs, or resolve to different values, in
+    /// the sense of `compare`.
+    pub fn changed_sections<'a>(&'a self, other: &'a Self) -> BTreeSet<&'a str> {
+        let mut changed = BTreeSet::new();
+        for (section_name, section) in self.0.values.iter() {
+            if !other
+                .0
+                .values
+                .get(section_name)
+                .map_or(false, |other_sec| other_sec.compare(section))
+            {
+                changed.insert(section_name.as_str());
+            }
+        }
+        for section_name in other.0.values.keys() {
+            if !self.0.values.contains_key(section_name) {
+                changed.insert(section_name.as_str());
+            }
+        }
+        changed
+    }
 }
@@ -110,6 +110,30 @@ impl HasBuildfiles for DiceComputations<'_> {
     }
 }
 
+pub mod testing {
+    use std::sync::Arc;
+
+    use buck2_core::cells::name::CellName;
+    use buck2_core::fs::paths::file_name::FileNameBuf;
+    use dice::testing::DiceBuilder;
+
+    use crate::legacy_configs::buildfiles::BuildfilesKey;
+
+    /// Mocks the buildfile names of `cell`, which otherwise come from its buckconfig.
+    pub fn mock_buildfiles(
+        builder: DiceBuilder,
+        cell: CellName,
+        buildfiles: &[&str],
+    ) -> DiceBuilder {
+        builder.mock_and_return(
+            BuildfilesKey(cell),
+            Ok(Arc::from_iter(
+                buildfiles.iter().map(|b| FileNameBuf::unchecked_new(*b)),
+            )),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use buck2_core::cells::name::CellName;
@@ -11,7 +11,6 @@ use std::cell::OnceCell;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::io;
-use std::sync::Arc;
 
 use anyhow::Context;
 use buck2_core::buck2_env;
@@ -37,8 +36,9 @@ use crate::dice::file_ops::DiceFileComputations;
 use crate::external_cells::EXTERNAL_CELLS_IMPL;
 use crate::file_ops::FileType;
 use crate::file_ops::RawPathMetadata;
-use crate::legacy_configs::dice::HasInjectedLegacyConfigs;
 use crate::legacy_configs::init::DaemonStartupConfig;
+use crate::legacy_configs::overlay::LegacyConfigOverlay;
+use crate::legacy_configs::parser::ConfigLayer;
 use crate::legacy_configs::path::BuckConfigFile;
 use crate::legacy_configs::path::DEFAULT_BUCK_CONFIG_FILES;
 use crate::legacy_configs::push_all_files_from_a_directory;
@@ -51,7 +51,6 @@ use crate::legacy_configs::LegacyBuckConfig;
 use crate::legacy_configs::LegacyBuckConfigs;
 use crate::legacy_configs::LegacyConfigCmdArg;
 use crate::legacy_configs::MainConfigFile;
-use crate::legacy_configs::ResolvedLegacyConfigArg;
 
 #[derive(Debug, buck2_error::Error)]
 enum CellsError {
@@ -75,10 +74,13 @@ enum CellsError {
 /// unlike v1, our cells implementation works just fine if that isn't the case.
 #[derive(Clone)]
 pub struct BuckConfigBasedCells {
+    /// The configs of the cells, with the values passed on the command line.
     pub configs_by_name: LegacyBuckConfigs,
+    /// The configs of the cells as parsed from their files, without the command line overlay.
+    pub base_configs_by_name: LegacyBuckConfigs,
     pub cell_resolver: CellResolver,
     pub config_paths: HashSet<AbsNormPathBuf>,
-    pub resolved_args: Arc<[ResolvedLegacyConfigArg]>,
+    pub overlay: LegacyConfigOverlay,
 }
 
 impl BuckConfigBasedCells {
@@ -110,10 +112,19 @@ impl BuckConfigBasedCells {
             .get(cells.cell_resolver.root_cell())
             .context("No config for root cell")?;
 
+        let mut daemon_startup_config =
+            DaemonStartupConfig::new(root_config).context("Error loading daemon startup config")?;
+        // Every cell's config is resolved in the daemon's environment, not just the root's.
+        daemon_startup_config.config_env = DaemonStartupConfig::config_env(
+            cells
+                .configs_by_name
+                .iter()
+                .flat_map(|(_, config)| config.env_dependencies()),
+        );
+
         Ok(ImmediateConfig {
             cell_resolver: cells.cell_resolver,
-            daemon_startup_config: DaemonStartupConfig::new(root_config)
-                .context("Error loading daemon startup config")?,
+            daemon_startup_config,
         })
     }
 
@@ -185,6 +196,10 @@ impl BuckConfigBasedCells {
             ) -> anyhow::Result<Vec<ConfigDirEntry>> {
                 self.inner.read_dir(path).await
             }
+
+            fn env_var(&self, var: &str) -> Option<String> {
+                self.inner.env_var(var)
+            }
         }
 
         let mut file_ops = TracingFileOps {
@@ -193,6 +208,7 @@ impl BuckConfigBasedCells {
         };
 
         let mut buckconfigs = HashMap::new();
+        let mut base_buckconfigs = HashMap::new();
         let mut work = vec![CellRootPathBuf::new(ProjectRelativePathBuf::try_from(
             "".to_owned(),
         )?)];
@@ -209,6 +225,12 @@ impl BuckConfigBasedCells {
         // NOTE: This will _not_ perform IO unless it needs to.
         let processed_config_args =
             LegacyBuckConfig::process_config_args(config_args, &cell_resolution, &mut file_ops)?;
+        let overlay = futures::executor::block_on(LegacyConfigOverlay::new(
+            &processed_config_args,
+            project_fs,
+            &mut file_ops,
+            options.follow_includes,
+        ))?;
 
         while let Some(path) = work.pop() {
             if buckconfigs.contains_key(&path) || cells_aggregator.is_external(&path) {
@@ -222,14 +244,13 @@ impl BuckConfigBasedCells {
                 &mut file_ops,
             ))?;
 
-            let config =
+            let base_config =
                 futures::executor::block_on(LegacyBuckConfig::parse_with_file_ops_with_includes(
                     buckconfig_paths.as_slice(),
-                    project_fs.resolve(path.as_project_relative_path()),
                     &mut file_ops,
-                    &processed_config_args,
                     options.follow_includes,
                 ))?;
+            let config = base_config.with_overlay(&overlay, &path)?;
 
             let is_root = path.is_repo_root();
 
@@ -296,22 +317,28 @@ impl BuckConfigBasedCells {
                 }
             }
 
+            base_buckconfigs.insert(path.clone(), base_config);
             buckconfigs.insert(path, config);
         }
 
         let cell_resolver = cells_aggregator.make_cell_resolver()?;
-        let configs_by_name = buckconfigs
-            .into_iter()
-            .map(|(path, config)| {
-                Ok((cell_resolver.find(path.as_project_relative_path())?, config))
-            })
-            .collect::<anyhow::Result<_>>()?;
+        let by_name = |configs: HashMap<CellRootPathBuf, LegacyBuckConfig>| {
+            configs
+                .into_iter()
+                .map(|(path, config)| {
+                    Ok((cell_resolver.find(path.as_project_relative_path())?, config))
+                })
+                .collect::<anyhow::Result<_>>()
+        };
+        let configs_by_name = by_name(buckconfigs)?;
+        let base_configs_by_name = by_name(base_buckconfigs)?;
 
         Ok(Self {
             configs_by_name: LegacyBuckConfigs::new(configs_by_name),
+            base_configs_by_name: LegacyBuckConfigs::new(base_configs_by_name),
             cell_resolver,
             config_paths: file_ops.trace,
-            resolved_args: processed_config_args.into_iter().collect(),
+            overlay,
         })
     }
 
@@ -332,6 +359,7 @@ impl BuckConfigBasedCells {
         Ok(aliases.into_iter())
     }
 
+    /// Parses the config files of a cell, without the command line overlay.
     pub(crate) async fn parse_single_cell_with_dice(
         ctx: &mut DiceComputations<'_>,
         cell_path: &CellRootPath,
@@ -339,7 +367,6 @@ impl BuckConfigBasedCells {
         let resolver = ctx.get_cell_resolver().await?;
         let io_provider = ctx.global_data().get_io_provider();
         let project_fs = io_provider.project_root();
-        let overrides = ctx.get_injected_legacy_config_overrides().await?;
 
         struct DiceConfigFileOps<'a, 'b>(
             &'a mut DiceComputations<'b>,
@@ -425,9 +452,7 @@ impl BuckConfigBasedCells {
 
         LegacyBuckConfig::parse_with_file_ops_with_includes(
             &config_paths,
-            project_fs.resolve(cell_path.as_project_relative_path()),
             &mut file_ops,
-            overrides.as_ref(),
             /* follow includes */ true,
         )
         .await
@@ -458,7 +483,7 @@ async fn get_buckconfig_paths_for_cell(
                 buckconfig_paths.push(MainConfigFile {
                     path: project_fs
                         .resolve(&path.as_project_relative_path().join(buckconfig_path)),
-                    owned_by_project: true,
+                    layer: ConfigLayer::Repo,
                 });
             }
 
@@ -469,7 +494,7 @@ async fn get_buckconfig_paths_for_cell(
                 push_all_files_from_a_directory(
                     &mut buckconfig_paths,
                     &buckconfig_folder_abs_path,
-                    true,
+                    ConfigLayer::Repo,
                     file_ops,
                 )
                 .await?;
@@ -480,7 +505,7 @@ async fn get_buckconfig_paths_for_cell(
                     let buckconfig_path = ForwardRelativePath::new(file)?;
                     buckconfig_paths.push(MainConfigFile {
                         path: AbsNormPath::new(&home_dir_path)?.join_normalized(buckconfig_path)?,
-                        owned_by_project: false,
+                        layer: ConfigLayer::External,
                     });
                 }
             }
@@ -493,7 +518,7 @@ async fn get_buckconfig_paths_for_cell(
                     push_all_files_from_a_directory(
                         &mut buckconfig_paths,
                         &buckconfig_folder_abs_path,
-                        false,
+                        ConfigLayer::External,
                         file_ops,
                     )
                     .await?;
@@ -502,7 +527,7 @@ async fn get_buckconfig_paths_for_cell(
             BuckConfigFile::GlobalFile(file) => {
                 buckconfig_paths.push(MainConfigFile {
                     path: AbsNormPathBuf::from(String::from(*file))?,
-                    owned_by_project: false,
+                    layer: ConfigLayer::External,
                 });
             }
             BuckConfigFile::GlobalFolder(folder) => {
@@ -510,7 +535,7 @@ async fn get_buckconfig_paths_for_cell(
                 push_all_files_from_a_directory(
                     &mut buckconfig_paths,
                     &buckconfig_folder_abs_path,
-                    false,
+                    ConfigLayer::External,
                     file_ops,
                 )
                 .await?;
@@ -524,7 +549,8 @@ async fn get_buckconfig_paths_for_cell(
     if let Some(f) = extra_external_config {
         buckconfig_paths.push(MainConfigFile {
             path: AbsNormPathBuf::from(f.to_owned())?,
-            owned_by_project: false,
+            // Tests use it to override the configs of the repo.
+            layer: ConfigLayer::Repo,
         });
     }
 
@@ -640,6 +666,53 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_immediate_config_env_dependencies_of_all_cells() -> anyhow::Result<()> {
+        let mut file_ops = TestConfigParserFileOps::new(&[
+            (
+                "/.buckconfig",
+                indoc!(
+                    r#"
+                            [cells]
+                                root = .
+                                other = other/
+                            [foo]
+                                root_value = $(env BUCK2_TEST_IMMEDIATE_CONFIG_ROOT)
+                        "#
+                ),
+            ),
+            (
+                "/other/.buckconfig",
+                indoc!(
+                    r#"
+                            [cells]
+                                other = .
+                            [foo]
+                                other_value = $(env BUCK2_TEST_IMMEDIATE_CONFIG_OTHER default)
+                        "#
+                ),
+            ),
+        ])?
+        .with_env(&[("BUCK2_TEST_IMMEDIATE_CONFIG_ROOT", "secret")]);
+
+        let project_fs = create_project_filesystem();
+        let config =
+            BuckConfigBasedCells::parse_immediate_config_with_file_ops(&project_fs, &mut file_ops)?;
+
+        let config_env = &config.daemon_startup_config.config_env;
+        assert_eq!(
+            Some(&Some(blake3::hash(b"secret").to_hex().to_string())),
+            config_env.get("BUCK2_TEST_IMMEDIATE_CONFIG_ROOT")
+        );
+        assert_eq!(
+            Some(&None),
+            config_env.get("BUCK2_TEST_IMMEDIATE_CONFIG_OTHER")
+        );
+        assert!(!config.daemon_startup_config.serialize()?.contains("secret"));
+
+        Ok(())
+    }
+
     #[test]
     fn test_multi_cell_with_config_file() -> anyhow::Result<()> {
         let mut file_ops = TestConfigParserFileOps::new(&[
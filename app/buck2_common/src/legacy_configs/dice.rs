@@ -16,6 +16,7 @@ use std::sync::Arc;
 use allocative::Allocative;
 use async_trait::async_trait;
 use buck2_core::cells::name::CellName;
+use buck2_error::internal_error;
 use buck2_futures::cancellation::CancellationContext;
 use derive_more::Display;
 use dice::DiceComputations;
@@ -30,15 +31,36 @@ use dupe::Dupe;
 use crate::dice::cells::HasCellResolver;
 use crate::legacy_configs::cells::BuckConfigBasedCells;
 use crate::legacy_configs::key::BuckconfigKeyRef;
+use crate::legacy_configs::overlay::LegacyConfigOverlay;
+use crate::legacy_configs::overlay::LegacyConfigOverlayDigest;
+use crate::legacy_configs::section_group::ConfigSectionGroup;
 use crate::legacy_configs::view::LegacyBuckConfigView;
 use crate::legacy_configs::LegacyBuckConfig;
 use crate::legacy_configs::LegacyBuckConfigs;
-use crate::legacy_configs::ResolvedLegacyConfigArg;
 
 /// Buckconfig view which queries buckconfig entry from DICE.
 #[derive(Clone, Dupe)]
 pub struct OpaqueLegacyBuckConfigOnDice {
-    config: Arc<OpaqueValue<LegacyBuckConfigForCellKey>>,
+    config: Arc<SectionGroupConfigs>,
+}
+
+/// The config of a cell, once per section group. Lookups go through the one of the section they
+/// read.
+#[derive(Debug)]
+struct SectionGroupConfigs {
+    alias: OpaqueValue<LegacyBuckConfigSectionGroupKey>,
+    client_only: OpaqueValue<LegacyBuckConfigSectionGroupKey>,
+    graph: OpaqueValue<LegacyBuckConfigSectionGroupKey>,
+}
+
+impl SectionGroupConfigs {
+    fn get(&self, group: ConfigSectionGroup) -> &OpaqueValue<LegacyBuckConfigSectionGroupKey> {
+        match group {
+            ConfigSectionGroup::Alias => &self.alias,
+            ConfigSectionGroup::ClientOnly => &self.client_only,
+            ConfigSectionGroup::Graph => &self.graph,
+        }
+    }
 }
 
 impl std::fmt::Debug for OpaqueLegacyBuckConfigOnDice {
@@ -57,7 +79,7 @@ impl OpaqueLegacyBuckConfigOnDice {
     ) -> anyhow::Result<Option<Arc<str>>> {
         let BuckconfigKeyRef { section, property } = key;
         Ok(ctx.projection(
-            &*self.config,
+            self.config.get(ConfigSectionGroup::of_section(section)),
             &LegacyBuckConfigPropertyProjectionKey {
                 section: section.to_owned(),
                 property: property.to_owned(),
@@ -102,6 +124,9 @@ impl<'a, 'd> LegacyBuckConfigView for LegacyBuckConfigOnDice<'a, 'd> {
 }
 
 pub trait HasInjectedLegacyConfigs {
+    /// The configs of the cells as parsed from their files, without the values passed on the
+    /// command line.
+    ///
     /// Use this function carefully: a computation which fetches this key will be recomputed
     /// if any buckconfig property changes.
     ///
@@ -113,12 +138,12 @@ pub trait HasInjectedLegacyConfigs {
     /// Checks if LegacyBuckConfigsKey has been set in the DICE graph.
     fn is_injected_legacy_configs_key_set(&mut self) -> impl Future<Output = anyhow::Result<bool>>;
 
-    /// Returns the list of overrides passed to the command line.
-    fn get_injected_legacy_config_overrides(
+    /// Returns the config values passed on the command line.
+    fn get_injected_legacy_config_overlay(
         &mut self,
-    ) -> impl Future<Output = anyhow::Result<Arc<[ResolvedLegacyConfigArg]>>>;
+    ) -> impl Future<Output = anyhow::Result<LegacyConfigOverlay>>;
 
-    fn is_injected_legacy_config_override_key_set(
+    fn is_injected_legacy_config_overlay_key_set(
         &mut self,
     ) -> impl Future<Output = anyhow::Result<bool>>;
 }
@@ -147,6 +172,14 @@ pub trait HasLegacyConfigs {
         cell_name: CellName,
     ) -> buck2_error::Result<LegacyBuckConfig>;
 
+    /// Like `get_legacy_config_for_cell`, but the computation is only recomputed when a section
+    /// of `group` changes. Only sections of that group may be read from the returned config.
+    async fn get_legacy_config_section_group(
+        &mut self,
+        cell_name: CellName,
+        group: ConfigSectionGroup,
+    ) -> buck2_error::Result<LegacyBuckConfig>;
+
     async fn get_legacy_config_property(
         &mut self,
         cell_name: CellName,
@@ -168,12 +201,9 @@ pub trait SetLegacyConfigs {
 
     fn set_none_legacy_configs(&mut self) -> anyhow::Result<()>;
 
-    fn set_legacy_config_overrides(
-        &mut self,
-        overrides: Arc<[ResolvedLegacyConfigArg]>,
-    ) -> anyhow::Result<()>;
+    fn set_legacy_config_overlay(&mut self, overlay: LegacyConfigOverlay) -> anyhow::Result<()>;
 
-    fn set_none_legacy_config_overrides(&mut self) -> anyhow::Result<()>;
+    fn set_none_legacy_config_overlay(&mut self) -> anyhow::Result<()>;
 }
 
 #[derive(Clone, Dupe, Display, Debug, Eq, Hash, PartialEq, Allocative)]
@@ -194,24 +224,26 @@ impl InjectedKey for LegacyBuckConfigKey {
 
 #[derive(Clone, Dupe, Display, Debug, Eq, Hash, PartialEq, Allocative)]
 #[display(fmt = "{:?}", self)]
-struct LegacyBuckConfigOverridesKey;
+struct LegacyBuckConfigOverlayKey;
 
-impl InjectedKey for LegacyBuckConfigOverridesKey {
-    type Value = Option<Arc<[ResolvedLegacyConfigArg]>>;
+impl InjectedKey for LegacyBuckConfigOverlayKey {
+    type Value = Option<LegacyConfigOverlay>;
 
     fn equality(x: &Self::Value, y: &Self::Value) -> bool {
-        x == y
+        x.as_ref().map(|x| x.digest()) == y.as_ref().map(|y| y.digest())
     }
 }
 
-#[derive(Clone, Display, Debug, Hash, Eq, PartialEq, Allocative)]
-#[display(fmt = "LegacyBuckConfigForCellKey({})", "self.cell_name")]
-struct LegacyBuckConfigForCellKey {
+/// The config of a cell as parsed from its files, shared by all commands whatever the values
+/// they pass on the command line.
+#[derive(Clone, Dupe, Display, Debug, Hash, Eq, PartialEq, Allocative)]
+#[display(fmt = "LegacyBuckConfigBaseForCellKey({})", "self.cell_name")]
+struct LegacyBuckConfigBaseForCellKey {
     cell_name: CellName,
 }
 
 #[async_trait]
-impl Key for LegacyBuckConfigForCellKey {
+impl Key for LegacyBuckConfigBaseForCellKey {
     type Value = buck2_error::Result<LegacyBuckConfig>;
 
     async fn compute(
@@ -242,7 +274,85 @@ impl Key for LegacyBuckConfigForCellKey {
     }
 }
 
-/// The computation `LegacyBuckConfigForCellKey` computation might encounter an error.
+/// The config of a cell with the overlay of the command applied. The digest of the overlay is
+/// part of the key, so that commands with different overlays don't invalidate each other.
+#[derive(Clone, Display, Debug, Hash, Eq, PartialEq, Allocative)]
+#[display(fmt = "LegacyBuckConfigForCellKey({}, {})", cell_name, overlay)]
+struct LegacyBuckConfigForCellKey {
+    cell_name: CellName,
+    overlay: LegacyConfigOverlayDigest,
+}
+
+#[async_trait]
+impl Key for LegacyBuckConfigForCellKey {
+    type Value = buck2_error::Result<LegacyBuckConfig>;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> buck2_error::Result<LegacyBuckConfig> {
+        let base = ctx
+            .compute(&LegacyBuckConfigBaseForCellKey {
+                cell_name: self.cell_name,
+            })
+            .await??;
+        let overlay = ctx.get_injected_legacy_config_overlay().await?;
+        if overlay.digest() != self.overlay {
+            return Err(
+                internal_error!("`{}` computed with overlay `{}`", self, overlay.digest()).into(),
+            );
+        }
+        let cells = ctx.get_cell_resolver().await?;
+        Ok(base.with_overlay(&overlay, cells.get(self.cell_name)?.path())?)
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        match (x, y) {
+            (Ok(x), Ok(y)) => x.compare(y),
+            _ => false,
+        }
+    }
+}
+
+/// The config of a cell, which is only considered changed when a section of `group` changes.
+#[derive(Clone, Dupe, Display, Debug, Hash, Eq, PartialEq, Allocative)]
+#[display(fmt = "LegacyBuckConfigSectionGroupKey({}, {})", cell_name, group)]
+struct LegacyBuckConfigSectionGroupKey {
+    cell_name: CellName,
+    group: ConfigSectionGroup,
+}
+
+#[derive(Clone, Dupe, Allocative)]
+struct SectionGroupConfig {
+    group: ConfigSectionGroup,
+    config: LegacyBuckConfig,
+}
+
+#[async_trait]
+impl Key for LegacyBuckConfigSectionGroupKey {
+    type Value = buck2_error::Result<SectionGroupConfig>;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> buck2_error::Result<SectionGroupConfig> {
+        Ok(SectionGroupConfig {
+            group: self.group,
+            config: ctx.get_legacy_config_for_cell(self.cell_name).await?,
+        })
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        match (x, y) {
+            (Ok(x), Ok(y)) => x.group == y.group && !x.group.changed(&x.config, &y.config),
+            _ => false,
+        }
+    }
+}
+
+/// The computation `LegacyBuckConfigSectionGroupKey` computation might encounter an error.
 ///
 /// We can't return that error immediately, because we only compute the opaque value. We could
 /// return the error when doing the projection to the buckconfig values, but that would result in us
@@ -253,12 +363,12 @@ impl Key for LegacyBuckConfigForCellKey {
 struct LegacyBuckConfigErrorKey();
 
 impl ProjectionKey for LegacyBuckConfigErrorKey {
-    type DeriveFromKey = LegacyBuckConfigForCellKey;
+    type DeriveFromKey = LegacyBuckConfigSectionGroupKey;
     type Value = Option<buck2_error::Error>;
 
     fn compute(
         &self,
-        config: &buck2_error::Result<LegacyBuckConfig>,
+        config: &buck2_error::Result<SectionGroupConfig>,
         _ctx: &DiceProjectionComputations,
     ) -> Option<buck2_error::Error> {
         config.as_ref().err().cloned()
@@ -277,17 +387,19 @@ struct LegacyBuckConfigPropertyProjectionKey {
 }
 
 impl ProjectionKey for LegacyBuckConfigPropertyProjectionKey {
-    type DeriveFromKey = LegacyBuckConfigForCellKey;
+    type DeriveFromKey = LegacyBuckConfigSectionGroupKey;
     type Value = Option<Arc<str>>;
 
     fn compute(
         &self,
-        config: &buck2_error::Result<LegacyBuckConfig>,
+        config: &buck2_error::Result<SectionGroupConfig>,
         _ctx: &DiceProjectionComputations,
     ) -> Option<Arc<str>> {
         // See the comment in `LegacyBuckConfigErrorKey` for why this is safe
         let config = config.as_ref().unwrap();
+        debug_assert_eq!(config.group, ConfigSectionGroup::of_section(&self.section));
         config
+            .config
             .get(BuckconfigKeyRef {
                 section: &self.section,
                 property: &self.property,
@@ -311,16 +423,14 @@ impl HasInjectedLegacyConfigs for DiceComputations<'_> {
         Ok(self.compute(&LegacyBuckConfigKey).await?.is_some())
     }
 
-    async fn get_injected_legacy_config_overrides(
-        &mut self,
-    ) -> anyhow::Result<Arc<[ResolvedLegacyConfigArg]>> {
-        self.compute(&LegacyBuckConfigOverridesKey).await?.ok_or_else(|| {
-            panic!("Tried to retrieve LegacyBuckConfigOverridesKey from the graph, but key has None value")
+    async fn get_injected_legacy_config_overlay(&mut self) -> anyhow::Result<LegacyConfigOverlay> {
+        self.compute(&LegacyBuckConfigOverlayKey).await?.ok_or_else(|| {
+            panic!("Tried to retrieve LegacyBuckConfigOverlayKey from the graph, but key has None value")
         })
     }
 
-    async fn is_injected_legacy_config_override_key_set(&mut self) -> anyhow::Result<bool> {
-        Ok(self.compute(&LegacyBuckConfigOverridesKey).await?.is_some())
+    async fn is_injected_legacy_config_overlay_key_set(&mut self) -> anyhow::Result<bool> {
+        Ok(self.compute(&LegacyBuckConfigOverlayKey).await?.is_some())
     }
 }
 
@@ -330,10 +440,16 @@ impl HasLegacyConfigs for DiceComputations<'_> {
         &mut self,
         cell_name: CellName,
     ) -> anyhow::Result<OpaqueLegacyBuckConfigOnDice> {
-        let config = self
-            .compute_opaque(&LegacyBuckConfigForCellKey { cell_name })
-            .await?;
-        if let Some(error) = self.projection(&config, &LegacyBuckConfigErrorKey())? {
+        let key = |group| LegacyBuckConfigSectionGroupKey { cell_name, group };
+        let config = SectionGroupConfigs {
+            alias: self.compute_opaque(&key(ConfigSectionGroup::Alias)).await?,
+            client_only: self
+                .compute_opaque(&key(ConfigSectionGroup::ClientOnly))
+                .await?,
+            graph: self.compute_opaque(&key(ConfigSectionGroup::Graph)).await?,
+        };
+        // All groups are computed from the same config, so they have the same error.
+        if let Some(error) = self.projection(&config.graph, &LegacyBuckConfigErrorKey())? {
             return Err(error.into());
         }
         Ok(OpaqueLegacyBuckConfigOnDice {
@@ -353,10 +469,22 @@ impl HasLegacyConfigs for DiceComputations<'_> {
         &mut self,
         cell_name: CellName,
     ) -> buck2_error::Result<LegacyBuckConfig> {
-        self.compute(&LegacyBuckConfigForCellKey { cell_name })
+        let overlay = self.get_injected_legacy_config_overlay().await?.digest();
+        self.compute(&LegacyBuckConfigForCellKey { cell_name, overlay })
             .await?
     }
 
+    async fn get_legacy_config_section_group(
+        &mut self,
+        cell_name: CellName,
+        group: ConfigSectionGroup,
+    ) -> buck2_error::Result<LegacyBuckConfig> {
+        Ok(self
+            .compute(&LegacyBuckConfigSectionGroupKey { cell_name, group })
+            .await??
+            .config)
+    }
+
     async fn get_legacy_config_property(
         &mut self,
         cell_name: CellName,
@@ -394,27 +522,48 @@ impl SetLegacyConfigs for DiceTransactionUpdater {
         Ok(self.changed_to(vec![(LegacyBuckConfigKey, None)])?)
     }
 
-    fn set_legacy_config_overrides(
-        &mut self,
-        overrides: Arc<[ResolvedLegacyConfigArg]>,
-    ) -> anyhow::Result<()> {
-        Ok(self.changed_to(vec![(LegacyBuckConfigOverridesKey, Some(overrides))])?)
+    fn set_legacy_config_overlay(&mut self, overlay: LegacyConfigOverlay) -> anyhow::Result<()> {
+        Ok(self.changed_to(vec![(LegacyBuckConfigOverlayKey, Some(overlay))])?)
     }
 
-    fn set_none_legacy_config_overrides(&mut self) -> anyhow::Result<()> {
-        Ok(self.changed_to(vec![(LegacyBuckConfigOverridesKey, None)])?)
+    fn set_none_legacy_config_overlay(&mut self) -> anyhow::Result<()> {
+        Ok(self.changed_to(vec![(LegacyBuckConfigOverlayKey, None)])?)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use allocative::Allocative;
+    use async_trait::async_trait;
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
     use buck2_core::cells::name::CellName;
+    use buck2_core::cells::CellResolver;
+    use buck2_core::target_aliases::TargetAliasResolver;
+    use buck2_futures::cancellation::CancellationContext;
+    use derive_more::Display;
+    use dice::DetectCycles;
+    use dice::Dice;
+    use dice::DiceComputations;
+    use dice::DiceTransaction;
     use dice::InjectedKey;
+    use dice::Key;
 
+    use crate::dice::cells::SetCellResolver;
+    use crate::legacy_configs::dice::HasLegacyConfigs;
+    use crate::legacy_configs::dice::LegacyBuckConfigBaseForCellKey;
     use crate::legacy_configs::dice::LegacyBuckConfigKey;
+    use crate::legacy_configs::dice::SetLegacyConfigs;
+    use crate::legacy_configs::key::BuckconfigKeyRef;
+    use crate::legacy_configs::testing::parse;
+    use crate::legacy_configs::testing::parse_overlay;
     use crate::legacy_configs::testing::parse_with_config_args;
     use crate::legacy_configs::LegacyBuckConfigs;
     use crate::legacy_configs::LegacyConfigCmdArg;
+    use crate::target_aliases::HasTargetAliasResolver;
 
     #[test]
     fn config_equals() -> anyhow::Result<()> {
@@ -489,4 +638,199 @@ mod tests {
 
         Ok(())
     }
+    fn root() -> CellName {
+        CellName::testing_new("root")
+    }
+
+    /// Reads a buckconfig property, like a target node would. Each computation gets a new number,
+    /// so that recomputations can be told apart from cached values.
+    #[derive(Clone, Display, Debug, Hash, Eq, PartialEq, Allocative)]
+    #[display(fmt = "ReadProperty({}.{})", section, property)]
+    struct ReadProperty {
+        section: String,
+        property: String,
+    }
+
+    impl ReadProperty {
+        fn new(section: &str, property: &str) -> Self {
+            ReadProperty {
+                section: section.to_owned(),
+                property: property.to_owned(),
+            }
+        }
+    }
+
+    static COMPUTATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    #[async_trait]
+    impl Key for ReadProperty {
+        type Value = (Option<Arc<str>>, usize);
+
+        async fn compute(
+            &self,
+            ctx: &mut DiceComputations,
+            _cancellations: &CancellationContext,
+        ) -> Self::Value {
+            let value = ctx
+                .get_legacy_config_property(
+                    root(),
+                    BuckconfigKeyRef {
+                        section: &self.section,
+                        property: &self.property,
+                    },
+                )
+                .await
+                .unwrap();
+            (value, COMPUTATIONS.fetch_add(1, Ordering::SeqCst))
+        }
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    async fn commit_config(dice: &Arc<Dice>, config: &str) -> anyhow::Result<DiceTransaction> {
+        commit_config_with_overlay(dice, config, &[]).await
+    }
+
+    async fn commit_config_with_overlay(
+        dice: &Arc<Dice>,
+        config: &str,
+        config_args: &[LegacyConfigCmdArg],
+    ) -> anyhow::Result<DiceTransaction> {
+        let mut updater = dice.updater();
+        updater.set_cell_resolver(CellResolver::testing_with_name_and_path(
+            root(),
+            CellRootPathBuf::testing_new(""),
+        ))?;
+        updater.set_legacy_configs(LegacyBuckConfigs::new(hashmap![
+            root() => parse(&[("/config", config)], "/config")?,
+        ]))?;
+        updater.set_legacy_config_overlay(parse_overlay(&[], config_args)?)?;
+        Ok(updater.commit().await)
+    }
+
+    async fn read(ctx: &mut DiceTransaction, key: &ReadProperty) -> (Option<String>, usize) {
+        let (value, computation) = ctx.compute(key).await.unwrap();
+        (value.map(|v| v.to_string()), computation)
+    }
+
+    async fn resolve_alias(ctx: &mut DiceTransaction, alias: &str) -> Option<String> {
+        ctx.target_alias_resolver_for_cell(root())
+            .await
+            .unwrap()
+            .get(alias)
+            .unwrap()
+            .map(|s| s.to_owned())
+    }
+
+    #[tokio::test]
+    async fn test_section_groups_are_invalidated_separately() -> anyhow::Result<()> {
+        let dice = Dice::builder().build(DetectCycles::Enabled);
+        let buildfile = ReadProperty::new("buildfile", "name");
+        let unknown = ReadProperty::new("some_new_section", "x");
+
+        let mut ctx = commit_config(
+            &dice,
+            "[alias]\nfoo = //:foo\n[buildfile]\nname = BUCK\n[ui]\nsuperconsole = true",
+        )
+        .await?;
+        let (value, computation) = read(&mut ctx, &buildfile).await;
+        assert_eq!(Some("BUCK"), value.as_deref());
+        let (value, unknown_computation) = read(&mut ctx, &unknown).await;
+        assert_eq!(None, value);
+        assert_eq!(
+            Some("//:foo"),
+            resolve_alias(&mut ctx, "foo").await.as_deref()
+        );
+
+        // Aliases and client-only sections don't invalidate graph computations.
+        let mut ctx = commit_config(
+            &dice,
+            "[alias]\nfoo = //:bar\n[buildfile]\nname = BUCK\n[ui]\nsuperconsole = false",
+        )
+        .await?;
+        assert_eq!(
+            Some("//:bar"),
+            resolve_alias(&mut ctx, "foo").await.as_deref()
+        );
+        assert_eq!(computation, read(&mut ctx, &buildfile).await.1);
+        assert_eq!(unknown_computation, read(&mut ctx, &unknown).await.1);
+
+        // Graph sections do.
+        let mut ctx = commit_config(
+            &dice,
+            "[alias]\nfoo = //:bar\n[buildfile]\nname = TARGETS\n[ui]\nsuperconsole = false",
+        )
+        .await?;
+        let (value, new_computation) = read(&mut ctx, &buildfile).await;
+        assert_eq!(Some("TARGETS"), value.as_deref());
+        assert_ne!(computation, new_computation);
+        assert_eq!(
+            Some("//:bar"),
+            resolve_alias(&mut ctx, "foo").await.as_deref()
+        );
+
+        // So do sections that aren't classified.
+        let mut ctx = commit_config(
+            &dice,
+            "[alias]\nfoo = //:bar\n[buildfile]\nname = TARGETS\n[ui]\nsuperconsole = false\n[some_new_section]\nx = y",
+        )
+        .await?;
+        let (value, new_computation) = read(&mut ctx, &unknown).await;
+        assert_eq!(Some("y"), value.as_deref());
+        assert_ne!(unknown_computation, new_computation);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_overlays_over_shared_base() -> anyhow::Result<()> {
+        let dice = Dice::builder().build(DetectCycles::Enabled);
+        let config = "[buildfile]\nname = BUCK\n[ui]\nsuperconsole = true";
+        let buildfile = ReadProperty::new("buildfile", "name");
+        let superconsole = ReadProperty::new("ui", "superconsole");
+
+        // Two commands running at the same time, with different values on the command line.
+        let mut first = commit_config_with_overlay(
+            &dice,
+            config,
+            &[LegacyConfigCmdArg::flag("ui.superconsole=false")?],
+        )
+        .await?;
+        let (value, computation) = read(&mut first, &buildfile).await;
+        assert_eq!(Some("BUCK"), value.as_deref());
+        let mut second = commit_config_with_overlay(
+            &dice,
+            config,
+            &[LegacyConfigCmdArg::flag("ui.superconsole=auto")?],
+        )
+        .await?;
+
+        assert_eq!(
+            Some("auto"),
+            read(&mut second, &superconsole).await.0.as_deref()
+        );
+        assert_eq!(
+            Some("false"),
+            read(&mut first, &superconsole).await.0.as_deref()
+        );
+
+        // Both use the same parsed config, which doesn't have the overrides.
+        let base_key = LegacyBuckConfigBaseForCellKey { cell_name: root() };
+        let first_base = first.compute(&base_key).await??;
+        let second_base = second.compute(&base_key).await??;
+        assert!(Arc::ptr_eq(&first_base.0, &second_base.0));
+        assert_eq!(
+            Some("true"),
+            first_base.get(BuckconfigKeyRef {
+                section: "ui",
+                property: "superconsole",
+            })
+        );
+
+        // Computations which don't read overridden sections are shared too.
+        assert_eq!(computation, read(&mut second, &buildfile).await.1);
+        Ok(())
+    }
 }
@@ -7,12 +7,14 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::time::Duration;
 
 use allocative::Allocative;
 use anyhow::Context;
 use buck2_core::buck2_env;
+use buck2_http::HttpVersionPreference;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -54,8 +56,24 @@ pub struct HttpConfig {
     connect_timeout_ms: Option<u64>,
     read_timeout_ms: Option<u64>,
     write_timeout_ms: Option<u64>,
-    pub http2: bool,
+    request_timeout_ms: Option<u64>,
+    pub version_preference: HttpVersionPreference,
     pub max_redirects: Option<usize>,
+    /// How many times to retry GET and HEAD requests that failed with a transient error
+    /// (`http.max_retries`). Defaults to 2; set it to 0 to not retry.
+    pub max_retries: Option<usize>,
+    /// How long to wait before the first retry (`http.retry_initial_delay_ms`). The delay doubles
+    /// with every retry.
+    retry_initial_delay_ms: Option<u64>,
+    /// Host patterns requests may go to (`http.allowed_hosts`). Empty means any host.
+    pub allowed_hosts: Vec<String>,
+    /// Host patterns requests may not go to (`http.denied_hosts`).
+    pub denied_hosts: Vec<String>,
+    /// Schemes requests may use (`http.allowed_schemes`). Empty means any scheme.
+    pub allowed_schemes: Vec<String>,
+    /// Headers added to every request, as `Name: value` (`http.default_headers`). Repeat a name
+    /// to send several values.
+    pub default_headers: Vec<String>,
 }
 
 impl HttpConfig {
@@ -72,23 +90,63 @@ impl HttpConfig {
             section: "http",
             property: "write_timeout_ms",
         })?;
+        let request_timeout_ms = config.parse(BuckconfigKeyRef {
+            section: "http",
+            property: "request_timeout_ms",
+        })?;
         let max_redirects = config.parse(BuckconfigKeyRef {
             section: "http",
             property: "max_redirects",
         })?;
+        let max_retries = config.parse(BuckconfigKeyRef {
+            section: "http",
+            property: "max_retries",
+        })?;
+        let retry_initial_delay_ms = config.parse(BuckconfigKeyRef {
+            section: "http",
+            property: "retry_initial_delay_ms",
+        })?;
         let http2 = config
             .parse(BuckconfigKeyRef {
                 section: "http",
                 property: "http2",
             })?
             .unwrap_or(true);
+        // `http.version` supersedes `http.http2`.
+        let version_preference = config
+            .parse(BuckconfigKeyRef {
+                section: "http",
+                property: "version",
+            })?
+            .unwrap_or(if http2 {
+                HttpVersionPreference::Auto
+            } else {
+                HttpVersionPreference::Http1Only
+            });
+        let parse_list = |property| {
+            anyhow::Ok(
+                config
+                    .parse_list(BuckconfigKeyRef {
+                        section: "http",
+                        property,
+                    })?
+                    .unwrap_or_default(),
+            )
+        };
 
         Ok(Self {
             connect_timeout_ms,
             read_timeout_ms,
             write_timeout_ms,
+            request_timeout_ms,
             max_redirects,
-            http2,
+            max_retries,
+            retry_initial_delay_ms,
+            version_preference,
+            allowed_hosts: parse_list("allowed_hosts")?,
+            denied_hosts: parse_list("denied_hosts")?,
+            allowed_schemes: parse_list("allowed_schemes")?,
+            default_headers: parse_list("default_headers")?,
         })
     }
 
@@ -115,6 +173,20 @@ impl HttpConfig {
             None => Timeout::Default,
         }
     }
+
+    pub fn retry_initial_delay(&self) -> Option<Duration> {
+        self.retry_initial_delay_ms.map(Duration::from_millis)
+    }
+
+    /// Bounds how long it takes to get a response, redirects included (`http.request_timeout_ms`).
+    /// Defaults to 5 minutes; `0` disables it.
+    pub fn request_timeout(&self) -> Timeout {
+        match self.request_timeout_ms.map(Duration::from_millis) {
+            Some(Duration::ZERO) => Timeout::NoTimeout,
+            Some(value) => Timeout::Value(value),
+            None => Timeout::Default,
+        }
+    }
 }
 
 #[derive(
@@ -224,6 +296,12 @@ pub struct DaemonStartupConfig {
     pub materializations: Option<String>,
     pub http: HttpConfig,
     pub resource_control: ResourceControlConfig,
+    /// Environment variables read by `$(env)` references in the configs of all cells, with a
+    /// digest of their values, since this config is logged and the values may be secrets. The
+    /// daemon resolves configs in the environment it was started with, so it needs to restart
+    /// when these change. `new` only covers the config it is given, the caller adds the other
+    /// cells.
+    pub config_env: BTreeMap<String, Option<String>>,
 }
 
 impl DaemonStartupConfig {
@@ -265,9 +343,23 @@ impl DaemonStartupConfig {
                 .map(ToOwned::to_owned),
             http: HttpConfig::from_config(config)?,
             resource_control: ResourceControlConfig::from_config(config)?,
+            config_env: Self::config_env(config.env_dependencies()),
         })
     }
 
+    /// The `config_env` of configs depending on the environment variables `env_dependencies`.
+    pub(crate) fn config_env<'a>(
+        env_dependencies: impl IntoIterator<Item = (&'a str, Option<&'a str>)>,
+    ) -> BTreeMap<String, Option<String>> {
+        env_dependencies
+            .into_iter()
+            .map(|(var, value)| {
+                let digest = value.map(|value| blake3::hash(value.as_bytes()).to_hex().to_string());
+                (var.to_owned(), digest)
+            })
+            .collect()
+    }
+
     pub fn serialize(&self) -> anyhow::Result<String> {
         serde_json::to_string(&self).context("Error serializing DaemonStartupConfig")
     }
@@ -286,6 +378,7 @@ impl DaemonStartupConfig {
             materializations: None,
             http: HttpConfig::default(),
             resource_control: ResourceControlConfig::default(),
+            config_env: BTreeMap::new(),
         }
     }
 }
@@ -0,0 +1,435 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Config values passed on the command line, with `--config` and `--config-file`, form an
+//! overlay. The overlay of a command is applied on top of the configs parsed from the files of
+//! each cell when they are read, so that the parsed configs can be shared by all commands.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use allocative::Allocative;
+use buck2_core::cells::cell_root_path::CellRootPath;
+use buck2_core::cells::cell_root_path::CellRootPathBuf;
+use buck2_core::cells::CellResolver;
+use buck2_core::fs::project::ProjectRoot;
+use dupe::Dupe;
+
+use crate::legacy_configs::parser::resolve_layers;
+use crate::legacy_configs::parser::ConfigLayer;
+use crate::legacy_configs::parser::LayerValues;
+use crate::legacy_configs::parser::LegacyConfigParser;
+use crate::legacy_configs::ConfigArgumentParseError;
+use crate::legacy_configs::ConfigParserFileOps;
+use crate::legacy_configs::ConfigValue;
+use crate::legacy_configs::LegacyBuckConfig;
+use crate::legacy_configs::LegacyBuckConfigs;
+use crate::legacy_configs::Location;
+use crate::legacy_configs::ResolvedLegacyConfigArg;
+
+/// Identifies the values of an overlay, so that configs read with different overlays are cached
+/// separately.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Allocative)]
+pub struct LegacyConfigOverlayDigest([u8; 32]);
+
+impl fmt::Display for LegacyConfigOverlayDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.0[..8]))
+    }
+}
+
+#[derive(Debug, Allocative)]
+struct OverlayFlag {
+    section: String,
+    key: String,
+    /// `None` unsets the value.
+    value: Option<String>,
+    /// The cell this flag only applies to, if any.
+    cell: Option<CellRootPathBuf>,
+}
+
+#[derive(Debug, Allocative)]
+struct OverlayData {
+    /// The values of all the `--config-file`, read when the overlay is created.
+    files: LayerValues,
+    /// `--config`, in command line order.
+    flags: Vec<OverlayFlag>,
+    digest: LegacyConfigOverlayDigest,
+}
+
+/// The config values passed on the command line of a command.
+///
+/// `--config` always takes precedence over `--config-file`, whatever their order on the command
+/// line, and both take precedence over the configs of the cells.
+#[derive(Clone, Dupe, Debug, Allocative)]
+pub struct LegacyConfigOverlay(Arc<OverlayData>);
+
+impl LegacyConfigOverlay {
+    pub fn empty() -> Self {
+        Self::from_parts(LayerValues::default(), Vec::new())
+    }
+
+    /// Reads the `--config-file` passed on the command line. Those files are not read again when
+    /// the overlay is applied.
+    pub(crate) async fn new(
+        args: &[ResolvedLegacyConfigArg],
+        project_fs: &ProjectRoot,
+        file_ops: &mut dyn ConfigParserFileOps,
+        follow_includes: bool,
+    ) -> anyhow::Result<Self> {
+        let mut parser = LegacyConfigParser::new();
+        let mut flags = Vec::new();
+        for arg in args {
+            match arg {
+                ResolvedLegacyConfigArg::Flag(pair) => {
+                    for banned_section in ["repositories", "cells"] {
+                        if pair.section == banned_section {
+                            return Err(ConfigArgumentParseError::CellOverrideViaCliConfig(
+                                banned_section,
+                            )
+                            .into());
+                        }
+                    }
+                    let cell = match &pair.cell_path {
+                        Some(cell_path) => Some(CellRootPathBuf::new(
+                            project_fs.relativize(cell_path)?.into_owned(),
+                        )),
+                        None => None,
+                    };
+                    flags.push(OverlayFlag {
+                        section: pair.section.clone(),
+                        key: pair.key.clone(),
+                        value: pair.value.clone(),
+                        cell,
+                    });
+                }
+                ResolvedLegacyConfigArg::File(path) => {
+                    parser
+                        .parse_file(
+                            path,
+                            Some(Location::CommandLineArgument),
+                            follow_includes,
+                            file_ops,
+                        )
+                        .await?
+                }
+            }
+        }
+        Ok(Self::from_parts(parser.finish(), flags))
+    }
+
+    fn from_parts(files: LayerValues, flags: Vec<OverlayFlag>) -> Self {
+        fn update(hasher: &mut blake3::Hasher, field: Option<&str>) {
+            match field {
+                Some(field) => {
+                    hasher.update(&[1]);
+                    hasher.update(&(field.len() as u64).to_le_bytes());
+                    hasher.update(field.as_bytes());
+                }
+                None => {
+                    hasher.update(&[0]);
+                }
+            }
+        }
+
+        // Where the values come from doesn't matter, only what they are.
+        let mut hasher = blake3::Hasher::new();
+        for (section, key, value) in files.iter() {
+            update(&mut hasher, Some(section));
+            update(&mut hasher, Some(key));
+            update(&mut hasher, value);
+        }
+        hasher.update(&(flags.len() as u64).to_le_bytes());
+        for flag in &flags {
+            update(&mut hasher, Some(&flag.section));
+            update(&mut hasher, Some(&flag.key));
+            update(&mut hasher, flag.value.as_deref());
+            update(
+                &mut hasher,
+                flag.cell
+                    .as_ref()
+                    .map(|cell| cell.as_project_relative_path().as_str()),
+            );
+        }
+        let digest = LegacyConfigOverlayDigest(*hasher.finalize().as_bytes());
+
+        Self(Arc::new(OverlayData {
+            files,
+            flags,
+            digest,
+        }))
+    }
+
+    pub fn digest(&self) -> LegacyConfigOverlayDigest {
+        self.0.digest
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.files.is_empty() && self.0.flags.is_empty()
+    }
+
+    /// The layers this overlay adds to the config of the cell at `cell_path`.
+    fn layers(&self, cell_path: &CellRootPath) -> Vec<(ConfigLayer, LayerValues)> {
+        let mut flags = LayerValues::default();
+        for flag in &self.0.flags {
+            if flag.cell.as_deref().map_or(true, |cell| cell == cell_path) {
+                flags.set(
+                    &flag.section,
+                    &flag.key,
+                    flag.value.clone().map(ConfigValue::new_raw_arg),
+                );
+            }
+        }
+        vec![
+            (ConfigLayer::ConfigFileArg, self.0.files.clone()),
+            (ConfigLayer::CliFlag, flags),
+        ]
+    }
+}
+
+impl LegacyBuckConfig {
+    /// This config, as parsed from the files of the cell at `cell_path`, with `overlay` applied on
+    /// top of it. `self` is left untouched, so it can be shared with commands using other
+    /// overlays.
+    pub fn with_overlay(
+        &self,
+        overlay: &LegacyConfigOverlay,
+        cell_path: &CellRootPath,
+    ) -> anyhow::Result<LegacyBuckConfig> {
+        if overlay.is_empty() {
+            return Ok(self.dupe());
+        }
+        let mut layers = overlay.layers(cell_path);
+        // External configs are already merged under the repo ones in a parsed config.
+        layers.push((ConfigLayer::Repo, LayerValues::from_config(self)));
+        // Resolve `self` with the environment it was parsed in, the overlay in the process one.
+        resolve_layers(layers, &|var| match self.0.env_dependencies.get(var) {
+            Some(value) => value.clone(),
+            None => std::env::var(var).ok(),
+        })
+    }
+}
+
+impl LegacyBuckConfigs {
+    /// The configs of all cells, with `overlay` applied on top of them.
+    pub fn with_overlay(
+        &self,
+        overlay: &LegacyConfigOverlay,
+        cell_resolver: &CellResolver,
+    ) -> anyhow::Result<LegacyBuckConfigs> {
+        if overlay.is_empty() {
+            return Ok(self.dupe());
+        }
+        let configs = self
+            .iter()
+            .map(|(cell_name, config)| {
+                let cell_path = cell_resolver.get(cell_name)?.path();
+                Ok((cell_name, config.with_overlay(overlay, cell_path)?))
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+        Ok(LegacyBuckConfigs::new(configs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::OnceCell;
+    use std::sync::Arc;
+
+    use buck2_core::cells::cell_root_path::CellRootPath;
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+    use indoc::indoc;
+
+    use crate::legacy_configs::cells::create_project_filesystem;
+    use crate::legacy_configs::key::BuckconfigKeyRef;
+    use crate::legacy_configs::overlay::LegacyConfigOverlay;
+    use crate::legacy_configs::parser::ConfigLayer;
+    use crate::legacy_configs::testing::TestConfigParserFileOps;
+    use crate::legacy_configs::tests::assert_config_value;
+    use crate::legacy_configs::CellResolutionState;
+    use crate::legacy_configs::LegacyBuckConfig;
+    use crate::legacy_configs::LegacyBuckConfigLocation;
+    use crate::legacy_configs::LegacyConfigCmdArg;
+    use crate::legacy_configs::MainConfigFile;
+
+    fn abs_path(path: &str) -> String {
+        #[cfg(not(windows))]
+        return path.to_owned();
+        // Need to add some disk drive on Windows to make path absolute.
+        #[cfg(windows)]
+        return format!("C:{}", path);
+    }
+
+    fn file_ops() -> anyhow::Result<TestConfigParserFileOps> {
+        TestConfigParserFileOps::new(&[
+            (
+                "/external",
+                indoc!(
+                    r#"
+                        [s]
+                            a = external
+                            b = external
+                            c = external
+                            d = external
+                    "#
+                ),
+            ),
+            (
+                "/.buckconfig",
+                indoc!(
+                    r#"
+                        [s]
+                            b = repo
+                            c = repo
+                            d = repo
+                            ref = <$(config s.d)>
+                    "#
+                ),
+            ),
+            (
+                "/cli-config",
+                indoc!(
+                    r#"
+                        [s]
+                            c = file
+                            d = file
+                    "#
+                ),
+            ),
+        ])
+    }
+
+    fn parse_base(file_ops: &mut TestConfigParserFileOps) -> anyhow::Result<LegacyBuckConfig> {
+        // Listed in the wrong order, since the layer decides.
+        let main_config_files = [
+            MainConfigFile {
+                path: AbsNormPathBuf::from(abs_path("/.buckconfig"))?,
+                layer: ConfigLayer::Repo,
+            },
+            MainConfigFile {
+                path: AbsNormPathBuf::from(abs_path("/external"))?,
+                layer: ConfigLayer::External,
+            },
+        ];
+        futures::executor::block_on(LegacyBuckConfig::parse_with_file_ops_with_includes(
+            &main_config_files,
+            file_ops,
+            true,
+        ))
+    }
+
+    fn overlay(
+        file_ops: &mut TestConfigParserFileOps,
+        args: &[LegacyConfigCmdArg],
+    ) -> anyhow::Result<LegacyConfigOverlay> {
+        let project_fs = create_project_filesystem();
+        let cell_resolution = CellResolutionState {
+            project_filesystem: &project_fs,
+            cwd: project_fs.root(),
+            cell_resolver: OnceCell::new(),
+        };
+        let args = LegacyBuckConfig::process_config_args(args, &cell_resolution, file_ops)?;
+        futures::executor::block_on(LegacyConfigOverlay::new(&args, &project_fs, file_ops, true))
+    }
+
+    fn root() -> &'static CellRootPath {
+        CellRootPath::testing_new("")
+    }
+
+    #[test]
+    fn test_resolution_order() -> anyhow::Result<()> {
+        let mut file_ops = file_ops()?;
+        let base = parse_base(&mut file_ops)?;
+        assert_config_value(&base, "s", "a", "external");
+        assert_config_value(&base, "s", "b", "repo");
+        assert_config_value(&base, "s", "c", "repo");
+        assert_config_value(&base, "s", "d", "repo");
+        assert_config_value(&base, "s", "ref", "<repo>");
+
+        // The flag comes first on the command line, but still overrides the file.
+        let overlay = overlay(
+            &mut file_ops,
+            &[
+                LegacyConfigCmdArg::flag("s.d=flag")?,
+                LegacyConfigCmdArg::file(&abs_path("/cli-config"))?,
+            ],
+        )?;
+        let config = base.with_overlay(&overlay, root())?;
+        assert_config_value(&config, "s", "a", "external");
+        assert_config_value(&config, "s", "b", "repo");
+        assert_config_value(&config, "s", "c", "file");
+        assert_config_value(&config, "s", "d", "flag");
+        // References are resolved against the overridden values.
+        assert_config_value(&config, "s", "ref", "<flag>");
+        assert_eq!(
+            LegacyBuckConfigLocation::CommandLineArgument,
+            config
+                .get_section("s")
+                .unwrap()
+                .get("d")
+                .unwrap()
+                .location()
+        );
+
+        // Unsetting a value unsets it from all the lower layers.
+        let overlay = overlay(
+            &mut file_ops,
+            &[
+                LegacyConfigCmdArg::file(&abs_path("/cli-config"))?,
+                LegacyConfigCmdArg::flag("s.c=")?,
+            ],
+        )?;
+        let config = base.with_overlay(&overlay, root())?;
+        assert_eq!(
+            None,
+            config.get(BuckconfigKeyRef {
+                section: "s",
+                property: "c"
+            })
+        );
+        assert_config_value(&config, "s", "d", "file");
+        assert_config_value(&config, "s", "ref", "<file>");
+
+        // The base config is left untouched.
+        assert_config_value(&base, "s", "c", "repo");
+        assert_config_value(&base, "s", "ref", "<repo>");
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlays_share_base() -> anyhow::Result<()> {
+        let mut file_ops = file_ops()?;
+        let base = parse_base(&mut file_ops)?;
+
+        let overlay_a = overlay(&mut file_ops, &[LegacyConfigCmdArg::flag("s.a=a")?])?;
+        let overlay_b = overlay(
+            &mut file_ops,
+            &[LegacyConfigCmdArg::file(&abs_path("/cli-config"))?],
+        )?;
+        assert_ne!(overlay_a.digest(), overlay_b.digest());
+        assert_eq!(
+            overlay_a.digest(),
+            overlay(&mut file_ops, &[LegacyConfigCmdArg::flag("s.a=a")?])?.digest()
+        );
+
+        let config_a = base.with_overlay(&overlay_a, root())?;
+        let config_b = base.with_overlay(&overlay_b, root())?;
+        assert_config_value(&config_a, "s", "a", "a");
+        assert_config_value(&config_a, "s", "c", "repo");
+        assert_config_value(&config_b, "s", "a", "external");
+        assert_config_value(&config_b, "s", "c", "file");
+
+        // Without overlay, the base config is used as is.
+        let empty = overlay(&mut file_ops, &[])?;
+        assert_eq!(LegacyConfigOverlay::empty().digest(), empty.digest());
+        assert!(Arc::ptr_eq(&base.0, &base.with_overlay(&empty, root())?.0));
+        Ok(())
+    }
+}
@@ -10,9 +10,9 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
+use allocative::Allocative;
 use anyhow::Context;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
-use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::paths::RelativePath;
 use dupe::Dupe;
 use futures::future::BoxFuture;
@@ -23,8 +23,6 @@ use regex::Regex;
 use starlark_map::sorted_map::SortedMap;
 
 use crate::legacy_configs::parser::resolver::ConfigResolver;
-use crate::legacy_configs::ConfigArgumentPair;
-use crate::legacy_configs::ConfigArgumentParseError;
 use crate::legacy_configs::ConfigData;
 use crate::legacy_configs::ConfigFileLocation;
 use crate::legacy_configs::ConfigFileLocationWithLine;
@@ -33,6 +31,7 @@ use crate::legacy_configs::ConfigValue;
 use crate::legacy_configs::LegacyBuckConfig;
 use crate::legacy_configs::LegacyBuckConfigSection;
 use crate::legacy_configs::Location;
+use crate::legacy_configs::ResolvedValue;
 
 mod resolver;
 
@@ -52,6 +51,15 @@ enum ConfigError {
     InvalidLine(String),
     #[error("Detected cycles in buckconfig $(config) references: {}", format_cycle(.0))]
     ReferenceCycle(Vec<(String, String)>),
+    #[error(
+        "Environment variable `{var}` referenced by buckconfig `{section}.{key}` {location} is not set, and no default was given"
+    )]
+    MissingEnvVar {
+        var: String,
+        section: String,
+        key: String,
+        location: String,
+    },
 }
 
 fn format_cycle(cycle: &[(String, String)]) -> String {
@@ -74,6 +82,28 @@ impl SectionBuilder {
     }
 }
 
+impl ConfigValue {
+    /// A copy of this value that gets resolved again, since the values it references might be
+    /// overridden.
+    fn unresolved(&self) -> ConfigValue {
+        ConfigValue {
+            raw_value: self.raw_value.clone(),
+            resolved_value: ResolvedValue::Unknown,
+            source: self.source.clone(),
+            overridden: self.overridden.clone(),
+        }
+    }
+}
+
+/// Sets `key` to `value`, keeping track of the value it overrides, if any.
+fn insert_overriding(values: &mut BTreeMap<String, ConfigValue>, key: String, value: ConfigValue) {
+    let value = match values.remove(&key) {
+        Some(previous) => value.overriding(previous),
+        None => value,
+    };
+    values.insert(key, value);
+}
+
 pub(crate) struct LegacyConfigParser {
     include_stack: Vec<ConfigFileLocationWithLine>,
     current_file: Option<Arc<ConfigFileLocation>>,
@@ -168,37 +198,6 @@ impl LegacyConfigParser {
         }
     }
 
-    pub(crate) fn apply_config_arg(
-        &mut self,
-        config_pair: &ConfigArgumentPair,
-        current_cell_path: AbsNormPathBuf,
-    ) -> anyhow::Result<()> {
-        for banned_section in ["repositories", "cells"] {
-            if config_pair.section == banned_section {
-                return Err(
-                    ConfigArgumentParseError::CellOverrideViaCliConfig(banned_section).into(),
-                );
-            };
-        }
-        let pair = config_pair.to_owned();
-        let cell_matches = pair.cell_path == Some(current_cell_path) || pair.cell_path.is_none();
-        if cell_matches {
-            let config_section = self
-                .values
-                .entry(pair.section)
-                .or_insert_with(SectionBuilder::default);
-
-            match pair.value {
-                Some(raw_value) => {
-                    let config_value = ConfigValue::new_raw_arg(raw_value);
-                    config_section.values.insert(pair.key, config_value)
-                }
-                None => config_section.values.remove(&pair.key),
-            };
-        }
-        Ok(())
-    }
-
     fn parse_file_on_stack<'a>(
         &'a mut self,
         path: &'a AbsNormPath,
@@ -284,10 +283,8 @@ impl LegacyConfigParser {
                 if key.is_empty() {
                     return Err(anyhow::anyhow!(ConfigError::EmptyKey(line.to_owned())));
                 }
-                self.current_section.1.insert(
-                    key.to_owned(),
-                    ConfigValue::new_raw(self.location(i), val.to_owned()),
-                );
+                let value = ConfigValue::new_raw(self.location(i), val.to_owned());
+                insert_overriding(&mut self.current_section.1, key.to_owned(), value);
             } else if let Some(m) = FILE_INCLUDE.captures(&line) {
                 if parse_includes {
                     let include = m.name("include").unwrap().as_str();
@@ -345,7 +342,7 @@ impl LegacyConfigParser {
             .entry(section)
             .or_insert_with(SectionBuilder::default);
         values.into_iter().for_each(|(k, v)| {
-            committed.values.insert(k, v);
+            insert_overriding(&mut committed.values, k, v);
         });
     }
 
@@ -356,11 +353,124 @@ impl LegacyConfigParser {
         self.commit_section(section);
     }
 
-    pub(crate) fn finish(self) -> anyhow::Result<LegacyBuckConfig> {
+    /// The values of the parsed files, to be merged with the other layers by `resolve_layers`.
+    pub(crate) fn finish(self) -> LayerValues {
         let LegacyConfigParser { values, .. } = self;
+        LayerValues {
+            values: values
+                .into_iter()
+                .map(|(section, builder)| {
+                    let values = builder
+                        .values
+                        .into_iter()
+                        .map(|(key, value)| (key, Some(value)))
+                        .collect();
+                    (section, values)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Where buckconfig values come from, from lowest to highest precedence.
+#[derive(Copy, Clone, Dupe, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub(crate) enum ConfigLayer {
+    /// Global and user configs, outside of the project.
+    External,
+    /// The configs of the cell: `.buckconfig.d`, `.buckconfig` and `.buckconfig.local`.
+    Repo,
+    /// `--config-file`.
+    ConfigFileArg,
+    /// `--config`.
+    CliFlag,
+}
+
+/// The values set by one layer, before `$(config)` references are resolved.
+#[derive(Clone, Debug, Default, Allocative)]
+pub(crate) struct LayerValues {
+    /// `None` unsets the value set by lower layers.
+    values: BTreeMap<String, BTreeMap<String, Option<ConfigValue>>>,
+}
+
+impl LayerValues {
+    /// The values of a resolved config, as a single layer, so that other layers can be applied
+    /// on top of it.
+    pub(crate) fn from_config(config: &LegacyBuckConfig) -> LayerValues {
+        LayerValues {
+            values: config
+                .0
+                .values
+                .iter()
+                .map(|(section, section_values)| {
+                    let values = section_values
+                        .values
+                        .iter()
+                        .map(|(key, value)| (key.clone(), Some(value.unresolved())))
+                        .collect();
+                    (section.clone(), values)
+                })
+                .collect(),
+        }
+    }
 
-        let values = ConfigResolver::resolve(values)?;
+    pub(crate) fn set(&mut self, section: &str, key: &str, value: Option<ConfigValue>) {
+        let values = self.values.entry(section.to_owned()).or_default();
+        let value = match (values.remove(key), value) {
+            (Some(Some(previous)), Some(value)) => Some(value.overriding(previous)),
+            (_, value) => value,
+        };
+        values.insert(key.to_owned(), value);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
 
-        Ok(LegacyBuckConfig(Arc::new(ConfigData { values })))
+    /// Entries as `(section, key, raw value)`, `None` for unset values.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &str, Option<&str>)> {
+        self.values.iter().flat_map(|(section, values)| {
+            values.iter().map(move |(key, value)| {
+                (
+                    section.as_str(),
+                    key.as_str(),
+                    value.as_ref().map(|v| v.raw_value()),
+                )
+            })
+        })
     }
 }
+
+/// Merges the layers, higher layers overriding lower ones whatever the order they are passed in,
+/// and resolves `$(config)` and `$(env)` references in the result, looking up environment
+/// variables with `env_var`. Layers of the same kind are applied in the
+/// order they are passed in.
+///
+/// This is the only place where precedence between layers is decided.
+pub(crate) fn resolve_layers(
+    mut layers: Vec<(ConfigLayer, LayerValues)>,
+    env_var: &dyn Fn(&str) -> Option<String>,
+) -> anyhow::Result<LegacyBuckConfig> {
+    // Stable, so that the order within a layer is preserved.
+    layers.sort_by_key(|(layer, _)| *layer);
+
+    let mut values: BTreeMap<String, SectionBuilder> = BTreeMap::new();
+    for (_, layer) in layers {
+        for (section, section_values) in layer.values {
+            let merged = values.entry(section).or_default();
+            for (key, value) in section_values {
+                match value {
+                    Some(value) => insert_overriding(&mut merged.values, key, value),
+                    None => {
+                        merged.values.remove(&key);
+                    }
+                };
+            }
+        }
+    }
+
+    let (values, env_dependencies) = ConfigResolver::resolve(values, env_var)?;
+    Ok(LegacyBuckConfig(Arc::new(ConfigData {
+        values,
+        env_dependencies,
+    })))
+}
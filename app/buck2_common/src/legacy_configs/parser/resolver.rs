@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 
 use once_cell::sync::Lazy;
@@ -17,6 +18,7 @@ use crate::legacy_configs::parse_config_section_and_key;
 use crate::legacy_configs::parser::ConfigError;
 use crate::legacy_configs::parser::SectionBuilder;
 use crate::legacy_configs::LegacyBuckConfigSection;
+use crate::legacy_configs::Location;
 use crate::legacy_configs::ResolvedValue;
 
 // Since we can't change other entries in values while we iterate over the configuration, we use
@@ -108,19 +110,35 @@ impl ResolvedItems {
     }
 }
 
-pub struct ConfigResolver {
+pub struct ConfigResolver<'e> {
     values: BTreeMap<String, SectionBuilder>,
+    /// Looks up the environment variables read by `$(env)` references.
+    env_var: &'e dyn Fn(&str) -> Option<String>,
+    /// Environment variables read by `$(env)` references, with the values they had.
+    env_dependencies: RefCell<BTreeMap<String, Option<String>>>,
 }
 
-impl ConfigResolver {
+impl<'e> ConfigResolver<'e> {
+    /// Resolves all the references in `values`, looking up `$(env)` references with `env_var`,
+    /// and returns the resolved sections along with the environment variables the resolution
+    /// depended on.
     #[allow(clippy::from_iter_instead_of_collect)]
     pub fn resolve(
         values: BTreeMap<String, SectionBuilder>,
-    ) -> anyhow::Result<SortedMap<String, LegacyBuckConfigSection>> {
-        let mut resolver = Self { values };
+        env_var: &'e dyn Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<(
+        SortedMap<String, LegacyBuckConfigSection>,
+        SortedMap<String, Option<String>>,
+    )> {
+        let mut resolver = Self {
+            values,
+            env_var,
+            env_dependencies: RefCell::new(BTreeMap::new()),
+        };
         resolver.resolve_all()?;
-        Ok(SortedMap::from_iter(
-            resolver.values.into_iter().map(|(k, v)| (k, v.finish())),
+        Ok((
+            SortedMap::from_iter(resolver.values.into_iter().map(|(k, v)| (k, v.finish()))),
+            SortedMap::from_iter(resolver.env_dependencies.into_inner()),
         ))
     }
 
@@ -147,8 +165,13 @@ impl ConfigResolver {
         Ok(())
     }
 
+    /// Matches `$(config section.key)` and `$(env VAR_NAME default)`, where the default is
+    /// optional. Anything else, including `$(env)` without a valid variable name, is left as is.
     fn regex() -> &'static Regex {
-        static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\(config ([^)]*)\)").unwrap());
+        static RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"\$\((?:config ([^)]*)|env ([A-Za-z_][A-Za-z0-9_]*)(?: ([^)]*))?)\)")
+                .unwrap()
+        });
         &RE
     }
 
@@ -158,10 +181,10 @@ impl ConfigResolver {
         section: &str,
         key: &str,
     ) -> anyhow::Result<&'a str> {
-        let raw_value = match self.values.get(section).and_then(|e| e.values.get(key)) {
+        let (raw_value, source) = match self.values.get(section).and_then(|e| e.values.get(key)) {
             None => return Ok(""),
             Some(v) => match &v.resolved_value {
-                ResolvedValue::Unknown => v.raw_value(),
+                ResolvedValue::Unknown => (v.raw_value(), &v.source),
                 ResolvedValue::Literal => {
                     return Ok(v.raw_value());
                 }
@@ -173,7 +196,7 @@ impl ConfigResolver {
 
         if resolved_items.get(section, key).is_none() {
             resolved_items.start_resolving(section, key)?;
-            let v = self.do_resolve(resolved_items, raw_value)?;
+            let v = self.do_resolve(resolved_items, section, key, source, raw_value)?;
             resolved_items.finish_resolving(section, key, v);
         }
 
@@ -183,34 +206,64 @@ impl ConfigResolver {
     fn do_resolve(
         &self,
         resolved_items: &mut ResolvedItems,
+        section: &str,
+        key: &str,
+        source: &Location,
         raw_value: &str,
     ) -> anyhow::Result<String> {
         let mut resolved = String::new();
         let mut last = 0;
 
-        let re = Self::regex();
-
         // TODO(cjhopman): Should add support for escaping the call, I guess.
-        for capture in re.captures_iter(raw_value) {
-            let m = capture.get(0).unwrap();
+        for captures in Self::regex().captures_iter(raw_value) {
+            let m = captures.get(0).unwrap();
 
             resolved.push_str(&raw_value[last..m.start()]);
             last = m.end();
 
-            let captures = re.captures(m.as_str()).unwrap();
-
-            let config_key = captures.get(1).unwrap().as_str();
-
-            let config_section_and_key = parse_config_section_and_key(config_key, None)?;
-
-            resolved.push_str(self.resolve_item(
-                resolved_items,
-                &config_section_and_key.section,
-                &config_section_and_key.key,
-            )?);
+            if let Some(config_key) = captures.get(1) {
+                let config_section_and_key =
+                    parse_config_section_and_key(config_key.as_str(), None)?;
+
+                resolved.push_str(self.resolve_item(
+                    resolved_items,
+                    &config_section_and_key.section,
+                    &config_section_and_key.key,
+                )?);
+            } else {
+                let var = captures.get(2).unwrap().as_str();
+                let default = captures.get(3).map(|d| d.as_str());
+                resolved.push_str(&self.resolve_env(var, default, section, key, source)?);
+            }
         }
 
         resolved.push_str(&raw_value[last..]);
         Ok(resolved)
     }
+
+    fn resolve_env(
+        &self,
+        var: &str,
+        default: Option<&str>,
+        section: &str,
+        key: &str,
+        source: &Location,
+    ) -> anyhow::Result<String> {
+        let value = (self.env_var)(var);
+        self.env_dependencies
+            .borrow_mut()
+            .insert(var.to_owned(), value.clone());
+
+        match (value, default) {
+            (Some(value), _) => Ok(value),
+            (None, Some(default)) => Ok(default.to_owned()),
+            (None, None) => Err(ConfigError::MissingEnvVar {
+                var: var.to_owned(),
+                section: section.to_owned(),
+                key: key.to_owned(),
+                location: source.as_legacy_buck_config_location().to_string(),
+            }
+            .into()),
+        }
+    }
 }
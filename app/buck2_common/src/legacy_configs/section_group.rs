@@ -0,0 +1,134 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use allocative::Allocative;
+use derive_more::Display;
+use dupe::Dupe;
+
+use crate::legacy_configs::LegacyBuckConfig;
+
+/// Sections that only configure the client or how the daemon reports to it, and never affect the
+/// graph.
+const CLIENT_ONLY_SECTIONS: &[&str] = &["build_report", "client", "console", "log", "scuba", "ui"];
+
+/// Buckconfig sections are read on DICE through a separate key per group, so that a change to a
+/// section only invalidates computations that read sections of the same group.
+///
+/// Every lookup goes through the key of the section it reads, so getting the classification wrong
+/// only costs invalidations, but sections that are not listed must stay in `Graph`.
+#[derive(Copy, Clone, Dupe, Debug, Display, Eq, PartialEq, Hash, Allocative)]
+pub enum ConfigSectionGroup {
+    /// `[alias]`, only read by the target alias resolver.
+    Alias,
+    /// Sections only relevant to the client, see `CLIENT_ONLY_SECTIONS`.
+    ClientOnly,
+    /// Everything else, including sections this list doesn't know about.
+    Graph,
+}
+
+impl ConfigSectionGroup {
+    pub const ALL: [ConfigSectionGroup; 3] = [
+        ConfigSectionGroup::Alias,
+        ConfigSectionGroup::ClientOnly,
+        ConfigSectionGroup::Graph,
+    ];
+
+    pub fn of_section(section: &str) -> ConfigSectionGroup {
+        if section == "alias" {
+            ConfigSectionGroup::Alias
+        } else if CLIENT_ONLY_SECTIONS.contains(&section) {
+            ConfigSectionGroup::ClientOnly
+        } else {
+            ConfigSectionGroup::Graph
+        }
+    }
+
+    /// Whether the sections of this group differ between the two configs.
+    pub fn changed(self, x: &LegacyBuckConfig, y: &LegacyBuckConfig) -> bool {
+        x.changed_sections(y)
+            .into_iter()
+            .any(|section| ConfigSectionGroup::of_section(section) == self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::legacy_configs::section_group::ConfigSectionGroup;
+    use crate::legacy_configs::testing::parse;
+
+    #[test]
+    fn test_of_section() {
+        assert_eq!(
+            ConfigSectionGroup::Alias,
+            ConfigSectionGroup::of_section("alias")
+        );
+        assert_eq!(
+            ConfigSectionGroup::ClientOnly,
+            ConfigSectionGroup::of_section("ui")
+        );
+        assert_eq!(
+            ConfigSectionGroup::Graph,
+            ConfigSectionGroup::of_section("buildfile")
+        );
+        assert_eq!(
+            ConfigSectionGroup::Graph,
+            ConfigSectionGroup::of_section("aliases")
+        );
+        assert_eq!(
+            ConfigSectionGroup::Graph,
+            ConfigSectionGroup::of_section("some_unknown_section")
+        );
+    }
+
+    #[test]
+    fn test_changed() -> anyhow::Result<()> {
+        let base = parse(
+            &[(
+                "/config",
+                "[alias]\nfoo = //:foo\n[ui]\nsuperconsole = true\n[cxx]\ncc = gcc",
+            )],
+            "/config",
+        )?;
+        let alias = parse(
+            &[(
+                "/config",
+                "[alias]\nfoo = //:bar\n[ui]\nsuperconsole = true\n[cxx]\ncc = gcc",
+            )],
+            "/config",
+        )?;
+        let ui = parse(
+            &[(
+                "/config",
+                "[alias]\nfoo = //:foo\n[ui]\nsuperconsole = false\n[cxx]\ncc = gcc",
+            )],
+            "/config",
+        )?;
+        let unknown = parse(
+            &[(
+                "/config",
+                "[alias]\nfoo = //:foo\n[ui]\nsuperconsole = true\n[cxx]\ncc = gcc\n[new_section]\nx = y",
+            )],
+            "/config",
+        )?;
+
+        let changed = |x, y| {
+            ConfigSectionGroup::ALL
+                .into_iter()
+                .filter(|group| group.changed(x, y))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(Vec::<ConfigSectionGroup>::new(), changed(&base, &base));
+        assert_eq!(vec![ConfigSectionGroup::Alias], changed(&base, &alias));
+        assert_eq!(vec![ConfigSectionGroup::ClientOnly], changed(&base, &ui));
+        assert_eq!(vec![ConfigSectionGroup::Graph], changed(&base, &unknown));
+        assert_eq!(vec![ConfigSectionGroup::Graph], changed(&unknown, &base));
+        Ok(())
+    }
+}
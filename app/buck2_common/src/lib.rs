@@ -39,6 +39,7 @@ pub mod http;
 pub mod ignores;
 pub mod invocation_paths;
 pub mod invocation_roots;
+pub mod invocation_scratch_dir;
 pub mod io;
 pub mod kill_util;
 pub mod legacy_configs;
@@ -52,4 +53,5 @@ pub mod scope;
 pub mod sqlite;
 pub mod systemd;
 pub mod target_aliases;
+pub mod target_progress;
 pub mod temp_path;
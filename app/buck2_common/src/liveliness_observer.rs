@@ -14,6 +14,7 @@ use async_trait::async_trait;
 use dupe::Dupe;
 use futures::future::FutureExt;
 use futures::future::Shared;
+use parking_lot::Mutex;
 use tokio::sync::OwnedRwLockWriteGuard;
 use tokio::sync::RwLock;
 use tokio::time::Sleep;
@@ -38,6 +39,12 @@ struct NotAlive;
 pub trait LivelinessObserver: Send + Sync {
     /// Pending while we are alive. Ready when we aren't.
     async fn while_alive(&self);
+
+    /// A name for this observer, reported by `CompositeLivelinessObserver` when it is the one that
+    /// declared death. See `NamedLivelinessObserver`.
+    fn name(&self) -> Option<&'static str> {
+        None
+    }
 }
 
 pub trait LivelinessObserverSync: LivelinessObserver {
@@ -184,6 +191,90 @@ impl LivelinessObserver for Arc<dyn LivelinessObserver> {
     async fn while_alive(&self) {
         self.as_ref().while_alive().await
     }
+
+    fn name(&self) -> Option<&'static str> {
+        self.as_ref().name()
+    }
+}
+
+/// Gives a name to a LivelinessObserver, to tell which one declared death when several are
+/// combined with `CompositeLivelinessObserver`.
+pub struct NamedLivelinessObserver {
+    name: &'static str,
+    inner: Arc<dyn LivelinessObserver>,
+}
+
+impl NamedLivelinessObserver {
+    pub fn new(name: &'static str, inner: Arc<dyn LivelinessObserver>) -> Self {
+        Self { name, inner }
+    }
+
+    pub fn create(
+        name: &'static str,
+        inner: Arc<dyn LivelinessObserver>,
+    ) -> Arc<dyn LivelinessObserver> {
+        Arc::new(Self::new(name, inner)) as _
+    }
+}
+
+#[async_trait]
+impl LivelinessObserver for NamedLivelinessObserver {
+    async fn while_alive(&self) {
+        self.inner.while_alive().await
+    }
+
+    fn name(&self) -> Option<&'static str> {
+        Some(self.name)
+    }
+}
+
+/// Alive while all of its children are alive, and records which child declared death first.
+pub struct CompositeLivelinessObserver {
+    children: Vec<Arc<dyn LivelinessObserver>>,
+    reason: Mutex<Option<&'static str>>,
+}
+
+impl CompositeLivelinessObserver {
+    /// Reported for children that don't have a name.
+    pub const UNNAMED: &'static str = "unnamed";
+
+    pub fn join(v: Vec<Arc<dyn LivelinessObserver>>) -> Self {
+        Self {
+            children: v,
+            reason: Mutex::new(None),
+        }
+    }
+
+    /// Like `while_alive`, but resolves to the name of the child that declared death.
+    pub async fn while_alive_with_reason(&self) -> &'static str {
+        if self.children.is_empty() {
+            return futures::future::pending().await;
+        }
+
+        let (_, index, _) =
+            futures::future::select_all(self.children.iter().map(|c| c.while_alive())).await;
+        let name = self.children[index].name().unwrap_or(Self::UNNAMED);
+
+        let mut reason = self.reason.lock();
+        if reason.is_none() {
+            tracing::debug!(reason = name, "liveliness observer declared death");
+            *reason = Some(name);
+        }
+        name
+    }
+
+    /// The name of the first child that was observed to declare death, if `while_alive` has
+    /// resolved.
+    pub fn last_reason(&self) -> Option<&'static str> {
+        *self.reason.lock()
+    }
+}
+
+#[async_trait]
+impl LivelinessObserver for CompositeLivelinessObserver {
+    async fn while_alive(&self) {
+        self.while_alive_with_reason().await;
+    }
 }
 
 pub struct LivelinessAnd<A, B> {
@@ -222,7 +313,7 @@ where
 #[async_trait]
 impl LivelinessObserver for buck2_futures::cancellable_future::CancellationObserver {
     async fn while_alive(&self) {
-        self.dupe().await
+        self.dupe().await;
     }
 }
 
@@ -278,6 +369,59 @@ mod tests {
         assert!(!manager.is_alive().await);
     }
 
+    #[tokio::test]
+    async fn test_composite_reports_reason() {
+        for (trip_a, expected) in [(true, "a"), (false, "b")] {
+            let (observer_a, guard_a) = LivelinessGuard::create();
+            let (observer_b, guard_b) = LivelinessGuard::create();
+            let composite = CompositeLivelinessObserver::join(vec![
+                NamedLivelinessObserver::create("a", observer_a),
+                NamedLivelinessObserver::create("b", observer_b),
+            ]);
+
+            assert!((&composite as &dyn LivelinessObserver).is_alive().await);
+            assert_eq!(None, composite.last_reason());
+
+            let _still_alive = if trip_a {
+                drop(guard_a);
+                guard_b
+            } else {
+                drop(guard_b);
+                guard_a
+            };
+
+            assert_eq!(expected, composite.while_alive_with_reason().await);
+            assert_eq!(Some(expected), composite.last_reason());
+            assert!(!(&composite as &dyn LivelinessObserver).is_alive().await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_composite_keeps_first_reason() {
+        let (observer_a, guard_a) = LivelinessGuard::create();
+        let (observer_b, guard_b) = LivelinessGuard::create();
+        let composite = CompositeLivelinessObserver::join(vec![
+            observer_a,
+            NamedLivelinessObserver::create("b", observer_b),
+        ]);
+
+        drop(guard_b);
+        composite.while_alive().await;
+        drop(guard_a);
+        // Both are dead, the first child wins the select, but the reason stays the first one.
+        assert_eq!(
+            CompositeLivelinessObserver::UNNAMED,
+            composite.while_alive_with_reason().await
+        );
+        assert_eq!(Some("b"), composite.last_reason());
+    }
+
+    #[tokio::test]
+    async fn test_composite_empty_is_alive() {
+        let composite = CompositeLivelinessObserver::join(Vec::new());
+        assert!((&composite as &dyn LivelinessObserver).is_alive().await);
+    }
+
     #[tokio::test]
     async fn test_cancel_restore_forget() {
         let (manager, guard) = LivelinessGuard::create();
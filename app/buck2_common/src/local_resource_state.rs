@@ -7,15 +7,41 @@
  * of this source tree.
  */
 
+use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
 use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+use buck2_wrapper_common::invocation_id::TraceId;
+use buck2_wrapper_common::kill::process_exists;
+use buck2_wrapper_common::pid::Pid;
 use derivative::Derivative;
+use dupe::Dupe;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Mutex;
 
+use crate::liveliness_observer::LivelinessObserver;
+
+#[derive(Debug, buck2_error::Error)]
+enum LocalResourceStateError {
+    #[error(
+        "Timed out after {}s waiting for a local resource from `{}`. All of them are held:{}",
+        .timeout.as_secs(),
+        .source_target,
+        .holders.iter().map(|h| format!("\n  {}", h)).collect::<String>()
+    )]
+    #[buck2(input)]
+    Timeout {
+        source_target: ConfiguredTargetLabel,
+        timeout: Duration,
+        holders: Vec<LocalResourceHolderInfo>,
+    },
+}
+
 #[derive(Debug, PartialEq)]
 pub struct EnvironmentVariable {
     pub key: String,
@@ -26,34 +52,147 @@ pub struct EnvironmentVariable {
 #[derive(Debug, PartialEq)]
 pub struct LocalResource(pub Vec<EnvironmentVariable>);
 
+/// Who is acquiring a resource. Used to report who holds a resource when acquiring it times out,
+/// and to reclaim resources whose owner went away without releasing them.
+#[derive(Clone, Dupe)]
+pub struct LocalResourceOwner {
+    /// What the resource is used for, typically the executable being run.
+    pub command: Arc<str>,
+    pub trace_id: Option<TraceId>,
+    /// Once this reports that the owner is no longer alive, its resource can be reclaimed by
+    /// [`LocalResourceState::force_release`].
+    pub liveliness_observer: Option<Arc<dyn LivelinessObserver>>,
+}
+
+impl LocalResourceOwner {
+    pub fn testing_new(command: &str) -> Self {
+        Self {
+            command: command.into(),
+            trace_id: None,
+            liveliness_observer: None,
+        }
+    }
+}
+
+/// A resource that is currently held, as reported in diagnostics.
+#[derive(Debug, Clone)]
+pub struct LocalResourceHolderInfo {
+    pub command: Arc<str>,
+    pub trace_id: Option<TraceId>,
+    /// How long the resource has been held.
+    pub held_for: Duration,
+}
+
+impl fmt::Display for LocalResourceHolderInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`", self.command)?;
+        if let Some(trace_id) = &self.trace_id {
+            write!(f, " (trace id {})", trace_id)?;
+        }
+        write!(f, " for {}s", self.held_for.as_secs())
+    }
+}
+
+struct HolderRecord {
+    /// Identifies the acquisition, so that a holder whose resource was force released does not
+    /// release it again when dropped, while someone else holds it.
+    acquisition: u64,
+    owner: LocalResourceOwner,
+    since: Instant,
+    /// The process using the resource, once it has been spawned.
+    pid: Option<u32>,
+}
+
+impl HolderRecord {
+    fn info(&self) -> LocalResourceHolderInfo {
+        LocalResourceHolderInfo {
+            command: self.owner.command.dupe(),
+            trace_id: self.owner.trace_id.clone(),
+            held_for: self.since.elapsed(),
+        }
+    }
+}
+
+/// Who holds which resource, by index in the list of resources.
+#[derive(Default)]
+struct Holders {
+    next_acquisition: u64,
+    records: HashMap<usize, HolderRecord>,
+}
+
+impl Holders {
+    fn insert(&mut self, index: usize, owner: LocalResourceOwner) -> u64 {
+        let acquisition = self.next_acquisition;
+        self.next_acquisition += 1;
+        self.records.insert(
+            index,
+            HolderRecord {
+                acquisition,
+                owner,
+                since: Instant::now(),
+                pid: None,
+            },
+        );
+        acquisition
+    }
+
+    /// Returns whether the resource was still held by this acquisition.
+    fn remove(&mut self, index: usize, acquisition: u64) -> bool {
+        match self.records.get(&index) {
+            Some(record) if record.acquisition == acquisition => {
+                self.records.remove(&index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn infos(&self) -> Vec<LocalResourceHolderInfo> {
+        let mut records: Vec<_> = self.records.values().collect();
+        records.sort_by_key(|r| r.since);
+        records.into_iter().map(HolderRecord::info).collect()
+    }
+}
+
 /// RAII handle for resource spec, returns spec to the pool on drop.
 pub struct LocalResourceHolder {
-    // Optionality is only needed so we can move out the spec on drop.
-    spec: Option<LocalResource>,
-    sender: UnboundedSender<LocalResource>,
+    index: usize,
+    acquisition: u64,
+    specs: Arc<Vec<LocalResource>>,
+    holders: Arc<parking_lot::Mutex<Holders>>,
+    sender: UnboundedSender<usize>,
 }
 
 impl Drop for LocalResourceHolder {
     fn drop(&mut self) {
-        let _ignored = self.sender.send(
-            self.spec
-                .take()
-                .expect("Should only be absent in already dropped object."),
-        );
+        // If it was force released, it's no longer ours to return.
+        if self.holders.lock().remove(self.index, self.acquisition) {
+            let _ignored = self.sender.send(self.index);
+        }
+    }
+}
+
+impl LocalResourceHolder {
+    /// Records the process that uses the resource. The resource is not reclaimed while that
+    /// process is running, even if its owner is no longer alive.
+    pub fn set_pid(&self, pid: u32) {
+        if let Some(record) = self.holders.lock().records.get_mut(&self.index) {
+            if record.acquisition == self.acquisition {
+                record.pid = Some(pid);
+            }
+        }
     }
 }
 
 impl AsRef<LocalResource> for LocalResourceHolder {
     fn as_ref(&self) -> &LocalResource {
-        self.spec
-            .as_ref()
-            .expect("Should only be absent in already dropped object.")
+        &self.specs[self.index]
     }
 }
 
 /// Blocking resource pool to manage access to prepared local resources.
-#[derive(Clone, Debug, Derivative)]
-#[derivative(PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Derivative)]
+#[derivative(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct LocalResourceState {
     // Set of resources of same type should be uniquely identified by configured target label providing `LocalResourceInfo`.
     // This is the assumption for equiality, ordering and hash implementations.
@@ -71,14 +210,30 @@ pub struct LocalResourceState {
         PartialOrd = "ignore",
         Ord = "ignore"
     )]
-    sender: UnboundedSender<LocalResource>,
+    specs: Arc<Vec<LocalResource>>,
+    #[derivative(
+        Debug = "ignore",
+        Hash = "ignore",
+        PartialEq = "ignore",
+        PartialOrd = "ignore",
+        Ord = "ignore"
+    )]
+    holders: Arc<parking_lot::Mutex<Holders>>,
+    /// Indices in `specs` of the resources that are available.
     #[derivative(
         Hash = "ignore",
         PartialEq = "ignore",
         PartialOrd = "ignore",
         Ord = "ignore"
     )]
-    receiver: Arc<Mutex<UnboundedReceiver<LocalResource>>>,
+    sender: UnboundedSender<usize>,
+    #[derivative(
+        Hash = "ignore",
+        PartialEq = "ignore",
+        PartialOrd = "ignore",
+        Ord = "ignore"
+    )]
+    receiver: Arc<Mutex<UnboundedReceiver<usize>>>,
 }
 
 impl LocalResourceState {
@@ -88,14 +243,16 @@ impl LocalResourceState {
         specs: Vec<LocalResource>,
     ) -> Self {
         let (sender, receiver) = mpsc::unbounded_channel();
-        for spec in specs {
-            sender.send(spec).expect(
+        for index in 0..specs.len() {
+            sender.send(index).expect(
                 "Not expected send to fail when channel is not closed and receiver is not dropped.",
             );
         }
         LocalResourceState {
             source_target,
             owning_pid,
+            specs: Arc::new(specs),
+            holders: Arc::new(parking_lot::Mutex::new(Holders::default())),
             sender,
             receiver: Arc::new(Mutex::new(receiver)),
         }
@@ -111,53 +268,256 @@ impl LocalResourceState {
         self.owning_pid
     }
 
-    pub async fn acquire_resource(&self) -> LocalResourceHolder {
-        let spec = {
-            let mut guard = self.receiver.lock().await;
-            Some(guard.recv().await.unwrap())
+    /// Waits for a resource to be available.
+    ///
+    /// If none is available after `timeout`, resources of owners that are no longer alive are
+    /// reclaimed. If there are none, this fails with an error that lists who holds the resources.
+    pub async fn acquire_resource(
+        &self,
+        owner: LocalResourceOwner,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<LocalResourceHolder> {
+        let index = loop {
+            let recv = async {
+                let mut guard = self.receiver.lock().await;
+                guard.recv().await.unwrap()
+            };
+            let Some(timeout) = timeout else {
+                break recv.await;
+            };
+            if let Ok(index) = tokio::time::timeout(timeout, recv).await {
+                break index;
+            }
+            let reclaimed = self.force_release().await;
+            if reclaimed.is_empty() {
+                return Err(LocalResourceStateError::Timeout {
+                    source_target: self.source_target.dupe(),
+                    timeout,
+                    holders: self.holders(),
+                }
+                .into());
+            }
+            for holder in reclaimed {
+                tracing::warn!(
+                    "Reclaimed local resource from `{}` held by {}, which is no longer running",
+                    self.source_target,
+                    holder
+                );
+            }
         };
-        LocalResourceHolder {
-            spec,
+
+        let acquisition = self.holders.lock().insert(index, owner);
+        Ok(LocalResourceHolder {
+            index,
+            acquisition,
+            specs: self.specs.dupe(),
+            holders: self.holders.dupe(),
             sender: self.sender.clone(),
+        })
+    }
+
+    /// Who holds the resources that are currently in use, longest held first.
+    pub fn holders(&self) -> Vec<LocalResourceHolderInfo> {
+        self.holders.lock().infos()
+    }
+
+    /// Returns the resources whose owners are no longer alive to the pool, even though they were
+    /// not released, and returns who held them. Owners without a liveliness observer are assumed
+    /// to be alive, and so are resources whose process is still running.
+    pub async fn force_release(&self) -> Vec<LocalResourceHolderInfo> {
+        let candidates: Vec<(usize, u64, Option<u32>, Arc<dyn LivelinessObserver>)> = self
+            .holders
+            .lock()
+            .records
+            .iter()
+            .filter_map(|(index, record)| {
+                Some((
+                    *index,
+                    record.acquisition,
+                    record.pid,
+                    record.owner.liveliness_observer.as_ref()?.dupe(),
+                ))
+            })
+            .collect();
+
+        let mut released = Vec::new();
+        for (index, acquisition, pid, liveliness_observer) in candidates {
+            if liveliness_observer.is_alive().await || pid.is_some_and(is_running) {
+                continue;
+            }
+            let mut holders = self.holders.lock();
+            let Some(record) = holders.records.get(&index) else {
+                continue;
+            };
+            if record.acquisition != acquisition {
+                continue;
+            }
+            let info = record.info();
+            holders.remove(index, acquisition);
+            drop(holders);
+            let _ignored = self.sender.send(index);
+            released.push(info);
         }
+        released
     }
 }
 
+/// Errors are treated as running, so that a resource is never handed out while it may be in use.
+fn is_running(pid: u32) -> bool {
+    Pid::from_u32(pid).and_then(process_exists).unwrap_or(true)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use buck2_core::configuration::data::ConfigurationData;
     use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+    use buck2_wrapper_common::invocation_id::TraceId;
 
     use super::EnvironmentVariable;
+    use crate::liveliness_observer::LivelinessGuard;
     use crate::local_resource_state::LocalResource;
+    use crate::local_resource_state::LocalResourceOwner;
     use crate::local_resource_state::LocalResourceState;
 
-    #[tokio::test]
-    async fn test_canary() -> anyhow::Result<()> {
+    fn state(count: usize) -> LocalResourceState {
         let target =
             ConfiguredTargetLabel::testing_parse("foo//bar:baz", ConfigurationData::testing_new());
-        let specs = vec![
-            LocalResource(vec![EnvironmentVariable {
-                key: "FOO".to_owned(),
-                value: "foo".to_owned(),
-            }]),
-            LocalResource(vec![EnvironmentVariable {
-                key: "BAR".to_owned(),
-                value: "bar".to_owned(),
-            }]),
-        ];
-
-        let state = LocalResourceState::new(target, Some(0), specs);
+        let specs = (0..count)
+            .map(|i| {
+                LocalResource(vec![EnvironmentVariable {
+                    key: "FOO".to_owned(),
+                    value: i.to_string(),
+                }])
+            })
+            .collect();
+        LocalResourceState::new(target, Some(0), specs)
+    }
+
+    fn owner(command: &str) -> LocalResourceOwner {
+        LocalResourceOwner::testing_new(command)
+    }
+
+    #[tokio::test]
+    async fn test_canary() -> anyhow::Result<()> {
+        let state = state(2);
         let handle = tokio::spawn(async move {
             {
-                let _holder1 = state.acquire_resource().await;
-                let _holder2 = state.acquire_resource().await;
+                let _holder1 = state.acquire_resource(owner("a"), None).await?;
+                let _holder2 = state.acquire_resource(owner("b"), None).await?;
             }
             for _ in 0..10 {
-                let _x = state.acquire_resource().await;
+                let _x = state.acquire_resource(owner("c"), None).await?;
             }
+            anyhow::Ok(())
         });
-        handle.await?;
+        handle.await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timeout_reports_holders() -> anyhow::Result<()> {
+        let state = state(1);
+        let trace_id = TraceId::new();
+        let _holder = state
+            .acquire_resource(
+                LocalResourceOwner {
+                    trace_id: Some(trace_id.clone()),
+                    ..owner("run_simulator_test")
+                },
+                None,
+            )
+            .await?;
+
+        let holders = state.holders();
+        assert_eq!(1, holders.len());
+        assert_eq!("run_simulator_test", &*holders[0].command);
+
+        let err = match state
+            .acquire_resource(owner("other"), Some(Duration::from_millis(10)))
+            .await
+        {
+            Ok(_) => panic!("expected acquiring to time out"),
+            Err(e) => format!("{:#}", e),
+        };
+        assert!(err.contains("foo//bar:baz"), "{}", err);
+        assert!(err.contains("`run_simulator_test`"), "{}", err);
+        assert!(err.contains(&trace_id.to_string()), "{}", err);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_force_release() -> anyhow::Result<()> {
+        let state = state(1);
+        let (liveliness_observer, guard) = LivelinessGuard::create();
+        let leaked = state
+            .acquire_resource(
+                LocalResourceOwner {
+                    liveliness_observer: Some(liveliness_observer),
+                    ..owner("leaked")
+                },
+                None,
+            )
+            .await?;
+
+        // The owner is still alive, so nothing is reclaimed.
+        assert!(state.force_release().await.is_empty());
+        assert!(
+            state
+                .acquire_resource(owner("other"), Some(Duration::from_millis(10)))
+                .await
+                .is_err()
+        );
+
+        // Once it is gone, waiting for the resource reclaims it.
+        drop(guard);
+        let holder = state
+            .acquire_resource(owner("other"), Some(Duration::from_millis(10)))
+            .await?;
+        assert_eq!(1, state.holders().len());
+        assert_eq!("other", &*state.holders()[0].command);
+
+        // The leaked holder no longer owns the resource, so dropping it doesn't release it.
+        drop(leaked);
+        assert_eq!(1, state.holders().len());
+        assert!(
+            state
+                .acquire_resource(owner("third"), Some(Duration::from_millis(10)))
+                .await
+                .is_err()
+        );
+
+        drop(holder);
+        assert!(state.holders().is_empty());
+        state.acquire_resource(owner("third"), None).await?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_force_release_waits_for_process() -> anyhow::Result<()> {
+        let state = state(1);
+        let (liveliness_observer, guard) = LivelinessGuard::create();
+        let leaked = state
+            .acquire_resource(
+                LocalResourceOwner {
+                    liveliness_observer: Some(liveliness_observer),
+                    ..owner("leaked")
+                },
+                None,
+            )
+            .await?;
+        let mut child = std::process::Command::new("sleep").arg("10").spawn()?;
+        leaked.set_pid(child.id());
+        drop(guard);
+
+        // The owner is gone but its process is still running.
+        assert!(state.force_release().await.is_empty());
+
+        child.kill()?;
+        child.wait()?;
+        assert_eq!(1, state.force_release().await.len());
         Ok(())
     }
 }
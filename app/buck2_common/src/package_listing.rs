@@ -11,5 +11,6 @@ mod binary_search;
 pub mod dice;
 pub mod file_listing;
 pub(crate) mod interpreter;
+pub mod limits;
 pub mod listing;
 pub mod resolver;
@@ -68,10 +68,30 @@ impl Key for PackageListingKey {
 
     fn equality(x: &Self::Value, y: &Self::Value) -> bool {
         match (x, y) {
-            (Ok(x), Ok(y)) => x == y,
+            // The listing is recomputed whenever the file watcher reports changes in the package,
+            // which are often to ignored files. Those don't make it into the listing, so this
+            // stops the invalidation there.
+            (Ok(x), Ok(y)) => {
+                if x == y {
+                    return true;
+                }
+                tracing::debug!("Package listing changed: {:?}", x.diff(y));
+                false
+            }
             _ => false,
         }
     }
+
+    fn validity(x: &Self::Value) -> bool {
+        match x {
+            Ok(_) => true,
+            // Listings can fail because the command exceeded its limit on listed entries, which
+            // doesn't hold for other commands.
+            Err(e) => !e
+                .tags()
+                .contains(&buck2_error::ErrorTag::PackageListingLimit),
+        }
+    }
 }
 
 pub struct DicePackageListingResolver<'compute, 'dice>(pub &'compute mut DiceComputations<'dice>);
@@ -110,3 +130,105 @@ impl DicePackageListingResolver<'_, '_> {
         self.resolve(package).await.map_err(anyhow::Error::from)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use buck2_core::cells::cell_path::CellPath;
+    use buck2_core::cells::cell_root_path::CellRootPath;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::nested::NestedCells;
+    use buck2_core::cells::paths::CellRelativePath;
+    use buck2_core::package::PackageLabel;
+    use dice::testing::DiceBuilder;
+    use dice::Key;
+    use dice::UserComputationData;
+    use dupe::Dupe;
+
+    use crate::file_ops::testing::TestFileOps;
+    use crate::ignores::file_ignores::CellFileIgnores;
+    use crate::legacy_configs::buildfiles::testing::mock_buildfiles;
+    use crate::package_listing::dice::PackageListingKey;
+    use crate::package_listing::interpreter::InterpreterPackageListingResolver;
+    use crate::package_listing::limits::PackageListingBudget;
+    use crate::package_listing::limits::SetPackageListingBudget;
+    use crate::package_listing::listing::PackageListing;
+
+    async fn gather(files: &[&str]) -> anyhow::Result<PackageListing> {
+        gather_with_budget(files, None).await
+    }
+
+    async fn gather_with_budget(
+        files: &[&str],
+        budget: Option<PackageListingBudget>,
+    ) -> anyhow::Result<PackageListing> {
+        let cell = CellName::testing_new("root");
+        let files = TestFileOps::new_with_files(
+            files
+                .iter()
+                .map(|f| {
+                    (
+                        CellPath::new(cell, CellRelativePath::testing_new(f).to_owned()),
+                        String::new(),
+                    )
+                })
+                .collect(),
+        );
+        let ignores = CellFileIgnores::new_for_interpreter(
+            "**/*.swp",
+            NestedCells::from_cell_roots(&[], CellRootPath::testing_new("")),
+            true,
+        )?;
+        let builder =
+            files.mock_in_cell_with_ignores(cell, Some(Arc::new(ignores)), DiceBuilder::new());
+        let builder = mock_buildfiles(builder, cell, &["BUCK"]);
+        let mut data = UserComputationData::new();
+        if let Some(budget) = budget {
+            data.set_package_listing_budget(Arc::new(budget));
+        }
+        let mut dice = builder.build(data)?.commit().await;
+
+        InterpreterPackageListingResolver::new(&mut dice)
+            .gather_package_listing(PackageLabel::testing_parse("root//pkg"))
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_only_ignored_file_changed() -> anyhow::Result<()> {
+        let before = gather(&["pkg/BUCK", "pkg/src/lib.rs"]).await?;
+        let after = gather(&["pkg/BUCK", "pkg/src/lib.rs", "pkg/src/.lib.rs.swp"]).await?;
+        assert!(PackageListingKey::equality(&Ok(before.dupe()), &Ok(after)));
+
+        let changed = gather(&["pkg/BUCK", "pkg/src/lib.rs", "pkg/src/main.rs"]).await?;
+        assert!(!PackageListingKey::equality(&Ok(before), &Ok(changed)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_only_limit_errors_are_invalid() -> anyhow::Result<()> {
+        // 4 entries in `pkg` and 2 in `pkg/src`.
+        let files = [
+            "pkg/BUCK",
+            "pkg/a.rs",
+            "pkg/b.rs",
+            "pkg/src/c.rs",
+            "pkg/src/d.rs",
+        ];
+        gather_with_budget(&files, Some(PackageListingBudget::new(6))).await?;
+
+        let err = gather_with_budget(&files, Some(PackageListingBudget::new(5)))
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("more than 5 files"),
+            "{:#}",
+            err
+        );
+        assert!(!PackageListingKey::validity(&Err(err.into())));
+
+        let err = gather(&["pkg/src/lib.rs"]).await.unwrap_err();
+        assert!(PackageListingKey::validity(&Err(err.into())));
+        Ok(())
+    }
+}
@@ -26,6 +26,10 @@ use starlark_map::sorted_vec::SortedVec;
 
 use crate::dice::file_ops::DiceFileComputations;
 use crate::find_buildfile::find_buildfile;
+use crate::find_buildfile::has_buildfile;
+use crate::package_listing::limits::max_entries_per_package;
+use crate::package_listing::limits::HasPackageListingBudget;
+use crate::package_listing::limits::PackageEntryCounter;
 use crate::package_listing::listing::PackageListing;
 use crate::package_listing::resolver::PackageListingResolver;
 
@@ -57,7 +61,7 @@ impl PackageListingResolver for InterpreterPackageListingResolver<'_, '_> {
                 let listing = DiceFileComputations::read_dir(self.ctx, path)
                     .await?
                     .included;
-                if find_buildfile(&buildfile_candidates, &listing).is_some() {
+                if find_buildfile(path.dupe(), &buildfile_candidates, &listing)?.is_some() {
                     return Ok(PackageLabel::from_cell_path(path));
                 }
             }
@@ -86,7 +90,7 @@ impl PackageListingResolver for InterpreterPackageListingResolver<'_, '_> {
                 let listing = DiceFileComputations::read_dir(self.ctx, path.dupe())
                     .await?
                     .included;
-                if find_buildfile(&buildfile_candidates, &listing).is_some() {
+                if find_buildfile(path.dupe(), &buildfile_candidates, &listing)?.is_some() {
                     packages.push(PackageLabel::from_cell_path(path));
                 }
             }
@@ -135,36 +139,42 @@ impl Directory {
     async fn gather(
         ctx: &mut DiceComputations<'_>,
         buildfile_candidates: &[FileNameBuf],
+        counter: &PackageEntryCounter<'_>,
         root: CellPathRef<'_>,
         path: &PackageRelativePath,
         is_root: bool,
     ) -> anyhow::Result<Option<Directory>> {
         let cell_path = root.join(path.as_forward_rel_path());
+        counter.check(cell_path.as_ref())?;
         let entries = DiceFileComputations::read_dir(ctx, cell_path.as_ref())
             .await
             .input()?
             .included;
 
-        let buildfile = find_buildfile(buildfile_candidates, &entries);
-
-        match (is_root, buildfile) {
-            (true, None) => {
-                return Err(PackageListingError::NoBuildFile(
-                    cell_path.to_owned(),
-                    buildfile_candidates.to_vec(),
-                ))
-                .input();
+        let buildfile = if is_root {
+            match find_buildfile(cell_path.as_ref(), buildfile_candidates, &entries)? {
+                Some(buildfile) => Some(buildfile),
+                None => {
+                    return Err(PackageListingError::NoBuildFile(
+                        cell_path.to_owned(),
+                        buildfile_candidates.to_vec(),
+                    ))
+                    .input();
+                }
             }
-            (false, Some(_)) => {
+        } else {
+            // An ambiguous subpackage is reported by its own listing, not by this one.
+            if has_buildfile(buildfile_candidates, &entries) {
                 return Ok(None);
             }
-            _ => {}
-        }
+            None
+        };
 
         let mut subdirs = Vec::new();
         let mut files = Vec::new();
 
         for d in &*entries {
+            counter.record_entry(cell_path.as_ref())?;
             let child_path = path.join(&d.file_name);
             if d.file_type.is_dir() {
                 subdirs.push(child_path);
@@ -174,7 +184,7 @@ impl Directory {
         }
 
         let (subdirs, subpackages) =
-            Self::gather_subdirs(ctx, buildfile_candidates, root, subdirs).await?;
+            Self::gather_subdirs(ctx, buildfile_candidates, counter, root, subdirs).await?;
 
         let mut recursive_files_count = files.len();
         let mut recursive_dirs_count = subdirs.len();
@@ -200,6 +210,7 @@ impl Directory {
     fn gather_subdirs<'a, 'd>(
         ctx: &'a mut DiceComputations<'d>,
         buildfile_candidates: &'a [FileNameBuf],
+        counter: &'a PackageEntryCounter<'a>,
         root: CellPathRef<'a>,
         subdirs: Vec<PackageRelativePathBuf>,
     ) -> BoxFuture<'a, anyhow::Result<(Vec<Directory>, Vec<ArcS<PackageRelativePath>>)>> {
@@ -213,6 +224,7 @@ impl Directory {
                                 let res = Directory::gather(
                                     ctx,
                                     buildfile_candidates,
+                                    counter,
                                     root,
                                     &path,
                                     false,
@@ -284,9 +296,16 @@ async fn gather_package_listing_impl(
     root: PackageLabel,
 ) -> anyhow::Result<PackageListing> {
     let buildfile_candidates = DiceFileComputations::buildfiles(ctx, root.cell_name()).await?;
+    let budget = ctx
+        .per_transaction_data()
+        .get_package_listing_budget()
+        .cloned();
+    let counter =
+        PackageEntryCounter::new(root.dupe(), max_entries_per_package()?, budget.as_deref());
     Ok(Directory::gather(
         ctx,
         &buildfile_candidates,
+        &counter,
         root.as_cell_path(),
         PackageRelativePath::empty(),
         true,
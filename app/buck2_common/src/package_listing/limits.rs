@@ -0,0 +1,332 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Limits on the number of entries gathered into package listings.
+//!
+//! A package listing contains every file and directory below the package which is not ignored
+//! and not part of a subpackage, so a directory like an unignored `node_modules` can make a single
+//! listing huge. Entries are counted one at a time as directories are listed, so that listing
+//! stops as soon as a limit is exceeded, rather than after everything was read.
+
+use std::fmt::Write;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use buck2_core::buck2_env;
+use buck2_core::cells::cell_path::CellPathRef;
+use buck2_core::package::PackageLabel;
+use dashmap::DashMap;
+use dice::UserComputationData;
+use dupe::Dupe;
+use itertools::Itertools;
+
+/// How many of the largest packages are listed when the per-command limit is exceeded.
+const TOP_PACKAGES_REPORTED: usize = 10;
+
+#[derive(Debug, buck2_error::Error)]
+#[buck2(input)]
+#[buck2(tag = PackageListingLimit)]
+enum PackageListingLimitError {
+    #[error(
+        "Package `{package}` contains more than {limit} files and directories ({count} found when reading `{dir}`). \
+        If these files are not sources, add the directory to `project.ignore` in `.buckconfig`. \
+        Otherwise, add a buildfile to a subdirectory to split it into a separate package. \
+        The limit can be changed with `BUCK2_PACKAGE_LISTING_MAX_ENTRIES`."
+    )]
+    TooManyEntriesInPackage {
+        package: PackageLabel,
+        dir: String,
+        count: usize,
+        limit: usize,
+    },
+    #[error(
+        "Package listings in this command contain more than {limit} files and directories in total. \
+        Add the directories which are not sources to `project.ignore` in `.buckconfig`. \
+        The limit can be changed with `BUCK2_PACKAGE_LISTING_MAX_ENTRIES_PER_COMMAND`. \
+        Largest packages:\n{top_packages}"
+    )]
+    TooManyEntriesInCommand { limit: usize, top_packages: String },
+}
+
+pub(crate) fn max_entries_per_package() -> anyhow::Result<usize> {
+    buck2_env!(
+        "BUCK2_PACKAGE_LISTING_MAX_ENTRIES",
+        type = usize,
+        default = 1_000_000
+    )
+}
+
+/// Counts the entries of all the package listings gathered by a command.
+pub struct PackageListingBudget {
+    limit: usize,
+    total: AtomicUsize,
+    /// The entry count of each package, shared with the counter listing it.
+    packages: DashMap<PackageLabel, Arc<AtomicUsize>>,
+}
+
+impl PackageListingBudget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            total: AtomicUsize::new(0),
+            packages: DashMap::new(),
+        }
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self::new(buck2_env!(
+            "BUCK2_PACKAGE_LISTING_MAX_ENTRIES_PER_COMMAND",
+            type = usize,
+            default = 50_000_000
+        )?))
+    }
+
+    /// The entry count of a package which is about to be listed.
+    fn package_count(&self, package: &PackageLabel) -> Arc<AtomicUsize> {
+        let count = Arc::new(AtomicUsize::new(0));
+        self.packages.insert(package.dupe(), count.dupe());
+        count
+    }
+
+    fn record_entry(&self) -> anyhow::Result<()> {
+        let total = self.total.fetch_add(1, Ordering::Relaxed) + 1;
+        self.check_total(total)
+    }
+
+    fn check_total(&self, total: usize) -> anyhow::Result<()> {
+        if total > self.limit {
+            return Err(PackageListingLimitError::TooManyEntriesInCommand {
+                limit: self.limit,
+                top_packages: self.top_packages(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    fn top_packages(&self) -> String {
+        let mut top = String::new();
+        for (package, count) in self
+            .packages
+            .iter()
+            .map(|e| (e.key().dupe(), e.value().load(Ordering::Relaxed)))
+            .sorted_by(|(a_package, a), (b_package, b)| b.cmp(a).then(a_package.cmp(b_package)))
+            .take(TOP_PACKAGES_REPORTED)
+        {
+            writeln!(top, "  {}: {}", package, count).unwrap();
+        }
+        top
+    }
+}
+
+/// Counts the entries of a single package listing as its directories are read.
+pub(crate) struct PackageEntryCounter<'a> {
+    package: PackageLabel,
+    limit: usize,
+    count: Arc<AtomicUsize>,
+    budget: Option<&'a PackageListingBudget>,
+}
+
+impl<'a> PackageEntryCounter<'a> {
+    pub(crate) fn new(
+        package: PackageLabel,
+        limit: usize,
+        budget: Option<&'a PackageListingBudget>,
+    ) -> Self {
+        let count = match budget {
+            Some(budget) => budget.package_count(&package),
+            None => Arc::new(AtomicUsize::new(0)),
+        };
+        Self {
+            package,
+            limit,
+            count,
+            budget,
+        }
+    }
+
+    /// Called before reading a directory, so that the listing stops as soon as the package is
+    /// known to be over the limit, even if other directories of the package are being read
+    /// concurrently.
+    pub(crate) fn check(&self, dir: CellPathRef) -> anyhow::Result<()> {
+        self.check_count(dir, self.count.load(Ordering::Relaxed))?;
+        if let Some(budget) = self.budget {
+            budget.check_total(budget.total.load(Ordering::Relaxed))?;
+        }
+        Ok(())
+    }
+
+    /// Record an entry of the directory being listed.
+    pub(crate) fn record_entry(&self, dir: CellPathRef) -> anyhow::Result<()> {
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        self.check_count(dir, count)?;
+        if let Some(budget) = self.budget {
+            budget.record_entry()?;
+        }
+        Ok(())
+    }
+
+    fn check_count(&self, dir: CellPathRef, count: usize) -> anyhow::Result<()> {
+        if count > self.limit {
+            return Err(PackageListingLimitError::TooManyEntriesInPackage {
+                package: self.package.dupe(),
+                dir: dir.to_string(),
+                count,
+                limit: self.limit,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+pub trait SetPackageListingBudget {
+    fn set_package_listing_budget(&mut self, budget: Arc<PackageListingBudget>);
+}
+
+impl SetPackageListingBudget for UserComputationData {
+    fn set_package_listing_budget(&mut self, budget: Arc<PackageListingBudget>) {
+        self.data.set(budget);
+    }
+}
+
+pub trait HasPackageListingBudget {
+    /// Not set outside of commands, in which case only the per-package limit applies.
+    fn get_package_listing_budget(&self) -> Option<&Arc<PackageListingBudget>>;
+}
+
+impl HasPackageListingBudget for UserComputationData {
+    fn get_package_listing_budget(&self) -> Option<&Arc<PackageListingBudget>> {
+        self.data.get::<Arc<PackageListingBudget>>().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    use buck2_core::cells::cell_path::CellPath;
+    use buck2_core::package::PackageLabel;
+
+    use crate::package_listing::limits::PackageEntryCounter;
+    use crate::package_listing::limits::PackageListingBudget;
+
+    /// A synthetic directory tree, mapping each directory to its number of files and its
+    /// subdirectories.
+    struct Tree(BTreeMap<&'static str, (usize, Vec<&'static str>)>);
+
+    impl Tree {
+        /// A package `root//pkg` with a buildfile and `dirs` subdirectories of `files` files each.
+        fn wide(dirs: &[&'static str], files: usize) -> Self {
+            let mut tree = BTreeMap::new();
+            tree.insert("root//pkg", (1, dirs.to_vec()));
+            for dir in dirs {
+                tree.insert(*dir, (files, Vec::new()));
+            }
+            Tree(tree)
+        }
+
+        /// Walks the tree the way the package listing does, returning the directories read.
+        fn list(
+            &self,
+            counter: &PackageEntryCounter,
+            reads: &Mutex<Vec<&'static str>>,
+        ) -> anyhow::Result<()> {
+            let mut queue = vec!["root//pkg"];
+            while let Some(dir) = queue.pop() {
+                let path = CellPath::testing_new(dir);
+                counter.check(path.as_ref())?;
+                reads.lock().unwrap().push(dir);
+                let (files, subdirs) = &self.0[dir];
+                for _ in 0..files + subdirs.len() {
+                    counter.record_entry(path.as_ref())?;
+                }
+                queue.extend(subdirs.iter().rev());
+            }
+            Ok(())
+        }
+    }
+
+    fn package(name: &str) -> PackageLabel {
+        PackageLabel::testing_parse(name)
+    }
+
+    #[test]
+    fn test_under_limits() -> anyhow::Result<()> {
+        let budget = PackageListingBudget::new(100);
+        let tree = Tree::wide(&["root//pkg/a", "root//pkg/b"], 10);
+        let counter = PackageEntryCounter::new(package("root//pkg"), 100, Some(&budget));
+        let reads = Mutex::new(Vec::new());
+
+        tree.list(&counter, &reads)?;
+
+        assert_eq!(3, reads.lock().unwrap().len());
+        assert_eq!(23, budget.total.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn test_package_limit_stops_early() {
+        let tree = Tree::wide(
+            &[
+                "root//pkg/node_modules",
+                "root//pkg/other",
+                "root//pkg/more",
+            ],
+            50,
+        );
+        let counter = PackageEntryCounter::new(package("root//pkg"), 40, None);
+        let reads = Mutex::new(Vec::new());
+
+        let err = tree.list(&counter, &reads).unwrap_err();
+
+        // The directories after the one exceeding the limit are never read.
+        assert_eq!(
+            vec!["root//pkg", "root//pkg/node_modules"],
+            *reads.lock().unwrap()
+        );
+        let err = format!("{:#}", err);
+        assert!(err.contains("`root//pkg`"), "{}", err);
+        assert!(err.contains("more than 40 files"), "{}", err);
+        assert!(err.contains("41 found"), "{}", err);
+        assert!(err.contains("`root//pkg/node_modules`"), "{}", err);
+        assert!(err.contains("project.ignore"), "{}", err);
+        assert!(err.contains("add a buildfile to a subdirectory"), "{}", err);
+    }
+
+    #[test]
+    fn test_command_limit() {
+        let budget = PackageListingBudget::new(100);
+
+        let small = Tree::wide(&["root//pkg/a"], 10);
+        let counter = PackageEntryCounter::new(package("root//small"), 1000, Some(&budget));
+        small.list(&counter, &Mutex::new(Vec::new())).unwrap();
+
+        let big = Tree::wide(&["root//pkg/a", "root//pkg/b"], 100);
+        let counter = PackageEntryCounter::new(package("root//big"), 1000, Some(&budget));
+        let reads = Mutex::new(Vec::new());
+        let err = format!("{:#}", big.list(&counter, &reads).unwrap_err());
+
+        assert_eq!(2, reads.lock().unwrap().len());
+        assert!(err.contains("more than 100 files"), "{}", err);
+        assert!(
+            err.contains("Largest packages:\n  root//big: 89\n  root//small: 12\n"),
+            "{}",
+            err
+        );
+
+        // Once exhausted, other packages fail before reading anything.
+        let counter = PackageEntryCounter::new(package("root//other"), 1000, Some(&budget));
+        let reads = Mutex::new(Vec::new());
+        assert!(small.list(&counter, &reads).is_err());
+        assert!(reads.lock().unwrap().is_empty());
+    }
+}
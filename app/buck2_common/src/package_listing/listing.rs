@@ -7,6 +7,8 @@
  * of this source tree.
  */
 
+use std::cmp::Ordering;
+use std::mem;
 use std::sync::Arc;
 
 use allocative::Allocative;
@@ -99,6 +101,138 @@ impl PackageListing {
     pub fn buildfile(&self) -> &FileName {
         &self.listing.buildfile
     }
+
+    /// What changed from `self` to `other`. The diff is empty if and only if the listings are
+    /// equal.
+    pub fn diff(&self, other: &PackageListing) -> PackageListingDiff {
+        if Arc::ptr_eq(&self.listing, &other.listing) {
+            return PackageListingDiff::default();
+        }
+        let old = &self.listing;
+        let new = &other.listing;
+
+        let (mut added_files, mut removed_files) =
+            diff_sorted(old.files.files.iter(), new.files.files.iter());
+        let (mut added_directories, mut removed_directories) =
+            diff_sorted(old.directories.iter(), new.directories.iter());
+        let (added_subpackages, removed_subpackages) =
+            diff_sorted(old.subpackages.iter(), new.subpackages.iter());
+
+        let mut changed_kind = take_common(&mut added_files, &mut removed_directories);
+        changed_kind.extend(take_common(&mut added_directories, &mut removed_files));
+        changed_kind.sort();
+
+        PackageListingDiff {
+            added_files,
+            removed_files,
+            added_directories,
+            removed_directories,
+            changed_kind,
+            added_subpackages,
+            removed_subpackages,
+            buildfile: if old.buildfile != new.buildfile {
+                Some((old.buildfile.clone(), new.buildfile.clone()))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// The difference between two listings of a package, see [`PackageListing::diff`].
+///
+/// Listings only record which paths exist, so changes to the contents of files are not part of
+/// the diff. Paths are compared exactly, so a file whose name only changed case is both removed
+/// and added.
+#[derive(Default, Debug, Eq, PartialEq)]
+pub struct PackageListingDiff {
+    pub added_files: Vec<ArcS<PackageRelativePath>>,
+    pub removed_files: Vec<ArcS<PackageRelativePath>>,
+    pub added_directories: Vec<ArcS<PackageRelativePath>>,
+    pub removed_directories: Vec<ArcS<PackageRelativePath>>,
+    /// Paths that were a file and are now a directory, or the other way around. These are not
+    /// included in the added and removed paths.
+    pub changed_kind: Vec<ArcS<PackageRelativePath>>,
+    pub added_subpackages: Vec<ArcS<PackageRelativePath>>,
+    pub removed_subpackages: Vec<ArcS<PackageRelativePath>>,
+    /// The old and new name of the build file, if it changed, e.g. from `BUCK` to `BUCK.v2`.
+    pub buildfile: Option<(FileNameBuf, FileNameBuf)>,
+}
+
+impl PackageListingDiff {
+    pub fn is_empty(&self) -> bool {
+        let PackageListingDiff {
+            added_files,
+            removed_files,
+            added_directories,
+            removed_directories,
+            changed_kind,
+            added_subpackages,
+            removed_subpackages,
+            buildfile,
+        } = self;
+        added_files.is_empty()
+            && removed_files.is_empty()
+            && added_directories.is_empty()
+            && removed_directories.is_empty()
+            && changed_kind.is_empty()
+            && added_subpackages.is_empty()
+            && removed_subpackages.is_empty()
+            && buildfile.is_none()
+    }
+}
+
+/// Returns the items only in `new` and the items only in `old`. Both must be sorted, which the
+/// sets in a listing are.
+fn diff_sorted<'a>(
+    old: impl Iterator<Item = &'a ArcS<PackageRelativePath>>,
+    new: impl Iterator<Item = &'a ArcS<PackageRelativePath>>,
+) -> (
+    Vec<ArcS<PackageRelativePath>>,
+    Vec<ArcS<PackageRelativePath>>,
+) {
+    let (removed, added, _) = partition_sorted(old, new);
+    (
+        added.into_iter().map(|x| x.dupe()).collect(),
+        removed.into_iter().map(|x| x.dupe()).collect(),
+    )
+}
+
+/// Removes the items that are in both sorted vectors from both, and returns them.
+fn take_common<T: Ord>(a: &mut Vec<T>, b: &mut Vec<T>) -> Vec<T> {
+    let (a_only, b_only, both) = partition_sorted(mem::take(a), mem::take(b));
+    *a = a_only;
+    *b = b_only;
+    both
+}
+
+/// Splits two sorted sequences into the items only in `a`, the items only in `b`, and the items
+/// in both (taken from `a`).
+fn partition_sorted<T: Ord>(
+    a: impl IntoIterator<Item = T>,
+    b: impl IntoIterator<Item = T>,
+) -> (Vec<T>, Vec<T>, Vec<T>) {
+    let mut a_only = Vec::new();
+    let mut b_only = Vec::new();
+    let mut both = Vec::new();
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+    loop {
+        let ordering = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) => x.cmp(y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return (a_only, b_only, both),
+        };
+        match ordering {
+            Ordering::Less => a_only.extend(a.next()),
+            Ordering::Greater => b_only.extend(b.next()),
+            Ordering::Equal => {
+                both.extend(a.next());
+                b.next();
+            }
+        }
+    }
 }
 
 pub mod testing {
@@ -140,3 +274,89 @@ pub mod testing {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::paths::file_name::FileNameBuf;
+    use buck2_core::package::package_relative_path::PackageRelativePath;
+    use buck2_core::package::package_relative_path::PackageRelativePathBuf;
+    use buck2_util::arc_str::ArcS;
+    use starlark_map::sorted_set::SortedSet;
+    use starlark_map::sorted_vec::SortedVec;
+
+    use crate::package_listing::listing::testing::PackageListingExt;
+    use crate::package_listing::listing::PackageListing;
+    use crate::package_listing::listing::PackageListingDiff;
+
+    fn path(p: &str) -> ArcS<PackageRelativePath> {
+        PackageRelativePathBuf::unchecked_new(p.to_owned()).to_arc()
+    }
+
+    fn paths(ps: &[&str]) -> Vec<ArcS<PackageRelativePath>> {
+        ps.iter().map(|p| path(p)).collect()
+    }
+
+    #[allow(clippy::from_iter_instead_of_collect)]
+    fn listing(files: &[&str], directories: &[&str], subpackages: &[&str]) -> PackageListing {
+        PackageListing::new(
+            SortedSet::from_iter(paths(files)),
+            SortedSet::from_iter(paths(directories)),
+            SortedVec::from_iter(paths(subpackages)),
+            FileNameBuf::unchecked_new("BUCK"),
+        )
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = listing(
+            &["BUCK", "a.txt", "b.txt", "c", "z.txt"],
+            &["d", "sub"],
+            &["sub"],
+        );
+        let new = listing(&["BUCK", "b.txt", "d", "y.txt", "z.txt"], &["c", "e"], &[]);
+        assert_eq!(
+            PackageListingDiff {
+                added_files: paths(&["y.txt"]),
+                removed_files: paths(&["a.txt"]),
+                added_directories: paths(&["e"]),
+                removed_directories: paths(&["sub"]),
+                changed_kind: paths(&["c", "d"]),
+                added_subpackages: paths(&[]),
+                removed_subpackages: paths(&["sub"]),
+                buildfile: None,
+            },
+            old.diff(&new)
+        );
+
+        // Going back is the opposite.
+        let back = new.diff(&old);
+        assert_eq!(paths(&["a.txt"]), back.added_files);
+        assert_eq!(paths(&["c", "d"]), back.changed_kind);
+    }
+
+    #[test]
+    fn test_diff_buildfile() {
+        let old = PackageListing::testing_new(&["BUCK", "a.txt"], "BUCK");
+        let new = PackageListing::testing_new(&["BUCK.v2", "a.txt"], "BUCK.v2");
+        let diff = old.diff(&new);
+        assert_eq!(
+            Some((
+                FileNameBuf::unchecked_new("BUCK"),
+                FileNameBuf::unchecked_new("BUCK.v2")
+            )),
+            diff.buildfile
+        );
+        assert_eq!(paths(&["BUCK.v2"]), diff.added_files);
+        assert_eq!(paths(&["BUCK"]), diff.removed_files);
+    }
+
+    #[test]
+    fn test_diff_is_case_sensitive() {
+        let old = PackageListing::testing_files(&["BUCK", "Readme.md"]);
+        let new = PackageListing::testing_files(&["BUCK", "README.md"]);
+        let diff = old.diff(&new);
+        assert_eq!(paths(&["README.md"]), diff.added_files);
+        assert_eq!(paths(&["Readme.md"]), diff.removed_files);
+        assert_ne!(old, new);
+    }
+}
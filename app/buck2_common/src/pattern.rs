@@ -9,3 +9,4 @@
 
 pub mod package_roots;
 pub mod resolve;
+pub mod strict;
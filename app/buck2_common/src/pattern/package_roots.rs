@@ -129,8 +129,11 @@ pub async fn collect_package_roots<E>(
             }
         };
 
-        if find_buildfile(&buildfile_candidates, &listing).is_some() {
-            collector(Ok(PackageLabel::from_cell_path(path.as_ref())))?;
+        match find_buildfile(path.as_ref(), &buildfile_candidates, &listing) {
+            Ok(Some(_)) => collector(Ok(PackageLabel::from_cell_path(path.as_ref())))?,
+            Ok(None) => {}
+            Err(e) => collector(Err(anyhow::Error::from(e)
+                .context(format!("Error resolving recursive spec `{}/...`", path))))?,
         }
 
         // The rev() call isn't necessary, it ends up causing us to slightly prefer running
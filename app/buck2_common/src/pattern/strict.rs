@@ -0,0 +1,195 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Strict checking of relative target patterns, enabled by `buck2.strict_target_patterns`.
+//!
+//! A relative pattern like `foo:bar` is resolved against the working directory. When that
+//! doesn't name an existing package, users often meant the package relative to the cell root
+//! instead. In strict mode we reject such patterns and say which interpretation exists.
+
+use buck2_core::cells::cell_path::CellPath;
+use buck2_core::cells::cell_path::CellPathRef;
+use buck2_core::pattern::pattern_type::PatternType;
+use buck2_core::pattern::ParsedPattern;
+use dupe::Dupe;
+
+use crate::file_ops::FileOps;
+use crate::file_ops::RawPathMetadata;
+use crate::find_buildfile::find_buildfile;
+
+#[derive(Debug, buck2_error::Error)]
+enum StrictPatternError {
+    #[error(
+        "Relative pattern `{pattern}` refers to package `{cwd_package}`, which does not exist \
+        (relative to the cell root it would refer to `{cell_root_package}`).{}",
+        did_you_mean(.suggestion)
+    )]
+    #[buck2(input)]
+    AmbiguousRelativePattern {
+        pattern: String,
+        cwd_package: CellPath,
+        cell_root_package: CellPath,
+        suggestion: Option<String>,
+    },
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(suggestion) => format!(" Did you mean `{}`?", suggestion),
+        None => String::new(),
+    }
+}
+
+/// Whether `path` is a package, i.e. a directory containing a buildfile.
+pub async fn is_package(file_ops: &dyn FileOps, path: CellPathRef<'_>) -> anyhow::Result<bool> {
+    match file_ops.read_path_metadata_if_exists(path).await? {
+        Some(RawPathMetadata::Directory) => {}
+        _ => return Ok(false),
+    }
+    let listing = file_ops.read_dir(path).await?.included;
+    let buildfiles = file_ops.buildfiles(path.cell()).await?;
+    Ok(find_buildfile(path, &buildfiles, &listing)?.is_some())
+}
+
+/// Returns the first of `candidates` that is a package, if any.
+pub async fn suggest_package<'a>(
+    file_ops: &dyn FileOps,
+    candidates: &[CellPathRef<'a>],
+) -> anyhow::Result<Option<CellPathRef<'a>>> {
+    for candidate in candidates {
+        if is_package(file_ops, candidate.dupe()).await? {
+            return Ok(Some(candidate.dupe()));
+        }
+    }
+    Ok(None)
+}
+
+/// Checks that a relative pattern refers to an existing package.
+///
+/// `cwd_parsed` is `pattern` parsed relative to the working directory and `cell_root_parsed` is
+/// the same pattern parsed relative to the root of the working directory's cell. When both are
+/// the same there is no ambiguity and nothing is checked. Recursive patterns are not checked
+/// either, since they need not point at a package.
+pub async fn check_relative_pattern<T: PatternType>(
+    file_ops: &dyn FileOps,
+    pattern: &str,
+    cwd_parsed: &ParsedPattern<T>,
+    cell_root_parsed: &ParsedPattern<T>,
+) -> anyhow::Result<()> {
+    if cwd_parsed == cell_root_parsed || matches!(cwd_parsed, ParsedPattern::Recursive(_)) {
+        return Ok(());
+    }
+
+    let cwd_package = cwd_parsed.cell_path();
+    let cell_root_package = cell_root_parsed.cell_path();
+    let suggestion =
+        match suggest_package(file_ops, &[cwd_package.dupe(), cell_root_package.dupe()]).await? {
+            Some(found) if found == cwd_package => return Ok(()),
+            Some(_) => Some(cell_root_parsed.to_string()),
+            None => None,
+        };
+
+    Err(StrictPatternError::AmbiguousRelativePattern {
+        pattern: pattern.to_owned(),
+        cwd_package: cwd_package.to_owned(),
+        cell_root_package: cell_root_package.to_owned(),
+        suggestion,
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use buck2_core::cells::cell_path::CellPath;
+    use buck2_core::pattern::pattern_type::TargetPatternExtra;
+    use buck2_core::pattern::ParsedPattern;
+
+    use crate::file_ops::testing::TestFileOps;
+    use crate::pattern::strict::check_relative_pattern;
+    use crate::pattern::strict::is_package;
+    use crate::pattern::strict::suggest_package;
+
+    fn file_ops(files: &[&str]) -> TestFileOps {
+        TestFileOps::new_with_files(
+            files
+                .iter()
+                .map(|f| (CellPath::testing_new(f), String::new()))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_is_package() -> anyhow::Result<()> {
+        let file_ops = file_ops(&["root//foo/BUCK", "root//bar/baz.txt"]);
+
+        assert!(is_package(&file_ops, CellPath::testing_new("root//foo").as_ref()).await?);
+        assert!(!is_package(&file_ops, CellPath::testing_new("root//bar").as_ref()).await?);
+        assert!(!is_package(&file_ops, CellPath::testing_new("root//missing").as_ref()).await?);
+        assert!(!is_package(&file_ops, CellPath::testing_new("root//foo/BUCK").as_ref()).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_suggest_package() -> anyhow::Result<()> {
+        let file_ops = file_ops(&["root//foo/BUCK", "root//bar/BUCK"]);
+        let cwd = CellPath::testing_new("root//bar/foo");
+        let cell_root = CellPath::testing_new("root//foo");
+        let missing = CellPath::testing_new("root//missing");
+
+        assert_eq!(
+            Some(cell_root.as_ref()),
+            suggest_package(&file_ops, &[cwd.as_ref(), cell_root.as_ref()]).await?
+        );
+        assert_eq!(
+            None,
+            suggest_package(&file_ops, &[cwd.as_ref(), missing.as_ref()]).await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_relative_pattern() -> anyhow::Result<()> {
+        let file_ops = file_ops(&["root//foo/BUCK", "root//bar/BUCK", "root//bar/qux/BUCK"]);
+        let check = |cwd_parsed: &str, cell_root_parsed: &str| {
+            let cwd_parsed = ParsedPattern::<TargetPatternExtra>::testing_parse(cwd_parsed);
+            let cell_root_parsed =
+                ParsedPattern::<TargetPatternExtra>::testing_parse(cell_root_parsed);
+            let file_ops = &file_ops;
+            async move {
+                check_relative_pattern(file_ops, "pattern", &cwd_parsed, &cell_root_parsed).await
+            }
+        };
+
+        // The working directory interpretation exists.
+        check("root//bar/qux:t", "root//qux:t").await?;
+        // Not relative, or the working directory is the cell root.
+        check("root//missing:t", "root//missing:t").await?;
+        // Recursive patterns are not checked.
+        check("root//bar/missing/...", "root//missing/...").await?;
+
+        let err = check("root//bar/foo:t", "root//foo:t")
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`root//bar/foo`"), "{}", err);
+        assert!(err.contains("`root//foo`"), "{}", err);
+        assert!(err.contains("Did you mean `root//foo:t`?"), "{}", err);
+
+        let err = check("root//bar/missing:", "root//missing:")
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`root//bar/missing`"), "{}", err);
+        assert!(err.contains("`root//missing`"), "{}", err);
+        assert!(!err.contains("Did you mean"), "{}", err);
+        Ok(())
+    }
+}
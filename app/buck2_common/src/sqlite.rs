@@ -7,8 +7,12 @@
  * of this source tree.
  */
 
+pub mod migrations;
+
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Context;
 use itertools::Itertools;
@@ -29,14 +33,20 @@ impl KeyValueSqliteTable {
         }
     }
 
-    pub fn create_table(&self) -> anyhow::Result<()> {
-        let sql = format!(
-            "CREATE TABLE {} (
+    /// The statement creating a table named `table_name`, if it does not exist. For use in
+    /// migrations, see [`migrations::SqliteMigrations`].
+    pub fn create_table_sql(table_name: &str) -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (
                 key     TEXT PRIMARY KEY NOT NULL,
                 value   TEXT NOT NULL
             )",
-            self.table_name
-        );
+            table_name
+        )
+    }
+
+    pub fn create_table(&self) -> anyhow::Result<()> {
+        let sql = Self::create_table_sql(&self.table_name);
         tracing::trace!(sql = %sql, "creating table");
         self.connection
             .lock()
@@ -77,6 +87,16 @@ impl KeyValueSqliteTable {
         Ok(map)
     }
 
+    pub fn delete_all(&self) -> anyhow::Result<()> {
+        let sql = format!("DELETE FROM {}", self.table_name);
+        tracing::trace!(sql = %sql, "deleting all from table");
+        self.connection
+            .lock()
+            .execute(&sql, [])
+            .with_context(|| format!("deleting from sqlite table {}", self.table_name))?;
+        Ok(())
+    }
+
     pub fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
         let sql = format!("SELECT value FROM {} WHERE key = ?", self.table_name);
         tracing::trace!(sql = %sql, key = %key, "read from table");
@@ -91,6 +111,192 @@ impl KeyValueSqliteTable {
     }
 }
 
+/// Page counts of a sqlite db.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SqlitePageStats {
+    pub page_size: u64,
+    pub page_count: u64,
+    /// Pages that are allocated in the file but hold no data (`PRAGMA freelist_count`).
+    pub freelist_count: u64,
+    /// Whether the db can be compacted incrementally (`PRAGMA auto_vacuum = INCREMENTAL`).
+    pub incremental_vacuum: bool,
+}
+
+impl SqlitePageStats {
+    pub fn read(connection: &Connection) -> anyhow::Result<Self> {
+        let pragma = |name: &str| -> anyhow::Result<u64> {
+            let value: i64 = connection
+                .pragma_query_value(None, name, |row| row.get(0))
+                .with_context(|| format!("reading sqlite pragma {}", name))?;
+            Ok(value.try_into()?)
+        };
+        Ok(Self {
+            page_size: pragma("page_size")?,
+            page_count: pragma("page_count")?,
+            freelist_count: pragma("freelist_count")?,
+            // 2 is INCREMENTAL.
+            incremental_vacuum: pragma("auto_vacuum")? == 2,
+        })
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.page_size * self.page_count
+    }
+
+    pub fn free_bytes(&self) -> u64 {
+        self.page_size * self.freelist_count
+    }
+}
+
+/// When and how to compact a sqlite db that accumulated free pages.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SqliteVacuumConfig {
+    /// Compact once this fraction of the pages of the db are free.
+    pub freelist_threshold: f64,
+    /// How many pages to free at a time. Compaction can only be interrupted between chunks.
+    pub chunk_pages: u64,
+    /// How long the db must go unused before it is compacted.
+    pub idle_delay: Duration,
+}
+
+impl Default for SqliteVacuumConfig {
+    fn default() -> Self {
+        Self {
+            freelist_threshold: 0.25,
+            chunk_pages: 1000,
+            idle_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl SqliteVacuumConfig {
+    pub fn should_vacuum(&self, stats: &SqlitePageStats) -> bool {
+        // Without incremental vacuum, freeing pages requires rebuilding the whole db, which is
+        // only done on request.
+        stats.incremental_vacuum
+            && stats.freelist_count > 0
+            && stats.freelist_count as f64 > stats.page_count as f64 * self.freelist_threshold
+    }
+}
+
+/// The outcome of compacting a sqlite db.
+#[derive(Clone, Debug)]
+pub struct SqliteVacuumResult {
+    pub before: SqlitePageStats,
+    pub after: SqlitePageStats,
+    /// Whether the whole db was rebuilt, as opposed to freeing pages incrementally.
+    pub full: bool,
+    /// Whether compaction stopped before all free pages were released.
+    pub interrupted: bool,
+    pub duration: Duration,
+}
+
+/// Keeps track of the free pages of a sqlite db and compacts it.
+///
+/// Incremental compaction is done in bounded chunks, and only on dbs that have
+/// `auto_vacuum = INCREMENTAL`, see [`SqliteMaintenance::enable_incremental_vacuum`]. Other dbs
+/// are switched over by a full compaction.
+pub struct SqliteMaintenance {
+    connection: Arc<Mutex<Connection>>,
+    needs_vacuum: bool,
+}
+
+impl SqliteMaintenance {
+    pub fn new(connection: Arc<Mutex<Connection>>) -> Self {
+        Self {
+            connection,
+            needs_vacuum: false,
+        }
+    }
+
+    /// Must be called before any table is created, otherwise it only takes effect after a full
+    /// compaction.
+    pub fn enable_incremental_vacuum(connection: &Connection) -> anyhow::Result<()> {
+        connection
+            .pragma_update(None, "auto_vacuum", "INCREMENTAL")
+            .context("enabling sqlite incremental vacuum")
+    }
+
+    pub fn page_stats(&self) -> anyhow::Result<SqlitePageStats> {
+        SqlitePageStats::read(&self.connection.lock())
+    }
+
+    /// To be called after writes, which may have freed pages. Cheap, since this only reads the
+    /// db header.
+    pub fn record_write_batch(&mut self, config: &SqliteVacuumConfig) -> anyhow::Result<()> {
+        let stats = self.page_stats()?;
+        self.needs_vacuum = config.should_vacuum(&stats);
+        tracing::trace!(stats = ?stats, needs_vacuum = self.needs_vacuum, "sqlite page stats");
+        Ok(())
+    }
+
+    /// Whether enough pages were free as of the last write batch to warrant compacting the db.
+    pub fn needs_vacuum(&self) -> bool {
+        self.needs_vacuum
+    }
+
+    /// Releases free pages `config.chunk_pages` at a time until there are none left, or
+    /// `interrupted` returns true, which it is asked before every chunk but the first.
+    pub fn incremental_vacuum(
+        &mut self,
+        config: &SqliteVacuumConfig,
+        mut interrupted: impl FnMut() -> bool,
+    ) -> anyhow::Result<SqliteVacuumResult> {
+        let start = Instant::now();
+        let before = self.page_stats()?;
+        let mut after = before;
+        let mut was_interrupted = false;
+        while after.incremental_vacuum && after.freelist_count > 0 {
+            if after != before && interrupted() {
+                was_interrupted = true;
+                break;
+            }
+            let sql = format!("PRAGMA incremental_vacuum({})", config.chunk_pages.max(1));
+            tracing::trace!(sql = %sql, "compacting sqlite db");
+            let connection = self.connection.lock();
+            connection
+                .execute_batch(&sql)
+                .context("running sqlite incremental vacuum")?;
+            let stats = SqlitePageStats::read(&connection)?;
+            if stats.freelist_count >= after.freelist_count {
+                // No progress, don't spin.
+                break;
+            }
+            after = stats;
+        }
+        self.needs_vacuum = was_interrupted;
+        Ok(SqliteVacuumResult {
+            before,
+            after,
+            full: false,
+            interrupted: was_interrupted,
+            duration: start.elapsed(),
+        })
+    }
+
+    /// Rebuilds the db, releasing all free pages, and enables incremental vacuum for the future.
+    /// This rewrites the whole db and blocks all access to it while it runs.
+    pub fn full_vacuum(&mut self) -> anyhow::Result<SqliteVacuumResult> {
+        let start = Instant::now();
+        let connection = self.connection.lock();
+        let before = SqlitePageStats::read(&connection)?;
+        Self::enable_incremental_vacuum(&connection)?;
+        tracing::trace!("running full sqlite vacuum");
+        connection
+            .execute_batch("VACUUM")
+            .context("running sqlite vacuum")?;
+        let after = SqlitePageStats::read(&connection)?;
+        self.needs_vacuum = false;
+        Ok(SqliteVacuumResult {
+            before,
+            after,
+            full: true,
+            interrupted: false,
+            duration: start.elapsed(),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -124,5 +330,119 @@ mod tests {
 
         assert_eq!(table.get("foo").unwrap().as_deref(), Some("foo"));
         assert_eq!(table.get("baz").unwrap(), None);
+
+        table.delete_all().unwrap();
+        assert_eq!(table.read_all().unwrap(), HashMap::new());
+    }
+
+    fn page_stats(page_count: u64, freelist_count: u64) -> SqlitePageStats {
+        SqlitePageStats {
+            page_size: 4096,
+            page_count,
+            freelist_count,
+            incremental_vacuum: true,
+        }
+    }
+
+    #[test]
+    fn test_should_vacuum() {
+        let config = SqliteVacuumConfig {
+            freelist_threshold: 0.25,
+            ..SqliteVacuumConfig::default()
+        };
+        assert!(!config.should_vacuum(&page_stats(0, 0)));
+        assert!(!config.should_vacuum(&page_stats(1000, 0)));
+        assert!(!config.should_vacuum(&page_stats(1000, 250)));
+        assert!(config.should_vacuum(&page_stats(1000, 251)));
+        assert!(config.should_vacuum(&page_stats(1000, 1000)));
+        assert!(!config.should_vacuum(&SqlitePageStats {
+            incremental_vacuum: false,
+            ..page_stats(1000, 900)
+        }));
+    }
+
+    /// A db in which most pages are free.
+    fn bloated_db(fs: &ProjectRootTemp, incremental_vacuum: bool) -> Arc<Mutex<Connection>> {
+        let connection = Connection::open(
+            fs.path()
+                .resolve(ProjectRelativePath::unchecked_new("bloated.db")),
+        )
+        .unwrap();
+        if incremental_vacuum {
+            SqliteMaintenance::enable_incremental_vacuum(&connection).unwrap();
+        }
+        connection
+            .execute_batch(
+                "CREATE TABLE blobs (value BLOB NOT NULL);
+                INSERT INTO blobs (value)
+                    WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 1000)
+                    SELECT zeroblob(4000) FROM n;
+                DELETE FROM blobs;",
+            )
+            .unwrap();
+        Arc::new(Mutex::new(connection))
+    }
+
+    #[test]
+    fn test_incremental_vacuum_interrupted() {
+        let fs = ProjectRootTemp::new().unwrap();
+        let mut maintenance = SqliteMaintenance::new(bloated_db(&fs, true));
+        let config = SqliteVacuumConfig {
+            chunk_pages: 100,
+            ..SqliteVacuumConfig::default()
+        };
+
+        maintenance.record_write_batch(&config).unwrap();
+        assert!(maintenance.needs_vacuum());
+        let bloated = maintenance.page_stats().unwrap();
+        assert!(bloated.freelist_count > 500, "{:?}", bloated);
+
+        // A command comes in while the first chunk is being freed.
+        let mut asked = 0;
+        let res = maintenance
+            .incremental_vacuum(&config, || {
+                asked += 1;
+                true
+            })
+            .unwrap();
+        assert_eq!(1, asked);
+        assert!(res.interrupted);
+        assert!(!res.full);
+        assert_eq!(bloated, res.before);
+        assert!(res.after.freelist_count > 0);
+        assert!(res.after.freelist_count <= bloated.freelist_count - 100);
+        assert!(res.after.size_bytes() < res.before.size_bytes());
+        // Picks up where it left off on the next idle period.
+        assert!(maintenance.needs_vacuum());
+
+        let res = maintenance.incremental_vacuum(&config, || false).unwrap();
+        assert!(!res.interrupted);
+        assert_eq!(0, res.after.freelist_count);
+        assert!(!maintenance.needs_vacuum());
+        maintenance.record_write_batch(&config).unwrap();
+        assert!(!maintenance.needs_vacuum());
+    }
+
+    #[test]
+    fn test_full_vacuum() {
+        let fs = ProjectRootTemp::new().unwrap();
+        let mut maintenance = SqliteMaintenance::new(bloated_db(&fs, false));
+        let config = SqliteVacuumConfig::default();
+
+        // Free pages can't be released incrementally.
+        maintenance.record_write_batch(&config).unwrap();
+        assert!(!maintenance.needs_vacuum());
+        let bloated = maintenance.page_stats().unwrap();
+        assert!(bloated.freelist_count > 500, "{:?}", bloated);
+        let res = maintenance.incremental_vacuum(&config, || false).unwrap();
+        assert_eq!(bloated, res.after);
+
+        let res = maintenance.full_vacuum().unwrap();
+        assert!(res.full);
+        assert_eq!(bloated, res.before);
+        assert_eq!(0, res.after.freelist_count);
+        assert!(res.after.size_bytes() * 10 < res.before.size_bytes());
+        // Later compactions can be incremental.
+        assert!(res.after.incremental_vacuum);
     }
 }
@@ -0,0 +1,273 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Versioned schemas for sqlite dbs.
+//!
+//! The schema version of a db is stored in its `user_version` header field. Version `n` means that
+//! the first `n` migrations of its [`SqliteMigrations`] were applied, so a db that was never
+//! migrated (or was created before it had migrations) is at version 0.
+
+use std::path::Path;
+
+use anyhow::Context;
+use rusqlite::Connection;
+use rusqlite::Transaction;
+
+#[derive(buck2_error::Error, Debug, PartialEq, Eq)]
+pub enum SqliteMigrationError {
+    #[error(
+        "sqlite db is at schema version {found}, which is newer than the latest version known to this buck2 ({latest})"
+    )]
+    TooNew { found: u32, latest: u32 },
+
+    #[error("Error migrating sqlite db to schema version {version} ({description})")]
+    StepFailed {
+        version: u32,
+        description: &'static str,
+    },
+}
+
+enum SqliteMigrationStep {
+    Sql(String),
+    Rust(fn(&Transaction<'_>) -> anyhow::Result<()>),
+}
+
+struct SqliteMigration {
+    description: &'static str,
+    step: SqliteMigrationStep,
+}
+
+/// The ordered migrations making up the schema of a db. Migrations must only ever be appended:
+/// each one is applied exactly once, to dbs at the version just before it.
+#[derive(Default)]
+pub struct SqliteMigrations {
+    migrations: Vec<SqliteMigration>,
+}
+
+/// The schema versions of a db before and after migrating it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SqliteMigrationOutcome {
+    pub from: u32,
+    pub to: u32,
+}
+
+impl SqliteMigrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a migration that runs `sql`, which may contain several statements.
+    pub fn sql(mut self, description: &'static str, sql: impl Into<String>) -> Self {
+        self.migrations.push(SqliteMigration {
+            description,
+            step: SqliteMigrationStep::Sql(sql.into()),
+        });
+        self
+    }
+
+    /// Adds a migration that runs `f`, for changes that can't be expressed in SQL alone.
+    pub fn rust(
+        mut self,
+        description: &'static str,
+        f: fn(&Transaction<'_>) -> anyhow::Result<()>,
+    ) -> Self {
+        self.migrations.push(SqliteMigration {
+            description,
+            step: SqliteMigrationStep::Rust(f),
+        });
+        self
+    }
+
+    /// The version of dbs that have all migrations applied.
+    pub fn latest_version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+
+    /// Brings the db up to the latest version. All pending migrations are applied in a single
+    /// transaction, so on error the db is left at the version it was at.
+    pub fn apply(&self, connection: &mut Connection) -> anyhow::Result<SqliteMigrationOutcome> {
+        let latest = self.latest_version();
+        let from = schema_version(connection)?;
+        if from > latest {
+            return Err(SqliteMigrationError::TooNew {
+                found: from,
+                latest,
+            }
+            .into());
+        }
+        if from == latest {
+            return Ok(SqliteMigrationOutcome { from, to: latest });
+        }
+
+        let transaction = connection
+            .transaction()
+            .context("starting sqlite migration transaction")?;
+        for (version, migration) in (from + 1..).zip(&self.migrations[from as usize..]) {
+            tracing::debug!(
+                version,
+                description = migration.description,
+                "migrating sqlite db"
+            );
+            let res = match &migration.step {
+                SqliteMigrationStep::Sql(sql) => {
+                    transaction.execute_batch(sql).map_err(anyhow::Error::from)
+                }
+                SqliteMigrationStep::Rust(f) => f(&transaction),
+            };
+            res.context(SqliteMigrationError::StepFailed {
+                version,
+                description: migration.description,
+            })?;
+        }
+        transaction
+            .pragma_update(None, "user_version", latest)
+            .context("writing sqlite schema version")?;
+        transaction
+            .commit()
+            .context("committing sqlite migration transaction")?;
+
+        Ok(SqliteMigrationOutcome { from, to: latest })
+    }
+}
+
+/// The schema version of the db, see the module docs.
+pub fn schema_version(connection: &Connection) -> anyhow::Result<u32> {
+    connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .context("reading sqlite schema version")
+}
+
+/// Opens the db at `path`, creating it if needed, and brings it up to the latest version.
+pub fn open_with_migrations(
+    path: &Path,
+    migrations: &SqliteMigrations,
+) -> anyhow::Result<Connection> {
+    let mut connection = Connection::open(path)?;
+    migrations.apply(&mut connection)?;
+    Ok(connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+
+    use super::*;
+
+    fn add_size(transaction: &Transaction<'_>) -> anyhow::Result<()> {
+        let names = transaction
+            .prepare("SELECT name FROM items")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for name in names {
+            transaction.execute(
+                "UPDATE items SET size = ? WHERE name = ?",
+                rusqlite::params![name.len() as i64, name],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn migrations() -> SqliteMigrations {
+        SqliteMigrations::new()
+            .sql(
+                "create items",
+                "CREATE TABLE IF NOT EXISTS items (name TEXT PRIMARY KEY NOT NULL)",
+            )
+            .sql("add size", "ALTER TABLE items ADD COLUMN size INTEGER")
+            .rust("backfill size", add_size)
+    }
+
+    fn items(connection: &Connection) -> Vec<(String, Option<i64>)> {
+        let mut stmt = connection
+            .prepare("SELECT name, size FROM items ORDER BY name")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_migrate_v0_db() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let path = fs
+            .path()
+            .resolve(ProjectRelativePath::unchecked_new("test.db"));
+
+        // A db created before there were migrations.
+        Connection::open(&path)?.execute_batch(
+            "CREATE TABLE items (name TEXT PRIMARY KEY NOT NULL);
+            INSERT INTO items (name) VALUES ('a'), ('bbb');",
+        )?;
+
+        let migrations = migrations();
+        let mut connection = open_with_migrations(path.as_path(), &migrations)?;
+        assert_eq!(3, schema_version(&connection)?);
+        assert_eq!(
+            vec![("a".to_owned(), Some(1)), ("bbb".to_owned(), Some(3))],
+            items(&connection)
+        );
+
+        // Nothing left to do.
+        assert_eq!(
+            SqliteMigrationOutcome { from: 3, to: 3 },
+            migrations.apply(&mut connection)?
+        );
+
+        // An older buck2 doesn't know about the latest migration.
+        let older = SqliteMigrations::new()
+            .sql("create items", "CREATE TABLE items (name TEXT)")
+            .sql("add size", "ALTER TABLE items ADD COLUMN size INTEGER");
+        let err = older.apply(&mut connection).unwrap_err();
+        assert_eq!(
+            Some(&SqliteMigrationError::TooNew {
+                found: 3,
+                latest: 2
+            }),
+            err.downcast_ref::<SqliteMigrationError>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_step_is_rolled_back() -> anyhow::Result<()> {
+        let mut connection = Connection::open_in_memory()?;
+        SqliteMigrations::new()
+            .sql("create items", "CREATE TABLE items (name TEXT)")
+            .apply(&mut connection)?;
+        connection.execute("INSERT INTO items (name) VALUES ('a')", [])?;
+
+        let broken = SqliteMigrations::new()
+            .sql("create items", "CREATE TABLE items (name TEXT)")
+            .sql("add size", "ALTER TABLE items ADD COLUMN size INTEGER")
+            .sql("bad step", "ALTER TABLE missing ADD COLUMN x INTEGER");
+        let err = broken.apply(&mut connection).unwrap_err();
+        assert_eq!(
+            Some(&SqliteMigrationError::StepFailed {
+                version: 3,
+                description: "bad step"
+            }),
+            err.downcast_ref::<SqliteMigrationError>()
+        );
+        assert!(
+            format!("{:#}", err).contains("schema version 3 (bad step)"),
+            "{:#}",
+            err
+        );
+
+        // Nothing was applied, including the step that succeeded.
+        assert_eq!(1, schema_version(&connection)?);
+        assert!(connection.prepare("SELECT size FROM items").is_err());
+        let count: i64 =
+            connection.query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0))?;
+        assert_eq!(1, count);
+        Ok(())
+    }
+}
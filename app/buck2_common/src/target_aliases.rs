@@ -23,6 +23,7 @@ use itertools::Itertools;
 
 use crate::dice::cells::HasCellResolver;
 use crate::legacy_configs::dice::HasLegacyConfigs;
+use crate::legacy_configs::section_group::ConfigSectionGroup;
 use crate::legacy_configs::LegacyBuckConfig;
 
 #[derive(buck2_error::Error, Debug)]
@@ -153,7 +154,10 @@ impl Key for TargetAliasResolverKey {
         ctx: &mut DiceComputations,
         _cancellations: &CancellationContext,
     ) -> buck2_error::Result<BuckConfigTargetAliasResolver> {
-        let legacy_configs = ctx.get_legacy_config_for_cell(self.cell_name).await?;
+        // Only reads `[alias]`, so other buckconfig changes don't invalidate this.
+        let legacy_configs = ctx
+            .get_legacy_config_section_group(self.cell_name, ConfigSectionGroup::Alias)
+            .await?;
         Ok(legacy_configs.target_alias_resolver())
     }
 
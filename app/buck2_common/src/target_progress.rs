@@ -0,0 +1,227 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Cheap, incremental accounting of how many targets a command has discovered and analyzed.
+//!
+//! Computing the total number of targets up front would require evaluating every package first,
+//! so instead we aggregate counts as package evaluations complete. This gives consoles an
+//! estimate of the denominator for analysis progress that converges as packages finish.
+
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use buck2_core::package::PackageLabel;
+use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
+use dice::UserComputationData;
+use dupe::Dupe;
+
+#[derive(Default)]
+struct PackageState {
+    /// Packages that were requested but whose evaluation has not finished yet.
+    pending: HashSet<PackageLabel>,
+    /// Packages whose targets were already counted.
+    finished: HashSet<PackageLabel>,
+}
+
+/// Per-command registry of target counts. Updated from the target graph and analysis
+/// computations, and periodically reported in the command's snapshot events.
+#[derive(Default)]
+pub struct TargetProgress {
+    packages: Mutex<PackageState>,
+    targets_discovered: AtomicU64,
+    /// Targets whose analysis results were requested, whether they were analyzed in this command
+    /// or already cached.
+    analyzed: Mutex<HashSet<ConfiguredTargetLabel>>,
+    targets_analyzed: AtomicU64,
+}
+
+/// A consistent-enough view of [`TargetProgress`] at a point in time.
+#[derive(Debug, Default, Clone, Copy, Dupe, PartialEq, Eq)]
+pub struct TargetProgressSnapshot {
+    pub targets_discovered: u64,
+    pub targets_analyzed: u64,
+    pub packages_finished: u64,
+    pub packages_pending: u64,
+}
+
+impl TargetProgress {
+    pub fn new() -> Arc<TargetProgress> {
+        Arc::new(TargetProgress::default())
+    }
+
+    /// Record that evaluation of `package` was requested. Packages that already finished are
+    /// not considered pending again.
+    pub fn package_started(&self, package: &PackageLabel) {
+        let mut packages = self.packages.lock().unwrap();
+        if !packages.finished.contains(package) {
+            packages.pending.insert(package.dupe());
+        }
+    }
+
+    /// Record that evaluation of `package` finished with `targets` targets. Each package is only
+    /// counted once per command, no matter how many times it is requested.
+    pub fn package_finished(&self, package: &PackageLabel, targets: usize) {
+        let mut packages = self.packages.lock().unwrap();
+        packages.pending.remove(package);
+        if packages.finished.insert(package.dupe()) {
+            self.targets_discovered
+                .fetch_add(targets as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Record that evaluation of `package` failed. The package is no longer pending, but it does
+    /// not contribute any targets.
+    pub fn package_failed(&self, package: &PackageLabel) {
+        self.packages.lock().unwrap().pending.remove(package);
+    }
+
+    /// Record that the analysis of `target` finished, or was already cached. Each target is only
+    /// counted once per command, no matter how many times it is requested.
+    pub fn target_analyzed(&self, target: &ConfiguredTargetLabel) {
+        if self.analyzed.lock().unwrap().insert(target.dupe()) {
+            self.targets_analyzed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> TargetProgressSnapshot {
+        let (packages_finished, packages_pending) = {
+            let packages = self.packages.lock().unwrap();
+            (
+                packages.finished.len() as u64,
+                packages.pending.len() as u64,
+            )
+        };
+        TargetProgressSnapshot {
+            targets_discovered: self.targets_discovered.load(Ordering::Relaxed),
+            targets_analyzed: self.targets_analyzed.load(Ordering::Relaxed),
+            packages_finished,
+            packages_pending,
+        }
+    }
+}
+
+pub trait HasTargetProgress {
+    /// Returns `None` if the command did not install a registry (e.g. in tests).
+    fn get_target_progress(&self) -> Option<&Arc<TargetProgress>>;
+}
+
+pub trait SetTargetProgress {
+    fn set_target_progress(&mut self, progress: Arc<TargetProgress>);
+}
+
+impl HasTargetProgress for UserComputationData {
+    fn get_target_progress(&self) -> Option<&Arc<TargetProgress>> {
+        self.data.get::<Arc<TargetProgress>>().ok()
+    }
+}
+
+impl SetTargetProgress for UserComputationData {
+    fn set_target_progress(&mut self, progress: Arc<TargetProgress>) {
+        self.data.set(progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::package::PackageLabel;
+    use buck2_core::target::label::label::TargetLabel;
+    use dupe::Dupe;
+
+    use crate::target_progress::TargetProgress;
+    use crate::target_progress::TargetProgressSnapshot;
+
+    #[test]
+    fn test_concurrent_increments() {
+        let progress = TargetProgress::new();
+        thread::scope(|s| {
+            for i in 0..8 {
+                let progress = progress.dupe();
+                s.spawn(move || {
+                    let package = PackageLabel::testing_parse(&format!("root//pkg{}", i));
+                    progress.package_started(&package);
+                    progress.package_finished(&package, 10);
+                    for j in 0..100 {
+                        let target = TargetLabel::testing_parse(&format!("root//pkg{}:t{}", i, j))
+                            .configure(ConfigurationData::testing_new());
+                        progress.target_analyzed(&target);
+                        // Requesting the same analysis again does not count it twice.
+                        progress.target_analyzed(&target);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            TargetProgressSnapshot {
+                targets_discovered: 80,
+                targets_analyzed: 800,
+                packages_finished: 8,
+                packages_pending: 0,
+            },
+            progress.snapshot()
+        );
+    }
+
+    #[test]
+    fn test_pending_packages() {
+        let progress = TargetProgress::new();
+        let a = PackageLabel::testing_parse("root//a");
+        let b = PackageLabel::testing_parse("root//b");
+        let c = PackageLabel::testing_parse("root//c");
+
+        progress.package_started(&a);
+        progress.package_started(&b);
+        progress.package_started(&c);
+        // Requesting the same package twice does not count it twice.
+        progress.package_started(&a);
+        assert_eq!(3, progress.snapshot().packages_pending);
+
+        progress.package_finished(&a, 5);
+        assert_eq!(
+            TargetProgressSnapshot {
+                targets_discovered: 5,
+                targets_analyzed: 0,
+                packages_finished: 1,
+                packages_pending: 2,
+            },
+            progress.snapshot()
+        );
+
+        // Already finished packages are neither pending nor counted again.
+        progress.package_started(&a);
+        progress.package_finished(&a, 5);
+        progress.package_failed(&b);
+        assert_eq!(
+            TargetProgressSnapshot {
+                targets_discovered: 5,
+                targets_analyzed: 0,
+                packages_finished: 1,
+                packages_pending: 1,
+            },
+            progress.snapshot()
+        );
+
+        progress.package_finished(&c, 7);
+        assert_eq!(
+            TargetProgressSnapshot {
+                targets_discovered: 12,
+                targets_analyzed: 0,
+                packages_finished: 2,
+                packages_pending: 0,
+            },
+            progress.snapshot()
+        );
+    }
+}
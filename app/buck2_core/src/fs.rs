@@ -15,4 +15,5 @@ pub mod fs_util;
 pub mod paths;
 pub mod project;
 pub mod project_rel_path;
+pub mod windows_path;
 pub mod working_dir;
@@ -29,6 +29,12 @@ use crate::fs::cwd::assert_cwd_is_not_set;
 use crate::fs::paths::abs_norm_path::AbsNormPath;
 use crate::fs::paths::abs_norm_path::AbsNormPathBuf;
 use crate::fs::paths::abs_path::AbsPath;
+use crate::fs::windows_path::find_windows_reserved_component;
+use crate::fs::windows_path::to_extended_length_path;
+use crate::fs::windows_path::ERROR_FILENAME_EXCED_RANGE;
+use crate::fs::windows_path::ERROR_INVALID_NAME;
+use crate::fs::windows_path::ERROR_PATH_NOT_FOUND;
+use crate::fs::windows_path::WINDOWS_MAX_PATH;
 use crate::io_counters::IoCounterGuard;
 use crate::io_counters::IoCounterKey;
 
@@ -90,6 +96,54 @@ macro_rules! make_error {
             Err(e) => Err(IoError { op: $context, e }),
         }
     }};
+    ($val:expr, $context:expr, path = $path:expr $(,)?) => {{
+        match ($val) {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                let op = match windows_path_hint(&e, $path) {
+                    Some(hint) => format!("{}: {}", $context, hint),
+                    None => $context,
+                };
+                Err(IoError { op, e })
+            }
+        }
+    }};
+}
+
+/// The path to pass to the OS. On Windows, long absolute paths get the extended-length prefix,
+/// otherwise they fail with `MAX_PATH` errors.
+fn io_path(path: &AbsPath) -> Cow<'_, Path> {
+    if cfg!(windows) {
+        if let Some(Cow::Owned(extended)) = path.as_path().to_str().map(to_extended_length_path) {
+            return Cow::Owned(PathBuf::from(extended));
+        }
+    }
+    Cow::Borrowed(path.as_maybe_relativized())
+}
+
+/// Windows reports reserved names and overlong paths with generic errors which don't say what is
+/// wrong with the path, so explain it.
+fn windows_path_hint(e: &io::Error, path: &AbsPath) -> Option<String> {
+    if !cfg!(windows) {
+        return None;
+    }
+    let path = path.as_path().to_str()?;
+    match e.raw_os_error()? {
+        ERROR_INVALID_NAME => find_windows_reserved_component(path).map(|component| {
+            format!(
+                "`{}` is a reserved file name on Windows and cannot be used in a path",
+                component
+            )
+        }),
+        ERROR_PATH_NOT_FOUND | ERROR_FILENAME_EXCED_RANGE if path.len() >= WINDOWS_MAX_PATH => {
+            Some(format!(
+                "path is {} characters long, which exceeds the Windows limit of {}",
+                path.len(),
+                WINDOWS_MAX_PATH
+            ))
+        }
+        _ => None,
+    }
 }
 
 macro_rules! make_anyhow_error {
@@ -122,7 +176,7 @@ where
 
 #[cfg(unix)]
 fn symlink_impl(original: &Path, link: &AbsPath) -> anyhow::Result<()> {
-    std::os::unix::fs::symlink(original, link.as_maybe_relativized()).map_err(|e| e.into())
+    std::os::unix::fs::symlink(original, io_path(link)).map_err(|e| e.into())
 }
 
 /// Create symlink on Windows.
@@ -208,16 +262,18 @@ pub fn set_current_dir<P: AsRef<AbsPath>>(path: P) -> anyhow::Result<()> {
 pub fn create_dir_all<P: AsRef<AbsPath>>(path: P) -> Result<(), IoError> {
     let _guard = IoCounterKey::MkDir.guard();
     make_error!(
-        fs::create_dir_all(path.as_ref().as_maybe_relativized()),
+        fs::create_dir_all(io_path(path.as_ref())),
         format!("create_dir_all({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
 pub fn create_dir<P: AsRef<AbsPath>>(path: P) -> Result<(), IoError> {
     let _guard = IoCounterKey::MkDir.guard();
     make_error!(
-        fs::create_dir(path.as_ref().as_maybe_relativized()),
-        format!("create_dir({})", P::as_ref(&path).display())
+        fs::create_dir(io_path(path.as_ref())),
+        format!("create_dir({})", P::as_ref(&path).display()),
+        path = path.as_ref()
     )
 }
 
@@ -229,7 +285,7 @@ pub fn create_dir_if_not_exists<P: AsRef<AbsPath>>(path: P) -> Result<(), IoErro
     let _guard = IoCounterKey::MkDir.guard();
     make_error!(
         {
-            let e = match fs::create_dir(path.as_maybe_relativized()) {
+            let e = match fs::create_dir(io_path(path)) {
                 Ok(()) => return Ok(()),
                 Err(e) => e,
             };
@@ -249,7 +305,8 @@ pub fn create_dir_if_not_exists<P: AsRef<AbsPath>>(path: P) -> Result<(), IoErro
                 }
             }
         },
-        format!("create_dir({})", path.display())
+        format!("create_dir({})", path.display()),
+        path = path
     )
 }
 
@@ -310,16 +367,18 @@ pub fn read_dir_if_exists<P: AsRef<AbsNormPath>>(path: P) -> Result<Option<ReadD
 pub fn try_exists<P: AsRef<AbsPath>>(path: P) -> Result<bool, IoError> {
     let _guard = IoCounterKey::Stat.guard();
     make_error!(
-        fs::try_exists(path.as_ref().as_maybe_relativized()),
-        format!("try_exists({})", P::as_ref(&path).display())
+        fs::try_exists(io_path(path.as_ref())),
+        format!("try_exists({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
 pub fn remove_file<P: AsRef<AbsPath>>(path: P) -> Result<(), IoError> {
     let _guard = IoCounterKey::Remove.guard();
     make_error!(
-        remove_file_impl(path.as_ref().as_maybe_relativized()),
+        remove_file_impl(&io_path(path.as_ref())),
         format!("remove_file({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
@@ -345,70 +404,84 @@ fn remove_file_impl(path: &Path) -> io::Result<()> {
 pub fn copy<P: AsRef<AbsPath>, Q: AsRef<AbsPath>>(from: P, to: Q) -> Result<u64, IoError> {
     let _guard = IoCounterKey::Copy.guard();
     make_error!(
-        fs::copy(
-            from.as_ref().as_maybe_relativized(),
-            to.as_ref().as_maybe_relativized(),
-        ),
+        fs::copy(io_path(from.as_ref()), io_path(to.as_ref())),
         format!(
             "copy(from={}, to={})",
             P::as_ref(&from).display(),
             Q::as_ref(&to).display()
         ),
+        path = to.as_ref(),
+    )
+}
+
+pub fn hard_link<P: AsRef<AbsPath>, Q: AsRef<AbsPath>>(src: P, dst: Q) -> Result<(), IoError> {
+    let _guard = IoCounterKey::Hardlink.guard();
+    make_error!(
+        fs::hard_link(io_path(src.as_ref()), io_path(dst.as_ref())),
+        format!(
+            "hard_link(src={}, dst={})",
+            P::as_ref(&src).display(),
+            Q::as_ref(&dst).display()
+        ),
+        path = dst.as_ref(),
     )
 }
 
 pub fn read_link<P: AsRef<AbsPath>>(path: P) -> Result<PathBuf, IoError> {
     let _guard = IoCounterKey::ReadLink.guard();
     make_error!(
-        fs::read_link(path.as_ref().as_maybe_relativized()),
+        fs::read_link(io_path(path.as_ref())),
         format!("read_link({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
 pub fn rename<P: AsRef<AbsPath>, Q: AsRef<AbsPath>>(from: P, to: Q) -> Result<(), IoError> {
     let _guard = IoCounterKey::Rename.guard();
     make_error!(
-        fs::rename(
-            from.as_ref().as_maybe_relativized(),
-            to.as_ref().as_maybe_relativized(),
-        ),
+        fs::rename(io_path(from.as_ref()), io_path(to.as_ref())),
         format!(
             "rename(from={}, to={})",
             P::as_ref(&from).display(),
             Q::as_ref(&to).display()
         ),
+        path = to.as_ref(),
     )
 }
 
 pub fn write<P: AsRef<AbsPath>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<(), IoError> {
     let _guard = IoCounterKey::Write.guard();
     make_error!(
-        fs::write(path.as_ref().as_maybe_relativized(), &contents),
+        fs::write(io_path(path.as_ref()), &contents),
         format!("write({}, _)", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
 pub fn metadata<P: AsRef<AbsPath>>(path: P) -> Result<fs::Metadata, IoError> {
     let _guard = IoCounterKey::Stat.guard();
     make_error!(
-        fs::metadata(path.as_ref().as_maybe_relativized()),
+        fs::metadata(io_path(path.as_ref())),
         format!("metadata({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
 pub fn symlink_metadata<P: AsRef<AbsPath>>(path: P) -> Result<fs::Metadata, IoError> {
     let _guard = IoCounterKey::Stat.guard();
     make_error!(
-        fs::symlink_metadata(path.as_ref().as_maybe_relativized()),
+        fs::symlink_metadata(io_path(path.as_ref())),
         format!("symlink_metadata({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
 pub fn set_permissions<P: AsRef<AbsPath>>(path: P, perm: fs::Permissions) -> Result<(), IoError> {
     let _guard = IoCounterKey::Chmod.guard();
     make_error!(
-        fs::set_permissions(path.as_ref().as_maybe_relativized(), perm),
+        fs::set_permissions(io_path(path.as_ref()), perm),
         format!("set_permissions({}, _)", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
@@ -437,8 +510,9 @@ pub fn set_executable<P: AsRef<AbsPath>>(path: P) -> anyhow::Result<()> {
 pub fn remove_dir_all<P: AsRef<AbsPath>>(path: P) -> Result<(), IoError> {
     let _guard = IoCounterKey::RmDirAll.guard();
     make_error!(
-        fs::remove_dir_all(path.as_ref().as_maybe_relativized()),
+        fs::remove_dir_all(io_path(path.as_ref())),
         format!("remove_dir_all({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
@@ -448,8 +522,9 @@ pub fn symlink_metadata_if_exists<P: AsRef<AbsPath>>(
 ) -> Result<Option<fs::Metadata>, IoError> {
     let _guard = IoCounterKey::Stat.guard();
     make_error!(
-        if_exists(fs::symlink_metadata(path.as_ref().as_maybe_relativized())),
-        format!("symlink_metadata({})", path.as_ref().display())
+        if_exists(fs::symlink_metadata(io_path(path.as_ref()))),
+        format!("symlink_metadata({})", path.as_ref().display()),
+        path = path.as_ref(),
     )
 }
 
@@ -482,16 +557,18 @@ pub fn remove_all<P: AsRef<AbsPath>>(path: P) -> Result<(), IoError> {
 pub fn read<P: AsRef<AbsPath>>(path: P) -> Result<Vec<u8>, IoError> {
     let _guard = IoCounterKey::Read.guard();
     make_error!(
-        fs::read(path.as_ref().as_maybe_relativized()),
+        fs::read(io_path(path.as_ref())),
         format!("read({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
 pub fn read_to_string<P: AsRef<AbsPath>>(path: P) -> Result<String, IoError> {
     let _guard = IoCounterKey::Read.guard();
     make_error!(
-        fs::read_to_string(path.as_ref().as_maybe_relativized()),
+        fs::read_to_string(io_path(path.as_ref())),
         format!("read_to_string({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
@@ -499,8 +576,9 @@ pub fn read_to_string<P: AsRef<AbsPath>>(path: P) -> Result<String, IoError> {
 pub fn read_to_string_if_exists<P: AsRef<AbsPath>>(path: P) -> Result<Option<String>, IoError> {
     let _guard = IoCounterKey::Read.guard();
     make_error!(
-        if_exists(fs::read_to_string(path.as_ref().as_maybe_relativized())),
+        if_exists(fs::read_to_string(io_path(path.as_ref()))),
         format!("read_to_string_if_exists({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
@@ -508,8 +586,9 @@ pub fn read_to_string_if_exists<P: AsRef<AbsPath>>(path: P) -> Result<Option<Str
 pub fn read_if_exists<P: AsRef<AbsPath>>(path: P) -> Result<Option<Vec<u8>>, IoError> {
     let _guard = IoCounterKey::Read.guard();
     make_error!(
-        if_exists(fs::read(path.as_ref().as_maybe_relativized())),
+        if_exists(fs::read(io_path(path.as_ref()))),
         format!("read_if_exists({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
@@ -541,8 +620,9 @@ pub fn simplified(path: &AbsPath) -> anyhow::Result<&AbsPath> {
 pub fn remove_dir<P: AsRef<AbsPath>>(path: P) -> Result<(), IoError> {
     let _guard = IoCounterKey::RmDir.guard();
     make_error!(
-        fs::remove_dir(path.as_ref().as_maybe_relativized()),
+        fs::remove_dir(io_path(path.as_ref())),
         format!("remove_dir({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )
 }
 
@@ -564,8 +644,9 @@ impl Write for FileWriteGuard {
 pub fn create_file<P: AsRef<AbsPath>>(path: P) -> Result<FileWriteGuard, IoError> {
     let guard = IoCounterKey::Write.guard();
     let file = make_error!(
-        File::create(path.as_ref().as_maybe_relativized()),
+        File::create(io_path(path.as_ref())),
         format!("create_file({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )?;
     Ok(FileWriteGuard {
         file,
@@ -587,8 +668,9 @@ impl Read for FileReadGuard {
 pub fn open_file<P: AsRef<AbsPath>>(path: P) -> Result<FileReadGuard, IoError> {
     let guard = IoCounterKey::Read.guard();
     let file = make_error!(
-        File::open(path.as_ref().as_maybe_relativized()),
+        File::open(io_path(path.as_ref())),
         format!("open_file({})", P::as_ref(&path).display()),
+        path = path.as_ref(),
     )?;
     Ok(FileReadGuard {
         file,
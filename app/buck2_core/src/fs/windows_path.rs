@@ -0,0 +1,218 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Windows path limitations.
+//!
+//! Windows refuses paths longer than `MAX_PATH` unless they carry the `\\?\` prefix, and refuses
+//! file names which are reserved device names (`CON`, `aux.c`, ...). These functions are
+//! platform independent so that paths can be checked before they reach a Windows filesystem, e.g.
+//! when cross-compiling for Windows.
+
+use std::borrow::Cow;
+
+/// `MAX_PATH` on Windows, including the terminating NUL.
+pub const WINDOWS_MAX_PATH: usize = 260;
+
+/// Directories have a lower limit, because it must be possible to create an 8.3 file name in them.
+pub const WINDOWS_MAX_DIR_PATH: usize = 248;
+
+/// `ERROR_PATH_NOT_FOUND`, returned for long paths on some APIs.
+pub const ERROR_PATH_NOT_FOUND: i32 = 3;
+/// `ERROR_INVALID_NAME`, returned for reserved names and invalid characters.
+pub const ERROR_INVALID_NAME: i32 = 123;
+/// `ERROR_FILENAME_EXCED_RANGE`, returned for long paths.
+pub const ERROR_FILENAME_EXCED_RANGE: i32 = 206;
+
+const RESERVED_NAMES: &[&str] = &["CON", "PRN", "AUX", "NUL"];
+const RESERVED_NUMBERED_NAMES: &[&str] = &["COM", "LPT"];
+
+/// Whether the path component is a reserved device name on Windows.
+///
+/// Windows ignores the extension and trailing spaces, so `aux.c`, `CON.h` and `nul ` are all
+/// reserved.
+pub fn is_windows_reserved_name(component: &str) -> bool {
+    let stem = match component.split_once('.') {
+        Some((stem, _)) => stem,
+        None => component,
+    };
+    let stem = stem.trim_end_matches(' ');
+
+    if RESERVED_NAMES
+        .iter()
+        .any(|name| stem.eq_ignore_ascii_case(name))
+    {
+        return true;
+    }
+
+    match stem
+        .len()
+        .checked_sub(1)
+        .filter(|i| stem.is_char_boundary(*i))
+        .map(|i| stem.split_at(i))
+    {
+        Some((prefix, digit)) => {
+            RESERVED_NUMBERED_NAMES
+                .iter()
+                .any(|name| prefix.eq_ignore_ascii_case(name))
+                && matches!(digit, "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9")
+        }
+        None => false,
+    }
+}
+
+/// Find the first component of the path (separated by either `/` or `\`) which is a reserved
+/// device name on Windows.
+pub fn find_windows_reserved_component(path: &str) -> Option<&str> {
+    path.split(['/', '\\'])
+        .find(|component| is_windows_reserved_name(component))
+}
+
+/// Length of a path once placed under a prefix of `prefix_len` bytes (e.g. the absolute path of
+/// `buck-out`), accounting for the separator between them.
+pub fn projected_path_len(prefix_len: usize, rel_path: &str) -> usize {
+    if prefix_len == 0 || rel_path.is_empty() {
+        prefix_len + rel_path.len()
+    } else {
+        prefix_len + 1 + rel_path.len()
+    }
+}
+
+/// Convert an absolute Windows path which is too long for the regular APIs to an extended-length
+/// path, i.e. `C:\x` to `\\?\C:\x` and `\\server\share\x` to `\\?\UNC\server\share\x`.
+///
+/// Extended-length paths are not normalized by Windows, so paths which are relative, already
+/// prefixed, or contain `.` or `..` components are returned unchanged, as are paths short enough
+/// not to need the prefix.
+pub fn to_extended_length_path(path: &str) -> Cow<str> {
+    if path.len() < WINDOWS_MAX_DIR_PATH
+        || path.starts_with(r"\\?\")
+        || path.starts_with(r"\\.\")
+        || path
+            .split(['/', '\\'])
+            .any(|component| component == "." || component == "..")
+    {
+        return Cow::Borrowed(path);
+    }
+
+    let bytes = path.as_bytes();
+    let is_drive_absolute = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && matches!(bytes[2], b'\\' | b'/');
+
+    if is_drive_absolute {
+        Cow::Owned(format!(r"\\?\{}", path.replace('/', r"\")))
+    } else if let Some(unc) = path.strip_prefix(r"\\").or_else(|| path.strip_prefix("//")) {
+        Cow::Owned(format!(r"\\?\UNC\{}", unc.replace('/', r"\")))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::fs::windows_path::find_windows_reserved_component;
+    use crate::fs::windows_path::is_windows_reserved_name;
+    use crate::fs::windows_path::projected_path_len;
+    use crate::fs::windows_path::to_extended_length_path;
+    use crate::fs::windows_path::WINDOWS_MAX_PATH;
+
+    #[test]
+    fn test_reserved_names() {
+        for name in [
+            "CON",
+            "con",
+            "Prn",
+            "aux",
+            "NUL",
+            "COM1",
+            "com9",
+            "LPT3",
+            "aux.c",
+            "con.h",
+            "NUL.tar.gz",
+            "nul ",
+            "con .txt",
+        ] {
+            assert!(is_windows_reserved_name(name), "{}", name);
+        }
+        for name in [
+            "",
+            "console",
+            "aux_",
+            "xaux",
+            "COM",
+            "COM0",
+            "COM10",
+            "LPT",
+            "auxiliary.c",
+            ".con",
+            "a.con",
+        ] {
+            assert!(!is_windows_reserved_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_find_reserved_component() {
+        assert_eq!(
+            Some("aux.c"),
+            find_windows_reserved_component("foo/aux.c/bar")
+        );
+        assert_eq!(
+            Some("CON.h"),
+            find_windows_reserved_component(r"foo\include\CON.h")
+        );
+        assert_eq!(None, find_windows_reserved_component("foo/auxiliary/bar.c"));
+    }
+
+    #[test]
+    fn test_projected_path_len() {
+        assert_eq!(7, projected_path_len(0, "foo/bar"));
+        assert_eq!(18, projected_path_len(10, "foo/bar"));
+        assert_eq!(10, projected_path_len(10, ""));
+        let long = "x".repeat(200);
+        assert!(projected_path_len(40, &long) < WINDOWS_MAX_PATH);
+        assert!(projected_path_len(60, &long) > WINDOWS_MAX_PATH);
+    }
+
+    #[test]
+    fn test_to_extended_length_path() {
+        let tail = "x".repeat(250);
+
+        assert_eq!(
+            format!(r"\\?\C:\repo\{}", tail),
+            to_extended_length_path(&format!(r"C:\repo\{}", tail))
+        );
+        assert_eq!(
+            format!(r"\\?\C:\repo\{}", tail),
+            to_extended_length_path(&format!("C:/repo/{}", tail))
+        );
+        assert_eq!(
+            format!(r"\\?\UNC\server\share\{}", tail),
+            to_extended_length_path(&format!(r"\\server\share\{}", tail))
+        );
+
+        for unchanged in [
+            r"C:\repo\short".to_owned(),
+            format!(r"\\?\C:\repo\{}", tail),
+            format!(r"\\.\pipe\{}", tail),
+            format!(r"C:\repo\..\{}", tail),
+            format!(r"repo\{}", tail),
+        ] {
+            assert!(
+                matches!(to_extended_length_path(&unchanged), Cow::Borrowed(p) if p == unchanged),
+                "{}",
+                unchanged
+            );
+        }
+    }
+}
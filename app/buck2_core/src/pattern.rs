@@ -198,7 +198,9 @@ impl ParsedPattern<ProvidersPatternExtra> {
 }
 
 impl<T: PatternType> ParsedPattern<T> {
-    pub(crate) fn cell_path(&self) -> CellPathRef {
+    /// The path this pattern refers to: the package for target and package patterns, or the
+    /// directory for recursive patterns.
+    pub fn cell_path(&self) -> CellPathRef {
         match self {
             ParsedPattern::Target(pkg, _, _) => pkg.as_cell_path(),
             ParsedPattern::Package(pkg) => pkg.as_cell_path(),
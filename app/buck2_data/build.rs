@@ -10,7 +10,7 @@
 use std::io;
 
 fn main() -> io::Result<()> {
-    let proto_files = &["data.proto", "error.proto"];
+    let proto_files = &["data.proto", "error.proto", "targets.proto"];
 
     buck2_protoc_dev::configure()
         .setup_protoc()
@@ -166,6 +166,18 @@ fn main() -> io::Result<()> {
             "RemoteCommand.queue_time",
             "#[serde(rename = \"queue_time_us\", with = \"crate::serialize_duration_as_micros\")]",
         )
+        .field_attribute(
+            "CommandResourceUsage.wall_time",
+            "#[serde(rename = \"wall_time_us\", with = \"crate::serialize_duration_as_micros\")]",
+        )
+        .field_attribute(
+            "overlapped_time",
+            "#[serde(rename = \"overlapped_time_us\", with = \"crate::serialize_duration_as_micros\")]",
+        )
+        .field_attribute(
+            "ConcurrentCommandProgress.elapsed",
+            "#[serde(rename = \"elapsed_us\", with = \"crate::serialize_duration_as_micros\")]",
+        )
         .field_attribute(
             "concurrent_command_blocking_duration",
             "#[serde(rename = \"concurrent_command_blocking_duration_us\", with = \"crate::serialize_duration_as_micros\")]",
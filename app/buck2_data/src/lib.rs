@@ -99,6 +99,10 @@ pub mod error {
     tonic::include_proto!("buck.data.error");
 }
 
+pub mod targets {
+    tonic::include_proto!("buck.data.targets");
+}
+
 /// Trait for things that can be converted into protobuf messages, for ease of emitting events. There are many core Buck
 /// types that are represented in the Daemon API that use this trait to ease conversion.
 pub trait ToProtoMessage {
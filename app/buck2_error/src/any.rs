@@ -53,6 +53,15 @@ fn maybe_add_context_from_metadata(mut e: crate::Error, context: &dyn StdError)
     }
 }
 
+/// All the tags of a `buck2_error::Error` that was converted to `anyhow::Error`, including tags
+/// attached anywhere in its source chain, sorted and deduplicated.
+///
+/// Unlike downcasting, this also finds tags when the `buck2_error::Error` was wrapped in further
+/// `anyhow` context or in another error type.
+pub fn find_tags(e: &anyhow::Error) -> Vec<crate::ErrorTag> {
+    recover_crate_error(e.as_ref(), None).tags()
+}
+
 pub(crate) fn recover_crate_error(
     value: &'_ (dyn StdError + 'static),
     source_location: Option<String>,
@@ -313,4 +322,60 @@ mod tests {
         let e: crate::Error = e.into();
         assert_eq!(e.get_tier(), Some(crate::Tier::Tier0));
     }
+
+    #[derive(Debug)]
+    struct AnyhowWrapperError(anyhow::Error);
+
+    impl fmt::Display for AnyhowWrapperError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "anyhow wrapper")
+        }
+    }
+
+    impl StdError for AnyhowWrapperError {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            Some(self.0.as_ref())
+        }
+    }
+
+    #[test]
+    fn test_find_tags_through_anyhow() {
+        let e = crate::Error::new(TestError)
+            .tag([crate::ErrorTag::InternalError])
+            .context("context 1");
+        let e: anyhow::Error = e.into();
+        let e: crate::Error = e.context("anyhow context").into();
+        let e = e.tag([crate::ErrorTag::DaemonIsBusy]).context("context 2");
+        let e: anyhow::Error = e.into();
+        let e = e.context("more anyhow context");
+
+        let expected = [
+            crate::ErrorTag::DaemonIsBusy,
+            crate::ErrorTag::InternalError,
+        ];
+        assert_eq!(&find_tags(&e), &expected);
+        assert_eq!(&crate::Error::from(e).tags(), &expected);
+    }
+
+    #[test]
+    fn test_find_tags_through_wrapper() {
+        let e: anyhow::Error = crate::Error::new(TestError)
+            .tag([crate::ErrorTag::InternalError])
+            .context("context")
+            .into();
+        let e = anyhow::Error::new(AnyhowWrapperError(e)).context("outer");
+        assert_eq!(&find_tags(&e), &[crate::ErrorTag::InternalError]);
+
+        // Tags provided as metadata by errors in the chain are found too.
+        let e = anyhow::Error::new(WrapperError(FullMetadataError));
+        assert_eq!(
+            &find_tags(&e),
+            &[
+                crate::ErrorTag::StarlarkFail,
+                crate::ErrorTag::WatchmanTimeout
+            ]
+        );
+
+        assert!(find_tags(&anyhow::anyhow!("untagged")).is_empty());
+    }
 }
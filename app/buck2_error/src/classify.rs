@@ -30,6 +30,7 @@ fn tag_rank(tag: ErrorTag) -> u32 {
         ErrorTag::InterruptedByDaemonShutdown => line!(),
         ErrorTag::DaemonWontDieFromKill => line!(),
         ErrorTag::DaemonIsBusy => line!(),
+        ErrorTag::PreemptedByNewerInvocation => line!(),
         ErrorTag::DaemonConnect => line!(),
         ErrorTag::GrpcResponseMessageTooLarge => line!(),
         ErrorTag::ClientGrpc => line!(),
@@ -47,6 +48,7 @@ fn tag_rank(tag: ErrorTag) -> u32 {
         ErrorTag::StarlarkFail => line!(),
         ErrorTag::StarlarkStackOverflow => line!(),
         ErrorTag::Visibility => line!(),
+        ErrorTag::PackageListingLimit => line!(),
         ErrorTag::WatchmanTimeout => line!(),
         ErrorTag::HttpServer => line!(),
         ErrorTag::HttpClient => line!(),
@@ -70,6 +72,7 @@ pub(crate) fn error_tag_category(tag: ErrorTag) -> Option<Tier> {
         ErrorTag::DaemonWontDieFromKill => Some(Tier::Tier0),
         ErrorTag::DaemonConnect => None,
         ErrorTag::DaemonIsBusy => Some(Tier::Input),
+        ErrorTag::PreemptedByNewerInvocation => Some(Tier::Input),
         ErrorTag::InternalError => Some(Tier::Tier0),
         // FIXME(JakobDegen): Make this bad experience once that's available. Usually when this
         // happens, it's probably because the user tried to shut down with Ctrl+C and something
@@ -91,6 +94,7 @@ pub(crate) fn error_tag_category(tag: ErrorTag) -> Option<Tier> {
         ErrorTag::StarlarkFail => Some(Tier::Input),
         ErrorTag::StarlarkStackOverflow => Some(Tier::Input),
         ErrorTag::Visibility => Some(Tier::Input),
+        ErrorTag::PackageListingLimit => Some(Tier::Input),
         ErrorTag::Analysis => Some(Tier::Input),
         ErrorTag::WatchmanTimeout => Some(Tier::Tier0),
         ErrorTag::HttpServer => Some(Tier::Tier0),
@@ -11,6 +11,7 @@ use smallvec::smallvec;
 
 use crate as buck2_error;
 use crate::context_value::ContextValue;
+use crate::context_value::TypedContext;
 
 /// Provides the `context` method for `Result`.
 ///
@@ -43,6 +44,12 @@ pub trait BuckErrorContext<T>: Sealed {
         self.buck_error_context(ContextValue::Tags(smallvec![tag]))
     }
 
+    /// Attach structured context, see [`crate::Error::find_typed_context`].
+    #[track_caller]
+    fn typed_context<C: TypedContext>(self, context: C) -> anyhow::Result<T> {
+        self.buck_error_context(ContextValue::typed(context))
+    }
+
     #[track_caller]
     fn internal_error(self, message: &str) -> anyhow::Result<T> {
         self.with_internal_error(|| message.to_owned())
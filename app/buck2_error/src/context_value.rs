@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::any::Any;
 use std::fmt;
 use std::sync::Arc;
 
@@ -17,6 +18,26 @@ pub enum ContextValue {
     Dyn(Arc<str>),
     Tier(Tier),
     Tags(SmallVec<[crate::ErrorTag; 1]>),
+    Typed(#[allocative(skip)] Arc<dyn TypedContext>),
+}
+
+/// A structured piece of context that can be retrieved from the error later with
+/// [`Error::find_typed_context`](crate::Error::find_typed_context).
+///
+/// It is displayed in the error message exactly as string context would be.
+pub trait TypedContext: AsAnyArc + fmt::Display + Send + Sync + 'static {}
+
+impl<T: Any + fmt::Display + Send + Sync + 'static> TypedContext for T {}
+
+/// Implementation detail of [`TypedContext`], needed to downcast it.
+pub trait AsAnyArc {
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+}
+
+impl<T: Any + Send + Sync + 'static> AsAnyArc for T {
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
 }
 
 impl ContextValue {
@@ -27,6 +48,7 @@ impl ContextValue {
             // Displaying the category in the middle of an error message doesn't seem useful
             Self::Tier(_) => None,
             Self::Tags(_) => None,
+            Self::Typed(v) => Some(format!("{}", v).into()),
         }
     }
 
@@ -36,6 +58,7 @@ impl ContextValue {
             Self::Dyn(v) => Arc::clone(v),
             Self::Tier(category) => format!("{:?}", category).into(),
             Self::Tags(tags) => format!("{:?}", tags).into(),
+            Self::Typed(v) => format!("{}", v).into(),
         }
     }
 
@@ -51,6 +74,9 @@ impl ContextValue {
             (ContextValue::Tags(a), ContextValue::Tags(b)) => {
                 assert_eq!(a, b);
             }
+            (ContextValue::Typed(a), ContextValue::Typed(b)) => {
+                assert!(Arc::ptr_eq(a, b));
+            }
             (_, _) => panic!("context variants don't match!"),
         }
     }
@@ -84,6 +110,12 @@ impl Tier {
     }
 }
 
+impl ContextValue {
+    pub fn typed<T: TypedContext>(value: T) -> Self {
+        ContextValue::Typed(Arc::new(value))
+    }
+}
+
 impl From<Tier> for ContextValue {
     fn from(value: Tier) -> Self {
         ContextValue::Tier(value)
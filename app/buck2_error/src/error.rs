@@ -11,12 +11,14 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::sync::Arc;
 
+use dupe::Dupe;
 use either::Either;
 use smallvec::SmallVec;
 
 use crate::classify::best_tag;
 use crate::classify::error_tag_category;
 use crate::context_value::ContextValue;
+use crate::context_value::TypedContext;
 use crate::format::into_anyhow_for_format;
 use crate::root::ErrorRoot;
 use crate::ErrorType;
@@ -148,6 +150,20 @@ impl Error {
         Self(Arc::new(ErrorKind::WithContext(context.into(), self)))
     }
 
+    /// Attach structured context, which can be retrieved later with
+    /// [`find_typed_context`](Self::find_typed_context).
+    pub fn typed_context<T: TypedContext>(self, value: T) -> Self {
+        self.context(ContextValue::typed(value))
+    }
+
+    /// The outermost typed context of type `T` attached to this error, if any.
+    pub fn find_typed_context<T: TypedContext>(&self) -> Option<Arc<T>> {
+        self.iter_context().find_map(|kind| match kind {
+            ContextValue::Typed(v) => v.dupe().as_any_arc().downcast::<T>().ok(),
+            _ => None,
+        })
+    }
+
     #[cold]
     #[track_caller]
     pub(crate) fn new_anyhow_with_context<E, C: Into<ContextValue>>(e: E, c: C) -> anyhow::Error
@@ -243,4 +259,44 @@ mod tests {
 
         assert_ne!(e1.root_id(), e2.root_id());
     }
+
+    #[derive(Debug, PartialEq, derive_more::Display)]
+    #[display(fmt = "target `{}`", "_0")]
+    struct TargetContext(&'static str);
+
+    #[derive(Debug, PartialEq, derive_more::Display)]
+    #[display(fmt = "digest `{}`", "_0")]
+    struct DigestContext(&'static str);
+
+    #[test]
+    fn test_typed_context() {
+        let e: crate::Error = TestError.into();
+        let e = e
+            .typed_context(DigestContext("abc:10"))
+            .context("string context")
+            .typed_context(TargetContext("root//:inner"));
+        let e: anyhow::Error = e.into();
+        let e: crate::Error = e.context("anyhow context").into();
+        let e = e.typed_context(TargetContext("root//:outer"));
+
+        assert_eq!(
+            Some(Arc::new(TargetContext("root//:outer"))),
+            e.find_typed_context::<TargetContext>()
+        );
+        assert_eq!(
+            Some(Arc::new(DigestContext("abc:10"))),
+            e.find_typed_context::<DigestContext>()
+        );
+        assert_eq!(None, e.find_typed_context::<String>());
+        assert_eq!(None, e.find_typed_context::<&'static str>());
+    }
+
+    #[test]
+    fn test_typed_context_display() {
+        let e: crate::Error = TestError.into();
+        let typed = e.clone().typed_context(TargetContext("root//:foo"));
+        let untyped = e.context("target `root//:foo`");
+        assert_eq!(format!("{:#}", untyped), format!("{:#}", typed));
+        assert_eq!(format!("{:?}", untyped), format!("{:?}", typed));
+    }
 }
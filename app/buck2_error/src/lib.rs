@@ -25,6 +25,7 @@ mod source_location;
 
 use std::error::Request;
 
+pub use any::find_tags;
 pub use context::AnyhowContextForError;
 pub use context::BuckErrorContext;
 /// A piece of metadata to indicate whether this error is an infra or user error.
@@ -36,6 +37,7 @@ pub use context::BuckErrorContext;
 /// the future.
 #[doc(inline)]
 pub use context_value::Tier;
+pub use context_value::TypedContext;
 pub use error::DynLateFormat;
 pub use error::Error;
 pub use root::UniqueRootId;
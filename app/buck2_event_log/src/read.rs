@@ -236,7 +236,10 @@ impl EventLogPathBuf {
                 Some(command_progress::Progress::PartialResult(result)) => {
                     Ok(StreamValue::PartialResult(result))
                 }
-                None => Err(anyhow::anyhow!("Event type not recognized")),
+                // Checkpoints are only sent to the client, they are never written to the log.
+                Some(command_progress::Progress::Checkpoint(_)) | None => {
+                    Err(anyhow::anyhow!("Event type not recognized"))
+                }
             }
         });
 
@@ -21,6 +21,7 @@ use anyhow::Context as _;
 use async_compression::tokio::write::GzipEncoder;
 use async_compression::tokio::write::ZstdEncoder;
 use buck2_cli_proto::*;
+use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::paths::abs_path::AbsPathBuf;
 use buck2_core::fs::working_dir::WorkingDir;
@@ -125,8 +126,8 @@ pub(crate) struct NamedEventLogWriter {
 pub(crate) enum LogWriterState {
     Unopened {
         logdir: AbsNormPathBuf,
-        extra_path: Option<AbsPathBuf>,
-        extra_user_event_log_path: Option<AbsPathBuf>,
+        extra_path: Option<EventLogPathBuf>,
+        extra_user_event_log_path: Option<EventLogPathBuf>,
     },
     Opened {
         writers: Vec<NamedEventLogWriter>,
@@ -158,6 +159,12 @@ impl<'a> WriteEventLog<'a> {
         log_size_counter_bytes: Option<Arc<AtomicU64>>,
         allow_vpnless: bool,
     ) -> anyhow::Result<Self> {
+        let extra_path = extra_path
+            .map(|path| prepare_extra_event_log(path, Encoding::JSON_GZIP))
+            .transpose()?;
+        let extra_user_event_log_path = extra_user_event_log_path
+            .map(|path| prepare_extra_event_log(path, Encoding::JSON))
+            .transpose()?;
         Ok(Self {
             state: LogWriterState::Unopened {
                 logdir,
@@ -290,12 +297,7 @@ impl<'a> WriteEventLog<'a> {
         if let Some(extra_path) = maybe_extra_path {
             writers.push(
                 open_event_log_for_writing(
-                    EventLogPathBuf::infer_opt(extra_path.clone())?.unwrap_or_else(
-                        |NoInference(path)| EventLogPathBuf {
-                            path,
-                            encoding: Encoding::JSON_GZIP,
-                        },
-                    ),
+                    extra_path.clone(),
                     self.log_size_counter_bytes.clone(),
                     EventLogType::System,
                 )
@@ -307,12 +309,7 @@ impl<'a> WriteEventLog<'a> {
         if let Some(extra_user_event_log_path) = maybe_extra_user_event_log_path {
             writers.push(
                 open_event_log_for_writing(
-                    EventLogPathBuf::infer_opt(extra_user_event_log_path.clone())?.unwrap_or_else(
-                        |NoInference(path)| EventLogPathBuf {
-                            path,
-                            encoding: Encoding::JSON,
-                        },
-                    ),
+                    extra_user_event_log_path.clone(),
                     self.log_size_counter_bytes.clone(),
                     EventLogType::User,
                 )
@@ -426,6 +423,36 @@ async fn start_persist_event_log_subprocess(
     Ok(writer)
 }
 
+/// Infer the encoding of a user provided event log from its extension, falling back to
+/// `default_encoding`, and check that it can be written. The log itself is only opened once the
+/// first event is received, so this makes a bad path fail the command before it starts, instead
+/// of losing the log.
+fn prepare_extra_event_log(
+    path: AbsPathBuf,
+    default_encoding: Encoding,
+) -> anyhow::Result<EventLogPathBuf> {
+    let path =
+        EventLogPathBuf::infer_opt(path)?.unwrap_or_else(|NoInference(path)| EventLogPathBuf {
+            path,
+            encoding: default_encoding,
+        });
+    if let Some(dir) = path.path.parent() {
+        fs_util::create_dir_all(dir)
+            .with_context(|| format!("Error creating event log directory: `{}`", dir))?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path.path)
+        .with_context(|| {
+            format!(
+                "Failed to open event log for writing at `{}`",
+                path.path.display()
+            )
+        })?;
+    Ok(path)
+}
+
 async fn open_event_log_for_writing(
     path: EventLogPathBuf,
     bytes_written: Option<Arc<AtomicU64>>,
@@ -797,8 +824,44 @@ mod tests {
             .unwrap();
         let expected = buck2_cli_proto::CommandProgress {
             progress: Some(command_progress::Progress::Event(event.into())),
+            sequence: 0,
         }
         .encode_length_delimited_to_vec();
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_prepare_extra_event_log_encoding() -> anyhow::Result<()> {
+        let tmp_dir = TempDir::new()?;
+
+        for (name, expected) in [
+            ("log.json-lines", Encoding::JSON),
+            ("log.json-lines.gz", Encoding::JSON_GZIP),
+            ("log.json-lines.zst", Encoding::JSON_ZSTD),
+            ("log.pb.zst", Encoding::PROTO_ZSTD),
+            // Falls back to the default encoding.
+            ("log", Encoding::JSON_GZIP),
+        ] {
+            let path = AbsPathBuf::try_from(tmp_dir.path().join(name)).unwrap();
+            let log = prepare_extra_event_log(path, Encoding::JSON_GZIP)?;
+            assert_eq!(expected.extensions, log.encoding.extensions, "for {}", name);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_extra_event_log_creates_dir() -> anyhow::Result<()> {
+        let tmp_dir = TempDir::new()?;
+        let path = AbsPathBuf::try_from(tmp_dir.path().join("a/b/log.json-lines")).unwrap();
+
+        prepare_extra_event_log(path.clone(), Encoding::JSON)?;
+        assert!(path.exists());
+
+        // A directory cannot be used as a log.
+        let dir = AbsPathBuf::try_from(tmp_dir.path().join("a")).unwrap();
+        assert!(prepare_extra_event_log(dir, Encoding::JSON).is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,141 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::time::Duration;
+
+use buck2_util::truncate::truncate;
+
+use crate::fmt_duration::fmt_duration;
+use crate::humanized::HumanizedCount;
+
+/// Longest command line shown for the command we are waiting for. The daemon already truncates
+/// it, but to a length that doesn't fit on a line.
+const MAX_CMD_ARGS_LEN: usize = 100;
+
+/// What the command this command is blocked on is doing, as last reported by the daemon.
+#[derive(Default)]
+pub struct ConcurrentCommandState {
+    progress: Option<buck2_data::ConcurrentCommandProgress>,
+}
+
+impl ConcurrentCommandState {
+    pub(crate) fn update(&mut self, progress: &buck2_data::ConcurrentCommandProgress) {
+        self.progress = Some(progress.clone());
+    }
+
+    /// This command is not blocked anymore.
+    pub(crate) fn clear(&mut self) {
+        self.progress = None;
+    }
+
+    /// A status line for the command we are waiting for, if we are waiting for one and the daemon
+    /// told us about it.
+    pub fn render(&self) -> Option<String> {
+        self.progress.as_ref().map(render_progress)
+    }
+}
+
+fn render_progress(progress: &buck2_data::ConcurrentCommandProgress) -> String {
+    let mut details = Vec::new();
+    match buck2_data::CommandPhase::from_i32(progress.phase) {
+        Some(buck2_data::CommandPhase::Loading) => details.push("loading".to_owned()),
+        Some(buck2_data::CommandPhase::Analysis) => details.push("analysis".to_owned()),
+        Some(buck2_data::CommandPhase::Execution) => details.push("execution".to_owned()),
+        Some(buck2_data::CommandPhase::Unknown) | None => {}
+    }
+    let total = progress.completed + progress.remaining;
+    if total > 0 {
+        details.push(format!(
+            "{}/{} done",
+            HumanizedCount::new(progress.completed),
+            HumanizedCount::new(total)
+        ));
+    }
+    if let Some(elapsed) = progress
+        .elapsed
+        .as_ref()
+        .and_then(|d| Duration::try_from(d.clone()).ok())
+    {
+        details.push(fmt_duration(elapsed, 1.0));
+    }
+
+    let cmd_args = truncate(&progress.cmd_args, MAX_CMD_ARGS_LEN);
+    if details.is_empty() {
+        format!("Waiting: daemon is running `{}`", cmd_args)
+    } else {
+        format!(
+            "Waiting: daemon is running `{}` ({})",
+            cmd_args,
+            details.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::concurrent_command::ConcurrentCommandState;
+
+    fn progress(cmd_args: &str) -> buck2_data::ConcurrentCommandProgress {
+        buck2_data::ConcurrentCommandProgress {
+            trace_id: "trace".to_owned(),
+            cmd_args: cmd_args.to_owned(),
+            elapsed: Some(Duration::from_secs(252).try_into().unwrap()),
+            phase: buck2_data::CommandPhase::Analysis as i32,
+            completed: 4,
+            remaining: 6,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let mut state = ConcurrentCommandState::default();
+        assert_eq!(None, state.render());
+
+        state.update(&progress("buck2 build //foo/..."));
+        assert_eq!(
+            Some(
+                "Waiting: daemon is running `buck2 build //foo/...` (analysis, 4/10 done, 4:12.0s)"
+            ),
+            state.render().as_deref()
+        );
+
+        state.update(&buck2_data::ConcurrentCommandProgress {
+            cmd_args: "buck2 targets //...".to_owned(),
+            ..Default::default()
+        });
+        assert_eq!(
+            Some("Waiting: daemon is running `buck2 targets //...`"),
+            state.render().as_deref()
+        );
+
+        state.clear();
+        assert_eq!(None, state.render());
+    }
+
+    #[test]
+    fn test_render_truncates_long_argv() {
+        let mut state = ConcurrentCommandState::default();
+        let targets = (0..100)
+            .map(|i| format!("//foo:t{}", i))
+            .collect::<Vec<_>>();
+        state.update(&progress(&format!("buck2 build {}", targets.join(" "))));
+
+        let rendered = state.render().unwrap();
+        assert!(rendered.len() < 200, "{}", rendered);
+        assert!(
+            rendered.starts_with("Waiting: daemon is running `buck2 build //foo:t0 "),
+            "{}",
+            rendered
+        );
+        assert!(rendered.contains("<<omitted>>"), "{}", rendered);
+        assert!(rendered.ends_with("//foo:t99` (analysis, 4/10 done, 4:12.0s)"));
+    }
+}
@@ -15,6 +15,7 @@ use buck2_events::BuckEvent;
 use buck2_wrapper_common::invocation_id::TraceId;
 
 use crate::action_stats::ActionStats;
+use crate::concurrent_command::ConcurrentCommandState;
 use crate::debug_events::DebugEventsState;
 use crate::dice_state::DiceState;
 use crate::re_state::ReState;
@@ -32,6 +33,7 @@ pub struct EventObserver<E> {
     session_info: SessionInfo,
     test_state: TestState,
     starlark_debugger_state: StarlarkDebuggerState,
+    concurrent_command: ConcurrentCommandState,
     /// When running without the Superconsole, we skip some state that we don't need. This might be
     /// premature optimization.
     extra: E,
@@ -54,6 +56,7 @@ where
             },
             test_state: TestState::default(),
             starlark_debugger_state: StarlarkDebuggerState::new(),
+            concurrent_command: ConcurrentCommandState::default(),
             extra: E::new(),
         }
     }
@@ -72,6 +75,9 @@ where
                         ActionExecution(action_execution_end) => {
                             self.action_stats.update(action_execution_end);
                         }
+                        DiceBlockConcurrentCommand(..) => {
+                            self.concurrent_command.clear();
+                        }
                         _ => {}
                     }
                 }
@@ -118,6 +124,9 @@ where
                                 self.session_info.modern_dice = true;
                             }
                         }
+                        ConcurrentCommandProgress(progress) => {
+                            self.concurrent_command.update(progress);
+                        }
                         _ => {}
                     }
                 }
@@ -158,6 +167,10 @@ where
         &self.test_state
     }
 
+    pub fn concurrent_command(&self) -> &ConcurrentCommandState {
+        &self.concurrent_command
+    }
+
     pub fn extra(&self) -> &E {
         &self.extra
     }
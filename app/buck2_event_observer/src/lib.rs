@@ -12,6 +12,7 @@
 
 pub mod action_stats;
 pub mod cache_hit_rate;
+pub mod concurrent_command;
 pub mod debug_events;
 pub mod dice_state;
 pub mod display;
@@ -63,6 +63,12 @@ impl TwoSnapshots {
     pub fn http_download_bytes_per_second(&self) -> Option<u64> {
         self.bytes_per_second(|snapshot| snapshot.http_download_bytes)
     }
+
+    /// Target counts reported in the most recent snapshot, if any.
+    pub fn target_progress(&self) -> Option<&buck2_data::TargetProgress> {
+        let (_, last_snapshot) = self.last.as_ref()?;
+        last_snapshot.target_progress.as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,396 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A clock that keeps working when the wall clock is stepped, e.g. by NTP, by a VM host, or on
+//! resume from suspend.
+//!
+//! Time is measured with the monotonic clock, from a wall clock origin. When the wall clock
+//! moves away from that timeline by more than a threshold, the step is reported once, and:
+//!
+//! * if the wall clock jumped forward (which is also what resuming from suspend looks like, since
+//!   the monotonic clock doesn't advance while suspended), the timeline follows it;
+//! * if it jumped backwards, the timeline is kept, so that timestamps never go backwards and
+//!   deadlines don't move. Until the daemon restarts, the time reported is then ahead of the
+//!   wall clock.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+/// Wall clock steps smaller than this are ignored.
+pub const DEFAULT_STEP_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// Where a [`HybridClock`] reads time from. Only replaced in tests.
+pub trait TimeSource: Send + Sync + 'static {
+    fn monotonic(&self) -> Instant;
+    fn wall(&self) -> SystemTime;
+}
+
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn monotonic(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HybridTime {
+    /// Use this to measure durations.
+    pub monotonic: Instant,
+    /// Use this for timestamps and wall clock deadlines. Never decreases.
+    pub wall: SystemTime,
+}
+
+/// A step of the wall clock, relative to the timeline of the [`HybridClock`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ClockStep {
+    /// What the wall clock was expected to read.
+    pub expected: SystemTime,
+    /// What it actually read.
+    pub actual: SystemTime,
+    /// Whether the timeline of the clock now follows the wall clock again.
+    pub recalibrated: bool,
+}
+
+impl ClockStep {
+    /// Signed size of the step, positive when the wall clock jumped forward.
+    pub fn step_millis(&self) -> i64 {
+        (to_nanos(self.actual) - to_nanos(self.expected)) / 1_000_000
+    }
+}
+
+/// This is read on every event, so reading the time only uses atomics. The mutex is only taken
+/// when the wall clock stepped.
+///
+/// All wall times are in nanoseconds since the epoch. The timeline is
+/// `origin_wall + (monotonic - origin_monotonic) + shift`.
+pub struct HybridClock {
+    source: Box<dyn TimeSource>,
+    step_threshold: Duration,
+    origin_monotonic: Instant,
+    origin_wall: i64,
+    /// How far forward the timeline was moved by forward steps.
+    shift: AtomicI64,
+    /// Wall clock minus timeline. Only ever negative, after a backwards step.
+    offset: AtomicI64,
+    /// The last wall time returned, so that it never decreases.
+    last_wall: AtomicI64,
+    has_pending_step: AtomicBool,
+    /// Step that has not been reported yet. Also serializes handling steps.
+    pending_step: Mutex<Option<ClockStep>>,
+}
+
+impl HybridClock {
+    pub fn new(source: Box<dyn TimeSource>, step_threshold: Duration) -> Self {
+        let origin_monotonic = source.monotonic();
+        let origin_wall = to_nanos(source.wall());
+        Self {
+            source,
+            step_threshold,
+            origin_monotonic,
+            origin_wall,
+            shift: AtomicI64::new(0),
+            offset: AtomicI64::new(0),
+            last_wall: AtomicI64::new(origin_wall),
+            has_pending_step: AtomicBool::new(false),
+            pending_step: Mutex::new(None),
+        }
+    }
+
+    /// The clock of this process.
+    pub fn system() -> &'static HybridClock {
+        static CLOCK: OnceLock<HybridClock> = OnceLock::new();
+        CLOCK.get_or_init(|| HybridClock::new(Box::new(SystemTimeSource), DEFAULT_STEP_THRESHOLD))
+    }
+
+    pub fn now(&self) -> HybridTime {
+        let monotonic = self.source.monotonic();
+        let actual = to_nanos(self.source.wall());
+        let elapsed = monotonic
+            .saturating_duration_since(self.origin_monotonic)
+            .as_nanos() as i64;
+
+        let mut timeline = self.origin_wall + elapsed + self.shift.load(Ordering::Acquire);
+        let offset = self.offset.load(Ordering::Acquire);
+        if self.is_step(actual - timeline, offset) {
+            timeline = self.handle_step(actual, elapsed);
+        }
+
+        let wall = self
+            .last_wall
+            .fetch_max(timeline, Ordering::AcqRel)
+            .max(timeline);
+        HybridTime {
+            monotonic,
+            wall: from_nanos(wall),
+        }
+    }
+
+    fn is_step(&self, drift: i64, offset: i64) -> bool {
+        (drift - offset).unsigned_abs() as u128 > self.step_threshold.as_nanos()
+    }
+
+    /// Check again for a step now that no other thread handles one, and move the timeline if
+    /// there is one. Returns the timeline.
+    #[cold]
+    fn handle_step(&self, actual: i64, elapsed: i64) -> i64 {
+        let mut pending_step = self.pending_step.lock().unwrap();
+
+        let shift = self.shift.load(Ordering::Acquire);
+        let offset = self.offset.load(Ordering::Acquire);
+        let timeline = self.origin_wall + elapsed + shift;
+        let drift = actual - timeline;
+        if !self.is_step(drift, offset) {
+            // Another thread handled it.
+            return timeline;
+        }
+
+        let step = ClockStep {
+            expected: from_nanos(timeline + offset),
+            actual: from_nanos(actual),
+            recalibrated: drift >= 0,
+        };
+        let timeline = if step.recalibrated {
+            self.shift.store(shift + drift, Ordering::Release);
+            self.offset.store(0, Ordering::Release);
+            actual
+        } else {
+            self.offset.store(drift, Ordering::Release);
+            timeline
+        };
+
+        tracing::warn!(
+            "Wall clock jumped by {}ms{}",
+            step.step_millis(),
+            if step.recalibrated {
+                ""
+            } else {
+                ", timestamps will be ahead of it"
+            }
+        );
+        *pending_step = Some(step);
+        self.has_pending_step.store(true, Ordering::Release);
+        timeline
+    }
+
+    /// The last wall clock step, if it wasn't taken yet. Each step is returned once, so that it
+    /// can be reported.
+    pub fn take_step(&self) -> Option<ClockStep> {
+        if !self.has_pending_step.load(Ordering::Acquire) {
+            return None;
+        }
+        let mut pending_step = self.pending_step.lock().unwrap();
+        self.has_pending_step.store(false, Ordering::Release);
+        pending_step.take()
+    }
+}
+
+/// Nanoseconds since the epoch, negative before it.
+fn to_nanos(time: SystemTime) -> i64 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => d.as_nanos() as i64,
+        Err(e) => -(e.duration().as_nanos() as i64),
+    }
+}
+
+fn from_nanos(nanos: i64) -> SystemTime {
+    let d = Duration::from_nanos(nanos.unsigned_abs());
+    if nanos >= 0 {
+        SystemTime::UNIX_EPOCH + d
+    } else {
+        SystemTime::UNIX_EPOCH - d
+    }
+}
+
+pub mod testing {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use std::time::Instant;
+    use std::time::SystemTime;
+
+    use crate::clock::TimeSource;
+
+    /// A time source that only moves when told to.
+    #[derive(Clone)]
+    pub struct FakeTimeSource {
+        state: Arc<Mutex<(Instant, SystemTime)>>,
+    }
+
+    impl FakeTimeSource {
+        pub fn new(wall: SystemTime) -> Self {
+            Self {
+                state: Arc::new(Mutex::new((Instant::now(), wall))),
+            }
+        }
+
+        /// Both clocks move forward.
+        pub fn advance(&self, d: Duration) {
+            let mut state = self.state.lock().unwrap();
+            state.0 += d;
+            state.1 += d;
+        }
+
+        /// Only the wall clock moves.
+        pub fn step_wall_forward(&self, d: Duration) {
+            self.state.lock().unwrap().1 += d;
+        }
+
+        /// Only the wall clock moves.
+        pub fn step_wall_backward(&self, d: Duration) {
+            self.state.lock().unwrap().1 -= d;
+        }
+    }
+
+    impl TimeSource for FakeTimeSource {
+        fn monotonic(&self) -> Instant {
+            self.state.lock().unwrap().0
+        }
+
+        fn wall(&self) -> SystemTime {
+            self.state.lock().unwrap().1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    use crate::clock::testing::FakeTimeSource;
+    use crate::clock::HybridClock;
+    use crate::clock::TimeSource;
+
+    const THRESHOLD: Duration = Duration::from_secs(10);
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn clock() -> (FakeTimeSource, HybridClock) {
+        let source = FakeTimeSource::new(SystemTime::UNIX_EPOCH + 1000 * HOUR);
+        let clock = HybridClock::new(Box::new(source.clone()), THRESHOLD);
+        (source, clock)
+    }
+
+    #[test]
+    fn test_follows_wall_clock() {
+        let (source, clock) = clock();
+        let start = clock.now();
+        source.advance(Duration::from_secs(5));
+        let now = clock.now();
+        assert_eq!(source.wall(), now.wall);
+        assert_eq!(Duration::from_secs(5), now.monotonic - start.monotonic);
+        assert_eq!(None, clock.take_step());
+    }
+
+    #[test]
+    fn test_small_steps_are_ignored() {
+        let (source, clock) = clock();
+        let start = clock.now();
+        source.step_wall_backward(Duration::from_secs(2));
+        assert_eq!(start.wall, clock.now().wall);
+        source.step_wall_forward(Duration::from_secs(5));
+        source.advance(Duration::from_secs(1));
+        assert_eq!(start.wall + Duration::from_secs(1), clock.now().wall);
+        assert_eq!(None, clock.take_step());
+    }
+
+    #[test]
+    fn test_backward_step_keeps_timeline() {
+        let (source, clock) = clock();
+        let before = clock.now();
+
+        source.advance(Duration::from_secs(1));
+        source.step_wall_backward(HOUR);
+        let after = clock.now();
+        assert_eq!(before.wall + Duration::from_secs(1), after.wall);
+
+        let step = clock.take_step().unwrap();
+        assert!(!step.recalibrated);
+        assert_eq!(-3600 * 1000, step.step_millis());
+        assert_eq!(None, clock.take_step());
+
+        // The clock keeps going from where it was, and the step is not reported again.
+        source.advance(Duration::from_secs(30));
+        assert_eq!(before.wall + Duration::from_secs(31), clock.now().wall);
+        assert_eq!(None, clock.take_step());
+    }
+
+    #[test]
+    fn test_forward_step_recalibrates() {
+        let (source, clock) = clock();
+        let before = clock.now();
+
+        // E.g. resuming from suspend, during which the monotonic clock did not move.
+        source.step_wall_forward(HOUR);
+        let after = clock.now();
+        assert_eq!(source.wall(), after.wall);
+        assert_eq!(before.monotonic, after.monotonic);
+
+        let step = clock.take_step().unwrap();
+        assert!(step.recalibrated);
+        assert_eq!(3600 * 1000, step.step_millis());
+        assert_eq!(None, clock.take_step());
+
+        source.advance(Duration::from_secs(1));
+        assert_eq!(source.wall(), clock.now().wall);
+        assert_eq!(None, clock.take_step());
+    }
+
+    #[test]
+    fn test_one_report_per_step() {
+        let (source, clock) = clock();
+        let mut steps = Vec::new();
+        for _ in 0..2 {
+            source.step_wall_backward(HOUR);
+            for _ in 0..3 {
+                source.advance(Duration::from_secs(1));
+                clock.now();
+                steps.extend(clock.take_step());
+            }
+        }
+        source.step_wall_forward(3 * HOUR);
+        clock.now();
+        steps.extend(clock.take_step());
+
+        assert_eq!(
+            vec![false, false, true],
+            steps.iter().map(|s| s.recalibrated).collect::<Vec<_>>()
+        );
+        assert_eq!(source.wall(), clock.now().wall);
+    }
+
+    #[test]
+    fn test_timestamps_never_decrease() {
+        let (source, clock) = clock();
+        let mut timestamps = Vec::new();
+        for i in 0..20 {
+            match i % 5 {
+                1 => source.step_wall_backward(HOUR),
+                3 => source.step_wall_backward(Duration::from_secs(5)),
+                4 => source.step_wall_forward(Duration::from_secs(30)),
+                _ => source.advance(Duration::from_secs(1)),
+            }
+            timestamps.push(clock.now().wall);
+        }
+
+        let mut sorted = timestamps.clone();
+        sorted.sort();
+        assert_eq!(sorted, timestamps);
+    }
+}
@@ -21,7 +21,6 @@ use std::sync::Arc;
 use std::task;
 use std::time::Duration;
 use std::time::Instant;
-use std::time::SystemTime;
 
 use buck2_data::buck_event;
 use buck2_data::span_end_event;
@@ -34,6 +33,7 @@ use futures::Future;
 use pin_project::pin_project;
 use smallvec::SmallVec;
 
+use crate::clock::HybridClock;
 use crate::sink::null::NullEventSink;
 use crate::span::SpanId;
 use crate::BuckEvent;
@@ -107,7 +107,27 @@ impl EventDispatcher {
         span_id: Option<SpanId>,
         parent_id: Option<SpanId>,
     ) {
-        let now = SystemTime::now();
+        let clock = HybridClock::system();
+        let now = clock.now().wall;
+
+        if let Some(step) = clock.take_step() {
+            let step = buck2_data::InstantEvent {
+                data: Some(
+                    buck2_data::WallClockStep {
+                        step_millis: step.step_millis(),
+                        recalibrated: step.recalibrated,
+                    }
+                    .into(),
+                ),
+            };
+            self.sink.send(Event::Buck(BuckEvent::new(
+                now,
+                self.trace_id.dupe(),
+                None,
+                parent_id,
+                step.into(),
+            )));
+        }
 
         let event = BuckEvent::new(now, self.trace_id.dupe(), span_id, parent_id, data.into());
         self.sink.send(Event::Buck(event));
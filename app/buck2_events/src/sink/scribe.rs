@@ -242,6 +242,9 @@ mod fbcode {
                     Some(Data::StructuredError(..)) => true,
                     Some(Data::PersistEventLogSubprocess(..)) => true,
                     Some(Data::CleanStaleResult(..)) => true,
+                    Some(Data::SqliteVacuum(..)) => true,
+                    Some(Data::MaterializerDeclareExistingMismatch(..)) => true,
+                    Some(Data::WallClockStep(..)) => true,
                     None => false,
                     _ => false,
                 }
@@ -10,10 +10,14 @@
 use std::fmt;
 
 use buck2_common::cas_digest::CasDigest;
+use buck2_common::cas_digest::CasDigestConfig;
 use buck2_common::cas_digest::CasDigestKind;
 use buck2_common::cas_digest::CasDigestParseError;
 use buck2_common::cas_digest::DigestAlgorithm;
+use buck2_common::cas_digest::DigestAlgorithmKind;
+use buck2_common::cas_digest::RawDigest;
 use buck2_common::cas_digest::TrackedCasDigest;
+use remote_execution::digest_function;
 use remote_execution::Digest;
 use remote_execution::TDigest;
 
@@ -28,6 +32,51 @@ pub enum DigestConversionError {
         #[source]
         error: CasDigestParseError,
     },
+
+    #[error("Digest `{}` uses unsupported digest function `{}`", digest, function)]
+    UnsupportedDigestFunction { digest: String, function: i32 },
+
+    #[error(
+        "Digest `{}` in an action result uses {}, which is not enabled in `buck2.digest_algorithms`",
+        digest,
+        algorithm
+    )]
+    AlgorithmNotEnabled {
+        digest: String,
+        algorithm: DigestAlgorithmKind,
+    },
+}
+
+/// The RE `DigestFunction` for a digest algorithm, if the protocol has one. Keyed BLAKE3 is sent
+/// as BLAKE3: the protocol has no separate value for it, the key is agreed on out of band.
+pub fn digest_function_for_kind(kind: DigestAlgorithmKind) -> digest_function::Value {
+    match kind {
+        DigestAlgorithmKind::Sha1 => digest_function::Value::Sha1,
+        DigestAlgorithmKind::Sha256 => digest_function::Value::Sha256,
+        DigestAlgorithmKind::Blake3 | DigestAlgorithmKind::Blake3Keyed => {
+            digest_function::Value::Blake3
+        }
+    }
+}
+
+/// The digest algorithm for an RE `DigestFunction`. BLAKE3 is taken to be keyed if that's the
+/// BLAKE3 variant enabled in `config`. Returns `None` for functions we don't support.
+pub fn kind_for_digest_function(
+    function: digest_function::Value,
+    config: CasDigestConfig,
+) -> Option<DigestAlgorithmKind> {
+    match function {
+        digest_function::Value::Sha1 => Some(DigestAlgorithmKind::Sha1),
+        digest_function::Value::Sha256 => Some(DigestAlgorithmKind::Sha256),
+        digest_function::Value::Blake3 => {
+            if config.allows_blake3_keyed() {
+                Some(DigestAlgorithmKind::Blake3Keyed)
+            } else {
+                Some(DigestAlgorithmKind::Blake3)
+            }
+        }
+        _ => None,
+    }
 }
 
 pub trait CasDigestFromReExt: Sized {
@@ -49,6 +98,49 @@ pub trait CasDigestFromReExt: Sized {
     fn from_grpc(x: &Digest, digest_config: DigestConfig) -> Result<Self, DigestConversionError> {
         Self::from_grpc_with_algo(x, digest_config).map(|(d, _a)| d)
     }
+
+    /// Parse a digest that came with an explicit RE `DigestFunction` (e.g. in an action result).
+    /// The function doesn't need to be enabled in `digest_config`. When it is `UNKNOWN` (i.e. not
+    /// set), the algorithm is inferred from the length of the hash, like `from_grpc`.
+    fn from_grpc_with_digest_function(
+        x: &Digest,
+        function: i32,
+        digest_config: DigestConfig,
+    ) -> Result<Self, DigestConversionError>;
+
+    /// Parse a digest from an action result the server computed with the RE `DigestFunction`
+    /// `function`. Unlike `from_grpc_with_digest_function`, the algorithm must be enabled in
+    /// `digest_config`, since we hash the outputs ourselves too.
+    fn from_re_action_result(
+        x: &TDigest,
+        function: i32,
+        digest_config: DigestConfig,
+    ) -> Result<Self, DigestConversionError>;
+
+    /// Like `from_re_action_result`, for digests in the trees of output directories.
+    fn from_grpc_action_result(
+        x: &Digest,
+        function: i32,
+        digest_config: DigestConfig,
+    ) -> Result<Self, DigestConversionError>;
+}
+
+fn check_action_result_digest<Kind: CasDigestKind>(
+    digest: CasDigest<Kind>,
+    digest_config: DigestConfig,
+) -> Result<CasDigest<Kind>, DigestConversionError> {
+    let algorithm = digest.raw_digest().algorithm();
+    if digest_config
+        .cas_digest_config()
+        .algorithm_for_kind(algorithm)
+        .is_none()
+    {
+        return Err(DigestConversionError::AlgorithmNotEnabled {
+            digest: digest.display_with_algorithm().to_string(),
+            algorithm,
+        });
+    }
+    Ok(digest)
 }
 
 pub trait CasDigestToReExt {
@@ -83,6 +175,69 @@ impl<Kind: CasDigestKind> CasDigestFromReExt for CasDigest<Kind> {
 
         Ok((Self::new(ret, digest.size_bytes as u64), algo))
     }
+
+    fn from_grpc_with_digest_function(
+        digest: &Digest,
+        function: i32,
+        digest_config: DigestConfig,
+    ) -> Result<Self, DigestConversionError> {
+        let display = || format!("{}:{}", digest.hash, digest.size_bytes);
+
+        let kind = match digest_function::Value::from_i32(function) {
+            Some(digest_function::Value::Unknown) => {
+                return Self::from_grpc(digest, digest_config);
+            }
+            Some(function) => kind_for_digest_function(function, digest_config.cas_digest_config()),
+            None => None,
+        };
+        let kind = kind.ok_or_else(|| DigestConversionError::UnsupportedDigestFunction {
+            digest: display(),
+            function,
+        })?;
+
+        let ret = RawDigest::parse_for_kind(kind, digest.hash.as_bytes()).map_err(|error| {
+            DigestConversionError::ParseError {
+                digest: display(),
+                error,
+            }
+        })?;
+        Ok(Self::new(ret, digest.size_bytes as u64))
+    }
+
+    fn from_re_action_result(
+        digest: &TDigest,
+        function: i32,
+        digest_config: DigestConfig,
+    ) -> Result<Self, DigestConversionError> {
+        Self::from_grpc_action_result(
+            &Digest {
+                hash: digest.hash.clone(),
+                size_bytes: digest.size_in_bytes,
+            },
+            function,
+            digest_config,
+        )
+    }
+
+    fn from_grpc_action_result(
+        digest: &Digest,
+        function: i32,
+        digest_config: DigestConfig,
+    ) -> Result<Self, DigestConversionError> {
+        let ret = if function == digest_function::Value::Unknown as i32 {
+            // Without a function the hash may still say what it is with an `ALGORITHM:` prefix.
+            let display = format!("{}:{}", digest.hash, digest.size_bytes);
+            Self::parse_foreign_digest(&display, digest_config.cas_digest_config())
+                .map_err(|error| DigestConversionError::ParseError {
+                    digest: display.clone(),
+                    error,
+                })?
+                .0
+        } else {
+            Self::from_grpc_with_digest_function(digest, function, digest_config)?
+        };
+        check_action_result_digest(ret, digest_config)
+    }
 }
 
 pub trait CasDigestConversionResultExt {
@@ -96,6 +251,10 @@ impl<Kind: CasDigestKind> CasDigestConversionResultExt
         match self {
             Self::Ok(ref v) => v as _,
             Self::Err(DigestConversionError::ParseError { ref digest, .. }) => digest as _,
+            Self::Err(DigestConversionError::UnsupportedDigestFunction { ref digest, .. }) => {
+                digest as _
+            }
+            Self::Err(DigestConversionError::AlgorithmNotEnabled { ref digest, .. }) => digest as _,
         }
     }
 }
@@ -126,3 +285,151 @@ impl<Kind: CasDigestKind> CasDigestToReExt for CasDigest<Kind> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use buck2_common::cas_digest::CasDigest;
+    use buck2_common::cas_digest::DigestAlgorithm;
+    use buck2_common::cas_digest::DigestAlgorithmKind;
+    use buck2_common::file_ops::FileDigestKind;
+    use remote_execution::digest_function;
+
+    use crate::digest::digest_function_for_kind;
+    use crate::digest::CasDigestFromReExt;
+    use crate::digest::CasDigestToReExt;
+    use crate::digest::DigestConversionError;
+    use crate::digest_config::DigestConfig;
+
+    #[test]
+    fn test_from_grpc_with_digest_function() {
+        let config =
+            DigestConfig::leak_new(vec![DigestAlgorithm::Sha1, DigestAlgorithm::Sha256], None)
+                .unwrap();
+        let blake3 = CasDigest::<FileDigestKind>::from_content_for_algorithm(
+            b"foo",
+            DigestAlgorithm::Blake3,
+        );
+
+        // BLAKE3 isn't enabled, and the hash would be taken for a SHA256 based on its length.
+        let function = digest_function_for_kind(DigestAlgorithmKind::Blake3) as i32;
+        let parsed = CasDigest::<FileDigestKind>::from_grpc_with_digest_function(
+            &blake3.to_grpc(),
+            function,
+            config,
+        )
+        .unwrap();
+        assert_eq!(parsed, blake3);
+        assert_eq!(parsed.raw_digest().algorithm(), DigestAlgorithmKind::Blake3);
+
+        let unknown = digest_function::Value::Unknown as i32;
+        let parsed = CasDigest::<FileDigestKind>::from_grpc_with_digest_function(
+            &blake3.to_grpc(),
+            unknown,
+            config,
+        )
+        .unwrap();
+        assert_eq!(parsed.raw_digest().algorithm(), DigestAlgorithmKind::Sha256);
+
+        let md5 = digest_function::Value::Md5 as i32;
+        assert!(
+            CasDigest::<FileDigestKind>::from_grpc_with_digest_function(
+                &blake3.to_grpc(),
+                md5,
+                config
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_blake3_keyed_digest_function() {
+        let config =
+            DigestConfig::leak_new(vec![DigestAlgorithm::Blake3Keyed { key: &[1; 32] }], None)
+                .unwrap();
+        let digest = CasDigest::<FileDigestKind>::from_content(b"foo", config.cas_digest_config());
+
+        let function = digest_function_for_kind(digest.raw_digest().algorithm());
+        assert_eq!(function, digest_function::Value::Blake3);
+        let parsed = CasDigest::<FileDigestKind>::from_grpc_with_digest_function(
+            &digest.to_grpc(),
+            function as i32,
+            config,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed.raw_digest().algorithm(),
+            DigestAlgorithmKind::Blake3Keyed
+        );
+        assert_eq!(parsed, digest);
+    }
+
+    #[test]
+    fn test_from_re_action_result() {
+        let sha256 =
+            DigestConfig::leak_new(vec![DigestAlgorithm::Sha256, DigestAlgorithm::Sha1], None)
+                .unwrap();
+        let blake3 =
+            DigestConfig::leak_new(vec![DigestAlgorithm::Blake3, DigestAlgorithm::Sha1], None)
+                .unwrap();
+        let sha256_function = digest_function_for_kind(DigestAlgorithmKind::Sha256) as i32;
+        let blake3_function = digest_function_for_kind(DigestAlgorithmKind::Blake3) as i32;
+        let unknown = digest_function::Value::Unknown as i32;
+
+        // Plain SHA256 digests parse as before, whether the server names its function or not.
+        let digest = CasDigest::<FileDigestKind>::from_content(b"foo", sha256.cas_digest_config());
+        for function in [sha256_function, unknown] {
+            let parsed = CasDigest::<FileDigestKind>::from_re_action_result(
+                &digest.to_re(),
+                function,
+                sha256,
+            )
+            .unwrap();
+            assert_eq!(parsed, digest);
+        }
+
+        // A BLAKE3 digest is recognized as such by its function, which a SHA256 config doesn't
+        // allow: we couldn't hash the outputs the same way.
+        let digest = CasDigest::<FileDigestKind>::from_content(b"foo", blake3.cas_digest_config());
+        assert_eq!(
+            CasDigest::<FileDigestKind>::from_re_action_result(
+                &digest.to_re(),
+                blake3_function,
+                blake3,
+            )
+            .unwrap(),
+            digest
+        );
+        let err = CasDigest::<FileDigestKind>::from_re_action_result(
+            &digest.to_re(),
+            blake3_function,
+            sha256,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                DigestConversionError::AlgorithmNotEnabled {
+                    algorithm: DigestAlgorithmKind::Blake3,
+                    ..
+                }
+            ),
+            "{:?}",
+            err
+        );
+        assert!(
+            err.to_string()
+                .contains(&digest.display_with_algorithm().to_string())
+        );
+
+        // Without a function, an algorithm prefix on the hash is honoured.
+        let prefixed = remote_execution::TDigest {
+            hash: format!("{}:{}", DigestAlgorithmKind::Blake3, digest.raw_digest()),
+            size_in_bytes: digest.size() as i64,
+            ..Default::default()
+        };
+        assert_eq!(
+            CasDigest::<FileDigestKind>::from_re_action_result(&prefixed, unknown, blake3).unwrap(),
+            digest
+        );
+    }
+}
@@ -49,6 +49,7 @@ use dupe::Dupe;
 use once_cell::sync::Lazy;
 use ref_cast::RefCast;
 use remote_execution as RE;
+use remote_execution::digest_function;
 use starlark_map::small_map::SmallMap;
 
 use crate::artifact_value::ArtifactValue;
@@ -285,6 +286,22 @@ pub fn re_tree_to_directory(
     tree: &RE::Tree,
     leaf_expires: &DateTime<Utc>,
     digest_config: DigestConfig,
+) -> anyhow::Result<ActionDirectoryBuilder> {
+    re_tree_to_directory_with_digest_function(
+        tree,
+        leaf_expires,
+        digest_function::Value::Unknown as i32,
+        digest_config,
+    )
+}
+
+/// Like `re_tree_to_directory`, for a tree from an action result whose file digests were computed
+/// with the RE `DigestFunction` `digest_function`.
+pub fn re_tree_to_directory_with_digest_function(
+    tree: &RE::Tree,
+    leaf_expires: &DateTime<Utc>,
+    digest_function: i32,
+    digest_config: DigestConfig,
 ) -> anyhow::Result<ActionDirectoryBuilder> {
     /// A map of digests to directories, populated lazily when we access it based on the hash we
     /// use. We need this because in a RE tree, the directories in the tree don't carry their hash,
@@ -328,6 +345,7 @@ pub fn re_tree_to_directory(
         re_dir_name: &'_ (impl fmt::Display + ?Sized),
         dirmap: &'_ mut DirMap<'a>,
         leaf_expires: &DateTime<Utc>,
+        digest_function: i32,
         digest_config: DigestConfig,
     ) -> anyhow::Result<ActionDirectoryBuilder> {
         let mut builder = ActionDirectoryBuilder::empty();
@@ -345,7 +363,8 @@ pub fn re_tree_to_directory(
                     dir: re_dir_name.to_string(),
                 }
             })?;
-            let digest = FileDigest::from_grpc(digest, digest_config)?;
+            let digest =
+                FileDigest::from_grpc_action_result(digest, digest_function, digest_config)?;
             let digest = TrackedFileDigest::new_expires(
                 digest,
                 *leaf_expires,
@@ -398,6 +417,7 @@ pub fn re_tree_to_directory(
                 &child_digest,
                 dirmap,
                 leaf_expires,
+                digest_function,
                 digest_config,
             )?;
             builder.insert(
@@ -427,6 +447,7 @@ pub fn re_tree_to_directory(
         "root directory",
         &mut DirMap::new(&tree.children),
         leaf_expires,
+        digest_function,
         digest_config,
     )
 }
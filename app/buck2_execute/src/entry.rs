@@ -20,7 +20,6 @@ use buck2_common::file_ops::FileType;
 use buck2_common::file_ops::TrackedFileDigest;
 use buck2_core::directory::DirectoryEntry;
 use buck2_core::fs::fs_util;
-use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::paths::file_name::FileNameBuf;
 use buck2_core::fs::paths::RelativePath;
@@ -38,6 +37,8 @@ use crate::directory::ActionDirectoryBuilder;
 use crate::directory::ActionDirectoryEntry;
 use crate::directory::ActionDirectoryMember;
 use crate::execute::blocking::BlockingExecutor;
+use crate::execute::blocking::IoTag;
+use crate::materialize::physical_roots::PhysicalRoots;
 
 #[derive(Add, Default)]
 pub struct HashingInfo {
@@ -58,7 +59,7 @@ pub async fn build_entry_from_disk(
     path: AbsNormPathBuf,
     digest_config: FileDigestConfig,
     blocking_executor: &dyn BlockingExecutor,
-    project_root: &AbsNormPath,
+    roots: &PhysicalRoots,
 ) -> anyhow::Result<(
     Option<ActionDirectoryEntry<ActionDirectoryBuilder>>,
     HashingInfo,
@@ -79,10 +80,10 @@ pub async fn build_entry_from_disk(
             hashing_info = hashing_info.add(file_hashing_info);
             DirectoryEntry::Leaf(ActionDirectoryMember::File(file_metadata))
         }
-        FileType::Symlink => DirectoryEntry::Leaf(create_symlink(&path, project_root)?),
+        FileType::Symlink => DirectoryEntry::Leaf(create_symlink(&path, roots)?),
         FileType::Directory => {
             let (dir, dir_hashing_info) =
-                build_dir_from_disk(path, digest_config, blocking_executor, project_root).await?;
+                build_dir_from_disk(path, digest_config, blocking_executor, roots).await?;
             hashing_info = hashing_info.add(dir_hashing_info);
             DirectoryEntry::Dir(dir)
         }
@@ -102,7 +103,7 @@ async fn build_dir_from_disk(
     disk_path: AbsNormPathBuf,
     digest_config: FileDigestConfig,
     blocking_executor: &dyn BlockingExecutor,
-    project_root: &AbsNormPath,
+    roots: &PhysicalRoots,
 ) -> anyhow::Result<(ActionDirectoryBuilder, HashingInfo)> {
     let mut builder = ActionDirectoryBuilder::empty();
     let mut hashing_info = HashingInfo::default();
@@ -113,7 +114,9 @@ async fn build_dir_from_disk(
     let mut file_futures: Vec<_> = Vec::new();
 
     let files = blocking_executor
-        .execute_io_inline(|| fs_util::read_dir(&disk_path).map_err(Into::into))
+        .execute_io_inline(IoTag::Other, || {
+            fs_util::read_dir(&disk_path).map_err(Into::into)
+        })
         .await?;
     for file in files {
         let file = file?;
@@ -139,16 +142,12 @@ async fn build_dir_from_disk(
             FileType::Symlink => {
                 builder.insert(
                     filename,
-                    DirectoryEntry::Leaf(create_symlink(&child_disk_path, project_root)?),
+                    DirectoryEntry::Leaf(create_symlink(&child_disk_path, roots)?),
                 )?;
             }
             FileType::Directory => {
-                let dir_future = build_dir_from_disk(
-                    child_disk_path,
-                    digest_config,
-                    blocking_executor,
-                    project_root,
-                );
+                let dir_future =
+                    build_dir_from_disk(child_disk_path, digest_config, blocking_executor, roots);
                 directory_names.push(filename);
                 directory_futures.push(dir_future);
             }
@@ -184,7 +183,8 @@ fn build_file_metadata(
 ) -> impl Future<Output = anyhow::Result<(FileMetadata, HashingInfo)>> + '_ {
     static SEMAPHORE: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(100));
     let exec_path = disk_path.clone();
-    let executable = blocking_executor.execute_io_inline(move || Ok(exec_path.executable()));
+    let executable =
+        blocking_executor.execute_io_inline(IoTag::Other, move || Ok(exec_path.executable()));
     let file_digest =
         tokio::task::spawn_blocking(move || FileDigest::from_file(&disk_path, digest_config));
 
@@ -204,7 +204,7 @@ fn build_file_metadata(
 
 fn create_symlink(
     path: &AbsNormPathBuf,
-    project_root: &AbsNormPath,
+    roots: &PhysicalRoots,
 ) -> anyhow::Result<ActionDirectoryMember> {
     let mut symlink_target = fs_util::read_link(path)?;
     if cfg!(windows) && symlink_target.is_relative() {
@@ -215,17 +215,20 @@ fn create_symlink(
             "failed to get canonical path of {}",
             directory_path.display()
         ))?;
-        if !canonical_path.starts_with(project_root) {
+        if !canonical_path.starts_with(roots.physical_root()) {
             let normalized_target = symlink_target
                 .to_str()
                 .context("can't convert path to str")?
                 .replace('\\', "/");
             let target_abspath =
                 canonical_path.join_normalized(RelativePath::from_path(&normalized_target)?)?;
-            // Recalculate symlink target if it points from symlinked buck-out to the files inside project root.
-            if target_abspath.starts_with(project_root) {
-                symlink_target = diff_paths(target_abspath, directory_path)
-                    .context("can't calculate relative path")?;
+            // Recalculate symlink target if it points from symlinked buck-out to the files inside
+            // project root, whichever way the target is spelled.
+            if let Ok(target) = roots.relativize(&target_abspath) {
+                if !roots.is_in_buck_out(&target) {
+                    symlink_target = diff_paths(roots.fs().resolve(&target), directory_path)
+                        .context("can't calculate relative path")?;
+                }
             }
         }
     }
@@ -7,7 +7,14 @@
  * of this source tree.
  */
 
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
 
 use allocative::Allocative;
 use anyhow::Context as _;
@@ -16,7 +23,7 @@ use buck2_core::buck2_env;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_futures::cancellation::CancellationContext;
 use buck2_util::threads::thread_spawn;
-use crossbeam_channel::unbounded;
+use derive_more::Display;
 use dice::DiceComputations;
 use dice::UserComputationData;
 use dupe::Dupe;
@@ -25,14 +32,64 @@ use futures::future::FutureExt;
 use tokio::sync::oneshot;
 use tokio::sync::Semaphore;
 
+/// What a blocking I/O operation is for. Each tag has its own queue on the I/O pool, and the
+/// queues take turns, so that a flood of operations of one tag doesn't hold up the others.
+#[derive(Copy, Clone, Dupe, Debug, Display, Eq, PartialEq, Hash, Allocative)]
+pub enum IoTag {
+    /// Materializing artifacts, e.g. action inputs.
+    #[display(fmt = "materialize_read")]
+    MaterializeRead,
+    /// Deleting paths nothing is waiting on, e.g. invalidated or stale artifacts. Deletions an
+    /// action or a materialization waits on use the tag of that operation, since this one is
+    /// throttled.
+    #[display(fmt = "clean")]
+    Clean,
+    /// Writing to the materializer state database.
+    #[display(fmt = "db_flush")]
+    DbFlush,
+    #[display(fmt = "other")]
+    Other,
+}
+
+impl IoTag {
+    const COUNT: usize = 4;
+
+    pub const ALL: [IoTag; IoTag::COUNT] = [
+        IoTag::MaterializeRead,
+        IoTag::Clean,
+        IoTag::DbFlush,
+        IoTag::Other,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Operations of a tag, since the executor was created.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct IoTagStats {
+    /// Operations waiting to start.
+    pub queued: u64,
+    pub running: u64,
+    pub finished: u64,
+    /// Time operations that started waited for it, in total and at most.
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+}
+
 #[async_trait]
 pub trait BlockingExecutor: Allocative + Send + Sync + 'static {
     /// Execute a blocking I/O operation on the current thread. This should be used sparingly. It
     /// is appropriate to use in cases we are doing a minimal amount of I/O (e.g. writing to just
     /// one file), or where I/O is mixed with other blocking operations.  Those operations run with
     /// fairly high concurrency as they aren't expected to contend with each other.
+    ///
+    /// The tag is only used for stats: [`IoTagLimits`] are in units of I/O pool threads, which
+    /// inline operations don't use, so they can't hold up the pool's queues.
     async fn execute_dyn_io_inline<'a>(
         &self,
+        tag: IoTag,
         f: Box<dyn FnOnce() -> anyhow::Result<()> + Send + 'a>,
     ) -> anyhow::Result<()>;
 
@@ -41,25 +98,31 @@ pub trait BlockingExecutor: Allocative + Send + Sync + 'static {
     /// something else they might contend for I/O threads with actual I/O).
     fn execute_io<'a>(
         &self,
+        tag: IoTag,
         io: Box<dyn IoRequest>,
         cancellations: &'a CancellationContext,
     ) -> BoxFuture<'a, anyhow::Result<()>>;
 
     /// The size of the queue of pending I/O.
     fn queue_size(&self) -> usize;
+
+    fn tag_stats(&self, tag: IoTag) -> IoTagStats;
 }
 
 impl dyn BlockingExecutor {
-    pub async fn execute_io_inline<F, T>(&self, f: F) -> anyhow::Result<T>
+    pub async fn execute_io_inline<F, T>(&self, tag: IoTag, f: F) -> anyhow::Result<T>
     where
         F: FnOnce() -> anyhow::Result<T> + Send,
         T: Send,
     {
         let mut res = None;
-        self.execute_dyn_io_inline(Box::new(|| {
-            res = Some(f()?);
-            Ok(())
-        }))
+        self.execute_dyn_io_inline(
+            tag,
+            Box::new(|| {
+                res = Some(f()?);
+                Ok(())
+            }),
+        )
         .await?;
         res.context("Inline I/O did not execute")
     }
@@ -69,9 +132,175 @@ pub trait IoRequest: Send + Sync + 'static {
     fn execute(self: Box<Self>, project_fs: &ProjectRoot) -> anyhow::Result<()>;
 }
 
+#[derive(Default)]
+struct TagCounters {
+    queued: AtomicU64,
+    running: AtomicU64,
+    finished: AtomicU64,
+    total_wait_us: AtomicU64,
+    max_wait_us: AtomicU64,
+}
+
+#[derive(Default)]
+struct IoTagCounters([TagCounters; IoTag::COUNT]);
+
+impl IoTagCounters {
+    fn queue(self: &Arc<Self>, tag: IoTag) -> QueuedIo {
+        self.0[tag.index()].queued.fetch_add(1, Ordering::Relaxed);
+        QueuedIo {
+            counters: self.dupe(),
+            tag,
+            queued_at: Instant::now(),
+        }
+    }
+
+    fn stats(&self, tag: IoTag) -> IoTagStats {
+        let counters = &self.0[tag.index()];
+        IoTagStats {
+            queued: counters.queued.load(Ordering::Relaxed),
+            running: counters.running.load(Ordering::Relaxed),
+            finished: counters.finished.load(Ordering::Relaxed),
+            total_wait: Duration::from_micros(counters.total_wait_us.load(Ordering::Relaxed)),
+            max_wait: Duration::from_micros(counters.max_wait_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// An operation waiting to start. Counted as queued until dropped.
+struct QueuedIo {
+    counters: Arc<IoTagCounters>,
+    tag: IoTag,
+    queued_at: Instant,
+}
+
+impl QueuedIo {
+    fn start(self) -> RunningIo {
+        let counters = &self.counters.0[self.tag.index()];
+        let wait_us = self.queued_at.elapsed().as_micros() as u64;
+        counters.total_wait_us.fetch_add(wait_us, Ordering::Relaxed);
+        counters.max_wait_us.fetch_max(wait_us, Ordering::Relaxed);
+        counters.running.fetch_add(1, Ordering::Relaxed);
+        RunningIo {
+            counters: self.counters.dupe(),
+            tag: self.tag,
+        }
+    }
+}
+
+impl Drop for QueuedIo {
+    fn drop(&mut self) {
+        self.counters.0[self.tag.index()]
+            .queued
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counted as running until dropped.
+struct RunningIo {
+    counters: Arc<IoTagCounters>,
+    tag: IoTag,
+}
+
+impl Drop for RunningIo {
+    fn drop(&mut self) {
+        let counters = &self.counters.0[self.tag.index()];
+        counters.running.fetch_sub(1, Ordering::Relaxed);
+        counters.finished.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 struct ThreadPoolIoRequest {
     io: Box<dyn IoRequest>,
     sender: oneshot::Sender<anyhow::Result<()>>,
+    queued: QueuedIo,
+}
+
+/// How many of the I/O threads each tag may use at once.
+#[derive(Copy, Clone, Debug)]
+pub struct IoTagLimits([usize; IoTag::COUNT]);
+
+impl IoTagLimits {
+    /// Every tag may use all threads.
+    pub fn unlimited(io_threads: usize) -> Self {
+        Self([io_threads; IoTag::COUNT])
+    }
+
+    pub fn with_limit(mut self, tag: IoTag, limit: usize) -> Self {
+        self.0[tag.index()] = limit.max(1);
+        self
+    }
+}
+
+/// The requests waiting for an I/O thread, by tag.
+struct IoQueues {
+    state: Mutex<IoQueuesState>,
+    available: Condvar,
+}
+
+struct IoQueuesState {
+    pending: [VecDeque<ThreadPoolIoRequest>; IoTag::COUNT],
+    running: [usize; IoTag::COUNT],
+    limits: IoTagLimits,
+    /// The tag to take the next request from, if it has one, so that tags take turns.
+    next: usize,
+    /// Set when the executor is dropped. Threads exit once the queues are empty.
+    closed: bool,
+}
+
+impl IoQueuesState {
+    fn queue_size(&self) -> usize {
+        self.pending.iter().map(|p| p.len()).sum()
+    }
+
+    fn pop(&mut self) -> Option<ThreadPoolIoRequest> {
+        for i in 0..IoTag::COUNT {
+            let tag = (self.next + i) % IoTag::COUNT;
+            if self.running[tag] >= self.limits.0[tag] {
+                continue;
+            }
+            if let Some(request) = self.pending[tag].pop_front() {
+                self.running[tag] += 1;
+                self.next = (tag + 1) % IoTag::COUNT;
+                return Some(request);
+            }
+        }
+        None
+    }
+}
+
+impl IoQueues {
+    fn push(&self, request: ThreadPoolIoRequest) {
+        let mut state = self.state.lock().unwrap();
+        state.pending[request.queued.tag.index()].push_back(request);
+        self.available.notify_one();
+    }
+
+    fn run_worker(&self, fs: &ProjectRoot) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let Some(ThreadPoolIoRequest { io, sender, queued }) = state.pop() else {
+                if state.closed && state.queue_size() == 0 {
+                    return;
+                }
+                state = self.available.wait(state).unwrap();
+                continue;
+            };
+            drop(state);
+
+            let tag = queued.tag;
+            let running = queued.start();
+            let res = io.execute(fs);
+            drop(running);
+            let _ignored = sender.send(res);
+
+            state = self.state.lock().unwrap();
+            state.running[tag.index()] -= 1;
+            if !state.pending[tag.index()].is_empty() {
+                // Another thread may be waiting for this tag to be under its limit.
+                self.available.notify_one();
+            }
+        }
+    }
 }
 
 #[derive(Allocative)]
@@ -79,7 +308,9 @@ pub struct BuckBlockingExecutor {
     #[allocative(skip)]
     io_data_semaphore: Semaphore,
     #[allocative(skip)]
-    command_sender: crossbeam_channel::Sender<ThreadPoolIoRequest>,
+    queues: Arc<IoQueues>,
+    #[allocative(skip)]
+    counters: Arc<IoTagCounters>,
 }
 
 impl BuckBlockingExecutor {
@@ -95,56 +326,97 @@ impl BuckBlockingExecutor {
     /// host. This is because those operations often have to do CPU bound work to generate the data
     /// they are trying to write, and writing to multiple files doesn't have the negative scaling
     /// issues modifying the directory structure does.
+    ///
+    /// Cleaning may use all I/O threads but one by default, which leaves one for materializations
+    /// when a lot of paths are deleted at once.
     pub fn default_concurrency(fs: ProjectRoot) -> anyhow::Result<Self> {
         let io_threads = buck2_env!("BUCK2_IO_THREADS", type=usize, default=4)?;
         let io_semaphore = buck2_env!("BUCK2_IO_SEMAPHORE", type=usize, default=num_cpus::get())?;
+        let clean_threads = buck2_env!(
+            "BUCK2_IO_CLEAN_THREADS",
+            type=usize,
+            default=io_threads.saturating_sub(1)
+        )?;
+
+        Self::new(
+            fs,
+            io_threads,
+            io_semaphore,
+            IoTagLimits::unlimited(io_threads).with_limit(IoTag::Clean, clean_threads),
+        )
+    }
 
-        let (command_sender, command_receiver) = unbounded();
+    pub fn new(
+        fs: ProjectRoot,
+        io_threads: usize,
+        io_semaphore: usize,
+        limits: IoTagLimits,
+    ) -> anyhow::Result<Self> {
+        let queues = Arc::new(IoQueues {
+            state: Mutex::new(IoQueuesState {
+                pending: Default::default(),
+                running: Default::default(),
+                limits,
+                next: 0,
+                closed: false,
+            }),
+            available: Condvar::new(),
+        });
 
         for i in 0..io_threads {
-            let command_receiver = command_receiver.clone();
+            let queues = queues.dupe();
             let fs = fs.dupe();
-            thread_spawn(&format!("buck-io-{}", i), move || {
-                for ThreadPoolIoRequest { sender, io } in command_receiver.iter() {
-                    let res = io.execute(&fs);
-                    let _ignored = sender.send(res);
-                }
-            })
-            .context("Failed to spawn io worker")?;
+            thread_spawn(&format!("buck-io-{}", i), move || queues.run_worker(&fs))
+                .context("Failed to spawn io worker")?;
         }
 
         Ok(Self {
             io_data_semaphore: Semaphore::new(io_semaphore),
-            command_sender,
+            queues,
+            counters: Default::default(),
         })
     }
 }
 
+impl Drop for BuckBlockingExecutor {
+    fn drop(&mut self) {
+        self.queues.state.lock().unwrap().closed = true;
+        self.queues.available.notify_all();
+    }
+}
+
 #[async_trait]
 impl BlockingExecutor for BuckBlockingExecutor {
     async fn execute_dyn_io_inline<'a>(
         &self,
+        tag: IoTag,
         f: Box<dyn FnOnce() -> anyhow::Result<()> + Send + 'a>,
     ) -> anyhow::Result<()> {
+        let queued = self.counters.queue(tag);
         let _permit = self
             .io_data_semaphore
             .acquire()
             .await
             .expect("This semaphore is never closed");
+        let _running = queued.start();
 
         tokio::task::block_in_place(f)
     }
 
     fn execute_io<'a>(
         &self,
+        tag: IoTag,
         io: Box<dyn IoRequest>,
         cancellations: &'a CancellationContext,
     ) -> BoxFuture<'a, anyhow::Result<()>> {
         let (sender, receiver) = oneshot::channel();
 
-        // Ignore errors sending as they'll translate to an error receiving once we drop the
-        // sender.
-        let _ignored = self.command_sender.send(ThreadPoolIoRequest { io, sender });
+        // If the pool is shut down, the request is dropped, which translates to an error receiving.
+        self.queues.push(ThreadPoolIoRequest {
+            io,
+            sender,
+            queued: self.counters.queue(tag),
+        });
 
         cancellations
             .critical_section(|| async move { receiver.await.context("Pool shut down")? })
@@ -152,7 +424,11 @@ impl BlockingExecutor for BuckBlockingExecutor {
     }
 
     fn queue_size(&self) -> usize {
-        self.command_sender.len()
+        self.queues.state.lock().unwrap().queue_size()
+    }
+
+    fn tag_stats(&self, tag: IoTag) -> IoTagStats {
+        self.counters.stats(tag)
     }
 }
 
@@ -192,6 +468,7 @@ pub mod testing {
     impl BlockingExecutor for DummyBlockingExecutor {
         async fn execute_dyn_io_inline<'a>(
             &self,
+            _tag: IoTag,
             f: Box<dyn FnOnce() -> anyhow::Result<()> + Send + 'a>,
         ) -> anyhow::Result<()> {
             f()
@@ -199,6 +476,7 @@ pub mod testing {
 
         fn execute_io<'a>(
             &self,
+            _tag: IoTag,
             io: Box<dyn IoRequest>,
             _cancellations: &'a CancellationContext,
         ) -> BoxFuture<'a, anyhow::Result<()>> {
@@ -208,5 +486,172 @@ pub mod testing {
         fn queue_size(&self) -> usize {
             0
         }
+
+        fn tag_stats(&self, _tag: IoTag) -> IoTagStats {
+            IoTagStats::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::Barrier;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use buck2_core::fs::project::ProjectRoot;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_futures::cancellation::CancellationContext;
+    use dupe::Dupe;
+
+    use crate::execute::blocking::BlockingExecutor;
+    use crate::execute::blocking::BuckBlockingExecutor;
+    use crate::execute::blocking::IoRequest;
+    use crate::execute::blocking::IoTag;
+    use crate::execute::blocking::IoTagLimits;
+
+    struct Record {
+        tag: IoTag,
+        completed: Arc<Mutex<Vec<IoTag>>>,
+        gate: Option<Arc<Barrier>>,
+    }
+
+    impl IoRequest for Record {
+        fn execute(self: Box<Self>, _project_fs: &ProjectRoot) -> anyhow::Result<()> {
+            if let Some(gate) = &self.gate {
+                gate.wait();
+            }
+            std::thread::sleep(Duration::from_millis(1));
+            self.completed.lock().unwrap().push(self.tag);
+            Ok(())
+        }
+    }
+
+    async fn wait_until_running(executor: &BuckBlockingExecutor, tag: IoTag) {
+        while executor.tag_stats(tag).running == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clean_flood_does_not_starve_materializations() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let executor =
+            BuckBlockingExecutor::new(fs.path().dupe(), 1, 1, IoTagLimits::unlimited(1))?;
+        let completed = Arc::new(Mutex::new(Vec::new()));
+        // Holds the first clean until everything is queued.
+        let gate = Arc::new(Barrier::new(2));
+
+        let mut futures = Vec::new();
+        for i in 0..20 {
+            futures.push(executor.execute_io(
+                IoTag::Clean,
+                Box::new(Record {
+                    tag: IoTag::Clean,
+                    completed: completed.dupe(),
+                    gate: (i == 0).then(|| gate.dupe()),
+                }),
+                CancellationContext::testing(),
+            ));
+            if i == 0 {
+                wait_until_running(&executor, IoTag::Clean).await;
+            }
+        }
+        for _ in 0..5 {
+            futures.push(executor.execute_io(
+                IoTag::MaterializeRead,
+                Box::new(Record {
+                    tag: IoTag::MaterializeRead,
+                    completed: completed.dupe(),
+                    gate: None,
+                }),
+                CancellationContext::testing(),
+            ));
+        }
+
+        let clean = executor.tag_stats(IoTag::Clean);
+        assert_eq!(19, clean.queued);
+        assert_eq!(1, clean.running);
+        assert_eq!(5, executor.tag_stats(IoTag::MaterializeRead).queued);
+        assert_eq!(24, executor.queue_size());
+
+        gate.wait();
+        for result in futures::future::join_all(futures).await {
+            result?;
+        }
+
+        // The queues take turns, so materializations don't wait for all cleans to finish.
+        let completed = completed.lock().unwrap().clone();
+        let last_materialization = completed
+            .iter()
+            .rposition(|tag| *tag == IoTag::MaterializeRead)
+            .unwrap();
+        assert_eq!(9, last_materialization);
+
+        let clean = executor.tag_stats(IoTag::Clean);
+        let materialize = executor.tag_stats(IoTag::MaterializeRead);
+        assert_eq!((0, 0, 20), (clean.queued, clean.running, clean.finished));
+        assert_eq!(
+            (0, 0, 5),
+            (
+                materialize.queued,
+                materialize.running,
+                materialize.finished
+            )
+        );
+        assert!(clean.max_wait > materialize.max_wait);
+        assert!(clean.total_wait > materialize.total_wait);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tag_limit() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let executor = BuckBlockingExecutor::new(
+            fs.path().dupe(),
+            2,
+            1,
+            IoTagLimits::unlimited(2).with_limit(IoTag::Clean, 1),
+        )?;
+        let completed = Arc::new(Mutex::new(Vec::new()));
+        let gate = Arc::new(Barrier::new(2));
+
+        let mut futures = Vec::new();
+        for i in 0..2 {
+            futures.push(executor.execute_io(
+                IoTag::Clean,
+                Box::new(Record {
+                    tag: IoTag::Clean,
+                    completed: completed.dupe(),
+                    gate: (i == 0).then(|| gate.dupe()),
+                }),
+                CancellationContext::testing(),
+            ));
+            if i == 0 {
+                wait_until_running(&executor, IoTag::Clean).await;
+            }
+        }
+        // The second thread doesn't pick up the second clean, so it runs this.
+        futures.push(executor.execute_io(
+            IoTag::Other,
+            Box::new(Record {
+                tag: IoTag::Other,
+                completed: completed.dupe(),
+                gate: None,
+            }),
+            CancellationContext::testing(),
+        ));
+        let other = futures.pop().unwrap();
+        other.await?;
+        assert_eq!(vec![IoTag::Other], *completed.lock().unwrap());
+        assert_eq!(1, executor.tag_stats(IoTag::Clean).queued);
+
+        gate.wait();
+        for result in futures::future::join_all(futures).await {
+            result?;
+        }
+        assert_eq!(2, executor.tag_stats(IoTag::Clean).finished);
+        Ok(())
     }
 }
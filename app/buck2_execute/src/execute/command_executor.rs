@@ -315,3 +315,53 @@ fn re_create_action(
         remote_execution_dependencies: remote_execution_dependencies.to_owned(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use buck2_common::file_ops::TrackedFileDigest;
+    use buck2_core::execution_types::executor_config::OutputPathsBehavior;
+    use remote_execution as RE;
+    use sorted_vector_map::SortedVectorMap;
+
+    use crate::digest_config::DigestConfig;
+    use crate::execute::action_digest_and_blobs::ActionDigest;
+    use crate::execute::command_executor::re_create_action;
+
+    fn action_digest(env: &[(&str, &str)]) -> anyhow::Result<ActionDigest> {
+        let digest_config = DigestConfig::testing_default();
+        let env: SortedVectorMap<String, String> = env
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect();
+        let action = re_create_action(
+            vec!["cc".to_owned(), "-c".to_owned()],
+            &[],
+            None,
+            &env,
+            &TrackedFileDigest::empty(digest_config.cas_digest_config()),
+            [],
+            None,
+            RE::Platform::default(),
+            false,
+            digest_config,
+            OutputPathsBehavior::Strict,
+            false,
+            &Vec::new(),
+        )?;
+        Ok(action.action_and_blobs.action)
+    }
+
+    #[test]
+    fn test_env_order_does_not_change_digest() -> anyhow::Result<()> {
+        let digest = action_digest(&[("A", "1"), ("B", "2"), ("C", "3")])?;
+        assert_eq!(
+            digest,
+            action_digest(&[("C", "3"), ("A", "1"), ("B", "2")])?
+        );
+        assert_ne!(
+            digest,
+            action_digest(&[("A", "1"), ("B", "3"), ("C", "2")])?
+        );
+        Ok(())
+    }
+}
@@ -319,6 +319,10 @@ pub struct CommandExecutionRequest {
     /// Whether the executor should guarantee that the inodes for all inputs are unique (i.e. avoid
     /// hardlinking identical input files, for example)
     unique_input_inodes: bool,
+    /// Whether the local executor may expose inputs as named pipes that stream their content,
+    /// instead of materializing them. Only for commands that read each input once, from start to
+    /// end.
+    streamed_inputs: bool,
     /// Remote dep file key, if the action has a dep file.
     /// If this key is set and remote dep file caching is enabled, it will be used to query the cache.
     pub remote_dep_file_key: Option<DepFileDigest>,
@@ -351,6 +355,7 @@ impl CommandExecutionRequest {
             required_local_resources: SortedSet::new(),
             worker: None,
             unique_input_inodes: false,
+            streamed_inputs: false,
             remote_dep_file_key: None,
             remote_execution_dependencies: Vec::new(),
         }
@@ -533,6 +538,15 @@ impl CommandExecutionRequest {
         self.unique_input_inodes
     }
 
+    pub fn with_streamed_inputs(mut self, streamed_inputs: bool) -> Self {
+        self.streamed_inputs = streamed_inputs;
+        self
+    }
+
+    pub fn streamed_inputs(&self) -> bool {
+        self.streamed_inputs
+    }
+
     pub fn with_remote_execution_dependencies(
         mut self,
         remote_execution_dependencies: Vec<RemoteExecutorDependency>,
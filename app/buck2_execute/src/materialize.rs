@@ -13,3 +13,4 @@ pub mod http;
 
 pub mod materializer;
 pub mod nodisk;
+pub mod physical_roots;
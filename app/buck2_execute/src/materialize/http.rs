@@ -193,6 +193,8 @@ impl AsHttpError for HttpDownloadError {
 }
 
 pub async fn http_head(client: &HttpClient, url: &str) -> anyhow::Result<Response<()>> {
+    // `http_retry` already retries the whole request.
+    let client = client.without_retries();
     let response = http_retry(
         || async {
             client
@@ -220,6 +222,9 @@ pub async fn http_download(
         fs_util::create_dir_all(fs.resolve(dir))?;
     }
 
+    // `http_retry` retries the whole download, including failures reading the body, which the
+    // client's own retries don't cover.
+    let client = client.without_retries();
     Ok(http_retry(
         || async {
             let file = fs_util::create_file(&abs_path)
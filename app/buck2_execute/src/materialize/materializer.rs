@@ -16,6 +16,7 @@ use buck2_common::file_ops::FileMetadata;
 use buck2_core::base_deferred_key::BaseDeferredKey;
 use buck2_core::directory::DirectoryEntry;
 use buck2_core::execution_types::executor_config::RemoteExecutorUseCase;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_futures::cancellation::CancellationContext;
@@ -25,6 +26,7 @@ use chrono::Utc;
 use derive_more::Display;
 use dice::UserComputationData;
 use dupe::Dupe;
+use futures::future::BoxFuture;
 use futures::stream::BoxStream;
 use futures::stream::TryStreamExt;
 
@@ -265,6 +267,94 @@ pub trait Materializer: Allocative + Send + Sync + 'static {
     /// Inject stats into a snapshot. This is also used only for the deferred materializer at this
     /// time.
     fn add_snapshot_stats(&self, _snapshot: &mut buck2_data::Snapshot) {}
+
+    /// Hint that these artifacts will be needed soon, e.g. because they are the inputs of an
+    /// action that is queued for local execution. The materializer may start materializing them
+    /// in the background, after work that was explicitly requested.
+    ///
+    /// Prefetches that haven't started yet are withdrawn when the returned guard is dropped.
+    /// Unlike `ensure_materialized`, this never fails and gives no guarantee.
+    fn prefetch(&self, _artifact_paths: Vec<ProjectRelativePathBuf>) -> PrefetchGuard {
+        PrefetchGuard::none()
+    }
+
+    /// Experimental: expose some of these artifacts, which are inputs of an action about to run
+    /// locally, as named pipes that stream their content when the action opens them, instead of
+    /// materializing them. The materializer picks which, and the others must be materialized as
+    /// usual.
+    async fn stream_inputs(
+        &self,
+        _artifact_paths: Vec<ProjectRelativePathBuf>,
+    ) -> anyhow::Result<StreamedInputs> {
+        Ok(StreamedInputs::none())
+    }
+}
+
+/// Returned by `Materializer::prefetch`. Withdraws the prefetches that haven't started yet when
+/// dropped.
+#[must_use]
+pub struct PrefetchGuard(Option<Box<dyn FnOnce() + Send>>);
+
+impl PrefetchGuard {
+    pub fn new(withdraw: impl FnOnce() + Send + 'static) -> Self {
+        Self(Some(Box::new(withdraw)))
+    }
+
+    /// A guard for a materializer that doesn't prefetch.
+    pub fn none() -> Self {
+        Self(None)
+    }
+}
+
+impl Drop for PrefetchGuard {
+    fn drop(&mut self) {
+        if let Some(withdraw) = self.0.take() {
+            withdraw();
+        }
+    }
+}
+
+/// Returned by `Materializer::stream_inputs`. Dropping it without calling `finish`, e.g. when the
+/// action is cancelled, removes the named pipes.
+#[must_use]
+pub struct StreamedInputs {
+    paths: Vec<ProjectRelativePathBuf>,
+    finish: Option<Box<dyn FnOnce() -> BoxFuture<'static, anyhow::Result<bool>> + Send>>,
+}
+
+impl StreamedInputs {
+    pub fn new(
+        paths: Vec<ProjectRelativePathBuf>,
+        finish: impl FnOnce() -> BoxFuture<'static, anyhow::Result<bool>> + Send + 'static,
+    ) -> Self {
+        Self {
+            paths,
+            finish: Some(Box::new(finish)),
+        }
+    }
+
+    /// Nothing is streamed.
+    pub fn none() -> Self {
+        Self {
+            paths: Vec::new(),
+            finish: None,
+        }
+    }
+
+    /// The inputs exposed as named pipes, which must not be materialized.
+    pub fn paths(&self) -> &[ProjectRelativePathBuf] {
+        &self.paths
+    }
+
+    /// Call once the action has exited. Removes the named pipes, and returns whether the action
+    /// must run again because an input could not be streamed, in which case all inputs are
+    /// materialized by the time this returns.
+    pub async fn finish(mut self) -> anyhow::Result<bool> {
+        match self.finish.take() {
+            Some(finish) => finish().await,
+            None => Ok(false),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Dupe, Debug)]
@@ -629,8 +719,100 @@ impl MaterializationMethod {
 /// `DeferredMaterializerEntry` lives in a crate that depends on this one.
 pub trait DeferredMaterializerEntry: Send + Sync + std::fmt::Display {}
 
-/// Obtain notifications for entries as they are materialized, and request eager materialization of
-/// those paths.
+/// A change to a path of a `DeferredMaterializerSubscription`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MaterializerSubscriptionEvent {
+    /// The path was materialized.
+    Materialized(ProjectRelativePathBuf),
+    /// Whatever was at the path was deleted from disk, before something else is materialized
+    /// there.
+    Cleaned(ProjectRelativePathBuf),
+    /// The artifact materialized at the path was superseded by a different artifact.
+    Invalidated(ProjectRelativePathBuf),
+}
+
+impl MaterializerSubscriptionEvent {
+    pub fn path(&self) -> &ProjectRelativePath {
+        match self {
+            Self::Materialized(path) | Self::Cleaned(path) | Self::Invalidated(path) => path,
+        }
+    }
+}
+
+/// Where an artifact is in the deferred materializer.
+#[derive(Clone, Copy, Debug, Dupe, PartialEq, Eq, Display)]
+pub enum MaterializationStage {
+    #[display(fmt = "declared")]
+    Declared,
+    #[display(fmt = "materializing")]
+    Materializing,
+    #[display(fmt = "materialized")]
+    Materialized,
+    /// The last attempt at materializing the artifact failed, and it wasn't retried since.
+    #[display(fmt = "failed")]
+    Failed,
+}
+
+/// The status of an artifact, see `DeferredMaterializerExtensions::materialization_status`.
+#[derive(Clone, Debug)]
+pub struct MaterializationStatus {
+    pub stage: MaterializationStage,
+    /// How the artifact is to be materialized. Only known until it is materialized.
+    pub method: Option<String>,
+    /// How many times materializing the artifact failed since it was declared.
+    pub failed_attempts: u64,
+    pub last_error: Option<String>,
+}
+
+impl fmt::Display for MaterializationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.stage)?;
+        if let Some(method) = &self.method {
+            write!(f, " ({})", method)?;
+        }
+        if let Some(last_error) = &self.last_error {
+            write!(
+                f,
+                ", failed {} time(s), last error: {}",
+                self.failed_attempts, last_error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What was found on disk for a materialized artifact that doesn't match the materializer state.
+#[derive(Debug, Display)]
+pub enum MaterializerVerifyFound {
+    #[display(fmt = "nothing")]
+    Missing,
+    #[display(fmt = "{}", _0)]
+    Entry(String),
+    /// The path couldn't be hashed.
+    #[display(fmt = "error: {:#}", _0)]
+    Error(anyhow::Error),
+}
+
+#[derive(Debug)]
+pub struct MaterializerVerifyMismatch {
+    pub path: ProjectRelativePathBuf,
+    /// The entry the materializer believes is materialized at `path`.
+    pub expected: String,
+    pub found: MaterializerVerifyFound,
+}
+
+/// The outcome of `DeferredMaterializerExtensions::verify`.
+#[derive(Debug, Default)]
+pub struct MaterializerVerifyReport {
+    /// How many materialized artifacts were hashed.
+    pub checked: usize,
+    pub mismatches: Vec<MaterializerVerifyMismatch>,
+    /// The mismatched artifacts that were removed from the materializer state.
+    pub invalidated: Vec<ProjectRelativePathBuf>,
+}
+
+/// Obtain notifications for entries as they are materialized, cleaned or invalidated, and request
+/// eager materialization of those paths.
 #[async_trait]
 pub trait DeferredMaterializerSubscription: Send + Sync {
     /// Get notifications for specific paths. This also implicitly requests their eager
@@ -641,8 +823,17 @@ pub trait DeferredMaterializerSubscription: Send + Sync {
     /// received.
     fn unsubscribe_from_paths(&mut self, paths: Vec<ProjectRelativePathBuf>);
 
-    /// Await the next materialization on this subscription.
-    async fn next_materialization(&mut self) -> Option<ProjectRelativePathBuf>;
+    /// Await the next event on this subscription.
+    async fn next_event(&mut self) -> Option<MaterializerSubscriptionEvent>;
+
+    /// Await the next materialization on this subscription, skipping other events.
+    async fn next_materialization(&mut self) -> Option<ProjectRelativePathBuf> {
+        loop {
+            if let MaterializerSubscriptionEvent::Materialized(path) = self.next_event().await? {
+                return Some(path);
+            }
+        }
+    }
 }
 
 /// Extensions to the Materializer trait that are only available in the Deferred materializer.
@@ -674,6 +865,27 @@ pub trait DeferredMaterializerExtensions: Send + Sync {
     async fn test_iter(&self, count: usize) -> anyhow::Result<String>;
     async fn flush_all_access_times(&self) -> anyhow::Result<String>;
 
+    /// Get the status of the artifacts at `paths`, or of the artifacts containing them, `None`
+    /// for paths that aren't part of a declared artifact.
+    async fn materialization_status(
+        &self,
+        paths: Vec<ProjectRelativePathBuf>,
+    ) -> anyhow::Result<Vec<(ProjectRelativePathBuf, Option<MaterializationStatus>)>>;
+
+    /// Re-hash the materialized artifacts at or under `paths`, or all of them if `paths` is empty,
+    /// and report those whose contents on disk don't match the materializer state. With
+    /// `invalidate`, the mismatched artifacts are also removed from disk and from the state.
+    /// Those the running daemon declared are materialized again the next time they are needed,
+    /// the others the next time they are declared.
+    async fn verify(
+        &self,
+        paths: Vec<ProjectRelativePathBuf>,
+        invalidate: bool,
+    ) -> anyhow::Result<MaterializerVerifyReport>;
+
+    /// Rebuild the materializer state db to release the space left behind by deleted entries.
+    async fn compact_db(&self) -> anyhow::Result<buck2_data::SqliteVacuum>;
+
     /// Create a new DeferredMaterializerSubscription.
     async fn create_subscription(
         &self,
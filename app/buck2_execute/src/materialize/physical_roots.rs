@@ -0,0 +1,251 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! `buck-out` is sometimes a symlink to another disk (`buck-out -> /mnt/fast/buck-out`), so the
+//! same file can be spelled through the project root or through where `buck-out` actually is.
+//!
+//! The convention is that the materializer only ever deals in project relative paths, spelled
+//! through the project root. Absolute paths are converted with [`PhysicalRoots::relativize`],
+//! which accepts both spellings. Symlinks are resolved once, when the materializer starts, and
+//! never per path.
+
+use allocative::Allocative;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use dupe::Dupe;
+
+#[derive(Debug, buck2_error::Error)]
+enum PhysicalRootsError {
+    #[error("Path `{0}` is not in the project root `{1}`, nor in buck-out at `{2}`")]
+    NotInProject(AbsNormPathBuf, AbsNormPathBuf, AbsNormPathBuf),
+}
+
+#[derive(Allocative, Clone, Debug)]
+pub struct PhysicalRoots {
+    fs: ProjectRoot,
+    /// Where the project root is on disk. Equal to the project root unless it's spelled through
+    /// a symlink.
+    physical_root: AbsNormPathBuf,
+    buck_out_path: ProjectRelativePathBuf,
+    /// Where `buck_out_path` is on disk.
+    physical_buck_out: AbsNormPathBuf,
+}
+
+impl PhysicalRoots {
+    /// Resolve where the project root and `buck_out_path` are on disk. `buck_out_path` doesn't
+    /// need to exist: the part that does is resolved, and the rest is assumed to be created as
+    /// regular directories.
+    pub fn detect(
+        fs: &ProjectRoot,
+        buck_out_path: &ProjectRelativePath,
+    ) -> anyhow::Result<PhysicalRoots> {
+        let physical_root = fs_util::canonicalize(fs.root())?;
+
+        let mut existing = buck_out_path;
+        let physical_buck_out = loop {
+            if let Some(physical) = fs_util::canonicalize_if_exists(fs.resolve(existing))? {
+                break physical.join(buck_out_path.strip_prefix(existing)?);
+            }
+            match existing.parent() {
+                Some(parent) => existing = parent,
+                None => break physical_root.join(buck_out_path.as_forward_relative_path()),
+            }
+        };
+
+        let roots = PhysicalRoots {
+            fs: fs.dupe(),
+            physical_root,
+            buck_out_path: buck_out_path.to_owned(),
+            physical_buck_out,
+        };
+        if roots.buck_out_is_redirected() {
+            tracing::info!(
+                "`{}` is at `{}`",
+                roots.buck_out_path,
+                roots.physical_buck_out
+            );
+        }
+        Ok(roots)
+    }
+
+    /// Roots for a project where nothing is symlinked, without touching the disk.
+    pub fn unchecked(fs: &ProjectRoot, buck_out_path: &ProjectRelativePath) -> PhysicalRoots {
+        PhysicalRoots {
+            fs: fs.dupe(),
+            physical_root: fs.root().to_owned(),
+            buck_out_path: buck_out_path.to_owned(),
+            physical_buck_out: fs.resolve(buck_out_path),
+        }
+    }
+
+    pub fn fs(&self) -> &ProjectRoot {
+        &self.fs
+    }
+
+    /// Where the project root is on disk.
+    pub fn physical_root(&self) -> &AbsNormPath {
+        &self.physical_root
+    }
+
+    pub fn buck_out_path(&self) -> &ProjectRelativePath {
+        &self.buck_out_path
+    }
+
+    /// Whether `buck-out` is somewhere else than where the project root says it is.
+    pub fn buck_out_is_redirected(&self) -> bool {
+        self.physical_buck_out
+            != self
+                .physical_root
+                .join(self.buck_out_path.as_forward_relative_path())
+    }
+
+    pub fn is_in_buck_out(&self, path: &ProjectRelativePath) -> bool {
+        path.starts_with(&self.buck_out_path)
+    }
+
+    /// The project relative path of `path`, which may be spelled through the project root, or
+    /// through where the project root or `buck-out` are on disk. Fails if `path` is outside of
+    /// all of them.
+    pub fn relativize(&self, path: &AbsNormPath) -> anyhow::Result<ProjectRelativePathBuf> {
+        // `buck-out` is checked first because it may be on disk under the project root, but under
+        // another name.
+        if let Ok(rest) = path.strip_prefix(&self.physical_buck_out) {
+            return Ok(self.buck_out_path.join(rest));
+        }
+        for root in [self.fs.root(), &*self.physical_root] {
+            if let Ok(rest) = path.strip_prefix(root) {
+                return Ok(ProjectRelativePath::empty().join(rest));
+            }
+        }
+        Err(PhysicalRootsError::NotInProject(
+            path.to_owned(),
+            self.fs.root().to_owned(),
+            self.physical_buck_out.clone(),
+        )
+        .into())
+    }
+
+    /// Where `path` is on disk, without following symlinks other than the roots.
+    pub fn physical_path(&self, path: &ProjectRelativePath) -> AbsNormPathBuf {
+        match path.strip_prefix_opt(&self.buck_out_path) {
+            Some(rest) => self.physical_buck_out.join(rest),
+            None => self.physical_root.join(path.as_forward_relative_path()),
+        }
+    }
+
+    /// Whether a hardlink between the two paths can work, i.e. they aren't on different sides of
+    /// a redirected `buck-out`.
+    pub fn can_hardlink(&self, x: &ProjectRelativePath, y: &ProjectRelativePath) -> bool {
+        !self.buck_out_is_redirected() || self.is_in_buck_out(x) == self.is_in_buck_out(y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::fs_util;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+
+    use crate::materialize::physical_roots::PhysicalRoots;
+
+    fn path(p: &str) -> &ProjectRelativePath {
+        ProjectRelativePath::new(p).unwrap()
+    }
+
+    #[test]
+    fn test_not_redirected() -> anyhow::Result<()> {
+        let project = ProjectRootTemp::new()?;
+        let fs = project.path();
+        let roots = PhysicalRoots::detect(fs, path("buck-out/v2"))?;
+
+        assert!(!roots.buck_out_is_redirected());
+        assert_eq!(
+            path("buck-out/v2/gen/foo").to_owned(),
+            roots.relativize(&fs.resolve(path("buck-out/v2/gen/foo")))?
+        );
+        assert_eq!(
+            fs.resolve(path("buck-out/v2/gen/foo")),
+            roots.physical_path(path("buck-out/v2/gen/foo"))
+        );
+        assert!(roots.can_hardlink(path("buck-out/v2/gen/foo"), path("src/foo")));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_redirected_buck_out() -> anyhow::Result<()> {
+        let project = ProjectRootTemp::new()?;
+        let fast_disk = ProjectRootTemp::new()?;
+        let fs = project.path();
+        let physical_buck_out = fast_disk.path().resolve(path("buck-out"));
+        fs_util::create_dir_all(&physical_buck_out)?;
+        fs_util::symlink(&physical_buck_out, fs.resolve(path("buck-out")))?;
+
+        // `v2` doesn't exist yet.
+        let roots = PhysicalRoots::detect(fs, path("buck-out/v2"))?;
+        assert!(roots.buck_out_is_redirected());
+
+        let artifact = path("buck-out/v2/gen/foo/bar");
+        let physical = fast_disk.path().resolve(path("buck-out/v2/gen/foo/bar"));
+        assert_eq!(physical, roots.physical_path(artifact));
+
+        // Both spellings resolve to the same path.
+        assert_eq!(
+            artifact.to_owned(),
+            roots.relativize(&fs.resolve(artifact))?
+        );
+        assert_eq!(artifact.to_owned(), roots.relativize(&physical)?);
+        assert_eq!(
+            path("src/foo").to_owned(),
+            roots.relativize(&fs.resolve(path("src/foo")))?
+        );
+
+        // Other files on the same disk are not in the project.
+        assert!(
+            roots
+                .relativize(&fast_disk.path().resolve(path("other")))
+                .is_err()
+        );
+        assert!(roots.is_in_buck_out(artifact));
+        assert!(!roots.is_in_buck_out(path("buck-out/v2-other")));
+
+        assert!(roots.can_hardlink(artifact, path("buck-out/v2/gen/baz")));
+        assert!(!roots.can_hardlink(artifact, path("src/foo")));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_redirected_buck_out_inside_project() -> anyhow::Result<()> {
+        let project = ProjectRootTemp::new()?;
+        let fs = project.path();
+        fs_util::create_dir_all(fs.resolve(path("fast/buck-out/v2")))?;
+        fs_util::symlink(
+            fs.resolve(path("fast/buck-out")),
+            fs.resolve(path("buck-out")),
+        )?;
+
+        let roots = PhysicalRoots::detect(fs, path("buck-out/v2"))?;
+        assert!(roots.buck_out_is_redirected());
+        // The physical spelling maps to buck-out, not to the directory it's in.
+        assert_eq!(
+            path("buck-out/v2/gen/foo").to_owned(),
+            roots.relativize(&fs.resolve(path("fast/buck-out/v2/gen/foo")))?
+        );
+        assert_eq!(
+            path("fast/other").to_owned(),
+            roots.relativize(&fs.resolve(path("fast/other")))?
+        );
+        Ok(())
+    }
+}
@@ -402,6 +402,12 @@ impl RemoteExecutionClient {
         self.data.client.client().get_experiment_name()
     }
 
+    /// The RE `DigestFunction` of the digests in action results, `UNKNOWN` if the server didn't
+    /// advertise one.
+    pub fn get_digest_function(&self) -> i32 {
+        self.data.client.client().get_digest_function()
+    }
+
     pub fn fill_network_stats(&self, stats: &mut RemoteExecutionClientStats) {
         stats.uploads = RemoteExecutionClientOpStats::from(&self.data.uploads);
         stats.downloads = RemoteExecutionClientOpStats::from(&self.data.downloads);
@@ -504,6 +504,10 @@ impl ManagedRemoteExecutionClient {
         Ok(session_id)
     }
 
+    pub async fn get_digest_function(&self) -> anyhow::Result<i32> {
+        Ok(self.lock()?.get().await?.get_digest_function())
+    }
+
     /// Construct a dummy ManagedRemoteExecutionClient that won't actually work. This is only
     /// remotely useful in tests.
     pub fn testing_new_dummy() -> Self {
@@ -12,9 +12,10 @@ use std::sync::Arc;
 use anyhow::Context;
 use async_trait::async_trait;
 use buck2_common::liveliness_observer::CancelledLivelinessGuard;
+use buck2_common::liveliness_observer::CompositeLivelinessObserver;
 use buck2_common::liveliness_observer::LivelinessGuard;
 use buck2_common::liveliness_observer::LivelinessObserver;
-use buck2_common::liveliness_observer::LivelinessObserverExt;
+use buck2_common::liveliness_observer::NamedLivelinessObserver;
 use buck2_core::execution_types::executor_config::HybridExecutionLevel;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_execute::execute::claim::Claim;
@@ -163,12 +164,13 @@ where
             command,
             Box::new(claim_manager.dupe()),
             manager.events.dupe(),
-            Arc::new(
-                manager
-                    .liveliness_observer
-                    .dupe()
-                    .and(local_execution_liveliness_observer.dupe()),
-            ),
+            Arc::new(CompositeLivelinessObserver::join(vec![
+                NamedLivelinessObserver::create("command", manager.liveliness_observer.dupe()),
+                NamedLivelinessObserver::create(
+                    "claimed_by_remote",
+                    local_execution_liveliness_observer.dupe(),
+                ),
+            ])),
             cancellations,
         );
 
@@ -8,6 +8,7 @@
  */
 
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::ops::ControlFlow;
@@ -20,9 +21,12 @@ use std::time::SystemTime;
 use anyhow::Context as _;
 use async_trait::async_trait;
 use buck2_common::file_ops::FileDigestConfig;
+use buck2_common::liveliness_observer::CompositeLivelinessObserver;
 use buck2_common::liveliness_observer::LivelinessObserver;
-use buck2_common::liveliness_observer::LivelinessObserverExt;
+use buck2_common::liveliness_observer::NamedLivelinessObserver;
 use buck2_common::local_resource_state::LocalResourceHolder;
+use buck2_common::local_resource_state::LocalResourceOwner;
+use buck2_core::buck2_env;
 use buck2_core::fs::artifact_path_resolver::ArtifactFs;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
@@ -41,6 +45,7 @@ use buck2_execute::entry::build_entry_from_disk;
 use buck2_execute::entry::HashingInfo;
 use buck2_execute::execute::action_digest::ActionDigest;
 use buck2_execute::execute::blocking::BlockingExecutor;
+use buck2_execute::execute::blocking::IoTag;
 use buck2_execute::execute::clean_output_paths::CleanOutputPaths;
 use buck2_execute::execute::environment_inheritance::EnvironmentInheritance;
 use buck2_execute::execute::executor_stage_async;
@@ -62,8 +67,12 @@ use buck2_execute::execute::result::CommandExecutionResult;
 use buck2_execute::knobs::ExecutorGlobalKnobs;
 use buck2_execute::materialize::materializer::MaterializationError;
 use buck2_execute::materialize::materializer::Materializer;
+use buck2_execute::materialize::materializer::PrefetchGuard;
+use buck2_execute::materialize::materializer::StreamedInputs;
+use buck2_execute::materialize::physical_roots::PhysicalRoots;
 use buck2_forkserver::client::ForkserverClient;
 use buck2_forkserver::run::gather_output;
+use buck2_forkserver::run::gather_output_with_pid;
 use buck2_forkserver::run::maybe_absolutize_exe;
 use buck2_forkserver::run::timeout_into_cancellation;
 use buck2_forkserver::run::GatherOutputStatus;
@@ -102,6 +111,8 @@ pub struct LocalExecutor {
     blocking_executor: Arc<dyn BlockingExecutor>,
     pub(crate) host_sharing_broker: Arc<HostSharingBroker>,
     root: AbsNormPathBuf,
+    /// Where the project root and `buck-out` are on disk, to hash outputs consistently.
+    physical_roots: PhysicalRoots,
     #[cfg_attr(not(unix), allow(unused))]
     forkserver: Option<ForkserverClient>,
     #[allow(unused)]
@@ -117,6 +128,7 @@ impl LocalExecutor {
         blocking_executor: Arc<dyn BlockingExecutor>,
         host_sharing_broker: Arc<HostSharingBroker>,
         root: AbsNormPathBuf,
+        physical_roots: PhysicalRoots,
         forkserver: Option<ForkserverClient>,
         knobs: ExecutorGlobalKnobs,
         worker_pool: Option<Arc<WorkerPool>>,
@@ -127,6 +139,7 @@ impl LocalExecutor {
             blocking_executor,
             host_sharing_broker,
             root,
+            physical_roots,
             forkserver,
             knobs,
             worker_pool,
@@ -145,6 +158,7 @@ impl LocalExecutor {
         env_inheritance: Option<&'a EnvironmentInheritance>,
         liveliness_observer: impl LivelinessObserver + 'static,
         disable_miniperf: bool,
+        on_spawned: impl FnOnce(u32) + Send + 'a,
     ) -> impl futures::future::Future<
         Output = anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)>,
     > + Send
@@ -169,13 +183,14 @@ impl LocalExecutor {
                             env_inheritance,
                             liveliness_observer,
                             self.knobs.enable_miniperf && !disable_miniperf,
+                            on_spawned,
                         )
                         .await
                     }
 
                     #[cfg(not(unix))]
                     {
-                        let _unused = (forkserver, disable_miniperf);
+                        let _unused = (forkserver, disable_miniperf, on_spawned);
                         Err(anyhow::anyhow!("Forkserver is not supported off-UNIX"))
                     }
                 }
@@ -200,13 +215,58 @@ impl LocalExecutor {
                     let cancellation =
                         select(timeout.boxed(), alive.boxed()).map(|r| r.factor_first().0);
 
-                    gather_output(cmd, cancellation).await
+                    gather_output_with_pid(cmd, cancellation, on_spawned).await
                 }
                 .with_context(|| format!("Failed to gather output from command: {}", exe)),
             }
         }
     }
 
+    /// Let the materializer start on the inputs of this request while it waits for local
+    /// resources.
+    fn prefetch_inputs(&self, request: &CommandExecutionRequest) -> PrefetchGuard {
+        if request.streamed_inputs() && request.worker().is_none() {
+            // Those are streamed instead, unless someone else materializes them first.
+            return PrefetchGuard::none();
+        }
+        let mut paths = Vec::new();
+        for input in request.inputs() {
+            if let CommandExecutionInput::Artifact(group) = input {
+                for (artifact, _) in group.iter() {
+                    if artifact.requires_materialization(&self.artifact_fs) {
+                        // Only a hint: errors are reported when the inputs are materialized.
+                        if let Ok(path) = artifact.resolve_path(&self.artifact_fs) {
+                            paths.push(path);
+                        }
+                    }
+                }
+            }
+        }
+        self.materializer.prefetch(paths)
+    }
+
+    /// Let the materializer expose inputs of this request as named pipes, rather than
+    /// materialize them, if the action opted into it.
+    async fn stream_inputs(
+        &self,
+        request: &CommandExecutionRequest,
+    ) -> anyhow::Result<StreamedInputs> {
+        if !request.streamed_inputs() || request.worker().is_some() {
+            return Ok(StreamedInputs::none());
+        }
+        let mut paths = Vec::new();
+        for input in request.inputs() {
+            if let CommandExecutionInput::Artifact(group) = input {
+                for (artifact, _) in group.iter() {
+                    if artifact.requires_materialization(&self.artifact_fs) {
+                        paths.push(artifact.resolve_path(&self.artifact_fs)?);
+                    }
+                }
+            }
+        }
+        self.materializer.stream_inputs(paths).await
+    }
+
     async fn exec_request(
         &self,
         action_digest: &ActionDigest,
@@ -229,10 +289,18 @@ impl LocalExecutor {
             async {
                 let start = Instant::now();
 
+                let streamed_inputs = self.stream_inputs(request).await?;
+
                 let (r1, r2) = future::join(
                     async {
-                        materialize_inputs(&self.artifact_fs, self.materializer.as_ref(), request)
-                            .await
+                        let streamed = streamed_inputs.paths().iter().collect();
+                        materialize_inputs_except(
+                            &self.artifact_fs,
+                            self.materializer.as_ref(),
+                            request,
+                            &streamed,
+                        )
+                        .await
                     },
                     async {
                         // When user requests to not perform a cleanup for a specific action
@@ -255,17 +323,16 @@ impl LocalExecutor {
                 let scratch_path = r1?.scratch;
                 r2?;
 
-                anyhow::Ok((scratch_path, start.elapsed()))
+                anyhow::Ok((scratch_path, start.elapsed(), streamed_inputs))
             },
         )
         .await;
 
-        let (scratch_path, input_materialization_duration) = match executor_stage_result {
-            Ok((scratch_path, input_materialization_duration)) => {
-                (scratch_path, input_materialization_duration)
-            }
-            Err(e) => return manager.error("materialize_inputs_failed", e),
-        };
+        let (scratch_path, input_materialization_duration, streamed_inputs) =
+            match executor_stage_result {
+                Ok(r) => r,
+                Err(e) => return manager.error("materialize_inputs_failed", e),
+            };
 
         // TODO: Release here.
         let manager = manager.claim().await;
@@ -369,7 +436,11 @@ impl LocalExecutor {
                     StrOrOsStr::from(build_id),
                 )))
         };
-        let liveliness_observer = manager.liveliness_observer.dupe().and(cancellation);
+        let liveliness_observer: Arc<dyn LivelinessObserver> =
+            Arc::new(CompositeLivelinessObserver::join(vec![
+                NamedLivelinessObserver::create("command", manager.liveliness_observer.dupe()),
+                NamedLivelinessObserver::create("cancellation", Arc::new(cancellation)),
+            ]));
 
         let (worker, manager) = self.initialize_worker(request, manager, dispatcher).await?;
 
@@ -420,25 +491,53 @@ impl LocalExecutor {
                 let execution_start = Instant::now();
                 let start_time = SystemTime::now();
 
-                let env = iter_env().map(|(k, v)| (k, v.into_os_str()));
-                let r = if let Some(worker) = worker {
-                    let env: Vec<(OsString, OsString)> = env
-                        .into_iter()
-                        .map(|(k, v)| (OsString::from(k), v.to_owned()))
-                        .collect();
-                    Ok(worker.exec_cmd(request.args(), env).await)
-                } else {
+                let exec_local = || {
                     self.exec(
                         &args[0],
                         &args[1..],
-                        env,
+                        iter_env().map(|(k, v)| (k, v.into_os_str())),
                         request.working_directory(),
                         request.timeout(),
                         request.local_environment_inheritance(),
-                        liveliness_observer,
+                        liveliness_observer.dupe(),
                         request.disable_miniperf(),
+                        |pid| {
+                            for holder in local_resource_holders {
+                                holder.set_pid(pid);
+                            }
+                        },
                     )
-                    .await
+                };
+
+                let r = if let Some(worker) = worker {
+                    let env: Vec<(OsString, OsString)> = iter_env()
+                        .map(|(k, v)| (OsString::from(k), v.into_os_str().to_owned()))
+                        .collect();
+                    Ok(worker.exec_cmd(request.args(), env).await)
+                } else {
+                    let r = exec_local().await;
+                    // The action may have read an input in a way a named pipe doesn't support.
+                    // By now, every input is materialized, so the action runs again, once,
+                    // against regular files.
+                    match streamed_inputs.finish().await {
+                        Ok(true) if matches!(r, Ok((GatherOutputStatus::Finished { .. }, ..))) => {
+                            async {
+                                create_output_dirs(
+                                    &self.artifact_fs,
+                                    request,
+                                    self.materializer.dupe(),
+                                    self.blocking_executor.dupe(),
+                                    cancellations,
+                                )
+                                .await
+                                .context("Error creating output directories")?;
+                                exec_local().await
+                            }
+                            .await
+                        }
+                        Ok(_) => r,
+                        Err(e) => Err(e.context("Error materializing streamed inputs")),
+                    }
                 };
 
                 let execution_time = execution_start.elapsed();
@@ -556,7 +655,7 @@ impl LocalExecutor {
                 abspath,
                 FileDigestConfig::build(digest_config.cas_digest_config()),
                 self.blocking_executor.as_ref(),
-                self.artifact_fs.fs().root(),
+                &self.physical_roots,
             )
             .await
             .with_context(|| format!("collecting output {:?}", path))?;
@@ -730,27 +829,49 @@ impl PreparedCommandExecutor for LocalExecutor {
             digest_config,
         } = command;
 
-        let local_resource_holders = executor_stage_async(
-            {
-                let a = buck2_data::AcquireLocalResource {};
-                buck2_data::LocalStage {
-                    stage: Some(a.into()),
-                }
-            },
-            async move {
-                let mut holders = vec![];
-                // Acquire resources in a sorted way to avoid deadlock.
-                // It might happen if 2 tests both requiring resources A and B are run simultaneously and there is only 1 instance of resource per type.
-                // If tests are not acquiring them in a sorted way the following situation might happen:
-                // Test 1 acquires resource B and test 2 acquires resource A.
-                // Now test 1 is waiting on resource B and test 2 is waiting on resource A.
-                for r in request.required_local_resources() {
-                    holders.push(r.acquire_resource().await);
-                }
-                holders
-            },
-        )
-        .await;
+        // Until the action is dequeued. If it is cancelled before that, the prefetches that
+        // haven't started yet are withdrawn.
+        let prefetch = self.prefetch_inputs(request);
+
+        let local_resource_holders = if request.required_local_resources().is_empty() {
+            Ok(Vec::new())
+        } else {
+            let local_resource_owner = LocalResourceOwner {
+                command: request.all_args().next().map_or("", |a| a.as_str()).into(),
+                trace_id: get_dispatcher_opt().map(|d| d.trace_id().dupe()),
+                liveliness_observer: Some(manager.liveliness_observer.dupe()),
+            };
+            executor_stage_async(
+                {
+                    let a = buck2_data::AcquireLocalResource {};
+                    buck2_data::LocalStage {
+                        stage: Some(a.into()),
+                    }
+                },
+                async move {
+                    let timeout = buck2_env!("BUCK2_LOCAL_RESOURCE_ACQUIRE_TIMEOUT_S", type=u64)?
+                        .map(Duration::from_secs);
+                    let mut holders = vec![];
+                    // Acquire resources in a sorted way to avoid deadlock.
+                    // It might happen if 2 tests both requiring resources A and B are run simultaneously and there is only 1 instance of resource per type.
+                    // If tests are not acquiring them in a sorted way the following situation might happen:
+                    // Test 1 acquires resource B and test 2 acquires resource A.
+                    // Now test 1 is waiting on resource B and test 2 is waiting on resource A.
+                    for r in request.required_local_resources() {
+                        holders.push(
+                            r.acquire_resource(local_resource_owner.dupe(), timeout)
+                                .await?,
+                        );
+                    }
+                    anyhow::Ok(holders)
+                },
+            )
+            .await
+        };
+        let local_resource_holders = match local_resource_holders {
+            Ok(holders) => holders,
+            Err(e) => return manager.error("acquire_local_resource", e),
+        };
 
         let _worker_permit = self.acquire_worker_permit(request).await;
 
@@ -763,6 +884,9 @@ impl PreparedCommandExecutor for LocalExecutor {
         )
         .await;
 
+        // `exec_request` materializes whatever wasn't prefetched.
+        drop(prefetch);
+
         // If we start running something, we don't want this task to get dropped, because if we do
         // we might interfere with e.g. clean up.
         cancellations
@@ -823,6 +947,17 @@ pub async fn materialize_inputs(
     artifact_fs: &ArtifactFs,
     materializer: &dyn Materializer,
     request: &CommandExecutionRequest,
+) -> anyhow::Result<MaterializedInputPaths> {
+    materialize_inputs_except(artifact_fs, materializer, request, &HashSet::new()).await
+}
+
+/// Like `materialize_inputs`, but leaves alone the artifacts at `except`, e.g. because they are
+/// streamed to the command instead.
+async fn materialize_inputs_except(
+    artifact_fs: &ArtifactFs,
+    materializer: &dyn Materializer,
+    request: &CommandExecutionRequest,
+    except: &HashSet<&ProjectRelativePathBuf>,
 ) -> anyhow::Result<MaterializedInputPaths> {
     let mut paths = vec![];
     let mut scratch = ScratchPath(None);
@@ -832,7 +967,10 @@ pub async fn materialize_inputs(
             CommandExecutionInput::Artifact(group) => {
                 for (artifact, _) in group.iter() {
                     if artifact.requires_materialization(artifact_fs) {
-                        paths.push(artifact.resolve_path(artifact_fs)?);
+                        let path = artifact.resolve_path(artifact_fs)?;
+                        if !except.contains(&path) {
+                            paths.push(path);
+                        }
                     }
                 }
             }
@@ -892,7 +1030,7 @@ async fn check_inputs(
     request: &CommandExecutionRequest,
 ) -> ControlFlow<CommandExecutionResult, CommandExecutionManagerWithClaim> {
     let res = blocking_executor
-        .execute_io_inline(|| {
+        .execute_io_inline(IoTag::Other, || {
             for input in request.inputs() {
                 match input {
                     CommandExecutionInput::Artifact(group) => {
@@ -993,6 +1131,7 @@ pub async fn create_output_dirs(
         } else {
             blocking_executor
                 .execute_io(
+                    IoTag::Other,
                     Box::new(CleanOutputPaths {
                         paths: output_paths,
                     }),
@@ -1090,6 +1229,7 @@ mod unix {
         env_inheritance: Option<&EnvironmentInheritance>,
         liveliness_observer: impl LivelinessObserver + 'static,
         enable_miniperf: bool,
+        on_spawned: impl FnOnce(u32),
     ) -> anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)> {
         let exe = exe.as_ref();
 
@@ -1110,7 +1250,11 @@ mod unix {
         };
         apply_local_execution_environment(&mut req, working_directory, env, env_inheritance);
         forkserver
-            .execute(req, async move { liveliness_observer.while_alive().await })
+            .execute(
+                req,
+                async move { liveliness_observer.while_alive().await },
+                on_spawned,
+            )
             .await
     }
 
@@ -1283,6 +1427,8 @@ mod tests {
         let temp = ProjectRootTemp::new().unwrap();
         let project_fs = temp.path();
         let artifact_fs = artifact_fs(project_fs.dupe());
+        let physical_roots =
+            PhysicalRoots::unchecked(project_fs, artifact_fs.buck_out_path_resolver().root());
 
         let executor = LocalExecutor::new(
             artifact_fs,
@@ -1295,6 +1441,7 @@ mod tests {
                 1,
             )),
             temp.path().root().to_buf(),
+            physical_roots,
             None,
             ExecutorGlobalKnobs::default(),
             None,
@@ -1318,6 +1465,7 @@ mod tests {
                 None,
                 NoopLivelinessObserver::create(),
                 false,
+                |_pid| {},
             )
             .await?;
         assert!(matches!(status, GatherOutputStatus::Finished { exit_code, .. } if exit_code == 0));
@@ -1354,6 +1502,7 @@ mod tests {
                 Some(&EnvironmentInheritance::empty()),
                 NoopLivelinessObserver::create(),
                 false,
+                |_pid| {},
             )
             .await?;
         assert!(matches!(status, GatherOutputStatus::Finished { exit_code, .. } if exit_code == 0));
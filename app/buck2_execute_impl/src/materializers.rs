@@ -7,6 +7,10 @@
  * of this source tree.
  */
 
+use buck2_events::clock::HybridClock;
+use chrono::DateTime;
+use chrono::Utc;
+
 #[cfg(fbcode_build)]
 pub mod eden;
 
@@ -14,3 +18,9 @@ pub mod deferred;
 pub mod immediate;
 pub mod io;
 pub mod sqlite;
+
+/// The time to stamp access times with and to compute TTL deadlines from. Unlike the wall clock, it
+/// never goes backwards, see `HybridClock`.
+pub(crate) fn clock_now() -> DateTime<Utc> {
+    HybridClock::system().now().wall.into()
+}
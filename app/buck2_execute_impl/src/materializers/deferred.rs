@@ -8,10 +8,15 @@
  */
 
 pub mod clean_stale;
+mod clean_tree;
 mod extension;
 mod file_tree;
 mod io_handler;
+mod prefetch;
+mod streamed_input;
 mod subscriptions;
+mod verify;
+mod write_dedup;
 
 #[cfg(test)]
 mod tests;
@@ -34,6 +39,8 @@ use async_trait::async_trait;
 use buck2_common::file_ops::FileMetadata;
 use buck2_common::file_ops::TrackedFileDigest;
 use buck2_common::liveliness_observer::LivelinessGuard;
+use buck2_common::sqlite::SqliteVacuumConfig;
+use buck2_common::sqlite::SqliteVacuumResult;
 use buck2_core::buck2_env;
 use buck2_core::directory::unordered_entry_walk;
 use buck2_core::directory::DirectoryEntry;
@@ -52,6 +59,7 @@ use buck2_events::dispatch::with_dispatcher_async;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_events::span::SpanId;
 use buck2_execute::artifact_value::ArtifactValue;
+use buck2_execute::digest::CasDigestToReExt;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::directory::ActionDirectory;
 use buck2_execute::directory::ActionDirectoryEntry;
@@ -66,7 +74,11 @@ use buck2_execute::materialize::materializer::DeferredMaterializerExtensions;
 use buck2_execute::materialize::materializer::HttpDownloadInfo;
 use buck2_execute::materialize::materializer::MaterializationError;
 use buck2_execute::materialize::materializer::Materializer;
+use buck2_execute::materialize::materializer::MaterializerVerifyMismatch;
+use buck2_execute::materialize::materializer::PrefetchGuard;
+use buck2_execute::materialize::materializer::StreamedInputs;
 use buck2_execute::materialize::materializer::WriteRequest;
+use buck2_execute::materialize::physical_roots::PhysicalRoots;
 use buck2_execute::output_size::OutputSize;
 use buck2_execute::re::manager::ReConnectionManager;
 use buck2_futures::cancellation::CancellationContext;
@@ -101,11 +113,13 @@ use tokio::sync::mpsc;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::oneshot;
 use tokio::sync::oneshot::error::TryRecvError;
+use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
 use tokio::time::Instant;
 use tokio::time::Interval;
 use tracing::instrument;
 
+use crate::materializers::clock_now;
 use crate::materializers::deferred::clean_stale::CleanResult;
 use crate::materializers::deferred::clean_stale::CleanStaleArtifactsCommand;
 use crate::materializers::deferred::clean_stale::CleanStaleConfig;
@@ -113,10 +127,20 @@ use crate::materializers::deferred::extension::ExtensionCommand;
 use crate::materializers::deferred::file_tree::FileTree;
 use crate::materializers::deferred::io_handler::DefaultIoHandler;
 use crate::materializers::deferred::io_handler::IoHandler;
+use crate::materializers::deferred::prefetch::PendingPrefetch;
+use crate::materializers::deferred::prefetch::PrefetchId;
+use crate::materializers::deferred::prefetch::PrefetchQueue;
+use crate::materializers::deferred::streamed_input::stream_claimed_inputs;
+use crate::materializers::deferred::streamed_input::StreamFinished;
+use crate::materializers::deferred::streamed_input::StreamedInputClaim;
+use crate::materializers::deferred::streamed_input::STREAMED_INPUT_MIN_SIZE;
 use crate::materializers::deferred::subscriptions::MaterializerSubscriptionOperation;
 use crate::materializers::deferred::subscriptions::MaterializerSubscriptions;
+use crate::materializers::deferred::verify::verify_artifact;
+use crate::materializers::deferred::write_dedup::WriteDedupIndex;
 use crate::materializers::sqlite::MaterializerState;
 use crate::materializers::sqlite::MaterializerStateSqliteDb;
+use crate::materializers::sqlite::SqliteWriteBatchConfig;
 
 /// Materializer implementation that defers materialization of declared
 /// artifacts until they are needed (i.e. `ensure_materialized` is called).
@@ -154,6 +178,10 @@ pub struct DeferredMaterializerAccessor<T: IoHandler + 'static> {
     materialize_final_artifacts: bool,
     defer_write_actions: bool,
 
+    /// Shares the contents of identical declared writes.
+    #[allocative(skip)]
+    write_dedup_index: Mutex<WriteDedupIndex>,
+
     io: Arc<T>,
 
     /// Tracked for logging purposes.
@@ -163,6 +191,9 @@ pub struct DeferredMaterializerAccessor<T: IoHandler + 'static> {
 
     /// Logs verbose events about materializer to the event log when enabled.
     verbose_materializer_log: bool,
+
+    /// Whether `prefetch` hints are sent to the command loop.
+    prefetch: bool,
 }
 
 pub type DeferredMaterializer = DeferredMaterializerAccessor<DefaultIoHandler>;
@@ -179,19 +210,43 @@ impl<T: IoHandler> Drop for DeferredMaterializerAccessor<T> {
 pub struct DeferredMaterializerStats {
     declares: AtomicU64,
     declares_reused: AtomicU64,
+    /// Declared writes whose contents were shared with an identical write.
+    write_dedup_hits: AtomicU64,
 }
 
 fn access_time_update_max_buffer_size() -> anyhow::Result<usize> {
     buck2_env!("BUCK_ACCESS_TIME_UPDATE_MAX_BUFFER_SIZE", type=usize, default=5000)
 }
 
+fn write_dedup_index_size() -> anyhow::Result<usize> {
+    buck2_env!("BUCK2_WRITE_DEDUP_INDEX_SIZE", type=usize, default=100000)
+}
+
 pub struct DeferredMaterializerConfigs {
     pub materialize_final_artifacts: bool,
     pub defer_write_actions: bool,
+    /// Materialize a write by hardlinking an identical write that is already materialized,
+    /// instead of writing its contents again.
+    pub hardlink_deduplicated_writes: bool,
     pub ttl_refresh: TtlRefreshConfiguration,
     pub update_access_times: AccessTimesUpdates,
     pub verbose_materializer_log: bool,
     pub clean_stale_config: Option<CleanStaleConfig>,
+    /// Compact the sqlite db when the materializer is idle. Disabled if `None`.
+    pub sqlite_vacuum_config: Option<SqliteVacuumConfig>,
+    /// Prefetch the inputs of actions queued for local execution, with at most this many bytes
+    /// being prefetched at once. Disabled if `None`.
+    pub prefetch_max_bytes: Option<u64>,
+    /// Materialize at most this many artifacts at once. Unlimited if `None`.
+    pub max_concurrent_materializations: Option<usize>,
+    /// Artifacts declared at or under these paths are materialized as soon as they are declared,
+    /// for tools that expect them on disk without asking for them.
+    pub eager_paths: Vec<ProjectRelativePathBuf>,
+    /// Write materialization state to the sqlite db in batches. Written as it comes if `None`.
+    pub sqlite_write_batch: Option<SqliteWriteBatchConfig>,
+    /// Hash artifacts declared as existing in the background, and invalidate those that don't
+    /// match what's on disk. For debugging, since it costs a hash of every such artifact.
+    pub verify_declare_existing: bool,
 }
 
 pub struct TtlRefreshConfiguration {
@@ -307,6 +362,7 @@ pub(crate) struct DeferredMaterializerCommandProcessor<T: 'static> {
     /// used by the rest of Buck.
     rt: Handle,
     defer_write_actions: bool,
+    hardlink_deduplicated_writes: bool,
     log_buffer: LogBuffer,
     /// Keep track of artifact versions to avoid callbacks clobbering state if the state has moved
     /// forward.
@@ -327,6 +383,14 @@ pub(crate) struct DeferredMaterializerCommandProcessor<T: 'static> {
     access_times_buffer: Option<HashSet<ProjectRelativePathBuf>>,
     verbose_materializer_log: bool,
     daemon_dispatcher: EventDispatcher,
+    /// Artifacts to materialize in the background, see `Materializer::prefetch`.
+    prefetch_queue: PrefetchQueue,
+    /// Limits the number of `IoHandler::materialize_entry` calls running at once, if set.
+    materialization_permits: Option<Arc<Semaphore>>,
+    /// Artifacts declared at or under these paths are materialized right away.
+    eager_paths: Vec<ProjectRelativePathBuf>,
+    /// Check that artifacts declared as existing are actually on disk.
+    verify_declare_existing: bool,
 }
 
 struct TtlRefreshHistoryEntry {
@@ -358,12 +422,36 @@ pub enum MaterializeEntryError {
     },
 }
 
+/// An artifact declared as existing didn't match what's on disk, and hasn't been declared since.
+#[derive(buck2_error::Error, Debug)]
+#[error(
+    "Artifact at `{path}` was declared as already existing, but what's on disk doesn't match it (found {found}). The action producing it must run again"
+)]
+struct MismatchedExistingError {
+    path: ProjectRelativePathBuf,
+    found: String,
+}
+
 impl From<anyhow::Error> for MaterializeEntryError {
     fn from(e: anyhow::Error) -> MaterializeEntryError {
         Self::Error(e)
     }
 }
 
+impl Display for SharedMaterializingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error(e) => write!(f, "{:#}", e),
+            Self::NotFound { info, debug } => write!(
+                f,
+                "Artifact not found (digest origin: {}, debug: {})",
+                info.origin.as_display_for_not_found(),
+                debug
+            ),
+        }
+    }
+}
+
 impl From<MaterializeEntryError> for SharedMaterializingError {
     fn from(e: MaterializeEntryError) -> SharedMaterializingError {
         match e {
@@ -435,6 +523,13 @@ enum MaterializerCommand<T: 'static> {
         oneshot::Sender<BoxStream<'static, Result<(), MaterializationError>>>,
     ),
 
+    /// Takes the inputs of an action about to run locally, and marks those that can be streamed
+    /// to it through named pipes as streamed. See `Materializer::stream_inputs`.
+    StreamInputs(
+        Vec<ProjectRelativePathBuf>,
+        oneshot::Sender<Vec<StreamedInputClaim<T>>>,
+    ),
+
     Subscription(MaterializerSubscriptionOperation<T>),
 
     Extension(Box<dyn ExtensionCommand<T>>),
@@ -467,6 +562,9 @@ impl<T> std::fmt::Debug for MaterializerCommand<T> {
                 write!(f, "InvalidateFilePaths({:?})", paths)
             }
             MaterializerCommand::Ensure(paths, _, _) => write!(f, "Ensure({:?}, _)", paths,),
+            MaterializerCommand::StreamInputs(paths, _) => {
+                write!(f, "StreamInputs({:?}, _)", paths)
+            }
             MaterializerCommand::Subscription(op) => write!(f, "Subscription({:?})", op,),
             MaterializerCommand::Extension(ext) => write!(f, "Extension({:?})", ext),
             MaterializerCommand::Abort => write!(f, "Abort"),
@@ -475,7 +573,8 @@ impl<T> std::fmt::Debug for MaterializerCommand<T> {
 }
 
 /// Materializer commands that can be reordered with regard to other commands.
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 enum LowPriorityMaterializerCommand {
     /// [Materialization task -> Command thread]
     /// Notifies the command thread that an artifact was materialized. It takes
@@ -494,6 +593,42 @@ enum LowPriorityMaterializerCommand {
         version: Version,
         result: Result<(), SharedMaterializingError>,
     },
+
+    /// [Materializer trait methods -> Command thread]
+    /// Queues artifacts to materialize in the background. Being low priority, this is only
+    /// processed once the explicit requests sent before it are.
+    Prefetch {
+        id: PrefetchId,
+        paths: Vec<ProjectRelativePathBuf>,
+        #[derivative(Debug = "ignore")]
+        event_dispatcher: EventDispatcher,
+    },
+
+    /// [Materializer trait methods -> Command thread]
+    /// Withdraws the prefetches of `Prefetch` that haven't started yet.
+    WithdrawPrefetch(PrefetchId),
+
+    /// [Prefetch task -> Command thread]
+    /// A prefetch finished, successfully or not, making room for more.
+    PrefetchFinished { path: ProjectRelativePathBuf },
+
+    /// [Streamed input -> Command thread]
+    /// A local action is done with an input that was streamed to it. `materialized` is whether the
+    /// input was left on disk as a regular file.
+    StreamedInputFinished {
+        path: ProjectRelativePathBuf,
+        timestamp: DateTime<Utc>,
+        materialized: bool,
+    },
+
+    /// [Verification task -> Command thread]
+    /// An artifact declared as existing was compared to what's on disk. `version` is the version
+    /// it was declared with.
+    DeclareExistingVerified {
+        version: Version,
+        entry: ActionDirectoryEntry<ActionSharedDirectory>,
+        mismatch: Option<MaterializerVerifyMismatch>,
+    },
 }
 
 /// Tree that stores materialization data for each artifact. Used internally by
@@ -539,6 +674,14 @@ pub struct ArtifactMaterializationData {
     /// this path would need to wait on the existing future to finish.
     /// TODO(scottcao): Turn this into a queue of pending futures.
     processing: Processing,
+    /// Set if materializing this artifact failed since it was declared. Boxed since it's rarely
+    /// set.
+    failures: Option<Box<MaterializationFailures>>,
+}
+
+struct MaterializationFailures {
+    count: u64,
+    last_error: String,
 }
 
 /// Represents a processing future + the version at which it was issued. When receiving
@@ -662,9 +805,19 @@ enum ArtifactMaterializationStage {
         /// Should not be deleted without invalidating DICE nodes, which currently
         /// means killing the daemon.
         active: bool,
+        /// What the artifact was declared with, if the running daemon declared it with a way to
+        /// materialize it, so that it can be materialized again if it is found corrupted on
+        /// disk. DICE holds on to the entries of these anyway.
+        declared: Option<Box<DeclaredArtifact>>,
     },
 }
 
+/// The `Declared` stage of an artifact that has since been materialized.
+struct DeclaredArtifact {
+    entry: ActionDirectoryEntry<ActionSharedDirectory>,
+    method: Arc<ArtifactMaterializationMethod>,
+}
+
 /// Different ways to materialize the files of an artifact. Some artifacts need
 /// to be fetched from the CAS, others copied locally.
 #[derive(Debug, Display)]
@@ -693,6 +846,19 @@ pub enum ArtifactMaterializationMethod {
     #[display(fmt = "http download ({})", info)]
     HttpDownload { info: HttpDownloadInfo },
 
+    /// A file that must be fetched from the CAS, and that a local action is currently reading
+    /// through a named pipe instead. Materializing it waits for the action to be done with it.
+    #[display(fmt = "streamed (action: {})", "info.origin")]
+    Streamed {
+        info: Arc<CasDownloadInfo>,
+        finished: StreamFinished,
+    },
+
+    /// The artifact was declared as existing, but what's on disk didn't match it. There is
+    /// nothing to materialize it from, so materializing it fails until it is declared again.
+    #[display(fmt = "existing (mismatched on disk)")]
+    MismatchedExisting { found: String },
+
     #[cfg(test)]
     Test,
 }
@@ -707,13 +873,17 @@ impl MaterializationMethodToProto for ArtifactMaterializationMethod {
             ArtifactMaterializationMethod::LocalCopy { .. } => {
                 buck2_data::MaterializationMethod::LocalCopy
             }
-            ArtifactMaterializationMethod::CasDownload { .. } => {
+            ArtifactMaterializationMethod::CasDownload { .. }
+            | ArtifactMaterializationMethod::Streamed { .. } => {
                 buck2_data::MaterializationMethod::CasDownload
             }
             ArtifactMaterializationMethod::Write { .. } => buck2_data::MaterializationMethod::Write,
             ArtifactMaterializationMethod::HttpDownload { .. } => {
                 buck2_data::MaterializationMethod::HttpDownload
             }
+            ArtifactMaterializationMethod::MismatchedExisting { .. } => {
+                buck2_data::MaterializationMethod::MismatchedExisting
+            }
             #[cfg(test)]
             ArtifactMaterializationMethod::Test => unimplemented!(),
         }
@@ -842,19 +1012,17 @@ impl<T: IoHandler + Allocative> Materializer for DeferredMaterializerAccessor<T>
                 is_executable,
             };
 
-            // NOTE: The zstd crate doesn't release extra capacity of its encoding buffer so it's
-            // important to do so here (or the compressed Vec is the same capacity as the input!).
-            let compressed_data = zstd::bulk::compress(&content, 0)
-                .with_context(|| format!("Error compressing {} bytes", content.len()))?
-                .into_boxed_slice();
+            let (write, reused) = self
+                .write_dedup_index
+                .lock()
+                .get_or_insert(&meta, || WriteFile::compress(&content, &meta))?;
+            if reused {
+                self.stats.write_dedup_hits.fetch_add(1, Ordering::Relaxed);
+            }
 
             paths.push(path);
             values.push(ArtifactValue::file(meta));
-            methods.push(ArtifactMaterializationMethod::Write(Arc::new(WriteFile {
-                compressed_data,
-                decompressed_size: content.len(),
-                is_executable,
-            })));
+            methods.push(ArtifactMaterializationMethod::Write(write));
         }
 
         for (path, (value, method)) in std::iter::zip(paths, std::iter::zip(values.iter(), methods))
@@ -959,8 +1127,46 @@ impl<T: IoHandler + Allocative> Materializer for DeferredMaterializerAccessor<T>
         snapshot.deferred_materializer_declares = self.stats.declares.load(Ordering::Relaxed);
         snapshot.deferred_materializer_declares_reused =
             self.stats.declares_reused.load(Ordering::Relaxed);
+        snapshot.deferred_materializer_write_dedup_hits =
+            self.stats.write_dedup_hits.load(Ordering::Relaxed);
         snapshot.deferred_materializer_queue_size = self.command_sender.counters.queue_size() as _;
     }
+
+    fn prefetch(&self, artifact_paths: Vec<ProjectRelativePathBuf>) -> PrefetchGuard {
+        if !self.prefetch || artifact_paths.is_empty() {
+            return PrefetchGuard::none();
+        }
+
+        let id = PrefetchId::next();
+        let _ignored =
+            self.command_sender
+                .send_low_priority(LowPriorityMaterializerCommand::Prefetch {
+                    id,
+                    paths: artifact_paths,
+                    event_dispatcher: get_dispatcher(),
+                });
+
+        let command_sender = self.command_sender.dupe();
+        PrefetchGuard::new(move || {
+            let _ignored = command_sender
+                .send_low_priority(LowPriorityMaterializerCommand::WithdrawPrefetch(id));
+        })
+    }
+
+    async fn stream_inputs(
+        &self,
+        artifact_paths: Vec<ProjectRelativePathBuf>,
+    ) -> anyhow::Result<StreamedInputs> {
+        if artifact_paths.is_empty() {
+            return Ok(StreamedInputs::none());
+        }
+
+        let (sender, recv) = oneshot::channel();
+        self.command_sender
+            .send(MaterializerCommand::StreamInputs(artifact_paths, sender))?;
+        let claims = recv.await?;
+        Ok(stream_claimed_inputs(&self.io, claims).await)
+    }
 }
 
 impl DeferredMaterializerAccessor<DefaultIoHandler> {
@@ -1009,10 +1215,11 @@ impl DeferredMaterializerAccessor<DefaultIoHandler> {
 
         let tree = ArtifactTree::initialize(sqlite_state);
 
+        let roots = PhysicalRoots::detect(&fs, &buck_out_path)?;
         let io = Arc::new(DefaultIoHandler::new(
             fs,
             digest_config,
-            buck_out_path,
+            roots,
             re_client_manager,
             io_executor,
             http_client,
@@ -1028,6 +1235,7 @@ impl DeferredMaterializerAccessor<DefaultIoHandler> {
                 sqlite_db,
                 rt,
                 defer_write_actions: configs.defer_write_actions,
+                hardlink_deduplicated_writes: configs.hardlink_deduplicated_writes,
                 log_buffer: LogBuffer::new(25),
                 version_tracker: VersionTracker::new(),
                 command_sender,
@@ -1040,10 +1248,17 @@ impl DeferredMaterializerAccessor<DefaultIoHandler> {
                 access_times_buffer,
                 verbose_materializer_log: configs.verbose_materializer_log,
                 daemon_dispatcher,
+                prefetch_queue: PrefetchQueue::new(configs.prefetch_max_bytes.unwrap_or(0)),
+                materialization_permits: configs
+                    .max_concurrent_materializations
+                    .map(|permits| Arc::new(Semaphore::new(permits))),
+                eager_paths: configs.eager_paths,
+                verify_declare_existing: configs.verify_declare_existing,
             }
         };
 
         let access_time_update_max_buffer_size = access_time_update_max_buffer_size()?;
+        let write_dedup_index = Mutex::new(WriteDedupIndex::new(write_dedup_index_size()?));
 
         let command_thread = thread_spawn("buck2-dm", {
             move || {
@@ -1060,6 +1275,8 @@ impl DeferredMaterializerAccessor<DefaultIoHandler> {
                     access_time_update_max_buffer_size,
                     configs.update_access_times,
                     configs.clean_stale_config,
+                    configs.sqlite_vacuum_config,
+                    configs.sqlite_write_batch,
                 ));
             }
         })
@@ -1070,14 +1287,29 @@ impl DeferredMaterializerAccessor<DefaultIoHandler> {
             command_sender,
             materialize_final_artifacts: configs.materialize_final_artifacts,
             defer_write_actions: configs.defer_write_actions,
+            write_dedup_index,
             io,
             materializer_state_info,
             stats,
             verbose_materializer_log: configs.verbose_materializer_log,
+            prefetch: configs.prefetch_max_bytes.is_some(),
         })
     }
 }
 
+fn sqlite_vacuum_event(res: &SqliteVacuumResult) -> buck2_data::SqliteVacuum {
+    buck2_data::SqliteVacuum {
+        db: "materializer_state".to_owned(),
+        full: res.full,
+        interrupted: res.interrupted,
+        size_before_bytes: res.before.size_bytes(),
+        size_after_bytes: res.after.size_bytes(),
+        free_bytes_before: res.before.free_bytes(),
+        free_bytes_after: res.after.free_bytes(),
+        duration: res.duration.try_into().ok(),
+    }
+}
+
 /// Simple ring buffer for tracking recent commands, to be shown on materializer error
 #[derive(Clone)]
 struct LogBuffer {
@@ -1115,6 +1347,8 @@ struct CommandStream<T: 'static> {
     io_buffer_ticker: Interval,
     clean_stale_ticker: Option<Interval>,
     clean_stale_fut: Option<BoxFuture<'static, anyhow::Result<CleanResult>>>,
+    sqlite_vacuum_ticker: Option<Interval>,
+    sqlite_flush_ticker: Option<Interval>,
 }
 
 enum Op<T: 'static> {
@@ -1123,6 +1357,8 @@ enum Op<T: 'static> {
     RefreshTtls,
     Tick,
     CleanStaleRequest,
+    SqliteVacuum,
+    SqliteFlush,
 }
 
 impl<T: 'static> Stream for CommandStream<T> {
@@ -1163,6 +1399,18 @@ impl<T: 'static> Stream for CommandStream<T> {
             }
         }
 
+        if let Some(ticker) = this.sqlite_vacuum_ticker.as_mut() {
+            if ticker.poll_tick(cx).is_ready() {
+                return Poll::Ready(Some(Op::SqliteVacuum));
+            }
+        }
+
+        if let Some(ticker) = this.sqlite_flush_ticker.as_mut() {
+            if ticker.poll_tick(cx).is_ready() {
+                return Poll::Ready(Some(Op::SqliteFlush));
+            }
+        }
+
         // We can never be done because we never drop the senders, so let's not bother.
         Poll::Pending
     }
@@ -1224,6 +1472,8 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
         access_time_update_max_buffer_size: usize,
         access_time_updates: AccessTimesUpdates,
         clean_stale_config: Option<CleanStaleConfig>,
+        sqlite_vacuum_config: Option<SqliteVacuumConfig>,
+        sqlite_write_batch: Option<SqliteWriteBatchConfig>,
     ) {
         let MaterializerReceiver {
             high_priority,
@@ -1249,6 +1499,24 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
 
         let io_buffer_ticker = tokio::time::interval(std::time::Duration::from_secs(5));
 
+        let sqlite_vacuum_ticker = sqlite_vacuum_config.as_ref().map(|config| {
+            tokio::time::interval_at(
+                tokio::time::Instant::now() + config.idle_delay,
+                config.idle_delay,
+            )
+        });
+
+        let sqlite_flush_ticker = match (sqlite_write_batch, self.sqlite_db.as_mut()) {
+            (Some(config), Some(sqlite_db)) => {
+                sqlite_db.set_max_pending_inserts(config.max_entries);
+                Some(tokio::time::interval_at(
+                    tokio::time::Instant::now() + config.max_delay,
+                    config.max_delay,
+                ))
+            }
+            _ => None,
+        };
+
         let mut stream = CommandStream {
             high_priority,
             low_priority,
@@ -1256,9 +1524,16 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
             io_buffer_ticker,
             clean_stale_ticker,
             clean_stale_fut: None,
+            sqlite_vacuum_ticker,
+            sqlite_flush_ticker,
         };
 
+        let mut last_command = Instant::now();
+
         while let Some(op) = stream.next().await {
+            if matches!(op, Op::Command(..) | Op::LowPriorityCommand(..)) {
+                last_command = Instant::now();
+            }
             match op {
                 Op::Command(command) => {
                     self.log_buffer.push(format!("{:?}", command));
@@ -1291,7 +1566,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
 
                                 self.spawn(async {
                                     let res = fut.await;
-                                    let _ignored = tx.send((Utc::now(), res));
+                                    let _ignored = tx.send((clock_now(), res));
                                 });
 
                                 rx
@@ -1302,7 +1577,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                                 self.ttl_refresh_instance = Some(ttl_refresh);
                             }
                             None => self.ttl_refresh_history.push(TtlRefreshHistoryEntry {
-                                at: Utc::now(),
+                                at: clock_now(),
                                 outcome: None,
                             }),
                         }
@@ -1313,12 +1588,15 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                         // Force a periodic flush.
                         self.flush_access_times(0);
                     };
+                    if let Some(config) = sqlite_vacuum_config.as_ref() {
+                        self.record_sqlite_write_batch(config);
+                    }
                 }
                 Op::CleanStaleRequest => {
                     if let Some(config) = clean_stale_config.as_ref() {
                         let dispatcher = self.daemon_dispatcher.dupe();
                         let cmd = CleanStaleArtifactsCommand {
-                            keep_since_time: chrono::Utc::now() - config.artifact_ttl,
+                            keep_since_time: clock_now() - config.artifact_ttl,
                             dry_run: config.dry_run,
                             tracked_only: false,
                             dispatcher,
@@ -1334,6 +1612,17 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                         .unwrap();
                     }
                 }
+                Op::SqliteVacuum => {
+                    if let Some(config) = sqlite_vacuum_config.as_ref() {
+                        // Don't compete with builds for the db.
+                        if last_command.elapsed() >= config.idle_delay {
+                            self.vacuum_sqlite_db(config, &counters);
+                        }
+                    }
+                }
+                Op::SqliteFlush => {
+                    self.flush_sqlite_inserts();
+                }
             }
         }
     }
@@ -1363,7 +1652,9 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
 
                 self.declare(&path, value, method);
 
-                if self.subscriptions.should_materialize_eagerly(&path) {
+                if self.subscriptions.should_materialize_eagerly(&path)
+                    || self.eager_paths.iter().any(|p| path.starts_with(p))
+                {
                     self.materialize_artifact(&path, event_dispatcher);
                 }
             }
@@ -1417,6 +1708,9 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                     .send(self.materialize_many_artifacts(paths, event_dispatcher))
                     .ok();
             }
+            MaterializerCommand::StreamInputs(paths, sender) => {
+                sender.send(self.claim_streamed_inputs(&paths)).ok();
+            }
             MaterializerCommand::Subscription(sub) => sub.execute(self),
             MaterializerCommand::Extension(ext) => ext.execute(self),
             MaterializerCommand::Abort => unreachable!(),
@@ -1439,11 +1733,257 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                 version,
                 result,
             } => {
-                self.tree.cleanup_finished(path, version, result);
+                if self.tree.cleanup_finished(&path, version, result) {
+                    self.subscriptions.on_cleanup_finished(&path);
+                }
+            }
+            LowPriorityMaterializerCommand::Prefetch {
+                id,
+                paths,
+                event_dispatcher,
+            } => {
+                self.prefetch(id, paths, event_dispatcher);
+            }
+            LowPriorityMaterializerCommand::WithdrawPrefetch(id) => {
+                self.prefetch_queue.withdraw(id);
+            }
+            LowPriorityMaterializerCommand::PrefetchFinished { path } => {
+                self.prefetch_queue.finished(&path);
+                self.start_prefetches();
+            }
+            LowPriorityMaterializerCommand::StreamedInputFinished {
+                path,
+                timestamp,
+                materialized,
+            } => {
+                self.streamed_input_finished(&path, timestamp, materialized);
+            }
+            LowPriorityMaterializerCommand::DeclareExistingVerified {
+                version,
+                entry,
+                mismatch,
+            } => {
+                if let Some(mismatch) = mismatch {
+                    self.declare_existing_mismatch(version, entry, mismatch);
+                }
             }
         }
     }
 
+    fn prefetch(
+        &mut self,
+        id: PrefetchId,
+        paths: Vec<ProjectRelativePathBuf>,
+        event_dispatcher: EventDispatcher,
+    ) {
+        for path in paths {
+            if self.prefetch_size(&path).is_some() && !self.prefetch_queue.contains(&path) {
+                self.prefetch_queue.push(PendingPrefetch {
+                    id,
+                    path,
+                    event_dispatcher: event_dispatcher.dupe(),
+                });
+            }
+        }
+        self.start_prefetches();
+    }
+
+    /// Size of the artifact at this path if prefetching it would do anything, i.e. it is declared
+    /// and nothing is materializing it yet.
+    fn prefetch_size(&self, path: &ProjectRelativePath) -> Option<u64> {
+        let data = self.tree.prefix_get(&mut path.iter())?;
+        if let Processing::Active {
+            future: ProcessingFuture::Materializing(..),
+            ..
+        } = &data.processing
+        {
+            return None;
+        }
+        match &data.stage {
+            ArtifactMaterializationStage::Declared { method, .. }
+                if matches!(&**method, ArtifactMaterializationMethod::Streamed { .. }) =>
+            {
+                None
+            }
+            ArtifactMaterializationStage::Declared { entry, .. } => {
+                Some(entry.calc_output_count_and_bytes().bytes)
+            }
+            ArtifactMaterializationStage::Materialized { .. } => None,
+        }
+    }
+
+    /// Start queued prefetches, in order, until the next one doesn't fit in the cap.
+    fn start_prefetches(&mut self) {
+        while let Some(next) = self.prefetch_queue.peek() {
+            let path = next.path.clone();
+            let bytes = match self.prefetch_size(&path) {
+                // Materialized, or being materialized, since it was queued.
+                None => {
+                    self.prefetch_queue.pop();
+                    continue;
+                }
+                Some(bytes) => bytes,
+            };
+            if !self.prefetch_queue.has_room_for(bytes) {
+                break;
+            }
+
+            let PendingPrefetch {
+                event_dispatcher, ..
+            } = self.prefetch_queue.pop().unwrap();
+            let Some(fut) = self.materialize_artifact(&path, event_dispatcher) else {
+                continue;
+            };
+            self.prefetch_queue.started(path.clone(), bytes);
+
+            let command_sender = self.command_sender.dupe();
+            self.spawn(async move {
+                // Errors are reported to whoever actually needs the artifact, when it is
+                // materialized again.
+                let _ignored = fut.await;
+                let _ignored = command_sender
+                    .send_low_priority(LowPriorityMaterializerCommand::PrefetchFinished { path });
+            });
+        }
+    }
+
+    /// Mark the inputs among `paths` that are worth streaming, and that can be streamed, as
+    /// streamed. That is files that would be downloaded from the CAS, that nothing is
+    /// materializing, and that materializing the other inputs doesn't need: they would wait for
+    /// the action, which waits for them.
+    fn claim_streamed_inputs(
+        &mut self,
+        paths: &[ProjectRelativePathBuf],
+    ) -> Vec<StreamedInputClaim<T>> {
+        let needed = self.materialization_deps(paths);
+        let mut claims = Vec::new();
+        for path in paths {
+            if needed.iter().any(|p| p.starts_with(path)) {
+                continue;
+            }
+            let mut path_iter = path.iter();
+            let Some(data) = self.tree.prefix_get_mut(&mut path_iter) else {
+                continue;
+            };
+            if path_iter.next().is_some()
+                || data.deps.is_some()
+                || !matches!(data.processing, Processing::Done(..))
+            {
+                continue;
+            }
+            let ArtifactMaterializationStage::Declared { entry, method } = &mut data.stage else {
+                continue;
+            };
+            let (
+                ArtifactMaterializationMethod::CasDownload { info },
+                DirectoryEntry::Leaf(ActionDirectoryMember::File(file)),
+            ) = (&**method, &*entry)
+            else {
+                continue;
+            };
+            if file.digest.size() < STREAMED_INPUT_MIN_SIZE {
+                continue;
+            }
+
+            let info = info.dupe();
+            let (claim, finished) = StreamedInputClaim::new(
+                path.clone(),
+                file.digest.to_re(),
+                file.is_executable,
+                info.re_use_case,
+                self.command_sender.dupe(),
+            );
+            *method = Arc::new(ArtifactMaterializationMethod::Streamed { info, finished });
+            claims.push(claim);
+        }
+        claims
+    }
+
+    /// Artifacts that materializing any of `paths` would materialize first, which includes some
+    /// of `paths` if they need one another.
+    fn materialization_deps(
+        &self,
+        paths: &[ProjectRelativePathBuf],
+    ) -> HashSet<ProjectRelativePathBuf> {
+        let mut deps = HashSet::new();
+        let mut queue = paths
+            .iter()
+            .flat_map(|p| self.direct_materialization_deps(p))
+            .collect::<Vec<_>>();
+        while let Some(path) = queue.pop() {
+            if deps.insert(path.clone()) {
+                queue.extend(self.direct_materialization_deps(&path));
+            }
+        }
+        deps
+    }
+
+    /// The artifacts copied into the artifact at `path`, and those its symlinks point to.
+    fn direct_materialization_deps(
+        &self,
+        path: &ProjectRelativePath,
+    ) -> Vec<ProjectRelativePathBuf> {
+        let Some(data) = self.tree.prefix_get(&mut path.iter()) else {
+            return Vec::new();
+        };
+        let mut deps = match &data.deps {
+            Some(deps) => self.tree.find_artifacts(deps),
+            None => Vec::new(),
+        };
+        if let ArtifactMaterializationStage::Declared { method, .. } = &data.stage {
+            if let ArtifactMaterializationMethod::LocalCopy(_, copied_artifacts) = &**method {
+                deps.extend(copied_artifacts.iter().map(|a| a.src.clone()));
+            }
+        }
+        deps
+    }
+
+    /// A local action is done with an input that was streamed to it. From now on, it is
+    /// materialized as usual, unless it was left on disk.
+    fn streamed_input_finished(
+        &mut self,
+        path: &ProjectRelativePath,
+        timestamp: DateTime<Utc>,
+        materialized: bool,
+    ) {
+        let Some(data) = self.tree.prefix_get_mut(&mut path.iter()) else {
+            // Invalidated in the meantime.
+            return;
+        };
+        let ArtifactMaterializationStage::Declared { entry, method } = &mut data.stage else {
+            return;
+        };
+        let ArtifactMaterializationMethod::Streamed { info, .. } = &**method else {
+            // Declared again in the meantime.
+            return;
+        };
+
+        if materialized {
+            let metadata = ArtifactMetadata::new(entry);
+            on_materialization(
+                self.sqlite_db.as_mut(),
+                &self.log_buffer,
+                &self.subscriptions,
+                path,
+                &metadata,
+                timestamp,
+                "materializer_streamed_input_error",
+            );
+            let declared = Box::new(DeclaredArtifact {
+                entry: entry.dupe(),
+                method: Arc::new(ArtifactMaterializationMethod::CasDownload { info: info.dupe() }),
+            });
+            data.stage = ArtifactMaterializationStage::Materialized {
+                metadata,
+                last_access_time: timestamp,
+                active: true,
+                declared: Some(declared),
+            };
+        } else {
+            *method = Arc::new(ArtifactMaterializationMethod::CasDownload { info: info.dupe() });
+        }
+    }
+
     /// Poll the current TTL refresh and remove it if it's done. Add the outcome to
     /// ttl_refresh_history.
     fn poll_current_ttl_refresh(&mut self) {
@@ -1464,7 +2004,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                 Err(TryRecvError::Closed) => {
                     // Shouldnt really happen unless Tokio is shutting down, but be safe.
                     self.ttl_refresh_history.push(TtlRefreshHistoryEntry {
-                        at: Utc::now(),
+                        at: clock_now(),
                         outcome: Some(Err(anyhow::anyhow!("Shutdown"))),
                     });
                     None
@@ -1496,6 +2036,8 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
             let buffer = std::mem::take(access_times_buffer);
             let now = Instant::now();
             tracing::debug!("Flushing access times buffer");
+            // Access times are only updated for rows that were written.
+            self.flush_sqlite_inserts();
             if let Some(sqlite_db) = self.sqlite_db.as_mut() {
                 if let Err(e) = sqlite_db
                     .materializer_state_table()
@@ -1519,6 +2061,73 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
         "Access time updates are disabled. Consider removing `update_access_times = false` from your .buckconfig".to_owned()
     }
 
+    /// Writes the materialization state that is pending in the sqlite db, if any.
+    fn flush_sqlite_inserts(&mut self) {
+        if let Some(sqlite_db) = self.sqlite_db.as_mut() {
+            if let Err(e) = sqlite_db.flush_inserts() {
+                soft_error!(
+                    "materializer_sqlite_flush_error",
+                    e.context(self.log_buffer.clone()),
+                    quiet: true
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    /// Keeps track of how many pages are free in the sqlite db after writing to it.
+    fn record_sqlite_write_batch(&mut self, config: &SqliteVacuumConfig) {
+        if let Some(sqlite_db) = self.sqlite_db.as_mut() {
+            if let Err(e) = sqlite_db.maintenance().record_write_batch(config) {
+                tracing::warn!("Error reading materializer state db stats: {:#}", e);
+            }
+        }
+    }
+
+    /// Incrementally compacts the sqlite db if it has enough free pages. Stops as soon as another
+    /// command comes in, and resumes next time the materializer is idle.
+    fn vacuum_sqlite_db(&mut self, config: &SqliteVacuumConfig, counters: &MaterializerCounters) {
+        let Some(sqlite_db) = self.sqlite_db.as_mut() else {
+            return;
+        };
+        if !sqlite_db.maintenance().needs_vacuum() {
+            return;
+        }
+        match sqlite_db
+            .maintenance()
+            .incremental_vacuum(config, || counters.queue_size() > 0)
+        {
+            Ok(res) => {
+                tracing::debug!(
+                    "Compacted materializer state db from {} to {} bytes",
+                    res.before.size_bytes(),
+                    res.after.size_bytes(),
+                );
+                self.daemon_dispatcher
+                    .instant_event(sqlite_vacuum_event(&res));
+            }
+            Err(e) => {
+                soft_error!(
+                    "materializer_sqlite_vacuum_error",
+                    e.context(self.log_buffer.clone()),
+                    quiet: true
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    /// Fully compacts the sqlite db, no matter how many pages are free.
+    fn compact_sqlite_db(&mut self) -> anyhow::Result<buck2_data::SqliteVacuum> {
+        let sqlite_db = self
+            .sqlite_db
+            .as_mut()
+            .context("Materializer state db is disabled, set buck2.sqlite_materializer_state")?;
+        sqlite_db.flush_inserts()?;
+        let res = sqlite_db.maintenance().full_vacuum()?;
+        Ok(sqlite_vacuum_event(&res))
+    }
+
     fn materialize_many_artifacts(
         &mut self,
         paths: Vec<ProjectRelativePathBuf>,
@@ -1550,24 +2159,118 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
             &self.subscriptions,
             path,
             &metadata,
-            Utc::now(),
+            clock_now(),
             "materializer_declare_existing_error",
         );
 
+        let version = self.version_tracker.next();
+        if self.verify_declare_existing {
+            self.verify_declared_existing(
+                path.to_buf(),
+                value.entry().dupe(),
+                metadata.dupe(),
+                version,
+            );
+        }
+
         self.tree.insert(
             path.iter().map(|f| f.to_owned()),
             Box::new(ArtifactMaterializationData {
                 deps: value.deps().duped(),
                 stage: ArtifactMaterializationStage::Materialized {
                     metadata,
-                    last_access_time: Utc::now(),
+                    last_access_time: clock_now(),
                     active: true,
+                    // Nothing to materialize it from.
+                    declared: None,
                 },
-                processing: Processing::Done(self.version_tracker.next()),
+                processing: Processing::Done(version),
+                failures: None,
             }),
         );
     }
 
+    /// Hashes what's on disk at `path` in the background, and reports back whether it is the
+    /// artifact that was declared as existing.
+    fn verify_declared_existing(
+        &self,
+        path: ProjectRelativePathBuf,
+        entry: ActionDirectoryEntry<ActionSharedDirectory>,
+        metadata: ArtifactMetadata,
+        version: Version,
+    ) {
+        let io = self.io.dupe();
+        let command_sender = self.command_sender.dupe();
+        self.spawn(async move {
+            let mismatch = verify_artifact(&io, path, &metadata).await;
+            // If the materializer has shut down, we ignore this.
+            let _ignored = command_sender.send_low_priority(
+                LowPriorityMaterializerCommand::DeclareExistingVerified {
+                    version,
+                    entry,
+                    mismatch,
+                },
+            );
+        });
+    }
+
+    /// Reports an artifact that was declared as existing but isn't on disk, and invalidates it.
+    /// The artifact stays declared, but with nothing to materialize it from, so that anything
+    /// using it fails rather than reading what's on disk until it is declared again.
+    fn declare_existing_mismatch(
+        &mut self,
+        version: Version,
+        entry: ActionDirectoryEntry<ActionSharedDirectory>,
+        mismatch: MaterializerVerifyMismatch,
+    ) {
+        tracing::warn!(
+            "Artifact declared as existing at `{}` doesn't match what's on disk: expected {}, found {}",
+            mismatch.path,
+            mismatch.expected,
+            mismatch.found,
+        );
+        let deps = self
+            .tree
+            .prefix_get(&mut mismatch.path.iter())
+            .and_then(|data| data.deps.dupe());
+        let res = self.invalidate_mismatches(vec![(mismatch.path.clone(), version)]);
+        let invalidated = match res {
+            Ok(invalidated) => !invalidated.is_empty(),
+            Err(e) => {
+                soft_error!(
+                    "materializer_declare_existing_mismatch_error",
+                    e.context(self.log_buffer.clone()),
+                    quiet: true
+                )
+                .unwrap();
+                false
+            }
+        };
+        if invalidated {
+            self.tree.insert(
+                mismatch.path.iter().map(|f| f.to_owned()),
+                Box::new(ArtifactMaterializationData {
+                    deps,
+                    stage: ArtifactMaterializationStage::Declared {
+                        entry,
+                        method: Arc::new(ArtifactMaterializationMethod::MismatchedExisting {
+                            found: mismatch.found.to_string(),
+                        }),
+                    },
+                    processing: Processing::Done(self.version_tracker.next()),
+                    failures: None,
+                }),
+            );
+        }
+        self.daemon_dispatcher
+            .instant_event(buck2_data::MaterializerDeclareExistingMismatch {
+                path: mismatch.path.to_string(),
+                expected: mismatch.expected,
+                found: mismatch.found.to_string(),
+                invalidated,
+            });
+    }
+
     fn declare(
         &mut self,
         path: &ProjectRelativePath,
@@ -1578,6 +2281,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
 
         // Check if artifact to be declared is same as artifact that's already materialized.
         let mut path_iter = path.iter();
+        let mut superseded = false;
         if let Some(data) = self.tree.prefix_get_mut(&mut path_iter) {
             match &data.stage {
                 ArtifactMaterializationStage::Materialized {
@@ -1594,9 +2298,8 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                     )
                     .unwrap();
 
-                    if path_iter.next().is_none()
-                        && metadata.matches_entry(value.entry())
-                        && !force_mismatch
+                    let is_artifact_path = path_iter.next().is_none();
+                    if is_artifact_path && metadata.matches_entry(value.entry()) && !force_mismatch
                     {
                         // In this case, the entry declared matches the already materialized
                         // entry on disk, so just update the deps field but leave
@@ -1610,6 +2313,10 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                             metadata: metadata.dupe(),
                             last_access_time: *last_access_time,
                             active: true,
+                            declared: Some(Box::new(DeclaredArtifact {
+                                entry: value.entry().dupe(),
+                                method: Arc::from(method),
+                            })),
                         };
                         data.deps = deps;
 
@@ -1617,6 +2324,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
 
                         return;
                     }
+                    superseded = is_artifact_path;
                 }
                 _ => {}
             }
@@ -1639,6 +2347,9 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
         let existing_futs = self
             .tree
             .invalidate_paths_and_collect_futures(vec![path.to_owned()], self.sqlite_db.as_mut());
+        if superseded {
+            self.subscriptions.on_invalidated(path);
+        }
 
         let existing_futs = ExistingFutures(existing_futs);
 
@@ -1655,9 +2366,11 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
 
         let future = match &*method {
             ArtifactMaterializationMethod::Write(write) if can_use_write_fast_path => {
+                let link_from = self.hardlink_source(path, write, value.entry());
                 let materialize = self.io.write(
                     path.to_owned(),
                     write.dupe(),
+                    link_from,
                     version,
                     self.command_sender.dupe(),
                     self.cancellations,
@@ -1682,10 +2395,42 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                 method,
             },
             processing: Processing::Active { future, version },
+            failures: None,
         });
         self.tree.insert(path.iter().map(|f| f.to_owned()), data);
     }
 
+    /// If enabled, find a path where the contents of `write` are materialized, to hardlink `path`
+    /// from it rather than writing them again. The path is only used if the tree still has the
+    /// same file materialized there and nothing is processing it.
+    fn hardlink_source(
+        &self,
+        path: &ProjectRelativePath,
+        write: &WriteFile,
+        entry: &ActionDirectoryEntry<ActionSharedDirectory>,
+    ) -> Option<ProjectRelativePathBuf> {
+        if !self.hardlink_deduplicated_writes {
+            return None;
+        }
+        let source = write.materialized_at.lock().clone()?;
+        if !self.io.roots().can_hardlink(&source, path) {
+            return None;
+        }
+        let mut path_iter = source.iter();
+        let data = self.tree.prefix_get(&mut path_iter)?;
+        if path_iter.next().is_some() {
+            return None;
+        }
+        match (&data.stage, &data.processing) {
+            (ArtifactMaterializationStage::Materialized { metadata, .. }, Processing::Done(_))
+                if metadata.matches_entry(entry) =>
+            {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+
     /// Check if artifact to be declared is same as artifact that's already materialized.
     #[instrument(level = "debug", skip(self), fields(path = %path, value = %value.entry()))]
     fn match_artifact(&mut self, path: ProjectRelativePathBuf, value: ArtifactValue) -> bool {
@@ -1805,6 +2550,13 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
         let check_deps = deps.is_some();
         let entry_and_method = match &mut data.stage {
             ArtifactMaterializationStage::Declared { entry, method } => {
+                if let ArtifactMaterializationMethod::MismatchedExisting { found } = &**method {
+                    return Err(MismatchedExistingError {
+                        path: path.to_buf(),
+                        found: found.clone(),
+                    }
+                    .into());
+                }
                 Some((entry.dupe(), method.dupe()))
             }
             ArtifactMaterializationStage::Materialized {
@@ -1815,7 +2567,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                 false => {
                     if let Some(ref mut buffer) = self.access_times_buffer.as_mut() {
                         // TODO (torozco): Why is it legal for something to be Materialized + Cleaning?
-                        let timestamp = Utc::now();
+                        let timestamp = clock_now();
                         *last_access_time = timestamp;
 
                         // NOTE (T142264535): We mostly expect that artifacts are always declared
@@ -1840,6 +2592,15 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
             },
         };
 
+        // A local action may be reading this artifact through a named pipe, which must be gone
+        // before the artifact is written in its place.
+        let streaming_fut = match entry_and_method.as_ref().map(|(_, m)| m.as_ref()) {
+            Some(ArtifactMaterializationMethod::Streamed { finished, .. }) => {
+                Some(finished.clone())
+            }
+            _ => None,
+        };
+
         let version = self.version_tracker.next();
 
         tracing::debug!(
@@ -1855,8 +2616,10 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
         let deps_tasks = match entry_and_method.as_ref() {
             Some((_, m)) => match m.as_ref() {
                 ArtifactMaterializationMethod::CasDownload { .. }
+                | ArtifactMaterializationMethod::Streamed { .. }
                 | ArtifactMaterializationMethod::HttpDownload { .. }
-                | ArtifactMaterializationMethod::Write { .. } => Vec::new(),
+                | ArtifactMaterializationMethod::Write { .. }
+                | ArtifactMaterializationMethod::MismatchedExisting { .. } => Vec::new(),
                 ArtifactMaterializationMethod::LocalCopy(_, copied_artifacts) => copied_artifacts
                     .iter()
                     .filter_map(|a| {
@@ -1896,6 +2659,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
         let path_buf_dup = path_buf.clone();
         let io = self.io.dupe();
         let command_sender = self.command_sender.dupe();
+        let materialization_permits = self.materialization_permits.dupe();
         let task = self
             .spawn(async move {
                 let cancellations = CancellationContext::never_cancelled(); // spawned
@@ -1903,7 +2667,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                 // Materialize the deps and this entry. This *must* happen in a try block because we
                 // need to notify the materializer regardless of whether this succeeds or fails.
 
-                let timestamp = Utc::now();
+                let timestamp = clock_now();
                 let res: Result<(), SharedMaterializingError> = try {
                     // If there is an existing future trying to delete conflicting paths, we must wait for it
                     // to finish before we can start materialization.
@@ -1917,6 +2681,10 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                         .map_err(|e| SharedMaterializingError::Error(e.into()))?;
                     };
 
+                    if let Some(streaming_fut) = streaming_fut {
+                        streaming_fut.wait().await;
+                    }
+
                     // In case this is a local copy, we first need to materialize the
                     // artifacts we are copying from, before we can copy them.
                     for t in deps_tasks {
@@ -1924,7 +2692,15 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                     }
 
                     if let Some((entry, method)) = entry_and_method {
-                        let materialize = || {
+                        let materialize = || async {
+                            // The permit is only held while this entry is materialized, and never
+                            // while waiting on deps, which may need permits of their own.
+                            let _permit = match &materialization_permits {
+                                Some(permits) => Some(
+                                    permits.acquire().await.expect("Semaphore is never closed"),
+                                ),
+                                None => None,
+                            };
                             io.materialize_entry(
                                 path_buf.clone(),
                                 method,
@@ -1932,6 +2708,7 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                                 event_dispatcher.dupe(),
                                 cancellations,
                             )
+                            .await
                         };
 
                         // Windows symlinks need to be specified whether it is to a file or target. We rely on the
@@ -2012,6 +2789,16 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                         }
                         ArtifactMaterializationStage::Declared { .. } => {
                             tracing::debug!("materialization failed, redeclaring artifact");
+                            if let Err(e) = &result {
+                                let failures = info.failures.get_or_insert_with(|| {
+                                    Box::new(MaterializationFailures {
+                                        count: 0,
+                                        last_error: String::new(),
+                                    })
+                                });
+                                failures.count += 1;
+                                failures.last_error = e.to_string();
+                            }
                             // Even though materialization failed, something may have still materialized at artifact_path,
                             // so we need to delete anything at artifact_path before we ever retry materializing it.
                             // TODO(scottcao): Once command processor accepts an ArtifactTree instead of initializing one,
@@ -2038,10 +2825,10 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                             tracing::debug!("artifact is already materialized");
                             None
                         }
-                        ArtifactMaterializationStage::Declared {
-                            entry,
-                            method: _method,
-                        } => {
+                        ArtifactMaterializationStage::Declared { entry, method } => {
+                            if let ArtifactMaterializationMethod::Write(write) = &**method {
+                                *write.materialized_at.lock() = Some(artifact_path.clone());
+                            }
                             let metadata = ArtifactMetadata::new(entry);
                             // NOTE: We only insert this artifact if there isn't an in-progress cleanup
                             // future on this path.
@@ -2059,6 +2846,10 @@ impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
                                 metadata,
                                 last_access_time: timestamp,
                                 active: true,
+                                declared: Some(Box::new(DeclaredArtifact {
+                                    entry: entry.dupe(),
+                                    method: method.dupe(),
+                                })),
                             })
                         }
                     };
@@ -2099,10 +2890,7 @@ fn on_materialization(
     error_name: &'static str,
 ) {
     if let Some(sqlite_db) = sqlite_db {
-        if let Err(e) = sqlite_db
-            .materializer_state_table()
-            .insert(path, metadata, timestamp)
-        {
+        if let Err(e) = sqlite_db.insert(path, metadata, timestamp) {
             soft_error!(error_name, e.context(log_buffer.clone()), quiet: true).unwrap();
         }
     }
@@ -2123,8 +2911,10 @@ impl ArtifactTree {
                             metadata,
                             last_access_time,
                             active: false,
+                            declared: None,
                         },
                         processing: Processing::Done(Version(0)),
+                        failures: None,
                     }),
                 );
             }
@@ -2158,7 +2948,8 @@ impl ArtifactTree {
             }
         };
         match method.as_ref() {
-            ArtifactMaterializationMethod::CasDownload { info } => {
+            ArtifactMaterializationMethod::CasDownload { info }
+            | ArtifactMaterializationMethod::Streamed { info, .. } => {
                 let path_iter = path_iter.peekable();
 
                 let root_entry = entry.dupe();
@@ -2194,7 +2985,8 @@ impl ArtifactTree {
                 }
             }
             ArtifactMaterializationMethod::HttpDownload { .. }
-            | ArtifactMaterializationMethod::Write { .. } => {
+            | ArtifactMaterializationMethod::Write { .. }
+            | ArtifactMaterializationMethod::MismatchedExisting { .. } => {
                 // TODO: Do the write directly to RE instead of materializing locally?
                 Err(ArtifactNotMaterializedReason::RequiresMaterialization { path })
             }
@@ -2220,12 +3012,13 @@ impl ArtifactTree {
     }
 
     #[instrument(level = "debug", skip(self, result), fields(path = %artifact_path))]
+    /// Returns whether the cleanup of the current version of the artifact succeeded.
     fn cleanup_finished(
         &mut self,
-        artifact_path: ProjectRelativePathBuf,
+        artifact_path: &ProjectRelativePath,
         version: Version,
         result: Result<(), SharedMaterializingError>,
-    ) {
+    ) -> bool {
         match self
             .prefix_get_mut(&mut artifact_path.iter())
             .context("Path is vacant")
@@ -2235,18 +3028,21 @@ impl ArtifactTree {
                     // We can only unset the future if version matches.
                     // Otherwise, we may be unsetting a different future from a newer version.
                     tracing::debug!("version conflict");
-                    return;
+                    return false;
                 }
 
                 if result.is_err() {
                     // Leave it alone, don't keep retrying.
+                    false
                 } else {
                     info.processing = Processing::Done(version);
+                    true
                 }
             }
             Err(e) => {
                 // NOTE: This shouldn't normally happen?
                 soft_error!("cleanup_finished_vacant", e, quiet: true).unwrap();
+                false
             }
         }
     }
@@ -2285,7 +3081,6 @@ impl ArtifactTree {
         // number.
         if let Some(sqlite_db) = sqlite_db {
             sqlite_db
-                .materializer_state_table()
                 .delete(invalidated_paths)
                 .context("Error invalidating paths in materializer state")?;
         }
@@ -2444,11 +3239,41 @@ impl ExistingFutures {
     }
 }
 
+/// The contents of a declared write. Shared by all the writes of identical files, see
+/// `WriteDedupIndex`.
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct WriteFile {
     #[derivative(Debug = "ignore")]
     compressed_data: Box<[u8]>,
     decompressed_size: usize,
+    /// Digest of the decompressed contents, to check that a file to hardlink has them.
+    digest: TrackedFileDigest,
     is_executable: bool,
+    /// The last path these contents were materialized at, if any. This is only a hint: the
+    /// path may have been cleaned or overwritten since.
+    #[derivative(Debug = "ignore")]
+    materialized_at: Mutex<Option<ProjectRelativePathBuf>>,
+}
+
+impl WriteFile {
+    fn compress(content: &[u8], metadata: &FileMetadata) -> anyhow::Result<Self> {
+        // NOTE: The zstd crate doesn't release extra capacity of its encoding buffer so it's
+        // important to do so here (or the compressed Vec is the same capacity as the input!).
+        let compressed_data = zstd::bulk::compress(content, 0)
+            .with_context(|| format!("Error compressing {} bytes", content.len()))?
+            .into_boxed_slice();
+        Ok(Self {
+            compressed_data,
+            decompressed_size: content.len(),
+            digest: metadata.digest.dupe(),
+            is_executable: metadata.is_executable,
+            materialized_at: Mutex::new(None),
+        })
+    }
+
+    fn decompress(&self) -> anyhow::Result<Vec<u8>> {
+        zstd::bulk::decompress(&self.compressed_data, self.decompressed_size)
+            .context("Error decompressing data")
+    }
 }
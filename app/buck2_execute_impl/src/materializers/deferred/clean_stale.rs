@@ -50,6 +50,7 @@ use crate::materializers::deferred::ArtifactMaterializationData;
 use crate::materializers::deferred::ArtifactMaterializationStage;
 use crate::materializers::deferred::ArtifactTree;
 use crate::materializers::deferred::DeferredMaterializerCommandProcessor;
+use crate::materializers::deferred::Processing;
 use crate::materializers::sqlite::MaterializerStateSqliteDb;
 
 #[derive(Debug, Clone)]
@@ -112,6 +113,7 @@ impl From<CleanResult> for buck2_cli_proto::CleanStaleResponse {
         Self {
             message: message.map(|m| m.to_owned()),
             stats: Some(result.stats),
+            compaction: None,
         }
     }
 }
@@ -210,9 +212,12 @@ impl CleanStaleArtifactsCommand {
     ) -> anyhow::Result<PendingCleanResult> {
         let start_time = Instant::now();
         let gen_path = io
+            .roots()
             .buck_out_path()
             .join(ProjectRelativePathBuf::unchecked_new("gen".to_owned()));
-        let gen_dir = io.fs().resolve(&gen_path);
+        // Scan where buck-out actually is, so that the paths found are the same whether or not
+        // buck-out is a symlink.
+        let gen_dir = io.roots().physical_path(&gen_path);
         if !fs_util::try_exists(&gen_dir)? {
             return Ok(CleanStaleResultKind::SkippedNoGenDir.into());
         }
@@ -274,6 +279,7 @@ impl CleanStaleArtifactsCommand {
         if stats.stale_artifact_count + stats.retained_artifact_count == 0 {
             // Just need to know if any entries exist, could be a simpler query.
             // Checking the db directly in case tree is somehow not in sync.
+            sqlite_db.flush_inserts()?;
             let materializer_state = sqlite_db
                 .materializer_state_table()
                 .read_all(io.digest_config())?;
@@ -424,7 +430,7 @@ async fn clean_artifact<T: IoHandler>(
 }
 
 pub struct CleanInvalidatedPathRequest {
-    path: ProjectRelativePathBuf,
+    pub(crate) path: ProjectRelativePathBuf,
     pub(crate) liveliness_observer: Arc<dyn LivelinessObserverSync>,
 }
 
@@ -500,7 +506,7 @@ impl<'a, T: IoHandler> StaleFinder<'a, T> {
             &'t HashMap<FileNameBuf, ArtifactTree>,
         )>,
     ) -> anyhow::Result<()> {
-        let abs_path = self.io.fs().resolve(path);
+        let abs_path = self.io.roots().physical_path(path);
 
         for child in self.io.read_dir(&abs_path)? {
             let child = child?;
@@ -545,7 +551,10 @@ impl<'a, T: IoHandler> StaleFinder<'a, T> {
                             active: false,
                             last_access_time,
                             metadata,
+                            ..
                         },
+                    // Something may still be reading or replacing it.
+                    processing: Processing::Done(_),
                     ..
                 }) if *last_access_time < self.keep_since_time => {
                     // This is something we can invalidate.
@@ -585,7 +594,8 @@ fn find_stale_tracked_only(
         } = &v.stage
         {
             let path = ProjectRelativePathBuf::from(f_path);
-            if *last_access_time < keep_since_time && !active {
+            let processing = matches!(v.processing, Processing::Active { .. });
+            if *last_access_time < keep_since_time && !active && !processing {
                 tracing::trace!(path = %path, "stale artifact");
                 found_paths.push(FoundPath::Stale(path, 0));
             } else {
@@ -0,0 +1,274 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Parallel removal of large trees when cleaning paths.
+//!
+//! Removing a tree with hundreds of thousands of files on a single thread takes minutes, during
+//! which nothing can be materialized at that path. Instead, the entries at the top of the tree are
+//! removed as separate IO requests, several at a time.
+
+use buck2_core::buck2_env;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_execute::execute::blocking::BlockingExecutor;
+use buck2_execute::execute::blocking::IoRequest;
+use buck2_execute::execute::blocking::IoTag;
+use buck2_futures::cancellation::CancellationContext;
+use futures::stream;
+use futures::stream::TryStreamExt;
+
+/// How many entries of a tree are removed at once.
+pub(crate) fn clean_path_parallelism() -> anyhow::Result<usize> {
+    buck2_env!("BUCK2_CLEAN_PATH_PARALLELISM", type=usize, default=8)
+}
+
+struct RemoveEntryIoRequest {
+    path: AbsNormPathBuf,
+}
+
+impl IoRequest for RemoveEntryIoRequest {
+    fn execute(self: Box<Self>, _project_fs: &ProjectRoot) -> anyhow::Result<()> {
+        // This doesn't follow symlinks: a symlink is removed, not what it points to.
+        fs_util::remove_all(&self.path)?;
+        Ok(())
+    }
+}
+
+fn list_dir(path: &AbsNormPath) -> anyhow::Result<Vec<AbsNormPathBuf>> {
+    // Only list actual directories, not symlinks to them. Errors are left to `cleanup_path`,
+    // which deals with paths that are below files.
+    match fs_util::symlink_metadata_if_exists(path) {
+        Ok(Some(metadata)) if metadata.is_dir() => {}
+        _ => return Ok(Vec::new()),
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs_util::read_dir(path)? {
+        entries.push(entry?.path());
+    }
+    Ok(entries)
+}
+
+/// Removes everything in the directory `path`, removing up to `parallelism` of its entries at
+/// once. The directory itself is kept. Does nothing if `path` is not a directory, including when
+/// it is a symlink to one.
+///
+/// Stops at the first error.
+pub(crate) async fn remove_dir_contents(
+    io_executor: &dyn BlockingExecutor,
+    path: &AbsNormPath,
+    parallelism: usize,
+    cancellations: &CancellationContext<'_>,
+) -> anyhow::Result<()> {
+    let entries = io_executor
+        .execute_io_inline(IoTag::Clean, || list_dir(path))
+        .await?;
+
+    stream::iter(entries.into_iter().map(anyhow::Ok))
+        .try_for_each_concurrent(parallelism.max(1), |path| {
+            io_executor.execute_io(
+                IoTag::Clean,
+                Box::new(RemoveEntryIoRequest { path }),
+                cancellations,
+            )
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use allocative::Allocative;
+    use async_trait::async_trait;
+    use buck2_core::fs::fs_util;
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+    use buck2_core::fs::project::ProjectRoot;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePath;
+    use buck2_execute::execute::blocking::testing::DummyBlockingExecutor;
+    use buck2_execute::execute::blocking::BlockingExecutor;
+    use buck2_execute::execute::blocking::IoRequest;
+    use buck2_execute::execute::blocking::IoTag;
+    use buck2_execute::execute::blocking::IoTagStats;
+    use buck2_futures::cancellation::CancellationContext;
+    use dupe::Dupe;
+    use futures::future::BoxFuture;
+    use futures::FutureExt;
+
+    use crate::materializers::deferred::clean_tree::remove_dir_contents;
+
+    fn resolve(fs: &ProjectRoot, path: &str) -> AbsNormPathBuf {
+        fs.resolve(ProjectRelativePath::unchecked_new(path))
+    }
+
+    fn write(fs: &ProjectRoot, path: &str) -> anyhow::Result<()> {
+        let path = resolve(fs, path);
+        fs_util::create_dir_all(path.parent().unwrap())?;
+        fs_util::write(&path, "contents")?;
+        Ok(())
+    }
+
+    fn is_empty_dir(fs: &ProjectRoot, path: &str) -> anyhow::Result<bool> {
+        Ok(fs_util::read_dir(resolve(fs, path))?.next().is_none())
+    }
+
+    #[tokio::test]
+    async fn test_deep_tree() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        for i in 0..10 {
+            let mut dir = format!("out/dir{}", i);
+            for depth in 0..20 {
+                write(fs, &format!("{}/file{}", dir, depth))?;
+                dir = format!("{}/sub", dir);
+            }
+        }
+        write(fs, "out/file")?;
+        write(fs, "other/file")?;
+
+        let executor = DummyBlockingExecutor { fs: fs.dupe() };
+        remove_dir_contents(
+            &executor,
+            &resolve(fs, "out"),
+            3,
+            CancellationContext::testing(),
+        )
+        .await?;
+
+        assert!(is_empty_dir(fs, "out")?);
+        assert!(fs_util::try_exists(resolve(fs, "other/file"))?);
+
+        // Missing paths and files are fine.
+        remove_dir_contents(
+            &executor,
+            &resolve(fs, "missing"),
+            3,
+            CancellationContext::testing(),
+        )
+        .await?;
+        remove_dir_contents(
+            &executor,
+            &resolve(fs, "other/file"),
+            3,
+            CancellationContext::testing(),
+        )
+        .await?;
+        assert!(fs_util::try_exists(resolve(fs, "other/file"))?);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinks_are_not_followed() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        write(fs, "outside/dir/file")?;
+        write(fs, "out/dir/file")?;
+        fs_util::symlink(resolve(fs, "outside"), resolve(fs, "out/link"))?;
+        fs_util::symlink(resolve(fs, "outside/dir"), resolve(fs, "out/dir/link"))?;
+        fs_util::symlink(resolve(fs, "outside"), resolve(fs, "out_link"))?;
+
+        let executor = DummyBlockingExecutor { fs: fs.dupe() };
+        remove_dir_contents(
+            &executor,
+            &resolve(fs, "out"),
+            2,
+            CancellationContext::testing(),
+        )
+        .await?;
+        assert!(is_empty_dir(fs, "out")?);
+        assert!(fs_util::try_exists(resolve(fs, "outside/dir/file"))?);
+
+        // A symlink to a directory is not a directory whose contents should be removed.
+        remove_dir_contents(
+            &executor,
+            &resolve(fs, "out_link"),
+            2,
+            CancellationContext::testing(),
+        )
+        .await?;
+        assert!(fs_util::try_exists(resolve(fs, "outside/dir/file"))?);
+        Ok(())
+    }
+
+    /// Fails the `fail_at`-th IO request, and runs the others.
+    #[derive(Allocative)]
+    struct FailingExecutor {
+        inner: DummyBlockingExecutor,
+        fail_at: usize,
+        #[allocative(skip)]
+        requests: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl BlockingExecutor for FailingExecutor {
+        async fn execute_dyn_io_inline<'a>(
+            &self,
+            tag: IoTag,
+            f: Box<dyn FnOnce() -> anyhow::Result<()> + Send + 'a>,
+        ) -> anyhow::Result<()> {
+            self.inner.execute_dyn_io_inline(tag, f).await
+        }
+
+        fn execute_io<'a>(
+            &self,
+            tag: IoTag,
+            io: Box<dyn IoRequest>,
+            cancellations: &'a CancellationContext,
+        ) -> BoxFuture<'a, anyhow::Result<()>> {
+            if self.requests.fetch_add(1, Ordering::SeqCst) == self.fail_at {
+                return futures::future::ready(Err(anyhow::anyhow!("Injected failure"))).boxed();
+            }
+            self.inner.execute_io(tag, io, cancellations)
+        }
+
+        fn queue_size(&self) -> usize {
+            0
+        }
+
+        fn tag_stats(&self, _tag: IoTag) -> IoTagStats {
+            IoTagStats::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_is_propagated() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        for i in 0..5 {
+            write(fs, &format!("out/dir{}/file", i))?;
+        }
+
+        let executor = FailingExecutor {
+            inner: DummyBlockingExecutor { fs: fs.dupe() },
+            fail_at: 2,
+            requests: AtomicUsize::new(0),
+        };
+        let err = remove_dir_contents(
+            &executor,
+            &resolve(fs, "out"),
+            1,
+            CancellationContext::testing(),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Injected failure"),
+            "{:#}",
+            err
+        );
+        // Removal stopped at the failure.
+        assert!(!is_empty_dir(fs, "out")?);
+        Ok(())
+    }
+}
@@ -23,6 +23,9 @@ use buck2_execute::directory::ActionDirectoryMember;
 use buck2_execute::materialize::materializer::DeferredMaterializerEntry;
 use buck2_execute::materialize::materializer::DeferredMaterializerExtensions;
 use buck2_execute::materialize::materializer::DeferredMaterializerSubscription;
+use buck2_execute::materialize::materializer::MaterializationStage;
+use buck2_execute::materialize::materializer::MaterializationStatus;
+use buck2_execute::materialize::materializer::MaterializerVerifyReport;
 use chrono::DateTime;
 use chrono::Duration;
 use chrono::TimeZone;
@@ -31,6 +34,7 @@ use derivative::Derivative;
 use dupe::Dupe;
 use futures::stream::BoxStream;
 use futures::stream::StreamExt;
+use gazebo::prelude::VecExt;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot;
@@ -43,6 +47,9 @@ use crate::materializers::deferred::clean_stale::CleanStaleArtifactsExtensionCom
 use crate::materializers::deferred::io_handler::create_ttl_refresh;
 use crate::materializers::deferred::io_handler::IoHandler;
 use crate::materializers::deferred::subscriptions::MaterializerSubscriptionOperation;
+use crate::materializers::deferred::verify::InvalidateMismatchesCommand;
+use crate::materializers::deferred::verify::VerifyArtifactsCommand;
+use crate::materializers::deferred::ArtifactMaterializationData;
 use crate::materializers::deferred::ArtifactMaterializationMethod;
 use crate::materializers::deferred::ArtifactMaterializationStage;
 use crate::materializers::deferred::DeferredMaterializerAccessor;
@@ -59,6 +66,8 @@ pub(super) trait ExtensionCommand<T>: Debug + Sync + Send + 'static {
 struct PathData {
     stage: PathStage,
     processing: PathProcessing,
+    /// How many times materializing the path failed, and the last error.
+    failures: Option<(u64, String)>,
 }
 
 #[derive(Debug)]
@@ -102,6 +111,10 @@ impl Display for PathData {
             }
         }
 
+        if let Some((count, last_error)) = &self.failures {
+            write!(f, " (failed {} time(s): {})", count, last_error)?;
+        }
+
         Ok(())
     }
 }
@@ -162,7 +175,16 @@ impl<T: IoHandler> ExtensionCommand<T> for Iterate {
                 } => PathProcessing::Cleaning,
             };
 
-            let path_data = PathData { stage, processing };
+            let failures = data
+                .failures
+                .as_ref()
+                .map(|f| (f.count, f.last_error.clone()));
+
+            let path_data = PathData {
+                stage,
+                processing,
+                failures,
+            };
 
             let path = ProjectRelativePathBuf::from(path);
 
@@ -345,6 +367,63 @@ impl<T: IoHandler> ExtensionCommand<T> for FlushAccessTimes {
     }
 }
 
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub(super) struct GetMaterializationStatus {
+    pub(super) paths: Vec<ProjectRelativePathBuf>,
+    #[derivative(Debug = "ignore")]
+    pub(super) sender: Sender<Vec<(ProjectRelativePathBuf, Option<MaterializationStatus>)>>,
+}
+
+impl<T> ExtensionCommand<T> for GetMaterializationStatus {
+    fn execute(self: Box<Self>, processor: &mut DeferredMaterializerCommandProcessor<T>) {
+        let statuses = self.paths.into_map(|path| {
+            let status = processor
+                .tree
+                .prefix_get(&mut path.iter())
+                .map(|data| materialization_status(data));
+            (path, status)
+        });
+        let _ignored = self.sender.send(statuses);
+    }
+}
+
+fn materialization_status(data: &ArtifactMaterializationData) -> MaterializationStatus {
+    let (stage, method) = match &data.stage {
+        ArtifactMaterializationStage::Declared { method, .. } => {
+            let stage = match &data.processing {
+                Processing::Active {
+                    future: ProcessingFuture::Materializing(..),
+                    ..
+                } => MaterializationStage::Materializing,
+                _ if data.failures.is_some() => MaterializationStage::Failed,
+                _ => MaterializationStage::Declared,
+            };
+            (stage, Some(method.to_string()))
+        }
+        ArtifactMaterializationStage::Materialized { .. } => {
+            (MaterializationStage::Materialized, None)
+        }
+    };
+    MaterializationStatus {
+        stage,
+        method,
+        failed_attempts: data.failures.as_ref().map_or(0, |f| f.count),
+        last_error: data.failures.as_ref().map(|f| f.last_error.clone()),
+    }
+}
+
+#[derive(Debug)]
+struct CompactSqliteDb {
+    sender: Sender<anyhow::Result<buck2_data::SqliteVacuum>>,
+}
+
+impl<T: IoHandler> ExtensionCommand<T> for CompactSqliteDb {
+    fn execute(self: Box<Self>, processor: &mut DeferredMaterializerCommandProcessor<T>) {
+        let _ignored = self.sender.send(processor.compact_sqlite_db());
+    }
+}
+
 #[async_trait]
 impl<T: IoHandler> DeferredMaterializerExtensions for DeferredMaterializerAccessor<T> {
     fn iterate(
@@ -442,6 +521,69 @@ impl<T: IoHandler> DeferredMaterializerExtensions for DeferredMaterializerAccess
         receiver.await.context("No response from materializer")
     }
 
+    async fn compact_db(&self) -> anyhow::Result<buck2_data::SqliteVacuum> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(MaterializerCommand::Extension(
+                Box::new(CompactSqliteDb { sender }) as _,
+            ))?;
+        let res = receiver.await.context("No response from materializer")??;
+        get_dispatcher().instant_event(res.clone());
+        Ok(res)
+    }
+
+    async fn materialization_status(
+        &self,
+        paths: Vec<ProjectRelativePathBuf>,
+    ) -> anyhow::Result<Vec<(ProjectRelativePathBuf, Option<MaterializationStatus>)>> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender
+            .send(MaterializerCommand::Extension(
+                Box::new(GetMaterializationStatus { paths, sender }) as _,
+            ))?;
+        receiver.await.context("No response from materializer")
+    }
+
+    async fn verify(
+        &self,
+        paths: Vec<ProjectRelativePathBuf>,
+        invalidate: bool,
+    ) -> anyhow::Result<MaterializerVerifyReport> {
+        let (sender, receiver) = oneshot::channel();
+        self.command_sender.send(MaterializerCommand::Extension(
+            Box::new(VerifyArtifactsCommand { paths, sender }) as _,
+        ))?;
+        let outcome = receiver
+            .await
+            .context("No response from materializer")?
+            .await
+            .context("Verify task aborted")?;
+
+        let mut invalidated = Vec::new();
+        if invalidate && !outcome.mismatches.is_empty() {
+            let (sender, receiver) = oneshot::channel();
+            let mismatches = outcome
+                .mismatches
+                .iter()
+                .map(|(version, mismatch)| (mismatch.path.clone(), *version))
+                .collect();
+            self.command_sender
+                .send(MaterializerCommand::Extension(
+                    Box::new(InvalidateMismatchesCommand { mismatches, sender }) as _,
+                ))?;
+            invalidated = receiver
+                .await
+                .context("No response from materializer")?
+                .context("Failed to invalidate mismatched artifacts")?;
+        }
+
+        Ok(MaterializerVerifyReport {
+            checked: outcome.checked,
+            mismatches: outcome.mismatches.into_map(|(_, mismatch)| mismatch),
+            invalidated,
+        })
+    }
+
     async fn create_subscription(
         &self,
     ) -> anyhow::Result<Box<dyn DeferredMaterializerSubscription>> {
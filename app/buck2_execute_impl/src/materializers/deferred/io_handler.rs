@@ -16,14 +16,19 @@ use allocative::Allocative;
 use anyhow::Context;
 use async_trait::async_trait;
 use buck2_common::file_ops::FileDigest;
+use buck2_common::file_ops::FileDigestConfig;
+use buck2_common::file_ops::TrackedFileDigest;
+use buck2_common::http::EventDispatcherDownloadProgress;
 use buck2_core::buck2_env;
 use buck2_core::directory::unordered_entry_walk;
 use buck2_core::directory::DirectoryEntry;
+use buck2_core::execution_types::executor_config::RemoteExecutorUseCase;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::fs_util::IoError;
 use buck2_core::fs::fs_util::ReadDir;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
 use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_execute::artifact_value::ArtifactValue;
@@ -35,13 +40,16 @@ use buck2_execute::directory::ActionDirectoryMember;
 use buck2_execute::directory::ActionSharedDirectory;
 use buck2_execute::execute::blocking::BlockingExecutor;
 use buck2_execute::execute::blocking::IoRequest;
+use buck2_execute::execute::blocking::IoTag;
 use buck2_execute::execute::clean_output_paths::cleanup_path;
 use buck2_execute::materialize::http::http_download;
 use buck2_execute::materialize::materializer::WriteRequest;
+use buck2_execute::materialize::physical_roots::PhysicalRoots;
 use buck2_execute::output_size::OutputSize;
 use buck2_execute::re::manager::ReConnectionManager;
 use buck2_futures::cancellation::CancellationContext;
 use buck2_http::HttpClient;
+use chrono::DateTime;
 use chrono::Duration;
 use chrono::Utc;
 use dupe::Dupe;
@@ -58,7 +66,13 @@ use remote_execution::TCode;
 use remote_execution::TDigest;
 use tracing::instrument;
 
+use crate::materializers::clock_now;
 use crate::materializers::deferred::clean_stale::CleanInvalidatedPathRequest;
+use crate::materializers::deferred::clean_tree::clean_path_parallelism;
+use crate::materializers::deferred::clean_tree::remove_dir_contents;
+use crate::materializers::deferred::streamed_input::StreamedInput;
+use crate::materializers::deferred::streamed_input::StreamedInputSource;
+use crate::materializers::deferred::verify::entry_from_disk;
 use crate::materializers::deferred::ArtifactMaterializationMethod;
 use crate::materializers::deferred::ArtifactMaterializationStage;
 use crate::materializers::deferred::ArtifactTree;
@@ -66,6 +80,7 @@ use crate::materializers::deferred::LowPriorityMaterializerCommand;
 use crate::materializers::deferred::MaterializationMethodToProto;
 use crate::materializers::deferred::MaterializeEntryError;
 use crate::materializers::deferred::MaterializerSender;
+use crate::materializers::deferred::MismatchedExistingError;
 use crate::materializers::deferred::SharedMaterializingError;
 use crate::materializers::deferred::Version;
 use crate::materializers::deferred::WriteFile;
@@ -77,7 +92,7 @@ use crate::materializers::io::MaterializeTreeStructure;
 pub struct DefaultIoHandler {
     fs: ProjectRoot,
     digest_config: DigestConfig,
-    buck_out_path: ProjectRelativePathBuf,
+    roots: PhysicalRoots,
     re_client_manager: Arc<ReConnectionManager>,
     /// Executor for blocking IO operations
     io_executor: Arc<dyn BlockingExecutor>,
@@ -95,6 +110,7 @@ pub trait IoHandler: Sized + Sync + Send + 'static {
         self: &Arc<Self>,
         path: ProjectRelativePathBuf,
         write: Arc<WriteFile>,
+        link_from: Option<ProjectRelativePathBuf>,
         version: Version,
         command_sender: MaterializerSender<Self>,
         cancellations: &'a CancellationContext<'a>,
@@ -134,8 +150,26 @@ pub trait IoHandler: Sized + Sync + Send + 'static {
         min_ttl: Duration,
     ) -> Option<BoxFuture<'static, anyhow::Result<()>>>;
 
+    /// Hash whatever is on disk at `path`, `None` if there's nothing there.
+    async fn read_entry_from_disk(
+        self: &Arc<Self>,
+        path: &ProjectRelativePath,
+    ) -> anyhow::Result<Option<ActionDirectoryEntry<ActionSharedDirectory>>>;
+
+    /// Experimental: expose `path` as a named pipe that streams `source` when a local action
+    /// opens it, instead of materializing it.
+    async fn stream_input(
+        self: &Arc<Self>,
+        path: &ProjectRelativePath,
+        source: Arc<dyn StreamedInputSource>,
+        is_executable: bool,
+    ) -> anyhow::Result<StreamedInput> {
+        StreamedInput::create(self.fs().resolve(path), source, is_executable).await
+    }
+
     fn read_dir(&self, path: &AbsNormPathBuf) -> Result<ReadDir, IoError>;
-    fn buck_out_path(&self) -> &ProjectRelativePathBuf;
+    /// Where the project root and `buck-out` are on disk, resolved when the materializer starts.
+    fn roots(&self) -> &PhysicalRoots;
     fn re_client_manager(&self) -> &Arc<ReConnectionManager>;
     fn fs(&self) -> &ProjectRoot;
     fn digest_config(&self) -> DigestConfig;
@@ -145,7 +179,7 @@ impl DefaultIoHandler {
     pub fn new(
         fs: ProjectRoot,
         digest_config: DigestConfig,
-        buck_out_path: ProjectRelativePathBuf,
+        roots: PhysicalRoots,
         re_client_manager: Arc<ReConnectionManager>,
         io_executor: Arc<dyn BlockingExecutor>,
         http_client: HttpClient,
@@ -153,25 +187,27 @@ impl DefaultIoHandler {
         Self {
             fs,
             digest_config,
-            buck_out_path,
+            roots,
             re_client_manager,
             io_executor,
             http_client,
         }
     }
     /// Materializes an `entry` at `path`, using the materialization `method`
-    #[instrument(level = "debug", skip(self, stat, cancellations), fields(path = %path, method = %method, entry = %entry))]
+    #[instrument(level = "debug", skip(self, event_dispatcher, stat, cancellations), fields(path = %path, method = %method, entry = %entry))]
     async fn materialize_entry_span(
         &self,
         path: ProjectRelativePathBuf,
         method: Arc<ArtifactMaterializationMethod>,
         entry: ActionDirectoryEntry<ActionSharedDirectory>,
+        event_dispatcher: &EventDispatcher,
         stat: &mut MaterializationStat,
         cancellations: &CancellationContext<'_>,
     ) -> Result<(), MaterializeEntryError> {
         // Materialize the dir structure, and symlinks
         self.io_executor
             .execute_io(
+                IoTag::MaterializeRead,
                 Box::new(MaterializeTreeStructure {
                     path: path.clone(),
                     entry: entry.dupe(),
@@ -182,7 +218,8 @@ impl DefaultIoHandler {
 
         // Materialize files
         match method.as_ref() {
-            ArtifactMaterializationMethod::CasDownload { info } => {
+            ArtifactMaterializationMethod::CasDownload { info }
+            | ArtifactMaterializationMethod::Streamed { info, .. } => {
                 let mut files = Vec::new();
 
                 {
@@ -239,8 +276,12 @@ impl DefaultIoHandler {
             }
             ArtifactMaterializationMethod::HttpDownload { info } => {
                 async {
+                    // Downloads can be large, so report how far along they are.
+                    let http_client = self.http_client.with_download_progress(
+                        EventDispatcherDownloadProgress::new(event_dispatcher.dupe()),
+                    );
                     let downloaded = http_download(
-                        &self.http_client,
+                        &http_client,
                         &self.fs,
                         self.digest_config,
                         &path,
@@ -276,7 +317,7 @@ impl DefaultIoHandler {
             }
             ArtifactMaterializationMethod::LocalCopy(_, copied_artifacts) => {
                 self.io_executor
-                    .execute_io_inline(|| {
+                    .execute_io_inline(IoTag::MaterializeRead, || {
                         for a in copied_artifacts {
                             let count_and_bytes = a.dest_entry.calc_output_count_and_bytes();
                             stat.file_count += count_and_bytes.count;
@@ -295,15 +336,20 @@ impl DefaultIoHandler {
             ArtifactMaterializationMethod::Write(write) => {
                 stat.file_count = 1;
                 self.io_executor
-                    .execute_io_inline(|| {
-                        let data =
-                            zstd::bulk::decompress(&write.compressed_data, write.decompressed_size)
-                                .context("Error decompressing data")?;
+                    .execute_io_inline(IoTag::MaterializeRead, || {
                         stat.total_bytes = write.decompressed_size as u64;
-                        self.fs.write_file(&path, data, write.is_executable)
+                        self.fs
+                            .write_file(&path, write.decompress()?, write.is_executable)
                     })
                     .await?;
             }
+            ArtifactMaterializationMethod::MismatchedExisting { found } => {
+                return Err(anyhow::Error::from(MismatchedExistingError {
+                    path,
+                    found: found.clone(),
+                })
+                .into());
+            }
             #[cfg(test)]
             ArtifactMaterializationMethod::Test => unimplemented!(),
         };
@@ -317,15 +363,19 @@ impl IoHandler for DefaultIoHandler {
         self: &Arc<Self>,
         path: ProjectRelativePathBuf,
         write: Arc<WriteFile>,
+        link_from: Option<ProjectRelativePathBuf>,
         version: Version,
         command_sender: MaterializerSender<Self>,
         cancellations: &'a CancellationContext,
     ) -> BoxFuture<'a, Result<(), SharedMaterializingError>> {
         self.io_executor
             .execute_io(
+                IoTag::MaterializeRead,
                 Box::new(WriteIoRequest {
                     path,
                     write,
+                    link_from,
+                    digest_config: self.digest_config,
                     version,
                     command_sender,
                 }),
@@ -355,17 +405,47 @@ impl IoHandler for DefaultIoHandler {
         command_sender: MaterializerSender<Self>,
         cancellations: &'a CancellationContext,
     ) -> BoxFuture<'a, Result<(), buck2_error::Error>> {
-        self.io_executor
-            .execute_io(
-                Box::new(CleanIoRequest {
-                    path,
-                    version,
-                    command_sender,
-                }),
-                cancellations,
-            )
-            .map(|r| r.map_err(buck2_error::Error::from))
-            .boxed()
+        let this = self.dupe();
+        async move {
+            // Remove the contents of large trees in parallel first. `CleanIoRequest` then removes
+            // what's left and notifies the materializer.
+            let res = async {
+                remove_dir_contents(
+                    this.io_executor.as_ref(),
+                    &this.fs.resolve(&path),
+                    clean_path_parallelism()?,
+                    cancellations,
+                )
+                .await
+            }
+            .await;
+            if let Err(e) = res {
+                let e = buck2_error::Error::from(e);
+                // If the materializer has shut down, we ignore this.
+                let _ignored = command_sender.send_low_priority(
+                    LowPriorityMaterializerCommand::CleanupFinished {
+                        path,
+                        version,
+                        result: Err(SharedMaterializingError::Error(e.dupe())),
+                    },
+                );
+                return Err(e);
+            }
+
+            this.io_executor
+                .execute_io(
+                    IoTag::Clean,
+                    Box::new(CleanIoRequest {
+                        path,
+                        version,
+                        command_sender,
+                    }),
+                    cancellations,
+                )
+                .await
+                .map_err(buck2_error::Error::from)
+        }
+        .boxed()
     }
 
     /// Used to clean paths that are already invalidated and don't need to notify the materializer
@@ -375,7 +455,7 @@ impl IoHandler for DefaultIoHandler {
         cancellations: &'a CancellationContext,
     ) -> anyhow::Result<()> {
         self.io_executor
-            .execute_io(Box::new(request), cancellations)
+            .execute_io(IoTag::Clean, Box::new(request), cancellations)
             .await
     }
 
@@ -391,13 +471,15 @@ impl IoHandler for DefaultIoHandler {
     ) -> Result<(), MaterializeEntryError> {
         let materialization_start = buck2_data::MaterializationStart {
             action_digest: match method.as_ref() {
-                ArtifactMaterializationMethod::CasDownload { info } => {
+                ArtifactMaterializationMethod::CasDownload { info }
+                | ArtifactMaterializationMethod::Streamed { info, .. } => {
                     info.action_digest().map(|digest| digest.to_string())
                 }
                 _ => None,
             },
         };
         event_dispatcher
+            .dupe()
             .span_async(materialization_start, async move {
                 let path_string = path.as_str().to_owned();
                 let mut stat = MaterializationStat {
@@ -405,7 +487,14 @@ impl IoHandler for DefaultIoHandler {
                     total_bytes: 0,
                 };
                 let res = self
-                    .materialize_entry_span(path, method.dupe(), entry, &mut stat, cancellations)
+                    .materialize_entry_span(
+                        path,
+                        method.dupe(),
+                        entry,
+                        &event_dispatcher,
+                        &mut stat,
+                        cancellations,
+                    )
                     .await;
                 let error = res.as_ref().err().map(|e| format!("{:#}", e));
 
@@ -435,12 +524,25 @@ impl IoHandler for DefaultIoHandler {
             .map(|f| f.boxed())
     }
 
+    async fn read_entry_from_disk(
+        self: &Arc<Self>,
+        path: &ProjectRelativePath,
+    ) -> anyhow::Result<Option<ActionDirectoryEntry<ActionSharedDirectory>>> {
+        entry_from_disk(
+            &self.roots,
+            self.digest_config,
+            self.io_executor.as_ref(),
+            path,
+        )
+        .await
+    }
+
     fn read_dir(&self, path: &AbsNormPathBuf) -> Result<ReadDir, IoError> {
         fs_util::read_dir(path)
     }
 
-    fn buck_out_path(&self) -> &ProjectRelativePathBuf {
-        &self.buck_out_path
+    fn roots(&self) -> &PhysicalRoots {
+        &self.roots
     }
 
     fn re_client_manager(&self) -> &Arc<ReConnectionManager> {
@@ -490,21 +592,18 @@ fn maybe_tombstone_digest(digest: &FileDigest) -> anyhow::Result<&FileDigest> {
     Ok(digest)
 }
 
-/// Spawn a task to refresh TTLs.
-pub(super) fn create_ttl_refresh(
+/// The digests that expire before `ttl_deadline`, by use case.
+pub(super) fn digests_to_refresh(
     tree: &ArtifactTree,
-    re_manager: &Arc<ReConnectionManager>,
-    min_ttl: Duration,
-    digest_config: DigestConfig,
-) -> Option<impl Future<Output = anyhow::Result<()>>> {
+    ttl_deadline: DateTime<Utc>,
+) -> HashMap<RemoteExecutorUseCase, HashSet<TrackedFileDigest>> {
     let mut digests_to_refresh = HashMap::<_, HashSet<_>>::new();
 
-    let ttl_deadline = Utc::now() + min_ttl;
-
     for data in tree.iter_without_paths() {
         match &data.stage {
             ArtifactMaterializationStage::Declared { entry, method } => match method.as_ref() {
-                ArtifactMaterializationMethod::CasDownload { info } => {
+                ArtifactMaterializationMethod::CasDownload { info }
+                | ArtifactMaterializationMethod::Streamed { info, .. } => {
                     let mut walk = unordered_entry_walk(entry.as_ref());
                     while let Some((_entry_path, entry)) = walk.next() {
                         if let DirectoryEntry::Leaf(ActionDirectoryMember::File(file)) = entry {
@@ -525,6 +624,20 @@ pub(super) fn create_ttl_refresh(
         }
     }
 
+    digests_to_refresh
+}
+
+/// Spawn a task to refresh TTLs.
+pub(super) fn create_ttl_refresh(
+    tree: &ArtifactTree,
+    re_manager: &Arc<ReConnectionManager>,
+    min_ttl: Duration,
+    digest_config: DigestConfig,
+) -> Option<impl Future<Output = anyhow::Result<()>>> {
+    // Not the wall clock: if it jumps backwards, digests would look like they expire later than
+    // they do.
+    let digests_to_refresh = digests_to_refresh(tree, clock_now() + min_ttl);
+
     if digests_to_refresh.is_empty() {
         return None;
     }
@@ -589,6 +702,9 @@ pub(super) fn create_ttl_refresh(
 struct WriteIoRequest {
     path: ProjectRelativePathBuf,
     write: Arc<WriteFile>,
+    /// An identical file to hardlink, see `write_or_link`.
+    link_from: Option<ProjectRelativePathBuf>,
+    digest_config: DigestConfig,
     version: Version,
     command_sender: MaterializerSender<DefaultIoHandler>,
 }
@@ -596,12 +712,63 @@ struct WriteIoRequest {
 impl WriteIoRequest {
     fn execute_inner(&self, project_fs: &ProjectRoot) -> anyhow::Result<()> {
         cleanup_path(project_fs, &self.path)?;
-        let data =
-            zstd::bulk::decompress(&self.write.compressed_data, self.write.decompressed_size)
-                .context("Error decompressing data")?;
-        project_fs.write_file(&self.path, data, self.write.is_executable)?;
-        Ok(())
+        write_or_link(
+            project_fs,
+            &self.path,
+            &self.write,
+            self.link_from.as_deref(),
+            self.digest_config,
+        )
+    }
+}
+
+/// Write `write` at `path`. If `link_from` is set, it's a path where the same contents were
+/// materialized, and `path` is hardlinked to it instead. Since the file there may have changed
+/// since, the link is only kept if the linked file has the digest of `write`, and the contents
+/// are written otherwise.
+pub(super) fn write_or_link(
+    project_fs: &ProjectRoot,
+    path: &ProjectRelativePath,
+    write: &WriteFile,
+    link_from: Option<&ProjectRelativePath>,
+    digest_config: DigestConfig,
+) -> anyhow::Result<()> {
+    if let Some(link_from) = link_from {
+        let dest = project_fs.resolve(path);
+        if let Some(parent) = dest.parent() {
+            fs_util::create_dir_all(parent)?;
+        }
+        match fs_util::hard_link(project_fs.resolve(link_from), &dest) {
+            Ok(()) => {
+                if linked_file_matches(&dest, write, digest_config)? {
+                    return Ok(());
+                }
+                fs_util::remove_file(&dest)?;
+            }
+            Err(e) => {
+                tracing::debug!("Failed to hardlink `{}`, writing it: {:#}", path, e);
+            }
+        }
+    }
+    project_fs.write_file(path, write.decompress()?, write.is_executable)
+}
+
+/// Whether the file hardlinked at `dest` has the contents of `write`. The size is checked
+/// first, so that the file is only hashed when it could match.
+fn linked_file_matches(
+    dest: &AbsNormPathBuf,
+    write: &WriteFile,
+    digest_config: DigestConfig,
+) -> anyhow::Result<bool> {
+    let metadata = fs_util::symlink_metadata(dest)?;
+    if metadata.len() != write.decompressed_size as u64 {
+        return Ok(false);
     }
+    let digest = FileDigest::from_file(
+        dest,
+        FileDigestConfig::build(digest_config.cas_digest_config()),
+    )?;
+    Ok(&digest == write.digest.data())
 }
 
 impl IoRequest for WriteIoRequest {
@@ -616,7 +783,7 @@ impl IoRequest for WriteIoRequest {
         let _ignored = self.command_sender.send_low_priority(
             LowPriorityMaterializerCommand::MaterializationFinished {
                 path: self.path,
-                timestamp: Utc::now(),
+                timestamp: clock_now(),
                 version: self.version,
                 result: res.dupe().map_err(SharedMaterializingError::Error),
             },
@@ -0,0 +1,129 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_events::dispatch::EventDispatcher;
+use dupe::Dupe;
+
+/// Identifies the artifacts prefetched for one action, so that they can be withdrawn together.
+#[derive(Copy, Clone, Dupe, Debug, Eq, PartialEq, Hash)]
+pub(crate) struct PrefetchId(u64);
+
+impl PrefetchId {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+pub(crate) struct PendingPrefetch {
+    pub(crate) id: PrefetchId,
+    pub(crate) path: ProjectRelativePathBuf,
+    pub(crate) event_dispatcher: EventDispatcher,
+}
+
+/// Artifacts that actions queued for local execution will need, materialized in the background
+/// when the materializer has nothing else to do.
+///
+/// Prefetches are started in the order they were requested, as long as the artifacts being
+/// prefetched add up to at most `max_bytes`, so that prefetching doesn't compete with the
+/// materializations builds are actually waiting for. An artifact larger than the cap is only
+/// prefetched when nothing else is.
+pub(crate) struct PrefetchQueue {
+    max_bytes: u64,
+    pending: VecDeque<PendingPrefetch>,
+    /// Paths in `pending`, so that checking whether a path is queued doesn't scan the queue.
+    pending_paths: HashSet<ProjectRelativePathBuf>,
+    /// Size of the artifacts being prefetched.
+    in_flight: HashMap<ProjectRelativePathBuf, u64>,
+    in_flight_bytes: u64,
+}
+
+impl PrefetchQueue {
+    pub(crate) fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            pending: VecDeque::new(),
+            pending_paths: HashSet::new(),
+            in_flight: HashMap::new(),
+            in_flight_bytes: 0,
+        }
+    }
+
+    /// Whether this path is already waiting to be prefetched, or being prefetched.
+    pub(crate) fn contains(&self, path: &ProjectRelativePath) -> bool {
+        self.in_flight.contains_key(path) || self.pending_paths.contains(path)
+    }
+
+    /// Queue a prefetch. Callers check `contains` first, so a path is never queued twice.
+    pub(crate) fn push(&mut self, prefetch: PendingPrefetch) {
+        self.pending_paths.insert(prefetch.path.clone());
+        self.pending.push_back(prefetch);
+    }
+
+    /// The next prefetch to start.
+    pub(crate) fn peek(&self) -> Option<&PendingPrefetch> {
+        self.pending.front()
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<PendingPrefetch> {
+        let prefetch = self.pending.pop_front()?;
+        self.pending_paths.remove(&prefetch.path);
+        Some(prefetch)
+    }
+
+    /// Whether an artifact of this size can be prefetched without exceeding the cap.
+    pub(crate) fn has_room_for(&self, bytes: u64) -> bool {
+        self.in_flight.is_empty() || self.in_flight_bytes.saturating_add(bytes) <= self.max_bytes
+    }
+
+    pub(crate) fn started(&mut self, path: ProjectRelativePathBuf, bytes: u64) {
+        if let Some(previous) = self.in_flight.insert(path, bytes) {
+            self.in_flight_bytes -= previous;
+        }
+        self.in_flight_bytes += bytes;
+    }
+
+    pub(crate) fn finished(&mut self, path: &ProjectRelativePath) {
+        if let Some(bytes) = self.in_flight.remove(path) {
+            self.in_flight_bytes -= bytes;
+        }
+    }
+
+    /// Drop the prefetches of this id that haven't started yet. Those that have are left to
+    /// finish, since their results can still be used.
+    pub(crate) fn withdraw(&mut self, id: PrefetchId) {
+        let pending_paths = &mut self.pending_paths;
+        self.pending.retain(|p| {
+            if p.id == id {
+                pending_paths.remove(&p.path);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    #[cfg(test)]
+    pub(crate) fn in_flight_bytes(&self) -> u64 {
+        self.in_flight_bytes
+    }
+
+    #[cfg(test)]
+    pub(crate) fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
@@ -0,0 +1,729 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Experimental: expose an input of a local action as a named pipe whose content is streamed
+//! from the CAS when the action opens it, instead of materializing it.
+//!
+//! This only works for inputs that the action reads exactly once, from start to end. If the
+//! action opens the input again, or stops reading it early (which is what happens when it tries
+//! to seek), the input is materialized as a regular file once the action exits, and the action
+//! must be re-run.
+
+use std::fmt;
+use std::sync::Arc;
+
+use buck2_core::execution_types::executor_config::RemoteExecutorUseCase;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_execute::materialize::materializer::StreamedInputs;
+use buck2_execute::re::manager::ReConnectionManager;
+use dupe::Dupe;
+use futures::future::BoxFuture;
+use futures::future::Shared;
+use futures::stream::BoxStream;
+use futures::FutureExt;
+use futures::StreamExt;
+use remote_execution::TDigest;
+use tokio::sync::oneshot;
+
+use crate::materializers::clock_now;
+use crate::materializers::deferred::io_handler::IoHandler;
+use crate::materializers::deferred::LowPriorityMaterializerCommand;
+use crate::materializers::deferred::MaterializerSender;
+
+/// Files smaller than this are materialized rather than streamed: they are cheap to write, and
+/// more likely to be read more than once (e.g. config files).
+pub(super) const STREAMED_INPUT_MIN_SIZE: u64 = 1024 * 1024;
+
+/// Where the content of a streamed input comes from.
+pub trait StreamedInputSource: Send + Sync + 'static {
+    /// The content of the input, in chunks. Called again to materialize the input if streaming
+    /// it did not work out.
+    fn chunks(&self) -> BoxStream<'static, anyhow::Result<Vec<u8>>>;
+}
+
+/// Streams a file from the CAS.
+pub struct CasStreamedInputSource {
+    re_client_manager: Arc<ReConnectionManager>,
+    digest: TDigest,
+    re_use_case: RemoteExecutorUseCase,
+}
+
+impl CasStreamedInputSource {
+    pub fn new(
+        re_client_manager: Arc<ReConnectionManager>,
+        digest: TDigest,
+        re_use_case: RemoteExecutorUseCase,
+    ) -> Self {
+        Self {
+            re_client_manager,
+            digest,
+            re_use_case,
+        }
+    }
+}
+
+impl StreamedInputSource for CasStreamedInputSource {
+    fn chunks(&self) -> BoxStream<'static, anyhow::Result<Vec<u8>>> {
+        let re_client_manager = self.re_client_manager.dupe();
+        let digest = self.digest.clone();
+        let re_use_case = self.re_use_case;
+        // The RE client only downloads whole blobs, so this is a single chunk for now.
+        futures::stream::once(async move {
+            re_client_manager
+                .get_re_connection()
+                .get_client()
+                .download_blob(&digest, re_use_case)
+                .await
+        })
+        .boxed()
+    }
+}
+
+/// Why a streamed input had to be materialized after all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+pub enum StreamedInputFallback {
+    /// The action opened the input more than once.
+    #[display(fmt = "input was opened more than once")]
+    Reopened,
+    /// The action stopped reading before the end of the input, typically because it tried to
+    /// seek.
+    #[display(fmt = "input was not read to the end")]
+    IncompleteRead,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamedInputOutcome {
+    /// The action read the input exactly once. The action result is valid.
+    Streamed,
+    /// The action never opened the input. The action result is valid.
+    NotOpened,
+    /// Named pipes are not supported on this platform, so the input was materialized as a
+    /// regular file before the action ran. The action result is valid.
+    Unsupported,
+    /// The input could not be streamed, and has now been materialized as a regular file. The
+    /// action result must be discarded and the action re-run.
+    NeedsRerun(StreamedInputFallback),
+}
+
+/// What the pump observed, shared with the `StreamedInput`.
+#[derive(Default, Debug)]
+struct PumpState {
+    opened: bool,
+    complete: bool,
+    reopened: bool,
+    error: Option<String>,
+    /// Set once the `StreamedInput` is finished or dropped. An open of the pipe that returns
+    /// after this was unblocked by `unblocker` rather than by the action.
+    abandoned: bool,
+    /// The read end of the pipe, opened to unblock the pump's open of the write end when the
+    /// input is abandoned. Kept until the last reference to the state goes away, so the pump's
+    /// open can't start waiting for a reader after it's closed.
+    #[cfg(unix)]
+    unblocker: Option<std::fs::File>,
+}
+
+/// A named pipe standing in for an input file. The pipe is removed when this is finished or
+/// dropped, the latter being what happens when the action is cancelled.
+pub struct StreamedInput {
+    path: AbsNormPathBuf,
+    source: Arc<dyn StreamedInputSource>,
+    is_executable: bool,
+    state: Arc<parking_lot::Mutex<PumpState>>,
+    /// `None` when the input was materialized up front.
+    pump: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl StreamedInput {
+    /// Create a named pipe at `path`, and stream `source` into it when it is opened. Where named
+    /// pipes are not supported, materialize `source` at `path` instead.
+    pub async fn create(
+        path: AbsNormPathBuf,
+        source: Arc<dyn StreamedInputSource>,
+        is_executable: bool,
+    ) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs_util::create_dir_all(dir)?;
+        }
+        let state = Arc::new(parking_lot::Mutex::new(PumpState::default()));
+
+        #[cfg(unix)]
+        let pump = Some(unix::start_pump(&path, &source, &state)?);
+
+        #[cfg(not(unix))]
+        let pump = {
+            materialize(&path, source.as_ref(), is_executable).await?;
+            None
+        };
+
+        Ok(Self {
+            path,
+            source,
+            is_executable,
+            state,
+            pump,
+        })
+    }
+
+    pub fn path(&self) -> &AbsNormPath {
+        &self.path
+    }
+
+    /// Call once the action has exited: stops streaming, removes the named pipe, and
+    /// materializes the input as a regular file if the action needs to be re-run.
+    pub async fn finish(mut self) -> anyhow::Result<StreamedInputOutcome> {
+        let Some(pump) = self.pump.take() else {
+            return Ok(StreamedInputOutcome::Unsupported);
+        };
+        let abandoned = self.abandon();
+        pump.abort();
+        // The pump is either done or cancelled, it can't panic.
+        drop(pump.await);
+        remove_fifo(&self.path)?;
+        abandoned?;
+
+        let outcome = {
+            let state = self.state.lock();
+            if let Some(error) = &state.error {
+                return Err(anyhow::anyhow!(
+                    "Error streaming input `{}`: {}",
+                    self.path,
+                    error
+                ));
+            }
+            if state.reopened {
+                StreamedInputOutcome::NeedsRerun(StreamedInputFallback::Reopened)
+            } else if state.opened && !state.complete {
+                StreamedInputOutcome::NeedsRerun(StreamedInputFallback::IncompleteRead)
+            } else if state.opened {
+                StreamedInputOutcome::Streamed
+            } else {
+                StreamedInputOutcome::NotOpened
+            }
+        };
+
+        if let StreamedInputOutcome::NeedsRerun(_) = outcome {
+            if let Err(e) = materialize(&self.path, self.source.as_ref(), self.is_executable).await
+            {
+                // Don't leave a partial file behind for the next materialization.
+                let _ignored = fs_util::remove_file(&self.path);
+                return Err(e);
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+impl StreamedInput {
+    /// Stop streaming: unblocks the pump if it is waiting for the pipe to be opened.
+    fn abandon(&self) -> anyhow::Result<()> {
+        #[cfg(unix)]
+        unix::abandon(&self.path, &self.state)?;
+        Ok(())
+    }
+}
+
+impl Drop for StreamedInput {
+    fn drop(&mut self) {
+        if let Some(pump) = self.pump.take() {
+            if let Err(e) = self.abandon() {
+                tracing::warn!("Error abandoning streamed input: {:#}", e);
+            }
+            pump.abort();
+            if let Err(e) = remove_fifo(&self.path) {
+                tracing::warn!("Error removing streamed input: {:#}", e);
+            }
+        }
+    }
+}
+
+/// Resolves once the local action reading a streamed input is done with it, see
+/// `ArtifactMaterializationMethod::Streamed`.
+#[derive(Clone)]
+pub(super) struct StreamFinished(Shared<BoxFuture<'static, ()>>);
+
+impl StreamFinished {
+    fn new(receiver: oneshot::Receiver<()>) -> Self {
+        Self(receiver.map(|_| ()).boxed().shared())
+    }
+
+    pub(super) async fn wait(self) {
+        self.0.await
+    }
+}
+
+impl fmt::Debug for StreamFinished {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StreamFinished")
+    }
+}
+
+/// An input the command thread marked as streamed. When this is dropped, the command thread is
+/// told whether the input was left on disk, and the artifact is materialized as usual from then
+/// on.
+pub(super) struct StreamedInputClaim<T: 'static> {
+    pub(super) path: ProjectRelativePathBuf,
+    pub(super) digest: TDigest,
+    pub(super) is_executable: bool,
+    pub(super) re_use_case: RemoteExecutorUseCase,
+    materialized: bool,
+    finished: Option<oneshot::Sender<()>>,
+    command_sender: MaterializerSender<T>,
+}
+
+impl<T: 'static> StreamedInputClaim<T> {
+    /// Returns the claim, and what materializations of the artifact wait on in the meantime.
+    pub(super) fn new(
+        path: ProjectRelativePathBuf,
+        digest: TDigest,
+        is_executable: bool,
+        re_use_case: RemoteExecutorUseCase,
+        command_sender: MaterializerSender<T>,
+    ) -> (Self, StreamFinished) {
+        let (sender, receiver) = oneshot::channel();
+        let claim = Self {
+            path,
+            digest,
+            is_executable,
+            re_use_case,
+            materialized: false,
+            finished: Some(sender),
+            command_sender,
+        };
+        (claim, StreamFinished::new(receiver))
+    }
+
+    /// Done with the input. `materialized` is whether it was left on disk as a regular file.
+    pub(super) fn release(mut self, materialized: bool) {
+        self.materialized = materialized;
+    }
+}
+
+impl<T: 'static> Drop for StreamedInputClaim<T> {
+    fn drop(&mut self) {
+        // The command thread must see this before anything waiting on `finished` materializes the
+        // artifact, which is why it is sent first.
+        let _ignored = self.command_sender.send_low_priority(
+            LowPriorityMaterializerCommand::StreamedInputFinished {
+                path: self.path.clone(),
+                timestamp: clock_now(),
+                materialized: self.materialized,
+            },
+        );
+        if let Some(finished) = self.finished.take() {
+            let _ignored = finished.send(());
+        }
+    }
+}
+
+struct ActiveStreamedInput<T: 'static> {
+    // Dropped first, which removes the named pipe before the claim is released.
+    input: StreamedInput,
+    claim: StreamedInputClaim<T>,
+}
+
+/// Create the named pipes of the claimed inputs. Inputs whose pipe can't be created are released,
+/// and get materialized as usual.
+pub(super) async fn stream_claimed_inputs<T: IoHandler>(
+    io: &Arc<T>,
+    claims: Vec<StreamedInputClaim<T>>,
+) -> StreamedInputs {
+    let mut inputs = Vec::with_capacity(claims.len());
+    for claim in claims {
+        let source = Arc::new(CasStreamedInputSource::new(
+            io.re_client_manager().dupe(),
+            claim.digest.clone(),
+            claim.re_use_case,
+        ));
+        match io
+            .stream_input(&claim.path, source, claim.is_executable)
+            .await
+        {
+            Ok(input) => inputs.push(ActiveStreamedInput { input, claim }),
+            Err(e) => {
+                tracing::warn!(
+                    "Error streaming input `{}`, materializing it instead: {:#}",
+                    claim.path,
+                    e
+                );
+            }
+        }
+    }
+
+    if inputs.is_empty() {
+        return StreamedInputs::none();
+    }
+
+    let paths = inputs.iter().map(|i| i.claim.path.clone()).collect();
+    StreamedInputs::new(paths, move || {
+        async move {
+            let mut needs_rerun = false;
+            for ActiveStreamedInput { input, claim } in inputs {
+                let outcome = input.finish().await?;
+                let materialized = match outcome {
+                    StreamedInputOutcome::Streamed | StreamedInputOutcome::NotOpened => false,
+                    StreamedInputOutcome::Unsupported => true,
+                    StreamedInputOutcome::NeedsRerun(fallback) => {
+                        tracing::debug!(
+                            "Streamed input `{}` had to be materialized: {}",
+                            claim.path,
+                            fallback
+                        );
+                        needs_rerun = true;
+                        true
+                    }
+                };
+                claim.release(materialized);
+            }
+            Ok(needs_rerun)
+        }
+        .boxed()
+    })
+}
+
+/// Where the pump prepares the pipe that replaces the current one.
+fn next_fifo_path(path: &AbsNormPath) -> AbsNormPathBuf {
+    let mut next = path.as_path().as_os_str().to_owned();
+    next.push(".streamed_input_next");
+    AbsNormPathBuf::unchecked_new(next.into())
+}
+
+/// Removes the pipe, as well as the pipe that might have been about to replace it.
+fn remove_fifo(path: &AbsNormPath) -> anyhow::Result<()> {
+    for path in [path.to_owned(), next_fifo_path(path)] {
+        if fs_util::symlink_metadata_if_exists(&path)?.is_some() {
+            fs_util::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write `source` to a regular file at `path`.
+async fn materialize(
+    path: &AbsNormPath,
+    source: &dyn StreamedInputSource,
+    is_executable: bool,
+) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut chunks = source.chunks();
+    while let Some(chunk) = chunks.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
+    drop(file);
+
+    if is_executable {
+        fs_util::set_executable(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+    use std::sync::Arc;
+
+    use anyhow::Context;
+    use buck2_core::fs::fs_util;
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+    use dupe::Dupe;
+    use futures::StreamExt;
+    use nix::fcntl::FcntlArg;
+    use nix::fcntl::OFlag;
+    use nix::sys::stat::Mode;
+    use tokio::io::unix::AsyncFd;
+    use tokio::io::Interest;
+
+    use crate::materializers::deferred::streamed_input::next_fifo_path;
+    use crate::materializers::deferred::streamed_input::PumpState;
+    use crate::materializers::deferred::streamed_input::StreamedInputSource;
+
+    fn create_fifo(path: &AbsNormPath) -> anyhow::Result<()> {
+        nix::unistd::mkfifo(path.as_path(), Mode::S_IRUSR | Mode::S_IWUSR)
+            .with_context(|| format!("Error creating named pipe at `{}`", path))
+    }
+
+    /// Opens the write end of the pipe, which blocks until there is a reader. Returns `None` if
+    /// the input was abandoned in the meantime.
+    async fn open_writer(
+        path: &AbsNormPath,
+        state: &Arc<parking_lot::Mutex<PumpState>>,
+    ) -> anyhow::Result<Option<AsyncFd<std::fs::File>>> {
+        let res = tokio::task::spawn_blocking({
+            let path = path.to_owned();
+            let state = state.dupe();
+            move || {
+                let res = std::fs::OpenOptions::new().write(true).open(path.as_path());
+                // Only now may the unblocker be closed, see `PumpState::unblocker`.
+                drop(state);
+                res
+            }
+        })
+        .await?;
+        if state.lock().abandoned {
+            return Ok(None);
+        }
+        let file = res.with_context(|| format!("Error opening `{}`", path))?;
+
+        let fd = file.as_raw_fd();
+        let flags = OFlag::from_bits_truncate(nix::fcntl::fcntl(fd, FcntlArg::F_GETFL)?);
+        nix::fcntl::fcntl(fd, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+        Ok(Some(AsyncFd::with_interest(file, Interest::WRITABLE)?))
+    }
+
+    /// Unblocks a pending `open_writer` by opening the read end of the pipe.
+    pub(super) fn abandon(
+        path: &AbsNormPath,
+        state: &Arc<parking_lot::Mutex<PumpState>>,
+    ) -> anyhow::Result<()> {
+        let mut state = state.lock();
+        if state.abandoned {
+            return Ok(());
+        }
+        state.abandoned = true;
+        // Opening a pipe for reading without blocking succeeds even if it has no writer.
+        let unblocker = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(nix::libc::O_NONBLOCK)
+            .open(path.as_path())
+            .with_context(|| format!("Error opening `{}`", path))?;
+        state.unblocker = Some(unblocker);
+        Ok(())
+    }
+
+    /// Returns `false` if the reader went away before reading everything.
+    async fn write_all(
+        writer: &AsyncFd<std::fs::File>,
+        source: &dyn StreamedInputSource,
+    ) -> anyhow::Result<bool> {
+        let mut chunks = source.chunks();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            let mut buf = &chunk[..];
+            while !buf.is_empty() {
+                let mut guard = writer.writable().await?;
+                match guard.try_io(|file| (&mut file.get_ref()).write(buf)) {
+                    Ok(Ok(written)) => buf = &buf[written..],
+                    Ok(Err(e)) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(false),
+                    Ok(Err(e)) => return Err(e.into()),
+                    Err(_would_block) => {}
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    pub(super) fn start_pump(
+        path: &AbsNormPath,
+        source: &Arc<dyn StreamedInputSource>,
+        state: &Arc<parking_lot::Mutex<PumpState>>,
+    ) -> anyhow::Result<tokio::task::JoinHandle<()>> {
+        create_fifo(path)?;
+        Ok(tokio::spawn(pump(
+            path.to_owned(),
+            source.dupe(),
+            state.dupe(),
+        )))
+    }
+
+    async fn pump(
+        path: AbsNormPathBuf,
+        source: Arc<dyn StreamedInputSource>,
+        state: Arc<parking_lot::Mutex<PumpState>>,
+    ) {
+        let res: anyhow::Result<()> = try {
+            let Some(writer) = open_writer(&path, &state).await? else {
+                return;
+            };
+            state.lock().opened = true;
+            let complete = write_all(&writer, source.as_ref()).await?;
+            state.lock().complete = complete;
+
+            // Replace the pipe before closing the write end, which is what lets the reader see
+            // the end of the input. Any later open then gets the new pipe, and can only come
+            // from opening the input again.
+            let next = next_fifo_path(&path);
+            create_fifo(&next)?;
+            {
+                // Renaming under the lock means `abandon` unblocks the open of whichever pipe
+                // is the current one.
+                let state = state.lock();
+                if state.abandoned {
+                    return;
+                }
+                fs_util::rename(&next, &path)?;
+            }
+            drop(writer);
+
+            if open_writer(&path, &state).await?.is_some() {
+                state.lock().reopened = true;
+            }
+        };
+        if let Err(e) = res {
+            state.lock().error = Some(format!("{:#}", e));
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io::Read;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use std::os::unix::fs::FileTypeExt;
+    use std::sync::Arc;
+
+    use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+    use futures::stream::BoxStream;
+    use futures::StreamExt;
+
+    use crate::materializers::deferred::streamed_input::StreamedInput;
+    use crate::materializers::deferred::streamed_input::StreamedInputFallback;
+    use crate::materializers::deferred::streamed_input::StreamedInputOutcome;
+    use crate::materializers::deferred::streamed_input::StreamedInputSource;
+
+    struct InMemorySource(Vec<Vec<u8>>);
+
+    impl StreamedInputSource for InMemorySource {
+        fn chunks(&self) -> BoxStream<'static, anyhow::Result<Vec<u8>>> {
+            futures::stream::iter(self.0.clone().into_iter().map(Ok)).boxed()
+        }
+    }
+
+    fn large_content() -> Vec<u8> {
+        // Larger than the pipe buffer, so that the writer notices when the reader goes away.
+        (0..(1024 * 1024)).map(|i| i as u8).collect()
+    }
+
+    async fn create(
+        dir: &tempfile::TempDir,
+        chunks: Vec<Vec<u8>>,
+    ) -> anyhow::Result<StreamedInput> {
+        let path = AbsNormPathBuf::try_from(dir.path().join("input"))?;
+        StreamedInput::create(path, Arc::new(InMemorySource(chunks)), false).await
+    }
+
+    #[tokio::test]
+    async fn test_single_read_streams_content() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = create(&dir, vec![b"hello ".to_vec(), b"world".to_vec()]).await?;
+        let path = input.path().to_owned();
+        assert!(std::fs::metadata(&path)?.file_type().is_fifo());
+
+        let read = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || std::fs::read(path)
+        })
+        .await??;
+        assert_eq!(b"hello world".to_vec(), read);
+
+        assert_eq!(StreamedInputOutcome::Streamed, input.finish().await?);
+        assert!(!path.as_path().exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_not_opened() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = create(&dir, vec![b"hello".to_vec()]).await?;
+        let path = input.path().to_owned();
+
+        assert_eq!(StreamedInputOutcome::NotOpened, input.finish().await?);
+        assert!(!path.as_path().exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reopen_falls_back_to_materialization() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = create(&dir, vec![b"hello ".to_vec(), b"world".to_vec()]).await?;
+        let path = input.path().to_owned();
+
+        let reads = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || anyhow::Ok((std::fs::read(&path)?, std::fs::read(&path)?))
+        })
+        .await??;
+        assert_eq!((b"hello world".to_vec(), Vec::new()), reads);
+
+        assert_eq!(
+            StreamedInputOutcome::NeedsRerun(StreamedInputFallback::Reopened),
+            input.finish().await?
+        );
+        let metadata = std::fs::metadata(&path)?;
+        assert!(metadata.file_type().is_file());
+        assert_eq!(b"hello world".to_vec(), std::fs::read(&path)?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_seek_falls_back_to_materialization() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let content = large_content();
+        let input = create(&dir, vec![content.clone()]).await?;
+        let path = input.path().to_owned();
+
+        let seek_result = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || {
+                let mut file = std::fs::File::open(path)?;
+                let mut header = [0; 16];
+                file.read_exact(&mut header)?;
+                anyhow::Ok(file.seek(SeekFrom::Start(1000)).is_err())
+            }
+        })
+        .await??;
+        assert!(seek_result, "seeking a pipe should fail");
+
+        assert_eq!(
+            StreamedInputOutcome::NeedsRerun(StreamedInputFallback::IncompleteRead),
+            input.finish().await?
+        );
+        assert_eq!(content, std::fs::read(&path)?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_removes_fifo() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = create(&dir, vec![large_content()]).await?;
+        let path = input.path().to_owned();
+
+        // Start reading, but cancel while the content is being streamed.
+        let mut reader = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || {
+                let mut reader = std::fs::File::open(path)?;
+                let mut header = [0; 16];
+                reader.read_exact(&mut header)?;
+                anyhow::Ok(reader)
+            }
+        })
+        .await??;
+        drop(input);
+
+        assert!(!path.as_path().exists());
+        // The reader sees the end of the input rather than hanging.
+        let rest = tokio::task::spawn_blocking(move || {
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest)?;
+            anyhow::Ok(rest)
+        })
+        .await??;
+        assert!(rest.len() < large_content().len());
+        Ok(())
+    }
+}
@@ -18,6 +18,7 @@ use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_execute::materialize::materializer::DeferredMaterializerSubscription;
+use buck2_execute::materialize::materializer::MaterializerSubscriptionEvent;
 use derivative::Derivative;
 use derive_more::Display;
 use dupe::Dupe;
@@ -33,7 +34,7 @@ use crate::materializers::deferred::MaterializerCommand;
 use crate::materializers::deferred::MaterializerSender;
 
 /// Subscriptions allow clients to request eager materialization of specific paths as well as
-/// notifications when those paths are materialized.
+/// notifications when those paths are materialized, cleaned or invalidated.
 pub(super) struct MaterializerSubscriptions {
     index: SubscriptionIndex,
     active: HashMap<SubscriptionIndex, SubscriptionData>,
@@ -60,9 +61,27 @@ impl MaterializerSubscriptions {
 
     /// Notify this subscription that a given path has been materialized.
     pub fn on_materialization_finished(&self, path: &ProjectRelativePath) {
+        self.notify(path, MaterializerSubscriptionEvent::Materialized);
+    }
+
+    /// Notify this subscription that a given path has been deleted from disk.
+    pub fn on_cleanup_finished(&self, path: &ProjectRelativePath) {
+        self.notify(path, MaterializerSubscriptionEvent::Cleaned);
+    }
+
+    /// Notify this subscription that the artifact materialized at a given path was superseded.
+    pub fn on_invalidated(&self, path: &ProjectRelativePath) {
+        self.notify(path, MaterializerSubscriptionEvent::Invalidated);
+    }
+
+    fn notify(
+        &self,
+        path: &ProjectRelativePath,
+        event: fn(ProjectRelativePathBuf) -> MaterializerSubscriptionEvent,
+    ) {
         for sub in self.active.values() {
             if sub.paths.contains(path) {
-                sub.sender.send(path.to_owned());
+                sub.sender.send(event(path.to_owned()));
             }
         }
     }
@@ -93,11 +112,11 @@ impl MaterializerSubscriptions {
 
 struct SubscriptionData {
     paths: HashSet<ProjectRelativePathBuf>,
-    sender: UnboundedSender<ProjectRelativePathBuf>,
+    sender: UnboundedSender<MaterializerSubscriptionEvent>,
 }
 
 impl SubscriptionData {
-    fn new(sender: UnboundedSender<ProjectRelativePathBuf>) -> Self {
+    fn new(sender: UnboundedSender<MaterializerSubscriptionEvent>) -> Self {
         Self {
             paths: HashSet::new(),
             sender,
@@ -189,7 +208,9 @@ where
                     .unwrap();
 
                 for path in paths_to_report {
-                    subscription.sender.send(path);
+                    subscription
+                        .sender
+                        .send(MaterializerSubscriptionEvent::Materialized(path));
                 }
 
                 subscription.paths.extend(paths);
@@ -222,12 +243,12 @@ pub(super) struct SubscriptionHandle<T: 'static> {
     command_sender: MaterializerSender<T>,
     /// Channel to send back notifications.
     #[derivative(Debug = "ignore")]
-    receiver: UnboundedReceiver<ProjectRelativePathBuf>,
+    receiver: UnboundedReceiver<MaterializerSubscriptionEvent>,
 }
 
 impl<T: 'static> SubscriptionHandle<T> {
     #[cfg(test)]
-    pub fn receiver(&mut self) -> &mut UnboundedReceiver<ProjectRelativePathBuf> {
+    pub fn receiver(&mut self) -> &mut UnboundedReceiver<MaterializerSubscriptionEvent> {
         &mut self.receiver
     }
 }
@@ -252,7 +273,7 @@ impl<T: 'static> DeferredMaterializerSubscription for SubscriptionHandle<T> {
         ));
     }
 
-    async fn next_materialization(&mut self) -> Option<ProjectRelativePathBuf> {
+    async fn next_event(&mut self) -> Option<MaterializerSubscriptionEvent> {
         self.receiver.recv().await
     }
 }
@@ -10,13 +10,20 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::time::SystemTime;
 
+use buck2_common::file_ops::FileDigest;
 use buck2_common::file_ops::FileMetadata;
+use buck2_core::execution_types::executor_config::RemoteExecutorUseCase;
 use buck2_core::fs::fs_util::IoError;
+use buck2_events::clock::testing::FakeTimeSource;
+use buck2_events::clock::HybridClock;
+use buck2_events::clock::DEFAULT_STEP_THRESHOLD;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::directory::insert_file;
 use buck2_execute::directory::ActionDirectoryBuilder;
 use buck2_execute::materialize::materializer::DeferredMaterializerSubscription;
+use buck2_execute::materialize::materializer::MaterializerSubscriptionEvent;
 use dupe::Dupe;
 
 use super::Version;
@@ -101,7 +108,11 @@ mod state_machine {
     use buck2_events::source::ChannelEventSource;
     use buck2_execute::directory::Symlink;
     use buck2_execute::directory::INTERNER;
+    use buck2_execute::execute::blocking::testing::DummyBlockingExecutor;
     use buck2_execute::execute::blocking::IoRequest;
+    use buck2_execute::materialize::materializer::MaterializationStage;
+    use buck2_execute::materialize::materializer::MaterializationStatus;
+    use buck2_execute::materialize::materializer::MaterializerVerifyFound;
     use buck2_util::threads::ignore_stack_overflow_checks_for_future;
     use parking_lot::Mutex;
     use tokio::time::sleep;
@@ -109,7 +120,12 @@ mod state_machine {
 
     use super::*;
     use crate::materializers::deferred::clean_stale::CleanInvalidatedPathRequest;
+    use crate::materializers::deferred::extension::GetMaterializationStatus;
+    use crate::materializers::deferred::io_handler::write_or_link;
     use crate::materializers::deferred::subscriptions::SubscriptionHandle;
+    use crate::materializers::deferred::verify::entry_from_disk;
+    use crate::materializers::deferred::verify::InvalidateMismatchesCommand;
+    use crate::materializers::deferred::verify::VerifyArtifactsCommand;
     use crate::materializers::sqlite::testing_materializer_state_sqlite_db;
 
     #[derive(Debug, Eq, PartialEq, Allocative)]
@@ -117,6 +133,8 @@ mod state_machine {
         Clean,
         Materialize,
         MaterializeError,
+        /// What's on disk was hashed to check it.
+        Verify,
     }
 
     #[derive(Allocative)]
@@ -131,8 +149,10 @@ mod state_machine {
         #[allocative(skip)]
         clean_barriers: Option<Arc<(Barrier, Barrier)>>,
         digest_config: DigestConfig,
-        buck_out_path: ProjectRelativePathBuf,
+        roots: PhysicalRoots,
         fs: ProjectRoot,
+        /// Number of `materialize_entry` calls currently running, and the most seen at once.
+        materializing: Mutex<(usize, usize)>,
     }
 
     impl DeferredMaterializerAccessor<StubIoHandler> {
@@ -160,6 +180,10 @@ mod state_machine {
             *self.fail_paths.lock() = paths;
         }
 
+        fn max_concurrent_materializations(&self) -> usize {
+            self.materializing.lock().1
+        }
+
         pub fn new(fs: ProjectRoot) -> Self {
             Self {
                 log: Default::default(),
@@ -169,11 +193,17 @@ mod state_machine {
                 read_dir_barriers: None,
                 clean_barriers: None,
                 digest_config: DigestConfig::testing_default(),
-                buck_out_path: make_path("buck-out/v2"),
+                roots: PhysicalRoots::unchecked(&fs, &make_path("buck-out/v2")),
                 fs,
+                materializing: Default::default(),
             }
         }
 
+        pub fn with_roots(mut self, roots: PhysicalRoots) -> Self {
+            self.roots = roots;
+            self
+        }
+
         pub fn with_materialization_config(
             mut self,
             materialization_config: HashMap<ProjectRelativePathBuf, TokioDuration>,
@@ -197,11 +227,13 @@ mod state_machine {
     }
 
     impl StubIoHandler {
-        fn actually_write(self: &Arc<Self>, path: &ProjectRelativePathBuf, write: &Arc<WriteFile>) {
-            let data = zstd::bulk::decompress(&write.compressed_data, write.decompressed_size)
-                .context("Error decompressing data")
-                .unwrap();
-            self.fs.write_file(path, data, write.is_executable).unwrap();
+        fn actually_write(
+            self: &Arc<Self>,
+            path: &ProjectRelativePathBuf,
+            write: &Arc<WriteFile>,
+            link_from: Option<&ProjectRelativePath>,
+        ) {
+            write_or_link(&self.fs, path, write, link_from, self.digest_config()).unwrap();
         }
     }
 
@@ -211,11 +243,12 @@ mod state_machine {
             self: &Arc<Self>,
             path: ProjectRelativePathBuf,
             write: Arc<WriteFile>,
+            link_from: Option<ProjectRelativePathBuf>,
             version: Version,
             command_sender: MaterializerSender<Self>,
             _cancellations: &'a CancellationContext<'a>,
         ) -> BoxFuture<'a, Result<(), SharedMaterializingError>> {
-            self.actually_write(&path, &write);
+            self.actually_write(&path, &write, link_from.as_deref());
             async move {
                 let _ignored = command_sender.send_low_priority(
                     LowPriorityMaterializerCommand::MaterializationFinished {
@@ -269,6 +302,7 @@ mod state_machine {
                 barriers.as_ref().0.wait();
                 barriers.as_ref().1.wait();
             }
+            self.log.lock().push((Op::Clean, request.path.clone()));
             Box::new(request).execute(&self.fs)
         }
 
@@ -280,6 +314,11 @@ mod state_machine {
             _event_dispatcher: EventDispatcher,
             _cancellations: &CancellationContext,
         ) -> Result<(), MaterializeEntryError> {
+            {
+                let mut materializing = self.materializing.lock();
+                materializing.0 += 1;
+                materializing.1 = std::cmp::max(materializing.0, materializing.1);
+            }
             // Simulate a non-immediate materialization if configured
             match self.materialization_config.get(&path) {
                 Some(duration) => {
@@ -287,6 +326,7 @@ mod state_machine {
                 }
                 None => (),
             }
+            self.materializing.lock().0 -= 1;
 
             if (*self.fail_paths.lock()).contains(&path) || *self.fail.lock() {
                 self.log.lock().push((Op::MaterializeError, path));
@@ -294,7 +334,7 @@ mod state_machine {
             } else {
                 match _method.as_ref() {
                     ArtifactMaterializationMethod::Write(write) => {
-                        self.actually_write(&path, write);
+                        self.actually_write(&path, write, None);
                     }
                     _ => {}
                 }
@@ -311,6 +351,15 @@ mod state_machine {
             unimplemented!()
         }
 
+        async fn read_entry_from_disk(
+            self: &Arc<Self>,
+            path: &ProjectRelativePath,
+        ) -> anyhow::Result<Option<ActionDirectoryEntry<ActionSharedDirectory>>> {
+            self.log.lock().push((Op::Verify, path.to_buf()));
+            let executor = DummyBlockingExecutor { fs: self.fs.dupe() };
+            entry_from_disk(&self.roots, self.digest_config, &executor, path).await
+        }
+
         fn read_dir(&self, path: &AbsNormPathBuf) -> Result<ReadDir, IoError> {
             if let Some(barriers) = self.read_dir_barriers.as_ref() {
                 // Allow tests to advance here, execute something and then continue
@@ -320,8 +369,8 @@ mod state_machine {
             fs_util::read_dir(path)
         }
 
-        fn buck_out_path(&self) -> &ProjectRelativePathBuf {
-            &self.buck_out_path
+        fn roots(&self) -> &PhysicalRoots {
+            &self.roots
         }
 
         fn re_client_manager(&self) -> &Arc<ReConnectionManager> {
@@ -399,7 +448,7 @@ mod state_machine {
             .await
             .unwrap()?;
         // block until materialization_finished updates the tree
-        handle.receiver().recv().await;
+        handle.next_materialization().await;
         Ok(())
     }
 
@@ -436,6 +485,7 @@ mod state_machine {
                 sqlite_db: Some(db),
                 rt: Handle::current(),
                 defer_write_actions: true,
+                hardlink_deduplicated_writes: false,
                 log_buffer: LogBuffer::new(1),
                 version_tracker: VersionTracker::new(),
                 command_sender: command_sender.dupe(),
@@ -448,6 +498,10 @@ mod state_machine {
                 access_times_buffer: Default::default(),
                 verbose_materializer_log: true,
                 daemon_dispatcher,
+                prefetch_queue: PrefetchQueue::new(u64::MAX),
+                materialization_permits: None,
+                eager_paths: Vec::new(),
+                verify_declare_existing: false,
             },
             command_sender,
             command_receiver,
@@ -501,6 +555,7 @@ mod state_machine {
                     0,
                     AccessTimesUpdates::Disabled,
                     clean_stale_config,
+                    None,
                 ));
             }
         })
@@ -513,12 +568,14 @@ mod state_machine {
                 command_sender,
                 materialize_final_artifacts: true,
                 defer_write_actions: true,
+                write_dedup_index: Mutex::new(WriteDedupIndex::new(100)),
                 io,
                 materializer_state_info: buck2_data::MaterializerStateInfo {
                     num_entries_from_sqlite: 0,
                 },
                 stats: Arc::new(DeferredMaterializerStats::default()),
                 verbose_materializer_log: true,
+                prefetch: true,
             },
             handle,
             daemon_dispatcher_events,
@@ -670,6 +727,73 @@ mod state_machine {
         .await
     }
 
+    #[tokio::test]
+    async fn test_max_concurrent_materializations() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let paths = (0..6)
+                .map(|i| make_path(&format!("foo/{}", i)))
+                .collect::<Vec<_>>();
+            let symlink_path = make_path("bar/symlink");
+            let target_path = make_path("bar/target");
+            let target_from_symlink = RelativePathBuf::from_path(Path::new("target"))?;
+
+            let (mut dm, _) = make_processor(
+                paths
+                    .iter()
+                    .chain([&symlink_path, &target_path])
+                    .map(|p| (p.clone(), TokioDuration::from_millis(50)))
+                    .collect(),
+            );
+            let digest_config = dm.io.digest_config();
+            dm.materialization_permits = Some(Arc::new(tokio::sync::Semaphore::new(2)));
+
+            for path in &paths {
+                dm.declare(
+                    path,
+                    ArtifactValue::file(digest_config.empty_file()),
+                    Box::new(ArtifactMaterializationMethod::Test),
+                );
+            }
+            let futs = paths
+                .iter()
+                .map(|p| {
+                    dm.materialize_artifact(p, EventDispatcher::null())
+                        .context("Expected a future")
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            futures::future::try_join_all(futs)
+                .await
+                .map_err(|_| anyhow::anyhow!("error materializing"))?;
+            assert_eq!(2, dm.io.max_concurrent_materializations());
+
+            // With a single permit, an artifact whose symlink target also needs materializing
+            // doesn't wait on itself.
+            dm.materialization_permits = Some(Arc::new(tokio::sync::Semaphore::new(1)));
+            dm.declare(
+                &target_path,
+                ArtifactValue::file(digest_config.empty_file()),
+                Box::new(ArtifactMaterializationMethod::Test),
+            );
+            dm.declare(
+                &symlink_path,
+                make_artifact_value_with_symlink_dep(
+                    &target_path,
+                    &target_from_symlink,
+                    digest_config,
+                )?,
+                Box::new(ArtifactMaterializationMethod::Test),
+            );
+            dm.io.take_log();
+            dm.materialize_artifact(&symlink_path, EventDispatcher::null())
+                .context("Expected a future")?
+                .await
+                .map_err(|_| anyhow::anyhow!("error materializing"))?;
+            assert_eq!(2, dm.io.take_log().len());
+            Ok(())
+        })
+        .await
+    }
+
     #[tokio::test]
     async fn test_materialize_symlink_first_then_target() -> anyhow::Result<()> {
         ignore_stack_overflow_checks_for_future(async {
@@ -742,6 +866,48 @@ mod state_machine {
         .await
     }
 
+    #[tokio::test]
+    async fn test_eager_paths() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, _channel) = make_processor(Default::default());
+            let digest_config = dm.io.digest_config();
+            dm.eager_paths = vec![make_path("foo/eager")];
+
+            let eager = make_path("foo/eager/bar");
+            let eager_nested = make_path("foo/eager/baz/qux");
+            let lazy = make_path("foo/eagerness");
+            for path in [&eager, &eager_nested, &lazy] {
+                dm.process_one_command(MaterializerCommand::Declare(
+                    path.clone(),
+                    ArtifactValue::file(digest_config.empty_file()),
+                    Box::new(ArtifactMaterializationMethod::Test),
+                    EventDispatcher::null(),
+                ));
+            }
+
+            // The materializations are spawned, so yield until they ran.
+            let mut log = Vec::new();
+            while log.iter().filter(|(op, _)| *op == Op::Materialize).count() < 2 {
+                log.extend(dm.io.take_log());
+                tokio::task::yield_now().await;
+            }
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+            log.extend(dm.io.take_log());
+
+            let mut materialized = log
+                .into_iter()
+                .filter_map(|(op, path)| (op == Op::Materialize).then_some(path))
+                .collect::<Vec<_>>();
+            materialized.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            assert_eq!(materialized, vec![eager, eager_nested]);
+
+            Ok(())
+        })
+        .await
+    }
+
     #[tokio::test]
     async fn test_subscription_create_destroy() {
         let (mut dm, mut channel) = make_processor(Default::default());
@@ -792,12 +958,19 @@ mod state_machine {
             dm.declare_existing(&foo_bar_baz, value.dupe());
             dm.declare_existing(&qux, value.dupe());
 
-            let mut paths = Vec::new();
-            while let Ok(path) = handle.receiver().try_recv() {
-                paths.push(path);
+            let mut events = Vec::new();
+            while let Ok(event) = handle.receiver().try_recv() {
+                events.push(event);
             }
 
-            assert_eq!(paths, vec![foo_bar_baz.clone(), bar, foo_bar_baz]);
+            assert_eq!(
+                events,
+                vec![
+                    MaterializerSubscriptionEvent::Materialized(foo_bar_baz.clone()),
+                    MaterializerSubscriptionEvent::Materialized(bar),
+                    MaterializerSubscriptionEvent::Materialized(foo_bar_baz),
+                ]
+            );
         })
         .await
     }
@@ -850,12 +1023,69 @@ mod state_machine {
                 dm.process_one_low_priority_command(cmd);
             }
 
-            let mut paths = Vec::new();
-            while let Ok(path) = handle.receiver().try_recv() {
-                paths.push(path);
+            let mut events = Vec::new();
+            while let Ok(event) = handle.receiver().try_recv() {
+                events.push(event);
+            }
+            assert_eq!(
+                events,
+                vec![MaterializerSubscriptionEvent::Materialized(foo_bar)]
+            );
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_subscription_cleanup_and_invalidation() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, mut channel) = make_processor(Default::default());
+            let digest_config = dm.io.digest_config();
+            let value1 = ArtifactValue::file(digest_config.empty_file());
+            let value2 = ArtifactValue::dir(digest_config.empty_directory());
+
+            let mut handle = {
+                let (sender, recv) = oneshot::channel();
+                MaterializerSubscriptionOperation::Create { sender }.execute(&mut dm);
+                recv.await.unwrap()
+            };
+
+            let path = make_path("foo/bar");
+            handle.subscribe_to_paths(vec![path.clone()]);
+            while let Ok(cmd) = channel.high_priority.try_recv() {
+                dm.process_one_command(cmd);
+            }
+
+            for value in [value1, value2] {
+                dm.declare(&path, value, Box::new(ArtifactMaterializationMethod::Test));
+                // Wait for the cleanup of whatever was at the path before.
+                let cmd = channel.low_priority.recv().await.context("No cleanup")?;
+                dm.process_one_low_priority_command(cmd);
+
+                dm.materialize_artifact(&path, EventDispatcher::null())
+                    .context("Expected a future")?
+                    .await
+                    .map_err(|_| anyhow::anyhow!("error materializing"))?;
+                while let Ok(cmd) = channel.low_priority.try_recv() {
+                    dm.process_one_low_priority_command(cmd);
+                }
             }
-            assert_eq!(paths, vec![foo_bar]);
 
+            let mut events = Vec::new();
+            while let Ok(event) = handle.receiver().try_recv() {
+                events.push(event);
+            }
+            assert_eq!(
+                events,
+                vec![
+                    MaterializerSubscriptionEvent::Cleaned(path.clone()),
+                    MaterializerSubscriptionEvent::Materialized(path.clone()),
+                    MaterializerSubscriptionEvent::Invalidated(path.clone()),
+                    MaterializerSubscriptionEvent::Cleaned(path.clone()),
+                    MaterializerSubscriptionEvent::Materialized(path),
+                ]
+            );
             Ok(())
         })
         .await
@@ -898,13 +1128,82 @@ mod state_machine {
                 .unwrap();
             dm.declare_existing(&path, value2.dupe());
 
-            let mut paths = Vec::new();
-            while let Ok(path) = handle.receiver().try_recv() {
-                paths.push(path);
+            let mut events = Vec::new();
+            while let Ok(event) = handle.receiver().try_recv() {
+                events.push(event);
             }
 
             // Expect only one notification
-            assert_eq!(paths, vec![path]);
+            assert_eq!(
+                events,
+                vec![MaterializerSubscriptionEvent::Materialized(path)]
+            );
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_write_batches() -> anyhow::Result<()> {
+        async fn burst(max_pending_inserts: usize) -> anyhow::Result<Vec<(String, String)>> {
+            let (mut dm, mut channel) = make_processor(Default::default());
+            let digest_config = dm.io.digest_config();
+            dm.sqlite_db
+                .as_mut()
+                .context("db missing")?
+                .set_max_pending_inserts(max_pending_inserts);
+
+            let paths = (0..5)
+                .map(|i| make_path(&format!("foo/{}", i)))
+                .collect::<Vec<_>>();
+            for path in &paths {
+                dm.declare_existing(path, ArtifactValue::file(digest_config.empty_file()));
+            }
+
+            // Replace an artifact that was declared, then drop another one entirely.
+            dm.declare(
+                &paths[3],
+                ArtifactValue::dir(digest_config.empty_directory()),
+                Box::new(ArtifactMaterializationMethod::Test),
+            );
+            let cmd = channel.low_priority.recv().await.context("No cleanup")?;
+            dm.process_one_low_priority_command(cmd);
+            dm.materialize_artifact(&paths[3], EventDispatcher::null())
+                .context("Expected a future")?
+                .await
+                .map_err(|_| anyhow::anyhow!("error materializing"))?;
+            while let Ok(cmd) = channel.low_priority.try_recv() {
+                dm.process_one_low_priority_command(cmd);
+            }
+            dm.tree.invalidate_paths_and_collect_futures(
+                vec![paths[4].clone()],
+                dm.sqlite_db.as_mut(),
+            )?;
+
+            let sqlite_db = dm.sqlite_db.as_mut().context("db missing")?;
+            sqlite_db.flush_inserts()?;
+            let mut state = sqlite_db
+                .materializer_state_table()
+                .read_all(digest_config)?
+                .into_iter()
+                .map(|(path, (metadata, _timestamp))| (path.to_string(), metadata.0.to_string()))
+                .collect::<Vec<_>>();
+            state.sort();
+            Ok(state)
+        }
+
+        ignore_stack_overflow_checks_for_future(async {
+            let unbatched = burst(0).await?;
+            assert_eq!(
+                unbatched
+                    .iter()
+                    .map(|(path, _)| path.as_str())
+                    .collect::<Vec<_>>(),
+                vec!["foo/0", "foo/1", "foo/2", "foo/3"]
+            );
+            // Batches that get flushed when full, and one that is never full.
+            assert_eq!(burst(3).await?, unbatched);
+            assert_eq!(burst(100).await?, unbatched);
+            Ok(())
         })
         .await
     }
@@ -1098,6 +1397,95 @@ mod state_machine {
         }).await
     }
 
+    #[tokio::test]
+    async fn test_materialization_status_reports_failures() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, mut channel) = make_processor(Default::default());
+            let digest_config = dm.io.digest_config();
+
+            let path = make_path("foo/bar");
+            let undeclared = make_path("foo/baz");
+            dm.declare(
+                &path,
+                ArtifactValue::file(digest_config.empty_file()),
+                Box::new(ArtifactMaterializationMethod::Test),
+            );
+            dm.io.set_fail_on(vec![path.clone()]);
+
+            let status = |dm: &mut DeferredMaterializerCommandProcessor<StubIoHandler>| {
+                let (sender, mut receiver) = oneshot::channel();
+                Box::new(GetMaterializationStatus {
+                    paths: vec![path.clone(), undeclared.clone()],
+                    sender,
+                })
+                .execute(dm);
+                receiver.try_recv().unwrap()
+            };
+
+            let statuses = status(&mut dm);
+            assert_matches!(
+                &statuses[..],
+                [
+                    (
+                        _,
+                        Some(MaterializationStatus {
+                            stage: MaterializationStage::Declared,
+                            method: Some(..),
+                            failed_attempts: 0,
+                            last_error: None,
+                        })
+                    ),
+                    (_, None),
+                ]
+            );
+
+            for attempts in 1..=2 {
+                let res = dm
+                    .materialize_artifact(&path, EventDispatcher::null())
+                    .context("Expected a future")?
+                    .await;
+                assert_matches!(res, Err(..));
+                while let Ok(cmd) = channel.low_priority.try_recv() {
+                    dm.process_one_low_priority_command(cmd);
+                }
+
+                let statuses = status(&mut dm);
+                assert_matches!(
+                    &statuses[0].1,
+                    Some(MaterializationStatus {
+                        stage: MaterializationStage::Failed,
+                        failed_attempts,
+                        last_error: Some(e),
+                        ..
+                    }) if *failed_attempts == attempts && e.contains("Injected error")
+                );
+            }
+
+            // The failures are still reported once materializing succeeds.
+            dm.io.set_fail_on(vec![]);
+            dm.materialize_artifact(&path, EventDispatcher::null())
+                .context("Expected a future")?
+                .await
+                .map_err(|err| anyhow::anyhow!("error materializing {:?}", err))?;
+            while let Ok(cmd) = channel.low_priority.try_recv() {
+                dm.process_one_low_priority_command(cmd);
+            }
+            let statuses = status(&mut dm);
+            assert_matches!(
+                &statuses[0].1,
+                Some(MaterializationStatus {
+                    stage: MaterializationStage::Materialized,
+                    method: None,
+                    failed_attempts: 2,
+                    ..
+                })
+            );
+
+            Ok(())
+        })
+        .await
+    }
+
     #[tokio::test]
     async fn test_clean_stale() -> anyhow::Result<()> {
         ignore_stack_overflow_checks_for_future(async {
@@ -1139,6 +1527,81 @@ mod state_machine {
         .await
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_clean_stale_symlinked_buck_out() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let project = ProjectRootTemp::new()?;
+            let fast_disk = ProjectRootTemp::new()?;
+            let project_root = project.path().dupe();
+            let physical_buck_out = fast_disk.path().resolve(&make_path("buck-out"));
+            fs_util::create_dir_all(&physical_buck_out)?;
+            fs_util::symlink(
+                &physical_buck_out,
+                project_root.resolve(&make_path("buck-out")),
+            )?;
+
+            let roots = PhysicalRoots::detect(&project_root, &make_path("buck-out/v2"))?;
+            let io = Arc::new(StubIoHandler::new(project_root.dupe()).with_roots(roots.clone()));
+
+            let stale = make_path("buck-out/v2/gen/foo/bar");
+            let (dm, mut handle, _) = make_materializer(io.dupe(), None).await;
+            materialize_write(&stale, b"contents", &mut handle, &dm).await?;
+
+            // The artifact is found whichever way its path is spelled.
+            let physical_stale = fast_disk
+                .path()
+                .resolve(&make_path("buck-out/v2/gen/foo/bar"));
+            for spelling in [project_root.resolve(&stale), physical_stale.clone()] {
+                let path = roots.relativize(&spelling)?;
+                assert_eq!(stale, path);
+                assert_matches!(
+                    &dm.get_materialized_file_paths(vec![path]).await?[..],
+                    [Ok(p)] if *p == stale
+                );
+            }
+            dm.abort();
+
+            // Written through the physical spelling, so the materializer doesn't know about it.
+            let physical_untracked = fast_disk
+                .path()
+                .resolve(&make_path("buck-out/v2/gen/untracked"));
+            fs_util::write(&physical_untracked, b"untracked")?;
+
+            let (dm, _, _) = make_materializer(io, None).await;
+            let res = dm
+                .clean_stale_artifacts(DateTime::<Utc>::MAX_UTC, false, false)
+                .await?;
+            let &buck2_data::CleanStaleStats {
+                stale_artifact_count,
+                untracked_artifact_count,
+                cleaned_artifact_count,
+                ..
+            } = res
+                .stats
+                .as_ref()
+                .unwrap_or_else(|| panic!("{}", res.message.unwrap()));
+            assert_eq!(
+                (
+                    stale_artifact_count,
+                    untracked_artifact_count,
+                    cleaned_artifact_count
+                ),
+                (1, 1, 2)
+            );
+
+            assert!(!fs_util::try_exists(&physical_stale)?);
+            assert!(!fs_util::try_exists(&physical_untracked)?);
+            assert!(
+                fs_util::symlink_metadata(project_root.resolve(&make_path("buck-out")))?
+                    .is_symlink()
+            );
+            dm.abort();
+            Ok(())
+        })
+        .await
+    }
+
     #[tokio::test]
     async fn test_clean_stale_interrupt() -> anyhow::Result<()> {
         ignore_stack_overflow_checks_for_future(async {
@@ -1147,6 +1610,8 @@ mod state_machine {
             let io = Arc::new(StubIoHandler::new(project_root.clone()));
             let (dm, mut handle, _) = make_materializer(io.dupe(), None).await;
             materialize_write(&path, b"contents", &mut handle, &dm).await?;
+            // Drop dm and release the sqlite db, so that the next materializer can use it.
+            dm.abort();
 
             let read_dir_barriers =
                 Arc::new((std::sync::Barrier::new(2), std::sync::Barrier::new(2)));
@@ -1160,7 +1625,7 @@ mod state_machine {
             let dm = Arc::new(dm);
             let dm_dup = dm.dupe();
             let fut = dm_dup.clean_stale_artifacts(DateTime::<Utc>::MAX_UTC, false, false);
-            thread::spawn(move || {
+            let interrupt = thread::spawn(move || {
                 // Wait until a read_dir request is about to execute
                 read_dir_barriers.0.wait();
                 // Sending a high_priority command will interrupt the processor
@@ -1186,6 +1651,11 @@ mod state_machine {
                 ),
                 (0, 0, 0, 0)
             );
+            interrupt.join().unwrap();
+            Arc::try_unwrap(dm_dup)
+                .ok()
+                .expect("materializer should not be shared anymore")
+                .abort();
 
             let clean_barriers = Arc::new((Barrier::new(2), Barrier::new(2)));
             let io = Arc::new(
@@ -1294,14 +1764,1415 @@ mod state_machine {
         })
         .await
     }
-}
 
-#[test]
-fn test_materialize_stack_display() {
-    let s = MaterializeStack::Empty;
-    assert_eq!("(empty)", s.to_string());
-    let s = MaterializeStack::Child(&s, ProjectRelativePath::new("foo").unwrap());
-    assert_eq!("foo", s.to_string());
-    let s = MaterializeStack::Child(&s, ProjectRelativePath::new("bar/baz").unwrap());
-    assert_eq!("foo -> bar/baz", s.to_string());
+    #[tokio::test]
+    async fn test_clean_stale_skips_recent_and_processing_artifacts() -> anyhow::Result<()> {
+        let io = Arc::new(StubIoHandler::new(temp_root()));
+        let (mut processor, _, _, _) = make_processor_for_io(io.dupe());
+        let now = Utc::now();
+        let ttl = Duration::days(7);
+
+        let stale = make_path("buck-out/v2/gen/stale");
+        let recent = make_path("buck-out/v2/gen/recent");
+        let processing = make_path("buck-out/v2/gen/processing");
+        for (path, last_access_time, busy) in [
+            (&stale, now - ttl * 2, false),
+            (&recent, now - ttl / 2, false),
+            (&processing, now - ttl * 2, true),
+        ] {
+            fs_util::create_dir_all(io.fs().resolve(path.parent().unwrap()))?;
+            fs_util::write(io.fs().resolve(path), path.as_str())?;
+            let digest = TrackedFileDigest::from_content(
+                path.as_str().as_bytes(),
+                io.digest_config().cas_digest_config(),
+            );
+            let data = Box::new(ArtifactMaterializationData {
+                deps: None,
+                stage: ArtifactMaterializationStage::Materialized {
+                    metadata: ArtifactMetadata(ActionDirectoryEntry::Leaf(
+                        ActionDirectoryMember::File(FileMetadata {
+                            digest,
+                            is_executable: false,
+                        }),
+                    )),
+                    last_access_time,
+                    active: false,
+                    declared: None,
+                },
+                processing: if busy {
+                    Processing::Active {
+                        future: ProcessingFuture::Cleaning(
+                            futures::future::pending::<buck2_error::Result<()>>()
+                                .boxed()
+                                .shared(),
+                        ),
+                        version: Version(1),
+                    }
+                } else {
+                    Processing::Done(Version(0))
+                },
+                failures: None,
+            });
+            processor
+                .tree
+                .insert(path.iter().map(|f| f.to_owned()), data);
+        }
+
+        CleanStaleArtifactsCommand {
+            keep_since_time: now - ttl,
+            dry_run: false,
+            tracked_only: true,
+            dispatcher: EventDispatcher::null(),
+        }
+        .create_clean_fut(&mut processor, None)
+        .await?;
+
+        assert_eq!(io.take_log(), &[(Op::Clean, stale.clone())]);
+        assert!(!fs_util::try_exists(io.fs().resolve(&stale))?);
+        assert!(fs_util::try_exists(io.fs().resolve(&recent))?);
+        assert!(fs_util::try_exists(io.fs().resolve(&processing))?);
+        // The next use of the stale artifact materializes it again.
+        assert_matches!(processor.tree.prefix_get(&mut stale.iter()), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_reports_and_invalidates_mismatches() -> anyhow::Result<()> {
+        let io = Arc::new(StubIoHandler::new(temp_root()));
+        let (mut processor, _, _, _) = make_processor_for_io(io.dupe());
+
+        let intact = make_path("buck-out/v2/gen/intact");
+        let corrupted = make_path("buck-out/v2/gen/corrupted");
+        let missing = make_path("buck-out/v2/gen/missing");
+        let processing = make_path("buck-out/v2/gen/processing");
+        for (path, on_disk, busy) in [
+            (&intact, Some(intact.as_str()), false),
+            (&corrupted, Some("garbage"), false),
+            (&missing, None, false),
+            (&processing, Some("garbage"), true),
+        ] {
+            if let Some(on_disk) = on_disk {
+                fs_util::create_dir_all(io.fs().resolve(path.parent().unwrap()))?;
+                fs_util::write(io.fs().resolve(path), on_disk)?;
+            }
+            let digest = TrackedFileDigest::from_content(
+                path.as_str().as_bytes(),
+                io.digest_config().cas_digest_config(),
+            );
+            let data = Box::new(ArtifactMaterializationData {
+                deps: None,
+                stage: ArtifactMaterializationStage::Materialized {
+                    metadata: ArtifactMetadata(ActionDirectoryEntry::Leaf(
+                        ActionDirectoryMember::File(FileMetadata {
+                            digest,
+                            is_executable: false,
+                        }),
+                    )),
+                    last_access_time: Utc::now(),
+                    active: true,
+                    declared: None,
+                },
+                processing: if busy {
+                    Processing::Active {
+                        future: ProcessingFuture::Cleaning(
+                            futures::future::pending::<buck2_error::Result<()>>()
+                                .boxed()
+                                .shared(),
+                        ),
+                        version: Version(1),
+                    }
+                } else {
+                    Processing::Done(Version(0))
+                },
+                failures: None,
+            });
+            processor
+                .tree
+                .insert(path.iter().map(|f| f.to_owned()), data);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        Box::new(VerifyArtifactsCommand {
+            paths: Vec::new(),
+            sender,
+        })
+        .execute(&mut processor);
+        let outcome = receiver.await?.await?;
+        // Artifacts being processed are not checked.
+        assert_eq!(outcome.checked, 3);
+        let mut mismatches = outcome
+            .mismatches
+            .iter()
+            .map(|(_, mismatch)| (mismatch.path.clone(), &mismatch.found))
+            .collect::<Vec<_>>();
+        mismatches.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        assert_matches!(
+            &mismatches[..],
+            [
+                (p1, MaterializerVerifyFound::Entry(..)),
+                (p2, MaterializerVerifyFound::Missing),
+            ] if p1 == &corrupted && p2 == &missing
+        );
+
+        // Only the requested paths are checked.
+        let (sender, receiver) = oneshot::channel();
+        Box::new(VerifyArtifactsCommand {
+            paths: vec![make_path("buck-out/v2/gen/intact/a/b")],
+            sender,
+        })
+        .execute(&mut processor);
+        let outcome = receiver.await?.await?;
+        assert_eq!(outcome.checked, 1);
+        assert!(outcome.mismatches.is_empty());
+
+        // Artifacts declared again since they were hashed are kept.
+        let (sender, receiver) = oneshot::channel();
+        Box::new(InvalidateMismatchesCommand {
+            mismatches: vec![
+                (corrupted.clone(), Version(0)),
+                (missing.clone(), Version(2)),
+            ],
+            sender,
+        })
+        .execute(&mut processor);
+        assert_eq!(receiver.await??, vec![corrupted.clone()]);
+        assert_matches!(processor.tree.prefix_get(&mut corrupted.iter()), None);
+        assert_matches!(processor.tree.prefix_get(&mut missing.iter()), Some(..));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalidated_mismatch_is_materialized_again() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, _) = make_processor(Default::default());
+            let digest_config = dm.io.digest_config();
+
+            let path = make_path("foo/bar");
+            let value = ArtifactValue::file(digest_config.empty_file());
+            dm.declare(&path, value, Box::new(ArtifactMaterializationMethod::Test));
+            let res = dm
+                .materialize_artifact(&path, EventDispatcher::null())
+                .context("Expected a future")?
+                .await;
+            dm.materialization_finished(
+                path.clone(),
+                Utc::now(),
+                dm.version_tracker.current(),
+                res,
+            );
+            dm.io.take_log();
+
+            // The artifact was found corrupted on disk: it is removed, and declared again.
+            let (sender, receiver) = oneshot::channel();
+            Box::new(InvalidateMismatchesCommand {
+                mismatches: vec![(path.clone(), dm.version_tracker.current())],
+                sender,
+            })
+            .execute(&mut dm);
+            assert_eq!(receiver.await??, vec![path.clone()]);
+            assert_eq!(dm.io.take_log(), &[(Op::Clean, path.clone())]);
+            assert_matches!(
+                declared_method(&dm, &path).as_deref(),
+                Some(ArtifactMaterializationMethod::Test)
+            );
+
+            // The next time it is needed, it is materialized again.
+            let res = dm
+                .materialize_artifact(&path, EventDispatcher::null())
+                .context("Expected a future")?
+                .await;
+            assert_eq!(dm.io.take_log(), &[(Op::Materialize, path.clone())]);
+            dm.materialization_finished(
+                path.clone(),
+                Utc::now(),
+                dm.version_tracker.current(),
+                res,
+            );
+            assert_matches!(
+                dm.tree.prefix_get(&mut path.iter()).map(|data| &data.stage),
+                Some(ArtifactMaterializationStage::Materialized { .. })
+            );
+
+            Ok(())
+        })
+        .await
+    }
+
+    fn declare_existing_on_disk(
+        dm: &mut DeferredMaterializerCommandProcessor<StubIoHandler>,
+        path: &ProjectRelativePathBuf,
+        declared: &str,
+        on_disk: &str,
+    ) -> anyhow::Result<()> {
+        let fs = dm.io.fs();
+        fs_util::create_dir_all(fs.resolve(path.parent().unwrap()))?;
+        fs_util::write(fs.resolve(path), on_disk)?;
+        let value = ArtifactValue::file(FileMetadata {
+            digest: TrackedFileDigest::from_content(
+                declared.as_bytes(),
+                dm.io.digest_config().cas_digest_config(),
+            ),
+            is_executable: false,
+        });
+        dm.declare_existing(path, value);
+        Ok(())
+    }
+
+    fn receive_declare_existing_mismatch(
+        events: &mut ChannelEventSource,
+    ) -> Option<buck2_data::MaterializerDeclareExistingMismatch> {
+        while let Some(event) = events.try_receive() {
+            if let buck2_data::buck_event::Data::Instant(instant) =
+                event.unpack_buck().unwrap().data()
+            {
+                if let Some(buck2_data::instant_event::Data::MaterializerDeclareExistingMismatch(
+                    mismatch,
+                )) = &instant.data
+                {
+                    return Some(mismatch.clone());
+                }
+            }
+        }
+        None
+    }
+
+    #[tokio::test]
+    async fn test_declare_existing_verify_match() -> anyhow::Result<()> {
+        let foo = make_path("buck-out/v2/gen/foo");
+        let bar = make_path("buck-out/v2/gen/bar");
+        let io = Arc::new(StubIoHandler::new(temp_root()));
+        let (mut dm, _, mut channel, mut events) = make_processor_for_io(io.dupe());
+
+        // Nothing is checked unless enabled.
+        declare_existing_on_disk(&mut dm, &foo, "foo", "garbage")?;
+        assert!(channel.low_priority.try_recv().is_err());
+        assert!(io.take_log().is_empty());
+
+        dm.verify_declare_existing = true;
+        declare_existing_on_disk(&mut dm, &bar, "bar", "bar")?;
+        let cmd = channel
+            .low_priority
+            .recv()
+            .await
+            .context("No verification")?;
+        assert_matches!(
+            cmd,
+            LowPriorityMaterializerCommand::DeclareExistingVerified { mismatch: None, .. }
+        );
+        dm.process_one_low_priority_command(cmd);
+
+        assert_eq!(io.take_log(), &[(Op::Verify, bar.clone())]);
+        assert_matches!(receive_declare_existing_mismatch(&mut events), None);
+        assert_matches!(
+            dm.tree.prefix_get(&mut bar.iter()).map(|data| &data.stage),
+            Some(ArtifactMaterializationStage::Materialized { .. })
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_declare_existing_verify_symlinked_buck_out() -> anyhow::Result<()> {
+        let project = ProjectRootTemp::new()?;
+        let fast_disk = ProjectRootTemp::new()?;
+        let project_root = project.path().dupe();
+        let physical_buck_out = fast_disk.path().resolve(&make_path("buck-out"));
+        fs_util::create_dir_all(&physical_buck_out)?;
+        fs_util::symlink(
+            &physical_buck_out,
+            project_root.resolve(&make_path("buck-out")),
+        )?;
+
+        let roots = PhysicalRoots::detect(&project_root, &make_path("buck-out/v2"))?;
+        let io = Arc::new(StubIoHandler::new(project_root.dupe()).with_roots(roots.clone()));
+        let (mut dm, _, mut channel, mut events) = make_processor_for_io(io.dupe());
+        dm.verify_declare_existing = true;
+
+        // Declared through where it is on disk, and checked through where buck-out is.
+        let physical = fast_disk.path().resolve(&make_path("buck-out/v2/gen/foo"));
+        fs_util::create_dir_all(physical.parent().unwrap())?;
+        fs_util::write(&physical, "foo")?;
+        let foo = roots.relativize(&physical)?;
+        assert_eq!(make_path("buck-out/v2/gen/foo"), foo);
+        let value = ArtifactValue::file(FileMetadata {
+            digest: TrackedFileDigest::from_content(
+                b"foo",
+                dm.io.digest_config().cas_digest_config(),
+            ),
+            is_executable: false,
+        });
+        dm.declare_existing(&foo, value);
+
+        let cmd = channel
+            .low_priority
+            .recv()
+            .await
+            .context("No verification")?;
+        assert_matches!(
+            cmd,
+            LowPriorityMaterializerCommand::DeclareExistingVerified { mismatch: None, .. }
+        );
+        dm.process_one_low_priority_command(cmd);
+        assert_matches!(receive_declare_existing_mismatch(&mut events), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_declare_existing_verify_mismatch() -> anyhow::Result<()> {
+        let foo = make_path("buck-out/v2/gen/foo");
+        let io = Arc::new(StubIoHandler::new(temp_root()));
+        let (mut dm, _, mut channel, mut events) = make_processor_for_io(io.dupe());
+        dm.verify_declare_existing = true;
+
+        declare_existing_on_disk(&mut dm, &foo, "foo", "garbage")?;
+        let stale = channel
+            .low_priority
+            .recv()
+            .await
+            .context("No verification")?;
+        // Declared again before the outcome of the first check is processed.
+        declare_existing_on_disk(&mut dm, &foo, "foo", "garbage")?;
+        let current = channel
+            .low_priority
+            .recv()
+            .await
+            .context("No verification")?;
+        assert_eq!(
+            io.take_log(),
+            &[(Op::Verify, foo.clone()), (Op::Verify, foo.clone())]
+        );
+
+        // The mismatch is reported, but the artifact is only invalidated if it wasn't declared
+        // again since.
+        dm.process_one_low_priority_command(stale);
+        let mismatch = receive_declare_existing_mismatch(&mut events).context("No event")?;
+        assert_eq!(mismatch.path, foo.as_str());
+        assert!(!mismatch.invalidated);
+        assert_matches!(dm.tree.prefix_get(&mut foo.iter()), Some(..));
+
+        dm.process_one_low_priority_command(current);
+        let mismatch = receive_declare_existing_mismatch(&mut events).context("No event")?;
+        assert!(mismatch.invalidated);
+        assert_ne!(mismatch.expected, mismatch.found);
+        // It stays declared, but using it fails instead of reading what's on disk.
+        assert_matches!(
+            dm.tree.prefix_get(&mut foo.iter()).map(|data| &data.stage),
+            Some(ArtifactMaterializationStage::Declared { .. })
+        );
+        let failed = ignore_stack_overflow_checks_for_future(async {
+            anyhow::Ok(
+                dm.materialize_artifact(&foo, EventDispatcher::null())
+                    .context("Expected a future")?
+                    .await
+                    .is_err(),
+            )
+        })
+        .await?;
+        assert!(failed);
+        assert!(io.take_log().is_empty());
+
+        // Until it is declared again.
+        declare_existing_on_disk(&mut dm, &foo, "foo", "foo")?;
+        assert_matches!(
+            dm.tree.prefix_get(&mut foo.iter()).map(|data| &data.stage),
+            Some(ArtifactMaterializationStage::Materialized { .. })
+        );
+        Ok(())
+    }
+
+    fn write_request(path: &ProjectRelativePathBuf, contents: &[u8]) -> WriteRequest {
+        WriteRequest {
+            path: path.clone(),
+            content: contents.to_vec(),
+            is_executable: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_identical_writes_share_contents() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let foo = make_path("buck-out/v2/gen/foo");
+            let bar = make_path("buck-out/v2/gen/bar");
+            let baz = make_path("buck-out/v2/gen/baz");
+            let io = Arc::new(StubIoHandler::new(temp_root()));
+            let (dm, mut handle, _) = make_materializer(io.dupe(), None).await;
+
+            dm.declare_write(Box::new(|| {
+                Ok(vec![
+                    write_request(&foo, b"contents"),
+                    write_request(&bar, b"contents"),
+                    write_request(&baz, b"other contents"),
+                ])
+            }))
+            .await?;
+            assert_eq!(1, dm.stats.write_dedup_hits.load(Ordering::Relaxed));
+
+            // Invalidating one of the identical writes doesn't affect the other.
+            dm.invalidate_many(vec![foo.clone()]).await?;
+            handle.subscribe_to_paths(vec![bar.clone()]);
+            dm.materialize_many(vec![bar.clone()])
+                .await?
+                .next()
+                .await
+                .unwrap()?;
+            handle.next_materialization().await;
+            assert_eq!("contents", fs_util::read_to_string(io.fs().resolve(&bar))?);
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_hardlink_source() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, _) = make_processor(Default::default());
+            dm.hardlink_deduplicated_writes = true;
+            let digest_config = dm.io.digest_config();
+
+            let foo = make_path("buck-out/v2/gen/foo");
+            let metadata = FileMetadata {
+                digest: TrackedFileDigest::from_content(
+                    b"contents",
+                    digest_config.cas_digest_config(),
+                ),
+                is_executable: false,
+            };
+            let write = Arc::new(WriteFile::compress(b"contents", &metadata)?);
+            let value = ArtifactValue::file(metadata);
+
+            dm.declare(
+                &foo,
+                value.dupe(),
+                Box::new(ArtifactMaterializationMethod::Write(write.dupe())),
+            );
+            assert_eq!(None, dm.hardlink_source(&foo, &write, value.entry()));
+
+            dm.materialization_finished(
+                foo.clone(),
+                Utc::now(),
+                dm.version_tracker.current(),
+                Ok(()),
+            );
+            assert_eq!(
+                Some(foo.clone()),
+                dm.hardlink_source(&foo, &write, value.entry())
+            );
+
+            // Once something else is declared at the path, it's no longer used.
+            dm.declare(
+                &foo,
+                ArtifactValue::file(digest_config.empty_file()),
+                Box::new(ArtifactMaterializationMethod::Test),
+            );
+            assert_eq!(None, dm.hardlink_source(&foo, &write, value.entry()));
+            Ok(())
+        })
+        .await
+    }
+
+    #[test]
+    fn test_write_or_link_checks_digest() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = temp.path();
+        let digest_config = DigestConfig::testing_default();
+        let metadata = FileMetadata {
+            digest: TrackedFileDigest::from_content(b"aaaa", digest_config.cas_digest_config()),
+            is_executable: false,
+        };
+        let write = WriteFile::compress(b"aaaa", &metadata)?;
+
+        // The file to link from was overwritten with other contents of the same size.
+        let source = make_path("buck-out/v2/gen/source");
+        let dest = make_path("buck-out/v2/gen/dest");
+        fs.write_file(&source, "bbbb", false)?;
+        write_or_link(fs, &dest, &write, Some(&source), digest_config)?;
+        assert_eq!("aaaa", fs_util::read_to_string(fs.resolve(&dest))?);
+        assert_eq!("bbbb", fs_util::read_to_string(fs.resolve(&source))?);
+
+        // Identical contents are linked.
+        let other = make_path("buck-out/v2/gen/other");
+        write_or_link(fs, &other, &write, Some(&dest), digest_config)?;
+        assert_eq!("aaaa", fs_util::read_to_string(fs.resolve(&other))?);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(
+                fs_util::symlink_metadata(fs.resolve(&dest))?.ino(),
+                fs_util::symlink_metadata(fs.resolve(&other))?.ino()
+            );
+        }
+        Ok(())
+    }
+
+    fn file_of_size(digest_config: DigestConfig, size: usize) -> ArtifactValue {
+        ArtifactValue::file(FileMetadata {
+            digest: TrackedFileDigest::from_content(
+                &vec![0; size],
+                digest_config.cas_digest_config(),
+            ),
+            is_executable: false,
+        })
+    }
+
+    fn prefetch(
+        dm: &mut DeferredMaterializerCommandProcessor<StubIoHandler>,
+        id: PrefetchId,
+        paths: &[&ProjectRelativePathBuf],
+    ) {
+        dm.process_one_low_priority_command(LowPriorityMaterializerCommand::Prefetch {
+            id,
+            paths: paths.iter().map(|p| (*p).clone()).collect(),
+            event_dispatcher: EventDispatcher::null(),
+        });
+    }
+
+    /// Process low priority commands until a prefetch finishes.
+    async fn wait_for_prefetch(
+        dm: &mut DeferredMaterializerCommandProcessor<StubIoHandler>,
+        channel: &mut MaterializerReceiver<StubIoHandler>,
+    ) {
+        while let Some(cmd) = channel.low_priority.recv().await {
+            let finished = matches!(cmd, LowPriorityMaterializerCommand::PrefetchFinished { .. });
+            dm.process_one_low_priority_command(cmd);
+            if finished {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefetch() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, mut channel) = make_processor(Default::default());
+            let digest_config = dm.io.digest_config();
+
+            let foo = make_path("foo");
+            let unknown = make_path("unknown");
+            dm.declare(
+                &foo,
+                file_of_size(digest_config, 10),
+                Box::new(ArtifactMaterializationMethod::Test),
+            );
+            dm.io.take_log();
+
+            // Paths that were never declared are ignored.
+            prefetch(&mut dm, PrefetchId::next(), &[&foo, &unknown]);
+            assert_eq!(10, dm.prefetch_queue.in_flight_bytes());
+            assert_eq!(0, dm.prefetch_queue.pending_len());
+
+            wait_for_prefetch(&mut dm, &mut channel).await;
+            assert_eq!(dm.io.take_log(), &[(Op::Materialize, foo.clone())]);
+            assert!(dm.is_path_materialized(&foo));
+            assert_eq!(0, dm.prefetch_queue.in_flight_bytes());
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_dedup() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let materialized = make_path("materialized");
+            let materializing = make_path("materializing");
+            let prefetched = make_path("prefetched");
+
+            let (mut dm, mut channel) = make_processor(HashMap::from([(
+                materializing.clone(),
+                TokioDuration::from_millis(100),
+            )]));
+            let digest_config = dm.io.digest_config();
+            for path in [&materialized, &materializing, &prefetched] {
+                dm.declare(
+                    path,
+                    file_of_size(digest_config, 10),
+                    Box::new(ArtifactMaterializationMethod::Test),
+                );
+            }
+
+            dm.materialize_artifact(&materialized, EventDispatcher::null())
+                .context("Expected a future")?
+                .await
+                .map_err(|_| anyhow::anyhow!("error materializing"))?;
+            while let Ok(cmd) = channel.low_priority.try_recv() {
+                dm.process_one_low_priority_command(cmd);
+            }
+            let materializing_fut = dm
+                .materialize_artifact(&materializing, EventDispatcher::null())
+                .context("Expected a future")?;
+            dm.io.take_log();
+
+            // Nothing to do for artifacts that are materialized or being materialized.
+            prefetch(
+                &mut dm,
+                PrefetchId::next(),
+                &[&materialized, &materializing],
+            );
+            assert_eq!(0, dm.prefetch_queue.in_flight_bytes());
+            assert_eq!(0, dm.prefetch_queue.pending_len());
+
+            // Two actions with the same input only prefetch it once.
+            prefetch(&mut dm, PrefetchId::next(), &[&prefetched]);
+            prefetch(&mut dm, PrefetchId::next(), &[&prefetched]);
+            assert_eq!(10, dm.prefetch_queue.in_flight_bytes());
+            assert_eq!(0, dm.prefetch_queue.pending_len());
+
+            wait_for_prefetch(&mut dm, &mut channel).await;
+            materializing_fut
+                .await
+                .map_err(|_| anyhow::anyhow!("error materializing"))?;
+            assert_eq!(
+                dm.io.take_log(),
+                &[
+                    (Op::Materialize, prefetched.clone()),
+                    (Op::Materialize, materializing.clone()),
+                ]
+            );
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_max_bytes() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, mut channel) = make_processor(Default::default());
+            dm.prefetch_queue = PrefetchQueue::new(25);
+            let digest_config = dm.io.digest_config();
+
+            let paths = ["a", "b", "c", "large"].map(make_path);
+            for (path, size) in paths.iter().zip([10, 10, 10, 100]) {
+                dm.declare(
+                    path,
+                    file_of_size(digest_config, size),
+                    Box::new(ArtifactMaterializationMethod::Test),
+                );
+            }
+            dm.io.take_log();
+
+            prefetch(
+                &mut dm,
+                PrefetchId::next(),
+                &paths.iter().collect::<Vec<_>>(),
+            );
+            assert_eq!(20, dm.prefetch_queue.in_flight_bytes());
+            assert_eq!(2, dm.prefetch_queue.pending_len());
+
+            // Each prefetch that finishes makes room for the next.
+            wait_for_prefetch(&mut dm, &mut channel).await;
+            assert_eq!(20, dm.prefetch_queue.in_flight_bytes());
+            assert_eq!(1, dm.prefetch_queue.pending_len());
+
+            // Artifacts larger than the cap are only prefetched once nothing else is.
+            wait_for_prefetch(&mut dm, &mut channel).await;
+            assert_eq!(10, dm.prefetch_queue.in_flight_bytes());
+            assert_eq!(1, dm.prefetch_queue.pending_len());
+            wait_for_prefetch(&mut dm, &mut channel).await;
+            assert_eq!(100, dm.prefetch_queue.in_flight_bytes());
+            assert_eq!(0, dm.prefetch_queue.pending_len());
+
+            wait_for_prefetch(&mut dm, &mut channel).await;
+            assert_eq!(0, dm.prefetch_queue.in_flight_bytes());
+            for path in &paths {
+                assert!(dm.is_path_materialized(path));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_withdraw() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, mut channel) = make_processor(Default::default());
+            dm.prefetch_queue = PrefetchQueue::new(10);
+            let digest_config = dm.io.digest_config();
+
+            let [a, b, c] = ["a", "b", "c"].map(make_path);
+            for path in [&a, &b, &c] {
+                dm.declare(
+                    path,
+                    file_of_size(digest_config, 10),
+                    Box::new(ArtifactMaterializationMethod::Test),
+                );
+            }
+            dm.io.take_log();
+
+            let cancelled = PrefetchId::next();
+            prefetch(&mut dm, cancelled, &[&a, &b]);
+            prefetch(&mut dm, PrefetchId::next(), &[&c]);
+            assert_eq!(2, dm.prefetch_queue.pending_len());
+
+            // The action was cancelled while `a` was being prefetched: `a` still finishes, but
+            // `b` is not prefetched.
+            dm.process_one_low_priority_command(LowPriorityMaterializerCommand::WithdrawPrefetch(
+                cancelled,
+            ));
+            assert_eq!(1, dm.prefetch_queue.pending_len());
+            assert!(!dm.prefetch_queue.contains(&b));
+            assert!(dm.prefetch_queue.contains(&c));
+
+            wait_for_prefetch(&mut dm, &mut channel).await;
+            wait_for_prefetch(&mut dm, &mut channel).await;
+            assert_eq!(
+                dm.io.take_log(),
+                &[(Op::Materialize, a.clone()), (Op::Materialize, c.clone())]
+            );
+            assert!(!dm.is_path_materialized(&b));
+            assert_eq!(0, dm.prefetch_queue.pending_len());
+            Ok(())
+        })
+        .await
+    }
+
+    fn declare_cas(
+        dm: &mut DeferredMaterializerCommandProcessor<StubIoHandler>,
+        path: &ProjectRelativePathBuf,
+        size: usize,
+    ) {
+        let digest_config = dm.io.digest_config();
+        dm.declare(
+            path,
+            file_of_size(digest_config, size),
+            Box::new(ArtifactMaterializationMethod::CasDownload {
+                info: Arc::new(CasDownloadInfo::new_declared(
+                    RemoteExecutorUseCase::buck2_default(),
+                )),
+            }),
+        );
+    }
+
+    fn declared_method(
+        dm: &DeferredMaterializerCommandProcessor<StubIoHandler>,
+        path: &ProjectRelativePathBuf,
+    ) -> Option<Arc<ArtifactMaterializationMethod>> {
+        match &dm.tree.prefix_get(&mut path.iter())?.stage {
+            ArtifactMaterializationStage::Declared { method, .. } => Some(method.dupe()),
+            ArtifactMaterializationStage::Materialized { .. } => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_inputs_claims_large_cas_files() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, mut channel) = make_processor(Default::default());
+            let digest_config = dm.io.digest_config();
+            let size = STREAMED_INPUT_MIN_SIZE as usize;
+
+            let [large, small, written] = ["large", "small", "written"].map(make_path);
+            declare_cas(&mut dm, &large, size);
+            declare_cas(&mut dm, &small, 10);
+            dm.declare(
+                &written,
+                file_of_size(digest_config, size),
+                Box::new(ArtifactMaterializationMethod::Test),
+            );
+            dm.io.take_log();
+
+            let claims = dm.claim_streamed_inputs(&[large.clone(), small.clone(), written]);
+            assert_eq!(
+                claims.iter().map(|c| &c.path).collect::<Vec<_>>(),
+                vec![&large]
+            );
+            assert_matches!(
+                declared_method(&dm, &large).as_deref(),
+                Some(ArtifactMaterializationMethod::Streamed { .. })
+            );
+            assert_matches!(
+                declared_method(&dm, &small).as_deref(),
+                Some(ArtifactMaterializationMethod::CasDownload { .. })
+            );
+
+            // The action was cancelled: the input goes back to being downloaded when needed.
+            drop(claims);
+            while let Ok(cmd) = channel.low_priority.try_recv() {
+                dm.process_one_low_priority_command(cmd);
+            }
+            assert_matches!(
+                declared_method(&dm, &large).as_deref(),
+                Some(ArtifactMaterializationMethod::CasDownload { .. })
+            );
+            assert!(!dm.is_path_materialized(&large));
+            assert_eq!(dm.io.take_log(), &[]);
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_streamed_input_left_on_disk_is_materialized() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, mut channel) = make_processor(Default::default());
+            let path = make_path("large");
+            declare_cas(&mut dm, &path, STREAMED_INPUT_MIN_SIZE as usize);
+            dm.io.take_log();
+
+            let mut claims = dm.claim_streamed_inputs(&[path.clone()]);
+            // The action read the input in a way a named pipe doesn't support, so it was
+            // written to disk instead.
+            claims.pop().context("Expected a claim")?.release(true);
+            while let Ok(cmd) = channel.low_priority.try_recv() {
+                dm.process_one_low_priority_command(cmd);
+            }
+
+            assert!(dm.is_path_materialized(&path));
+            assert!(
+                dm.materialize_artifact(&path, EventDispatcher::null())
+                    .is_none()
+            );
+            assert_eq!(dm.io.take_log(), &[]);
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_streamed_input_materialization_waits_for_release() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, mut channel) = make_processor(Default::default());
+            let path = make_path("large");
+            declare_cas(&mut dm, &path, STREAMED_INPUT_MIN_SIZE as usize);
+            dm.io.take_log();
+
+            let claims = dm.claim_streamed_inputs(&[path.clone()]);
+            assert_eq!(1, claims.len());
+
+            // Something else needs the artifact while the action still reads it through the
+            // named pipe: writing it must wait until the pipe is gone.
+            let mut fut = dm
+                .materialize_artifact(&path, EventDispatcher::null())
+                .context("Expected a future")?;
+            assert!(
+                tokio::time::timeout(TokioDuration::from_millis(50), &mut fut)
+                    .await
+                    .is_err()
+            );
+            assert_eq!(dm.io.take_log(), &[]);
+
+            drop(claims);
+            fut.await
+                .map_err(|err| anyhow::anyhow!("error materializing {:?}", err))?;
+            assert_eq!(dm.io.take_log(), &[(Op::Materialize, path.clone())]);
+
+            while let Ok(cmd) = channel.low_priority.try_recv() {
+                dm.process_one_low_priority_command(cmd);
+            }
+            assert!(dm.is_path_materialized(&path));
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_stream_inputs_skips_copied_inputs() -> anyhow::Result<()> {
+        ignore_stack_overflow_checks_for_future(async {
+            let (mut dm, _channel) = make_processor(Default::default());
+            let digest_config = dm.io.digest_config();
+            let size = STREAMED_INPUT_MIN_SIZE as usize;
+
+            let [src, dest, other] = ["src", "dest", "other"].map(make_path);
+            declare_cas(&mut dm, &src, size);
+            declare_cas(&mut dm, &other, size);
+            let meta = FileMetadata {
+                digest: TrackedFileDigest::from_content(
+                    &vec![0; size],
+                    digest_config.cas_digest_config(),
+                ),
+                is_executable: false,
+            };
+            let copied = CopiedArtifact::new(
+                src.clone(),
+                dest.clone(),
+                ActionDirectoryEntry::Leaf(ActionDirectoryMember::File(meta.dupe())),
+            );
+            dm.declare(
+                &dest,
+                ArtifactValue::file(meta),
+                Box::new(ArtifactMaterializationMethod::LocalCopy(
+                    FileTree::new(),
+                    vec![copied],
+                )),
+            );
+
+            // Materializing `dest` copies `src`, which would wait for the action that waits for
+            // `dest`.
+            let claims = dm.claim_streamed_inputs(&[src.clone(), dest, other.clone()]);
+            assert_eq!(
+                claims.iter().map(|c| &c.path).collect::<Vec<_>>(),
+                vec![&other]
+            );
+            assert_matches!(
+                declared_method(&dm, &src).as_deref(),
+                Some(ArtifactMaterializationMethod::CasDownload { .. })
+            );
+            Ok(())
+        })
+        .await
+    }
+
+    /// Soak test of the state machine: random interleavings of declarations, materializations,
+    /// completions delivered out of order, subscriptions, invalidations and IO failures, with
+    /// invariants checked after every step.
+    mod soak {
+        use rand::Rng;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        use super::*;
+
+        /// Nested paths, so that declarations overlap each other.
+        const PATHS: &[&str] = &["a", "a/b", "a/b/c", "d", "d/e", "f"];
+
+        #[derive(Debug, Clone, Copy)]
+        enum Step {
+            /// Declare `PATHS[path]` as a file with contents `content`. Few distinct contents are
+            /// used, so that some declarations match what's already materialized.
+            Declare {
+                path: usize,
+                content: u8,
+            },
+            Materialize {
+                path: usize,
+            },
+            /// Deliver one of the pending low priority commands, picked modulo their count.
+            Deliver {
+                pick: usize,
+            },
+            Subscribe {
+                path: usize,
+            },
+            Unsubscribe {
+                path: usize,
+            },
+            Invalidate {
+                path: usize,
+            },
+            SetFailing {
+                path: usize,
+                fail: bool,
+            },
+        }
+
+        impl Step {
+            fn random(rng: &mut ChaCha8Rng) -> Self {
+                let path = rng.gen_range(0..PATHS.len());
+                match rng.gen_range(0..100) {
+                    0..=24 => Step::Declare {
+                        path,
+                        content: rng.gen_range(0..2),
+                    },
+                    25..=44 => Step::Materialize { path },
+                    45..=69 => Step::Deliver { pick: rng.gen() },
+                    70..=77 => Step::Subscribe { path },
+                    78..=82 => Step::Unsubscribe { path },
+                    83..=92 => Step::Invalidate { path },
+                    _ => Step::SetFailing {
+                        path,
+                        fail: rng.gen_bool(0.5),
+                    },
+                }
+            }
+        }
+
+        struct Harness {
+            dm: DeferredMaterializerCommandProcessor<StubIoHandler>,
+            channel: MaterializerReceiver<StubIoHandler>,
+            handle: SubscriptionHandle<StubIoHandler>,
+            /// Low priority commands sent by the IO, which are delivered in random order.
+            pending: Vec<LowPriorityMaterializerCommand>,
+            /// All the IO operations so far.
+            log: Vec<(Op, ProjectRelativePathBuf)>,
+            /// The paths the subscription is subscribed to.
+            subscribed: HashSet<ProjectRelativePathBuf>,
+            failing: Vec<ProjectRelativePathBuf>,
+            /// The version of each path in the tree after the previous step.
+            versions: HashMap<ProjectRelativePathBuf, Version>,
+            current_version: Version,
+        }
+
+        impl Harness {
+            async fn new() -> Self {
+                let (mut dm, channel) = make_processor(Default::default());
+                let handle = {
+                    let (sender, recv) = oneshot::channel();
+                    MaterializerSubscriptionOperation::Create { sender }.execute(&mut dm);
+                    recv.await.unwrap()
+                };
+                let current_version = dm.version_tracker.current();
+                Self {
+                    dm,
+                    channel,
+                    handle,
+                    pending: Vec::new(),
+                    log: Vec::new(),
+                    subscribed: HashSet::new(),
+                    failing: Vec::new(),
+                    versions: HashMap::new(),
+                    current_version,
+                }
+            }
+
+            fn process_high_priority(&mut self) {
+                while let Ok(cmd) = self.channel.high_priority.try_recv() {
+                    self.dm.process_one_command(cmd);
+                }
+            }
+
+            async fn run(&mut self, step: Step) -> anyhow::Result<()> {
+                match step {
+                    Step::Declare { path, content } => {
+                        let value = ArtifactValue::file(FileMetadata {
+                            digest: TrackedFileDigest::from_content(
+                                &[content],
+                                self.dm.io.digest_config().cas_digest_config(),
+                            ),
+                            is_executable: false,
+                        });
+                        self.dm.process_one_command(MaterializerCommand::Declare(
+                            make_path(PATHS[path]),
+                            value,
+                            Box::new(ArtifactMaterializationMethod::Test),
+                            EventDispatcher::null(),
+                        ));
+                    }
+                    Step::Materialize { path } => {
+                        // The materialization is spawned, its outcome is observed through the
+                        // low priority commands.
+                        drop(self.dm.materialize_artifact(
+                            &make_path(PATHS[path]),
+                            EventDispatcher::null(),
+                        ));
+                    }
+                    Step::Deliver { pick } => {
+                        if !self.pending.is_empty() {
+                            let cmd = self.pending.remove(pick % self.pending.len());
+                            self.dm.process_one_low_priority_command(cmd);
+                        }
+                    }
+                    Step::Subscribe { path } => {
+                        self.subscribed.insert(make_path(PATHS[path]));
+                        self.handle.subscribe_to_paths(vec![make_path(PATHS[path])]);
+                        self.process_high_priority();
+                    }
+                    Step::Unsubscribe { path } => {
+                        self.handle
+                            .unsubscribe_from_paths(vec![make_path(PATHS[path])]);
+                        self.process_high_priority();
+                    }
+                    Step::Invalidate { path } => {
+                        let (sender, _recv) = oneshot::channel();
+                        self.dm
+                            .process_one_command(MaterializerCommand::InvalidateFilePaths(
+                                vec![make_path(PATHS[path])],
+                                sender,
+                                EventDispatcher::null(),
+                            ));
+                    }
+                    Step::SetFailing { path, fail } => {
+                        let path = make_path(PATHS[path]);
+                        self.failing.retain(|p| p != &path);
+                        if fail {
+                            self.failing.push(path);
+                        }
+                        self.dm.io.set_fail_on(self.failing.clone());
+                    }
+                }
+
+                // Let the spawned materializations and cleanups run.
+                for _ in 0..10 {
+                    tokio::task::yield_now().await;
+                }
+                while let Ok(cmd) = self.channel.low_priority.try_recv() {
+                    self.pending.push(cmd);
+                }
+
+                self.check_invariants()?;
+
+                if let Step::Unsubscribe { path } = step {
+                    self.subscribed.remove(&make_path(PATHS[path]));
+                }
+                Ok(())
+            }
+
+            fn check_invariants(&mut self) -> anyhow::Result<()> {
+                self.log.extend(self.dm.io.take_log());
+
+                // Notifications are only sent for subscribed paths, and materializations only for
+                // paths which are materialized.
+                while let Ok(event) = self.handle.receiver().try_recv() {
+                    let path = event.path();
+                    anyhow::ensure!(
+                        self.subscribed.contains(path),
+                        "Notified about `{}` which isn't subscribed",
+                        path
+                    );
+                    anyhow::ensure!(
+                        !matches!(event, MaterializerSubscriptionEvent::Materialized(_))
+                            || self.dm.is_path_materialized(path),
+                        "Notified about `{}` which isn't materialized",
+                        path
+                    );
+                }
+
+                let current_version = self.dm.version_tracker.current();
+                anyhow::ensure!(
+                    current_version >= self.current_version,
+                    "Version went from {} to {}",
+                    self.current_version,
+                    current_version
+                );
+                self.current_version = current_version;
+
+                let mut versions = HashMap::new();
+                for (path, data) in self.dm.tree.iter_with_paths() {
+                    let path = ProjectRelativePathBuf::from(path);
+
+                    let version = data.processing.current_version();
+                    anyhow::ensure!(
+                        version < current_version,
+                        "`{}` has version {} which wasn't issued yet",
+                        path,
+                        version
+                    );
+                    if let Some(previous) = self.versions.get(&path) {
+                        anyhow::ensure!(
+                            version >= *previous,
+                            "Version of `{}` went from {} to {}",
+                            path,
+                            previous,
+                            version
+                        );
+                    }
+
+                    // What's materialized must have been materialized after it was last cleaned.
+                    if let ArtifactMaterializationStage::Materialized { .. } = data.stage {
+                        let last =
+                            |op: Op| self.log.iter().rposition(|(o, p)| *o == op && *p == path);
+                        match (last(Op::Materialize), last(Op::Clean)) {
+                            (Some(materialize), Some(clean)) if materialize > clean => {}
+                            (Some(_), None) => {}
+                            (materialize, clean) => anyhow::bail!(
+                                "`{}` is materialized, but was last materialized at {:?} and cleaned at {:?}",
+                                path,
+                                materialize,
+                                clean
+                            ),
+                        }
+                    }
+
+                    versions.insert(path, version);
+                }
+                self.versions = versions;
+
+                Ok(())
+            }
+
+            /// Deliver all the pending commands, until the materializer is idle.
+            async fn drain(&mut self) -> anyhow::Result<()> {
+                while !self.pending.is_empty() {
+                    self.run(Step::Deliver { pick: 0 }).await?;
+                }
+                Ok(())
+            }
+        }
+
+        async fn run_steps(steps: impl IntoIterator<Item = Step>) -> anyhow::Result<()> {
+            let mut harness = Harness::new().await;
+            for (i, step) in steps.into_iter().enumerate() {
+                harness
+                    .run(step)
+                    .await
+                    .with_context(|| format!("At step {} ({:?})", i, step))?;
+            }
+            harness.drain().await.context("Draining")
+        }
+
+        async fn run_seed(seed: u64, steps: usize) -> anyhow::Result<()> {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            run_steps((0..steps).map(|_| Step::random(&mut rng)))
+                .await
+                .with_context(|| {
+                    format!(
+                        "Soak test failed with seed {}, rerun with BUCK2_TEST_MATERIALIZER_SOAK_SEED={}",
+                        seed, seed
+                    )
+                })
+        }
+
+        #[tokio::test]
+        async fn test_soak() -> anyhow::Result<()> {
+            ignore_stack_overflow_checks_for_future(async {
+                let steps = 200;
+                if let Some(seed) =
+                    buck2_env!("BUCK2_TEST_MATERIALIZER_SOAK_SEED", type=u64, applicability=testing)?
+                {
+                    return run_seed(seed, steps).await;
+                }
+
+                let iterations = buck2_env!(
+                    "BUCK2_TEST_MATERIALIZER_SOAK_ITERATIONS",
+                    type=u64,
+                    applicability=testing,
+                )?
+                .unwrap_or(50);
+                for seed in 0..iterations {
+                    run_seed(seed, steps).await?;
+                }
+                Ok(())
+            })
+            .await
+        }
+
+        // Interleavings which broke the invariants at some point. They are written out as steps
+        // rather than seeds, so that they keep testing the same thing when the generator changes.
+
+        #[tokio::test]
+        async fn test_soak_regression_materialized_after_redeclare() -> anyhow::Result<()> {
+            ignore_stack_overflow_checks_for_future(run_steps([
+                Step::Declare {
+                    path: 0,
+                    content: 0,
+                },
+                Step::Materialize { path: 0 },
+                // The materialization of the first declaration finishes after the second one.
+                Step::Declare {
+                    path: 0,
+                    content: 1,
+                },
+                Step::Deliver { pick: 1 },
+                Step::Deliver { pick: 0 },
+            ]))
+            .await
+        }
+
+        #[tokio::test]
+        async fn test_soak_regression_cleanup_finished_last() -> anyhow::Result<()> {
+            ignore_stack_overflow_checks_for_future(run_steps([
+                Step::Declare {
+                    path: 0,
+                    content: 0,
+                },
+                Step::Materialize { path: 0 },
+                // `MaterializationFinished` is delivered before the `CleanupFinished` it waited on.
+                Step::Deliver { pick: 1 },
+                Step::Deliver { pick: 0 },
+                Step::Materialize { path: 0 },
+            ]))
+            .await
+        }
+
+        #[tokio::test]
+        async fn test_soak_regression_parent_declared_during_materialization() -> anyhow::Result<()>
+        {
+            ignore_stack_overflow_checks_for_future(run_steps([
+                Step::Subscribe { path: 2 },
+                Step::Declare {
+                    path: 2,
+                    content: 0,
+                },
+                // `a` replaces `a/b/c` while it materializes, whose completion must not be
+                // applied to `a` nor notified.
+                Step::Declare {
+                    path: 0,
+                    content: 0,
+                },
+                Step::Deliver { pick: 0 },
+                Step::Deliver { pick: 0 },
+                Step::Materialize { path: 2 },
+            ]))
+            .await
+        }
+
+        #[tokio::test]
+        async fn test_soak_regression_failure_then_unsubscribe() -> anyhow::Result<()> {
+            ignore_stack_overflow_checks_for_future(run_steps([
+                Step::SetFailing {
+                    path: 3,
+                    fail: true,
+                },
+                Step::Subscribe { path: 3 },
+                Step::Declare {
+                    path: 3,
+                    content: 1,
+                },
+                Step::Deliver { pick: 0 },
+                Step::Unsubscribe { path: 3 },
+                Step::SetFailing {
+                    path: 3,
+                    fail: false,
+                },
+                Step::Materialize { path: 3 },
+                Step::Deliver { pick: 0 },
+            ]))
+            .await
+        }
+    }
+}
+
+#[test]
+fn test_materialize_stack_display() {
+    let s = MaterializeStack::Empty;
+    assert_eq!("(empty)", s.to_string());
+    let s = MaterializeStack::Child(&s, ProjectRelativePath::new("foo").unwrap());
+    assert_eq!("foo", s.to_string());
+    let s = MaterializeStack::Child(&s, ProjectRelativePath::new("bar/baz").unwrap());
+    assert_eq!("foo -> bar/baz", s.to_string());
+}
+
+#[test]
+fn test_ttl_refresh_survives_backward_clock_step() {
+    let digest_config = DigestConfig::testing_default();
+    let source = FakeTimeSource::new(SystemTime::now());
+    let clock = HybridClock::new(Box::new(source.clone()), DEFAULT_STEP_THRESHOLD);
+    let now: DateTime<Utc> = clock.now().wall.into();
+
+    let declared = |contents: &[u8], expires: DateTime<Utc>| {
+        let digest = FileDigest::from_content(contents, digest_config.cas_digest_config());
+        Box::new(ArtifactMaterializationData {
+            deps: None,
+            stage: ArtifactMaterializationStage::Declared {
+                entry: ActionDirectoryEntry::Leaf(ActionDirectoryMember::File(FileMetadata {
+                    digest: TrackedFileDigest::new_expires(
+                        digest,
+                        expires,
+                        digest_config.cas_digest_config(),
+                    ),
+                    is_executable: false,
+                })),
+                method: Arc::new(ArtifactMaterializationMethod::CasDownload {
+                    info: Arc::new(CasDownloadInfo::new_declared(
+                        RemoteExecutorUseCase::buck2_default(),
+                    )),
+                }),
+            },
+            processing: Processing::Done(Version(0)),
+            failures: None,
+        })
+    };
+
+    let mut tree = ArtifactTree::new();
+    let near = declared(b"near", now + Duration::minutes(1));
+    let far = declared(b"far", now + Duration::hours(2));
+    for (path, data) in [("near", near), ("far", far)] {
+        let path = ProjectRelativePathBuf::unchecked_new(path.to_owned());
+        tree.insert(path.iter().map(|f| f.to_owned()), data);
+    }
+
+    let to_refresh = |clock: &HybridClock| {
+        let deadline = DateTime::<Utc>::from(clock.now().wall) + Duration::minutes(10);
+        io_handler::digests_to_refresh(&tree, deadline)
+            .into_values()
+            .flatten()
+            .map(|digest| digest.to_string())
+            .collect::<Vec<_>>()
+    };
+    let near_digest =
+        FileDigest::from_content(b"near", digest_config.cas_digest_config()).to_string();
+
+    assert_eq!(vec![near_digest.clone()], to_refresh(&clock));
+
+    // With the wall clock an hour behind, the near digest would look like it has plenty of time
+    // left.
+    source.step_wall_backward(std::time::Duration::from_secs(3600));
+    assert_eq!(vec![near_digest], to_refresh(&clock));
+    assert!(clock.take_step().is_some());
 }
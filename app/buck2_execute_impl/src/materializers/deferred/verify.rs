@@ -0,0 +1,249 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Checks that materialized artifacts are still on disk as the materializer believes they are.
+//! Flaky disks or tools writing to `buck-out` can leave the two out of sync, and since matching
+//! declarations reuse what is materialized, nothing would fix it otherwise.
+
+use std::sync::Arc;
+
+use buck2_common::file_ops::FileDigestConfig;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_execute::digest_config::DigestConfig;
+use buck2_execute::directory::ActionDirectoryEntry;
+use buck2_execute::directory::ActionSharedDirectory;
+use buck2_execute::directory::INTERNER;
+use buck2_execute::entry::build_entry_from_disk;
+use buck2_execute::execute::blocking::BlockingExecutor;
+use buck2_execute::materialize::materializer::MaterializerVerifyFound;
+use buck2_execute::materialize::materializer::MaterializerVerifyMismatch;
+use buck2_execute::materialize::physical_roots::PhysicalRoots;
+use derivative::Derivative;
+use dupe::Dupe;
+use futures::stream;
+use futures::StreamExt;
+use tokio::sync::oneshot::Sender;
+use tokio::task::JoinHandle;
+
+use crate::materializers::deferred::clean_path;
+use crate::materializers::deferred::extension::ExtensionCommand;
+use crate::materializers::deferred::io_handler::IoHandler;
+use crate::materializers::deferred::ArtifactMaterializationData;
+use crate::materializers::deferred::ArtifactMaterializationStage;
+use crate::materializers::deferred::ArtifactMetadata;
+use crate::materializers::deferred::DeferredMaterializerCommandProcessor;
+use crate::materializers::deferred::ExistingFutures;
+use crate::materializers::deferred::Processing;
+use crate::materializers::deferred::ProcessingFuture;
+use crate::materializers::deferred::Version;
+
+/// How many artifacts are hashed at once.
+const VERIFY_CONCURRENCY: usize = 32;
+
+/// Hashes whatever is on disk at `path`, returns `None` if there's nothing.
+pub(super) async fn entry_from_disk(
+    roots: &PhysicalRoots,
+    digest_config: DigestConfig,
+    blocking_executor: &dyn BlockingExecutor,
+    path: &ProjectRelativePath,
+) -> anyhow::Result<Option<ActionDirectoryEntry<ActionSharedDirectory>>> {
+    let (entry, _hashing_info) = build_entry_from_disk(
+        roots.physical_path(path),
+        FileDigestConfig::build(digest_config.cas_digest_config()),
+        blocking_executor,
+        roots,
+    )
+    .await?;
+    Ok(entry.map(|entry| {
+        entry.map_dir(|dir| {
+            dir.fingerprint(digest_config.as_directory_serializer())
+                .shared(&*INTERNER)
+        })
+    }))
+}
+
+/// Hashes what is on disk at `path` and compares it to `metadata`, the artifact the materializer
+/// believes is materialized there.
+pub(super) async fn verify_artifact<T: IoHandler>(
+    io: &Arc<T>,
+    path: ProjectRelativePathBuf,
+    metadata: &ArtifactMetadata,
+) -> Option<MaterializerVerifyMismatch> {
+    let found = match io.read_entry_from_disk(&path).await {
+        Ok(Some(entry)) if metadata.matches_entry(&entry) => return None,
+        Ok(Some(entry)) => {
+            MaterializerVerifyFound::Entry(ArtifactMetadata::new(&entry).0.to_string())
+        }
+        Ok(None) => MaterializerVerifyFound::Missing,
+        Err(e) => MaterializerVerifyFound::Error(e),
+    };
+    Some(MaterializerVerifyMismatch {
+        path,
+        expected: metadata.0.to_string(),
+        found,
+    })
+}
+
+pub(super) struct VerifyOutcome {
+    pub(super) checked: usize,
+    /// The mismatches, along with the version the artifact had when it was hashed.
+    pub(super) mismatches: Vec<(Version, MaterializerVerifyMismatch)>,
+}
+
+/// Hashes the materialized artifacts at or under `paths`, or all of them if `paths` is empty.
+/// Artifacts being processed are skipped, since what's on disk is expected to change.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub(super) struct VerifyArtifactsCommand {
+    pub(super) paths: Vec<ProjectRelativePathBuf>,
+    #[derivative(Debug = "ignore")]
+    pub(super) sender: Sender<JoinHandle<VerifyOutcome>>,
+}
+
+impl<T: IoHandler> ExtensionCommand<T> for VerifyArtifactsCommand {
+    fn execute(self: Box<Self>, processor: &mut DeferredMaterializerCommandProcessor<T>) {
+        let artifacts = processor
+            .tree
+            .iter_with_paths()
+            .filter_map(|(path, data)| {
+                let path = ProjectRelativePathBuf::from(path);
+                // A requested path may also be within an artifact.
+                if !self.paths.is_empty()
+                    && !self
+                        .paths
+                        .iter()
+                        .any(|p| p.starts_with(&path) || path.starts_with(p))
+                {
+                    return None;
+                }
+                match &**data {
+                    ArtifactMaterializationData {
+                        stage: ArtifactMaterializationStage::Materialized { metadata, .. },
+                        processing: Processing::Done(version),
+                        ..
+                    } => Some((path, metadata.dupe(), *version)),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let io = processor.io.dupe();
+        let task = processor.spawn(async move {
+            let checked = artifacts.len();
+            let mismatches = stream::iter(artifacts)
+                .map(|(path, metadata, version)| {
+                    let io = io.dupe();
+                    async move {
+                        verify_artifact(&io, path, &metadata)
+                            .await
+                            .map(|mismatch| (version, mismatch))
+                    }
+                })
+                .buffer_unordered(VERIFY_CONCURRENCY)
+                .filter_map(futures::future::ready)
+                .collect::<Vec<_>>()
+                .await;
+            VerifyOutcome {
+                checked,
+                mismatches,
+            }
+        });
+        let _ignored = self.sender.send(task);
+    }
+}
+
+/// Removes mismatched artifacts from the materializer state, unless they were declared or
+/// processed again since they were hashed. Artifacts the running daemon declared go back to being
+/// declared, so that they are materialized again the next time they are needed. Responds with the
+/// paths that were removed.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub(super) struct InvalidateMismatchesCommand {
+    pub(super) mismatches: Vec<(ProjectRelativePathBuf, Version)>,
+    #[derivative(Debug = "ignore")]
+    pub(super) sender: Sender<anyhow::Result<Vec<ProjectRelativePathBuf>>>,
+}
+
+impl<T: IoHandler> ExtensionCommand<T> for InvalidateMismatchesCommand {
+    fn execute(self: Box<Self>, processor: &mut DeferredMaterializerCommandProcessor<T>) {
+        let res = processor.invalidate_mismatches(self.mismatches);
+        let _ignored = self.sender.send(res);
+    }
+}
+
+impl<T: IoHandler> DeferredMaterializerCommandProcessor<T> {
+    /// See [`InvalidateMismatchesCommand`]. Returns the paths that were removed.
+    pub(super) fn invalidate_mismatches(
+        &mut self,
+        mismatches: Vec<(ProjectRelativePathBuf, Version)>,
+    ) -> anyhow::Result<Vec<ProjectRelativePathBuf>> {
+        let mut paths = Vec::new();
+        let mut redeclared = Vec::new();
+        for (path, version) in mismatches {
+            let mut path_iter = path.iter();
+            let data = match self.tree.prefix_get(&mut path_iter) {
+                Some(data) if path_iter.next().is_none() => data,
+                _ => continue,
+            };
+            if let ArtifactMaterializationData {
+                deps,
+                stage: ArtifactMaterializationStage::Materialized { declared, .. },
+                processing: Processing::Done(current),
+                ..
+            } = &**data
+            {
+                if *current != version {
+                    continue;
+                }
+                if let Some(declared) = declared {
+                    redeclared.push((
+                        path.clone(),
+                        deps.dupe(),
+                        declared.entry.dupe(),
+                        declared.method.dupe(),
+                    ));
+                }
+                paths.push(path);
+            }
+        }
+
+        // Nothing is processing these paths, so there are no futures to wait for.
+        self.tree
+            .invalidate_paths_and_collect_futures(paths.clone(), self.sqlite_db.as_mut())?;
+        for path in &paths {
+            self.subscriptions.on_invalidated(path);
+        }
+
+        for (path, deps, entry, method) in redeclared {
+            // What's on disk must be removed before it is materialized again.
+            let version = self.version_tracker.next();
+            let future = ProcessingFuture::Cleaning(clean_path(
+                &self.io,
+                path.clone(),
+                version,
+                self.command_sender.dupe(),
+                ExistingFutures::empty(),
+                &self.rt,
+                self.cancellations,
+            ));
+            self.tree.insert(
+                path.iter().map(|f| f.to_owned()),
+                Box::new(ArtifactMaterializationData {
+                    deps,
+                    stage: ArtifactMaterializationStage::Declared { entry, method },
+                    processing: Processing::Active { future, version },
+                    failures: None,
+                }),
+            );
+        }
+
+        Ok(paths)
+    }
+}
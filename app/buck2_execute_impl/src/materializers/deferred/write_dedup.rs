@@ -0,0 +1,139 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::Arc;
+use std::sync::Weak;
+
+use buck2_common::file_ops::FileMetadata;
+use dupe::Dupe;
+use linked_hash_map::LinkedHashMap;
+
+use crate::materializers::deferred::WriteFile;
+
+/// Index of the contents of declared writes, so that writes of identical files (e.g. the same
+/// argsfile written for many targets) share a single compressed copy in memory.
+///
+/// Entries are weak: the contents are kept alive by the artifacts declared with them, and are
+/// dropped once the last of those is materialized, cleaned or re-declared. The index is bounded,
+/// evicting the least recently used entries, so it doesn't grow with the number of distinct
+/// files ever written by the daemon.
+pub(crate) struct WriteDedupIndex {
+    entries: LinkedHashMap<FileMetadata, Weak<WriteFile>>,
+    capacity: usize,
+}
+
+impl WriteDedupIndex {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            entries: LinkedHashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Returns the contents already declared for a file with this metadata, or the ones created
+    /// by `new_write`. The boolean is true when existing contents are reused.
+    pub(crate) fn get_or_insert(
+        &mut self,
+        metadata: &FileMetadata,
+        new_write: impl FnOnce() -> anyhow::Result<WriteFile>,
+    ) -> anyhow::Result<(Arc<WriteFile>, bool)> {
+        if let Some(write) = self
+            .entries
+            .get_refresh(metadata)
+            .and_then(|write| write.upgrade())
+        {
+            return Ok((write, true));
+        }
+
+        let write = Arc::new(new_write()?);
+        self.entries.insert(metadata.dupe(), Arc::downgrade(&write));
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+        Ok((write, false))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use buck2_common::file_ops::FileMetadata;
+    use buck2_common::file_ops::TrackedFileDigest;
+    use buck2_execute::digest_config::DigestConfig;
+
+    use crate::materializers::deferred::write_dedup::WriteDedupIndex;
+    use crate::materializers::deferred::WriteFile;
+
+    fn metadata(content: &str) -> FileMetadata {
+        FileMetadata {
+            digest: TrackedFileDigest::from_content(
+                content.as_bytes(),
+                DigestConfig::testing_default().cas_digest_config(),
+            ),
+            is_executable: false,
+        }
+    }
+
+    fn write(content: &str) -> anyhow::Result<WriteFile> {
+        WriteFile::compress(content.as_bytes(), &metadata(content))
+    }
+
+    #[test]
+    fn test_identical_contents_are_shared() -> anyhow::Result<()> {
+        let mut index = WriteDedupIndex::new(10);
+
+        let (a, reused) = index.get_or_insert(&metadata("foo"), || write("foo"))?;
+        assert!(!reused);
+        let (b, reused) = index.get_or_insert(&metadata("foo"), || panic!("not reused"))?;
+        assert!(reused);
+        assert!(Arc::ptr_eq(&a, &b));
+
+        let (_c, reused) = index.get_or_insert(&metadata("bar"), || write("bar"))?;
+        assert!(!reused);
+        Ok(())
+    }
+
+    #[test]
+    fn test_contents_dropped_with_last_artifact() -> anyhow::Result<()> {
+        let mut index = WriteDedupIndex::new(10);
+
+        let (a, _) = index.get_or_insert(&metadata("foo"), || write("foo"))?;
+        drop(a);
+
+        let (_a, reused) = index.get_or_insert(&metadata("foo"), || write("foo"))?;
+        assert!(!reused);
+        Ok(())
+    }
+
+    #[test]
+    fn test_least_recently_used_evicted() -> anyhow::Result<()> {
+        let mut index = WriteDedupIndex::new(2);
+
+        let (_a, _) = index.get_or_insert(&metadata("a"), || write("a"))?;
+        let (_b, _) = index.get_or_insert(&metadata("b"), || write("b"))?;
+        // Use `a` again so that `b` is the one evicted.
+        let (_, reused) = index.get_or_insert(&metadata("a"), || write("a"))?;
+        assert!(reused);
+        let (_c, _) = index.get_or_insert(&metadata("c"), || write("c"))?;
+        assert_eq!(2, index.len());
+
+        let (_, reused) = index.get_or_insert(&metadata("a"), || write("a"))?;
+        assert!(reused);
+        // `b` is still declared, but no longer deduplicated.
+        let (_, reused) = index.get_or_insert(&metadata("b"), || write("b"))?;
+        assert!(!reused);
+        Ok(())
+    }
+}
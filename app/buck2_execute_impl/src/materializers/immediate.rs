@@ -23,6 +23,7 @@ use buck2_execute::digest::CasDigestToReExt;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::directory::ActionDirectoryMember;
 use buck2_execute::execute::blocking::BlockingExecutor;
+use buck2_execute::execute::blocking::IoTag;
 use buck2_execute::execute::clean_output_paths::cleanup_path;
 use buck2_execute::execute::clean_output_paths::CleanOutputPaths;
 use buck2_execute::materialize::http::http_download;
@@ -99,6 +100,7 @@ impl Materializer for ImmediateMaterializer {
     ) -> anyhow::Result<()> {
         self.io_executor
             .execute_io(
+                IoTag::MaterializeRead,
                 Box::new(CleanOutputPaths {
                     paths: vec![path.to_owned()],
                 }),
@@ -109,6 +111,7 @@ impl Materializer for ImmediateMaterializer {
         // TODO: display [materializing] in superconsole
         self.io_executor
             .execute_io(
+                IoTag::MaterializeRead,
                 Box::new(MaterializeTreeStructure {
                     path: path.clone(),
                     entry: value.entry().dupe(),
@@ -118,7 +121,7 @@ impl Materializer for ImmediateMaterializer {
             .await?;
 
         self.io_executor
-            .execute_io_inline(|| {
+            .execute_io_inline(IoTag::MaterializeRead, || {
                 for copied_artifact in srcs {
                     // Make sure `path` is a prefix of `dest`, so we don't
                     // materialize anything outside `path`.
@@ -164,6 +167,7 @@ impl Materializer for ImmediateMaterializer {
     ) -> anyhow::Result<()> {
         self.io_executor
             .execute_io(
+                IoTag::MaterializeRead,
                 Box::new(CleanOutputPaths {
                     paths: vec![path.to_owned()],
                 }),
@@ -239,7 +243,7 @@ pub async fn write_to_disk<'a>(
     gen: Box<dyn FnOnce() -> anyhow::Result<Vec<WriteRequest>> + Send + 'a>,
 ) -> anyhow::Result<Vec<ArtifactValue>> {
     io_executor
-        .execute_io_inline({
+        .execute_io_inline(IoTag::Other, {
             move || {
                 let requests = gen()?;
                 let mut values = Vec::with_capacity(requests.len());
@@ -278,6 +282,7 @@ pub async fn cas_download<'a, 'b>(
     cancellations: &CancellationContext<'_>,
 ) -> anyhow::Result<()> {
     io.execute_io(
+        IoTag::MaterializeRead,
         Box::new(CleanOutputPaths {
             paths: artifacts.map(|(p, _)| p.to_owned()),
         }),
@@ -287,6 +292,7 @@ pub async fn cas_download<'a, 'b>(
 
     for (path, value) in artifacts.iter() {
         io.execute_io(
+            IoTag::MaterializeRead,
             Box::new(MaterializeTreeStructure {
                 path: path.to_owned(),
                 entry: value.entry().dupe(),
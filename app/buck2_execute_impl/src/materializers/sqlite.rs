@@ -8,6 +8,7 @@
  */
 
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
 
 use allocative::Allocative;
@@ -16,7 +17,12 @@ use buck2_common::external_symlink::ExternalSymlink;
 use buck2_common::file_ops::FileDigest;
 use buck2_common::file_ops::FileMetadata;
 use buck2_common::file_ops::TrackedFileDigest;
+use buck2_common::legacy_configs::key::BuckconfigKeyRef;
+use buck2_common::legacy_configs::LegacyBuckConfig;
+use buck2_common::sqlite::migrations::SqliteMigrations;
 use buck2_common::sqlite::KeyValueSqliteTable;
+use buck2_common::sqlite::SqliteMaintenance;
+use buck2_common::sqlite::SqliteVacuumConfig;
 use buck2_core::directory::DirectoryEntry;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
@@ -25,22 +31,29 @@ use buck2_core::fs::paths::file_name::FileName;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_events::daemon_id::DAEMON_UUID;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::directory::ActionDirectoryMember;
 use buck2_execute::directory::Symlink;
 use buck2_execute::execute::blocking::BlockingExecutor;
+use buck2_execute::execute::blocking::IoTag;
+use buck2_wrapper_common::kill::process_exists;
+use buck2_wrapper_common::kill::process_start_time;
+use buck2_wrapper_common::pid::Pid;
 use chrono::DateTime;
 use chrono::TimeZone;
 use chrono::Utc;
 use derive_more::Display;
 use derive_more::From;
 use dupe::Dupe;
+use fs4::FileExt;
 use gazebo::prelude::*;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use rusqlite::Connection;
 
+use crate::materializers::clock_now;
 use crate::materializers::deferred::ArtifactMetadata;
 use crate::materializers::deferred::DirectoryMetadata;
 
@@ -48,15 +61,127 @@ use crate::materializers::deferred::DirectoryMetadata;
 pub struct MaterializerStateIdentity(String);
 
 /// Hand-maintained schema version for the materializer state sqlite db.
-/// PLEASE bump this version if you are making a breaking change to the
-/// materializer state sqlite db schema! If you forget to bump this version,
-/// then you can fix forward by bumping the `buck2.sqlite_materializer_state_version`
-/// buckconfig in the project root's .buckconfig.
-pub const DB_SCHEMA_VERSION: u64 = 6;
+/// Changes to the tables should be made by adding a migration to `materializer_state_migrations`,
+/// which keeps the existing state. Only bump this version if the state can't be migrated, since
+/// bumping it throws the state away. If you forget to bump this version, then you can fix forward
+/// by bumping the `buck2.sqlite_materializer_state_version` buckconfig in the project root's
+/// .buckconfig.
+pub const DB_SCHEMA_VERSION: u64 = 7;
 
 const STATE_TABLE_NAME: &str = "materializer_state";
+const VERSIONS_TABLE_NAME: &str = "versions";
+const CREATED_BY_TABLE_NAME: &str = "created_by";
+const LAST_READ_BY_TABLE_NAME: &str = "last_read_by";
+const OWNER_TABLE_NAME: &str = "owner";
 const IDENTITY_KEY: &str = "timestamp_on_initialization";
 
+/// The daemon using the materializer state db. It is recorded in the db so that another daemon
+/// pointed at the same buck-out can tell whether the db is still in use.
+#[derive(Debug, Clone, PartialEq, Eq, Allocative)]
+pub struct MaterializerStateOwner {
+    daemon_id: String,
+    pid: u32,
+    /// In seconds. Distinguishes the owner from another process that reused its pid.
+    start_time: Option<u64>,
+}
+
+impl fmt::Display for MaterializerStateOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "daemon {} (pid {})", self.daemon_id, self.pid)
+    }
+}
+
+impl MaterializerStateOwner {
+    const DAEMON_ID_KEY: &'static str = "daemon_id";
+    const PID_KEY: &'static str = "pid";
+    const START_TIME_KEY: &'static str = "start_time";
+
+    pub fn current() -> Self {
+        Self::for_current_process(DAEMON_UUID.to_string())
+    }
+
+    fn for_current_process(daemon_id: String) -> Self {
+        let pid = std::process::id();
+        let start_time = Pid::from_u32(pid)
+            .ok()
+            .and_then(process_start_time)
+            .map(|t| t.as_secs());
+        Self {
+            daemon_id,
+            pid,
+            start_time,
+        }
+    }
+
+    fn to_map(&self) -> HashMap<String, String> {
+        let mut map = HashMap::from([
+            (Self::DAEMON_ID_KEY.to_owned(), self.daemon_id.clone()),
+            (Self::PID_KEY.to_owned(), self.pid.to_string()),
+        ]);
+        if let Some(start_time) = self.start_time {
+            map.insert(Self::START_TIME_KEY.to_owned(), start_time.to_string());
+        }
+        map
+    }
+
+    /// Returns `None` if no owner was recorded, or if the record can't be understood.
+    fn from_map(map: &HashMap<String, String>) -> Option<Self> {
+        Some(Self {
+            daemon_id: map.get(Self::DAEMON_ID_KEY)?.clone(),
+            pid: map.get(Self::PID_KEY)?.parse().ok()?,
+            start_time: match map.get(Self::START_TIME_KEY) {
+                Some(start_time) => Some(start_time.parse().ok()?),
+                None => None,
+            },
+        })
+    }
+
+    /// Whether both are the same process, whichever daemon they were recorded for.
+    fn is_same_process(&self, other: &Self) -> bool {
+        self.pid == other.pid && self.start_time == other.start_time
+    }
+
+    /// Whether the owner is still running. We only consider it dead if we can prove it: either
+    /// its pid is gone, or the process with its pid was started at a different time.
+    fn is_alive(&self) -> bool {
+        let pid = match Pid::from_u32(self.pid) {
+            Ok(pid) => pid,
+            Err(_) => return false,
+        };
+        match process_exists(pid) {
+            Ok(false) => return false,
+            Ok(true) | Err(_) => {}
+        }
+        match (self.start_time, process_start_time(pid)) {
+            (Some(recorded), Some(actual)) => recorded == actual.as_secs(),
+            _ => true,
+        }
+    }
+}
+
+/// Exclusive advisory lock on the materializer state, held for as long as the db is open. Since
+/// the OS releases it when the file is closed, a crashed daemon does not leave it behind.
+struct MaterializerStateLock {
+    _file: std::fs::File,
+}
+
+impl MaterializerStateLock {
+    /// Returns `None` if another process holds the lock.
+    fn try_acquire(path: &AbsNormPath) -> anyhow::Result<Option<Self>> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Error opening materializer state lock `{}`", path))?;
+        match file.try_lock_exclusive() {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(e) if e.raw_os_error() == fs4::lock_contended_error().raw_os_error() => Ok(None),
+            Err(e) => Err(anyhow::Error::new(e)
+                .context(format!("Error locking materializer state lock `{}`", path))),
+        }
+    }
+}
+
 pub type MaterializerState = Vec<(ProjectRelativePathBuf, (ArtifactMetadata, DateTime<Utc>))>;
 
 #[derive(buck2_error::Error, Debug, PartialEq, Eq)]
@@ -306,9 +431,9 @@ impl MaterializerStateSqliteTable {
         Self { connection }
     }
 
-    pub(crate) fn create_table(&self) -> anyhow::Result<()> {
-        let sql = format!(
-            "CREATE TABLE {} (
+    fn create_table_sql() -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (
                 path                    TEXT NOT NULL PRIMARY KEY,
                 artifact_type           TEXT CHECK(artifact_type IN ('directory','file','symlink','external_symlink')) NOT NULL,
                 digest_size             INTEGER NULL DEFAULT NULL,
@@ -320,7 +445,11 @@ impl MaterializerStateSqliteTable {
                 directory_size          INTEGER NULL DEFAULT NULL
             )",
             STATE_TABLE_NAME,
-        );
+        )
+    }
+
+    pub(crate) fn create_table(&self) -> anyhow::Result<()> {
+        let sql = Self::create_table_sql();
         tracing::trace!(sql = %*sql, "creating table");
         self.connection
             .lock()
@@ -334,6 +463,15 @@ impl MaterializerStateSqliteTable {
         path: &ProjectRelativePath,
         metadata: &ArtifactMetadata,
         timestamp: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        Self::insert_with(&self.connection.lock(), path, metadata, timestamp)
+    }
+
+    fn insert_with(
+        conn: &Connection,
+        path: &ProjectRelativePath,
+        metadata: &ArtifactMetadata,
+        timestamp: DateTime<Utc>,
     ) -> anyhow::Result<()> {
         let entry: ArtifactMetadataSqliteEntry = metadata.into();
         static SQL: Lazy<String> = Lazy::new(|| {
@@ -343,31 +481,52 @@ impl MaterializerStateSqliteTable {
             )
         });
         tracing::trace!(sql = %*SQL, entry = ?entry, "inserting into table");
-        self.connection
-            .lock()
-            .execute(
-                &SQL,
-                rusqlite::params![
-                    path.as_str(),
-                    entry.artifact_type,
-                    entry.entry_size,
-                    entry.entry_hash,
-                    entry.entry_hash_kind,
-                    entry.file_is_executable,
-                    entry.symlink_target,
-                    entry.directory_size,
-                    timestamp.timestamp(),
-                ],
+        conn.execute(
+            &SQL,
+            rusqlite::params![
+                path.as_str(),
+                entry.artifact_type,
+                entry.entry_size,
+                entry.entry_hash,
+                entry.entry_hash_kind,
+                entry.file_is_executable,
+                entry.symlink_target,
+                entry.directory_size,
+                timestamp.timestamp(),
+            ],
+        )
+        .with_context(|| {
+            format!(
+                "inserting `{}` into sqlite table {}",
+                path, STATE_TABLE_NAME
             )
-            .with_context(|| {
-                format!(
-                    "inserting `{}` into sqlite table {}",
-                    path, STATE_TABLE_NAME
-                )
-            })?;
+        })?;
         Ok(())
     }
 
+    /// Inserts all the `entries` in a single transaction. Entries that fail to be inserted are
+    /// skipped, and the first error is returned once the others are committed.
+    pub(crate) fn insert_many<'a>(
+        &self,
+        entries: impl IntoIterator<
+            Item = (&'a ProjectRelativePath, &'a ArtifactMetadata, DateTime<Utc>),
+        >,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.connection.lock();
+        let tx = conn.transaction()?;
+        let mut first_error = None;
+        for (path, metadata, timestamp) in entries {
+            if let Err(e) = Self::insert_with(&tx, path, metadata, timestamp) {
+                first_error.get_or_insert(e);
+            }
+        }
+        tx.commit()?;
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
     pub(crate) fn update_access_times(
         &self,
         updates: Vec<&ProjectRelativePathBuf>,
@@ -378,7 +537,7 @@ impl MaterializerStateSqliteTable {
             let sql = format!(
                 "UPDATE {} SET last_access_time = {} WHERE path IN ({})",
                 STATE_TABLE_NAME,
-                Utc::now().timestamp(),
+                clock_now().timestamp(),
                 itertools::repeat_n("?", chunk.len()).join(","),
             );
             tracing::trace!(sql = %sql, chunk = ?chunk, "updating last_access_times");
@@ -487,6 +646,16 @@ enum MaterializerStateSqliteDbError {
 
     #[error("Materializer identity was rejected: {}", .identity)]
     RejectedIdentity { identity: MaterializerStateIdentity },
+
+    #[error(
+        "Materializer state at {} is in use by another buck2 daemon: {}. Only one daemon can use a buck-out at a time, kill the other daemon or use a different isolation dir",
+        .path,
+        .owner.as_ref().map_or_else(|| "unknown owner".to_owned(), |o| o.to_string())
+    )]
+    InUse {
+        path: AbsNormPathBuf,
+        owner: Option<MaterializerStateOwner>,
+    },
 }
 
 /// DB that opens the sqlite connection to the materializer state db on disk and
@@ -496,21 +665,17 @@ pub struct MaterializerStateSqliteDb {
     /// A unique ID identifying this particular instance of the database. This will reset when we
     /// recreate it.
     identity: MaterializerStateIdentity,
+    /// Inserts into the materializer state table that were not written yet, see `insert`.
+    pending_inserts: HashMap<ProjectRelativePathBuf, (ArtifactMetadata, DateTime<Utc>)>,
+    /// Inserts are written as soon as they are made if this is 0.
+    max_pending_inserts: usize,
+    /// Declared last so that it is released after the connection is closed.
+    _lock: MaterializerStateLock,
 }
 
 impl MaterializerStateSqliteDb {
     const DB_FILENAME: &'static str = "db.sqlite";
-
-    fn new(tables: MaterializerStateTables) -> anyhow::Result<Self> {
-        let identity = tables
-            .created_by_table
-            .get(IDENTITY_KEY)
-            .context("Error reading creation metadata")?
-            .map(MaterializerStateIdentity)
-            .with_context(|| format!("Identity key is missing in db: `{}`", IDENTITY_KEY))?;
-
-        Ok(Self { tables, identity })
-    }
+    const LOCK_FILENAME: &'static str = "db.lock";
 
     /// Given path to the sqlite DB, attempts to read `MaterializerState` from the DB. If we encounter
     /// any failure along the way, such as if the DB path does not exist, the sqlite read fails,
@@ -521,6 +686,9 @@ impl MaterializerStateSqliteDb {
     /// from the existing DB. These failures are expected if db doesn't exist or versions don't match.
     /// The outer `Result` captures any failure encountered when trying to delete the existing DB and
     /// create a new one.
+    ///
+    /// Fails if another daemon is using the db, since interleaving writes from two daemons
+    /// corrupts it.
     /// TODO(scottcao): pull this method into a shared trait once we add a another sqlite DB
     pub async fn initialize(
         materializer_state_dir: AbsNormPathBuf,
@@ -533,13 +701,14 @@ impl MaterializerStateSqliteDb {
         reject_identity: Option<&MaterializerStateIdentity>,
     ) -> anyhow::Result<(Self, anyhow::Result<MaterializerState>)> {
         io_executor
-            .execute_io_inline(|| {
+            .execute_io_inline(IoTag::DbFlush, || {
                 Self::initialize_impl(
                     materializer_state_dir,
                     versions,
                     current_instance_metadata,
                     digest_config,
                     reject_identity,
+                    MaterializerStateOwner::current(),
                 )
             })
             .await
@@ -551,13 +720,40 @@ impl MaterializerStateSqliteDb {
         mut current_instance_metadata: HashMap<String, String>,
         digest_config: DigestConfig,
         reject_identity: Option<&MaterializerStateIdentity>,
+        owner: MaterializerStateOwner,
     ) -> anyhow::Result<(Self, anyhow::Result<MaterializerState>)> {
         let timestamp_on_initialization = Utc::now().to_rfc3339();
         current_instance_metadata.insert(IDENTITY_KEY.to_owned(), timestamp_on_initialization);
 
         let db_path = materializer_state_dir.join(FileName::unchecked_new(Self::DB_FILENAME));
+        let lock_path = materializer_state_dir.join(FileName::unchecked_new(Self::LOCK_FILENAME));
+
+        fs_util::create_dir_all(&materializer_state_dir)?;
+        let lock = match MaterializerStateLock::try_acquire(&lock_path)? {
+            Some(lock) => lock,
+            None => {
+                return Err(MaterializerStateSqliteDbError::InUse {
+                    owner: MaterializerStateTables::read_owner(&db_path),
+                    path: materializer_state_dir,
+                }
+                .into());
+            }
+        };
+
+        // The lock should be enough, but it is advisory and does not work on all file systems, so
+        // also check the owner recorded in the db. Only take the db over from an owner that is
+        // this process, or that is provably dead.
+        if let Some(recorded) = MaterializerStateTables::read_owner(&db_path) {
+            if !recorded.is_same_process(&owner) && recorded.is_alive() {
+                return Err(MaterializerStateSqliteDbError::InUse {
+                    owner: Some(recorded),
+                    path: materializer_state_dir,
+                }
+                .into());
+            }
+        }
 
-        let result: anyhow::Result<(Self, MaterializerState)> = try {
+        let result: anyhow::Result<_> = try {
             // try reading the existing db, if it exists.
             if !db_path.exists() {
                 Err(anyhow::anyhow!(
@@ -582,38 +778,49 @@ impl MaterializerStateSqliteDb {
             tables
                 .last_read_by_table
                 .insert_all(current_instance_metadata.clone())?;
+            tables.record_owner(&owner)?;
 
-            let mut db = Self::new(tables)?;
+            let identity = tables.identity()?;
 
             if let Some(reject_identity) = reject_identity {
-                if db.identity == *reject_identity {
+                if identity == *reject_identity {
                     Err(MaterializerStateSqliteDbError::RejectedIdentity {
-                        identity: db.identity.clone(),
+                        identity: identity.clone(),
                     })?;
                 }
             }
 
-            let state = db.materializer_state_table().read_all(digest_config)?;
+            let state = tables.materializer_state_table.read_all(digest_config)?;
 
-            (db, state)
+            (tables, identity, state)
         };
 
         match result {
-            Ok((db, state)) => Ok((db, Ok(state))),
+            Ok((tables, identity, state)) => Ok((
+                Self {
+                    tables,
+                    identity,
+                    pending_inserts: HashMap::new(),
+                    max_pending_inserts: 0,
+                    _lock: lock,
+                },
+                Ok(state),
+            )),
             Err(e) => {
                 // Loading failed. Initialize a new db from scratch.
 
-                // Delete the existing materializer_state directory and create a new one.
-                // We delete the entire directory and not just the db file because sqlite
-                // can leave behind other files.
-                if materializer_state_dir.exists() {
-                    fs_util::remove_dir_all(&materializer_state_dir)?;
+                // Empty the existing materializer_state directory. We delete everything and not
+                // just the db file because sqlite can leave behind other files. We keep the lock
+                // file, which we hold.
+                for entry in fs_util::read_dir(&materializer_state_dir)? {
+                    let entry = entry?;
+                    if entry.file_name() != Self::LOCK_FILENAME {
+                        fs_util::remove_all(entry.path())?;
+                    }
                 }
-                fs_util::create_dir_all(&materializer_state_dir)?;
 
                 // Initialize a new db
                 let tables = MaterializerStateTables::open(&db_path)?;
-                tables.create_all_tables()?;
                 tables.versions_table.insert_all(versions)?;
                 // Update both "last_read_by" and "created_by"
                 tables
@@ -622,21 +829,104 @@ impl MaterializerStateSqliteDb {
                 tables
                     .last_read_by_table
                     .insert_all(current_instance_metadata)?;
-
-                Ok((Self::new(tables)?, Err(e)))
+                tables.record_owner(&owner)?;
+
+                let identity = tables.identity()?;
+                Ok((
+                    Self {
+                        tables,
+                        identity,
+                        pending_inserts: HashMap::new(),
+                        max_pending_inserts: 0,
+                        _lock: lock,
+                    },
+                    Err(e),
+                ))
             }
         }
     }
 
+    /// Accesses the table directly, which doesn't see the pending inserts. Call `flush_inserts`
+    /// first to read or update the rows they add.
     pub(crate) fn materializer_state_table(&mut self) -> &MaterializerStateSqliteTable {
         &self.tables.materializer_state_table
     }
 
+    /// Keep up to `max_pending_inserts` inserts in memory and write them together, since writing
+    /// each one in its own transaction is slow. If the daemon crashes, the pending inserts are
+    /// lost: their artifacts are then treated as untracked on the next startup, the same as
+    /// artifacts whose materialization was interrupted, so they are cleaned up and materialized
+    /// again when needed.
+    pub(crate) fn set_max_pending_inserts(&mut self, max_pending_inserts: usize) {
+        self.max_pending_inserts = max_pending_inserts;
+    }
+
+    /// Records that the artifact at `path` was materialized.
+    pub(crate) fn insert(
+        &mut self,
+        path: &ProjectRelativePath,
+        metadata: &ArtifactMetadata,
+        timestamp: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        if self.max_pending_inserts == 0 {
+            return self
+                .tables
+                .materializer_state_table
+                .insert(path, metadata, timestamp);
+        }
+
+        self.pending_inserts
+            .insert(path.to_owned(), (metadata.dupe(), timestamp));
+        if self.pending_inserts.len() >= self.max_pending_inserts {
+            self.flush_inserts()?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the artifacts at `paths`, including their pending inserts.
+    pub(crate) fn delete(&mut self, paths: Vec<ProjectRelativePathBuf>) -> anyhow::Result<usize> {
+        if !self.pending_inserts.is_empty() {
+            for path in &paths {
+                self.pending_inserts.remove(path);
+            }
+        }
+        self.tables.materializer_state_table.delete(paths)
+    }
+
+    /// Writes the pending inserts.
+    pub(crate) fn flush_inserts(&mut self) -> anyhow::Result<()> {
+        if self.pending_inserts.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending_inserts);
+        self.tables.materializer_state_table.insert_many(
+            pending
+                .iter()
+                .map(|(path, (metadata, timestamp))| (&**path, metadata, *timestamp)),
+        )
+    }
+
+    pub(crate) fn maintenance(&mut self) -> &mut SqliteMaintenance {
+        &mut self.tables.maintenance
+    }
+
     pub fn identity(&self) -> &MaterializerStateIdentity {
         &self.identity
     }
 }
 
+impl Drop for MaterializerStateSqliteDb {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush_inserts() {
+            tracing::warn!("Error writing materializer state: {:#}", e);
+        }
+        // We are done with the db, so let the next daemon take over even if we keep running.
+        if let Err(e) = self.tables.owner_table.delete_all() {
+            tracing::warn!("Error clearing materializer state owner: {:#}", e);
+        }
+    }
+}
+
 struct MaterializerStateTables {
     /// Table storing actual materializer state
     materializer_state_table: MaterializerStateSqliteTable,
@@ -650,11 +940,60 @@ struct MaterializerStateTables {
     created_by_table: KeyValueSqliteTable,
     /// Table for logging metadata associated with the buck2 that last updated the db.
     last_read_by_table: KeyValueSqliteTable,
+    /// Table recording the `MaterializerStateOwner` currently using the db.
+    owner_table: KeyValueSqliteTable,
+    /// Compacts the db as artifacts get deleted.
+    maintenance: SqliteMaintenance,
+}
+
+/// The schema of the materializer state db, see `buck2_common::sqlite::migrations`.
+///
+/// Dbs created before there were migrations are at version 0 but already have the tables of
+/// version 1, which is why it creates them only if they do not exist.
+fn materializer_state_migrations() -> SqliteMigrations {
+    SqliteMigrations::new().sql(
+        "create tables",
+        [
+            MaterializerStateSqliteTable::create_table_sql(),
+            KeyValueSqliteTable::create_table_sql(VERSIONS_TABLE_NAME),
+            KeyValueSqliteTable::create_table_sql(CREATED_BY_TABLE_NAME),
+            KeyValueSqliteTable::create_table_sql(LAST_READ_BY_TABLE_NAME),
+            KeyValueSqliteTable::create_table_sql(OWNER_TABLE_NAME),
+        ]
+        .join(";\n"),
+    )
 }
 
 impl MaterializerStateTables {
-    /// Given path to sqlite DB, opens and returns a new connection to the DB.
+    /// Given path to sqlite DB, opens and returns a new connection to the DB, creating the tables
+    /// or migrating them to the current schema as needed.
     fn open(path: &AbsNormPath) -> anyhow::Result<Self> {
+        let mut connection = Self::connect(path)?;
+        materializer_state_migrations().apply(&mut connection)?;
+
+        let connection = Arc::new(Mutex::new(connection));
+        let materializer_state_table = MaterializerStateSqliteTable::new(connection.dupe());
+        let versions_table =
+            KeyValueSqliteTable::new(VERSIONS_TABLE_NAME.to_owned(), connection.dupe());
+        let created_by_table =
+            KeyValueSqliteTable::new(CREATED_BY_TABLE_NAME.to_owned(), connection.dupe());
+        let last_read_by_table =
+            KeyValueSqliteTable::new(LAST_READ_BY_TABLE_NAME.to_owned(), connection.dupe());
+        let owner_table = KeyValueSqliteTable::new(OWNER_TABLE_NAME.to_owned(), connection.dupe());
+        let maintenance = SqliteMaintenance::new(connection);
+
+        Ok(Self {
+            materializer_state_table,
+            versions_table,
+            created_by_table,
+            last_read_by_table,
+            owner_table,
+            maintenance,
+        })
+    }
+
+    /// Opens a connection to the DB, without touching its tables.
+    fn connect(path: &AbsNormPath) -> anyhow::Result<Connection> {
         let connection = Connection::open(path)?;
         // TODO: make this work on Windows too
         if cfg!(unix) {
@@ -681,29 +1020,107 @@ impl MaterializerStateTables {
         // we completely stall I/O for a little while.
         connection.pragma_update(None, "synchronous", "OFF")?;
 
-        let connection = Arc::new(Mutex::new(connection));
-        let materializer_state_table = MaterializerStateSqliteTable::new(connection.dupe());
-        let versions_table = KeyValueSqliteTable::new("versions".to_owned(), connection.dupe());
-        let created_by_table = KeyValueSqliteTable::new("created_by".to_owned(), connection.dupe());
-        let last_read_by_table = KeyValueSqliteTable::new("last_read_by".to_owned(), connection);
+        // Deleting artifacts leaves free pages behind, which we want to release without having to
+        // rebuild the whole db. This only takes effect on new dbs, existing ones are switched over
+        // by `buck2 clean --compact-db`.
+        SqliteMaintenance::enable_incremental_vacuum(&connection)?;
 
-        Ok(Self {
-            materializer_state_table,
-            versions_table,
-            created_by_table,
-            last_read_by_table,
-        })
+        Ok(connection)
     }
 
-    fn create_all_tables(&self) -> anyhow::Result<()> {
-        self.materializer_state_table.create_table()?;
-        self.versions_table.create_table()?;
-        self.created_by_table.create_table()?;
-        self.last_read_by_table.create_table()?;
-        Ok(())
+    /// Reads the owner recorded in the db at `path`, if any. Any error, such as the db not
+    /// existing, is treated as there being no owner. The db is not migrated, since it may be in
+    /// use by its owner.
+    fn read_owner(path: &AbsNormPath) -> Option<MaterializerStateOwner> {
+        if !path.exists() {
+            return None;
+        }
+        let connection = Arc::new(Mutex::new(Self::connect(path).ok()?));
+        let owner = KeyValueSqliteTable::new(OWNER_TABLE_NAME.to_owned(), connection)
+            .read_all()
+            .ok()?;
+        MaterializerStateOwner::from_map(&owner)
+    }
+
+    fn record_owner(&self, owner: &MaterializerStateOwner) -> anyhow::Result<()> {
+        self.owner_table.delete_all()?;
+        self.owner_table.insert_all(owner.to_map())
+    }
+
+    fn identity(&self) -> anyhow::Result<MaterializerStateIdentity> {
+        self.created_by_table
+            .get(IDENTITY_KEY)
+            .context("Error reading creation metadata")?
+            .map(MaterializerStateIdentity)
+            .with_context(|| format!("Identity key is missing in db: `{}`", IDENTITY_KEY))
     }
 }
 
+/// Reads when to compact the materializer state db, if at all.
+pub fn sqlite_vacuum_config_from_buck_config(
+    root_config: &LegacyBuckConfig,
+) -> anyhow::Result<Option<SqliteVacuumConfig>> {
+    let enabled = root_config
+        .parse(BuckconfigKeyRef {
+            section: "buck2",
+            property: "sqlite_vacuum_enabled",
+        })?
+        .unwrap_or(true);
+    if !enabled {
+        return Ok(None);
+    }
+
+    let mut config = SqliteVacuumConfig::default();
+    if let Some(threshold) = root_config.parse(BuckconfigKeyRef {
+        section: "buck2",
+        property: "sqlite_vacuum_freelist_threshold",
+    })? {
+        config.freelist_threshold = threshold;
+    }
+    if let Some(idle_delay_s) = root_config.parse(BuckconfigKeyRef {
+        section: "buck2",
+        property: "sqlite_vacuum_idle_delay_s",
+    })? {
+        config.idle_delay = std::time::Duration::from_secs(idle_delay_s);
+    }
+    Ok(Some(config))
+}
+
+/// How materialization state is written to the materializer state db in batches.
+#[derive(Clone, Copy, Debug)]
+pub struct SqliteWriteBatchConfig {
+    /// Pending writes are flushed once there are this many.
+    pub max_entries: usize,
+    /// Pending writes are flushed at least this often.
+    pub max_delay: std::time::Duration,
+}
+
+/// Reads how to batch writes to the materializer state db, if at all.
+pub fn sqlite_write_batch_config_from_buck_config(
+    root_config: &LegacyBuckConfig,
+) -> anyhow::Result<Option<SqliteWriteBatchConfig>> {
+    let max_entries = root_config
+        .parse(BuckconfigKeyRef {
+            section: "buck2",
+            property: "materializer_sqlite_write_batch_size",
+        })?
+        .unwrap_or(1000);
+    // A batch of one is the same as writing each entry as it comes.
+    if max_entries <= 1 {
+        return Ok(None);
+    }
+    let max_delay_ms = root_config
+        .parse(BuckconfigKeyRef {
+            section: "buck2",
+            property: "materializer_sqlite_write_batch_delay_ms",
+        })?
+        .unwrap_or(1000);
+    Ok(Some(SqliteWriteBatchConfig {
+        max_entries,
+        max_delay: std::time::Duration::from_millis(max_delay_ms),
+    }))
+}
+
 #[allow(unused)] // Used by test modules
 pub(crate) fn testing_materializer_state_sqlite_db(
     fs: &ProjectRoot,
@@ -719,6 +1136,7 @@ pub(crate) fn testing_materializer_state_sqlite_db(
         metadata,
         DigestConfig::testing_default(),
         reject_identity,
+        MaterializerStateOwner::current(),
     )
 }
 
@@ -729,6 +1147,8 @@ mod tests {
     use assert_matches::assert_matches;
     use buck2_common::file_ops::FileMetadata;
     use buck2_common::file_ops::TrackedFileDigest;
+    use buck2_common::sqlite::migrations::schema_version;
+    use buck2_common::sqlite::migrations::SqliteMigrationError;
     use buck2_core::directory::DirectoryEntry;
     use buck2_core::fs::project::ProjectRootTemp;
     use buck2_core::fs::project_rel_path::ProjectRelativePath;
@@ -1068,6 +1488,237 @@ mod tests {
         Ok(())
     }
 
+    fn initialize_as(
+        fs: &ProjectRoot,
+        daemon_id: &str,
+    ) -> anyhow::Result<MaterializerStateSqliteDb> {
+        let (db, _) = MaterializerStateSqliteDb::initialize_impl(
+            materializer_state_dir(fs),
+            HashMap::new(),
+            HashMap::new(),
+            DigestConfig::testing_default(),
+            None,
+            MaterializerStateOwner::for_current_process(daemon_id.to_owned()),
+        )?;
+        Ok(db)
+    }
+
+    fn materializer_state_dir(fs: &ProjectRoot) -> AbsNormPathBuf {
+        fs.resolve(ProjectRelativePath::unchecked_new(
+            "buck-out/v2/cache/materializer_state",
+        ))
+    }
+
+    fn recorded_owner(fs: &ProjectRoot) -> Option<MaterializerStateOwner> {
+        MaterializerStateTables::read_owner(&materializer_state_dir(fs).join(
+            FileName::unchecked_new(MaterializerStateSqliteDb::DB_FILENAME),
+        ))
+    }
+
+    fn record_owner(fs: &ProjectRoot, owner: &MaterializerStateOwner) -> anyhow::Result<()> {
+        MaterializerStateTables::open(&materializer_state_dir(fs).join(FileName::unchecked_new(
+            MaterializerStateSqliteDb::DB_FILENAME,
+        )))?
+        .record_owner(owner)
+    }
+
+    #[test]
+    fn test_concurrent_owner_is_rejected() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+
+        let _first = initialize_as(fs.path(), "first")?;
+        let first_owner = MaterializerStateOwner::for_current_process("first".to_owned());
+        assert_eq!(recorded_owner(fs.path()), Some(first_owner.clone()));
+
+        let err = initialize_as(fs.path(), "second").err().unwrap();
+        assert_matches!(
+            err.downcast_ref::<MaterializerStateSqliteDbError>(),
+            Some(MaterializerStateSqliteDbError::InUse { owner: Some(owner), .. }) => {
+                assert_eq!(*owner, first_owner);
+            }
+        );
+        assert!(err.to_string().contains("daemon first"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_released_on_drop() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+
+        drop(initialize_as(fs.path(), "first")?);
+        assert_eq!(recorded_owner(fs.path()), None);
+
+        let _second = initialize_as(fs.path(), "second")?;
+        assert_eq!(
+            recorded_owner(fs.path()),
+            Some(MaterializerStateOwner::for_current_process(
+                "second".to_owned()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_owner_is_taken_over() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        drop(initialize_as(fs.path(), "first")?);
+
+        let current = MaterializerStateOwner::for_current_process("stale".to_owned());
+        let mut stale_owners = vec![
+            // Our pid, but reused by us after the owner died.
+            MaterializerStateOwner {
+                start_time: Some(current.start_time.unwrap() + 1),
+                ..current.clone()
+            },
+        ];
+        if cfg!(unix) {
+            let mut child = std::process::Command::new("true").spawn()?;
+            let pid = child.id();
+            child.wait()?;
+            stale_owners.push(MaterializerStateOwner {
+                daemon_id: "stale".to_owned(),
+                pid,
+                start_time: None,
+            });
+        }
+
+        for stale in stale_owners {
+            record_owner(fs.path(), &stale)?;
+            let db = initialize_as(fs.path(), "second")?;
+            assert_eq!(
+                recorded_owner(fs.path()),
+                Some(MaterializerStateOwner::for_current_process(
+                    "second".to_owned()
+                ))
+            );
+            drop(db);
+        }
+
+        // A record left by this process, e.g. for a previous daemon instance, is taken over too.
+        record_owner(fs.path(), &current)?;
+        let _db = initialize_as(fs.path(), "second")?;
+        assert_eq!(
+            recorded_owner(fs.path()),
+            Some(MaterializerStateOwner::for_current_process(
+                "second".to_owned()
+            ))
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_live_owner_is_rejected() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        drop(initialize_as(fs.path(), "first")?);
+
+        // Another process that recorded itself as the owner, on a file system where the lock
+        // didn't stop us.
+        let mut child = std::process::Command::new("sleep").arg("60").spawn()?;
+        let pid = child.id();
+        let live = MaterializerStateOwner {
+            daemon_id: "live".to_owned(),
+            pid,
+            start_time: Pid::from_u32(pid)
+                .ok()
+                .and_then(process_start_time)
+                .map(|t| t.as_secs()),
+        };
+        record_owner(fs.path(), &live)?;
+
+        let res = initialize_as(fs.path(), "second");
+        child.kill()?;
+        child.wait()?;
+
+        assert_matches!(
+            res.err()
+                .unwrap()
+                .downcast_ref::<MaterializerStateSqliteDbError>(),
+            Some(MaterializerStateSqliteDbError::InUse { owner: Some(owner), .. }) => {
+                assert_eq!(*owner, live);
+            }
+        );
+        assert_eq!(recorded_owner(fs.path()), Some(live));
+        Ok(())
+    }
+
+    fn db_path(fs: &ProjectRoot) -> AbsNormPathBuf {
+        materializer_state_dir(fs).join(FileName::unchecked_new(
+            MaterializerStateSqliteDb::DB_FILENAME,
+        ))
+    }
+
+    fn set_schema_version(fs: &ProjectRoot, version: u32) -> anyhow::Result<()> {
+        Connection::open(db_path(fs))?.pragma_update(None, "user_version", version)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_v0_db() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let versions = HashMap::from([("version".to_owned(), "0".to_owned())]);
+        let path = ProjectRelativePath::unchecked_new("foo").to_owned();
+        let artifact_metadata =
+            ArtifactMetadata(DirectoryEntry::Leaf(ActionDirectoryMember::File(
+                FileMetadata::empty(DigestConfig::testing_default().cas_digest_config()),
+            )));
+        let timestamp = now_seconds();
+
+        {
+            let (mut db, _) = testing_materializer_state_sqlite_db(
+                fs.path(),
+                versions.clone(),
+                HashMap::new(),
+                None,
+            )?;
+            db.materializer_state_table()
+                .insert(&path, &artifact_metadata, timestamp)?;
+        }
+        // Make it look like a db written before there were migrations.
+        set_schema_version(fs.path(), 0)?;
+
+        let (_db, loaded_state) =
+            testing_materializer_state_sqlite_db(fs.path(), versions, HashMap::new(), None)?;
+        assert_eq!(loaded_state?, vec![(path, (artifact_metadata, timestamp))]);
+        assert_eq!(
+            materializer_state_migrations().latest_version(),
+            schema_version(&Connection::open(db_path(fs.path()))?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_too_new_db_is_recreated() -> anyhow::Result<()> {
+        let fs = ProjectRootTemp::new()?;
+        let versions = HashMap::from([("version".to_owned(), "0".to_owned())]);
+
+        drop(testing_materializer_state_sqlite_db(
+            fs.path(),
+            versions.clone(),
+            HashMap::new(),
+            None,
+        )?);
+        let latest = materializer_state_migrations().latest_version();
+        set_schema_version(fs.path(), latest + 1)?;
+
+        let (_db, loaded_state) =
+            testing_materializer_state_sqlite_db(fs.path(), versions, HashMap::new(), None)?;
+        assert_matches!(
+            loaded_state,
+            Err(e) => {
+                assert_eq!(
+                    e.downcast_ref::<SqliteMigrationError>(),
+                    Some(&SqliteMigrationError::TooNew { found: latest + 1, latest })
+                );
+            }
+        );
+        assert_eq!(
+            latest,
+            schema_version(&Connection::open(db_path(fs.path()))?)?
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_delete_many() -> anyhow::Result<()> {
         let conn = Connection::open_in_memory()?;
@@ -26,7 +26,7 @@ use buck2_execute::artifact_value::ArtifactValue;
 use buck2_execute::digest::CasDigestFromReExt;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::directory::extract_artifact_value;
-use buck2_execute::directory::re_tree_to_directory;
+use buck2_execute::directory::re_tree_to_directory_with_digest_function;
 use buck2_execute::directory::ActionDirectoryMember;
 use buck2_execute::execute::action_digest::TrackedActionDigest;
 use buck2_execute::execute::executor_stage_async;
@@ -285,8 +285,16 @@ impl CasDownloader<'_> {
         let output_paths = paths.output_paths();
         let mut input_dir = input_dir.clone().into_builder();
 
+        // Output digests are computed by the server with its own digest function, which isn't
+        // necessarily the first one in `buck2.digest_algorithms`.
+        let digest_function = self.re_client.get_digest_function().await?;
+
         for x in output_spec.output_files() {
-            let digest = FileDigest::from_re(&x.digest.digest, self.digest_config)?;
+            let digest = FileDigest::from_re_action_result(
+                &x.digest.digest,
+                digest_function,
+                self.digest_config,
+            )?;
             let digest = TrackedFileDigest::new_expires(
                 digest,
                 expires,
@@ -317,7 +325,12 @@ impl CasDownloader<'_> {
             .context(DownloadError::DownloadTrees)?;
 
         for (dir, tree) in output_spec.output_directories().iter().zip(trees) {
-            let entry = re_tree_to_directory(&tree, &expires, self.digest_config)?;
+            let entry = re_tree_to_directory_with_digest_function(
+                &tree,
+                &expires,
+                digest_function,
+                self.digest_config,
+            )?;
             input_dir.insert(
                 re_forward_path(dir.path.as_str())?,
                 DirectoryEntry::Dir(entry),
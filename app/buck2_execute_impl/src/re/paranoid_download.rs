@@ -17,6 +17,7 @@ use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_execute::artifact_value::ArtifactValue;
 use buck2_execute::execute::blocking::BlockingExecutor;
 use buck2_execute::execute::blocking::IoRequest;
+use buck2_execute::execute::blocking::IoTag;
 use buck2_execute::execute::clean_output_paths::CleanOutputPaths;
 use buck2_execute::execute::manager::CommandExecutionManager;
 use buck2_execute::execute::manager::CommandExecutionManagerExt;
@@ -140,6 +141,7 @@ impl ParanoidDownloader {
                 self.inner
                     .io
                     .execute_io(
+                        IoTag::MaterializeRead,
                         Box::new(CleanOutputPaths {
                             paths: artifacts.map(|(p, _)| p.to_owned()),
                         }),
@@ -152,7 +154,11 @@ impl ParanoidDownloader {
 
                 self.inner
                     .io
-                    .execute_io(Box::new(MoveOutputsIntoPlace { mapping }), cancellations)
+                    .execute_io(
+                        IoTag::MaterializeRead,
+                        Box::new(MoveOutputsIntoPlace { mapping }),
+                        cancellations,
+                    )
                     .await?;
 
                 materializer.declare_existing(artifacts).await?;
@@ -214,7 +220,11 @@ impl Drop for CacheDownload {
 
             // Delete the cache path.
             let _ignored = io
-                .execute_io(Box::new(CleanOutputPaths { paths }), cancellations)
+                .execute_io(
+                    IoTag::Clean,
+                    Box::new(CleanOutputPaths { paths }),
+                    cancellations,
+                )
                 .await;
         });
     }
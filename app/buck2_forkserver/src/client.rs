@@ -87,10 +87,12 @@ impl ForkserverClient {
         self.inner.pid
     }
 
+    /// `on_spawned` is called with the pid of the command once it was spawned.
     pub async fn execute<C>(
         &self,
         req: buck2_forkserver_proto::CommandRequest,
         cancel: C,
+        on_spawned: impl FnOnce(u32),
     ) -> anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)>
     where
         C: Future<Output = ()> + Send + 'static,
@@ -123,7 +125,7 @@ impl ForkserverClient {
             .context("Error dispatching command to Forkserver")?
             .into_inner();
         let stream = decode_event_stream(stream);
-        decode_command_event_stream(stream).await
+        decode_command_event_stream(stream, on_spawned).await
     }
 
     pub async fn set_log_filter(&self, log_filter: String) -> anyhow::Result<()> {
@@ -26,6 +26,9 @@ where
         use buck2_forkserver_proto::command_event::Data;
 
         let data = match e {
+            CommandEvent::Spawned(pid) => {
+                Data::Spawned(buck2_forkserver_proto::SpawnedEvent { pid })
+            }
             CommandEvent::Stdout(bytes) => Data::Stdout(buck2_forkserver_proto::StreamEvent {
                 data: bytes.to_vec(),
             }),
@@ -70,6 +73,9 @@ where
         use buck2_forkserver_proto::command_event::Data;
 
         let event = match e.data.context("Missing `data`")? {
+            Data::Spawned(buck2_forkserver_proto::SpawnedEvent { pid }) => {
+                CommandEvent::Spawned(pid)
+            }
             Data::Stdout(buck2_forkserver_proto::StreamEvent { data }) => {
                 CommandEvent::Stdout(data.into())
             }
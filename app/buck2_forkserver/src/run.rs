@@ -72,6 +72,8 @@ impl From<DecodedStatus> for GatherOutputStatus {
 
 #[derive(Debug)]
 pub(crate) enum CommandEvent {
+    /// The process was spawned with this pid. Comes before any other event.
+    Spawned(u32),
     Stdout(Bytes),
     Stderr(Bytes),
     Exit(GatherOutputStatus),
@@ -192,6 +194,8 @@ where
         }
     };
 
+    let pid = process_group.id();
+
     let stdio = if stream_stdio {
         let stdout = process_group
             .take_stdout()
@@ -264,11 +268,16 @@ where
         })
     };
 
-    Ok(CommandEventStream::new(status, stdio).right_stream())
+    let spawned = pid.map(|pid| Ok(CommandEvent::Spawned(pid)));
+    Ok(futures::stream::iter(spawned)
+        .chain(CommandEventStream::new(status, stdio))
+        .right_stream())
 }
 
+/// `on_spawned` is called with the pid of the process once it was spawned.
 pub(crate) async fn decode_command_event_stream<S>(
     stream: S,
+    on_spawned: impl FnOnce(u32),
 ) -> anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)>
 where
     S: Stream<Item = anyhow::Result<CommandEvent>>,
@@ -277,9 +286,15 @@ where
 
     let mut stdout = Vec::<u8>::new();
     let mut stderr = Vec::<u8>::new();
+    let mut on_spawned = Some(on_spawned);
 
     while let Some(event) = stream.try_next().await? {
         match event {
+            CommandEvent::Spawned(pid) => {
+                if let Some(on_spawned) = on_spawned.take() {
+                    on_spawned(pid);
+                }
+            }
             CommandEvent::Stdout(bytes) => stdout.extend(&bytes),
             CommandEvent::Stderr(bytes) => stderr.extend(&bytes),
             CommandEvent::Exit(exit) => return Ok((exit, stdout, stderr)),
@@ -295,6 +310,19 @@ pub async fn gather_output<T>(
     cmd: Command,
     cancellation: T,
 ) -> anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)>
+where
+    T: Future<Output = anyhow::Result<GatherOutputStatus>> + Send,
+{
+    gather_output_with_pid(cmd, cancellation, |_pid| {}).await
+}
+
+/// Like [`gather_output`], but `on_spawned` is called with the pid of the process once it was
+/// spawned.
+pub async fn gather_output_with_pid<T>(
+    cmd: Command,
+    cancellation: T,
+    on_spawned: impl FnOnce(u32),
+) -> anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)>
 where
     T: Future<Output = anyhow::Result<GatherOutputStatus>> + Send,
 {
@@ -310,7 +338,7 @@ where
         DefaultKillProcess::default(),
         true,
     )?;
-    decode_command_event_stream(stream).await
+    decode_command_event_stream(stream, on_spawned).await
 }
 
 /// Dependency injection for kill. We use this in testing.
@@ -610,6 +638,7 @@ mod tests {
             true,
         )?
         .boxed();
+        assert_matches!(events.next().await, Some(Ok(CommandEvent::Spawned(..))));
         assert_matches!(events.next().await, Some(Ok(CommandEvent::Exit(..))));
         assert_matches!(futures::poll!(events.next()), Poll::Ready(None));
         Ok(())
@@ -693,7 +722,7 @@ mod tests {
             true,
         )?;
 
-        let (status, _stdout, _stderr) = decode_command_event_stream(stream).await?;
+        let (status, _stdout, _stderr) = decode_command_event_stream(stream, |_pid| {}).await?;
         assert!(matches!(status, GatherOutputStatus::TimedOut(..)));
 
         assert!(*killed.lock().unwrap());
@@ -722,6 +751,7 @@ mod tests {
             false,
         )?
         .boxed();
+        assert_matches!(events.next().await, Some(Ok(CommandEvent::Spawned(..))));
         assert_matches!(events.next().await, Some(Ok(CommandEvent::Exit(..))));
         assert_matches!(futures::poll!(events.next()), Poll::Ready(None));
 
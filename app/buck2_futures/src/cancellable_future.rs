@@ -28,6 +28,7 @@ use pin_project::pin_project;
 use tokio::sync::oneshot;
 
 use crate::cancellation::future::CancellationNotificationFuture;
+use crate::cancellation::CancellationReason;
 
 thread_local! {
     /// The ExecutionContext for the currently executing CancellableFuture.
@@ -467,12 +468,14 @@ impl Default for CancellationObserverInner {
 impl Dupe for CancellationObserver {}
 
 impl Future for CancellationObserver {
-    type Output = ();
+    type Output = CancellationReason;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match &mut self.0 {
+            // The legacy implementation is cancelled by dropping the last reference to the future,
+            // so there is no reason available.
             CancellationObserverInner::Legacy(fut) => match fut {
-                Some(ref mut rx) => rx.poll_unpin(cx).map(|_| ()),
+                Some(ref mut rx) => rx.poll_unpin(cx).map(|_| CancellationReason::unknown()),
                 None => Poll::Pending,
             },
             CancellationObserverInner::Explicit(fut) => fut.poll_unpin(cx),
@@ -481,8 +484,8 @@ impl Future for CancellationObserver {
 }
 
 /// Enter a structured cancellation section. The caller receives a CancellationObserver. The
-/// CancellationObserver is a future that resolves when cancellation is requested (or when this
-/// section exits).
+/// CancellationObserver is a future that resolves to the `CancellationReason` when cancellation is
+/// requested (or when this section exits).
 pub(crate) fn with_structured_cancellation<F, Fut>(
     make: F,
 ) -> impl Future<Output = <Fut as Future>::Output>
@@ -15,6 +15,7 @@ use std::future::Future;
 use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Duration;
 
 use dupe::Dupe;
 use futures::FutureExt;
@@ -34,6 +35,56 @@ use crate::cancellation::future::ExecutionContext;
 static INSTANCE: Lazy<CancellationContext> =
     Lazy::new(|| CancellationContext(CancellationContextInner::ThreadLocal));
 
+/// Why a future was cancelled. This is what a `CancellationObserver` resolves to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancellationReason {
+    pub kind: CancellationReasonKind,
+    /// Optional free-form detail, for logging.
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, Dupe, PartialEq, Eq)]
+pub enum CancellationReasonKind {
+    /// The canceller did not say why, or the reason isn't known (e.g. the thread local
+    /// implementation, which is cancelled by dropping the last reference to the future).
+    Unknown,
+    /// The client went away, e.g. because the user interrupted the command with ctrl-c.
+    ClientDisconnected,
+    /// The DICE transaction this computation belonged to is no longer live, e.g. because it was
+    /// superseded by a newer one.
+    DiceTransactionSuperseded,
+    /// The daemon is shutting down.
+    DaemonShutdown,
+    /// The command was preempted by a newer command.
+    Preempted,
+}
+
+impl CancellationReason {
+    pub fn new(kind: CancellationReasonKind) -> Self {
+        Self {
+            kind,
+            message: None,
+        }
+    }
+
+    pub fn with_message(kind: CancellationReasonKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: Some(message.into()),
+        }
+    }
+
+    pub fn unknown() -> Self {
+        Self::new(CancellationReasonKind::Unknown)
+    }
+}
+
+impl Default for CancellationReason {
+    fn default() -> Self {
+        Self::unknown()
+    }
+}
+
 /// Context available to the function running inside the future to control and manage it's own
 /// cancellation
 pub struct CancellationContext<'a>(CancellationContextInner<'a>);
@@ -71,9 +122,23 @@ impl<'a> CancellationContext<'a> {
         self.0.critical_section(make)
     }
 
+    /// Like `critical_section`, but returns `None` without calling `make` if cancellation of the
+    /// current future has already been requested. This lets callers avoid starting expensive work
+    /// that can't be aborted once it's begun.
+    pub fn try_critical_section<F, Fut>(
+        &'a self,
+        make: F,
+    ) -> Option<impl Future<Output = <Fut as Future>::Output> + 'a>
+    where
+        F: FnOnce() -> Fut + 'a,
+        Fut: Future + 'a,
+    {
+        self.0.try_critical_section(make)
+    }
+
     /// Enter a structured cancellation section. The caller receives a CancellationObserver. The
-    /// CancellationObserver is a future that resolves when cancellation is requested (or when this
-    /// section exits).
+    /// CancellationObserver is a future that resolves to the `CancellationReason` when
+    /// cancellation is requested (or when this section exits).
     pub fn with_structured_cancellation<F, Fut>(
         &'a self,
         make: F,
@@ -95,6 +160,60 @@ impl<'a> CancellationContext<'a> {
     pub fn try_to_disable_cancellation(&self) -> Option<DisableCancellationGuard> {
         self.0.try_to_disable_cancellation()
     }
+
+    /// Sleep for the given duration, waking up early if cancellation is requested. The sleep
+    /// happens inside a structured cancellation section, so the sleep is never dropped midway.
+    /// Note that the current future may still be cancelled once the section exits.
+    pub fn sleep(&'a self, duration: Duration) -> impl Future<Output = SleepOutcome> + 'a {
+        self.with_structured_cancellation(move |observer| async move {
+            tokio::select! {
+                biased;
+                _ = observer => SleepOutcome::Cancelled,
+                _ = tokio::time::sleep(duration) => SleepOutcome::Completed,
+            }
+        })
+    }
+
+    /// Run the future for up to the given duration, waking up early if cancellation is requested.
+    /// Like `sleep`, this runs inside a structured cancellation section, so the future is dropped
+    /// (rather than the caller) when either the timer or the cancellation wins.
+    pub fn timeout<Fut>(
+        &'a self,
+        duration: Duration,
+        fut: Fut,
+    ) -> impl Future<Output = TimeoutOutcome<<Fut as Future>::Output>> + 'a
+    where
+        Fut: Future + 'a,
+    {
+        self.with_structured_cancellation(move |observer| async move {
+            tokio::select! {
+                biased;
+                r = fut => TimeoutOutcome::Completed(r),
+                _ = observer => TimeoutOutcome::Cancelled,
+                _ = tokio::time::sleep(duration) => TimeoutOutcome::TimedOut,
+            }
+        })
+    }
+}
+
+/// The result of `CancellationContext::sleep`.
+#[derive(Debug, Copy, Clone, Dupe, PartialEq, Eq)]
+pub enum SleepOutcome {
+    /// The full duration elapsed.
+    Completed,
+    /// Cancellation was requested before the duration elapsed.
+    Cancelled,
+}
+
+/// The result of `CancellationContext::timeout`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeoutOutcome<T> {
+    /// The future finished before the duration elapsed.
+    Completed(T),
+    /// The duration elapsed before the future finished.
+    TimedOut,
+    /// Cancellation was requested before the future finished.
+    Cancelled,
 }
 
 /// Context available to only explicitly cancellable futures to manage their own cancellation
@@ -118,6 +237,11 @@ impl<'a> IgnoreCancellationGuard<'a> {
         ))
     }
 
+    /// Has cancellation of the current future already been requested?
+    fn is_cancellation_requested(&self) -> bool {
+        self.notification.is_notified()
+    }
+
     /// Allow cancellations again, but unlike dropping it, also checks if we should be cancelled
     /// right now, at this specific await point.
     pub async fn allow_cancellations_again(self) {
@@ -197,9 +321,34 @@ impl ExplicitCancellationContext {
         }
     }
 
+    /// Like `critical_section`, but returns `None` without calling `make` if cancellation of the
+    /// current future has already been requested. This lets callers avoid starting expensive work
+    /// that can't be aborted once it's begun.
+    pub fn try_critical_section<'a, F, Fut>(
+        &'a self,
+        make: F,
+    ) -> Option<impl Future<Output = <Fut as Future>::Output> + 'a>
+    where
+        F: FnOnce() -> Fut + 'a,
+        Fut: Future + 'a,
+    {
+        let guard = self.begin_ignore_cancellation();
+        if guard.is_cancellation_requested() {
+            return None;
+        }
+
+        Some(async move {
+            let r = make().await;
+
+            guard.allow_cancellations_again().await;
+
+            r
+        })
+    }
+
     /// Enter a structured cancellation section. The caller receives a CancellationObserver. The
-    /// CancellationObserver is a future that resolves when cancellation is requested (or when this
-    /// section exits).
+    /// CancellationObserver is a future that resolves to the `CancellationReason` when
+    /// cancellation is requested (or when this section exits).
     pub fn with_structured_cancellation<'a, F, Fut>(
         &'a self,
         make: F,
@@ -225,6 +374,52 @@ impl ExplicitCancellationContext {
             .keep_going_on_cancellations_if_not_cancelled()
     }
 
+    /// Runs `fut` until it completes or `cancel` resolves, returning the output of `cancel` in the
+    /// latter case. Once `cancel` resolves, `fut` is treated as if the current future had been
+    /// cancelled with `reason`: it's dropped right away unless it's in a critical section or
+    /// structured cancellation section of this context, in which case its observers are notified
+    /// and it's dropped as soon as the section exits.
+    pub async fn cancel_when<Fut, C, E>(
+        &self,
+        fut: Fut,
+        cancel: C,
+        reason: CancellationReason,
+    ) -> Result<Fut::Output, E>
+    where
+        Fut: Future,
+        C: Future<Output = E>,
+    {
+        futures::pin_mut!(fut);
+        futures::pin_mut!(cancel);
+
+        let mut reason = Some(reason);
+        let mut cancelled = None;
+
+        futures::future::poll_fn(|cx| {
+            if cancelled.is_none() {
+                if let Poll::Ready(e) = cancel.as_mut().poll(cx) {
+                    let reason = reason.take().expect("only cancelled once");
+                    if self.inner.request_cancellation(reason) {
+                        return Poll::Ready(Err(e));
+                    }
+                    cancelled = Some(e);
+                }
+            }
+
+            if let Poll::Ready(r) = fut.as_mut().poll(cx) {
+                return Poll::Ready(Ok(r));
+            }
+
+            // If the section that held us up just exited, then we should exit now.
+            if cancelled.is_some() && self.inner.can_exit() {
+                return Poll::Ready(Err(cancelled.take().expect("checked above")));
+            }
+
+            Poll::Pending
+        })
+        .await
+    }
+
     pub fn into_compatible(&self) -> CancellationContext {
         CancellationContext(CancellationContextInner::Explicit(self))
     }
@@ -268,9 +463,32 @@ impl<'a> CancellationContextInner<'a> {
         }
     }
 
+    /// Like `critical_section`, but returns `None` without calling `make` if cancellation of the
+    /// current future has already been requested. For the thread local implementation, this is
+    /// only known once the last reference to the future is gone.
+    pub fn try_critical_section<F, Fut>(
+        &'a self,
+        make: F,
+    ) -> Option<impl Future<Output = <Fut as Future>::Output> + 'a>
+    where
+        F: FnOnce() -> Fut + 'a,
+        Fut: Future + 'a,
+    {
+        match self {
+            CancellationContextInner::ThreadLocal => {
+                // Hold on to the task while entering so that it can't get cancelled in between.
+                let _guard = try_to_disable_cancellation()?;
+                Some(critical_section(make).left_future())
+            }
+            CancellationContextInner::Explicit(context) => {
+                Some(context.try_critical_section(make)?.right_future())
+            }
+        }
+    }
+
     /// Enter a structured cancellation section. The caller receives a CancellationObserver. The
-    /// CancellationObserver is a future that resolves when cancellation is requested (or when this
-    /// section exits).
+    /// CancellationObserver is a future that resolves to the `CancellationReason` when
+    /// cancellation is requested (or when this section exits).
     pub fn with_structured_cancellation<F, Fut>(
         &'a self,
         make: F,
@@ -300,3 +518,339 @@ impl<'a> CancellationContextInner<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::task::Poll;
+    use std::time::Duration;
+
+    use assert_matches::assert_matches;
+    use dupe::Dupe;
+    use futures::FutureExt;
+    use parking_lot::Mutex;
+
+    use crate::cancellable_future::CancellableFuture;
+    use crate::cancellation::future::make_cancellable_future;
+    use crate::cancellation::future::CancellationHandle;
+    use crate::cancellation::CancellationContext;
+    use crate::cancellation::CancellationReason;
+    use crate::cancellation::CancellationReasonKind;
+    use crate::cancellation::SleepOutcome;
+    use crate::cancellation::TimeoutOutcome;
+
+    const LONG: Duration = Duration::from_secs(3600);
+    const SHORT: Duration = Duration::from_millis(1);
+
+    #[tokio::test]
+    async fn test_explicit_sleep_cancelled() {
+        let outcome = Arc::new(Mutex::new(None));
+
+        let (fut, handle) = make_cancellable_future({
+            let outcome = outcome.dupe();
+            move |cancellations| {
+                async move {
+                    // Record the outcome from an enclosing section, since exiting the outermost
+                    // section of a cancelled future doesn't return control to the caller.
+                    cancellations
+                        .with_structured_cancellation(|_observer| async move {
+                            let r = cancellations.into_compatible().sleep(LONG).await;
+                            *outcome.lock() = Some(r);
+                        })
+                        .await
+                }
+                .boxed()
+            }
+        });
+        futures::pin_mut!(fut);
+
+        assert_matches!(futures::poll!(&mut fut), Poll::Pending);
+
+        handle.cancel();
+        assert_matches!(futures::poll!(&mut fut), Poll::Ready(..));
+        assert_eq!(*outcome.lock(), Some(SleepOutcome::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn test_explicit_sleep_completed() {
+        let (fut, _handle) = make_cancellable_future(|cancellations| {
+            async move { cancellations.into_compatible().sleep(SHORT).await }.boxed()
+        });
+
+        assert_eq!(fut.await, Some(SleepOutcome::Completed));
+    }
+
+    #[tokio::test]
+    async fn test_explicit_timeout() {
+        let (fut, _handle) = make_cancellable_future(|cancellations| {
+            async move {
+                cancellations
+                    .into_compatible()
+                    .timeout(LONG, futures::future::ready(1))
+                    .await
+            }
+            .boxed()
+        });
+        assert_eq!(fut.await, Some(TimeoutOutcome::Completed(1)));
+
+        let (fut, _handle) = make_cancellable_future(|cancellations| {
+            async move {
+                cancellations
+                    .into_compatible()
+                    .timeout(SHORT, futures::future::pending::<()>())
+                    .await
+            }
+            .boxed()
+        });
+        assert_eq!(fut.await, Some(TimeoutOutcome::TimedOut));
+    }
+
+    #[tokio::test]
+    async fn test_explicit_timeout_cancelled() {
+        let outcome = Arc::new(Mutex::new(None));
+
+        let (fut, handle) = make_cancellable_future({
+            let outcome = outcome.dupe();
+            move |cancellations| {
+                async move {
+                    cancellations
+                        .with_structured_cancellation(|_observer| async move {
+                            let r = cancellations
+                                .into_compatible()
+                                .timeout(LONG, futures::future::pending::<()>())
+                                .await;
+                            *outcome.lock() = Some(r);
+                        })
+                        .await
+                }
+                .boxed()
+            }
+        });
+        futures::pin_mut!(fut);
+
+        assert_matches!(futures::poll!(&mut fut), Poll::Pending);
+
+        handle.cancel();
+        assert_matches!(futures::poll!(&mut fut), Poll::Ready(..));
+        assert_eq!(*outcome.lock(), Some(TimeoutOutcome::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn test_nested_critical_section_inner_exits_first() {
+        let handle_slot = Arc::new(Mutex::new(None::<CancellationHandle>));
+        let outer_done = Arc::new(AtomicBool::new(false));
+
+        let (fut, handle) = make_cancellable_future({
+            let handle_slot = handle_slot.dupe();
+            let outer_done = outer_done.dupe();
+
+            move |cancellations| {
+                async move {
+                    cancellations
+                        .critical_section(|| async move {
+                            cancellations
+                                .critical_section(|| async move {
+                                    tokio::task::yield_now().await;
+                                })
+                                .await;
+
+                            // Cancel after the inner section exited, while the outer one is
+                            // still held.
+                            handle_slot
+                                .lock()
+                                .take()
+                                .expect("Expected the handle to be here by now")
+                                .cancel();
+                            tokio::task::yield_now().await;
+
+                            outer_done.store(true, Ordering::SeqCst);
+                        })
+                        .await;
+                    futures::future::pending::<()>().await
+                }
+                .boxed()
+            }
+        });
+        futures::pin_mut!(fut);
+
+        *handle_slot.lock() = Some(handle);
+
+        assert_matches!(futures::poll!(&mut fut), Poll::Pending);
+        assert!(!outer_done.load(Ordering::SeqCst));
+
+        // The outer section keeps us alive until it completes, and we're cancelled right after.
+        assert_eq!(fut.await, None);
+        assert!(outer_done.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_try_critical_section() {
+        let (fut, _handle) = make_cancellable_future(|cancellations| {
+            async move {
+                cancellations
+                    .try_critical_section(|| async { 1 })
+                    .expect("not cancelled")
+                    .await
+            }
+            .boxed()
+        });
+
+        assert_eq!(fut.await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_try_critical_section_after_cancel() {
+        let ran = Arc::new(AtomicBool::new(false));
+        let skipped = Arc::new(AtomicBool::new(false));
+
+        let (fut, handle) = make_cancellable_future({
+            let ran = ran.dupe();
+            let skipped = skipped.dupe();
+
+            move |cancellations| {
+                async move {
+                    cancellations
+                        .critical_section(|| async move {
+                            tokio::task::yield_now().await;
+
+                            match cancellations.try_critical_section(|| async move {
+                                ran.store(true, Ordering::SeqCst);
+                            }) {
+                                Some(fut) => fut.await,
+                                None => skipped.store(true, Ordering::SeqCst),
+                            }
+                        })
+                        .await
+                }
+                .boxed()
+            }
+        });
+        futures::pin_mut!(fut);
+
+        assert_matches!(futures::poll!(&mut fut), Poll::Pending);
+
+        handle.cancel();
+        assert_eq!(fut.await, None);
+        assert!(!ran.load(Ordering::SeqCst));
+        assert!(skipped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_when() {
+        let (fut, _handle) = make_cancellable_future(|cancellations| {
+            async move {
+                cancellations
+                    .cancel_when(
+                        futures::future::pending::<()>(),
+                        futures::future::ready(1),
+                        CancellationReason::new(CancellationReasonKind::Preempted),
+                    )
+                    .await
+            }
+            .boxed()
+        });
+        assert_eq!(fut.await, Some(Err(1)));
+
+        let (fut, _handle) = make_cancellable_future(|cancellations| {
+            async move {
+                cancellations
+                    .cancel_when(
+                        futures::future::ready(()),
+                        futures::future::pending::<()>(),
+                        CancellationReason::new(CancellationReasonKind::Preempted),
+                    )
+                    .await
+            }
+            .boxed()
+        });
+        assert_eq!(fut.await, Some(Ok(())));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_when_in_structured_cancellation() {
+        let (cancel, cancelled) = tokio::sync::oneshot::channel::<()>();
+        let observed = Arc::new(Mutex::new(None));
+
+        let (fut, _handle) = make_cancellable_future({
+            let observed = observed.dupe();
+            move |cancellations| {
+                async move {
+                    cancellations
+                        .cancel_when(
+                            async move {
+                                cancellations
+                                    .with_structured_cancellation(|observer| async move {
+                                        *observed.lock() = Some(observer.await);
+                                    })
+                                    .await;
+                                futures::future::pending::<()>().await
+                            },
+                            cancelled,
+                            CancellationReason::new(CancellationReasonKind::Preempted),
+                        )
+                        .await
+                        .is_err()
+                }
+                .boxed()
+            }
+        });
+        futures::pin_mut!(fut);
+
+        assert_matches!(futures::poll!(&mut fut), Poll::Pending);
+
+        cancel.send(()).unwrap();
+        // The section gets to observe the reason and wind down before the future is dropped.
+        assert_eq!(fut.await, Some(true));
+        assert_eq!(
+            *observed.lock(),
+            Some(CancellationReason::new(CancellationReasonKind::Preempted))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_thread_local_sleep_cancelled() {
+        let outcome = Arc::new(Mutex::new(None));
+
+        let (fut, guard) = CancellableFuture::new_refcounted({
+            let outcome = outcome.dupe();
+            async move {
+                let r = CancellationContext::testing().sleep(LONG).await;
+                *outcome.lock() = Some(r);
+            }
+        });
+        futures::pin_mut!(fut);
+
+        assert_matches!(futures::poll!(&mut fut), Poll::Pending);
+
+        drop(guard);
+        assert_matches!(futures::poll!(&mut fut), Poll::Ready(..));
+        assert_eq!(*outcome.lock(), Some(SleepOutcome::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn test_thread_local_sleep_completed() {
+        let (fut, _guard) = CancellableFuture::new_refcounted(async {
+            CancellationContext::testing().sleep(SHORT).await
+        });
+
+        assert_eq!(fut.await, Some(SleepOutcome::Completed));
+    }
+
+    #[tokio::test]
+    async fn test_thread_local_timeout() {
+        assert_eq!(
+            CancellationContext::testing()
+                .timeout(SHORT, futures::future::pending::<()>())
+                .await,
+            TimeoutOutcome::TimedOut
+        );
+        assert_eq!(
+            CancellationContext::testing()
+                .timeout(LONG, futures::future::ready(1))
+                .await,
+            TimeoutOutcome::Completed(1)
+        );
+    }
+}
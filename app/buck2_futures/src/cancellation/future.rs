@@ -33,6 +33,7 @@ use parking_lot::Mutex;
 use pin_project::pin_project;
 use slab::Slab;
 
+use crate::cancellation::CancellationReason;
 use crate::cancellation::ExplicitCancellationContext;
 use crate::maybe_future::MaybeFuture;
 use crate::owning_future::OwningFuture;
@@ -125,7 +126,7 @@ impl<T> ExplicitlyCancellableFutureInner<T> {
             if execution.can_exit() {
                 return Poll::Ready(None);
             }
-            execution.notify_cancelled();
+            execution.notify_cancelled(self.shared.reason());
         }
 
         let res = Pin::new(&mut self.future).poll(cx).map(Some);
@@ -206,6 +207,16 @@ impl CancellationHandle {
     /// Attempts to cancel the future this handle is associated with as soon as possible, returning
     /// a future that completes when the future is canceled.
     pub fn cancel(self) {
+        self.cancel_with_reason(CancellationReason::unknown())
+    }
+
+    /// Like `cancel`, but the `CancellationObserver`s of the future will resolve to the given
+    /// reason.
+    pub fn cancel_with_reason(self, reason: CancellationReason) {
+        // Store the reason before anything else, so that it's available by the time the future
+        // observes the cancellation.
+        *self.shared_state.inner.reason.lock() = Some(reason);
+
         // Store to the boolean first before we write to state.
         // This is because on `poll`, the future will update the state first then check the boolean.
         // This ordering ensures that either the `poll` has read our cancellation, and hence will
@@ -254,9 +265,14 @@ impl SharedState {
             inner: Arc::new(SharedStateData {
                 state: Mutex::new(State::Pending),
                 cancelled: AtomicBool::new(false),
+                reason: Mutex::new(None),
             }),
         }
     }
+
+    fn reason(&self) -> CancellationReason {
+        self.inner.reason.lock().clone().unwrap_or_default()
+    }
 }
 
 struct SharedStateData {
@@ -264,6 +280,9 @@ struct SharedStateData {
 
     /// When set, this future has been cancelled and should attempt to exit as soon as possible.
     cancelled: AtomicBool,
+
+    /// Why this future was cancelled. Set before `cancelled`.
+    reason: Mutex<Option<CancellationReason>>,
 }
 
 enum State {
@@ -296,6 +315,7 @@ impl ExecutionContext {
                     CancellationNotificationData {
                         inner: Arc::new(CancellationNotificationDataInner {
                             notified: Default::default(),
+                            reason: Mutex::new(None),
                             wakers: Mutex::new(Some(Default::default())),
                         }),
                     }
@@ -310,6 +330,22 @@ impl ExecutionContext {
         Self::new()
     }
 
+    /// Does the current future not currently prevent its cancellation?
+    pub(crate) fn can_exit(&self) -> bool {
+        self.shared.lock().can_exit()
+    }
+
+    /// Requests cancellation from within the future. Returns whether it can exit right away,
+    /// otherwise its observers are notified with `reason` and it must run until `can_exit`.
+    pub(crate) fn request_cancellation(&self, reason: CancellationReason) -> bool {
+        let mut shared = self.shared.lock();
+        if shared.can_exit() {
+            return true;
+        }
+        shared.notify_cancelled(reason);
+        false
+    }
+
     pub(crate) fn enter_structured_cancellation(
         &self,
     ) -> (CancellationNotificationData, CriticalSectionGuard) {
@@ -388,7 +424,15 @@ impl ExecutionContextData {
         self.cancellation_notification.dupe()
     }
 
-    fn notify_cancelled(&mut self) {
+    fn notify_cancelled(&mut self, reason: CancellationReason) {
+        // Record the reason before notifying so that it's visible to any observer that sees the
+        // notification.
+        self.cancellation_notification
+            .inner
+            .reason
+            .lock()
+            .get_or_insert(reason);
+
         let updated = self.cancellation_notification.inner.notified.fetch_update(
             Ordering::SeqCst,
             Ordering::SeqCst,
@@ -474,6 +518,8 @@ pub(crate) struct CancellationNotificationData {
 struct CancellationNotificationDataInner {
     /// notification status per enum 'CancellationNotificationStatus'
     notified: AtomicU8,
+    /// the reason for the cancellation, set before `notified`
+    reason: Mutex<Option<CancellationReason>>,
     wakers: Mutex<Option<Slab<Arc<AtomicWaker>>>>,
 }
 
@@ -485,6 +531,20 @@ pub(crate) struct CancellationNotificationFuture {
     waker: Arc<AtomicWaker>,
 }
 
+impl CancellationNotificationData {
+    /// Has the future been notified of its cancellation?
+    pub(crate) fn is_notified(&self) -> bool {
+        matches!(
+            CancellationNotificationStatus::from(self.inner.notified.load(Ordering::SeqCst)),
+            CancellationNotificationStatus::Notified
+        )
+    }
+
+    fn reason(&self) -> CancellationReason {
+        self.inner.reason.lock().clone().unwrap_or_default()
+    }
+}
+
 impl CancellationNotificationFuture {
     pub(crate) fn new(data: CancellationNotificationData) -> Self {
         let waker = Arc::new(AtomicWaker::new());
@@ -524,7 +584,7 @@ impl Drop for CancellationNotificationFuture {
 }
 
 impl Future for CancellationNotificationFuture {
-    type Output = ();
+    type Output = CancellationReason;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match CancellationNotificationStatus::from(self.data.inner.notified.load(Ordering::SeqCst))
@@ -534,7 +594,7 @@ impl Future for CancellationNotificationFuture {
                 // after completion
                 let id = self.id.take();
                 self.remove_waker(id);
-                Poll::Ready(())
+                Poll::Ready(self.data.reason())
             }
             _ => {
                 self.waker.register(cx.waker());
@@ -564,6 +624,8 @@ mod tests {
 
     use crate::cancellation::future::make_cancellable_future;
     use crate::cancellation::future::CancellationHandle;
+    use crate::cancellation::CancellationReason;
+    use crate::cancellation::CancellationReasonKind;
 
     struct MaybePanicOnDrop {
         panic: bool,
@@ -820,6 +882,47 @@ mod tests {
         assert_matches!(futures::poll!(&mut fut), Poll::Ready(..));
     }
 
+    #[tokio::test]
+    async fn test_structured_cancellation_observes_reason() {
+        for reason in [
+            None,
+            Some(CancellationReason::with_message(
+                CancellationReasonKind::DiceTransactionSuperseded,
+                "newer transaction",
+            )),
+        ] {
+            let observed = Arc::new(Mutex::new(None));
+
+            let (fut, handle) = make_cancellable_future({
+                let observed = observed.dupe();
+                move |cancellations| {
+                    async move {
+                        cancellations
+                            .with_structured_cancellation(|observer| async move {
+                                *observed.lock() = Some(observer.await);
+                            })
+                            .await;
+                    }
+                    .boxed()
+                }
+            });
+            futures::pin_mut!(fut);
+
+            assert_matches!(futures::poll!(&mut fut), Poll::Pending);
+
+            match reason.clone() {
+                Some(reason) => handle.cancel_with_reason(reason),
+                None => handle.cancel(),
+            }
+            assert_matches!(futures::poll!(&mut fut), Poll::Ready(..));
+
+            assert_eq!(
+                observed.lock().take(),
+                Some(reason.unwrap_or_else(CancellationReason::unknown))
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_structured_cancellation_cancels_on_exit() {
         let (fut, handle) = make_cancellable_future(|cancellations| {
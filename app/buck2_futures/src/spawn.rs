@@ -11,6 +11,7 @@
 //! tokio's JoinHandle
 
 use std::any::Any;
+use std::mem;
 use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
@@ -30,6 +31,7 @@ use crate::cancellable_future::StrongRefCount;
 use crate::cancellable_future::WeakRefCount;
 use crate::cancellation::future::make_cancellable_future;
 use crate::cancellation::future::CancellationHandle;
+use crate::cancellation::CancellationReason;
 use crate::cancellation::ExplicitCancellationContext;
 use crate::instrumented_shared::SharedEvents;
 use crate::instrumented_shared::SharedEventsFuture;
@@ -220,7 +222,13 @@ pub struct FutureAndCancellationHandle<T> {
 
 impl<T> FutureAndCancellationHandle<T> {
     pub fn into_drop_cancel(self) -> DropCancelFuture<T> {
-        self.future.into_drop_cancel(self.cancellation_handle)
+        self.into_drop_cancel_with_reason(CancellationReason::unknown())
+    }
+
+    /// Like `into_drop_cancel`, but the future is cancelled with the given reason when dropped.
+    pub fn into_drop_cancel_with_reason(self, reason: CancellationReason) -> DropCancelFuture<T> {
+        self.future
+            .into_drop_cancel(self.cancellation_handle, reason)
     }
 }
 
@@ -281,6 +289,7 @@ pub struct DropCancelFuture<T> {
     #[pin]
     fut: BoxFuture<'static, Result<T, WeakFutureError>>,
     cancellation_handle: Option<CancellationHandle>,
+    reason: CancellationReason,
 }
 
 impl<T> Future for DropCancelFuture<T> {
@@ -299,22 +308,28 @@ impl<T> Future for DropCancelFuture<T> {
 
 #[pinned_drop]
 impl<T> PinnedDrop for DropCancelFuture<T> {
-    fn drop(mut self: Pin<&mut Self>) {
+    fn drop(self: Pin<&mut Self>) {
         // ignore the termination future of when we actually shutdown. The creator of this
         // DropCancelFuture has the termination future as well that it can use to observe termination
         // if it cares
-        self.cancellation_handle
+        let this = self.project();
+        this.cancellation_handle
             .take()
             .expect("dropped twice")
-            .cancel();
+            .cancel_with_reason(mem::take(this.reason));
     }
 }
 
 impl<T> CancellableJoinHandle<T> {
-    fn into_drop_cancel(self, cancellation_handle: CancellationHandle) -> DropCancelFuture<T> {
+    fn into_drop_cancel(
+        self,
+        cancellation_handle: CancellationHandle,
+        reason: CancellationReason,
+    ) -> DropCancelFuture<T> {
         DropCancelFuture {
             fut: self.0,
             cancellation_handle: Some(cancellation_handle),
+            reason,
         }
     }
 }
@@ -432,7 +447,7 @@ mod tests {
             &MockCtx,
         );
 
-        let future = task.into_drop_cancel(cancellation_handle);
+        let future = task.into_drop_cancel(cancellation_handle, CancellationReason::unknown());
 
         drop(future);
 
@@ -466,7 +481,7 @@ mod tests {
             cancellation_handle,
         } = spawn_cancellable(|_| fut, sp.as_ref(), &MockCtx);
 
-        let future = task.into_drop_cancel(cancellation_handle);
+        let future = task.into_drop_cancel(cancellation_handle, CancellationReason::unknown());
 
         let res = future.await;
         assert_eq!(res, "Hello world!");
@@ -8,35 +8,46 @@
  */
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use allocative::Allocative;
 use anyhow::Context;
 use bytes::Bytes;
 use dupe::Dupe;
+use futures::future::BoxFuture;
 use futures::stream::BoxStream;
+use futures::FutureExt;
 use futures::StreamExt;
 use futures::TryStreamExt;
 use http::request::Builder;
 use http::uri::Scheme;
+use http::HeaderMap;
 use http::Method;
 use http::Uri;
 use hyper::client::connect::Connect;
-use hyper::client::ResponseFuture;
 use hyper::Body;
 use hyper::Request;
 use hyper::Response;
 use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
 use tokio_util::io::StreamReader;
 
+use crate::policy::HttpPolicy;
+use crate::progress::DownloadProgress;
+use crate::progress::ProgressStream;
 use crate::redirect::PendingRequest;
 use crate::redirect::RedirectEngine;
+use crate::retries::RetryPolicy;
 use crate::stats::CountingStream;
 use crate::stats::HttpNetworkStats;
 use crate::x2p::X2PAgentError;
 use crate::HttpError;
 
 mod builder;
+mod cert_reload;
 pub use builder::HttpClientBuilder;
+pub use builder::HttpVersionPreference;
 
 const DEFAULT_USER_AGENT: &str = "Buck2";
 
@@ -47,8 +58,16 @@ pub struct HttpClient {
     inner: Arc<dyn RequestClient>,
     max_redirects: Option<usize>,
     supports_vpnless: bool,
-    http2: bool,
+    version_preference: HttpVersionPreference,
+    policy: Arc<HttpPolicy>,
     stats: HttpNetworkStats,
+    max_error_body_bytes: usize,
+    retry: RetryPolicy,
+    request_timeout: Option<Duration>,
+    #[allocative(skip)]
+    default_headers: Arc<HeaderMap>,
+    #[allocative(skip)]
+    progress: Option<Arc<dyn DownloadProgress>>,
 }
 
 impl HttpClient {
@@ -81,6 +100,40 @@ impl HttpClient {
         self.request(req).await
     }
 
+    /// Send a GET request and stream the response body into `writer` as it is received, rather
+    /// than buffering it. Returns the number of bytes written.
+    pub async fn get_to_writer(
+        &self,
+        uri: &str,
+        writer: &mut (dyn AsyncWrite + Unpin + Send),
+    ) -> Result<u64, HttpError> {
+        let mut body = self.get(uri).await?.into_body();
+        let mut written = 0;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|source| HttpError::ReadBody {
+                uri: uri.to_owned(),
+                received: written,
+                source,
+            })?;
+            writer
+                .write_all(&chunk)
+                .await
+                .map_err(|source| HttpError::WriteBody {
+                    uri: uri.to_owned(),
+                    source,
+                })?;
+            written += chunk.len() as u64;
+        }
+        writer
+            .flush()
+            .await
+            .map_err(|source| HttpError::WriteBody {
+                uri: uri.to_owned(),
+                source,
+            })?;
+        Ok(written)
+    }
+
     pub async fn post(
         &self,
         uri: &str,
@@ -128,7 +181,7 @@ impl HttpClient {
             if is_hyper_error_due_to_timeout(&e) {
                 HttpError::Timeout {
                     uri,
-                    duration: now.elapsed().as_secs(),
+                    duration: now.elapsed(),
                 }
             } else {
                 HttpError::SendRequest { uri, source: e }
@@ -141,26 +194,110 @@ impl HttpClient {
         )
     }
 
-    /// Send a generic request.
+    /// Send a generic request, retrying it as configured if it fails with a transient error.
     pub async fn request(
         &self,
         request: Request<Bytes>,
+    ) -> Result<Response<BoxStream<hyper::Result<Bytes>>>, HttpError> {
+        let uri = request.uri().to_string();
+        let resp = self.request_with_retries(request).await?;
+        match &self.progress {
+            Some(progress) => {
+                let total_bytes = resp
+                    .headers()
+                    .get(http::header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok()?.parse().ok());
+                let progress = progress.dupe();
+                Ok(resp.map(|body| ProgressStream::new(body, progress, uri, total_bytes).boxed()))
+            }
+            None => Ok(resp),
+        }
+    }
+
+    async fn request_with_retries(
+        &self,
+        mut request: Request<Bytes>,
+    ) -> Result<Response<BoxStream<hyper::Result<Bytes>>>, HttpError> {
+        // Added before redirects are handled, so that they are removed like any other header
+        // when redirecting to another host.
+        for name in self.default_headers.keys() {
+            if !request.headers().contains_key(name) {
+                for value in self.default_headers.get_all(name) {
+                    request.headers_mut().append(name, value.clone());
+                }
+            }
+        }
+
+        if !self.retry.applies_to(request.method()) {
+            return self.request_once(request).await;
+        }
+
+        let pending_request = PendingRequest::from_request(&request);
+        let mut attempts = 1;
+        let mut result = self.request_once(request).await;
+        while let Err(e) = &result {
+            if attempts > self.retry.max_retries || !e.is_transient() {
+                break;
+            }
+            let delay = self.retry.delay(attempts);
+            tracing::debug!(
+                "http: retrying request to {} in {:?} after error: {}",
+                pending_request.uri(),
+                delay,
+                e
+            );
+            tokio::time::sleep(delay).await;
+            result = self.request_once(pending_request.to_request()?).await;
+            attempts += 1;
+        }
+
+        result.map_err(|e| {
+            if attempts > 1 {
+                HttpError::Retried {
+                    uri: pending_request.uri().to_string(),
+                    attempts,
+                    source: Box::new(e),
+                }
+            } else {
+                e
+            }
+        })
+    }
+
+    async fn request_once(
+        &self,
+        request: Request<Bytes>,
     ) -> Result<Response<BoxStream<hyper::Result<Bytes>>>, HttpError> {
         let pending_request = PendingRequest::from_request(&request);
         let uri = request.uri().clone();
+        self.policy.check(&uri)?;
         tracing::debug!("http: request: {:?}", request);
-        let resp = self.send_request_impl(request).await?;
-        tracing::debug!("http: response: {:?}", resp.status());
-
-        // Handle redirects up to self.max_redirects times.
-        let resp = if let Some(max_redirects) = self.max_redirects {
-            let redirect_engine = RedirectEngine::new(max_redirects, pending_request, resp);
-            redirect_engine
-                .handle_redirects(|req| self.send_request_impl(req))
-                .await?
-        } else {
-            resp
+        let send = async {
+            let resp = self.send_request_impl(request).await?;
+            tracing::debug!("http: response: {:?}", resp.status());
+
+            // Handle redirects up to self.max_redirects times.
+            if let Some(max_redirects) = self.max_redirects {
+                let redirect_engine =
+                    RedirectEngine::new(max_redirects, &self.policy, pending_request, resp);
+                redirect_engine
+                    .handle_redirects(|req| self.send_request_impl(req))
+                    .await
+            } else {
+                Ok(resp)
+            }
         };
+        // The timeout covers all the redirects, rather than restarting with each of them.
+        let resp =
+            match self.request_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, send).await.map_err(|_| {
+                    HttpError::Timeout {
+                        uri: uri.to_string(),
+                        duration: timeout,
+                    }
+                })??,
+                None => send.await?,
+            };
 
         if !resp.status().is_success() {
             // Handle x2p errors as indicated by headers.
@@ -172,7 +309,7 @@ impl HttpClient {
             }
 
             let status = resp.status();
-            let text = read_truncated_error_response(resp).await;
+            let text = read_truncated_error_response(resp, self.max_error_body_bytes).await;
             return Err(HttpError::Status {
                 status,
                 uri: uri.to_string(),
@@ -183,6 +320,27 @@ impl HttpClient {
         Ok(resp)
     }
 
+    /// A client sending its requests like this one, and telling `progress` how far along the
+    /// bodies of the successful responses have been read.
+    pub fn with_download_progress(&self, progress: Arc<dyn DownloadProgress>) -> HttpClient {
+        HttpClient {
+            progress: Some(progress),
+            ..self.dupe()
+        }
+    }
+
+    /// A client sending its requests like this one, but never retrying them. For callers that
+    /// already retry the whole request themselves.
+    pub fn without_retries(&self) -> HttpClient {
+        HttpClient {
+            retry: RetryPolicy {
+                max_retries: 0,
+                ..self.retry
+            },
+            ..self.dupe()
+        }
+    }
+
     pub fn stats(&self) -> &HttpNetworkStats {
         &self.stats
     }
@@ -194,8 +352,13 @@ impl HttpClient {
         self.supports_vpnless
     }
 
+    /// Whether this client may use HTTP/2.
     pub fn http2(&self) -> bool {
-        self.http2
+        self.version_preference != HttpVersionPreference::Http1Only
+    }
+
+    pub fn http_version_preference(&self) -> HttpVersionPreference {
+        self.version_preference
     }
 }
 
@@ -203,31 +366,43 @@ impl HttpClient {
 /// the connector. At runtime, we want to pick different connectors (e.g. HttpsConnector,
 /// ProxyConnector<HttpsConnector<..>>, etc); thus wrap the client so we can switch
 /// out the concrete type without exposing implementation details to callers.
-pub(super) trait RequestClient: Send + Sync {
-    fn request(&self, request: Request<Bytes>) -> ResponseFuture;
+pub(crate) trait RequestClient: Send + Sync {
+    fn request(&self, request: Request<Bytes>)
+    -> BoxFuture<'static, hyper::Result<Response<Body>>>;
 }
 
 impl<C> RequestClient for hyper::Client<C>
 where
     C: Connect + Clone + Send + Sync + 'static,
 {
-    fn request(&self, request: Request<Bytes>) -> ResponseFuture {
-        self.request(request.map(Body::from))
+    fn request(
+        &self,
+        request: Request<Bytes>,
+    ) -> BoxFuture<'static, hyper::Result<Response<Body>>> {
+        self.request(request.map(Body::from)).boxed()
     }
 }
 
+/// Read at most `max_bytes` of the body of an error response, since the server may send an
+/// arbitrarily large one.
 async fn read_truncated_error_response(
     mut resp: Response<BoxStream<'_, hyper::Result<Bytes>>>,
+    max_bytes: usize,
 ) -> String {
     let read = StreamReader::new(
         resp.body_mut()
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e)),
     );
-    let mut buf = Vec::with_capacity(1024);
-    read.take(1024).read_to_end(&mut buf).await.map_or_else(
-        |e| format!("Error decoding response: {:?}", e),
-        |_| String::from_utf8_lossy(buf.as_ref()).into_owned(),
-    )
+    let mut buf = Vec::with_capacity(max_bytes.min(1024));
+    // Read one more byte to tell whether the body was truncated.
+    match read.take(max_bytes as u64 + 1).read_to_end(&mut buf).await {
+        Ok(_) if buf.len() > max_bytes => {
+            buf.truncate(max_bytes);
+            format!("{}... (truncated)", String::from_utf8_lossy(&buf))
+        }
+        Ok(_) => String::from_utf8_lossy(&buf).into_owned(),
+        Err(e) => format!("Error decoding response: {:?}", e),
+    }
 }
 
 /// Helper function to consume a response stream and convert it to a Bytes container.
@@ -273,12 +448,14 @@ fn is_hyper_error_due_to_timeout(e: &hyper::Error) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use assert_matches::assert_matches;
     use http::StatusCode;
     use httptest::matchers::*;
     use httptest::responders;
     use httptest::Expectation;
 
     use super::*;
+    use crate::PolicyRule;
 
     #[test]
     fn test_change_scheme_to_http_succeeds() -> anyhow::Result<()> {
@@ -402,6 +579,30 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_large_error_response_is_truncated() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .respond_with(responders::status_code(500).body(vec![b'x'; 16 * 1024 * 1024])),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_max_error_body_bytes(100)
+            .build();
+        let result = client.get(&test_server.url_str("/foo")).await;
+        if let Err(HttpError::Status { status, text, .. }) = &result {
+            assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, *status);
+            assert_eq!(format!("{}... (truncated)", "x".repeat(100)), *text);
+        } else {
+            unreachable!("Expected HttpError::Status, got {:?}", result.err());
+        }
+        // The rest of the body was not read.
+        assert!(client.stats().get_downloaded_bytes() < 16 * 1024 * 1024);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_count_response_size() -> anyhow::Result<()> {
         let test_server = httptest::Server::run();
@@ -428,6 +629,61 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_http1_only() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .respond_with(responders::status_code(200)),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_http_version_preference(HttpVersionPreference::Http1Only)
+            .build();
+        let resp = client.get(&test_server.url_str("/foo")).await?;
+        assert_eq!(200, resp.status().as_u16());
+        assert_eq!(http::Version::HTTP_11, resp.version());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http2_only_against_http1_server() -> anyhow::Result<()> {
+        // A server that only speaks HTTP/1.1.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/foo", listener.local_addr()?);
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await?;
+            let mut buf = [0; 1024];
+            let _ = socket.read(&mut buf).await?;
+            socket
+                .write_all(b"HTTP/1.1 505 HTTP Version Not Supported\r\ncontent-length: 0\r\n\r\n")
+                .await?;
+            anyhow::Ok(())
+        });
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_http_version_preference(HttpVersionPreference::Http2Only)
+            .build();
+        let result = client.get(&url).await;
+        server.await??;
+        match result.err() {
+            Some(HttpError::SendRequest { uri, source }) => {
+                assert_eq!(url, uri);
+                // The client spoke HTTP/2 without negotiating it, and couldn't make sense of the
+                // HTTP/1.1 response.
+                assert!(
+                    source.to_string().contains("http2 error"),
+                    "Expected an HTTP/2 error, got: {:?}",
+                    source
+                );
+            }
+            e => unreachable!("Expected HttpError::SendRequest, got {:?}", e),
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_follows_redirects() -> anyhow::Result<()> {
         let test_server = httptest::Server::run();
@@ -461,6 +717,108 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_get_to_writer() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(1)
+                .respond_with(
+                    responders::status_code(302).append_header(http::header::LOCATION, "/bar"),
+                ),
+        );
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/bar"))
+                .times(1)
+                .respond_with(responders::status_code(200).body(vec![7; 100_000])),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_max_redirects(10)
+            .build();
+        let mut out = Vec::new();
+        let written = client
+            .get_to_writer(&test_server.url_str("/foo"), &mut out)
+            .await?;
+        assert_eq!(100_000, written);
+        assert_eq!(vec![7; 100_000], out);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_to_writer_truncated_body() -> anyhow::Result<()> {
+        // Announce more bytes than are sent, then hang up.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/foo", listener.local_addr()?);
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await?;
+            let mut buf = [0; 1024];
+            let _ = socket.read(&mut buf).await?;
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 100\r\n\r\n0123456789")
+                .await?;
+            anyhow::Ok(())
+        });
+
+        let client = HttpClientBuilder::https_with_system_roots()?.build();
+        let mut out = Vec::new();
+        let result = client.get_to_writer(&url, &mut out).await;
+        server.await??;
+        match result {
+            Err(HttpError::ReadBody { uri, received, .. }) => {
+                assert_eq!(url, uri);
+                assert_eq!(10, received);
+            }
+            r => unreachable!("Expected HttpError::ReadBody, got {:?}", r),
+        }
+        assert_eq!(b"0123456789", out.as_slice());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_progress_reported_for_final_response() -> anyhow::Result<()> {
+        #[derive(Default)]
+        struct Recorder(std::sync::Mutex<Vec<(String, u64, Option<u64>)>>);
+
+        impl DownloadProgress for Recorder {
+            fn progress(&self, uri: &str, downloaded_bytes: u64, total_bytes: Option<u64>) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((uri.to_owned(), downloaded_bytes, total_bytes));
+            }
+        }
+
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(1)
+                .respond_with(
+                    responders::status_code(302)
+                        .append_header(http::header::LOCATION, "/bar")
+                        .body("redirecting"),
+                ),
+        );
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/bar"))
+                .times(1)
+                .respond_with(responders::status_code(200).body("0123456789")),
+        );
+
+        let recorder = Arc::new(Recorder::default());
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_max_redirects(1)
+            .build()
+            .with_download_progress(recorder.clone());
+        let url = test_server.url_str("/foo");
+        assert_eq!(10, client.get_to_writer(&url, &mut Vec::new()).await?);
+
+        assert_eq!(vec![(url, 10, Some(10))], *recorder.0.lock().unwrap());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_head_changes_to_get_on_redirect() -> anyhow::Result<()> {
         let test_server = httptest::Server::run();
@@ -582,6 +940,307 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_request_timeout_covers_redirects() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        // Each response comes in before the timeout, but not all of them.
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(1)
+                .respond_with(responders::delay_and_then(
+                    Duration::from_millis(300),
+                    responders::status_code(302).append_header(http::header::LOCATION, "/bar"),
+                )),
+        );
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/bar"))
+                .times(1)
+                .respond_with(responders::delay_and_then(
+                    Duration::from_millis(300),
+                    responders::status_code(200),
+                )),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_max_redirects(10)
+            .with_request_timeout(Some(Duration::from_millis(500)))
+            .build();
+        let url = test_server.url_str("/foo");
+        let result = client.get(&url).await;
+        if let Err(HttpError::Timeout { uri, duration }) = &result {
+            assert_eq!(url, *uri);
+            assert_eq!(Duration::from_millis(500), *duration);
+        } else {
+            unreachable!("Expected HttpError::Timeout, got {:?}", result.err());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(3)
+                .respond_with(httptest::cycle![
+                    responders::status_code(503),
+                    responders::status_code(429),
+                    responders::status_code(200),
+                ]),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_retries(2, Duration::from_millis(1))
+            .build();
+        let resp = client.get(&test_server.url_str("/foo")).await?;
+        assert_eq!(200, resp.status().as_u16());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retries_exhausted() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(3)
+                .respond_with(responders::status_code(500)),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_retries(2, Duration::from_millis(1))
+            .build();
+        let result = client.get(&test_server.url_str("/foo")).await;
+        if let Err(HttpError::Retried {
+            attempts, source, ..
+        }) = &result
+        {
+            assert_eq!(3, *attempts);
+            assert_matches!(
+                **source,
+                HttpError::Status { status, .. } if status == StatusCode::INTERNAL_SERVER_ERROR
+            );
+        } else {
+            unreachable!("Expected HttpError::Retried, got {:?}", result.err());
+        }
+        let err = buck2_error::Error::from(result.err().unwrap());
+        assert!(err.tags().contains(&buck2_error::ErrorTag::HttpServer));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_non_transient_errors() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(1)
+                .respond_with(responders::status_code(404)),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_retries(2, Duration::from_millis(1))
+            .build();
+        let result = client.get(&test_server.url_str("/foo")).await;
+        assert_matches!(result.err(), Some(HttpError::Status { .. }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_without_retries_sends_request_once() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(1)
+                .respond_with(responders::status_code(503)),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_retries(2, Duration::from_millis(1))
+            .build()
+            .without_retries();
+        let result = client.get(&test_server.url_str("/foo")).await;
+        assert_matches!(result.err(), Some(HttpError::Status { .. }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retries_non_idempotent_only_when_enabled() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("POST", "/foo"))
+                .times(3)
+                .respond_with(httptest::cycle![
+                    responders::status_code(503),
+                    responders::status_code(503),
+                    responders::status_code(200),
+                ]),
+        );
+
+        let mut builder = HttpClientBuilder::https_with_system_roots()?;
+        builder.with_retries(2, Duration::from_millis(1));
+        let url = test_server.url_str("/foo");
+
+        let result = builder.build().post(&url, Bytes::new(), vec![]).await;
+        assert_matches!(result.err(), Some(HttpError::Status { .. }));
+
+        let resp = builder
+            .with_retry_non_idempotent()
+            .build()
+            .post(&url, Bytes::new(), vec![])
+            .await?;
+        assert_eq!(200, resp.status().as_u16());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_request_blocked_by_policy() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(0)
+                .respond_with(responders::status_code(200)),
+        );
+
+        let url = test_server.url_str("/foo");
+        for policy in [
+            HttpPolicy::new(&["example.com"], &[], &[]),
+            HttpPolicy::new(&[], &["127.0.0.1"], &[]),
+            HttpPolicy::new(&[], &[], &["https"]),
+        ] {
+            let client = HttpClientBuilder::https_with_system_roots()?
+                .with_policy(policy)
+                .build();
+            let result = client.get(&url).await;
+            if let Err(HttpError::BlockedByPolicy { uri, .. }) = &result {
+                assert_eq!(&url, uri);
+            } else {
+                unreachable!(
+                    "Expected HttpError::BlockedByPolicy, got {:?}",
+                    result.err()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_redirect_blocked_by_policy() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        // The initial host is allowed, but the redirect goes to another one.
+        let redirect_url = format!("http://localhost:{}/bar", test_server.addr().port());
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(1)
+                .respond_with(
+                    responders::status_code(302)
+                        .append_header(http::header::LOCATION, redirect_url.clone()),
+                ),
+        );
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/bar"))
+                .times(0)
+                .respond_with(responders::status_code(200)),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_max_redirects(10)
+            .with_policy(HttpPolicy::new(&["127.0.0.1"], &[], &[]))
+            .build();
+        let result = client.get(&test_server.url_str("/foo")).await;
+        if let Err(HttpError::BlockedByPolicy { uri, rule }) = &result {
+            assert_eq!(&redirect_url, uri);
+            assert_eq!(
+                &PolicyRule::HostNotAllowed {
+                    host: "localhost".to_owned()
+                },
+                rule
+            );
+        } else {
+            unreachable!(
+                "Expected HttpError::BlockedByPolicy, got {:?}",
+                result.err()
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_default_headers_dropped_on_cross_host_redirect() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        // Same server, but a different host.
+        let redirect_url = format!("http://localhost:{}/bar", test_server.addr().port());
+        test_server.expect(
+            Expectation::matching(all_of![
+                request::method_path("GET", "/foo"),
+                request::headers(contains(("authorization", "Bearer token"))),
+                request::headers(contains(("x-custom", "explicit"))),
+            ])
+            .times(1)
+            .respond_with(
+                responders::status_code(302)
+                    .append_header(http::header::LOCATION, redirect_url.clone()),
+            ),
+        );
+        test_server.expect(
+            Expectation::matching(all_of![
+                request::method_path("GET", "/bar"),
+                request::headers(not(contains(key("authorization")))),
+            ])
+            .times(1)
+            .respond_with(responders::status_code(200)),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_max_redirects(10)
+            .with_default_header(
+                http::header::AUTHORIZATION,
+                http::HeaderValue::from_static("Bearer token"),
+            )
+            .with_default_header(
+                http::HeaderName::from_static("x-custom"),
+                http::HeaderValue::from_static("default"),
+            )
+            .build();
+        // Headers set on the request take precedence.
+        let request = Request::builder()
+            .uri(test_server.url_str("/foo"))
+            .header("x-custom", "explicit")
+            .body(Bytes::new())?;
+        let resp = client.request(request).await?;
+        assert_eq!(200, resp.status().as_u16());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_request_allowed_by_policy() -> anyhow::Result<()> {
+        let test_server = httptest::Server::run();
+        test_server.expect(
+            Expectation::matching(request::method_path("GET", "/foo"))
+                .times(1)
+                .respond_with(responders::status_code(200)),
+        );
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_policy(HttpPolicy::new(
+                &["127.0.0.1"],
+                &["*.example.com"],
+                &["http"],
+            ))
+            .build();
+        let resp = client.get(&test_server.url_str("/foo")).await?;
+        assert_eq!(200, resp.status().as_u16());
+
+        Ok(())
+    }
+
     #[cfg(unix)]
     mod unix {
         use std::convert::Infallible;
@@ -8,10 +8,17 @@
  */
 
 use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use allocative::Allocative;
 use anyhow::Context;
+use dupe::Dupe;
+use http::HeaderMap;
+use http::HeaderName;
+use http::HeaderValue;
 use hyper::client::HttpConnector;
 use hyper::service::Service;
 use hyper::Body;
@@ -22,17 +29,65 @@ use hyper_rustls::HttpsConnector;
 use hyper_rustls::HttpsConnectorBuilder;
 use hyper_timeout::TimeoutConnector;
 use rustls::ClientConfig;
+use serde::Deserialize;
+use serde::Serialize;
 use tokio::io::AsyncRead;
 use tokio::io::AsyncWrite;
 use tokio_rustls::TlsConnector;
 
+use super::cert_reload::CertReloadingRequestClient;
 use super::HttpClient;
 use super::RequestClient;
+use crate::policy::HttpPolicy;
 use crate::proxy;
+use crate::retries::RetryPolicy;
 use crate::stats::HttpNetworkStats;
 use crate::tls;
+#[cfg(unix)]
+use crate::unix_socket;
 use crate::x2p;
 
+const DEFAULT_MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// Which HTTP versions the client may use.
+#[derive(
+    Copy,
+    Clone,
+    Dupe,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Allocative,
+    Serialize,
+    Deserialize
+)]
+pub enum HttpVersionPreference {
+    /// HTTP/2 if the server agrees to it when negotiating TLS, HTTP/1.1 otherwise.
+    #[default]
+    Auto,
+    /// Only HTTP/1.1, for servers and middleboxes that handle HTTP/2 badly.
+    Http1Only,
+    /// Only HTTP/2, also without TLS, assuming the server supports it ("prior knowledge").
+    Http2Only,
+}
+
+impl FromStr for HttpVersionPreference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "http1_only" => Ok(Self::Http1Only),
+            "http2_only" => Ok(Self::Http2Only),
+            _ => Err(anyhow::anyhow!(
+                "Invalid HTTP version preference: `{}`, expected one of `auto`, `http1_only`, `http2_only`",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct TimeoutConfig {
     connect_timeout: Option<Duration>,
@@ -56,13 +111,24 @@ impl TimeoutConfig {
     }
 }
 
+#[derive(Clone)]
 pub struct HttpClientBuilder {
     tls_config: ClientConfig,
+    /// Certificate `tls_config` authenticates with, if it was loaded from a file.
+    client_auth_cert: Option<PathBuf>,
+    /// Socket to send all the requests to, rather than to the hosts of their URLs.
+    #[cfg(unix)]
+    unix_socket: Option<PathBuf>,
     proxies: Vec<Proxy>,
     max_redirects: Option<usize>,
     supports_vpnless: bool,
-    http2: bool,
+    version_preference: HttpVersionPreference,
     timeout_config: Option<TimeoutConfig>,
+    policy: HttpPolicy,
+    max_error_body_bytes: usize,
+    retry: RetryPolicy,
+    request_timeout: Option<Duration>,
+    default_headers: HeaderMap,
 }
 
 impl HttpClientBuilder {
@@ -71,6 +137,11 @@ impl HttpClientBuilder {
         tracing::debug!("Using OSS client");
         let mut builder = Self::https_with_system_roots()?;
         builder.with_proxy_from_env()?;
+        #[cfg(unix)]
+        if let Some(socket) = unix_socket::unix_socket_from_env() {
+            tracing::debug!("Using unix socket client at: {}", socket.display());
+            builder.with_unix_socket(socket);
+        }
         Ok(builder)
     }
 
@@ -93,25 +164,53 @@ impl HttpClientBuilder {
 
     /// Creates a barebones https client using system roots for TLS authentication.
     pub fn https_with_system_roots() -> anyhow::Result<Self> {
-        let tls_config = tls::tls_config_with_system_roots()?;
-        Ok(Self {
+        Ok(Self::new(tls::tls_config_with_system_roots()?))
+    }
+
+    pub(crate) fn new(tls_config: ClientConfig) -> Self {
+        Self {
             tls_config,
+            client_auth_cert: None,
+            #[cfg(unix)]
+            unix_socket: None,
             proxies: Vec::new(),
             max_redirects: None,
             supports_vpnless: false,
-            http2: true,
+            version_preference: HttpVersionPreference::Auto,
             timeout_config: None,
-        })
+            policy: HttpPolicy::default(),
+            max_error_body_bytes: DEFAULT_MAX_ERROR_BODY_BYTES,
+            retry: RetryPolicy {
+                max_retries: 0,
+                initial_delay: Duration::from_secs(1),
+                retry_non_idempotent: false,
+            },
+            request_timeout: None,
+            default_headers: HeaderMap::new(),
+        }
     }
 
     pub fn with_tls_config(&mut self, tls_config: ClientConfig) -> &mut Self {
         self.tls_config = tls_config;
+        self.client_auth_cert = None;
         self
     }
 
+    /// Authenticate with the certificate and key at `path`. The clients built reload them when
+    /// the file changes.
     pub fn with_client_auth_cert<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<&mut Self> {
         let tls_config = tls::tls_config_with_single_cert(path.as_ref(), path.as_ref())?;
-        Ok(self.with_tls_config(tls_config))
+        self.with_tls_config(tls_config);
+        self.client_auth_cert = Some(path.as_ref().to_owned());
+        Ok(self)
+    }
+
+    /// Send all the requests over plain HTTP to the server listening on the unix domain socket
+    /// at `path`, whatever the host in their URL. Proxies are not used.
+    #[cfg(unix)]
+    pub fn with_unix_socket<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.unix_socket = Some(path.into());
+        self
     }
 
     pub fn with_proxy(&mut self, proxy: Proxy) -> &mut Self {
@@ -185,6 +284,45 @@ impl HttpClientBuilder {
         self.timeout_config.as_ref().and_then(|c| c.write_timeout)
     }
 
+    /// Bounds how long it takes to get the response to a request, from sending it to receiving
+    /// the headers of the response to its last redirect. Reading the body is bounded by the read
+    /// timeout instead.
+    pub fn with_request_timeout(&mut self, request_timeout: Option<Duration>) -> &mut Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+    }
+
+    /// Adds a header to all the requests that don't set it themselves, e.g. to authenticate
+    /// them. Like other sensitive headers, `Authorization` is not sent to the other hosts
+    /// requests are redirected to.
+    ///
+    /// Adding the same header several times sends all its values.
+    pub fn with_default_header(&mut self, name: HeaderName, value: HeaderValue) -> &mut Self {
+        self.default_headers.append(name, value);
+        self
+    }
+
+    /// Like [`with_default_header`](Self::with_default_header), for a header written as
+    /// `Name: value`, as in `http.default_headers`.
+    pub fn with_default_header_line(&mut self, header: &str) -> anyhow::Result<&mut Self> {
+        let (name, value) = header
+            .split_once(':')
+            .with_context(|| format!("Invalid header `{}`, expected `Name: value`", header))?;
+        let name = HeaderName::from_str(name.trim())
+            .with_context(|| format!("Invalid header name in `{}`", header))?;
+        let value = HeaderValue::from_str(value.trim())
+            .with_context(|| format!("Invalid header value for `{}`", name))?;
+        Ok(self.with_default_header(name, value))
+    }
+
+    pub fn default_headers(&self) -> &HeaderMap {
+        &self.default_headers
+    }
+
     pub fn with_max_redirects(&mut self, max_redirects: usize) -> &mut Self {
         self.max_redirects = Some(max_redirects);
         self
@@ -199,16 +337,85 @@ impl HttpClientBuilder {
         self
     }
 
-    pub fn with_http2(&mut self, http2: bool) -> &mut Self {
-        self.http2 = http2;
+    pub fn with_http_version_preference(
+        &mut self,
+        version_preference: HttpVersionPreference,
+    ) -> &mut Self {
+        self.version_preference = version_preference;
         self
     }
 
+    pub fn http_version_preference(&self) -> HttpVersionPreference {
+        self.version_preference
+    }
+
     pub fn supports_vpnless(&self) -> bool {
         self.supports_vpnless
     }
 
+    /// Restricts the hosts and schemes requests can be sent to.
+    pub fn with_policy(&mut self, policy: HttpPolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> &HttpPolicy {
+        &self.policy
+    }
+
+    /// Caps how much of the body of an unsuccessful response is read to report it in errors.
+    pub fn with_max_error_body_bytes(&mut self, max_error_body_bytes: usize) -> &mut Self {
+        self.max_error_body_bytes = max_error_body_bytes;
+        self
+    }
+
+    pub fn max_error_body_bytes(&self) -> usize {
+        self.max_error_body_bytes
+    }
+
+    /// Retry GET and HEAD requests up to `max_retries` times when they fail with connection
+    /// errors, timeouts, 429 or 5xx, waiting about `initial_delay` before the first retry and
+    /// twice as long before every following one.
+    pub fn with_retries(&mut self, max_retries: usize, initial_delay: Duration) -> &mut Self {
+        self.retry.max_retries = max_retries;
+        self.retry.initial_delay = initial_delay;
+        self
+    }
+
+    pub fn max_retries(&self) -> usize {
+        self.retry.max_retries
+    }
+
+    /// Also retry requests that are not idempotent, like POST and PUT. Only use this if the
+    /// server handles receiving the same request twice.
+    pub fn with_retry_non_idempotent(&mut self) -> &mut Self {
+        self.retry.retry_non_idempotent = true;
+        self
+    }
+
+    fn client_builder(&self) -> hyper::client::Builder {
+        let mut builder = hyper::Client::builder();
+        // ALPN can't tell the server which version to use without TLS, so assume it speaks the
+        // one we want.
+        if self.version_preference == HttpVersionPreference::Http2Only {
+            builder.http2_only(true);
+        }
+        builder
+    }
+
     fn build_inner(&self) -> Arc<dyn RequestClient> {
+        #[cfg(unix)]
+        if let Some(socket) = &self.unix_socket {
+            let connector = unix_socket::UnixSocketConnector::new(socket.clone());
+            return match &self.timeout_config {
+                Some(timeout_config) => Arc::new(
+                    self.client_builder()
+                        .build::<_, Body>(timeout_config.to_connector(connector)),
+                ),
+                None => Arc::new(self.client_builder().build::<_, Body>(connector)),
+            };
+        }
+
         match (self.proxies.as_slice(), &self.timeout_config) {
             // Construct x2p unix socket client.
             // Note: This ignores (and does not require) the TLS config.
@@ -220,7 +427,7 @@ impl HttpClientBuilder {
                     timeout_config.to_connector(hyper_unix_connector::UnixClient);
                 let proxy_connector =
                     build_proxy_connector(&[unix_socket.clone()], timeout_connector, None);
-                Arc::new(hyper::Client::builder().build::<_, Body>(proxy_connector))
+                Arc::new(self.client_builder().build::<_, Body>(proxy_connector))
             }
             #[cfg(unix)]
             (proxies @ [_, ..], None) if let Some(unix_socket) = find_unix_proxy(proxies) => {
@@ -229,7 +436,7 @@ impl HttpClientBuilder {
                     hyper_unix_connector::UnixClient,
                     None,
                 );
-                Arc::new(hyper::Client::builder().build::<_, Body>(proxy_connector))
+                Arc::new(self.client_builder().build::<_, Body>(proxy_connector))
             }
 
             // Construct x2p http proxy client.
@@ -239,19 +446,20 @@ impl HttpClientBuilder {
                 http_connector.enforce_http(true);
                 let timeout_connector = timeout_config.to_connector(http_connector);
                 let proxy_connector = build_proxy_connector(proxies, timeout_connector, None);
-                Arc::new(hyper::Client::builder().build::<_, Body>(proxy_connector))
+                Arc::new(self.client_builder().build::<_, Body>(proxy_connector))
             }
             (proxies @ [_, ..], None) if self.supports_vpnless => {
                 let mut http_connector = HttpConnector::new();
                 // When talking to local x2pagent proxy, only http is supported.
                 http_connector.enforce_http(true);
                 let proxy_connector = build_proxy_connector(proxies, http_connector, None);
-                Arc::new(hyper::Client::builder().build::<_, Body>(proxy_connector))
+                Arc::new(self.client_builder().build::<_, Body>(proxy_connector))
             }
 
             // Proxied http client with TLS.
             (proxies @ [_, ..], Some(timeout_config)) => {
-                let https_connector = build_https_connector(self.tls_config.clone(), self.http2);
+                let https_connector =
+                    build_https_connector(self.tls_config.clone(), self.version_preference);
                 let timeout_connector = timeout_config.to_connector(https_connector);
                 // Re-use TLS config from https connection for communication with proxies.
                 let proxy_connector = build_proxy_connector(
@@ -259,49 +467,82 @@ impl HttpClientBuilder {
                     timeout_connector,
                     Some(self.tls_config.clone()),
                 );
-                Arc::new(hyper::Client::builder().build::<_, Body>(proxy_connector))
+                Arc::new(self.client_builder().build::<_, Body>(proxy_connector))
             }
             (proxies @ [_, ..], None) => {
-                let https_connector = build_https_connector(self.tls_config.clone(), self.http2);
+                let https_connector =
+                    build_https_connector(self.tls_config.clone(), self.version_preference);
                 let proxy_connector =
                     build_proxy_connector(proxies, https_connector, Some(self.tls_config.clone()));
-                Arc::new(hyper::Client::builder().build::<_, Body>(proxy_connector))
+                Arc::new(self.client_builder().build::<_, Body>(proxy_connector))
             }
 
             // Client with TLS only.
             ([], Some(timeout_config)) => {
-                let https_connector = build_https_connector(self.tls_config.clone(), self.http2);
+                let https_connector =
+                    build_https_connector(self.tls_config.clone(), self.version_preference);
                 let timeout_connector = timeout_config.to_connector(https_connector);
-                Arc::new(hyper::Client::builder().build::<_, Body>(timeout_connector))
+                Arc::new(self.client_builder().build::<_, Body>(timeout_connector))
             }
             ([], None) => {
-                let https_connector = build_https_connector(self.tls_config.clone(), self.http2);
-                Arc::new(hyper::Client::builder().build::<_, Body>(https_connector))
+                let https_connector =
+                    build_https_connector(self.tls_config.clone(), self.version_preference);
+                Arc::new(self.client_builder().build::<_, Body>(https_connector))
             }
         }
     }
 
     pub fn build(&self) -> HttpClient {
+        let inner = self.build_inner();
+        let inner = match &self.client_auth_cert {
+            Some(cert_path) => {
+                let builder = self.clone();
+                Arc::new(CertReloadingRequestClient::new(
+                    cert_path.clone(),
+                    inner,
+                    move |cert_path| {
+                        let mut builder = builder.clone();
+                        builder.tls_config =
+                            tls::tls_config_with_single_cert(cert_path, cert_path)?;
+                        Ok(builder.build_inner())
+                    },
+                ))
+            }
+            None => inner,
+        };
+        self.build_with_request_client(inner)
+    }
+
+    /// Builds a client sending its requests with `inner` rather than over the network.
+    pub(crate) fn build_with_request_client(&self, inner: Arc<dyn RequestClient>) -> HttpClient {
         HttpClient {
-            inner: self.build_inner(),
+            inner,
             max_redirects: self.max_redirects,
             supports_vpnless: self.supports_vpnless,
-            http2: self.http2,
+            version_preference: self.version_preference,
+            policy: Arc::new(self.policy.clone()),
             stats: HttpNetworkStats::new(),
+            max_error_body_bytes: self.max_error_body_bytes,
+            retry: self.retry,
+            request_timeout: self.request_timeout,
+            default_headers: Arc::new(self.default_headers.clone()),
+            progress: None,
         }
     }
 }
 
-fn build_https_connector(tls_config: ClientConfig, http2: bool) -> HttpsConnector<HttpConnector> {
+fn build_https_connector(
+    tls_config: ClientConfig,
+    version_preference: HttpVersionPreference,
+) -> HttpsConnector<HttpConnector> {
     let builder = HttpsConnectorBuilder::new()
         .with_tls_config(tls_config)
-        .https_or_http()
-        .enable_http1();
+        .https_or_http();
 
-    if http2 {
-        builder.enable_http2().build()
-    } else {
-        builder.build()
+    match version_preference {
+        HttpVersionPreference::Auto => builder.enable_http1().enable_http2().build(),
+        HttpVersionPreference::Http1Only => builder.enable_http1().build(),
+        HttpVersionPreference::Http2Only => builder.enable_http2().build(),
     }
 }
 
@@ -357,6 +598,7 @@ mod tests {
         assert_eq!(None, builder.max_redirects);
         assert!(builder.proxies.is_empty());
         assert!(!builder.supports_vpnless);
+        assert!(builder.policy.is_allow_all());
         Ok(())
     }
 
@@ -370,12 +612,45 @@ mod tests {
     }
 
     #[test]
-    fn test_http2_option() -> anyhow::Result<()> {
+    fn test_http_version_preference_option() -> anyhow::Result<()> {
+        let mut builder = HttpClientBuilder::https_with_system_roots()?;
+        assert_eq!(HttpVersionPreference::Auto, builder.version_preference);
+        builder.with_http_version_preference(HttpVersionPreference::Http1Only);
+
+        assert_eq!(HttpVersionPreference::Http1Only, builder.version_preference);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_http_version_preference() -> anyhow::Result<()> {
+        assert_eq!(HttpVersionPreference::Auto, "auto".parse()?);
+        assert_eq!(HttpVersionPreference::Http1Only, "http1_only".parse()?);
+        assert_eq!(HttpVersionPreference::Http2Only, "http2_only".parse()?);
+        assert!("http3".parse::<HttpVersionPreference>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_header_lines() -> anyhow::Result<()> {
         let mut builder = HttpClientBuilder::https_with_system_roots()?;
-        assert!(builder.http2);
-        builder.with_http2(false);
+        builder
+            .with_default_header_line("Authorization: Bearer token")?
+            .with_default_header_line("x-tag:a")?
+            .with_default_header_line("x-tag: b")?;
+
+        let headers = builder.default_headers();
+        assert_eq!("Bearer token", headers[http::header::AUTHORIZATION]);
+        assert_eq!(
+            vec!["a", "b"],
+            headers
+                .get_all("x-tag")
+                .iter()
+                .map(|v| v.to_str().unwrap())
+                .collect::<Vec<_>>()
+        );
 
-        assert!(!builder.http2);
+        assert!(builder.with_default_header_line("no colon").is_err());
+        assert!(builder.with_default_header_line("bad name: value").is_err());
         Ok(())
     }
 
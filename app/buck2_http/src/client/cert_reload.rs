@@ -0,0 +1,169 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use arc_swap::ArcSwap;
+use bytes::Bytes;
+use dupe::Dupe;
+use futures::future::BoxFuture;
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+
+use crate::client::RequestClient;
+
+struct LoadedClient {
+    /// Modification time of the certificate when the client was built.
+    modified: Option<SystemTime>,
+    client: Arc<dyn RequestClient>,
+}
+
+/// Sends requests with a client authenticating with the certificate at `cert_path`, and rebuilds
+/// that client when the certificate changes. Certificates are rotated periodically, and a client
+/// built once would fail its TLS handshakes when its certificate expires, which long running
+/// daemons would outlive.
+pub(crate) struct CertReloadingRequestClient {
+    cert_path: PathBuf,
+    build: Box<dyn Fn(&Path) -> anyhow::Result<Arc<dyn RequestClient>> + Send + Sync>,
+    current: ArcSwap<LoadedClient>,
+}
+
+impl CertReloadingRequestClient {
+    /// `client` is the client built with the current certificate, and `build` builds a client
+    /// with the certificate at the given path.
+    pub(crate) fn new(
+        cert_path: PathBuf,
+        client: Arc<dyn RequestClient>,
+        build: impl Fn(&Path) -> anyhow::Result<Arc<dyn RequestClient>> + Send + Sync + 'static,
+    ) -> Self {
+        let modified = modified(&cert_path);
+        Self {
+            cert_path,
+            build: Box::new(build),
+            current: ArcSwap::from_pointee(LoadedClient { modified, client }),
+        }
+    }
+
+    fn client(&self) -> Arc<dyn RequestClient> {
+        let current = self.current.load();
+        let modified = modified(&self.cert_path);
+        if modified == current.modified {
+            return current.client.dupe();
+        }
+
+        match (self.build)(&self.cert_path) {
+            Ok(client) => {
+                tracing::debug!(
+                    "http: reloaded client certificate `{}`",
+                    self.cert_path.display()
+                );
+                self.current.store(Arc::new(LoadedClient {
+                    modified,
+                    client: client.dupe(),
+                }));
+                client
+            }
+            Err(e) => {
+                // The certificate may be halfway through being rewritten. Keep using the
+                // previous one, and try again with the next request.
+                tracing::warn!(
+                    "http: failed to reload client certificate `{}`: {:#}",
+                    self.cert_path.display(),
+                    e
+                );
+                current.client.dupe()
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl RequestClient for CertReloadingRequestClient {
+    fn request(
+        &self,
+        request: Request<Bytes>,
+    ) -> BoxFuture<'static, hyper::Result<Response<Body>>> {
+        self.client().request(request)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs::File;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    use bytes::Bytes;
+    use hyper::Request;
+
+    use crate::client::cert_reload::CertReloadingRequestClient;
+    use crate::client::RequestClient;
+    use crate::testing::MockHttpClient;
+
+    const URI: &str = "https://example.com/whoami";
+
+    /// A client responding with the contents of the certificate it was built with.
+    fn build(path: &Path) -> anyhow::Result<Arc<dyn RequestClient>> {
+        let cert = std::fs::read(path)?;
+        anyhow::ensure!(!cert.is_empty(), "Empty certificate");
+        Ok(Arc::new(
+            MockHttpClient::builder()
+                .expect_get(URI, 200, &cert)
+                .build(),
+        ))
+    }
+
+    fn rotate(path: &Path, contents: &str, modified: SystemTime) -> anyhow::Result<()> {
+        std::fs::write(path, contents)?;
+        File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(modified)?;
+        Ok(())
+    }
+
+    async fn whoami(client: &CertReloadingRequestClient) -> anyhow::Result<String> {
+        let request = Request::get(URI).body(Bytes::new())?;
+        let body = client.request(request).await?.into_body();
+        Ok(String::from_utf8(
+            hyper::body::to_bytes(body).await?.to_vec(),
+        )?)
+    }
+
+    #[tokio::test]
+    async fn test_reloads_rotated_certificate() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let cert_path = tempdir.path().join("cert.pem");
+        let t0 = SystemTime::now() - Duration::from_secs(3600);
+        rotate(&cert_path, "first", t0)?;
+
+        let client = CertReloadingRequestClient::new(cert_path.clone(), build(&cert_path)?, build);
+        assert_eq!("first", whoami(&client).await?);
+
+        rotate(&cert_path, "second", t0 + Duration::from_secs(60))?;
+        assert_eq!("second", whoami(&client).await?);
+        assert_eq!("second", whoami(&client).await?);
+
+        // A certificate that can't be loaded doesn't replace the one in use.
+        rotate(&cert_path, "", t0 + Duration::from_secs(120))?;
+        assert_eq!("second", whoami(&client).await?);
+        rotate(&cert_path, "third", t0 + Duration::from_secs(180))?;
+        assert_eq!("third", whoami(&client).await?);
+        Ok(())
+    }
+}
@@ -10,19 +10,31 @@
 #![feature(error_generic_member_access)]
 #![feature(if_let_guard)]
 
+use std::time::Duration;
+
 use hyper::StatusCode;
 
 mod client;
+mod policy;
+mod progress;
 mod proxy;
 mod redirect;
 pub mod retries;
 mod stats;
+pub mod testing;
 pub mod tls;
+#[cfg(unix)]
+mod unix_socket;
+pub mod url;
 mod x2p;
 
 pub use client::to_bytes;
 pub use client::HttpClient;
 pub use client::HttpClientBuilder;
+pub use client::HttpVersionPreference;
+pub use policy::HttpPolicy;
+pub use policy::PolicyRule;
+pub use progress::DownloadProgress;
 
 fn http_error_label(status: StatusCode) -> &'static str {
     if status.is_server_error() {
@@ -44,6 +56,15 @@ fn tag_from_status(status: StatusCode) -> Option<buck2_error::ErrorTag> {
     }
 }
 
+/// Retrying doesn't change what kind of failure it was: tag it like the last attempt's error.
+fn tag_from_retried(source: &HttpError) -> Option<buck2_error::ErrorTag> {
+    match source {
+        HttpError::Status { status, .. } => tag_from_status(*status),
+        HttpError::Retried { source, .. } => tag_from_retried(source),
+        _ => None,
+    }
+}
+
 #[derive(Debug, buck2_error::Error)]
 #[buck2(tag = Http)]
 pub enum HttpError {
@@ -62,6 +83,20 @@ pub enum HttpError {
         #[source]
         source: hyper::Error,
     },
+    #[error("HTTP: Error reading response body from {uri} after {received} bytes")]
+    #[buck2(tier0)]
+    ReadBody {
+        uri: String,
+        received: u64,
+        #[source]
+        source: hyper::Error,
+    },
+    #[error("HTTP: Error writing response body from {uri}")]
+    WriteBody {
+        uri: String,
+        #[source]
+        source: std::io::Error,
+    },
     #[error("HTTP {} Error ({status}) when querying URI: {uri}. Response text: {text}", http_error_label(*.status))]
     #[buck2(tag = tag_from_status(*status))]
     Status {
@@ -73,9 +108,20 @@ pub enum HttpError {
     TooManyRedirects { uri: String, max_redirects: usize },
     #[error("HTTP: Error mutating request")]
     MutateRequest(#[source] anyhow::Error),
-    #[error("HTTP: Timed out while making request to URI: {uri} after {duration} seconds.")]
+    #[error("HTTP: Timed out while making request to URI: {uri} after {duration:?}.")]
     #[buck2(tier0)]
-    Timeout { uri: String, duration: u64 },
+    Timeout { uri: String, duration: Duration },
+    #[error("HTTP: Request to {uri} blocked by policy: {rule}")]
+    #[buck2(input)]
+    BlockedByPolicy { uri: String, rule: PolicyRule },
+    #[error("HTTP: Request to {uri} failed after {attempts} attempts")]
+    #[buck2(tag = tag_from_retried(source))]
+    Retried {
+        uri: String,
+        attempts: usize,
+        #[source]
+        source: Box<HttpError>,
+    },
     #[error("While making request to {uri} via x2p")]
     X2P {
         uri: String,
@@ -84,6 +130,21 @@ pub enum HttpError {
     },
 }
 
+impl HttpError {
+    /// Whether sending the same request again may succeed.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Status { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Self::Timeout { .. } => true,
+            Self::SendRequest { .. } => true,
+            Self::ReadBody { source, .. } => !source.is_connect(),
+            _ => false,
+        }
+    }
+}
+
 impl From<http::Error> for HttpError {
     fn from(err: http::Error) -> Self {
         Self::BuildRequest(err)
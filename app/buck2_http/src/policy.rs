@@ -0,0 +1,233 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Restricts which hosts and schemes the http client is allowed to talk to.
+
+use std::fmt;
+
+use allocative::Allocative;
+use http::Uri;
+
+use crate::HttpError;
+
+/// A host pattern, either an exact host (`example.com`) or a suffix wildcard (`*.example.com`),
+/// which matches any subdomain of `example.com` but not `example.com` itself.
+#[derive(Allocative, Clone, Debug, PartialEq, Eq)]
+enum HostPattern {
+    Exact(String),
+    /// The suffix, including the leading dot.
+    Suffix(String),
+}
+
+impl HostPattern {
+    fn new(pattern: &str) -> Self {
+        let pattern = normalize_host(pattern);
+        match pattern.strip_prefix('*') {
+            Some(suffix) if suffix.starts_with('.') => Self::Suffix(suffix.to_owned()),
+            _ => Self::Exact(pattern),
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            Self::Exact(exact) => host == exact,
+            Self::Suffix(suffix) => host.len() > suffix.len() && host.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(exact) => write!(f, "{}", exact),
+            Self::Suffix(suffix) => write!(f, "*{}", suffix),
+        }
+    }
+}
+
+fn normalize_host(host: &str) -> String {
+    host.trim().trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// The rule of an [`HttpPolicy`] that blocked a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyRule {
+    DeniedHost { host: String, pattern: String },
+    HostNotAllowed { host: String },
+    SchemeNotAllowed { scheme: String },
+}
+
+impl fmt::Display for PolicyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DeniedHost { host, pattern } => write!(
+                f,
+                "host `{}` matches `{}` in `http.denied_hosts`",
+                host, pattern
+            ),
+            Self::HostNotAllowed { host } => {
+                write!(f, "host `{}` is not listed in `http.allowed_hosts`", host)
+            }
+            Self::SchemeNotAllowed { scheme } => write!(
+                f,
+                "scheme `{}` is not listed in `http.allowed_schemes`",
+                scheme
+            ),
+        }
+    }
+}
+
+/// Hosts and schemes requests are allowed to go to, including every hop of a redirect chain.
+///
+/// Each list is ignored when empty, so the default policy allows everything. Denied hosts take
+/// precedence over allowed hosts.
+#[derive(Allocative, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HttpPolicy {
+    allowed_hosts: Vec<HostPattern>,
+    denied_hosts: Vec<HostPattern>,
+    allowed_schemes: Vec<String>,
+}
+
+impl HttpPolicy {
+    pub fn new<S: AsRef<str>>(
+        allowed_hosts: &[S],
+        denied_hosts: &[S],
+        allowed_schemes: &[S],
+    ) -> Self {
+        fn patterns<S: AsRef<str>>(patterns: &[S]) -> Vec<HostPattern> {
+            patterns
+                .iter()
+                .filter(|p| !p.as_ref().trim().is_empty())
+                .map(|p| HostPattern::new(p.as_ref()))
+                .collect()
+        }
+
+        Self {
+            allowed_hosts: patterns(allowed_hosts),
+            denied_hosts: patterns(denied_hosts),
+            allowed_schemes: allowed_schemes
+                .iter()
+                .map(|s| s.as_ref().trim().to_ascii_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn is_allow_all(&self) -> bool {
+        self.allowed_hosts.is_empty()
+            && self.denied_hosts.is_empty()
+            && self.allowed_schemes.is_empty()
+    }
+
+    fn check_rule(&self, uri: &Uri) -> Option<PolicyRule> {
+        if !self.allowed_schemes.is_empty() {
+            let scheme = uri.scheme_str().unwrap_or_default().to_ascii_lowercase();
+            if !self.allowed_schemes.contains(&scheme) {
+                return Some(PolicyRule::SchemeNotAllowed { scheme });
+            }
+        }
+
+        let host = normalize_host(uri.host().unwrap_or_default());
+        if let Some(pattern) = self.denied_hosts.iter().find(|p| p.matches(&host)) {
+            return Some(PolicyRule::DeniedHost {
+                host,
+                pattern: pattern.to_string(),
+            });
+        }
+        if !self.allowed_hosts.is_empty() && !self.allowed_hosts.iter().any(|p| p.matches(&host)) {
+            return Some(PolicyRule::HostNotAllowed { host });
+        }
+
+        None
+    }
+
+    /// Checks that a request to `uri` is allowed.
+    pub(crate) fn check(&self, uri: &Uri) -> Result<(), HttpError> {
+        match self.check_rule(uri) {
+            None => Ok(()),
+            Some(rule) => Err(HttpError::BlockedByPolicy {
+                uri: uri.to_string(),
+                rule,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::Uri;
+
+    use crate::policy::HttpPolicy;
+    use crate::policy::PolicyRule;
+
+    fn check(policy: &HttpPolicy, uri: &str) -> Option<PolicyRule> {
+        policy.check_rule(&uri.parse::<Uri>().unwrap())
+    }
+
+    #[test]
+    fn test_default_allows_all() {
+        let policy = HttpPolicy::default();
+        assert!(policy.is_allow_all());
+        assert_eq!(None, check(&policy, "http://example.com/foo"));
+        assert_eq!(None, check(&policy, "ftp://1.2.3.4:21/foo"));
+        assert!(HttpPolicy::new::<&str>(&[""], &[], &[" "]).is_allow_all());
+    }
+
+    #[test]
+    fn test_allowed_hosts() {
+        let policy = HttpPolicy::new(&["example.com", "*.example.org"], &[], &[]);
+        assert_eq!(None, check(&policy, "https://example.com/foo"));
+        assert_eq!(None, check(&policy, "https://EXAMPLE.com./foo"));
+        assert_eq!(None, check(&policy, "https://a.example.org/foo"));
+        assert_eq!(None, check(&policy, "https://a.b.example.org:8080/foo"));
+        assert_eq!(
+            Some(PolicyRule::HostNotAllowed {
+                host: "example.org".to_owned()
+            }),
+            check(&policy, "https://example.org/foo")
+        );
+        assert_eq!(
+            Some(PolicyRule::HostNotAllowed {
+                host: "a.example.com".to_owned()
+            }),
+            check(&policy, "https://a.example.com/foo")
+        );
+        assert_eq!(
+            Some(PolicyRule::HostNotAllowed {
+                host: "badexample.org".to_owned()
+            }),
+            check(&policy, "https://badexample.org/foo")
+        );
+    }
+
+    #[test]
+    fn test_denied_hosts_take_precedence() {
+        let policy = HttpPolicy::new(&["*.example.com"], &["*.evil.example.com"], &[]);
+        assert_eq!(None, check(&policy, "https://good.example.com/foo"));
+        assert_eq!(
+            Some(PolicyRule::DeniedHost {
+                host: "a.evil.example.com".to_owned(),
+                pattern: "*.evil.example.com".to_owned(),
+            }),
+            check(&policy, "https://a.evil.example.com/foo")
+        );
+    }
+
+    #[test]
+    fn test_allowed_schemes() {
+        let policy = HttpPolicy::new(&[], &[], &["https"]);
+        assert_eq!(None, check(&policy, "https://example.com/foo"));
+        assert_eq!(
+            Some(PolicyRule::SchemeNotAllowed {
+                scheme: "http".to_owned()
+            }),
+            check(&policy, "http://example.com/foo")
+        );
+    }
+}
@@ -0,0 +1,160 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use bytes::Bytes;
+use futures::task::Poll;
+use futures::Stream;
+use pin_project::pin_project;
+
+/// Bytes received between two progress reports, at most.
+const PROGRESS_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Time between two progress reports, at most, as long as bytes are being received.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Told about the progress of the response bodies read through an `HttpClient`.
+pub trait DownloadProgress: Send + Sync + 'static {
+    /// `total_bytes` is the `Content-Length` of the response, if it had one.
+    fn progress(&self, uri: &str, downloaded_bytes: u64, total_bytes: Option<u64>);
+}
+
+/// Reports the progress of reading `inner` every `PROGRESS_BYTES` or `PROGRESS_INTERVAL`,
+/// whichever comes first, and once more when it's done.
+#[pin_project]
+pub(crate) struct ProgressStream<S> {
+    #[pin]
+    inner: S,
+    progress: Arc<dyn DownloadProgress>,
+    uri: String,
+    total_bytes: Option<u64>,
+    downloaded_bytes: u64,
+    reported_bytes: u64,
+    reported_at: Instant,
+}
+
+impl<S> ProgressStream<S> {
+    pub(crate) fn new(
+        stream: S,
+        progress: Arc<dyn DownloadProgress>,
+        uri: String,
+        total_bytes: Option<u64>,
+    ) -> Self {
+        Self {
+            inner: stream,
+            progress,
+            uri,
+            total_bytes,
+            downloaded_bytes: 0,
+            reported_bytes: 0,
+            reported_at: Instant::now(),
+        }
+    }
+}
+
+impl<S, E> Stream for ProgressStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        let res = this.inner.as_mut().poll_next(cx);
+        let done = match &res {
+            Poll::Ready(Some(Ok(bytes))) => {
+                *this.downloaded_bytes += bytes.len() as u64;
+                false
+            }
+            Poll::Ready(None) => true,
+            _ => return res,
+        };
+
+        if *this.downloaded_bytes > *this.reported_bytes
+            && (done
+                || *this.downloaded_bytes - *this.reported_bytes >= PROGRESS_BYTES
+                || this.reported_at.elapsed() >= PROGRESS_INTERVAL)
+        {
+            this.progress
+                .progress(this.uri, *this.downloaded_bytes, *this.total_bytes);
+            *this.reported_bytes = *this.downloaded_bytes;
+            *this.reported_at = Instant::now();
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use bytes::Bytes;
+    use futures::StreamExt;
+
+    use crate::progress::DownloadProgress;
+    use crate::progress::ProgressStream;
+    use crate::progress::PROGRESS_BYTES;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(u64, Option<u64>)>>);
+
+    impl DownloadProgress for Recorder {
+        fn progress(&self, _uri: &str, downloaded_bytes: u64, total_bytes: Option<u64>) {
+            self.0.lock().unwrap().push((downloaded_bytes, total_bytes));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_every_few_bytes_and_at_the_end() {
+        let chunk = Bytes::from(vec![0; PROGRESS_BYTES as usize / 2]);
+        let chunks = (0..5).map(|_| Ok::<_, ()>(chunk.clone()));
+        let total = 5 * chunk.len() as u64;
+
+        let recorder = Arc::new(Recorder::default());
+        let stream = ProgressStream::new(
+            futures::stream::iter(chunks),
+            recorder.clone(),
+            "http://example.com".to_owned(),
+            Some(total),
+        );
+        assert_eq!(5, stream.count().await);
+
+        let reports = recorder.0.lock().unwrap().clone();
+        assert_eq!(
+            vec![
+                (2 * chunk.len() as u64, Some(total)),
+                (4 * chunk.len() as u64, Some(total)),
+                (total, Some(total)),
+            ],
+            reports
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_body_is_not_reported() {
+        let recorder = Arc::new(Recorder::default());
+        let stream = ProgressStream::new(
+            futures::stream::iter(Vec::<Result<Bytes, ()>>::new()),
+            recorder.clone(),
+            "http://example.com".to_owned(),
+            None,
+        );
+        assert_eq!(0, stream.count().await);
+        assert!(recorder.0.lock().unwrap().is_empty());
+    }
+}
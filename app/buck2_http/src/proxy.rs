@@ -29,48 +29,47 @@ fn env_to_string(env: &'static str) -> anyhow::Result<Option<String>> {
         .map_err(|original| anyhow::anyhow!("Invalid utf8 string: '{:?}'", original))
 }
 
-fn noproxy_from_env(scheme: Scheme) -> anyhow::Result<Option<NoProxy>> {
-    Ok(env_to_string("NO_PROXY")?.map(|no_proxy| NoProxy::new(scheme, no_proxy)))
-}
-
 /// Returns a hyper_proxy::Proxy struct that proxies connections to the uri at
 /// $HTTPS_PROXY (or $https_proxy if the former is unset). Respects $NO_PROXY.
 pub(super) fn https_proxy_from_env() -> anyhow::Result<Option<Proxy>> {
-    if let Some(https_proxy) = env_to_string("HTTPS_PROXY")? {
-        let uri: DefaultSchemeUri = https_proxy
-            .parse()
-            .with_context(|| format!("Invalid HTTPS_PROXY uri: {}", https_proxy))?;
-        if let Some(no_proxy) = noproxy_from_env(Scheme::HTTPS)? {
-            Ok(Some(Proxy::new(
-                no_proxy.into_proxy_intercept(),
-                uri.into(),
-            )))
-        } else {
-            Ok(Some(Proxy::new(Intercept::Https, uri.into())))
-        }
-    } else {
-        Ok(None)
-    }
+    proxy_for_scheme(
+        Scheme::HTTPS,
+        "HTTPS_PROXY",
+        env_to_string("HTTPS_PROXY")?,
+        env_to_string("NO_PROXY")?,
+    )
 }
 
 /// Returns a hyper_proxy::Proxy struct that proxies connections to the uri at
 /// $HTTP_PROXY (or $http_proxy if the former is unset). Respects $NO_PROXY.
 pub(super) fn http_proxy_from_env() -> anyhow::Result<Option<Proxy>> {
-    if let Some(http_proxy) = env_to_string("HTTP_PROXY")? {
-        let uri: DefaultSchemeUri = http_proxy
-            .parse()
-            .with_context(|| format!("Invalid HTTP_PROXY uri: {}", http_proxy))?;
-        if let Some(no_proxy) = noproxy_from_env(Scheme::HTTP)? {
-            Ok(Some(Proxy::new(
-                no_proxy.into_proxy_intercept(),
-                uri.into(),
-            )))
-        } else {
-            Ok(Some(Proxy::new(Intercept::Http, uri.into())))
-        }
-    } else {
-        Ok(None)
-    }
+    proxy_for_scheme(
+        Scheme::HTTP,
+        "HTTP_PROXY",
+        env_to_string("HTTP_PROXY")?,
+        env_to_string("NO_PROXY")?,
+    )
+}
+
+/// A proxy at `proxy` for connections with `scheme`, except to the hosts in `no_proxy`.
+fn proxy_for_scheme(
+    scheme: Scheme,
+    var: &str,
+    proxy: Option<String>,
+    no_proxy: Option<String>,
+) -> anyhow::Result<Option<Proxy>> {
+    let Some(proxy) = proxy else {
+        return Ok(None);
+    };
+    let uri: DefaultSchemeUri = proxy
+        .parse()
+        .with_context(|| format!("Invalid {} uri: {}", var, proxy))?;
+    let intercept = match no_proxy {
+        Some(no_proxy) => NoProxy::new(scheme, no_proxy).into_proxy_intercept(),
+        None if scheme == Scheme::HTTPS => Intercept::Https,
+        None => Intercept::Http,
+    };
+    Ok(Some(Proxy::new(intercept, uri.into())))
 }
 
 /// A wrapped Uri that handles inserting a default scheme (http) if one is not present.
@@ -105,12 +104,13 @@ struct Domain(String);
 
 impl Domain {
     /// Returns whether this domain "matches" candidate according to Curl's rules
-    /// for NO_PROXY.
+    /// for NO_PROXY. Domains are case insensitive, and `self` is already lowercase.
     ///
     /// See https://github.com/curl/curl/issues/1208 for a bit of discussion about
     /// some of the particulars of subdomain matching.
     fn is_match<S: AsRef<str>>(&self, candidate: S) -> bool {
-        let candidate = candidate.as_ref();
+        let candidate = candidate.as_ref().to_ascii_lowercase();
+        let candidate = candidate.as_str();
         // * unambiguously matches all domains.
         self.0 == "*"
             // Exact match
@@ -126,6 +126,17 @@ impl Domain {
 /// Wrapper for the parsed version of Curl's "no proxy" format from the standard
 /// NO_PROXY / no_proxy environment variables.
 ///
+/// The spec is a comma separated list of entries, each of which is one of:
+///
+/// - an IP address, e.g. `10.1.2.3`, matching only that address;
+/// - a CIDR range, e.g. `10.0.0.0/8`, matching all the addresses in it;
+/// - a domain, e.g. `example.com` or `.example.com`, matching that host and all its
+///   subdomains, but not `www.otherexample.com`;
+/// - `*`, matching all hosts.
+///
+/// Addresses and ranges only match hosts spelled as IP addresses, and domains only match
+/// hosts spelled as names: there are no DNS lookups. Ports are ignored.
+///
 /// Uses hyper-proxy's Intercept::Custom to drive matching logic for whether Uris
 /// should be proxied or not.
 ///
@@ -149,13 +160,13 @@ impl NoProxy {
         let mut networks = Vec::new();
         let mut domains = Vec::new();
         let s = s.as_ref();
-        for entity in s.split(',').map(str::trim) {
+        for entity in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
             if let Ok(network) = entity.parse::<IpNetwork>() {
                 networks.push(network);
             } else if let Ok(address) = entity.parse::<IpAddr>() {
                 addresses.push(address);
             } else {
-                domains.push(Domain(entity.to_owned()));
+                domains.push(Domain(entity.to_ascii_lowercase()));
             }
         }
 
@@ -317,6 +328,54 @@ mod tests {
         assert!(!intercept.matches(&uri("http://www.facebook.com/foo/bar")));
     }
 
+    #[test]
+    fn test_noproxy_ignores_empty_entries_and_case() {
+        let noproxy = NoProxy::new(Scheme::HTTP, "Mirror.Corp.Example,, ");
+        assert!(noproxy.should_bypass_proxy_for_host("mirror.corp.example"));
+        assert!(noproxy.should_bypass_proxy_for_host("MIRROR.corp.example"));
+        assert!(!noproxy.should_bypass_proxy_for_host("example.com"));
+    }
+
+    #[test]
+    fn test_proxy_for_scheme_respects_no_proxy() -> anyhow::Result<()> {
+        let proxy = proxy_for_scheme(
+            Scheme::HTTPS,
+            "HTTPS_PROXY",
+            Some("proxy.corp:3128".to_owned()),
+            Some("mirror.corp.example, .internal.example, 10.0.0.0/8".to_owned()),
+        )?
+        .unwrap();
+        assert_eq!(&uri("http://proxy.corp:3128/"), proxy.uri());
+        let intercept = proxy.intercept();
+        // Exact host match.
+        assert!(!intercept.matches(&uri("https://mirror.corp.example/artifact")));
+        // Suffix match.
+        assert!(!intercept.matches(&uri("https://cache.internal.example/artifact")));
+        assert!(intercept.matches(&uri("https://notinternal.example/artifact")));
+        // CIDR exclusion.
+        assert!(!intercept.matches(&uri("https://10.20.30.40/artifact")));
+        assert!(intercept.matches(&uri("https://11.0.0.1/artifact")));
+        // Other hosts are proxied.
+        assert!(intercept.matches(&uri("https://example.com/artifact")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxy_for_scheme_without_no_proxy() -> anyhow::Result<()> {
+        let proxy = proxy_for_scheme(
+            Scheme::HTTP,
+            "HTTP_PROXY",
+            Some("http://proxy.corp:3128".to_owned()),
+            None,
+        )?
+        .unwrap();
+        assert!(proxy.intercept().matches(&uri("http://example.com/")));
+        assert!(!proxy.intercept().matches(&uri("https://example.com/")));
+
+        assert!(proxy_for_scheme(Scheme::HTTP, "HTTP_PROXY", None, None)?.is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_noproxy_intercept_does_not_proxy_for_scheme_mismatch() {
         let noproxy = NoProxy::new(Scheme::HTTP, ".facebook.com");
@@ -18,6 +18,7 @@ use hyper::Request;
 use hyper::Response;
 use hyper::StatusCode;
 
+use crate::policy::HttpPolicy;
 use crate::HttpError;
 
 trait UriWithRedirect {
@@ -69,6 +70,10 @@ impl PendingRequest {
         }
     }
 
+    pub(super) fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
     pub(super) fn to_request(&self) -> anyhow::Result<Request<Bytes>> {
         let mut builder = Request::builder()
             .method(self.method.clone())
@@ -87,21 +92,24 @@ impl PendingRequest {
 /// as well as the [`follow-redirects`](https://github.com/srijs/rust-follow-redirects) crate.
 /// Unfortunately, the latter is abandoned; until and unless it's maintained by someone
 /// (preferably the hyper folks), let's roll our own.
-pub(super) struct RedirectEngine<B> {
+pub(super) struct RedirectEngine<'a, B> {
     processed_redirects: usize,
+    policy: &'a HttpPolicy,
     max_redirects: usize,
     pending_request: PendingRequest,
     response: Response<B>,
 }
 
-impl<B> RedirectEngine<B> {
+impl<'a, B> RedirectEngine<'a, B> {
     pub(super) fn new(
         max_redirects: usize,
+        policy: &'a HttpPolicy,
         pending_request: PendingRequest,
         response: Response<B>,
     ) -> Self {
         Self {
             processed_redirects: 0,
+            policy,
             max_redirects,
             pending_request,
             response,
@@ -139,6 +147,8 @@ impl<B> RedirectEngine<B> {
                 .update_and_create_request()
                 .map_err(HttpError::MutateRequest)?
             {
+                // Every hop must be allowed, not just the initial request.
+                self.policy.check(redirect_request.uri())?;
                 self.response = sender_func(redirect_request).await?;
                 self.processed_redirects += 1;
             } else {
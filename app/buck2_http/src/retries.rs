@@ -9,8 +9,10 @@
 
 use std::time::Duration;
 
+use allocative::Allocative;
+use dupe::Dupe;
 use futures::future::Future;
-use http::StatusCode;
+use http::Method;
 
 #[derive(Debug, buck2_error::Error)]
 pub enum HttpError {
@@ -35,18 +37,37 @@ impl From<crate::HttpError> for HttpError {
 impl HttpError {
     fn is_retryable(&self) -> bool {
         match self {
-            Self::Client(client_error) => match client_error {
-                crate::HttpError::Status { status, .. } => {
-                    status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
-                }
-                crate::HttpError::Timeout { .. } => true,
-                crate::HttpError::SendRequest { .. } => true,
-                _ => false,
-            },
+            Self::Client(client_error) => client_error.is_transient(),
             Self::Transfer { source, .. } => !source.is_connect(),
         }
     }
 }
+
+/// How `HttpClient` retries requests that failed with a transient error.
+#[derive(Copy, Clone, Dupe, Debug, Allocative)]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_retries: usize,
+    pub(crate) initial_delay: Duration,
+    /// Whether to also retry requests that are not idempotent, like POST and PUT.
+    pub(crate) retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    pub(crate) fn applies_to(&self, method: &Method) -> bool {
+        self.max_retries > 0
+            && (self.retry_non_idempotent || matches!(*method, Method::GET | Method::HEAD))
+    }
+
+    /// How long to wait before sending the request again, after `attempts` failed attempts. The
+    /// delay doubles on every attempt, and is randomized so that clients that failed together
+    /// don't retry together.
+    pub(crate) fn delay(&self, attempts: usize) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(16) as u32;
+        let delay = self.initial_delay.saturating_mul(1 << exponent);
+        delay / 2 + delay.mul_f64(rand::random::<f64>() / 2.0)
+    }
+}
+
 pub trait AsHttpError {
     fn as_http_error(&self) -> Option<&HttpError>;
 }
@@ -90,6 +111,8 @@ where
 
 #[cfg(test)]
 mod tests {
+    use http::StatusCode;
+
     use super::*;
 
     struct Mock {
@@ -0,0 +1,301 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! An in-memory [`HttpClient`], to test code making requests without running a server.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use dupe::Dupe;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::HeaderMap;
+use http::Method;
+use http::StatusCode;
+use http::Uri;
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use rustls::ClientConfig;
+use rustls::RootCertStore;
+
+use crate::client::RequestClient;
+use crate::HttpClient;
+use crate::HttpClientBuilder;
+
+/// A canned response.
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: impl Into<Bytes>) -> Self {
+        Self {
+            status: StatusCode::from_u16(status).expect("Invalid mock status"),
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+/// A failure injected instead of the canned response.
+#[derive(Clone, Debug)]
+pub enum MockFailure {
+    /// Respond with this status, and no body.
+    Status(u16),
+    /// Respond with success, but fail when the body is read.
+    BrokenBody,
+}
+
+/// A request received by a [`MockHttpClient`].
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub uri: String,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+#[derive(Default)]
+pub struct MockHttpClientBuilder {
+    responses: HashMap<(Method, String), MockResponse>,
+    failures: HashMap<usize, MockFailure>,
+}
+
+impl MockHttpClientBuilder {
+    /// Respond to `method` requests to `uri` with `response`. Requests that don't match any
+    /// expectation get a 404.
+    pub fn expect(mut self, method: Method, uri: &str, response: MockResponse) -> Self {
+        self.responses
+            .insert((method, normalize_uri(uri)), response);
+        self
+    }
+
+    pub fn expect_get(self, uri: &str, status: u16, body: &[u8]) -> Self {
+        self.expect(
+            Method::GET,
+            uri,
+            MockResponse::new(status, Bytes::copy_from_slice(body)),
+        )
+    }
+
+    /// Fail the `n`th request received, counting from 0, whatever it is.
+    pub fn fail_call(mut self, n: usize, failure: MockFailure) -> Self {
+        self.failures.insert(n, failure);
+        self
+    }
+
+    pub fn build(self) -> MockHttpClient {
+        MockHttpClient(Arc::new(MockState {
+            responses: self.responses,
+            failures: self.failures,
+            requests: Mutex::new(Vec::new()),
+        }))
+    }
+}
+
+struct MockState {
+    responses: HashMap<(Method, String), MockResponse>,
+    failures: HashMap<usize, MockFailure>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+/// Responds to the requests of the [`HttpClient`]s it creates with canned responses, and records
+/// them.
+///
+/// ```ignore
+/// let mock = MockHttpClient::builder()
+///     .expect_get("https://example.com/x", 200, b"data")
+///     .build();
+/// download(&mock.client(), "https://example.com/x").await?;
+/// assert_eq!(1, mock.requests().len());
+/// ```
+#[derive(Clone, Dupe)]
+pub struct MockHttpClient(Arc<MockState>);
+
+impl MockHttpClient {
+    pub fn builder() -> MockHttpClientBuilder {
+        MockHttpClientBuilder::default()
+    }
+
+    /// A client with default settings sending its requests to this mock.
+    pub fn client(&self) -> HttpClient {
+        // No request goes over the network, so TLS is not used.
+        let tls_config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(RootCertStore::empty())
+            .with_no_client_auth();
+        self.client_from(&HttpClientBuilder::new(tls_config))
+    }
+
+    /// A client with the settings of `builder`, e.g. redirects or retries, sending its requests
+    /// to this mock.
+    pub fn client_from(&self, builder: &HttpClientBuilder) -> HttpClient {
+        builder.build_with_request_client(Arc::new(self.dupe()))
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.0.requests.lock().unwrap().clone()
+    }
+
+    fn respond(&self, request: Request<Bytes>) -> Response<Body> {
+        let (parts, body) = request.into_parts();
+        let uri = parts.uri.to_string();
+        let call = {
+            let mut requests = self.0.requests.lock().unwrap();
+            requests.push(RecordedRequest {
+                method: parts.method.clone(),
+                uri: uri.clone(),
+                headers: parts.headers,
+                body,
+            });
+            requests.len() - 1
+        };
+
+        let (status, headers, body) = match self.0.failures.get(&call) {
+            Some(MockFailure::Status(status)) => (
+                StatusCode::from_u16(*status).expect("Invalid mock status"),
+                Vec::new(),
+                Body::empty(),
+            ),
+            Some(MockFailure::BrokenBody) => {
+                let (sender, body) = Body::channel();
+                sender.abort();
+                (StatusCode::OK, Vec::new(), body)
+            }
+            None => match self.0.responses.get(&(parts.method.clone(), uri.clone())) {
+                Some(response) => (
+                    response.status,
+                    response.headers.clone(),
+                    Body::from(response.body.clone()),
+                ),
+                None => (
+                    StatusCode::NOT_FOUND,
+                    Vec::new(),
+                    Body::from(format!("No mock response for {} {}", parts.method, uri)),
+                ),
+            },
+        };
+
+        let mut response = Response::builder().status(status);
+        for (name, value) in headers {
+            response = response.header(name, value);
+        }
+        response.body(body).expect("Invalid mock response")
+    }
+}
+
+impl RequestClient for MockHttpClient {
+    fn request(
+        &self,
+        request: Request<Bytes>,
+    ) -> BoxFuture<'static, hyper::Result<Response<Body>>> {
+        futures::future::ready(Ok(self.respond(request))).boxed()
+    }
+}
+
+fn normalize_uri(uri: &str) -> String {
+    uri.parse::<Uri>().expect("Invalid mock URI").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use assert_matches::assert_matches;
+    use bytes::Bytes;
+    use http::Method;
+
+    use crate::testing::MockFailure;
+    use crate::testing::MockHttpClient;
+    use crate::testing::MockResponse;
+    use crate::HttpClientBuilder;
+    use crate::HttpError;
+
+    #[tokio::test]
+    async fn test_canned_responses() -> anyhow::Result<()> {
+        let mock = MockHttpClient::builder()
+            .expect_get("https://example.com/x", 200, b"data")
+            .expect(
+                Method::POST,
+                "https://example.com/upload",
+                MockResponse::new(201, "").with_header("location", "/x"),
+            )
+            .build();
+        let client = mock.client();
+
+        let mut out = Vec::new();
+        client
+            .get_to_writer("https://example.com/x", &mut out)
+            .await?;
+        assert_eq!(b"data", out.as_slice());
+
+        let resp = client
+            .post(
+                "https://example.com/upload",
+                Bytes::from_static(b"payload"),
+                vec![("key".to_owned(), "value".to_owned())],
+            )
+            .await?;
+        assert_eq!(201, resp.status().as_u16());
+        assert_eq!("/x", resp.headers()["location"]);
+
+        assert_matches!(
+            client.get("https://example.com/y").await.err(),
+            Some(HttpError::Status { status, .. }) if status.as_u16() == 404
+        );
+
+        let requests = mock.requests();
+        assert_eq!(3, requests.len());
+        assert_eq!(Method::POST, requests[1].method);
+        assert_eq!("https://example.com/upload", requests[1].uri);
+        assert_eq!("value", requests[1].headers["key"]);
+        assert_eq!(Bytes::from_static(b"payload"), requests[1].body);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_injected_failures() -> anyhow::Result<()> {
+        let mock = MockHttpClient::builder()
+            .expect_get("https://example.com/x", 200, b"data")
+            .fail_call(0, MockFailure::Status(503))
+            .fail_call(2, MockFailure::BrokenBody)
+            .build();
+        let client = mock.client_from(
+            HttpClientBuilder::https_with_system_roots()?.with_retries(1, Duration::from_millis(1)),
+        );
+
+        // The first call fails, and is retried.
+        let mut out = Vec::new();
+        client
+            .get_to_writer("https://example.com/x", &mut out)
+            .await?;
+        assert_eq!(b"data", out.as_slice());
+
+        assert_matches!(
+            client
+                .get_to_writer("https://example.com/x", &mut Vec::new())
+                .await,
+            Err(HttpError::ReadBody { .. })
+        );
+        assert_eq!(3, mock.requests().len());
+        Ok(())
+    }
+}
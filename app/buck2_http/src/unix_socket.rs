@@ -0,0 +1,187 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Sending requests to a server listening on a unix domain socket, e.g. a local artifact cache.
+
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
+use dupe::Dupe;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::Uri;
+use hyper::client::connect::Connected;
+use hyper::client::connect::Connection;
+use hyper::service::Service;
+use pin_project::pin_project;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+use tokio::net::UnixStream;
+
+/// When set, all requests are sent to the server listening on this socket, whatever the host in
+/// their URL.
+const UNIX_SOCKET_ENV: &str = "BUCK2_HTTP_UNIX_SOCKET";
+
+pub(crate) fn unix_socket_from_env() -> Option<PathBuf> {
+    std::env::var_os(UNIX_SOCKET_ENV)
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Connects to the socket at `path` for every URI.
+#[derive(Clone, Dupe)]
+pub(crate) struct UnixSocketConnector {
+    path: Arc<PathBuf>,
+}
+
+impl UnixSocketConnector {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path: Arc::new(path),
+        }
+    }
+}
+
+impl Service<Uri> for UnixSocketConnector {
+    type Response = UnixSocketConnection;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<UnixSocketConnection>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.path.dupe();
+        async move {
+            let stream = UnixStream::connect(&*path).await.map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "Error connecting to unix socket `{}`: {}",
+                        path.display(),
+                        e
+                    ),
+                )
+            })?;
+            Ok(UnixSocketConnection(stream))
+        }
+        .boxed()
+    }
+}
+
+#[pin_project]
+pub(crate) struct UnixSocketConnection(#[pin] UnixStream);
+
+impl Connection for UnixSocketConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for UnixSocketConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().0.poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixSocketConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().0.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().0.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().0.poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use anyhow::Context;
+    use assert_matches::assert_matches;
+    use hyper::service::make_service_fn;
+    use hyper::service::service_fn;
+    use hyper::Body;
+    use hyper::Request;
+    use hyper::Response;
+    use hyper::Server;
+    use hyper_unix_connector::UnixConnector;
+
+    use crate::HttpClientBuilder;
+    use crate::HttpError;
+
+    #[tokio::test]
+    async fn test_get_over_unix_socket() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let socket = tempdir.path().join("server.sock");
+        let listener: UnixConnector = tokio::net::UnixListener::bind(&socket)
+            .context("binding to unix socket")?
+            .into();
+        let server = make_service_fn(|_conn| async {
+            Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+                match req.uri().path() {
+                    "/foo" => Response::builder()
+                        .status(302)
+                        .header(http::header::LOCATION, "/bar")
+                        .body(Body::empty()),
+                    "/bar" => Response::builder().body(Body::from(format!(
+                        "hello {}",
+                        req.headers()[http::header::HOST].to_str().unwrap()
+                    ))),
+                    _ => Response::builder().status(404).body(Body::empty()),
+                }
+            }))
+        });
+        let handle = tokio::spawn(Server::builder(listener).serve(server));
+
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_unix_socket(&socket)
+            .with_max_redirects(1)
+            .build();
+        let mut body = Vec::new();
+        client
+            .get_to_writer("http://example.com/foo", &mut body)
+            .await?;
+        assert_eq!(b"hello example.com", body.as_slice());
+
+        assert_matches!(
+            client.get("http://example.com/baz").await,
+            Err(HttpError::Status { status, .. }) if status.as_u16() == 404
+        );
+
+        handle.abort();
+        let client = HttpClientBuilder::https_with_system_roots()?
+            .with_unix_socket(tempdir.path().join("missing.sock"))
+            .build();
+        assert_matches!(
+            client.get("http://example.com/foo").await,
+            Err(HttpError::SendRequest { .. })
+        );
+        Ok(())
+    }
+}
@@ -12,7 +12,6 @@ use buck2_node::attrs::attr_type::AttrType;
 use buck2_node::attrs::coerced_attr::CoercedAttr;
 use buck2_node::attrs::coercion_context::AttrCoercionContext;
 use buck2_node::attrs::configurable::AttrIsConfigurable;
-use buck2_node::visibility::VisibilityPattern;
 use buck2_node::visibility::VisibilityWithinViewBuilder;
 use starlark::values::Value;
 
@@ -75,12 +74,7 @@ pub(crate) fn parse_visibility_with_view(
             return Err(VisibilityAttrTypeCoerceError::WrongType(attr.to_repr()).into());
         };
 
-        if item == VisibilityPattern::PUBLIC {
-            // TODO(cjhopman): We should probably enforce that this is the only entry.
-            builder.add_public();
-        } else {
-            builder.add(VisibilityPattern(ctx.coerce_target_pattern(item)?));
-        }
+        builder.parse_and_add(item, |p| ctx.coerce_target_pattern(p))?;
     }
     Ok(builder)
 }
@@ -162,6 +162,10 @@ impl BuildAttrCoercionContext {
         )
     }
 
+    pub(crate) fn cell_resolver(&self) -> &CellResolver {
+        &self.cell_resolver
+    }
+
     pub fn parse_pattern<P: PatternType>(&self, value: &str) -> anyhow::Result<ParsedPattern<P>> {
         ParsedPattern::parsed_opt_absolute(
             value,
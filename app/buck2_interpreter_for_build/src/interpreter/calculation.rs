@@ -16,6 +16,7 @@ use std::time::Instant;
 use allocative::Allocative;
 use async_trait::async_trait;
 use buck2_common::package_listing::dice::DicePackageListingResolver;
+use buck2_common::target_progress::HasTargetProgress;
 use buck2_core::build_file_path::BuildFilePath;
 use buck2_core::bzl::ImportPath;
 use buck2_core::cells::build_file_cell::BuildFileCell;
@@ -123,8 +124,27 @@ impl TargetGraphCalculationImpl for TargetGraphCalculationInstance {
             }
         }
 
+        let target_progress = ctx.per_transaction_data().get_target_progress().cloned();
+        if let Some(target_progress) = &target_progress {
+            target_progress.package_started(&package);
+        }
+
         ctx.compute(&InterpreterResultsKey(package.dupe()))
-            .map(|v| v?.map_err(anyhow::Error::from))
+            .map(move |v| {
+                let result = match v {
+                    Ok(v) => v.map_err(anyhow::Error::from),
+                    Err(e) => Err(e.into()),
+                };
+                if let Some(target_progress) = &target_progress {
+                    match &result {
+                        Ok(result) => {
+                            target_progress.package_finished(&package, result.targets().len())
+                        }
+                        Err(_) => target_progress.package_failed(&package),
+                    }
+                }
+                result
+            })
             .boxed()
     }
 }
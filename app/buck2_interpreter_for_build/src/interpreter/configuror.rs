@@ -32,6 +32,7 @@ use crate::interpreter::functions::host_info::HostInfo;
 use crate::interpreter::globals::base_globals;
 use crate::interpreter::module_internals::ModuleInternals;
 use crate::interpreter::module_internals::PackageImplicits;
+use crate::nodes::validate_visibility::ValidateVisibility;
 
 #[derive(Clone, Dupe, Allocative)]
 pub struct AdditionalGlobalsFn(
@@ -69,6 +70,7 @@ pub struct BuildInterpreterConfiguror {
     host_info: HostInfo,
     record_target_call_stack: bool,
     skip_targets_with_duplicate_names: bool,
+    validate_visibility: ValidateVisibility,
     global_target_interner: Arc<ConcurrentTargetLabelInterner>,
     /// For test.
     additional_globals: Option<AdditionalGlobalsFn>,
@@ -82,6 +84,7 @@ impl BuildInterpreterConfiguror {
         host_xcode_version: Option<XcodeVersionInfo>,
         record_target_call_stack: bool,
         skip_targets_with_duplicate_names: bool,
+        validate_visibility: ValidateVisibility,
         additional_globals: Option<AdditionalGlobalsFn>,
         global_target_interner: Arc<ConcurrentTargetLabelInterner>,
     ) -> anyhow::Result<Arc<Self>> {
@@ -90,6 +93,7 @@ impl BuildInterpreterConfiguror {
             host_info: HostInfo::new(host_platform, host_architecture, host_xcode_version),
             record_target_call_stack,
             skip_targets_with_duplicate_names,
+            validate_visibility,
             additional_globals,
             global_target_interner,
         }))
@@ -154,6 +158,7 @@ impl BuildInterpreterConfiguror {
             package_implicits,
             record_target_call_stack,
             skip_targets_with_duplicate_names,
+            self.validate_visibility,
             package_listing,
             super_package,
         ))
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::cell::Cell;
 use std::cell::RefCell;
 use std::cell::RefMut;
 use std::fmt;
@@ -34,6 +35,8 @@ use starlark::values::OwnedFrozenValue;
 
 use crate::attrs::coerce::ctx::BuildAttrCoercionContext;
 use crate::interpreter::globspec::GlobSpec;
+use crate::nodes::validate_visibility::validate_visibility;
+use crate::nodes::validate_visibility::ValidateVisibility;
 
 impl From<ModuleInternals> for EvaluationResult {
     // TODO(cjhopman): Let's make this an `into_evaluation_result()` on ModuleInternals instead.
@@ -87,6 +90,9 @@ pub struct ModuleInternals {
     package_implicits: Option<PackageImplicits>,
     record_target_call_stacks: bool,
     skip_targets_with_duplicate_names: bool,
+    validate_visibility: ValidateVisibility,
+    /// Number of visibility validation findings reported for this package.
+    visibility_findings: Cell<usize>,
     /// The files owned by this directory. Is `None` for .bzl files.
     package_listing: PackageListing,
     pub(crate) super_package: SuperPackage,
@@ -127,6 +133,7 @@ impl ModuleInternals {
         package_implicits: Option<PackageImplicits>,
         record_target_call_stacks: bool,
         skip_targets_with_duplicate_names: bool,
+        validate_visibility: ValidateVisibility,
         package_listing: PackageListing,
         super_package: SuperPackage,
     ) -> Self {
@@ -138,6 +145,8 @@ impl ModuleInternals {
             package_implicits,
             record_target_call_stacks,
             skip_targets_with_duplicate_names,
+            validate_visibility,
+            visibility_findings: Cell::new(0),
             package_listing,
             super_package,
         }
@@ -148,6 +157,12 @@ impl ModuleInternals {
     }
 
     pub fn record(&self, target_node: TargetNode) -> anyhow::Result<()> {
+        validate_visibility(
+            &target_node,
+            self.attr_coercion_context.cell_resolver(),
+            self.validate_visibility,
+            &self.visibility_findings,
+        )?;
         match self.recording_targets().recorder.record(target_node) {
             Ok(()) => Ok(()),
             Err(e @ TargetsMapRecordError::RegisteredTargetTwice { .. }) => {
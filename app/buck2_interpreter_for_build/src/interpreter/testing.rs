@@ -47,6 +47,7 @@ use crate::interpreter::configuror::BuildInterpreterConfiguror;
 use crate::interpreter::global_interpreter_state::GlobalInterpreterState;
 use crate::interpreter::interpreter_for_cell::InterpreterForCell;
 use crate::interpreter::interpreter_for_cell::ParseData;
+use crate::nodes::validate_visibility::ValidateVisibility;
 use crate::super_package::package_value::SuperPackageValuesImpl;
 
 /// Simple container that allows us to instrument things like imports
@@ -196,6 +197,7 @@ impl Tester {
                     None,
                     false,
                     false,
+                    ValidateVisibility::Off,
                     Some(AdditionalGlobalsFn(Arc::new(move |globals_builder| {
                         for additional_globals in &additional_globals {
                             (additional_globals.0)(globals_builder)
@@ -10,3 +10,4 @@
 pub mod attr_spec;
 pub(crate) mod check_within_view;
 pub mod unconfigured;
+pub mod validate_visibility;
@@ -155,6 +155,7 @@ impl AttributeSpecExt for AttributeSpec {
                 check_within_view(
                     a.value,
                     internals.buildfile_path().package(),
+                    name,
                     a.attr.coercer(),
                     within_view,
                 )
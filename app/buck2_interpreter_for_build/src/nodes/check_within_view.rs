@@ -14,44 +14,26 @@ use buck2_core::package::source_path::SourcePathRef;
 use buck2_core::package::PackageLabel;
 use buck2_core::plugins::PluginKind;
 use buck2_core::target::label::label::TargetLabel;
+use buck2_core::target::name::TargetNameRef;
 use buck2_node::attrs::attr_type::AttrType;
 use buck2_node::attrs::coerced_attr::CoercedAttr;
 use buck2_node::attrs::traversal::CoercedAttrTraversal;
 use buck2_node::configuration::resolved::ConfigurationSettingKey;
-use buck2_node::visibility::VisibilityPattern;
-use buck2_node::visibility::VisibilityPatternList;
+use buck2_node::visibility::VisibilityError;
 use buck2_node::visibility::WithinViewSpecification;
 use dupe::Dupe;
 
-fn indented_within_view(spec: &WithinViewSpecification) -> String {
-    match &spec.0 {
-        VisibilityPatternList::Public => format!("  {}\n", VisibilityPattern::PUBLIC),
-        VisibilityPatternList::List(items) => {
-            let mut s = String::new();
-            for item in items {
-                s.push_str(&format!("  {}\n", item));
-            }
-            s
-        }
-    }
-}
+/// At most this many violating dependencies are listed per attribute.
+const MAX_REPORTED_DEPS: usize = 10;
 
-#[derive(Debug, buck2_error::Error)]
-#[buck2(input)]
-enum CheckWithinViewError {
-    #[error(
-        "Target's `within_view` attribute does not allow dependency `{}`. Allowed dependencies:\n{}",
-        _0,
-        indented_within_view(_1)
-    )]
-    #[buck2(tag = Visibility)]
-    DepNotWithinView(TargetLabel, WithinViewSpecification),
-}
-
-/// Check that dependencies in attribute do not violate `within_view`.
+/// Check that dependencies in attribute of target `name` do not violate `within_view`.
+///
+/// All the dependencies of the attribute are checked, so that the error lists the packages that
+/// need to be added to `within_view` rather than only the first one.
 pub(crate) fn check_within_view(
     attr: &CoercedAttr,
     pkg: PackageLabel,
+    name: &TargetNameRef,
     attr_type: &AttrType,
     within_view: &WithinViewSpecification,
 ) -> anyhow::Result<()> {
@@ -63,18 +45,20 @@ pub(crate) fn check_within_view(
     struct WithinViewCheckTraversal<'x> {
         pkg: PackageLabel,
         within_view: &'x WithinViewSpecification,
+        /// First `MAX_REPORTED_DEPS` violations.
+        violations: Vec<TargetLabel>,
+        total: usize,
     }
 
     impl<'x> WithinViewCheckTraversal<'x> {
-        fn check_dep_within_view(&self, dep: &TargetLabel) -> anyhow::Result<()> {
-            if self.pkg == dep.pkg() || self.within_view.0.matches_target(dep) {
-                Ok(())
-            } else {
-                Err(
-                    CheckWithinViewError::DepNotWithinView(dep.dupe(), self.within_view.dupe())
-                        .into(),
-                )
+        fn check_dep_within_view(&mut self, dep: &TargetLabel) -> anyhow::Result<()> {
+            if self.pkg != dep.pkg() && !self.within_view.0.matches_target(dep) {
+                if self.violations.len() < MAX_REPORTED_DEPS {
+                    self.violations.push(dep.dupe());
+                }
+                self.total += 1;
             }
+            Ok(())
         }
     }
 
@@ -125,9 +109,33 @@ pub(crate) fn check_within_view(
         }
     }
 
-    attr.traverse(
-        attr_type,
-        pkg.dupe(),
-        &mut WithinViewCheckTraversal { pkg, within_view },
-    )
+    let mut traversal = WithinViewCheckTraversal {
+        pkg: pkg.dupe(),
+        within_view,
+        violations: Vec::new(),
+        total: 0,
+    };
+    attr.traverse(attr_type, pkg.dupe(), &mut traversal)?;
+
+    let WithinViewCheckTraversal {
+        mut violations,
+        total,
+        ..
+    } = traversal;
+    match violations.len() {
+        0 => Ok(()),
+        1 if total == 1 => Err(VisibilityError::not_within_view(
+            TargetLabel::new(pkg, name),
+            violations.pop().unwrap(),
+            within_view,
+        )
+        .into()),
+        _ => Err(VisibilityError::deps_not_within_view(
+            TargetLabel::new(pkg, name),
+            violations,
+            total,
+            within_view,
+        )
+        .into()),
+    }
 }
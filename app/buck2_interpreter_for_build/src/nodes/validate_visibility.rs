@@ -0,0 +1,232 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Optional lint pass over `visibility` and `within_view`, run as target nodes are recorded.
+//!
+//! Target node construction already rejects dependencies set explicitly outside `within_view`.
+//! This pass also covers the dependencies that come from attribute defaults (e.g. toolchains),
+//! and patterns that refer to cells the cell resolver doesn't know about.
+
+use std::cell::Cell;
+use std::str::FromStr;
+
+use allocative::Allocative;
+use buck2_core::cells::name::CellName;
+use buck2_core::cells::CellResolver;
+use buck2_core::package::PackageLabel;
+use buck2_core::pattern::pattern_type::TargetPatternExtra;
+use buck2_core::pattern::ParsedPattern;
+use buck2_core::soft_error;
+use buck2_core::target::label::label::TargetLabel;
+use buck2_node::nodes::unconfigured::TargetNode;
+use buck2_node::visibility::VisibilityPattern;
+use buck2_node::visibility::VisibilityPatternList;
+use buck2_node::visibility::WithinViewSpecification;
+use dupe::Dupe;
+
+/// At most this many findings are reported per package.
+const MAX_REPORTED_FINDINGS: usize = 10;
+
+/// What to do with the findings of the visibility validation pass. Set with
+/// `--validate-visibility` or `buck2.validate_visibility`.
+#[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq, Default, Allocative)]
+pub enum ValidateVisibility {
+    /// Don't run the pass.
+    #[default]
+    Off,
+    /// Report findings as soft errors.
+    Warn,
+    /// Fail the evaluation of the package on the first finding.
+    Strict,
+}
+
+#[derive(Debug, buck2_error::Error)]
+#[error("Invalid visibility validation mode `{0}`, expected one of `off`, `warn`, `strict`")]
+#[buck2(input)]
+pub struct InvalidValidateVisibility(String);
+
+impl FromStr for ValidateVisibility {
+    type Err = InvalidValidateVisibility;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(ValidateVisibility::Off),
+            "warn" => Ok(ValidateVisibility::Warn),
+            "strict" => Ok(ValidateVisibility::Strict),
+            _ => Err(InvalidValidateVisibility(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, buck2_error::Error)]
+#[buck2(input, tag = Visibility)]
+enum VisibilityValidationError {
+    #[error("`{target}`: `{attr}` pattern `{pattern}` refers to unknown cell `{cell}`")]
+    UnknownCell {
+        target: TargetLabel,
+        attr: &'static str,
+        pattern: VisibilityPattern,
+        cell: CellName,
+    },
+    #[error(
+        "`{target}`: `within_view` does not include package `{package}` of dependency `{dep}`, \
+        so the target can't be built"
+    )]
+    DepNotWithinView {
+        target: TargetLabel,
+        dep: TargetLabel,
+        package: PackageLabel,
+    },
+}
+
+fn pattern_cell(pattern: &ParsedPattern<TargetPatternExtra>) -> CellName {
+    match pattern {
+        ParsedPattern::Target(package, _, _) | ParsedPattern::Package(package) => {
+            package.cell_name()
+        }
+        ParsedPattern::Recursive(path) => path.cell(),
+    }
+}
+
+fn patterns(list: &VisibilityPatternList) -> Vec<&VisibilityPattern> {
+    match list {
+        VisibilityPatternList::Public => Vec::new(),
+        VisibilityPatternList::PublicExcept(excluded) => excluded.iter().collect(),
+        VisibilityPatternList::List(entries) => entries.iter().map(|e| &e.pattern).collect(),
+    }
+}
+
+fn unknown_cells(
+    node: &TargetNode,
+    attr: &'static str,
+    list: &VisibilityPatternList,
+    cell_resolver: &CellResolver,
+    findings: &mut Vec<VisibilityValidationError>,
+) {
+    for pattern in patterns(list) {
+        let cell = pattern_cell(&pattern.0);
+        if cell_resolver.get(cell).is_err() {
+            findings.push(VisibilityValidationError::UnknownCell {
+                target: node.label().dupe(),
+                attr,
+                pattern: pattern.clone(),
+                cell,
+            });
+        }
+    }
+}
+
+fn deps_not_within_view(
+    node: &TargetNode,
+    within_view: &WithinViewSpecification,
+    findings: &mut Vec<VisibilityValidationError>,
+) {
+    if within_view == &WithinViewSpecification::PUBLIC {
+        return;
+    }
+    let pkg = node.label().pkg();
+    for dep in node.deps().chain(node.platform_deps()) {
+        if dep.pkg() != pkg && !within_view.0.matches_target(dep) {
+            findings.push(VisibilityValidationError::DepNotWithinView {
+                target: node.label().dupe(),
+                dep: dep.dupe(),
+                package: dep.pkg(),
+            });
+        }
+    }
+}
+
+/// Runs the visibility validation pass over `node`. `reported` counts the findings reported so
+/// far for the package of `node`.
+pub(crate) fn validate_visibility(
+    node: &TargetNode,
+    cell_resolver: &CellResolver,
+    mode: ValidateVisibility,
+    reported: &Cell<usize>,
+) -> anyhow::Result<()> {
+    if mode == ValidateVisibility::Off {
+        return Ok(());
+    }
+
+    let within_view = node.within_view()?;
+    let mut findings = Vec::new();
+    unknown_cells(
+        node,
+        "visibility",
+        &node.visibility()?.0,
+        cell_resolver,
+        &mut findings,
+    );
+    unknown_cells(
+        node,
+        "within_view",
+        &within_view.0,
+        cell_resolver,
+        &mut findings,
+    );
+    deps_not_within_view(node, within_view, &mut findings);
+
+    for finding in findings {
+        match mode {
+            ValidateVisibility::Off => {}
+            ValidateVisibility::Warn => {
+                if reported.get() >= MAX_REPORTED_FINDINGS {
+                    break;
+                }
+                reported.set(reported.get() + 1);
+                soft_error!("visibility_validation", finding.into())?;
+            }
+            ValidateVisibility::Strict => return Err(finding.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::CellResolver;
+    use buck2_node::visibility::VisibilityPattern;
+    use buck2_node::visibility::VisibilitySpecification;
+
+    use crate::nodes::validate_visibility::pattern_cell;
+    use crate::nodes::validate_visibility::patterns;
+    use crate::nodes::validate_visibility::ValidateVisibility;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(ValidateVisibility::Warn, "warn".parse().unwrap());
+        assert_eq!(ValidateVisibility::Strict, "strict".parse().unwrap());
+        assert_eq!(ValidateVisibility::Off, "off".parse().unwrap());
+        assert!("true".parse::<ValidateVisibility>().is_err());
+    }
+
+    #[test]
+    fn test_unknown_cells() {
+        let cell_resolver = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("root"),
+            CellRootPathBuf::testing_new(""),
+        );
+        let visibility = VisibilitySpecification::testing_parse(&[
+            "root//foo:bar",
+            "rooot//foo/...",
+            "!root//foo:baz",
+        ]);
+
+        let unknown: Vec<&VisibilityPattern> = patterns(&visibility.0)
+            .into_iter()
+            .filter(|p| cell_resolver.get(pattern_cell(&p.0)).is_err())
+            .collect();
+        assert_eq!(
+            vec![&VisibilityPattern::testing_new("rooot//foo/...")],
+            unknown
+        );
+    }
+}
@@ -14,9 +14,11 @@ use std::sync::Arc;
 
 use allocative::Allocative;
 use anyhow::Context;
+use async_trait::async_trait;
 use buck2_core::bzl::ImportPath;
 use buck2_core::configuration::transition::id::TransitionId;
 use buck2_core::plugins::PluginKind;
+use buck2_interpreter::load_module::InterpreterCalculation;
 use buck2_interpreter::types::rule::FROZEN_PROMISE_ARTIFACT_MAPPINGS_GET_IMPL;
 use buck2_interpreter::types::rule::FROZEN_RULE_GET_IMPL;
 use buck2_interpreter::types::transition::transition_id_from_value;
@@ -25,9 +27,12 @@ use buck2_node::attrs::spec::AttributeSpec;
 use buck2_node::nodes::unconfigured::RuleKind;
 use buck2_node::nodes::unconfigured::TargetNode;
 use buck2_node::rule::Rule;
+use buck2_node::rule::RuleDefinitionCalculation;
+use buck2_node::rule::RULE_DEFINITION_CALCULATION;
 use buck2_node::rule_type::RuleType;
 use buck2_node::rule_type::StarlarkRuleType;
 use derive_more::Display;
+use dice::DiceComputations;
 use dupe::Dupe;
 use gazebo::prelude::*;
 use itertools::Itertools;
@@ -141,6 +146,8 @@ enum RuleError {
     IsConfigurationAndToolchain,
     #[error("`rule` can only be declared in bzl files")]
     RuleNonInBzl,
+    #[error("`{0}` is not a rule")]
+    NotARule(StarlarkRuleType),
 }
 
 impl<'v> AllocValue<'v> for RuleCallable<'v> {
@@ -347,6 +354,30 @@ pub(crate) fn init_frozen_rule_get_impl() {
     })
 }
 
+struct RuleDefinitionCalculationInstance;
+
+#[async_trait]
+impl RuleDefinitionCalculation for RuleDefinitionCalculationInstance {
+    async fn rule_definition(
+        &self,
+        ctx: &mut DiceComputations<'_>,
+        rule_type: &StarlarkRuleType,
+    ) -> anyhow::Result<Arc<Rule>> {
+        let module = ctx
+            .get_loaded_module_from_import_path(&rule_type.import_path)
+            .await?;
+        let rule = module.env().get_any_visibility(&rule_type.name)?.0;
+        let rule = rule
+            .downcast_anyhow::<FrozenRuleCallable>()
+            .map_err(|_| RuleError::NotARule(rule_type.clone()))?;
+        Ok(rule.rule.dupe())
+    }
+}
+
+pub(crate) fn init_rule_definition_calculation() {
+    RULE_DEFINITION_CALCULATION.init(&RuleDefinitionCalculationInstance);
+}
+
 pub(crate) fn init_frozen_promise_artifact_mappings_get_impl() {
     FROZEN_PROMISE_ARTIFACT_MAPPINGS_GET_IMPL.init(|rule| {
         let rule = unpack_frozen_rule(rule)?;
@@ -11,7 +11,6 @@ use buck2_core::cells::name::CellName;
 use buck2_core::cells::CellAliasResolver;
 use buck2_core::cells::CellResolver;
 use buck2_core::pattern::ParsedPattern;
-use buck2_node::visibility::VisibilityPattern;
 use buck2_node::visibility::VisibilitySpecification;
 use buck2_node::visibility::VisibilityWithinViewBuilder;
 use buck2_node::visibility::WithinViewSpecification;
@@ -44,16 +43,9 @@ fn parse_visibility(
 ) -> anyhow::Result<VisibilitySpecification> {
     let mut builder = VisibilityWithinViewBuilder::with_capacity(patterns.len());
     for pattern in patterns {
-        if pattern == VisibilityPattern::PUBLIC {
-            builder.add_public();
-        } else {
-            builder.add(VisibilityPattern(ParsedPattern::parse_precise(
-                pattern,
-                cell_name,
-                cell_resolver,
-                cell_alias_resolver,
-            )?));
-        }
+        builder.parse_and_add(pattern, |p| {
+            ParsedPattern::parse_precise(p, cell_name, cell_resolver, cell_alias_resolver)
+        })?;
     }
     Ok(builder.build_visibility())
 }
@@ -66,16 +58,9 @@ fn parse_within_view(
 ) -> anyhow::Result<WithinViewSpecification> {
     let mut builder = VisibilityWithinViewBuilder::with_capacity(patterns.len());
     for pattern in patterns {
-        if pattern == VisibilityPattern::PUBLIC {
-            builder.add_public();
-        } else {
-            builder.add(VisibilityPattern(ParsedPattern::parse_precise(
-                pattern,
-                cell_name,
-                cell_resolver,
-                cell_alias_resolver,
-            )?));
-        }
+        builder.parse_and_add(pattern, |p| {
+            ParsedPattern::parse_precise(p, cell_name, cell_resolver, cell_alias_resolver)
+        })?;
     }
     Ok(builder.build_within_view())
 }
@@ -184,8 +184,9 @@ fn cells() -> CellsData {
     let BuckConfigBasedCells {
         cell_resolver,
         configs_by_name,
+        base_configs_by_name: _,
         config_paths: _,
-        resolved_args: _,
+        overlay: _,
     } = BuckConfigBasedCells::parse_with_file_ops(
         &project_fs,
         &mut TestConfigParserFileOps::new(&[(
@@ -9,10 +9,12 @@
 
 use buck2_core::fs::project::ProjectRootTemp;
 use buck2_core::target::label::label::TargetLabel;
+use buck2_interpreter_for_build::nodes::validate_visibility::ValidateVisibility;
 use buck2_node::nodes::frontend::TargetGraphCalculation;
 use buck2_node::visibility::VisibilitySpecification;
 
 use crate::tests::calculation;
+use crate::tests::calculation_with_validate_visibility;
 
 const RULES_BZL: &str = r#"
 simple = rule(
@@ -141,3 +143,100 @@ simple(name = "a")
         a.visibility().unwrap(),
     );
 }
+
+#[tokio::test]
+async fn test_within_view_reports_all_violating_deps() {
+    let fs = ProjectRootTemp::new().unwrap();
+
+    fs.write_file(
+        "rules.bzl",
+        r#"
+with_deps = rule(
+    impl = lambda ctx: fail(),
+    attrs = {"deps": attrs.list(attrs.dep(), default = [])},
+)
+"#,
+    );
+    fs.write_file(
+        "juxtaposition/PACKAGE",
+        r#"
+package(
+    within_view = ["//bbb/..."],
+)
+"#,
+    );
+    fs.write_file(
+        "juxtaposition/BUCK",
+        r#"
+load("//:rules.bzl", "with_deps")
+with_deps(
+    name = "a",
+    deps = [":b", "//bbb:c", "//ccc:d", "//ddd:e"],
+)
+"#,
+    );
+
+    let mut ctx = calculation(&fs).await;
+
+    let err = ctx
+        .get_target_node(&TargetLabel::testing_parse("root//juxtaposition:a"))
+        .await
+        .unwrap_err();
+    let err = format!("{:?}", err);
+    assert!(err.contains("does not allow 2 dependencies"), "{}", err);
+    assert!(err.contains("root//ccc:d"), "{}", err);
+    assert!(err.contains("root//ddd:e"), "{}", err);
+    assert!(!err.contains("root//bbb:c"), "{}", err);
+}
+
+async fn eval_with_default_dep_outside_within_view(
+    validate_visibility: ValidateVisibility,
+) -> anyhow::Result<()> {
+    let fs = ProjectRootTemp::new().unwrap();
+
+    fs.write_file(
+        "rules.bzl",
+        r#"
+with_toolchain = rule(
+    impl = lambda ctx: fail(),
+    attrs = {"_toolchain": attrs.default_only(attrs.dep(default = "//toolchains:tc"))},
+)
+"#,
+    );
+    fs.write_file(
+        "juxtaposition/PACKAGE",
+        r#"
+package(
+    within_view = ["//bbb/..."],
+)
+"#,
+    );
+    fs.write_file(
+        "juxtaposition/BUCK",
+        r#"
+load("//:rules.bzl", "with_toolchain")
+with_toolchain(name = "a")
+"#,
+    );
+
+    let mut ctx = calculation_with_validate_visibility(&fs, validate_visibility).await;
+    ctx.get_target_node(&TargetLabel::testing_parse("root//juxtaposition:a"))
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_validate_visibility_default_dep_outside_within_view() {
+    eval_with_default_dep_outside_within_view(ValidateVisibility::Off)
+        .await
+        .unwrap();
+    eval_with_default_dep_outside_within_view(ValidateVisibility::Warn)
+        .await
+        .unwrap();
+
+    let err = eval_with_default_dep_outside_within_view(ValidateVisibility::Strict)
+        .await
+        .unwrap_err();
+    let err = format!("{:?}", err);
+    assert!(err.contains("root//toolchains:tc"), "{}", err);
+}
@@ -12,6 +12,7 @@ use std::sync::Arc;
 use buck2_common::dice::cells::SetCellResolver;
 use buck2_common::dice::data::testing::SetTestingIoProvider;
 use buck2_common::legacy_configs::dice::SetLegacyConfigs;
+use buck2_common::legacy_configs::overlay::LegacyConfigOverlay;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_common::legacy_configs::LegacyBuckConfigs;
 use buck2_core::bzl::ImportPath;
@@ -32,6 +33,7 @@ use buck2_interpreter::extra::InterpreterHostPlatform;
 use buck2_interpreter::load_module::InterpreterCalculation;
 use buck2_interpreter_for_build::interpreter::configuror::BuildInterpreterConfiguror;
 use buck2_interpreter_for_build::interpreter::context::SetInterpreterContext;
+use buck2_interpreter_for_build::nodes::validate_visibility::ValidateVisibility;
 use buck2_node::nodes::frontend::TargetGraphCalculation;
 use dice::DetectCycles;
 use dice::Dice;
@@ -54,6 +56,13 @@ pub(crate) fn root_cell() -> CellName {
 }
 
 pub(crate) async fn calculation(fs: &ProjectRootTemp) -> DiceTransaction {
+    calculation_with_validate_visibility(fs, ValidateVisibility::Off).await
+}
+
+pub(crate) async fn calculation_with_validate_visibility(
+    fs: &ProjectRootTemp,
+    validate_visibility: ValidateVisibility,
+) -> DiceTransaction {
     let mut dice = Dice::builder();
     dice.set(EventDispatcher::null());
     dice.set_testing_io_provider(fs);
@@ -79,6 +88,7 @@ pub(crate) async fn calculation(fs: &ProjectRootTemp) -> DiceTransaction {
             None,
             false,
             false,
+            validate_visibility,
             None,
             Arc::new(ConcurrentTargetLabelInterner::default()),
         )
@@ -86,7 +96,8 @@ pub(crate) async fn calculation(fs: &ProjectRootTemp) -> DiceTransaction {
     )
     .unwrap();
     ctx.set_legacy_configs(cell_configs).unwrap();
-    ctx.set_legacy_config_overrides(Arc::new([])).unwrap();
+    ctx.set_legacy_config_overlay(LegacyConfigOverlay::empty())
+        .unwrap();
     ctx.set_starlark_profiler_instrumentation_override(StarlarkProfilerConfiguration::default())
         .unwrap();
     ctx.set_starlark_types(false, false).unwrap();
@@ -22,6 +22,7 @@ pub mod configured_attr;
 pub mod configured_attr_full;
 pub mod configured_attr_info_for_tests;
 pub mod configured_traversal;
+pub mod describe;
 pub mod display;
 pub mod fmt_context;
 pub mod hacks;
@@ -146,6 +146,16 @@ impl CoercedSelector {
         Ok(())
     }
 
+    /// The entries other than `DEFAULT`, in declaration order.
+    pub fn entries(&self) -> &[(ConfigurationSettingKey, CoercedAttr)] {
+        &self.entries
+    }
+
+    /// The `DEFAULT` entry, if any.
+    pub fn default(&self) -> Option<&CoercedAttr> {
+        self.default.as_ref()
+    }
+
     fn all_entries(&self) -> impl Iterator<Item = (CoercedSelectorKeyRef, &CoercedAttr)> {
         self.entries
             .iter()
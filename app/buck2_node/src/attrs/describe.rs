@@ -0,0 +1,194 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use serde::Serialize;
+use starlark_map::small_map::SmallMap;
+
+use crate::attrs::configurable::AttrIsConfigurable;
+use crate::attrs::fmt_context::AttrFmtContext;
+use crate::attrs::internal::attr_is_configurable;
+use crate::attrs::spec::AttributeSpec;
+
+/// Description of a rule attribute, as reported by `buck2 audit defaults`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttributeDescription {
+    /// The attribute type, formatted like the `attrs` call that declares it.
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The default value, rendered the same way as attribute values are in `uquery --output-all-attributes`.
+    /// `None` when the attribute is required.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    /// Whether the attribute can be set with a `select`.
+    pub configurable: bool,
+    /// Whether the attribute cannot be set by the user at all.
+    pub default_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+}
+
+impl AttributeSpec {
+    /// Describe all the attributes of this spec, including the internal ones, in declaration order.
+    pub fn describe(&self) -> anyhow::Result<SmallMap<String, AttributeDescription>> {
+        self.attr_specs()
+            .map(|(name, _, attr)| {
+                let default = attr
+                    .default()
+                    .map(|default| default.to_json(&AttrFmtContext::NO_CONTEXT))
+                    .transpose()?;
+                let doc = Some(attr.doc()).filter(|doc| !doc.is_empty());
+                Ok((
+                    name.to_owned(),
+                    AttributeDescription {
+                        ty: attr.coercer().to_string(),
+                        default,
+                        configurable: attr_is_configurable(name) == AttrIsConfigurable::Yes,
+                        default_only: attr.is_default_only(),
+                        doc: doc.map(str::to_owned),
+                    },
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use buck2_util::arc_str::ArcSlice;
+    use serde_json::json;
+    use starlark_map::ordered_map::OrderedMap;
+
+    use crate::attrs::attr::Attribute;
+    use crate::attrs::attr_type::list::ListLiteral;
+    use crate::attrs::attr_type::string::StringLiteral;
+    use crate::attrs::attr_type::AttrType;
+    use crate::attrs::coerced_attr::CoercedAttr;
+    use crate::attrs::coerced_attr::CoercedSelector;
+    use crate::attrs::spec::AttributeSpec;
+    use crate::configuration::resolved::ConfigurationSettingKey;
+
+    fn string(s: &str) -> CoercedAttr {
+        CoercedAttr::String(StringLiteral(s.into()))
+    }
+
+    fn fixture_rule_spec() -> anyhow::Result<AttributeSpec> {
+        let select = CoercedSelector::new(
+            ArcSlice::new([(
+                ConfigurationSettingKey::testing_parse("root//config:linux"),
+                string("linux"),
+            )]),
+            Some(string("other")),
+        )?;
+        Ok(AttributeSpec::testing_new(OrderedMap::from_iter([
+            (
+                "mode".to_owned(),
+                Attribute::new(
+                    Some(Arc::new(string("opt"))),
+                    "Optimization mode",
+                    AttrType::string(),
+                ),
+            ),
+            (
+                "flags".to_owned(),
+                Attribute::new(
+                    Some(Arc::new(CoercedAttr::List(ListLiteral(ArcSlice::new([
+                        string("-a"),
+                        string("-b"),
+                    ]))))),
+                    "",
+                    AttrType::list(AttrType::string()),
+                ),
+            ),
+            (
+                "os".to_owned(),
+                Attribute::new(
+                    Some(Arc::new(CoercedAttr::Selector(Box::new(select)))),
+                    "",
+                    AttrType::string(),
+                ),
+            ),
+            (
+                "srcs".to_owned(),
+                Attribute::new(None, "", AttrType::list(AttrType::string())),
+            ),
+        ])))
+    }
+
+    #[test]
+    fn test_describe_defaults() -> anyhow::Result<()> {
+        let described = fixture_rule_spec()?.describe()?;
+
+        let mode = described.get("mode").unwrap();
+        assert_eq!("attrs.string()", mode.ty);
+        assert_eq!(Some(json!("opt")), mode.default);
+        assert_eq!(Some("Optimization mode"), mode.doc.as_deref());
+        assert!(mode.configurable);
+        assert!(!mode.default_only);
+
+        let flags = described.get("flags").unwrap();
+        assert_eq!("attrs.list(attrs.string())", flags.ty);
+        assert_eq!(Some(json!(["-a", "-b"])), flags.default);
+        assert_eq!(None, flags.doc);
+
+        assert_eq!(
+            Some(json!({
+                "__type": "selector",
+                "entries": {
+                    "root//config:linux": "linux",
+                    "DEFAULT": "other",
+                },
+            })),
+            described.get("os").unwrap().default
+        );
+
+        assert_eq!(None, described.get("srcs").unwrap().default);
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_internal_attributes() -> anyhow::Result<()> {
+        let described = fixture_rule_spec()?.describe()?;
+
+        // Internal attributes come first, followed by the rule's own attributes in order.
+        assert_eq!(
+            Some("name"),
+            described.keys().next().map(|name| name.as_str())
+        );
+        assert_eq!(
+            Some("srcs"),
+            described.keys().last().map(|name| name.as_str())
+        );
+
+        assert!(!described.get("name").unwrap().configurable);
+        assert!(!described.get("visibility").unwrap().configurable);
+        assert_eq!(
+            Some(json!([])),
+            described.get("visibility").unwrap().default
+        );
+        assert!(described.get("tests").unwrap().configurable);
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_serializes_without_missing_fields() -> anyhow::Result<()> {
+        let described = fixture_rule_spec()?.describe()?;
+
+        assert_eq!(
+            json!({
+                "type": "attrs.list(attrs.string())",
+                "configurable": true,
+                "default_only": false,
+            }),
+            serde_json::to_value(described.get("srcs").unwrap())?
+        );
+        Ok(())
+    }
+}
@@ -140,6 +140,10 @@ impl QueryTarget for ConfiguredGraphNodeRef {
             .toolchain_deps()
             .map(ConfiguredGraphNodeRef::ref_cast)
     }
+
+    fn is_visible_to(&self, target: &Self) -> anyhow::Result<bool> {
+        self.0.is_visible_to(target.0.label().unconfigured())
+    }
     fn attr_any_matches(
         attr: &Self::Attr<'_>,
         filter: &dyn Fn(&str) -> anyhow::Result<bool>,
@@ -49,6 +49,7 @@ use crate::package::Package;
 use crate::rule::Rule;
 use crate::rule_type::RuleType;
 use crate::visibility::VisibilitySpecification;
+use crate::visibility::WithinViewSpecification;
 
 /// Describes a target including its name, type, and the values that the user provided.
 /// Some information (e.g. deps) is extracted eagerly, most is in the attrs map and needs to be
@@ -218,6 +219,20 @@ impl TargetNode {
         }
     }
 
+    pub fn within_view(&self) -> anyhow::Result<&WithinViewSpecification> {
+        match self.0.attributes.get(AttributeSpec::within_view_attr_id()) {
+            Some(CoercedAttr::WithinView(v)) => Ok(v),
+            Some(a) => Err(internal_error!(
+                "`within_view` attribute coerced incorrectly (`{0}`)",
+                a.as_display_no_ctx().to_string(),
+            )),
+            None => {
+                static PUBLIC: WithinViewSpecification = WithinViewSpecification::PUBLIC;
+                Ok(&PUBLIC)
+            }
+        }
+    }
+
     pub fn is_visible_to(&self, target: &TargetLabel) -> anyhow::Result<bool> {
         if self.label().pkg() == target.pkg() {
             return Ok(true);
@@ -613,12 +628,14 @@ pub mod testing {
     use crate::attrs::coerced_deps_collector::CoercedDepsCollector;
     use crate::attrs::fmt_context::AttrFmtContext;
     use crate::attrs::inspect_options::AttrInspectOptions;
+    use crate::attrs::internal::internal_attrs;
     use crate::attrs::spec::AttributeSpec;
     use crate::attrs::values::AttrValues;
     use crate::nodes::targets_map::TargetsMap;
     use crate::rule_type::RuleType;
 
     pub trait TargetNodeExt {
+        /// Internal attributes such as `visibility` can be set too, their `Attribute` is ignored.
         fn testing_new(
             label: TargetLabel,
             rule_type: RuleType,
@@ -635,6 +652,7 @@ pub mod testing {
             let attr_spec = AttributeSpec::testing_new(
                 attrs
                     .iter()
+                    .filter(|(name, _, _)| !internal_attrs().contains_key(name))
                     .map(|(name, attr, _)| ((*name).to_owned(), attr.clone()))
                     .collect(),
             );
@@ -77,6 +77,10 @@ impl QueryTarget for ConfiguredTargetNode {
         Some(self.tests().map(|t| t.target().dupe()))
     }
 
+    fn is_visible_to(&self, target: &Self) -> anyhow::Result<bool> {
+        ConfiguredTargetNode::is_visible_to(self, target.label().unconfigured())
+    }
+
     fn special_attrs_for_each<E, F: FnMut(&str, &Self::Attr<'_>) -> Result<(), E>>(
         &self,
         mut func: F,
@@ -67,6 +67,10 @@ impl QueryTarget for TargetNode {
         Some(self.tests().map(|t| t.target().dupe()))
     }
 
+    fn is_visible_to(&self, target: &Self) -> anyhow::Result<bool> {
+        TargetNode::is_visible_to(self, target.label())
+    }
+
     fn attr_any_matches(
         attr: &Self::Attr<'_>,
         filter: &dyn Fn(&str) -> anyhow::Result<bool>,
@@ -10,12 +10,16 @@
 use std::sync::Arc;
 
 use allocative::Allocative;
+use async_trait::async_trait;
 use buck2_core::configuration::transition::id::TransitionId;
 use buck2_core::plugins::PluginKind;
+use buck2_util::late_binding::LateBinding;
+use dice::DiceComputations;
 
 use crate::attrs::spec::AttributeSpec;
 use crate::nodes::unconfigured::RuleKind;
 use crate::rule_type::RuleType;
+use crate::rule_type::StarlarkRuleType;
 
 /// Common rule data needed in `TargetNode`.
 #[derive(Debug, Eq, PartialEq, Hash, Allocative)]
@@ -32,3 +36,16 @@ pub struct Rule {
     /// The plugin kinds that are used by the target
     pub uses_plugins: Vec<PluginKind>,
 }
+
+/// Resolves a Starlark rule type to the rule definition, by evaluating the module which declares it.
+#[async_trait]
+pub trait RuleDefinitionCalculation: Send + Sync + 'static {
+    async fn rule_definition(
+        &self,
+        ctx: &mut DiceComputations<'_>,
+        rule_type: &StarlarkRuleType,
+    ) -> anyhow::Result<Arc<Rule>>;
+}
+
+pub static RULE_DEFINITION_CALCULATION: LateBinding<&'static dyn RuleDefinitionCalculation> =
+    LateBinding::new("RULE_DEFINITION_CALCULATION");
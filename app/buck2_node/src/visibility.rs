@@ -28,6 +28,92 @@ pub enum VisibilityError {
     )]
     #[buck2(input, tag = Visibility)]
     NotVisibleTo(TargetLabel, TargetLabel),
+    #[error(
+        "`{dep}` is not within the view of `{target}`, `within_view` allows {within_view}{}",
+        closest_pattern_suggestion(closest_pattern.as_deref())
+    )]
+    #[buck2(input, tag = Visibility)]
+    NotWithinView {
+        target: TargetLabel,
+        dep: TargetLabel,
+        /// The `within_view` patterns of `target`, as they are displayed.
+        within_view: String,
+        closest_pattern: Option<String>,
+    },
+    #[error(
+        "{total} dependencies are not within the view of `{target}`, `within_view` allows {within_view}:\n{}",
+        indented_deps(deps, *total)
+    )]
+    #[buck2(input, tag = Visibility)]
+    DepsNotWithinView {
+        target: TargetLabel,
+        /// The first few deps that are out of view, each with its closest pattern.
+        deps: Vec<(TargetLabel, Option<String>)>,
+        total: usize,
+        /// The `within_view` patterns of `target`, as they are displayed.
+        within_view: String,
+    },
+}
+
+impl VisibilityError {
+    pub fn not_within_view(
+        target: TargetLabel,
+        dep: TargetLabel,
+        within_view: &WithinViewSpecification,
+    ) -> VisibilityError {
+        let closest_pattern = within_view.closest_pattern(&dep).map(|p| p.to_string());
+        VisibilityError::NotWithinView {
+            target,
+            dep,
+            within_view: within_view.to_string(),
+            closest_pattern,
+        }
+    }
+
+    /// `deps` are the first few of `total` deps of `target` that are out of view.
+    pub fn deps_not_within_view(
+        target: TargetLabel,
+        deps: Vec<TargetLabel>,
+        total: usize,
+        within_view: &WithinViewSpecification,
+    ) -> VisibilityError {
+        let deps = deps
+            .into_iter()
+            .map(|dep| {
+                let closest_pattern = within_view.closest_pattern(&dep).map(|p| p.to_string());
+                (dep, closest_pattern)
+            })
+            .collect();
+        VisibilityError::DepsNotWithinView {
+            target,
+            deps,
+            total,
+            within_view: within_view.to_string(),
+        }
+    }
+}
+
+fn indented_deps(deps: &[(TargetLabel, Option<String>)], total: usize) -> String {
+    let mut s = String::new();
+    for (dep, closest_pattern) in deps {
+        s.push_str(&format!(
+            "  {} (package `{}`){}\n",
+            dep,
+            dep.pkg(),
+            closest_pattern_suggestion(closest_pattern.as_deref())
+        ));
+    }
+    if total > deps.len() {
+        s.push_str(&format!("  ... and {} more\n", total - deps.len()));
+    }
+    s
+}
+
+fn closest_pattern_suggestion(closest_pattern: Option<&str>) -> String {
+    match closest_pattern {
+        Some(pattern) => format!(" (closest pattern is `{}`)", pattern),
+        None => String::new(),
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Allocative, derive_more::Display)]
@@ -35,76 +121,138 @@ pub struct VisibilityPattern(pub ParsedPattern<TargetPatternExtra>);
 
 impl VisibilityPattern {
     pub const PUBLIC: &'static str = "PUBLIC";
+    /// Prefix of the patterns excluding the targets they match.
+    pub const NEGATION_PREFIX: &'static str = "!";
 
     pub fn testing_new(pattern: &str) -> VisibilityPattern {
         VisibilityPattern(ParsedPattern::testing_parse(pattern))
     }
 }
 
+/// A pattern of a visibility list, which either allows or excludes the targets it matches.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Allocative)]
+pub struct VisibilityPatternEntry {
+    pub pattern: VisibilityPattern,
+    pub negative: bool,
+}
+
+impl Display for VisibilityPatternEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.negative {
+            write!(f, "{}", VisibilityPattern::NEGATION_PREFIX)?;
+        }
+        Display::fmt(&self.pattern, f)
+    }
+}
+
 #[derive(derive_more::Display)]
 #[display(fmt = "\"{}\"", _0)]
-struct VisibilityPatternQuoted<'a>(&'a VisibilityPattern);
+struct VisibilityPatternQuoted<'a, P: Display>(&'a P);
 
+/// Targets are matched by a list if they match some positive pattern and no negative pattern.
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Dupe, Allocative)]
 pub enum VisibilityPatternList {
     Public,
-    List(ThinArcSlice<VisibilityPattern>),
+    /// `PUBLIC` along with negative patterns, matching everything these don't match.
+    PublicExcept(ThinArcSlice<VisibilityPattern>),
+    List(ThinArcSlice<VisibilityPatternEntry>),
 }
 
 impl VisibilityPatternList {
     fn is_empty(&self) -> bool {
         match self {
-            VisibilityPatternList::Public => false,
+            VisibilityPatternList::Public | VisibilityPatternList::PublicExcept(_) => false,
             VisibilityPatternList::List(patterns) => patterns.is_empty(),
         }
     }
 
     fn to_json(&self) -> serde_json::Value {
-        let list = match self {
-            VisibilityPatternList::Public => vec![serde_json::Value::String(
-                VisibilityPattern::PUBLIC.to_owned(),
-            )],
-            VisibilityPatternList::List(patterns) => {
-                patterns.map(|p| serde_json::Value::String(p.to_string()))
+        serde_json::Value::Array(
+            self.to_strings()
+                .into_iter()
+                .map(serde_json::Value::String)
+                .collect(),
+        )
+    }
+
+    /// The patterns as they are written in `BUCK` files.
+    pub fn to_strings(&self) -> Vec<String> {
+        match self {
+            VisibilityPatternList::Public => vec![VisibilityPattern::PUBLIC.to_owned()],
+            VisibilityPatternList::PublicExcept(excluded) => {
+                let mut list = Vec::with_capacity(excluded.len() + 1);
+                list.push(VisibilityPattern::PUBLIC.to_owned());
+                list.extend(
+                    excluded
+                        .iter()
+                        .map(|p| format!("{}{}", VisibilityPattern::NEGATION_PREFIX, p)),
+                );
+                list
             }
-        };
-        serde_json::Value::Array(list)
+            VisibilityPatternList::List(patterns) => patterns.map(|p| p.to_string()),
+        }
     }
 
+    fn excluded(&self) -> Vec<VisibilityPattern> {
+        match self {
+            VisibilityPatternList::Public => Vec::new(),
+            VisibilityPatternList::PublicExcept(excluded) => excluded.to_vec(),
+            VisibilityPatternList::List(patterns) => patterns
+                .iter()
+                .filter(|p| p.negative)
+                .map(|p| p.pattern.clone())
+                .collect(),
+        }
+    }
+
+    /// Negative patterns of either list apply to the result.
     fn extend_with(&self, other: &VisibilityPatternList) -> VisibilityPatternList {
         match (self, other) {
-            (VisibilityPatternList::Public, _) | (_, VisibilityPatternList::Public) => {
-                VisibilityPatternList::Public
-            }
             (VisibilityPatternList::List(this), VisibilityPatternList::List(other)) => {
                 VisibilityPatternList::List(this.iter().chain(other).cloned().collect())
             }
+            _ => {
+                let excluded = self
+                    .excluded()
+                    .into_iter()
+                    .chain(other.excluded())
+                    .collect::<Vec<_>>();
+                if excluded.is_empty() {
+                    VisibilityPatternList::Public
+                } else {
+                    VisibilityPatternList::PublicExcept(ThinArcSlice::from_iter(excluded))
+                }
+            }
         }
     }
 
     fn testing_parse(patterns: &[&str]) -> VisibilityPatternList {
-        if patterns.contains(&VisibilityPattern::PUBLIC) {
-            VisibilityPatternList::Public
-        } else {
-            VisibilityPatternList::List(
-                patterns
-                    .iter()
-                    .map(|p| VisibilityPattern::testing_new(p))
-                    .collect(),
-            )
+        let mut builder = VisibilityWithinViewBuilder::with_capacity(patterns.len());
+        for pattern in patterns {
+            builder
+                .parse_and_add(pattern, |p| Ok(ParsedPattern::testing_parse(p)))
+                .unwrap();
         }
+        builder.build_list()
     }
 
     pub fn matches_target(&self, target: &TargetLabel) -> bool {
         match self {
             VisibilityPatternList::Public => true,
+            VisibilityPatternList::PublicExcept(excluded) => {
+                !excluded.iter().any(|p| p.0.matches(target))
+            }
             VisibilityPatternList::List(patterns) => {
+                let mut matched = false;
                 for pattern in patterns {
-                    if pattern.0.matches(target) {
-                        return true;
+                    if pattern.pattern.0.matches(target) {
+                        if pattern.negative {
+                            return false;
+                        }
+                        matched = true;
                     }
                 }
-                false
+                matched
             }
         }
     }
@@ -114,29 +262,26 @@ impl Display for VisibilityPatternList {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             VisibilityPatternList::Public => write!(f, "[\"{}\"]", VisibilityPattern::PUBLIC),
-            VisibilityPatternList::List(patterns) => display_container::fmt_container(
-                f,
-                "[",
-                "]",
-                patterns.iter().map(VisibilityPatternQuoted),
-            ),
+            VisibilityPatternList::PublicExcept(_) | VisibilityPatternList::List(_) => {
+                display_container::fmt_container(
+                    f,
+                    "[",
+                    "]",
+                    self.to_strings().iter().map(VisibilityPatternQuoted),
+                )
+            }
         }
     }
 }
 
 impl AnyMatches for VisibilityPatternList {
     fn any_matches(&self, filter: &dyn Fn(&str) -> anyhow::Result<bool>) -> anyhow::Result<bool> {
-        match self {
-            VisibilityPatternList::Public => filter(VisibilityPattern::PUBLIC),
-            VisibilityPatternList::List(patterns) => {
-                for p in patterns {
-                    if filter(&p.to_string())? {
-                        return Ok(true);
-                    }
-                }
-                Ok(false)
+        for p in self.to_strings() {
+            if filter(&p)? {
+                return Ok(true);
             }
         }
+        Ok(false)
     }
 }
 
@@ -194,6 +339,35 @@ impl WithinViewSpecification {
     pub fn to_json(&self) -> serde_json::Value {
         self.0.to_json()
     }
+
+    /// The pattern that shares the longest prefix with `target`, to suggest which pattern is
+    /// almost the one that would allow it. `None` if no pattern shares anything with it.
+    pub fn closest_pattern(&self, target: &TargetLabel) -> Option<&VisibilityPattern> {
+        match &self.0 {
+            VisibilityPatternList::Public | VisibilityPatternList::PublicExcept(_) => None,
+            VisibilityPatternList::List(patterns) => {
+                let target = target.to_string();
+                patterns
+                    .iter()
+                    .filter(|p| !p.negative)
+                    .map(|p| &p.pattern)
+                    .map(|p| {
+                        let common = p
+                            .to_string()
+                            .chars()
+                            .zip(target.chars())
+                            .take_while(|(a, b)| a == b)
+                            .count();
+                        (common, p)
+                    })
+                    .filter(|(common, _)| *common > 0)
+                    // Prefer the first pattern on ties.
+                    .rev()
+                    .max_by_key(|(common, _)| *common)
+                    .map(|(_, p)| p)
+            }
+        }
+    }
 }
 
 impl Display for WithinViewSpecification {
@@ -217,7 +391,7 @@ impl AnyMatches for WithinViewSpecification {
 pub struct VisibilityWithinViewBuilder {
     cap: usize,
     seen_public: bool,
-    patterns: Option<Vec<VisibilityPattern>>,
+    patterns: Option<Vec<VisibilityPatternEntry>>,
 }
 
 impl VisibilityWithinViewBuilder {
@@ -234,18 +408,59 @@ impl VisibilityWithinViewBuilder {
     }
 
     pub fn add(&mut self, pattern: VisibilityPattern) {
-        if !self.seen_public {
-            self.patterns
-                .get_or_insert_with(|| Vec::with_capacity(self.cap))
-                .push(pattern);
+        self.add_entry(VisibilityPatternEntry {
+            pattern,
+            negative: false,
+        });
+    }
+
+    /// Excludes the targets matched by `pattern`, even if other patterns match them.
+    pub fn add_negative(&mut self, pattern: VisibilityPattern) {
+        self.add_entry(VisibilityPatternEntry {
+            pattern,
+            negative: true,
+        });
+    }
+
+    fn add_entry(&mut self, entry: VisibilityPatternEntry) {
+        self.patterns
+            .get_or_insert_with(|| Vec::with_capacity(self.cap))
+            .push(entry);
+    }
+
+    /// Adds a pattern as written in `BUCK` and `PACKAGE` files: `PUBLIC`, or a target pattern
+    /// parsed with `parse`, negative if it starts with `!`.
+    pub fn parse_and_add(
+        &mut self,
+        pattern: &str,
+        parse: impl FnOnce(&str) -> anyhow::Result<ParsedPattern<TargetPatternExtra>>,
+    ) -> anyhow::Result<()> {
+        if pattern == VisibilityPattern::PUBLIC {
+            // TODO(cjhopman): We should probably enforce that this is the only entry.
+            self.add_public();
+        } else if let Some(pattern) = pattern.strip_prefix(VisibilityPattern::NEGATION_PREFIX) {
+            self.add_negative(VisibilityPattern(parse(pattern)?));
+        } else {
+            self.add(VisibilityPattern(parse(pattern)?));
         }
+        Ok(())
     }
 
     fn build_list(self) -> VisibilityPatternList {
+        let patterns = self.patterns.unwrap_or_default();
         if self.seen_public {
-            VisibilityPatternList::Public
+            let excluded = patterns
+                .into_iter()
+                .filter(|p| p.negative)
+                .map(|p| p.pattern)
+                .collect::<Vec<_>>();
+            if excluded.is_empty() {
+                VisibilityPatternList::Public
+            } else {
+                VisibilityPatternList::PublicExcept(ThinArcSlice::from_iter(excluded))
+            }
         } else {
-            VisibilityPatternList::List(ThinArcSlice::from_iter(self.patterns.unwrap_or_default()))
+            VisibilityPatternList::List(ThinArcSlice::from_iter(patterns))
         }
     }
 
@@ -262,3 +477,209 @@ impl VisibilityWithinViewBuilder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::target::label::label::TargetLabel;
+    use buck2_util::arc_str::ThinArcSlice;
+
+    use crate::visibility::VisibilityError;
+    use crate::visibility::VisibilityPattern;
+    use crate::visibility::VisibilityPatternList;
+    use crate::visibility::VisibilitySpecification;
+    use crate::visibility::WithinViewSpecification;
+
+    fn not_within_view(patterns: &[&str], dep: &str) -> String {
+        VisibilityError::not_within_view(
+            TargetLabel::testing_parse("root//a:target"),
+            TargetLabel::testing_parse(dep),
+            &WithinViewSpecification(VisibilityPatternList::testing_parse(patterns)),
+        )
+        .to_string()
+    }
+
+    fn deps_not_within_view(patterns: &[&str], deps: &[&str], total: usize) -> String {
+        VisibilityError::deps_not_within_view(
+            TargetLabel::testing_parse("root//a:target"),
+            deps.iter().map(|d| TargetLabel::testing_parse(d)).collect(),
+            total,
+            &WithinViewSpecification(VisibilityPatternList::testing_parse(patterns)),
+        )
+        .to_string()
+    }
+
+    #[test]
+    fn test_not_within_view_suggests_closest_pattern() {
+        assert_eq!(
+            "`root//foo/bar:baz` is not within the view of `root//a:target`, `within_view` allows \
+                [\"root//a/...\", \"root//foo/baz/...\", \"root//foo:\"] \
+                (closest pattern is `root//foo/baz/...`)",
+            not_within_view(
+                &["root//a/...", "root//foo/baz/...", "root//foo:"],
+                "root//foo/bar:baz"
+            )
+        );
+    }
+
+    #[test]
+    fn test_not_within_view_without_similar_pattern() {
+        assert_eq!(
+            "`other//foo:bar` is not within the view of `root//a:target`, `within_view` allows \
+                [\"root//a/...\"]",
+            not_within_view(&["root//a/..."], "other//foo:bar")
+        );
+    }
+
+    #[test]
+    fn test_not_within_view_empty() {
+        assert_eq!(
+            "`root//foo:bar` is not within the view of `root//a:target`, `within_view` allows []",
+            not_within_view(&[], "root//foo:bar")
+        );
+    }
+
+    #[test]
+    fn test_not_within_view_public() {
+        assert_eq!(
+            "`root//foo:bar` is not within the view of `root//a:target`, `within_view` allows \
+                [\"PUBLIC\"]",
+            not_within_view(&["PUBLIC"], "root//foo:bar")
+        );
+    }
+
+    #[test]
+    fn test_negative_pattern_wins() {
+        let spec = VisibilitySpecification::testing_parse(&[
+            "root//foo/...",
+            "!root//foo/experimental/...",
+            "root//foo/experimental:allowed",
+        ]);
+        assert!(
+            spec.0
+                .matches_target(&TargetLabel::testing_parse("root//foo/bar:baz"))
+        );
+        assert!(!spec.0.matches_target(&TargetLabel::testing_parse(
+            "root//foo/experimental:allowed"
+        )));
+        assert!(
+            !spec
+                .0
+                .matches_target(&TargetLabel::testing_parse("root//foo/experimental/x:y"))
+        );
+        assert!(
+            !spec
+                .0
+                .matches_target(&TargetLabel::testing_parse("root//bar:baz"))
+        );
+
+        // Only negative patterns match nothing.
+        let spec = VisibilitySpecification::testing_parse(&["!root//foo/..."]);
+        assert!(
+            !spec
+                .0
+                .matches_target(&TargetLabel::testing_parse("root//bar:baz"))
+        );
+    }
+
+    #[test]
+    fn test_negative_pattern_with_public() {
+        let spec = VisibilitySpecification::testing_parse(&[
+            "root//foo/...",
+            "PUBLIC",
+            "!root//foo/experimental/...",
+        ]);
+        assert_eq!(
+            VisibilitySpecification(VisibilityPatternList::PublicExcept(
+                ThinArcSlice::from_iter([VisibilityPattern::testing_new(
+                    "root//foo/experimental/..."
+                )])
+            )),
+            spec
+        );
+        assert!(
+            spec.0
+                .matches_target(&TargetLabel::testing_parse("root//bar:baz"))
+        );
+        assert!(
+            !spec
+                .0
+                .matches_target(&TargetLabel::testing_parse("root//foo/experimental:x"))
+        );
+
+        assert_eq!(
+            VisibilitySpecification::testing_parse(&["PUBLIC"]),
+            VisibilitySpecification::testing_parse(&["root//foo/...", "PUBLIC"]),
+        );
+
+        // Exclusions of either spec apply when extending.
+        let extended = VisibilitySpecification::testing_parse(&["PUBLIC"]).extend_with(
+            &VisibilitySpecification::testing_parse(&["root//a/...", "!root//b/..."]),
+        );
+        assert_eq!(
+            VisibilitySpecification::testing_parse(&["PUBLIC", "!root//b/..."]),
+            extended
+        );
+        let extended = VisibilitySpecification::testing_parse(&["root//a/..."])
+            .extend_with(&VisibilitySpecification::testing_parse(&["!root//a/b/..."]));
+        assert!(
+            !extended
+                .0
+                .matches_target(&TargetLabel::testing_parse("root//a/b:c"))
+        );
+        assert!(
+            extended
+                .0
+                .matches_target(&TargetLabel::testing_parse("root//a:c"))
+        );
+    }
+
+    #[test]
+    fn test_negative_pattern_json() {
+        for patterns in [
+            vec!["PUBLIC"],
+            vec!["PUBLIC", "!root//foo/..."],
+            vec!["root//foo/...", "!root//foo/bar:", "root//baz:qux"],
+        ] {
+            let spec = VisibilitySpecification::testing_parse(&patterns);
+            let json = spec.to_json();
+            assert_eq!(serde_json::json!(patterns), json);
+
+            let strings = json
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|p| p.as_str().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(spec, VisibilitySpecification::testing_parse(&strings));
+        }
+    }
+
+    #[test]
+    fn test_negative_pattern_display() {
+        assert_eq!(
+            r#"["PUBLIC", "!root//foo/..."]"#,
+            VisibilitySpecification::testing_parse(&["PUBLIC", "!root//foo/..."]).to_string()
+        );
+        assert_eq!(
+            r#"["root//foo/...", "!root//foo/bar:"]"#,
+            VisibilitySpecification::testing_parse(&["root//foo/...", "!root//foo/bar:"])
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_deps_not_within_view() {
+        assert_eq!(
+            "3 dependencies are not within the view of `root//a:target`, `within_view` allows \
+                [\"root//a/...\", \"root//foo/baz/...\"]:\n  \
+                root//foo/bar:baz (package `root//foo/bar`) (closest pattern is `root//foo/baz/...`)\n  \
+                other//foo:bar (package `other//foo`)\n  \
+                ... and 1 more\n",
+            deps_not_within_view(
+                &["root//a/...", "root//foo/baz/..."],
+                &["root//foo/bar:baz", "other//foo:bar"],
+                3
+            )
+        );
+    }
+}
@@ -109,6 +109,13 @@ pub trait QueryTarget: LabeledNode + Dupe + Send + Sync + 'static {
         None::<iter::Empty<Self::Key>>
     }
 
+    /// Whether `target` is allowed to depend on this target. `visible_to()` function uses this.
+    fn is_visible_to(&self, _target: &Self) -> anyhow::Result<bool> {
+        Err(anyhow::anyhow!(QueryError::NotAvailableInContext(
+            "visible_to"
+        )))
+    }
+
     fn attr_any_matches(
         attr: &Self::Attr<'_>,
         filter: &dyn Fn(&str) -> anyhow::Result<bool>,
@@ -206,6 +213,26 @@ pub trait QueryEnvironment: Send + Sync {
         Ok(target_set)
     }
 
+    /// Like `somepath`, but the nodes of the path are returned in order, starting at a node
+    /// in `from` and ending at a node in `to`.
+    #[allow(clippy::from_iter_instead_of_collect)]
+    async fn shortestpath(
+        &self,
+        from: &TargetSet<Self::Target>,
+        to: &TargetSet<Self::Target>,
+    ) -> anyhow::Result<TargetSet<Self::Target>> {
+        let path = async_bfs_find_path(
+            from.iter(),
+            QueryEnvironmentAsNodeLookup { env: self },
+            QueryTargetDepsSuccessors,
+            |t| to.get(t).duped(),
+        )
+        .await?
+        .unwrap_or_default();
+
+        Ok(TargetSet::from_iter(path))
+    }
+
     async fn allbuildfiles(&self, _universe: &TargetSet<Self::Target>) -> anyhow::Result<FileSet> {
         Err(anyhow::anyhow!(QueryError::FunctionUnimplemented(
             "allbuildfiles() is implemented only for uquery and cquery.",
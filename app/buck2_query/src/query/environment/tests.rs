@@ -22,6 +22,7 @@ use dupe::OptionDupedExt;
 use indexmap::IndexSet;
 
 use super::*;
+use crate::query::syntax::simple::eval::file_set::FileGlob;
 use crate::query::traversal::AsyncNodeLookup;
 
 #[derive(Debug, Copy, Clone, Dupe, Eq, PartialEq, Hash, Display, From)]
@@ -36,6 +37,7 @@ struct TestTargetAttr;
 struct TestTarget {
     id: TestTargetId,
     deps: Arc<IndexSet<TestTargetId>>,
+    inputs: Arc<Vec<CellPath>>,
 }
 
 /// Custom debug to make the test output more readable
@@ -56,8 +58,14 @@ impl LabeledNode for TestTarget {
 impl QueryTarget for TestTarget {
     type Attr<'a> = TestTargetAttr;
 
-    fn inputs_for_each<E, F: FnMut(CellPath) -> Result<(), E>>(&self, _func: F) -> Result<(), E> {
-        unimplemented!()
+    fn inputs_for_each<E, F: FnMut(CellPath) -> Result<(), E>>(
+        &self,
+        mut func: F,
+    ) -> Result<(), E> {
+        for input in self.inputs.iter() {
+            func(input.clone())?;
+        }
+        Ok(())
     }
 
     fn rule_type(&self) -> Cow<str> {
@@ -221,6 +229,7 @@ impl TestEnv {
 #[derive(Default)]
 pub struct TestEnvBuilder {
     graph: HashMap<u64, IndexSet<u64>>,
+    inputs: HashMap<u64, Vec<CellPath>>,
 }
 
 impl TestEnvBuilder {
@@ -229,6 +238,14 @@ impl TestEnvBuilder {
         self.graph.entry(to).or_default();
     }
 
+    fn inputs(&mut self, target: u64, inputs: &[&str]) {
+        self.graph.entry(target).or_default();
+        self.inputs
+            .entry(target)
+            .or_default()
+            .extend(inputs.iter().map(|p| CellPath::testing_new(p)));
+    }
+
     fn build(&self) -> TestEnv {
         TestEnv {
             graph: self
@@ -237,7 +254,8 @@ impl TestEnvBuilder {
                 .map(|(id, vs)| {
                     let id = TestTargetId(*id);
                     let deps = Arc::new(vs.iter().map(|v| TestTargetId(*v)).collect());
-                    (id, TestTarget { id, deps })
+                    let inputs = Arc::new(self.inputs.get(&id.0).cloned().unwrap_or_default());
+                    (id, TestTarget { id, deps, inputs })
                 })
                 .collect(),
         }
@@ -291,6 +309,33 @@ async fn test_many_paths() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_shortestpath_diamond() -> anyhow::Result<()> {
+    let mut env = TestEnvBuilder::default();
+    // A diamond, with a longer chain from 1 to 4 on the side.
+    env.edge(1, 2);
+    env.edge(1, 3);
+    env.edge(2, 4);
+    env.edge(3, 4);
+    env.edge(1, 10);
+    env.edge(10, 11);
+    env.edge(11, 4);
+    let env = env.build();
+
+    let path = env.shortestpath(&env.set("1")?, &env.set("4")?).await?;
+    let path: Vec<u64> = path.iter().map(|t| t.id.0).collect();
+    assert!(
+        path == vec![1, 2, 4] || path == vec![1, 3, 4],
+        "unexpected path: {:?}",
+        path
+    );
+
+    let path = env.shortestpath(&env.set("4")?, &env.set("1")?).await?;
+    assert_eq!(path, TargetSet::new());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_distinct_paths() -> anyhow::Result<()> {
     let mut env = TestEnvBuilder::default();
@@ -405,3 +450,106 @@ async fn test_rdeps() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+fn inputs_fixture() -> TestEnv {
+    let mut env = TestEnvBuilder::default();
+    env.edge(1, 2);
+    env.edge(1, 3);
+    env.edge(2, 3);
+    env.inputs(
+        1,
+        &[
+            "root//bin/main.rs",
+            "root//bin/BUCK",
+            "root//bin/.hidden.rs",
+        ],
+    );
+    env.inputs(
+        2,
+        &[
+            "root//lib/api/service.proto",
+            "root//lib/api/service.rs",
+            "root//lib/Types.PROTO",
+        ],
+    );
+    env.inputs(3, &["root//lib/api/v2/extra.proto", "third//vendor/dep.rs"]);
+    env.build()
+}
+
+fn paths(files: &FileSet) -> Vec<String> {
+    files.iter().map(|p| p.to_string()).collect()
+}
+
+#[test]
+fn test_inputs_matching_extension() -> anyhow::Result<()> {
+    let env = inputs_fixture();
+    let targets = env.set("1,2,3")?;
+
+    assert_eq!(
+        vec![
+            "root//lib/api/service.proto",
+            "root//lib/Types.PROTO",
+            "root//lib/api/v2/extra.proto",
+        ],
+        paths(&targets.inputs_matching(&FileGlob::new("**/*.proto")?)?)
+    );
+    // Leading dots must be matched explicitly, like in `glob()`.
+    assert_eq!(
+        vec![
+            "root//bin/main.rs",
+            "root//lib/api/service.rs",
+            "third//vendor/dep.rs"
+        ],
+        paths(&targets.inputs_matching(&FileGlob::new("**/*.rs")?)?)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_inputs_matching_directory() -> anyhow::Result<()> {
+    let env = inputs_fixture();
+    let targets = env.set("1,2,3")?;
+
+    assert_eq!(
+        vec!["root//lib/api/service.proto", "root//lib/api/service.rs"],
+        paths(&targets.inputs_matching(&FileGlob::new("lib/api/*")?)?)
+    );
+    assert_eq!(
+        vec![
+            "root//lib/api/service.proto",
+            "root//lib/api/service.rs",
+            "root//lib/api/v2/extra.proto",
+        ],
+        paths(&targets.inputs_matching(&FileGlob::new("lib/api/**")?)?)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_inputs_matching_is_filtered_inputs() -> anyhow::Result<()> {
+    let env = inputs_fixture();
+    let targets = env.set("1,2,3")?;
+
+    for pattern in [
+        "**/*.proto",
+        "**/*.rs",
+        "lib/**",
+        "*/main.rs",
+        "**/BUCK",
+        "nothing",
+    ] {
+        let glob = FileGlob::new(pattern)?;
+        assert_eq!(
+            targets.inputs()?.filter_glob(&glob)?,
+            targets.inputs_matching(&glob)?,
+            "pattern `{}`",
+            pattern
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_invalid_file_glob() {
+    assert!(FileGlob::new("lib/[").is_err());
+}
@@ -53,6 +53,13 @@ pub enum QueryError {
         "Operation + requires either two set types, or one set and one string, got `{0}` and `{1}`"
     )]
     UnionIncompatibleTypes(&'static str, &'static str),
+    #[error("Invalid file glob `{0}`: {1}")]
+    InvalidFileGlob(String, String),
+    #[error("function `{function}` expects a single target, got {actual}")]
+    ExpectedSingleTarget {
+        function: &'static str,
+        actual: usize,
+    },
     /// Used to propagate up an inner error. The inner span will mark where the inner error was (which itself may be the
     /// propagation of another error). This error will end up in a Spanned that indicates where this error (the propagation) occurs.
     /// Since QueryError has an impl for `From<Spanned<QueryError>>`, just propagating inner eval errors via `?` will hit this case (and
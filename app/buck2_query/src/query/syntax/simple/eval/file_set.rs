@@ -21,6 +21,34 @@ use crate::query::environment::QueryTarget;
 use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::set::TargetSet;
 
+/// A glob over the cell relative path of files, e.g. `**/*.proto` or `foo/bar/**`, used to
+/// filter files as they are collected rather than afterwards.
+///
+/// Matching follows the same rules as `glob()` in build files: `*` does not match `/`, `**`
+/// matches any number of directories, a leading `.` must be matched explicitly, and matching is
+/// case insensitive.
+#[derive(Debug, Clone)]
+pub struct FileGlob(glob::Pattern);
+
+impl FileGlob {
+    const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+        require_literal_separator: true,
+        require_literal_leading_dot: true,
+        case_sensitive: false,
+    };
+
+    pub fn new(pattern: &str) -> anyhow::Result<Self> {
+        Ok(Self(glob::Pattern::new(pattern).map_err(|e| {
+            QueryError::InvalidFileGlob(pattern.to_owned(), e.to_string())
+        })?))
+    }
+
+    pub fn matches(&self, path: &CellPath) -> bool {
+        self.0
+            .matches_with(path.path().as_str(), Self::MATCH_OPTIONS)
+    }
+}
+
 /// An entry in a FileSet.
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Display, Allocative)]
 pub struct FileNode(pub CellPath);
@@ -41,6 +69,10 @@ impl FileSet {
         Self { files }
     }
 
+    pub fn filter_glob(&self, glob: &FileGlob) -> anyhow::Result<Self> {
+        self.filter(|node| Ok(glob.matches(&node.0)))
+    }
+
     pub(crate) fn filter_name(&self, regex: &str) -> anyhow::Result<Self> {
         let re = Regex::new(regex)?;
         self.filter(|node| Ok(re.is_match(&node.0.to_string())?))
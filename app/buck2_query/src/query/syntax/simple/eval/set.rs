@@ -18,6 +18,7 @@ use fancy_regex::RegexBuilder;
 use indexmap::IndexSet;
 
 use crate::query::environment::QueryTarget;
+use crate::query::syntax::simple::eval::file_set::FileGlob;
 use crate::query::syntax::simple::eval::file_set::FileNode;
 use crate::query::syntax::simple::eval::file_set::FileSet;
 use crate::query::syntax::simple::eval::label_indexed;
@@ -89,6 +90,20 @@ impl<T: QueryTarget> TargetSet<T> {
         Ok(FileSet::new(files))
     }
 
+    /// Like `inputs`, but only keeps the files matching `glob`, without collecting the others.
+    pub fn inputs_matching(&self, glob: &FileGlob) -> anyhow::Result<FileSet> {
+        let mut files = IndexSet::new();
+        for target in self.targets.iter() {
+            target.inputs_for_each(|file| {
+                if glob.matches(&file) {
+                    files.insert(FileNode(file));
+                }
+                anyhow::Ok(())
+            })?;
+        }
+        Ok(FileSet::new(files))
+    }
+
     pub fn union(&self, right: &TargetSet<T>) -> TargetSet<T> {
         let mut targets = LabelIndexedSet::new();
         for target in self.targets.iter() {
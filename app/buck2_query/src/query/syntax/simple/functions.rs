@@ -18,11 +18,14 @@ use buck2_query_derive::query_module;
 use buck2_query_parser::spanned::Spanned;
 use buck2_query_parser::BinaryOp;
 use buck2_query_parser::Expr;
+use dupe::Dupe;
 use gazebo::variants::VariantName;
 
 use crate::query::environment::QueryEnvironment;
+use crate::query::environment::QueryTarget;
 use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
+use crate::query::syntax::simple::eval::file_set::FileGlob;
 use crate::query::syntax::simple::eval::file_set::FileSet;
 use crate::query::syntax::simple::eval::set::TargetSet;
 use crate::query::syntax::simple::eval::values::QueryResult;
@@ -227,6 +230,19 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         Ok(self.implementation.somepath(env, &from, &to).await?.into())
     }
 
+    async fn shortestpath(
+        &self,
+        env: &Env,
+        from: TargetSet<Env::Target>,
+        to: TargetSet<Env::Target>,
+    ) -> QueryFuncResult<Env> {
+        Ok(self
+            .implementation
+            .shortestpath(env, &from, &to)
+            .await?
+            .into())
+    }
+
     /// The `attrfilter(attribute, value, targets)` operator evaluates the given target expression and filters the resulting build targets to those where the specified attribute contains the specified value.
     /// In this context, the term attribute refers to an argument in a build rule, such as name, headers, srcs, or deps.
     ///
@@ -336,8 +352,22 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         }
     }
 
-    async fn inputs(&self, targets: TargetSet<Env::Target>) -> QueryFuncResult<Env> {
-        Ok(self.implementation.inputs(&targets)?.into())
+    /// The `inputs(targets [, glob])` operator returns the source files of the given targets.
+    ///
+    /// If `glob` is given, only the files whose path within their cell matches it are returned,
+    /// which is cheaper than filtering the result afterwards. For example,
+    /// `buck2 uquery "inputs(deps('//foo:bar'), '**/*.proto')"` returns the `.proto` files of the
+    /// dependencies of `//foo:bar`. The glob follows the same rules as `glob()` in build files,
+    /// in particular it is case insensitive and `*` does not match `/`.
+    async fn inputs(
+        &self,
+        targets: TargetSet<Env::Target>,
+        glob: Option<String>,
+    ) -> QueryFuncResult<Env> {
+        match glob {
+            Some(glob) => Ok(self.implementation.inputs_matching(&targets, &glob)?.into()),
+            None => Ok(self.implementation.inputs(&targets)?.into()),
+        }
     }
 
     /// The `kind(regex, targets)` operator evaluates the specified target expression, `targets`, and returns the targets where the rule type matches the specified `regex`.
@@ -396,6 +426,20 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         Ok(self.implementation.testsof(env, &targets).await?.into())
     }
 
+    /// The `visible_to(target, targets)` operator returns the targets in `targets` that are allowed to depend on `target` according to its `visibility` attribute.
+    /// Targets in the same package as `target` can always depend on it.
+    ///
+    /// For example, `buck2 uquery "visible_to('//lib:core', '//app/...')"` returns the targets under `//app` that can depend on `//lib:core`, which helps to debug visibility errors.
+    ///
+    /// `target` must evaluate to a single target.
+    async fn visible_to(
+        &self,
+        target: TargetSet<Env::Target>,
+        targets: TargetSet<Env::Target>,
+    ) -> QueryFuncResult<Env> {
+        Ok(self.implementation.visible_to(&target, &targets)?.into())
+    }
+
     // These three functions are intentionally implemented as errors. They are only available within the context
     // of a deps functions 3rd parameter expr. When used in that context, the QueryFunctions will be augmented to
     // have non-erroring implementations.
@@ -562,6 +606,35 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
         Ok(env.somepath(from, to).await?)
     }
 
+    /// Find the shortest dependency chain from one target set to another.
+    ///
+    /// Unlike `somepath`, results are returned in order from down to up: the first node is in
+    /// `from`, each node depends on the next one, and the last node is in `to`.
+    /// Output formats that preserve order (like `--output-format=json`) render the chain as an
+    /// ordered list.
+    ///
+    /// If there are multiple shortest paths, which one is returned is unspecified. If there's no
+    /// path, return an empty set.
+    ///
+    /// # Example
+    ///
+    /// ```text
+    /// $ buck2 uquery 'shortestpath(fbcode//buck2:buck2, fbcode//buck2/app/buck2_node:buck2_node)'
+    ///
+    /// fbcode//buck2:buck2
+    /// fbcode//buck2/app/buck2:buck2-bin
+    /// fbcode//buck2/app/buck2_analysis:buck2_analysis
+    /// fbcode//buck2/app/buck2_node:buck2_node
+    /// ```
+    pub async fn shortestpath(
+        &self,
+        env: &Env,
+        from: &TargetSet<Env::Target>,
+        to: &TargetSet<Env::Target>,
+    ) -> Result<TargetSet<Env::Target>, QueryError> {
+        Ok(env.shortestpath(from, to).await?)
+    }
+
     pub fn attrfilter(
         &self,
         attr: &str,
@@ -642,6 +715,14 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
         targets.inputs()
     }
 
+    pub fn inputs_matching(
+        &self,
+        targets: &TargetSet<Env::Target>,
+        glob: &str,
+    ) -> anyhow::Result<FileSet> {
+        targets.inputs_matching(&FileGlob::new(glob)?)
+    }
+
     pub fn labels(
         &self,
         _attr: &str,
@@ -684,6 +765,29 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
         env.testsof(targets).await
     }
 
+    pub fn visible_to(
+        &self,
+        target: &TargetSet<Env::Target>,
+        targets: &TargetSet<Env::Target>,
+    ) -> Result<TargetSet<Env::Target>, QueryError> {
+        let target = match target.get_index(0) {
+            Some(target_node) if target.len() == 1 => target_node,
+            _ => {
+                return Err(QueryError::ExpectedSingleTarget {
+                    function: "visible_to",
+                    actual: target.len(),
+                });
+            }
+        };
+        let mut visible = TargetSet::new();
+        for candidate in targets.iter() {
+            if target.is_visible_to(candidate)? {
+                visible.insert_unique_unchecked(candidate.dupe());
+            }
+        }
+        Ok(visible)
+    }
+
     pub async fn testsof_with_default_target_platform(
         &self,
         env: &Env,
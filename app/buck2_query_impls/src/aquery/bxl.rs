@@ -126,6 +126,23 @@ impl BxlAqueryFunctions for BxlAqueryFunctionsImpl {
         })
         .await
     }
+    async fn shortestpath(
+        &self,
+        dice: &mut DiceComputations<'_>,
+        from: &TargetSet<ActionQueryNode>,
+        to: &TargetSet<ActionQueryNode>,
+    ) -> anyhow::Result<TargetSet<ActionQueryNode>> {
+        dice.with_linear_recompute(|dice| async move {
+            Ok(aquery_functions()
+                .shortestpath(
+                    &self.aquery_env(&self.aquery_delegate(&dice).await?).await?,
+                    from,
+                    to,
+                )
+                .await?)
+        })
+        .await
+    }
     async fn deps(
         &self,
         dice: &mut DiceComputations<'_>,
@@ -286,6 +303,27 @@ impl BxlAqueryFunctions for BxlAqueryFunctionsImpl {
         })
         .await
     }
+
+    async fn all_inputs(
+        &self,
+        dice: &mut DiceComputations<'_>,
+        targets: &TargetSet<ActionQueryNode>,
+    ) -> anyhow::Result<TargetSet<ActionQueryNode>> {
+        dice.with_linear_recompute(|dice| async move {
+            let query_val = special_aquery_functions()
+                .all_inputs(
+                    &self.aquery_env(&self.aquery_delegate(&dice).await?).await?,
+                    targets.clone(),
+                )
+                .await?;
+
+            match &query_val {
+                QueryValue::TargetSet(s) => Ok(s.clone()),
+                _ => unreachable!("all_inputs should always return target set"),
+            }
+        })
+        .await
+    }
 }
 
 pub(crate) fn init_new_bxl_aquery_functions() {
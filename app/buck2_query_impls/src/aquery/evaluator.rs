@@ -53,7 +53,7 @@ impl AqueryEvaluator<'_, '_> {
                     &literals,
                     &mut self.dice_query_delegate.ctx(),
                 )
-                .await;
+                .await?;
                 Ok(AqueryEnvironment::new(
                     self.dice_query_delegate.dupe(),
                     Arc::new(resolved_literals),
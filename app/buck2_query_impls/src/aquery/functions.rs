@@ -11,9 +11,12 @@ use std::fmt;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 
+use buck2_artifact::actions::key::ActionKey;
 use buck2_artifact::artifact::provide_outputs::ProvideActionKey;
 use buck2_build_api::actions::query::ActionQueryNode;
 use buck2_build_api::actions::query::ActionQueryNodeData;
+use buck2_build_api::actions::query::ActionQueryNodeRef;
+use buck2_query::query::environment::QueryTarget;
 use buck2_query::query::syntax::simple::eval::error::QueryError;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::eval::values::QueryValue;
@@ -23,6 +26,7 @@ use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use buck2_query::query::syntax::simple::functions::QueryFunctions;
 use buck2_query::query_module;
 use buck2_query_parser::BinaryOp;
+use indexmap::IndexSet;
 
 use crate::aquery::environment::AqueryEnvironment;
 
@@ -96,6 +100,31 @@ impl<'a> AqueryFunctions<'a> {
         Ok(nodes)
     }
 
+    /// Obtain the actions producing the inputs of the actions passed as input, i.e. the direct
+    /// deps of those actions. Inputs reached through transitive sets are included, down to the
+    /// leaves of the sets, since the action consumes all of them. The actions producing those
+    /// inputs are not traversed further: use `deps()` for that.
+    ///
+    /// Like `all_outputs`, this reports actions rather than files: source inputs are not
+    /// produced by any action, so they are not included. This operation only makes sense on
+    /// actions (target literals are ignored).
+    pub(crate) async fn all_inputs(
+        &self,
+        env: &AqueryEnvironment<'a>,
+        targets: TargetSet<ActionQueryNode>,
+    ) -> Result<QueryValue<ActionQueryNode>, QueryError> {
+        let action_keys = input_action_keys(targets.iter().flat_map(|node| node.deps()))?;
+
+        let nodes = futures::future::try_join_all(
+            action_keys
+                .into_iter()
+                .map(|key| env.delegate.get_node(key)),
+        )
+        .await?;
+
+        Ok(nodes.into_iter().collect::<TargetSet<_>>().into())
+    }
+
     /// Obtain all the actions declared within the analysis of a given target.
     ///
     /// This operation only makes sense on a target literal (it is a simple passthrough when passed
@@ -132,3 +161,85 @@ impl<'a> AqueryFunctions<'a> {
         Ok(res.into())
     }
 }
+
+/// The keys of the actions among `deps`, each once, in the order they are first seen.
+fn input_action_keys<'x>(
+    deps: impl IntoIterator<Item = &'x ActionQueryNodeRef>,
+) -> anyhow::Result<IndexSet<&'x ActionKey>> {
+    deps.into_iter().map(|dep| dep.require_action()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_artifact::actions::key::ActionKey;
+    use buck2_artifact::deferred::data::DeferredData;
+    use buck2_artifact::deferred::id::DeferredId;
+    use buck2_artifact::deferred::key::DeferredKey;
+    use buck2_build_api::actions::query::iter_action_inputs;
+    use buck2_build_api::actions::query::ActionInput;
+    use buck2_build_api::actions::query::ActionQueryNodeRef;
+    use buck2_build_api::actions::query::SetProjectionInputs;
+    use buck2_build_api::artifact_groups::TransitiveSetProjectionKey;
+    use buck2_core::base_deferred_key::BaseDeferredKey;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::target::label::label::TargetLabel;
+    use dupe::Dupe;
+
+    use crate::aquery::functions::input_action_keys;
+
+    fn deferred_key(id: u32) -> DeferredKey {
+        DeferredKey::Base(
+            BaseDeferredKey::TargetLabel(
+                TargetLabel::testing_parse("root//pkg:target")
+                    .configure(ConfigurationData::testing_new()),
+            ),
+            DeferredId::testing_new(id),
+        )
+    }
+
+    fn action(id: u32) -> ActionQueryNodeRef {
+        ActionQueryNodeRef::Action(ActionKey::unchecked_new(deferred_key(id)))
+    }
+
+    fn tset(
+        id: u32,
+        direct: Vec<ActionQueryNodeRef>,
+        children: Vec<SetProjectionInputs>,
+    ) -> SetProjectionInputs {
+        SetProjectionInputs::new(
+            TransitiveSetProjectionKey {
+                key: DeferredData::unchecked_new(deferred_key(id)),
+                projection: 0,
+            },
+            direct,
+            children,
+        )
+    }
+
+    #[test]
+    fn test_input_action_keys() -> anyhow::Result<()> {
+        // Source inputs are dropped when the action's inputs are converted, so only the actions
+        // producing generated inputs show up here.
+        let leaf = tset(100, vec![action(3), action(1)], Vec::new());
+        let first = vec![
+            ActionInput::ActionKey(action(1)),
+            ActionInput::IndirectInputs(tset(101, vec![action(2)], vec![leaf.dupe()])),
+        ];
+        let second = vec![
+            ActionInput::ActionKey(action(4)),
+            ActionInput::IndirectInputs(leaf),
+        ];
+
+        let keys =
+            input_action_keys(iter_action_inputs(&first).chain(iter_action_inputs(&second)))?;
+        let expected = [1, 2, 3, 4].map(action);
+        assert_eq!(
+            expected
+                .iter()
+                .map(|a| a.require_action())
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            keys.into_iter().collect::<Vec<_>>(),
+        );
+        Ok(())
+    }
+}
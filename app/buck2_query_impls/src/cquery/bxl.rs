@@ -132,6 +132,26 @@ impl BxlCqueryFunctions for BxlCqueryFunctionsImpl {
         .await
     }
 
+    async fn shortestpath(
+        &self,
+        dice: &mut DiceComputations<'_>,
+        from: &TargetSet<ConfiguredTargetNode>,
+        to: &TargetSet<ConfiguredTargetNode>,
+    ) -> anyhow::Result<TargetSet<ConfiguredTargetNode>> {
+        dice.with_linear_recompute(|dice| async move {
+            Ok(cquery_functions()
+                .shortestpath(
+                    &self
+                        .cquery_env(&self.setup_dice_query_delegate(&dice).await?, None)
+                        .await?,
+                    from,
+                    to,
+                )
+                .await?)
+        })
+        .await
+    }
+
     async fn owner(
         &self,
         dice: &mut DiceComputations<'_>,
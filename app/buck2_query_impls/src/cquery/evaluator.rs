@@ -11,6 +11,8 @@
 
 use std::sync::Arc;
 
+use anyhow::Context;
+use buck2_build_api::configure_targets::pattern_resolution_concurrency;
 use buck2_build_api::query::oneshot::CqueryOwnerBehavior;
 use buck2_common::events::HasEvents;
 use buck2_common::global_cfg_options::GlobalCfgOptions;
@@ -22,7 +24,6 @@ use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use dice::LinearRecomputeDiceComputations;
 use dupe::Dupe;
-use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use gazebo::prelude::*;
 
@@ -92,7 +93,7 @@ pub(crate) async fn preresolve_literals_and_build_universe(
         literals,
         &mut dice_query_delegate.ctx(),
     )
-    .await;
+    .await?;
     let universe = CqueryUniverse::build(&resolved_literals.literals()?)?;
     Ok((universe, resolved_literals))
 }
@@ -140,19 +141,28 @@ async fn resolve_literals_in_universe<L: AsRef<str>, U: AsRef<str>>(
     // TODO(cjhopman): Using the default resolution for recursive literals is inefficient.
     // If we can have a package-trie or cellpath-trie we can do the resolution directly
     // against the universe.
-    let resolution_futs: FuturesUnordered<_> = literals
-        .iter()
-        .map(|lit| async move {
+    let mut resolved: Vec<(usize, _)> =
+        futures::stream::iter(literals.iter().enumerate().map(|(index, lit)| async move {
             let lit = lit.as_ref();
             let result: anyhow::Result<_> = try {
-                let resolved_pattern = dice_query_delegate.resolve_target_patterns(&[lit]).await?;
+                let resolved_pattern = dice_query_delegate
+                    .resolve_target_patterns(&[lit])
+                    .await
+                    .with_context(|| format!("Error resolving literal `{}`", lit))?;
                 universe_ref.get(&resolved_pattern)
             };
 
-            (lit.to_owned(), result.map_err(buck2_error::Error::from))
-        })
-        .collect();
+            (
+                index,
+                (lit.to_owned(), result.map_err(buck2_error::Error::from)),
+            )
+        }))
+        .buffer_unordered(pattern_resolution_concurrency()?)
+        .collect()
+        .await;
+    // Keep the literals in the order they were given, so errors are reported deterministically.
+    resolved.sort_by_key(|(index, _)| *index);
+    let resolved = resolved.into_iter().map(|(_, resolved)| resolved).collect();
 
-    let resolved = resolution_futs.collect().await;
     Ok((universe, PreresolvedQueryLiterals::new(resolved)))
 }
@@ -106,6 +106,23 @@ impl BxlUqueryFunctions for BxlUqueryFunctionsImpl {
         })
         .await
     }
+    async fn shortestpath(
+        &self,
+        dice: &mut DiceComputations<'_>,
+        from: &TargetSet<TargetNode>,
+        to: &TargetSet<TargetNode>,
+    ) -> anyhow::Result<TargetSet<TargetNode>> {
+        dice.with_linear_recompute(|dice| async move {
+            Ok(uquery_functions()
+                .shortestpath(
+                    &self.uquery_env(&self.uquery_delegate(&dice).await?).await?,
+                    from,
+                    to,
+                )
+                .await?)
+        })
+        .await
+    }
     async fn deps(
         &self,
         dice: &mut DiceComputations<'_>,
@@ -12,6 +12,7 @@ use std::sync::Arc;
 
 use anyhow::Context;
 use async_trait::async_trait;
+use buck2_build_api::configure_targets::pattern_resolution_concurrency;
 use buck2_common::package_listing::dice::DicePackageListingResolver;
 use buck2_common::package_listing::resolver::PackageListingResolver;
 use buck2_common::pattern::resolve::ResolvedPattern;
@@ -51,6 +52,7 @@ use futures::stream::FuturesUnordered;
 use futures::FutureExt;
 use futures::StreamExt;
 use gazebo::prelude::*;
+use indexmap::IndexMap;
 use indexmap::IndexSet;
 use itertools::Itertools;
 use ref_cast::RefCast;
@@ -119,12 +121,13 @@ pub(crate) struct UqueryEnvironment<'c> {
 }
 
 pub(crate) struct PreresolvedQueryLiterals<T: QueryTarget> {
-    resolved_literals: HashMap<String, buck2_error::Result<TargetSet<T>>>,
+    /// Literals in the order they were given.
+    resolved_literals: IndexMap<String, buck2_error::Result<TargetSet<T>>>,
 }
 
 impl<T: QueryTarget> PreresolvedQueryLiterals<T> {
     pub(crate) fn new(
-        resolved_literals: HashMap<String, buck2_error::Result<TargetSet<T>>>,
+        resolved_literals: IndexMap<String, buck2_error::Result<TargetSet<T>>>,
     ) -> Self {
         Self { resolved_literals }
     }
@@ -133,16 +136,44 @@ impl<T: QueryTarget> PreresolvedQueryLiterals<T> {
         base: &dyn QueryLiterals<T>,
         literals: &[String],
         dice: &mut DiceComputations<'_>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::pre_resolve_with_concurrency(
+            base,
+            literals,
+            dice,
+            pattern_resolution_concurrency()?,
+        )
+        .await)
+    }
+
+    /// Resolves at most `concurrency` literals at a time. Results are kept in the order of
+    /// `literals`, regardless of the order resolutions finish in.
+    async fn pre_resolve_with_concurrency(
+        base: &dyn QueryLiterals<T>,
+        literals: &[String],
+        dice: &mut DiceComputations<'_>,
+        concurrency: usize,
     ) -> Self {
-        let resolved_literal_results = dice
-            .compute_join(literals.iter(), |ctx, lit| {
-                async move { (lit.to_owned(), base.eval_literals(&[lit], ctx).await) }.boxed()
+        let resolutions = dice.compute_many(literals.iter().map(|lit| {
+            DiceComputations::declare_closure(move |ctx| {
+                async move {
+                    let result = base
+                        .eval_literals(&[lit], ctx)
+                        .await
+                        .with_context(|| format!("Error resolving literal `{}`", lit));
+                    (lit.to_owned(), result.map_err(buck2_error::Error::from))
+                }
+                .boxed()
             })
+        }));
+        let mut resolved_literals: HashMap<_, _> = futures::stream::iter(resolutions)
+            .buffer_unordered(concurrency)
+            .collect()
             .await;
-        let mut resolved_literals = HashMap::new();
-        for (literal, result) in resolved_literal_results {
-            resolved_literals.insert(literal, result.map_err(buck2_error::Error::from));
-        }
+        let resolved_literals = literals
+            .iter()
+            .filter_map(|lit| resolved_literals.remove_entry(lit))
+            .collect();
         Self { resolved_literals }
     }
 
@@ -710,3 +741,208 @@ pub(crate) async fn get_transitive_loads<'c>(
 
     Ok(imports)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use assert_matches::assert_matches;
+    use async_trait::async_trait;
+    use buck2_core::bzl::ImportPath;
+    use buck2_core::target::label::label::TargetLabel;
+    use buck2_node::attrs::coerced_attr::CoercedAttr;
+    use buck2_node::attrs::internal::internal_attrs;
+    use buck2_node::attrs::internal::VISIBILITY_ATTRIBUTE_FIELD;
+    use buck2_node::nodes::unconfigured::testing::TargetNodeExt;
+    use buck2_node::nodes::unconfigured::TargetNode;
+    use buck2_node::rule_type::RuleType;
+    use buck2_node::rule_type::StarlarkRuleType;
+    use buck2_node::visibility::VisibilitySpecification;
+    use buck2_query::query::syntax::simple::eval::error::QueryError;
+    use buck2_query::query::syntax::simple::eval::set::TargetSet;
+    use buck2_query::query::syntax::simple::functions::DefaultQueryFunctions;
+    use dice::testing::DiceBuilder;
+    use dice::DiceComputations;
+    use dice::UserComputationData;
+    use dupe::Dupe;
+
+    use crate::uquery::environment::PreresolvedQueryLiterals;
+    use crate::uquery::environment::QueryLiterals;
+    use crate::uquery::environment::UqueryEnvironment;
+
+    /// Resolves literals to nothing, failing those starting with `fail`. Yields while resolving so
+    /// that concurrent resolutions overlap, and records how many were in flight at once.
+    #[derive(Default)]
+    struct CountingLiterals {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl QueryLiterals<TargetNode> for CountingLiterals {
+        async fn eval_literals(
+            &self,
+            literals: &[&str],
+            _dice: &mut DiceComputations<'_>,
+        ) -> anyhow::Result<TargetSet<TargetNode>> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            if literals.iter().any(|lit| lit.starts_with("fail")) {
+                return Err(anyhow::anyhow!("Package does not exist"));
+            }
+            Ok(TargetSet::new())
+        }
+    }
+
+    fn literals(literals: &[&str]) -> Vec<String> {
+        literals.iter().map(|lit| (*lit).to_owned()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_pre_resolve_attributes_errors_in_order() -> anyhow::Result<()> {
+        let mut dice = DiceBuilder::new()
+            .build(UserComputationData::new())?
+            .commit()
+            .await;
+        let base = CountingLiterals::default();
+        let literals = literals(&["root//a:a", "fail//b:b", "root//c:c", "fail//d:d"]);
+
+        let resolved =
+            PreresolvedQueryLiterals::pre_resolve_with_concurrency(&base, &literals, &mut dice, 2)
+                .await;
+
+        assert_eq!(
+            literals,
+            resolved
+                .resolved_literals
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+        // The first failing literal in input order is reported, with the literal that failed.
+        let err = format!("{:#}", resolved.literals().unwrap_err());
+        assert!(
+            err.contains("Error resolving literal `fail//b:b`"),
+            "{}",
+            err
+        );
+        assert!(err.contains("Package does not exist"), "{}", err);
+
+        let err = format!(
+            "{:#}",
+            resolved
+                .eval_literals(&["root//a:a", "fail//d:d"], &mut dice)
+                .await
+                .unwrap_err()
+        );
+        assert!(
+            err.contains("Error resolving literal `fail//d:d`"),
+            "{}",
+            err
+        );
+
+        assert!(
+            resolved
+                .eval_literals(&["root//a:a", "root//c:c"], &mut dice)
+                .await?
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pre_resolve_is_concurrent_and_bounded() -> anyhow::Result<()> {
+        let mut dice = DiceBuilder::new()
+            .build(UserComputationData::new())?
+            .commit()
+            .await;
+        let base = CountingLiterals::default();
+        let literals: Vec<String> = (0..10).map(|i| format!("root//:t{}", i)).collect();
+
+        let resolved =
+            PreresolvedQueryLiterals::pre_resolve_with_concurrency(&base, &literals, &mut dice, 3)
+                .await;
+
+        assert_eq!(10, resolved.resolved_literals.len());
+        assert!(resolved.literals()?.is_empty());
+        assert_eq!(3, base.max_in_flight.load(Ordering::SeqCst));
+        assert_eq!(0, base.in_flight.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    fn node_with_visibility(label: &str, visibility: Option<&[&str]>) -> TargetNode {
+        let rule_type = RuleType::Starlark(Arc::new(StarlarkRuleType {
+            import_path: ImportPath::testing_new("root//:rules.bzl"),
+            name: "foo_library".to_owned(),
+        }));
+        let attrs = match visibility {
+            Some(visibility) => vec![(
+                VISIBILITY_ATTRIBUTE_FIELD,
+                internal_attrs()
+                    .get(VISIBILITY_ATTRIBUTE_FIELD)
+                    .unwrap()
+                    .clone(),
+                CoercedAttr::Visibility(VisibilitySpecification::testing_parse(visibility)),
+            )],
+            None => Vec::new(),
+        };
+        TargetNode::testing_new(TargetLabel::testing_parse(label), rule_type, attrs)
+    }
+
+    fn labels(targets: &TargetSet<TargetNode>) -> Vec<String> {
+        targets.iter().map(|t| t.label().to_string()).collect()
+    }
+
+    #[test]
+    fn test_visible_to() -> anyhow::Result<()> {
+        let functions = DefaultQueryFunctions::<UqueryEnvironment>::new();
+        let restricted = node_with_visibility("root//lib:core", Some(&["root//app/allowed/..."]));
+        let public = node_with_visibility("root//lib:public", Some(&["PUBLIC"]));
+        let private = node_with_visibility("root//lib:private", None);
+
+        let candidates = TargetSet::from_iter([
+            node_with_visibility("root//app/allowed:a", None),
+            node_with_visibility("root//app/allowed/nested:b", None),
+            node_with_visibility("root//app/other:c", None),
+            node_with_visibility("root//lib:sibling", None),
+        ]);
+        let visible_to = |target: &TargetNode| {
+            functions.visible_to(&TargetSet::from_iter([target.dupe()]), &candidates)
+        };
+
+        assert_eq!(
+            vec![
+                "root//app/allowed:a",
+                "root//app/allowed/nested:b",
+                "root//lib:sibling"
+            ],
+            labels(&visible_to(&restricted)?)
+        );
+        assert_eq!(labels(&candidates), labels(&visible_to(&public)?));
+        // Targets are visible to their package by default.
+        assert_eq!(vec!["root//lib:sibling"], labels(&visible_to(&private)?));
+
+        assert_matches!(
+            functions.visible_to(
+                &TargetSet::from_iter([restricted.dupe(), public.dupe()]),
+                &candidates
+            ),
+            Err(QueryError::ExpectedSingleTarget {
+                function: "visible_to",
+                actual: 2
+            })
+        );
+        assert_matches!(
+            functions.visible_to(&TargetSet::new(), &candidates),
+            Err(QueryError::ExpectedSingleTarget { actual: 0, .. })
+        );
+        Ok(())
+    }
+}
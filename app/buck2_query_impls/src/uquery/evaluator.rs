@@ -51,7 +51,7 @@ impl UqueryEvaluator<'_, '_> {
                     &literals,
                     &mut self.dice_query_delegate.ctx(),
                 )
-                .await;
+                .await?;
                 Ok(UqueryEnvironment::new(
                     &self.dice_query_delegate,
                     Arc::new(resolved_literals),
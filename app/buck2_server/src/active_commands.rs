@@ -10,6 +10,7 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Instant;
 
 use buck2_cli_proto::ClientContext;
 use buck2_event_observer::dice_state::DiceState;
@@ -20,6 +21,8 @@ use buck2_event_observer::span_tracker::Roots;
 use buck2_events::dispatch::EventDispatcher;
 use buck2_events::span::SpanId;
 use buck2_events::BuckEvent;
+use buck2_server_ctx::concurrency::format_argv;
+use buck2_server_ctx::concurrency::CONCURRENT_COMMAND_PROGRESS;
 use buck2_wrapper_common::invocation_id::TraceId;
 use dupe::Dupe;
 use once_cell::sync::Lazy;
@@ -60,6 +63,17 @@ pub fn broadcast_shutdown(shutdown: &buck2_data::DaemonShutdown) {
     }
 }
 
+fn concurrent_command_progress(
+    trace_id: &TraceId,
+) -> Option<buck2_data::ConcurrentCommandProgress> {
+    let state = ACTIVE_COMMANDS.lock().get(trace_id)?.state.dupe();
+    Some(state.progress(trace_id, Instant::now()))
+}
+
+pub(crate) fn init_concurrent_command_progress() {
+    CONCURRENT_COMMAND_PROGRESS.init(concurrent_command_progress);
+}
+
 /// Allows interactions with commands found via active_commands().
 #[derive(Clone, Dupe)]
 pub struct ActiveCommandHandle {
@@ -100,9 +114,11 @@ impl Drop for ActiveCommandDropGuard {
 
 /// A handle to the stats for this command. We use this to broadcast state about this command.
 pub struct ActiveCommandState {
-    #[allow(unused)]
+    /// Sanitized, so it can be shown to other clients.
     pub argv: Vec<String>,
 
+    start: Instant,
+
     spans: Mutex<SpansSnapshot>,
 }
 
@@ -114,9 +130,59 @@ impl ActiveCommandState {
     fn new(argv: Vec<String>) -> Self {
         Self {
             argv,
+            start: Instant::now(),
             spans: Mutex::new(SpansSnapshot::default()),
         }
     }
+
+    /// What this command is doing, for the commands waiting for it.
+    fn progress(&self, trace_id: &TraceId, now: Instant) -> buck2_data::ConcurrentCommandProgress {
+        let spans = self.spans();
+        buck2_data::ConcurrentCommandProgress {
+            trace_id: trace_id.to_string(),
+            cmd_args: format_argv(&self.argv),
+            elapsed: now.saturating_duration_since(self.start).try_into().ok(),
+            phase: spans.phase.to_proto() as i32,
+            completed: spans.closed,
+            remaining: spans.open + spans.pending,
+        }
+    }
+}
+
+/// The furthest a command got, going by the spans it started.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Default, Copy, Clone, Dupe)]
+pub enum CommandPhase {
+    #[default]
+    Unknown,
+    Loading,
+    Analysis,
+    Execution,
+}
+
+impl CommandPhase {
+    fn of_span(event: &BuckEvent) -> Option<CommandPhase> {
+        use buck2_data::span_start_event::Data;
+
+        match event.span_start_event()?.data.as_ref()? {
+            Data::Load(..) | Data::LoadPackage(..) => Some(CommandPhase::Loading),
+            Data::Analysis(..) | Data::BxlExecution(..) => Some(CommandPhase::Analysis),
+            Data::ActionExecution(..)
+            | Data::FinalMaterialization(..)
+            | Data::TestDiscovery(..)
+            | Data::TestStart(..)
+            | Data::InstallEventInfo(..) => Some(CommandPhase::Execution),
+            _ => None,
+        }
+    }
+
+    fn to_proto(self) -> buck2_data::CommandPhase {
+        match self {
+            CommandPhase::Unknown => buck2_data::CommandPhase::Unknown,
+            CommandPhase::Loading => buck2_data::CommandPhase::Loading,
+            CommandPhase::Analysis => buck2_data::CommandPhase::Analysis,
+            CommandPhase::Execution => buck2_data::CommandPhase::Execution,
+        }
+    }
 }
 
 #[derive(PartialEq, Debug, Default, Copy, Clone, Dupe)]
@@ -124,6 +190,7 @@ pub struct SpansSnapshot {
     pub open: u64,
     pub closed: u64,
     pub pending: u64,
+    pub phase: CommandPhase,
 }
 
 /// A wrapper around ActiveCommandState that allows 1 client to write to it.
@@ -133,6 +200,7 @@ pub struct ActiveCommandStateWriter {
     non_roots: HashSet<SpanId>,
     dice_state: DiceState,
     closed: u64,
+    phase: CommandPhase,
     shared: Arc<ActiveCommandState>,
 }
 
@@ -143,6 +211,7 @@ impl ActiveCommandStateWriter {
             non_roots: HashSet::new(),
             dice_state: DiceState::new(),
             closed: 0,
+            phase: CommandPhase::default(),
             shared,
         }
     }
@@ -163,6 +232,13 @@ impl ActiveCommandStateWriter {
                     return;
                 }
 
+                if let Some(phase) = CommandPhase::of_span(buck_event) {
+                    if phase > self.phase {
+                        self.phase = phase;
+                        changed = true;
+                    }
+                }
+
                 let is_root = buck_event.parent_id().map_or(true, |id| {
                     !self.roots.contains(id) && !self.non_roots.contains(&id)
                 });
@@ -210,6 +286,7 @@ impl ActiveCommandStateWriter {
                 open,
                 closed: self.closed,
                 pending,
+                phase: self.phase,
             };
         }
     }
@@ -275,6 +352,7 @@ impl ActiveCommand {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
     use std::time::SystemTime;
 
     use super::*;
@@ -304,7 +382,8 @@ mod tests {
             SpansSnapshot {
                 open: 1,
                 closed: 0,
-                pending: 0
+                pending: 0,
+                phase: CommandPhase::Analysis,
             }
         );
 
@@ -324,7 +403,8 @@ mod tests {
             SpansSnapshot {
                 open: 1,
                 closed: 0,
-                pending: 0
+                pending: 0,
+                phase: CommandPhase::Analysis,
             }
         );
 
@@ -345,7 +425,8 @@ mod tests {
             SpansSnapshot {
                 open: 1,
                 closed: 0,
-                pending: 0
+                pending: 0,
+                phase: CommandPhase::Analysis,
             }
         );
 
@@ -366,7 +447,8 @@ mod tests {
             SpansSnapshot {
                 open: 0,
                 closed: 1,
-                pending: 0
+                pending: 0,
+                phase: CommandPhase::Analysis,
             }
         );
 
@@ -403,8 +485,71 @@ mod tests {
             SpansSnapshot {
                 open: 0,
                 closed: 1,
-                pending: 2
+                pending: 2,
+                phase: CommandPhase::Analysis,
             }
         );
     }
+
+    #[test]
+    fn test_concurrent_command_progress() {
+        let state = Arc::new(ActiveCommandState::new(vec![
+            "/usr/local/bin/buck2".to_owned(),
+            "build".to_owned(),
+            "//foo/...".to_owned(),
+        ]));
+        let mut writer = ActiveCommandStateWriter::new(state.dupe());
+        let trace = TraceId::new();
+        let start = |span, data: buck2_data::span_start_event::Data| {
+            BuckEvent::new(
+                SystemTime::now(),
+                trace.clone(),
+                Some(span),
+                None,
+                buck2_data::SpanStartEvent { data: Some(data) }.into(),
+            )
+        };
+
+        let progress = state.progress(&trace, state.start + Duration::from_secs(252));
+        assert_eq!("buck2 build //foo/...", progress.cmd_args);
+        assert_eq!(trace.to_string(), progress.trace_id);
+        assert_eq!(
+            Some(Duration::from_secs(252).try_into().unwrap()),
+            progress.elapsed
+        );
+        assert_eq!(buck2_data::CommandPhase::Unknown as i32, progress.phase);
+
+        let load = SpanId::next();
+        writer.peek_event(&start(
+            load,
+            buck2_data::LoadBuildFileStart::default().into(),
+        ));
+        writer.peek_event(&start(
+            SpanId::next(),
+            buck2_data::AnalysisStart::default().into(),
+        ));
+        let progress = state.progress(&trace, Instant::now());
+        assert_eq!(buck2_data::CommandPhase::Analysis as i32, progress.phase);
+        assert_eq!((0, 2), (progress.completed, progress.remaining));
+
+        // Loading after analysis started doesn't move the command back.
+        writer.peek_event(&BuckEvent::new(
+            SystemTime::now(),
+            trace.clone(),
+            Some(load),
+            None,
+            buck2_data::SpanEndEvent {
+                data: Some(buck2_data::LoadBuildFileEnd::default().into()),
+                ..Default::default()
+            }
+            .into(),
+        ));
+        writer.peek_event(&start(
+            SpanId::next(),
+            buck2_data::LoadBuildFileStart::default().into(),
+        ));
+        let progress = state.progress(&trace, Instant::now());
+        assert_eq!(buck2_data::CommandPhase::Analysis as i32, progress.phase);
+        assert_eq!((1, 2), (progress.completed, progress.remaining));
+    }
 }
@@ -59,15 +59,35 @@ impl ServerCommandTemplate for CleanStaleServerCommand {
                     .as_deferred_materializer_extension()
                     .context("Deferred materializer is not in use")?;
 
-                let keep_since_time = Utc
-                    .timestamp_opt(self.req.keep_since_time, 0)
-                    .single()
-                    .context("Invalid timestamp")?;
+                let mut response = match self.req.keep_since_time {
+                    Some(keep_since_time) => {
+                        let keep_since_time = Utc
+                            .timestamp_opt(keep_since_time, 0)
+                            .single()
+                            .context("Invalid timestamp")?;
 
-                extension
-                    .clean_stale_artifacts(keep_since_time, self.req.dry_run, self.req.tracked_only)
-                    .await
-                    .context("Failed to clean stale artifacts.")
+                        extension
+                            .clean_stale_artifacts(
+                                keep_since_time,
+                                self.req.dry_run,
+                                self.req.tracked_only,
+                            )
+                            .await
+                            .context("Failed to clean stale artifacts.")?
+                    }
+                    None => buck2_cli_proto::CleanStaleResponse::default(),
+                };
+
+                if self.req.compact_db {
+                    response.compaction = Some(
+                        extension
+                            .compact_db()
+                            .await
+                            .context("Failed to compact materializer state db.")?,
+                    );
+                }
+
+                anyhow::Ok(response)
             })
             .await
     }
@@ -21,6 +21,8 @@ use buck2_build_api::actions::execute::dice_data::SetCommandExecutor;
 use buck2_build_api::actions::execute::dice_data::SetReClient;
 use buck2_build_api::actions::impls::run_action_knobs::HasRunActionKnobs;
 use buck2_build_api::actions::impls::run_action_knobs::RunActionKnobs;
+use buck2_build_api::build::cache_attribution::CacheAttributionCollector;
+use buck2_build_api::build::cache_attribution::SetCacheAttribution;
 use buck2_build_api::build::HasCreateUnhashedSymlinkLock;
 use buck2_build_api::build_signals::create_build_signals;
 use buck2_build_api::build_signals::BuildSignalsInstaller;
@@ -32,6 +34,8 @@ use buck2_build_signals::CriticalPathBackendName;
 use buck2_build_signals::HasCriticalPathBackend;
 use buck2_cli_proto::client_context::HostArchOverride;
 use buck2_cli_proto::client_context::HostPlatformOverride;
+use buck2_cli_proto::client_context::PreemptibleWhen as GrpcPreemptibleWhen;
+use buck2_cli_proto::client_context::ValidateVisibility as GrpcValidateVisibility;
 use buck2_cli_proto::common_build_options::ExecutionStrategy;
 use buck2_cli_proto::ClientContext;
 use buck2_cli_proto::CommonBuildOptions;
@@ -39,6 +43,7 @@ use buck2_common::dice::cells::HasCellResolver;
 use buck2_common::dice::cycles::CycleDetectorAdapter;
 use buck2_common::dice::cycles::PairDiceCycleDetector;
 use buck2_common::dice::data::HasIoProvider;
+use buck2_common::http::EventDispatcherDownloadProgress;
 use buck2_common::http::SetHttpClient;
 use buck2_common::invocation_paths::InvocationPaths;
 use buck2_common::io::trace::TracingIoProvider;
@@ -48,6 +53,10 @@ use buck2_common::legacy_configs::dice::HasLegacyConfigs;
 use buck2_common::legacy_configs::key::BuckconfigKeyRef;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_common::legacy_configs::LegacyConfigCmdArg;
+use buck2_common::package_listing::limits::PackageListingBudget;
+use buck2_common::package_listing::limits::SetPackageListingBudget;
+use buck2_common::target_progress::SetTargetProgress;
+use buck2_common::target_progress::TargetProgress;
 use buck2_configured::calculation::ConfiguredGraphCycleDescriptor;
 use buck2_core::async_once_cell::AsyncOnceCell;
 use buck2_core::execution_types::executor_config::CommandExecutorConfig;
@@ -72,6 +81,7 @@ use buck2_execute::execute::blocking::SetBlockingExecutor;
 use buck2_execute::knobs::ExecutorGlobalKnobs;
 use buck2_execute::materialize::materializer::Materializer;
 use buck2_execute::materialize::materializer::SetMaterializer;
+use buck2_execute::materialize::physical_roots::PhysicalRoots;
 use buck2_execute::re::client::RemoteExecutionClient;
 use buck2_execute::re::manager::ReConnectionHandle;
 use buck2_execute::re::manager::ReConnectionObserver;
@@ -83,6 +93,7 @@ use buck2_file_watcher::mergebase::SetMergebase;
 use buck2_forkserver::client::ForkserverClient;
 use buck2_futures::cancellation::ExplicitCancellationContext;
 use buck2_http::HttpClient;
+use buck2_http::HttpVersionPreference;
 use buck2_interpreter::dice::starlark_debug::SetStarlarkDebugger;
 use buck2_interpreter::dice::starlark_profiler::StarlarkProfilerConfiguration;
 use buck2_interpreter::extra::xcode::XcodeVersionInfo;
@@ -92,8 +103,10 @@ use buck2_interpreter::prelude_path::prelude_path;
 use buck2_interpreter_for_build::interpreter::configuror::BuildInterpreterConfiguror;
 use buck2_interpreter_for_build::interpreter::cycles::LoadCycleDescriptor;
 use buck2_interpreter_for_build::interpreter::interpreter_setup::setup_interpreter;
+use buck2_interpreter_for_build::nodes::validate_visibility::ValidateVisibility;
 use buck2_server_ctx::concurrency::DiceDataProvider;
 use buck2_server_ctx::concurrency::DiceUpdater;
+use buck2_server_ctx::concurrency::PreemptibleWhen;
 use buck2_server_ctx::ctx::DiceAccessor;
 use buck2_server_ctx::ctx::PrivateStruct;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
@@ -183,6 +196,8 @@ pub struct ServerCommandContext<'a> {
 
     record_target_call_stacks: bool,
     skip_targets_with_duplicate_names: bool,
+    /// `None` when not set on the command line.
+    validate_visibility: Option<ValidateVisibility>,
     disable_starlark_types: bool,
     unstable_typecheck: bool,
 
@@ -200,6 +215,9 @@ pub struct ServerCommandContext<'a> {
     /// dropped.
     heartbeat_guard_handle: Option<HeartbeatGuard>,
 
+    /// Target counts for this command, reported in heartbeat snapshots.
+    target_progress: Arc<TargetProgress>,
+
     /// Daemon uuid passed in from the client side to detect nested invocation.
     pub(crate) daemon_uuid_from_client: Option<String>,
 
@@ -212,6 +230,8 @@ pub struct ServerCommandContext<'a> {
     cancellations: &'a ExplicitCancellationContext,
 
     exit_when_different_state: bool,
+
+    preemptible: PreemptibleWhen,
 }
 
 impl<'a> ServerCommandContext<'a> {
@@ -287,8 +307,13 @@ impl<'a> ServerCommandContext<'a> {
             .find(|m| m.key == "id")
             .map(|m| m.value.clone());
 
-        let heartbeat_guard_handle =
-            HeartbeatGuard::new(base_context.events.dupe(), snapshot_collector);
+        let target_progress = TargetProgress::new();
+
+        let heartbeat_guard_handle = HeartbeatGuard::new(
+            base_context.events.dupe(),
+            snapshot_collector,
+            target_progress.dupe(),
+        );
 
         let config_overrides = get_legacy_config_args(&client_context.config_overrides)?;
 
@@ -319,15 +344,27 @@ impl<'a> ServerCommandContext<'a> {
             cell_configs_loader,
             record_target_call_stacks: client_context.target_call_stacks,
             skip_targets_with_duplicate_names: client_context.skip_targets_with_duplicate_names,
+            validate_visibility: match client_context.validate_visibility() {
+                GrpcValidateVisibility::Unset => None,
+                GrpcValidateVisibility::Off => Some(ValidateVisibility::Off),
+                GrpcValidateVisibility::Warn => Some(ValidateVisibility::Warn),
+                GrpcValidateVisibility::Strict => Some(ValidateVisibility::Strict),
+            },
             disable_starlark_types: client_context.disable_starlark_types,
             unstable_typecheck: client_context.unstable_typecheck,
             heartbeat_guard_handle: Some(heartbeat_guard_handle),
+            target_progress,
             daemon_uuid_from_client: client_context.daemon_uuid.clone(),
             command_name: client_context.command_name.clone(),
             sanitized_argv: client_context.sanitized_argv.clone(),
             debugger_handle,
             cancellations,
             exit_when_different_state: client_context.exit_when_different_state,
+            preemptible: match client_context.preemptible() {
+                GrpcPreemptibleWhen::Never => PreemptibleWhen::Never,
+                GrpcPreemptibleWhen::OnDifferentState => PreemptibleWhen::OnDifferentState,
+                GrpcPreemptibleWhen::Always => PreemptibleWhen::Always,
+            },
         })
     }
 
@@ -412,12 +449,16 @@ impl<'a> ServerCommandContext<'a> {
                 .as_ref()
                 .map_or(false, |opts| opts.keep_going),
             http_client: self.base_context.daemon.http_client.dupe(),
+            target_progress: self.target_progress.dupe(),
             paranoid: self.base_context.daemon.paranoid.dupe(),
             spawner: self.base_context.spawner.dupe(),
             materialize_failed_inputs: self
                 .build_options
                 .as_ref()
                 .map_or(false, |opts| opts.materialize_failed_inputs),
+            cache_attribution: self.build_options.as_ref().map_or(false, |opts| {
+                opts.unstable_print_build_report && opts.unstable_include_cache_attribution
+            }),
         }
     }
 
@@ -443,6 +484,7 @@ impl<'a> ServerCommandContext<'a> {
             disable_starlark_types: self.disable_starlark_types,
             unstable_typecheck: self.unstable_typecheck,
             skip_targets_with_duplicate_names: self.skip_targets_with_duplicate_names,
+            validate_visibility: self.validate_visibility,
             record_target_call_stacks: self.record_target_call_stacks,
         })
     }
@@ -481,7 +523,7 @@ impl CellConfigLoader {
                     // If there is a previous command and --reuse-current-config is set, then the old config is used, ignoring any overrides.
                     if dice_ctx.is_cell_resolver_key_set().await?
                         && dice_ctx.is_injected_legacy_configs_key_set().await?
-                        && dice_ctx.is_injected_legacy_config_override_key_set().await?
+                        && dice_ctx.is_injected_legacy_config_overlay_key_set().await?
                     {
                         if !self.config_overrides.is_empty() {
                             warn!(
@@ -489,12 +531,16 @@ impl CellConfigLoader {
                                 truncate_container(self.config_overrides.iter().map(|o| o.to_string()), 200),
                             );
                         }
+                        let cell_resolver = dice_ctx.get_cell_resolver().await?;
+                        let base_configs_by_name = dice_ctx.get_injected_legacy_configs().await?;
+                        let overlay = dice_ctx.get_injected_legacy_config_overlay().await?;
                         return buck2_error::Ok(BuckConfigBasedCellsStatus {
                             cells_and_configs: BuckConfigBasedCells {
-                                cell_resolver: dice_ctx.get_cell_resolver().await?,
-                                configs_by_name: dice_ctx.get_injected_legacy_configs().await?,
+                                configs_by_name: base_configs_by_name.with_overlay(&overlay, &cell_resolver)?,
+                                base_configs_by_name,
+                                cell_resolver,
                                 config_paths: HashSet::new(),
-                                resolved_args: dice_ctx.get_injected_legacy_config_overrides().await?,
+                                overlay,
                             },
                             new_configs: false,
                         });
@@ -508,9 +554,13 @@ impl CellConfigLoader {
                 let cells_and_configs = BuckConfigBasedCells::parse_with_config_args(&self.project_root, &self.config_overrides, &self.working_dir)
                     .map_err(buck2_error::Error::from)?;
 
-                let new_configs = if dice_ctx.is_injected_legacy_configs_key_set().await? {
+                let new_configs = if dice_ctx.is_injected_legacy_configs_key_set().await?
+                    && dice_ctx.is_injected_legacy_config_overlay_key_set().await?
+                {
                     let injected_legacy_configs = dice_ctx.get_injected_legacy_configs().await?;
-                    !injected_legacy_configs.compare(&cells_and_configs.configs_by_name)
+                    let injected_overlay = dice_ctx.get_injected_legacy_config_overlay().await?;
+                    !injected_legacy_configs.compare(&cells_and_configs.base_configs_by_name)
+                        || injected_overlay.digest() != cells_and_configs.overlay.digest()
                 } else {
                     true
                 };
@@ -543,9 +593,11 @@ struct DiceCommandDataProvider {
     starlark_debugger: Option<BuckStarlarkDebuggerHandle>,
     keep_going: bool,
     http_client: HttpClient,
+    target_progress: Arc<TargetProgress>,
     paranoid: Option<ParanoidDownloader>,
     spawner: Arc<BuckSpawner>,
     materialize_failed_inputs: bool,
+    cache_attribution: bool,
 }
 
 #[async_trait]
@@ -672,6 +724,10 @@ impl DiceDataProvider for DiceCommandDataProvider {
             })?
             .unwrap_or(CriticalPathBackendName::Default);
 
+        let project_root = ctx.global_data().get_io_provider().project_root().dupe();
+        // Resolved once per command rather than for every output that's hashed.
+        let physical_roots = PhysicalRoots::detect(&project_root, &self.buck_out_dir)?;
+
         set_fallback_executor_config(&mut data.data, self.executor_config.dupe());
         data.set_re_client(self.re_connection.get_client());
         data.set_command_executor(Box::new(CommandExecutorFactory::new(
@@ -686,16 +742,18 @@ impl DiceDataProvider for DiceCommandDataProvider {
             self.forkserver.dupe(),
             self.skip_cache_read,
             self.skip_cache_write,
-            ctx.global_data()
-                .get_io_provider()
-                .project_root()
-                .to_owned(),
+            project_root.dupe(),
+            physical_roots,
             worker_pool,
             self.paranoid.dupe(),
             self.materialize_failed_inputs,
         )));
         data.set_blocking_executor(self.blocking_executor.dupe());
-        data.set_http_client(self.http_client.dupe());
+        data.set_http_client(
+            self.http_client
+                .with_download_progress(EventDispatcherDownloadProgress::new(self.events.dupe())),
+        );
+        data.set_target_progress(self.target_progress.dupe());
         data.set_materializer(self.materializer.dupe());
         data.set_build_signals(self.build_signals.build_signals.dupe());
         data.set_run_action_knobs(run_action_knobs);
@@ -703,6 +761,10 @@ impl DiceDataProvider for DiceCommandDataProvider {
         data.set_starlark_debugger_handle(self.starlark_debugger.clone().map(|v| Box::new(v) as _));
         data.set_keep_going(self.keep_going);
         data.set_critical_path_backend(critical_path_backend);
+        data.set_package_listing_budget(Arc::new(PackageListingBudget::from_env()?));
+        if self.cache_attribution {
+            data.set_cache_attribution(Arc::new(CacheAttributionCollector::default()));
+        }
         data.spawner = self.spawner.dupe();
 
         let tags = vec![
@@ -740,6 +802,7 @@ struct DiceCommandUpdater {
     unstable_typecheck: bool,
     record_target_call_stacks: bool,
     skip_targets_with_duplicate_names: bool,
+    validate_visibility: Option<ValidateVisibility>,
 }
 
 #[async_trait]
@@ -757,10 +820,23 @@ impl DiceUpdater for DiceCommandUpdater {
             .cells_and_configs(&mut ctx.existing_state().await.clone())
             .await?;
         let cell_resolver = cells_and_configs.cell_resolver;
-        let legacy_configs = cells_and_configs.configs_by_name;
         // TODO(cjhopman): The CellResolver and the legacy configs shouldn't be leaves on the graph. This should
         // just be setting the config overrides and host platform override as leaves on the graph.
 
+        let validate_visibility = match self.validate_visibility {
+            Some(validate_visibility) => validate_visibility,
+            None => cells_and_configs
+                .configs_by_name
+                .get(cell_resolver.root_cell())
+                .context("No config for root cell")?
+                .parse(BuckconfigKeyRef {
+                    section: "buck2",
+                    property: "validate_visibility",
+                })?
+                .unwrap_or_default(),
+        };
+        let legacy_configs = cells_and_configs.base_configs_by_name;
+
         let configuror = BuildInterpreterConfiguror::new(
             Some(prelude_path(&cell_resolver)?),
             self.interpreter_platform,
@@ -768,6 +844,7 @@ impl DiceUpdater for DiceCommandUpdater {
             self.interpreter_xcode_version.clone(),
             self.record_target_call_stacks,
             self.skip_targets_with_duplicate_names,
+            validate_visibility,
             None,
             // New interner for each transaction.
             Arc::new(ConcurrentTargetLabelInterner::default()),
@@ -783,7 +860,7 @@ impl DiceUpdater for DiceCommandUpdater {
             cell_resolver,
             configuror,
             legacy_configs,
-            cells_and_configs.resolved_args,
+            cells_and_configs.overlay,
             self.starlark_profiler_instrumentation_override.dupe(),
             self.disable_starlark_types,
             self.unstable_typecheck,
@@ -847,6 +924,7 @@ impl<'a> ServerCommandContextTrait for ServerCommandContext<'a> {
             is_nested_invocation,
             sanitized_argv: self.sanitized_argv.clone(),
             exit_when_different_state: self.exit_when_different_state,
+            preemptible: self.preemptible,
             build_signals: deferred_build_signals,
         })
     }
@@ -902,9 +980,15 @@ impl<'a> ServerCommandContextTrait for ServerCommandContext<'a> {
 
         metadata.insert(
             "http_versions".to_owned(),
-            match self.base_context.daemon.http_client.http2() {
-                true => "1,2",
-                false => "1",
+            match self
+                .base_context
+                .daemon
+                .http_client
+                .http_version_preference()
+            {
+                HttpVersionPreference::Auto => "1,2",
+                HttpVersionPreference::Http1Only => "1",
+                HttpVersionPreference::Http2Only => "2",
             }
             .to_owned(),
         );
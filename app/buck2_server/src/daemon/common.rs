@@ -39,6 +39,7 @@ use buck2_execute::execute::prepared::PreparedCommandOptionalExecutor;
 use buck2_execute::execute::request::ExecutorPreference;
 use buck2_execute::knobs::ExecutorGlobalKnobs;
 use buck2_execute::materialize::materializer::Materializer;
+use buck2_execute::materialize::physical_roots::PhysicalRoots;
 use buck2_execute::re::manager::ReConnectionHandle;
 use buck2_execute_impl::executors::action_cache::ActionCacheChecker;
 use buck2_execute_impl::executors::action_cache::RemoteDepFileCacheChecker;
@@ -85,6 +86,7 @@ pub struct CommandExecutorFactory {
     skip_cache_read: bool,
     skip_cache_write: bool,
     project_root: ProjectRoot,
+    physical_roots: PhysicalRoots,
     worker_pool: Arc<WorkerPool>,
     paranoid: Option<ParanoidDownloader>,
     materialize_failed_inputs: bool,
@@ -106,6 +108,7 @@ impl CommandExecutorFactory {
         skip_cache_read: bool,
         skip_cache_write: bool,
         project_root: ProjectRoot,
+        physical_roots: PhysicalRoots,
         worker_pool: Arc<WorkerPool>,
         paranoid: Option<ParanoidDownloader>,
         materialize_failed_inputs: bool,
@@ -126,6 +129,7 @@ impl CommandExecutorFactory {
             skip_cache_read,
             skip_cache_write,
             project_root,
+            physical_roots,
             worker_pool,
             paranoid,
             materialize_failed_inputs,
@@ -155,6 +159,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
                 self.blocking_executor.dupe(),
                 self.host_sharing_broker.dupe(),
                 self.project_root.root().to_owned(),
+                self.physical_roots.clone(),
                 self.forkserver.dupe(),
                 self.executor_global_knobs.dupe(),
                 worker_pool,
@@ -21,6 +21,7 @@ use buck2_core::fs::paths::file_name::FileName;
 use buck2_core::rollout_percentage::RolloutPercentage;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::execute::blocking::BlockingExecutor;
+use buck2_execute::execute::blocking::IoTag;
 use buck2_execute::materialize::materializer::MaterializationMethod;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializerConfigs;
 use buck2_execute_impl::materializers::sqlite::MaterializerState;
@@ -70,7 +71,7 @@ pub(crate) async fn maybe_initialize_materializer_sqlite_db(
         // When sqlite materializer state is disabled, we should always delete the materializer state db.
         // Otherwise, artifacts in buck-out will diverge from the state stored in db.
         io_executor
-            .execute_io_inline(|| {
+            .execute_io_inline(IoTag::Other, || {
                 fs_util::remove_all(&paths.materializer_state_path()).map_err(anyhow::Error::from)
             })
             .await?;
@@ -117,6 +117,7 @@ mod tests {
                     ..Default::default()
                 }),
             )),
+            sequence: 0,
         }
     }
 
@@ -26,6 +26,7 @@ use async_trait::async_trait;
 use buck2_build_api::configure_dice::configure_dice_for_buck;
 use buck2_build_api::spawner::BuckSpawner;
 use buck2_cli_proto::daemon_api_server::*;
+use buck2_cli_proto::stream_integrity::StreamStamper;
 use buck2_cli_proto::*;
 use buck2_common::buckd_connection::BUCK_AUTH_TOKEN_HEADER;
 use buck2_common::events::HasEvents;
@@ -49,6 +50,8 @@ use buck2_events::Event;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::materialize::materializer::MaterializationMethod;
 use buck2_execute_impl::materializers::sqlite::MaterializerStateIdentity;
+use buck2_futures::cancellation::CancellationReason;
+use buck2_futures::cancellation::CancellationReasonKind;
 use buck2_futures::cancellation::ExplicitCancellationContext;
 use buck2_futures::drop::DropTogether;
 use buck2_futures::spawn::spawn_cancellable;
@@ -550,6 +553,7 @@ fn error_to_command_progress(e: anyhow::Error) -> CommandProgress {
         progress: Some(command_progress::Progress::Result(Box::new(
             error_to_command_result(e),
         ))),
+        sequence: 0,
     }
 }
 
@@ -590,33 +594,46 @@ fn pump_events(
         Result<buck2_cli_proto::CommandProgress, tonic::Status>,
     >,
 ) {
+    // Lets the client detect events lost on the way.
+    let mut stamper = StreamStamper::default();
+
     // This function returns the receiving channel back to `tonic` as a streaming response.
     while let Some(next_event) = events.receive() {
-        // Ignoring errors from writing to `output_send` because they occur only when
-        // the receiving end of the channel is closed. This can happen, for example,
-        // if Tonic drops the streaming response due the client disconnecting.
-        // In these cases, ignoring the errors is intentional as no client is listening.
-        match next_event {
+        let (progress, is_result) = match next_event {
             // The CommandResult event indicates that the spawned
             // computation won't be producing any more events.
-            Event::CommandResult(result) => {
-                let _ignore = output_send.send(Ok(CommandProgress {
-                    progress: Some(command_progress::Progress::Result(result)),
-                }));
-                return;
-            }
-            Event::PartialResult(result) => {
-                let _ignore = output_send.send(Ok(CommandProgress {
-                    progress: Some(command_progress::Progress::PartialResult(Box::new(result))),
-                }));
-            }
+            Event::CommandResult(result) => (command_progress::Progress::Result(result), true),
+            Event::PartialResult(result) => (
+                command_progress::Progress::PartialResult(Box::new(result)),
+                false,
+            ),
             Event::Buck(buck_event) => {
                 state.peek_event(&buck_event);
-
-                let _ignore = output_send.send(Ok(CommandProgress {
-                    progress: Some(command_progress::Progress::Event(buck_event.into())),
-                }));
+                (command_progress::Progress::Event(buck_event.into()), false)
             }
+        };
+
+        // Ignoring errors from writing to `output_send` because they occur only when
+        // the receiving end of the channel is closed. This can happen, for example,
+        // if Tonic drops the streaming response due the client disconnecting.
+        // In these cases, ignoring the errors is intentional as no client is listening.
+        if is_result {
+            // Covers the end of the stream.
+            let _ignore = output_send.send(Ok(stamper.checkpoint()));
+        }
+
+        let mut progress = CommandProgress {
+            progress: Some(progress),
+            sequence: 0,
+        };
+        let checkpoint = stamper.stamp(&mut progress);
+        let _ignore = output_send.send(Ok(progress));
+        if let Some(checkpoint) = checkpoint {
+            let _ignore = output_send.send(Ok(checkpoint));
+        }
+
+        if is_result {
+            return;
         }
     }
 }
@@ -699,6 +716,8 @@ where
                     ),
                 },
             ))),
+            // Sent out of band, so not covered by the stream checkpoints.
+            sequence: 0,
         })
         .into_stream()
         .filter_map(|e| {
@@ -716,7 +735,10 @@ where
     Response::new(Box::pin(SyncStream {
         wrapped: sync_wrapper::SyncWrapper::new(DropTogether::new(
             events,
-            spawned.into_drop_cancel(),
+            // The response stream is dropped when the client goes away, e.g. on ctrl-c.
+            spawned.into_drop_cancel_with_reason(CancellationReason::new(
+                CancellationReasonKind::ClientDisconnected,
+            )),
         )),
     }))
 }
@@ -32,6 +32,7 @@ use buck2_core::cells::name::CellName;
 use buck2_core::facebook_only;
 use buck2_core::fs::cwd::WorkingDirectory;
 use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_core::is_open_source;
 use buck2_core::rollout_percentage::RolloutPercentage;
@@ -44,6 +45,7 @@ use buck2_events::EventSinkWithStats;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::execute::blocking::BlockingExecutor;
 use buck2_execute::execute::blocking::BuckBlockingExecutor;
+use buck2_execute::execute::blocking::IoTag;
 use buck2_execute::materialize::materializer::MaterializationMethod;
 use buck2_execute::materialize::materializer::Materializer;
 use buck2_execute::re::manager::ReConnectionManager;
@@ -53,6 +55,8 @@ use buck2_execute_impl::materializers::deferred::DeferredMaterializer;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializerConfigs;
 use buck2_execute_impl::materializers::deferred::TtlRefreshConfiguration;
 use buck2_execute_impl::materializers::immediate::ImmediateMaterializer;
+use buck2_execute_impl::materializers::sqlite::sqlite_vacuum_config_from_buck_config;
+use buck2_execute_impl::materializers::sqlite::sqlite_write_batch_config_from_buck_config;
 use buck2_execute_impl::materializers::sqlite::MaterializerState;
 use buck2_execute_impl::materializers::sqlite::MaterializerStateIdentity;
 use buck2_execute_impl::materializers::sqlite::MaterializerStateSqliteDb;
@@ -61,6 +65,7 @@ use buck2_file_watcher::file_watcher::FileWatcher;
 use buck2_forkserver::client::ForkserverClient;
 use buck2_http::HttpClient;
 use buck2_http::HttpClientBuilder;
+use buck2_http::HttpPolicy;
 use buck2_re_configuration::RemoteExecutionStaticMetadata;
 use buck2_re_configuration::RemoteExecutionStaticMetadataImpl;
 use buck2_server_ctx::concurrency::ConcurrencyHandler;
@@ -387,6 +392,20 @@ impl DaemonState {
                     }),
                 )?;
 
+                let hardlink_deduplicated_writes = root_config
+                    .parse(BuckconfigKeyRef {
+                        section: "buck2",
+                        property: "hardlink_deduplicated_writes",
+                    })?
+                    .unwrap_or(false);
+
+                let verify_declare_existing = root_config
+                    .parse(BuckconfigKeyRef {
+                        section: "buck2",
+                        property: "materializer_verify_declare_existing",
+                    })?
+                    .unwrap_or(false);
+
                 let verbose_materializer_log = root_config
                     .parse(BuckconfigKeyRef {
                         section: "buck2",
@@ -394,7 +413,32 @@ impl DaemonState {
                     })?
                     .unwrap_or(false);
 
+                let prefetch_max_bytes = root_config.parse(BuckconfigKeyRef {
+                    section: "buck2",
+                    property: "materializer_prefetch_max_bytes",
+                })?;
+
+                // 0 means unlimited.
+                let max_concurrent_materializations = root_config
+                    .parse::<usize>(BuckconfigKeyRef {
+                        section: "buck2",
+                        property: "materializer_max_concurrent_materializations",
+                    })?
+                    .unwrap_or(1024);
+
+                let eager_paths = root_config
+                    .parse_list::<String>(BuckconfigKeyRef {
+                        section: "buck2",
+                        property: "materializer_eager_paths",
+                    })?
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|p| ProjectRelativePath::new(p).map(|p| p.to_owned()))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+
                 let clean_stale_config = CleanStaleConfig::from_buck_config(root_config)?;
+                let sqlite_vacuum_config = sqlite_vacuum_config_from_buck_config(root_config)?;
+                let sqlite_write_batch = sqlite_write_batch_config_from_buck_config(root_config)?;
 
                 DeferredMaterializerConfigs {
                     materialize_final_artifacts: matches!(
@@ -402,6 +446,7 @@ impl DaemonState {
                         MaterializationMethod::Deferred
                     ),
                     defer_write_actions,
+                    hardlink_deduplicated_writes,
                     ttl_refresh: TtlRefreshConfiguration {
                         frequency: std::time::Duration::from_secs(ttl_refresh_frequency),
                         min_ttl: chrono::Duration::seconds(ttl_refresh_min_ttl),
@@ -410,6 +455,13 @@ impl DaemonState {
                     update_access_times,
                     verbose_materializer_log,
                     clean_stale_config,
+                    sqlite_vacuum_config,
+                    prefetch_max_bytes,
+                    max_concurrent_materializations: Some(max_concurrent_materializations)
+                        .filter(|n| *n > 0),
+                    eager_paths,
+                    sqlite_write_batch,
+                    verify_declare_existing,
                 }
             };
 
@@ -421,12 +473,15 @@ impl DaemonState {
                     digest_config.cas_digest_config(),
                     init_ctx.enable_trace_io,
                 ),
-                (blocking_executor.dupe() as Arc<dyn BlockingExecutor>).execute_io_inline(|| {
-                    // Using `execute_io_inline` is just out of convenience.
-                    // It doesn't really matter what's used here since there's no IO-heavy
-                    // operations on daemon startup
-                    delete_unknown_disk_state(&cache_dir_path, &valid_cache_dirs)
-                }),
+                (blocking_executor.dupe() as Arc<dyn BlockingExecutor>).execute_io_inline(
+                    IoTag::Other,
+                    || {
+                        // Using `execute_io_inline` is just out of convenience.
+                        // It doesn't really matter what's used here since there's no IO-heavy
+                        // operations on daemon startup
+                        delete_unknown_disk_state(&cache_dir_path, &valid_cache_dirs)
+                    },
+                ),
                 maybe_initialize_materializer_sqlite_db(
                     &disk_state_options,
                     paths.clone(),
@@ -871,6 +926,9 @@ fn convert_algorithm_kind(kind: DigestAlgorithmKind) -> anyhow::Result<DigestAlg
 const DEFAULT_MAX_REDIRECTS: usize = 10;
 const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 5000;
 const DEFAULT_READ_TIMEOUT_MS: u64 = 10000;
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 300000;
+const DEFAULT_MAX_RETRIES: usize = 2;
+const DEFAULT_RETRY_INITIAL_DELAY_MS: u64 = 500;
 
 /// Customize an http client based on http.* legacy buckconfigs.
 fn http_client_from_startup_config(
@@ -882,7 +940,22 @@ fn http_client_from_startup_config(
         HttpClientBuilder::internal(config.allow_vpnless)?
     };
     builder.with_max_redirects(config.http.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS));
-    builder.with_http2(config.http.http2);
+    builder.with_http_version_preference(config.http.version_preference);
+    builder.with_retries(
+        config.http.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+        config
+            .http
+            .retry_initial_delay()
+            .unwrap_or(Duration::from_millis(DEFAULT_RETRY_INITIAL_DELAY_MS)),
+    );
+    builder.with_policy(HttpPolicy::new(
+        &config.http.allowed_hosts,
+        &config.http.denied_hosts,
+        &config.http.allowed_schemes,
+    ));
+    for header in &config.http.default_headers {
+        builder.with_default_header_line(header)?;
+    }
     match config.http.connect_timeout() {
         Timeout::Value(d) => {
             builder.with_connect_timeout(Some(d));
@@ -907,6 +980,17 @@ fn http_client_from_startup_config(
         }
         _ => {}
     }
+    // The read timeout is reset by every byte received, so a server trickling its response could
+    // otherwise hold a request forever. The default is generous since it includes redirects.
+    match config.http.request_timeout() {
+        Timeout::Value(d) => {
+            builder.with_request_timeout(Some(d));
+        }
+        Timeout::Default => {
+            builder.with_request_timeout(Some(Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS)));
+        }
+        _ => {}
+    }
 
     Ok(builder)
 }
@@ -916,6 +1000,7 @@ mod tests {
     use std::time::Duration;
 
     use buck2_common::legacy_configs::testing::parse;
+    use buck2_http::HttpVersionPreference;
     use indoc::indoc;
 
     use super::*;
@@ -934,6 +1019,11 @@ mod tests {
             builder.read_timeout()
         );
         assert_eq!(None, builder.write_timeout());
+        assert_eq!(
+            Some(Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS)),
+            builder.request_timeout()
+        );
+        assert_eq!(DEFAULT_MAX_RETRIES, builder.max_retries());
 
         Ok(())
     }
@@ -949,6 +1039,8 @@ mod tests {
                     max_redirects = 5
                     connect_timeout_ms = 10
                     write_timeout_ms = 5
+                    request_timeout_ms = 20
+                    max_retries = 0
                     "#
                 ),
             )],
@@ -963,6 +1055,59 @@ mod tests {
             builder.read_timeout()
         );
         assert_eq!(Some(Duration::from_millis(5)), builder.write_timeout());
+        assert_eq!(Some(Duration::from_millis(20)), builder.request_timeout());
+        assert_eq!(0, builder.max_retries());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_startup_config_default_headers() -> anyhow::Result<()> {
+        let config = parse(
+            &[(
+                "/config",
+                indoc!(
+                    r#"
+                    [http]
+                    default_headers = Authorization: Bearer token, x-tag: a, x-tag: b
+                    "#
+                ),
+            )],
+            "/config",
+        )?;
+        let builder = http_client_from_startup_config(&DaemonStartupConfig::new(&config)?)?;
+        let headers = builder.default_headers();
+        assert_eq!("Bearer token", headers["authorization"]);
+        assert_eq!(
+            vec!["a", "b"],
+            headers
+                .get_all("x-tag")
+                .iter()
+                .map(|v| v.to_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_startup_config_http_version() -> anyhow::Result<()> {
+        let builder = |config: &str| {
+            let config = parse(&[("/config", config)], "/config")?;
+            http_client_from_startup_config(&DaemonStartupConfig::new(&config)?)
+        };
+        assert_eq!(
+            HttpVersionPreference::Auto,
+            builder("")?.http_version_preference()
+        );
+        assert_eq!(
+            HttpVersionPreference::Http1Only,
+            builder("[http]\nhttp2 = false\n")?.http_version_preference()
+        );
+        assert_eq!(
+            HttpVersionPreference::Http2Only,
+            builder("[http]\nhttp2 = false\nversion = http2_only\n")?.http_version_preference()
+        );
 
         Ok(())
     }
@@ -11,6 +11,7 @@ use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 
+use buck2_common::target_progress::TargetProgress;
 use buck2_events::dispatch::EventDispatcher;
 use dupe::Dupe;
 use tokio::task::JoinHandle;
@@ -21,11 +22,32 @@ use crate::snapshot::SnapshotCollector;
 pub struct HeartbeatGuard {
     handle: JoinHandle<()>,
     collector: SnapshotCollector,
+    target_progress: Arc<TargetProgress>,
     events: Arc<Mutex<Option<EventDispatcher>>>,
 }
 
+/// Create a snapshot that includes the target counts of the current command.
+fn create_snapshot(
+    collector: &SnapshotCollector,
+    target_progress: &TargetProgress,
+) -> buck2_data::Snapshot {
+    let mut snapshot = collector.create_snapshot();
+    let progress = target_progress.snapshot();
+    snapshot.target_progress = Some(buck2_data::TargetProgress {
+        targets_discovered: progress.targets_discovered,
+        targets_analyzed: progress.targets_analyzed,
+        packages_finished: progress.packages_finished,
+        packages_pending: progress.packages_pending,
+    });
+    snapshot
+}
+
 impl HeartbeatGuard {
-    pub fn new(events: EventDispatcher, collector: SnapshotCollector) -> Self {
+    pub fn new(
+        events: EventDispatcher,
+        collector: SnapshotCollector,
+        target_progress: Arc<TargetProgress>,
+    ) -> Self {
         let events = Arc::new(Mutex::new(Some(events)));
 
         // NOTE: This doesn't use the ambient dispatcher wrappers because we want to control the
@@ -33,11 +55,12 @@ impl HeartbeatGuard {
         let handle = tokio::spawn({
             let events = events.dupe();
             let collector = collector.clone();
+            let target_progress = target_progress.dupe();
             async move {
                 let mut interval = tokio::time::interval(Duration::from_secs(1));
                 interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
                 loop {
-                    let snapshot = collector.create_snapshot();
+                    let snapshot = create_snapshot(&collector, &target_progress);
                     match events.lock().expect("Poisoned lock").as_ref() {
                         Some(events) => events.instant_event(Box::new(snapshot)),
                         None => break,
@@ -50,6 +73,7 @@ impl HeartbeatGuard {
         Self {
             handle,
             collector,
+            target_progress,
             events,
         }
     }
@@ -61,8 +85,10 @@ impl Drop for HeartbeatGuard {
         // Synchronously remove access for sending new heartbeats.
         if let Some(events) = maybe_events.take() {
             // Send one last snapshot.
-            let collector = self.collector.dupe();
-            events.instant_event(Box::new(collector.create_snapshot()));
+            events.instant_event(Box::new(create_snapshot(
+                &self.collector,
+                &self.target_progress,
+            )));
         }
         // Cancel the task as well.
         self.handle.abort();
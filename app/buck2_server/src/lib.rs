@@ -31,3 +31,7 @@ pub mod profile;
 mod snapshot;
 mod subscription;
 mod trace_io;
+
+pub fn init_late_bindings() {
+    active_commands::init_concurrent_command_progress();
+}
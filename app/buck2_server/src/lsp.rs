@@ -46,6 +46,7 @@ use buck2_server_ctx::command_end::command_end;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::ctx::ServerCommandDiceContext;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
+use buck2_server_ctx::resource_usage::CommandResourceTracker;
 use buck2_server_ctx::streaming_request_handler::StreamingRequestHandler;
 use dice::DiceEquality;
 use dice::DiceTransaction;
@@ -795,10 +796,11 @@ pub(crate) async fn run_lsp_server_command(
         data: Some(buck2_data::LspCommandStart {}.into()),
     };
     span_async(start_event, async move {
+        let resource_tracker = CommandResourceTracker::start();
         let result = run_lsp_server(ctx, partial_result_dispatcher, req)
             .await
             .map_err(Into::into);
-        let end_event = command_end(&result, buck2_data::LspCommandEnd {});
+        let end_event = command_end(&result, buck2_data::LspCommandEnd {}, resource_tracker);
         (result.map_err(Into::into), end_event)
     })
     .await
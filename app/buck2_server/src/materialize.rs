@@ -14,6 +14,7 @@ use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_events::dispatch::span_async;
 use buck2_server_ctx::command_end::command_end;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
+use buck2_server_ctx::resource_usage::CommandResourceTracker;
 
 use crate::ctx::BaseServerCommandContext;
 use crate::ctx::ServerCommandContext;
@@ -27,12 +28,17 @@ pub(crate) async fn materialize_command(
         data: Some(buck2_data::MaterializeCommandStart {}.into()),
     };
     span_async(start_event, async move {
+        let resource_tracker = CommandResourceTracker::start();
         let result = materialize(&context.base_context, req.paths)
             .await
             .map(|()| MaterializeResponse {})
             .context("Failed to materialize paths")
             .map_err(Into::into);
-        let end_event = command_end(&result, buck2_data::MaterializeCommandEnd {});
+        let end_event = command_end(
+            &result,
+            buck2_data::MaterializeCommandEnd {},
+            resource_tracker,
+        );
         (result.map_err(Into::into), end_event)
     })
     .await
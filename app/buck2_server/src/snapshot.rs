@@ -13,6 +13,7 @@ use std::sync::Arc;
 use anyhow::Context as _;
 use buck2_core::io_counters::IoCounterKey;
 use buck2_events::EventSinkStats;
+use buck2_execute::execute::blocking::IoTag;
 use buck2_execute::re::manager::ReConnectionManager;
 use buck2_util::process_stats::process_stats;
 use buck2_util::system_stats::UnixSystemStats;
@@ -55,6 +56,22 @@ impl SnapshotCollector {
     fn add_daemon_metrics(&self, snapshot: &mut buck2_data::Snapshot) {
         snapshot.blocking_executor_io_queue_size =
             self.daemon.blocking_executor.queue_size() as u64;
+        snapshot.blocking_executor_tag_stats = IoTag::ALL
+            .into_iter()
+            .map(|tag| {
+                let stats = self.daemon.blocking_executor.tag_stats(tag);
+                (
+                    tag.to_string(),
+                    buck2_data::BlockingExecutorTagStats {
+                        queued: stats.queued,
+                        running: stats.running,
+                        finished: stats.finished,
+                        total_wait_us: stats.total_wait.as_micros() as u64,
+                        max_wait_us: stats.max_wait.as_micros() as u64,
+                    },
+                )
+            })
+            .collect();
     }
 
     fn add_io_metrics(&self, snapshot: &mut buck2_data::Snapshot) {
@@ -211,6 +228,7 @@ impl SnapshotCollector {
         }
         snapshot.daemon_uptime_s = self.daemon.start_time.elapsed().as_secs();
         snapshot.buck2_rss = process_stats.rss_bytes;
+        snapshot.buck2_major_page_faults = process_stats.major_page_faults;
         let allocator_stats = get_allocator_stats().ok();
         if let Some(alloc_stats) = allocator_stats {
             snapshot.malloc_bytes_active = alloc_stats.bytes_active;
@@ -15,6 +15,7 @@ use buck2_events::dispatch::span_async;
 use buck2_server_ctx::command_end::command_end;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
+use buck2_server_ctx::resource_usage::CommandResourceTracker;
 use buck2_server_ctx::streaming_request_handler::StreamingRequestHandler;
 use futures::future::FutureExt;
 use gazebo::prelude::*;
@@ -34,6 +35,7 @@ pub(crate) async fn run_subscription_server_command(
         data: Some(buck2_data::SubscriptionCommandStart {}.into()),
     };
     span_async(start_event, async move {
+        let resource_tracker = CommandResourceTracker::start();
         let result: anyhow::Result<buck2_cli_proto::SubscriptionCommandResponse> = try {
             // NOTE: Long term if we expose more things here then we should probably move this error to
             // only occur when we try to actually interact with materializer subscriptioons
@@ -110,7 +112,11 @@ pub(crate) async fn run_subscription_server_command(
         };
         let result = result.map_err(Into::into);
 
-        let end_event = command_end(&result, buck2_data::SubscriptionCommandEnd {});
+        let end_event = command_end(
+            &result,
+            buck2_data::SubscriptionCommandEnd {},
+            resource_tracker,
+        );
         (result.map_err(Into::into), end_event)
     })
     .await
@@ -15,6 +15,7 @@ use buck2_common::io::trace::TracingIoProvider;
 use buck2_events::dispatch::span_async;
 use buck2_server_ctx::command_end::command_end;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
+use buck2_server_ctx::resource_usage::CommandResourceTracker;
 
 use crate::ctx::ServerCommandContext;
 
@@ -27,6 +28,7 @@ pub(crate) async fn trace_io_command(
         data: Some(buck2_data::TraceIoCommandStart {}.into()),
     };
     span_async(start_event, async move {
+        let resource_tracker = CommandResourceTracker::start();
         let tracing_provider = TracingIoProvider::from_io(&*context.base_context.daemon.io);
         let respond_with_trace = matches!(
             req.read_state,
@@ -52,7 +54,7 @@ pub(crate) async fn trace_io_command(
         }
         .map_err(Into::into);
 
-        let end_event = command_end(&result, buck2_data::TraceIoCommandEnd {});
+        let end_event = command_end(&result, buck2_data::TraceIoCommandEnd {}, resource_tracker);
         (result.map_err(Into::into), end_event)
     })
     .await
@@ -23,6 +23,7 @@ use buck2_build_api::actions::artifact::get_artifact_fs::GetArtifactFs;
 use buck2_build_api::build;
 use buck2_build_api::build::build_report::generate_build_report;
 use buck2_build_api::build::build_report::BuildReportOpts;
+use buck2_build_api::build::cache_attribution::HasCacheAttribution;
 use buck2_build_api::build::BuildEvent;
 use buck2_build_api::build::BuildTargetResult;
 use buck2_build_api::build::ConfiguredBuildEvent;
@@ -329,6 +330,9 @@ async fn process_build_result(
             server_ctx.events().trace_id(),
             &build_result.configured,
             &build_result.other_errors,
+            ctx.per_transaction_data()
+                .get_cache_attribution()
+                .map(|c| &**c),
         )?
     } else {
         None
@@ -9,6 +9,7 @@
 
 mod default;
 pub(crate) mod fmt;
+mod proto;
 mod resolve_alias;
 mod streaming;
 use std::fs::File;
@@ -18,6 +19,7 @@ use std::io::Write;
 use anyhow::Context as _;
 use async_trait::async_trait;
 use buck2_cli_proto::targets_request;
+use buck2_cli_proto::targets_request::OutputFormat;
 use buck2_cli_proto::targets_request::TargetHashGraphType;
 use buck2_cli_proto::TargetsRequest;
 use buck2_cli_proto::TargetsResponse;
@@ -36,6 +38,7 @@ use dice::DiceTransaction;
 use crate::commands::targets::default::targets_batch;
 use crate::commands::targets::default::TargetHashOptions;
 use crate::commands::targets::fmt::create_formatter;
+use crate::commands::targets::proto::targets_proto;
 use crate::commands::targets::resolve_alias::targets_resolve_aliases;
 use crate::commands::targets::streaming::targets_streaming;
 
@@ -78,6 +81,14 @@ impl Outputter {
         Ok(())
     }
 
+    fn write_bytes(&mut self, stdout: &mut impl Write, x: &[u8]) -> anyhow::Result<()> {
+        match self {
+            Self::Stdout => stdout.write_all(x)?,
+            Self::File(f) => f.write_all(x)?,
+        }
+        Ok(())
+    }
+
     /// If this outputter should write anything to a file, do so, and return whatever buffer is left over.
     fn write_to_file(&mut self, buffer: String) -> anyhow::Result<String> {
         match self {
@@ -170,7 +181,21 @@ async fn targets(
             targets_resolve_aliases(dice, request, parsed_target_patterns).await?
         }
         Some(targets_request::Targets::Other(other)) => {
-            if other.streaming {
+            if request.output_format == OutputFormat::Proto as i32 {
+                let res = targets_proto(
+                    server_ctx,
+                    stdout,
+                    dice,
+                    request,
+                    other,
+                    &mut outputter,
+                    parsed_target_patterns,
+                )
+                .await;
+                // Make sure we always flush the outputter, even on failure, as we may have partially written to it
+                outputter.flush()?;
+                res?
+            } else if other.streaming {
                 let formatter = create_formatter(request, other)?;
                 let hashing = match TargetHashGraphType::from_i32(other.target_hash_graph_type)
                     .expect("buck cli should send valid target hash graph type")
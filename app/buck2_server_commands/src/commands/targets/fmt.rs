@@ -46,7 +46,11 @@ pub(crate) struct TargetInfo<'a> {
     pub(crate) super_package: &'a SuperPackage,
 }
 
-fn package_error_to_stderr(package: PackageLabel, error: &buck2_error::Error, stderr: &mut String) {
+pub(crate) fn package_error_to_stderr(
+    package: PackageLabel,
+    error: &buck2_error::Error,
+    stderr: &mut String,
+) {
     writeln!(stderr, "Error parsing {package}\n{error:?}").unwrap();
 }
 
@@ -410,7 +414,7 @@ pub(crate) fn create_formatter(
     let target_call_stacks = request.client_context()?.target_call_stacks;
 
     match output_format {
-        OutputFormat::Json | OutputFormat::JsonLines => {}
+        OutputFormat::Json | OutputFormat::JsonLines | OutputFormat::Proto => {}
         _ => {
             // Self-check.
             if !other.output_attributes.is_empty() {
@@ -423,6 +427,9 @@ pub(crate) fn create_formatter(
 
     match output_format {
         OutputFormat::Unknown => Err(internal_error!("`output_format` is not set")),
+        OutputFormat::Proto => Err(internal_error!(
+            "Proto output is not written through a `TargetFormatter`"
+        )),
         OutputFormat::Stats => Ok(Arc::new(StatsFormat)),
         OutputFormat::Text => Ok(Arc::new(TargetNameFormat {
             target_call_stacks,
@@ -0,0 +1,583 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Server-side implementation of `buck2 targets --output-format=proto`.
+//!
+//! The output is a stream of length-delimited `buck.data.targets.TargetsStreamMessage`, see
+//! `targets.proto` in `buck2_data` for the schema. It is always streamed, as packages complete.
+
+use std::io::Write;
+use std::sync::Arc;
+
+use buck2_cli_proto::config_override::ConfigType;
+use buck2_cli_proto::targets_request;
+use buck2_cli_proto::ClientContext;
+use buck2_cli_proto::HasClientContext;
+use buck2_cli_proto::TargetCfg;
+use buck2_cli_proto::TargetsRequest;
+use buck2_cli_proto::TargetsResponse;
+use buck2_common::dice::cells::HasCellResolver;
+use buck2_core::cells::CellResolver;
+use buck2_core::package::PackageLabel;
+use buck2_core::pattern::pattern_type::TargetPatternExtra;
+use buck2_core::pattern::ParsedPattern;
+use buck2_data::targets::attr_value;
+use buck2_data::targets::target_node;
+use buck2_data::targets::targets_header;
+use buck2_data::targets::targets_stream_message;
+use buck2_data::targets::AttrValue;
+use buck2_data::targets::PackageError;
+use buck2_data::targets::TargetsHeader;
+use buck2_data::targets::TargetsStreamMessage;
+use buck2_futures::spawn::spawn_cancellable;
+use buck2_node::attrs::coerced_attr::CoercedAttr;
+use buck2_node::attrs::hacks::value_to_json;
+use buck2_node::attrs::inspect_options::AttrInspectOptions;
+use buck2_node::nodes::unconfigured::TargetNodeRef;
+use buck2_server_ctx::ctx::ServerCommandContextTrait;
+use dice::DiceTransaction;
+use dupe::Dupe;
+use futures::future::FutureExt;
+use futures::StreamExt;
+use prost::Message;
+use regex::RegexSet;
+use tokio::sync::Semaphore;
+
+use crate::commands::targets::fmt::package_error_to_stderr;
+use crate::commands::targets::fmt::Stats;
+use crate::commands::targets::streaming::load_targets;
+use crate::commands::targets::streaming::stream_packages;
+use crate::commands::targets::Outputter;
+
+/// Bumped on incompatible changes to `targets.proto`.
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+
+/// The attribute holding the labels of a target.
+const LABELS: &str = "labels";
+
+pub(crate) struct ProtoFormat {
+    attributes: Option<RegexSet>,
+    attr_inspect_opts: AttrInspectOptions,
+}
+
+impl ProtoFormat {
+    pub(crate) fn new(other: &targets_request::Other) -> anyhow::Result<Self> {
+        Ok(ProtoFormat {
+            attributes: if other.output_attributes.is_empty() {
+                None
+            } else {
+                Some(RegexSet::new(&other.output_attributes)?)
+            },
+            attr_inspect_opts: if other.include_default_attributes {
+                AttrInspectOptions::All
+            } else {
+                AttrInspectOptions::DefinedOnly
+            },
+        })
+    }
+
+    pub(crate) fn target_node(
+        &self,
+        node: TargetNodeRef<'_>,
+    ) -> anyhow::Result<buck2_data::targets::TargetNode> {
+        let pkg = node.label().pkg();
+
+        let labels = match node.attr_or_none(LABELS, AttrInspectOptions::All) {
+            Some(labels) => match value_to_json(labels.value, pkg.dupe())? {
+                serde_json::Value::Array(items) => items
+                    .into_iter()
+                    .map(|item| match item {
+                        serde_json::Value::String(s) => Some(s),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .unwrap_or_default(),
+                _ => Vec::new(),
+            },
+            None => Vec::new(),
+        };
+
+        let mut attrs = Vec::new();
+        for a in node.attrs(self.attr_inspect_opts) {
+            if let Some(filter) = &self.attributes {
+                if !filter.is_match(a.name) {
+                    continue;
+                }
+            }
+            attrs.push(target_node::Attribute {
+                name: a.name.to_owned(),
+                value: Some(attr_value(a.value, pkg.dupe())?),
+            });
+        }
+
+        Ok(buck2_data::targets::TargetNode {
+            label: node.label().to_string(),
+            package: pkg.to_string(),
+            rule_type: node.rule_type().to_string(),
+            oncall: node.oncall().map(|oncall| oncall.to_owned()),
+            labels,
+            attrs,
+        })
+    }
+}
+
+/// Converts an attribute value, keeping the structure of selects.
+fn attr_value(value: &CoercedAttr, pkg: PackageLabel) -> anyhow::Result<AttrValue> {
+    let value = match value {
+        // `Select` and `AttrValue` contain each other, so prost boxes them.
+        CoercedAttr::Selector(selector) => {
+            attr_value::Value::Select(Box::new(attr_value::Select {
+                entries: selector
+                    .entries()
+                    .iter()
+                    .map(|(key, value)| {
+                        anyhow::Ok(attr_value::select::Entry {
+                            key: key.to_string(),
+                            value: Some(attr_value(value, pkg.dupe())?),
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+                default: selector
+                    .default()
+                    .map(|default| attr_value(default, pkg.dupe()).map(Box::new))
+                    .transpose()?,
+            }))
+        }
+        CoercedAttr::Concat(items) => attr_value::Value::Concat(attr_value::Concat {
+            items: items
+                .iter()
+                .map(|item| attr_value(item, pkg.dupe()))
+                .collect::<anyhow::Result<_>>()?,
+        }),
+        value => attr_value::Value::Json(value_to_json(value, pkg)?.to_string()),
+    };
+    Ok(AttrValue { value: Some(value) })
+}
+
+pub(crate) fn header(
+    context: &ClientContext,
+    target_cfg: Option<&TargetCfg>,
+    cell_resolver: &CellResolver,
+) -> TargetsHeader {
+    let mut cells: Vec<_> = cell_resolver
+        .cells()
+        .map(|(name, instance)| targets_header::Cell {
+            name: name.to_string(),
+            path: instance.path().to_string(),
+        })
+        .collect();
+    cells.sort_by(|a, b| a.name.cmp(&b.name));
+
+    TargetsHeader {
+        schema_version: SCHEMA_VERSION,
+        working_dir: context.working_dir.clone(),
+        root_cell: cell_resolver.root_cell().to_string(),
+        cells,
+        config_overrides: context
+            .config_overrides
+            .iter()
+            .map(|o| targets_header::ConfigOverride {
+                config_override: o.config_override.clone(),
+                is_file: o.config_type == ConfigType::File as i32,
+            })
+            .collect(),
+        target_platform: target_cfg
+            .map(|cfg| cfg.target_platform.clone())
+            .unwrap_or_default(),
+        cli_modifiers: target_cfg
+            .map(|cfg| cfg.cli_modifiers.clone())
+            .unwrap_or_default(),
+    }
+}
+
+/// Appends the message to the buffer, prefixed with its length.
+pub(crate) fn write_message(message: targets_stream_message::Message, buffer: &mut Vec<u8>) {
+    TargetsStreamMessage {
+        message: Some(message),
+    }
+    .encode_length_delimited(buffer)
+    .expect("Vec has unlimited capacity");
+}
+
+pub(crate) async fn targets_proto(
+    server_ctx: &dyn ServerCommandContextTrait,
+    stdout: &mut impl Write,
+    mut dice: DiceTransaction,
+    request: &TargetsRequest,
+    other: &targets_request::Other,
+    outputter: &mut Outputter,
+    parsed_patterns: Vec<ParsedPattern<TargetPatternExtra>>,
+) -> anyhow::Result<TargetsResponse> {
+    struct Res {
+        stats: Stats,           // Stats to merge in
+        stderr: Option<String>, // Print to stderr (and break unless keep_going is set)
+        stdout: Vec<u8>,        // Print to stdout
+    }
+
+    let keep_going = other.keep_going;
+    let cached = other.cached;
+    let format = Arc::new(ProtoFormat::new(other)?);
+    let threads = Arc::new(Semaphore::new(
+        request
+            .concurrency
+            .as_ref()
+            .map_or(Semaphore::MAX_PERMITS, |x| x.concurrency as usize),
+    ));
+
+    let mut buffer = Vec::new();
+    write_message(
+        targets_stream_message::Message::Header(header(
+            request.client_context()?,
+            request.target_cfg.as_ref(),
+            &dice.get_cell_resolver().await?,
+        )),
+        &mut buffer,
+    );
+    outputter.write_bytes(stdout, &buffer)?;
+
+    let mut packages = stream_packages(&dice, parsed_patterns)
+        .map(|x| {
+            let format = format.dupe();
+            let threads = threads.dupe();
+            let mut ctx = dice.dupe();
+
+            spawn_cancellable(
+                |_cancellation| {
+                    async move {
+                        let (package, spec) = x?;
+                        let mut res = Res {
+                            stats: Stats::default(),
+                            stderr: None,
+                            stdout: Vec::new(),
+                        };
+                        let targets = {
+                            // This bit of code is the heavy CPU stuff, so guard it with the threads
+                            let _permit = threads.acquire().await.unwrap();
+                            load_targets(&mut ctx, package.dupe(), spec, cached, keep_going).await
+                        };
+                        let mut show_err = |err: &buck2_error::Error| {
+                            res.stats.add_error(err);
+                            let mut stderr = String::new();
+                            package_error_to_stderr(package.dupe(), err, &mut stderr);
+                            res.stderr = Some(stderr);
+                            write_message(
+                                targets_stream_message::Message::PackageError(PackageError {
+                                    package: package.to_string(),
+                                    error: format!("{:?}", err),
+                                }),
+                                &mut res.stdout,
+                            );
+                        };
+                        match targets {
+                            Ok((_eval_result, targets, err)) => {
+                                if let Some(err) = err {
+                                    show_err(&err.into());
+                                }
+                                res.stats.success += 1;
+                                for node in targets.iter() {
+                                    res.stats.targets += 1;
+                                    write_message(
+                                        targets_stream_message::Message::Target(
+                                            format.target_node(node.as_ref())?,
+                                        ),
+                                        &mut res.stdout,
+                                    );
+                                }
+                            }
+                            Err(err) => {
+                                show_err(&err.into());
+                            }
+                        }
+                        anyhow::Ok(res)
+                    }
+                    .boxed()
+                },
+                &*dice.per_transaction_data().spawner,
+                dice.per_transaction_data(),
+            )
+            .into_drop_cancel()
+        })
+        // Use unlimited parallelism - tokio will restrict us anyway
+        .buffer_unordered(1000000);
+
+    let mut stats = Stats::default();
+    while let Some(res) = packages.next().await {
+        let res = res?;
+        stats.merge(&res.stats);
+        if let Some(stderr) = &res.stderr {
+            server_ctx.stderr()?.write_all(stderr.as_bytes())?;
+            if !keep_going {
+                return Err(stats
+                    .to_error()
+                    .expect("Result only has a stderr if there were errors"));
+            }
+        }
+        outputter.write_bytes(stdout, &res.stdout)?;
+    }
+
+    Ok(TargetsResponse {
+        error_count: stats.errors,
+        serialized_targets_output: String::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use buck2_cli_proto::config_override::ConfigType;
+    use buck2_cli_proto::targets_request;
+    use buck2_cli_proto::ClientContext;
+    use buck2_cli_proto::ConfigOverride;
+    use buck2_cli_proto::TargetCfg;
+    use buck2_core::bzl::ImportPath;
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::CellResolver;
+    use buck2_core::target::label::label::TargetLabel;
+    use buck2_data::targets::attr_value;
+    use buck2_data::targets::targets_stream_message;
+    use buck2_data::targets::AttrValue;
+    use buck2_data::targets::TargetsStreamMessage;
+    use buck2_node::attrs::attr::Attribute;
+    use buck2_node::attrs::attr_type::bool::BoolLiteral;
+    use buck2_node::attrs::attr_type::list::ListLiteral;
+    use buck2_node::attrs::attr_type::string::StringLiteral;
+    use buck2_node::attrs::attr_type::AttrType;
+    use buck2_node::attrs::coerced_attr::CoercedAttr;
+    use buck2_node::attrs::coerced_attr::CoercedSelector;
+    use buck2_node::configuration::resolved::ConfigurationSettingKey;
+    use buck2_node::nodes::unconfigured::testing::TargetNodeExt;
+    use buck2_node::nodes::unconfigured::TargetNode;
+    use buck2_node::rule_type::RuleType;
+    use buck2_node::rule_type::StarlarkRuleType;
+    use buck2_util::arc_str::ArcSlice;
+    use prost::Message;
+
+    use crate::commands::targets::proto::header;
+    use crate::commands::targets::proto::write_message;
+    use crate::commands::targets::proto::ProtoFormat;
+    use crate::commands::targets::proto::SCHEMA_VERSION;
+
+    fn format() -> ProtoFormat {
+        ProtoFormat::new(&targets_request::Other::default()).unwrap()
+    }
+
+    fn json(value: &str) -> AttrValue {
+        AttrValue {
+            value: Some(attr_value::Value::Json(value.to_owned())),
+        }
+    }
+
+    fn string(s: &str) -> CoercedAttr {
+        CoercedAttr::String(StringLiteral(s.into()))
+    }
+
+    fn node(name: &str) -> TargetNode {
+        let rule_type = RuleType::Starlark(Arc::new(StarlarkRuleType {
+            import_path: ImportPath::testing_new("cell//foo/bar:def.bzl"),
+            name: "some_rule".to_owned(),
+        }));
+        let select = CoercedAttr::Selector(Box::new(
+            CoercedSelector::new(
+                ArcSlice::new([(
+                    ConfigurationSettingKey::testing_parse("cell//os:linux"),
+                    CoercedAttr::Bool(BoolLiteral(true)),
+                )]),
+                Some(CoercedAttr::Bool(BoolLiteral(false))),
+            )
+            .unwrap(),
+        ));
+        TargetNode::testing_new(
+            TargetLabel::testing_parse(&format!("cell//foo/bar:{}", name)),
+            rule_type,
+            vec![
+                (
+                    "labels",
+                    Attribute::new(None, "", AttrType::list(AttrType::string())),
+                    CoercedAttr::List(ListLiteral(ArcSlice::new([string("a"), string("b")]))),
+                ),
+                (
+                    "enabled",
+                    Attribute::new(None, "", AttrType::bool()),
+                    select,
+                ),
+            ],
+        )
+    }
+
+    fn decode_all(mut buffer: &[u8]) -> Vec<TargetsStreamMessage> {
+        let mut messages = Vec::new();
+        while !buffer.is_empty() {
+            messages.push(TargetsStreamMessage::decode_length_delimited(&mut buffer).unwrap());
+        }
+        messages
+    }
+
+    #[test]
+    fn test_target_node_round_trip() {
+        let node = format().target_node(node("t").as_ref()).unwrap();
+
+        let mut buffer = Vec::new();
+        write_message(
+            targets_stream_message::Message::Target(node.clone()),
+            &mut buffer,
+        );
+        let decoded = decode_all(&buffer);
+        assert_eq!(
+            vec![TargetsStreamMessage {
+                message: Some(targets_stream_message::Message::Target(node.clone())),
+            }],
+            decoded
+        );
+
+        assert_eq!("cell//foo/bar:t", node.label);
+        assert_eq!("cell//foo/bar", node.package);
+        assert_eq!("cell//foo/bar:def.bzl:some_rule", node.rule_type);
+        assert_eq!(vec!["a".to_owned(), "b".to_owned()], node.labels);
+
+        let enabled = node
+            .attrs
+            .iter()
+            .find(|a| a.name == "enabled")
+            .and_then(|a| a.value.clone());
+        assert_eq!(
+            Some(AttrValue {
+                value: Some(attr_value::Value::Select(Box::new(attr_value::Select {
+                    entries: vec![attr_value::select::Entry {
+                        key: "cell//os:linux".to_owned(),
+                        value: Some(json("true")),
+                    }],
+                    default: Some(Box::new(json("false"))),
+                }))),
+            }),
+            enabled
+        );
+    }
+
+    #[test]
+    fn test_one_message_per_target() {
+        let format = format();
+        let mut buffer = Vec::new();
+        for name in ["a", "b", "c"] {
+            write_message(
+                targets_stream_message::Message::Target(
+                    format.target_node(node(name).as_ref()).unwrap(),
+                ),
+                &mut buffer,
+            );
+        }
+
+        let labels: Vec<_> = decode_all(&buffer)
+            .into_iter()
+            .map(|m| match m.message {
+                Some(targets_stream_message::Message::Target(node)) => node.label,
+                m => panic!("unexpected message: {:?}", m),
+            })
+            .collect();
+        assert_eq!(
+            vec!["cell//foo/bar:a", "cell//foo/bar:b", "cell//foo/bar:c"],
+            labels
+        );
+    }
+
+    #[test]
+    fn test_attribute_filter() {
+        let format = ProtoFormat::new(&targets_request::Other {
+            output_attributes: vec!["^enabled$".to_owned()],
+            ..Default::default()
+        })
+        .unwrap();
+        let node = format.target_node(node("t").as_ref()).unwrap();
+        assert_eq!(
+            vec!["enabled"],
+            node.attrs
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        // Labels are always included.
+        assert_eq!(vec!["a".to_owned(), "b".to_owned()], node.labels);
+    }
+
+    #[test]
+    fn test_header() {
+        let cell_resolver = CellResolver::testing_with_names_and_paths(&[
+            (
+                CellName::testing_new("root"),
+                CellRootPathBuf::testing_new(""),
+            ),
+            (
+                CellName::testing_new("cell"),
+                CellRootPathBuf::testing_new("foo/cell"),
+            ),
+        ]);
+        let context = ClientContext {
+            working_dir: "/repo/foo".to_owned(),
+            config_overrides: vec![
+                ConfigOverride {
+                    config_override: "a.b=c".to_owned(),
+                    config_type: ConfigType::Value as i32,
+                },
+                ConfigOverride {
+                    config_override: "/repo/mode".to_owned(),
+                    config_type: ConfigType::File as i32,
+                },
+            ],
+            ..Default::default()
+        };
+        let target_cfg = TargetCfg {
+            target_platform: "root//:platform".to_owned(),
+            cli_modifiers: vec!["root//:linux".to_owned()],
+        };
+
+        let header = header(&context, Some(&target_cfg), &cell_resolver);
+
+        assert_eq!(SCHEMA_VERSION, header.schema_version);
+        assert_eq!("/repo/foo", header.working_dir);
+        assert_eq!("root", header.root_cell);
+        assert_eq!(
+            vec![("cell", "foo/cell"), ("root", "")],
+            header
+                .cells
+                .iter()
+                .map(|c| (c.name.as_str(), c.path.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(2, header.config_overrides.len());
+        assert_eq!("a.b=c", header.config_overrides[0].config_override);
+        assert!(!header.config_overrides[0].is_file);
+        assert!(header.config_overrides[1].is_file);
+        assert_eq!("root//:platform", header.target_platform);
+        assert_eq!(vec!["root//:linux".to_owned()], header.cli_modifiers);
+
+        // The header is the first message of the stream.
+        let mut buffer = Vec::new();
+        write_message(
+            targets_stream_message::Message::Header(header.clone()),
+            &mut buffer,
+        );
+        assert_eq!(
+            Some(targets_stream_message::Message::Header(header)),
+            decode_all(&buffer).remove(0).message
+        );
+    }
+
+    #[test]
+    fn test_target_without_select_is_json() {
+        let node = format().target_node(node("t").as_ref()).unwrap();
+        let labels = node
+            .attrs
+            .iter()
+            .find(|a| a.name == "labels")
+            .and_then(|a| a.value.clone());
+        assert_eq!(Some(json(r#"["a","b"]"#)), labels);
+        assert_eq!(None, node.oncall);
+    }
+}
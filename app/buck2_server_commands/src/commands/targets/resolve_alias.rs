@@ -13,6 +13,8 @@
 enum ResolveAliasError {
     #[error("`--stat` format is not supported by `--resolve-alias`")]
     StatFormatNotSupported,
+    #[error("`--output-format=proto` is not supported by `--resolve-alias`")]
+    ProtoFormatNotSupported,
 }
 
 use std::collections::HashMap;
@@ -161,6 +163,7 @@ pub(crate) async fn targets_resolve_aliases(
             &json_writer as &dyn ResolveAliasFormatter
         }
         OutputFormat::Stats => return Err(ResolveAliasError::StatFormatNotSupported.into()),
+        OutputFormat::Proto => return Err(ResolveAliasError::ProtoFormatNotSupported.into()),
     };
 
     let mut needs_separator = false;
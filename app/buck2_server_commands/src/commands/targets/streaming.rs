@@ -248,7 +248,7 @@ pub(crate) async fn targets_streaming(
 }
 
 /// Given the patterns, separate into those which have an explicit package, and those which are recursive
-fn stream_packages<'a, T: PatternType>(
+pub(crate) fn stream_packages<'a, T: PatternType>(
     dice: &'a DiceTransaction,
     patterns: Vec<ParsedPattern<T>>,
 ) -> impl Stream<Item = anyhow::Result<(PackageLabel, PackageSpec<T>)>> + 'a {
@@ -283,7 +283,7 @@ enum TargetsError {
 }
 
 /// Load the targets from a package. If `keep_going` is specified then it may return a `Some` error in the triple.
-async fn load_targets(
+pub(crate) async fn load_targets(
     dice: &mut DiceComputations<'_>,
     package: PackageLabel,
     spec: PackageSpec<TargetPatternExtra>,
@@ -19,6 +19,7 @@ use buck2_build_api::configure_targets::get_compatible_targets;
 use buck2_common::dice::file_ops::DiceFileComputations;
 use buck2_common::file_ops::PathMetadata;
 use buck2_common::file_ops::PathMetadataOrRedirection;
+use buck2_common::file_ops::RawPathMetadata;
 use buck2_common::global_cfg_options::GlobalCfgOptions;
 use buck2_core::cells::cell_path::CellPath;
 use buck2_core::cells::cell_path::CellPathRef;
@@ -131,6 +132,16 @@ impl FileHasher for PathsAndContentsHasher {
             res: &mut Vec<u8>,
         ) -> anyhow::Result<()> {
             let info = DiceFileComputations::read_path_metadata(ctx, cell_path.dupe()).await?;
+            hash_metadata(ctx, cell_path, info, res).await
+        }
+
+        #[async_recursion]
+        async fn hash_metadata(
+            ctx: &mut DiceComputations<'_>,
+            cell_path: CellPathRef<'async_recursion>,
+            info: RawPathMetadata,
+            res: &mut Vec<u8>,
+        ) -> anyhow::Result<()> {
             // Important that the different branches can never clash, so add a prefix byte to them
             match PathMetadataOrRedirection::from(info) {
                 PathMetadataOrRedirection::PathMetadata(meta) => match meta {
@@ -153,15 +164,22 @@ impl FileHasher for PathsAndContentsHasher {
                     }
                     PathMetadata::Directory => {
                         res.push(2u8);
-                        let files = DiceFileComputations::read_dir(ctx, cell_path.dupe())
-                            .await?
-                            .included;
-                        res.extend(files.len().to_be_bytes());
-                        for x in &*files {
+                        // Fetch the metadata of all the entries at once rather than one at a time.
+                        let entries =
+                            DiceFileComputations::read_dir_with_metadata(ctx, cell_path.dupe())
+                                .await?;
+                        res.extend(entries.len().to_be_bytes());
+                        for x in &*entries {
                             let name = x.file_name.as_str();
                             res.extend(name.len().to_be_bytes());
                             res.extend(name.as_bytes());
-                            hash_item(ctx, cell_path.join(&x.file_name).as_ref(), res).await?;
+                            hash_metadata(
+                                ctx,
+                                cell_path.join(&x.file_name).as_ref(),
+                                x.metadata.clone(),
+                                res,
+                            )
+                            .await?;
                         }
                     }
                 },
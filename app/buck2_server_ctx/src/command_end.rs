@@ -9,12 +9,26 @@
 
 use buck2_events::errors::create_error_report;
 
+use crate::resource_usage::CommandResourceTracker;
+
 /// Common code executed in the end of command to produce `CommandEnd`.
-pub fn command_end<R, D>(result: &buck2_error::Result<R>, data: D) -> buck2_data::CommandEnd
+///
+/// `resource_tracker` should be started when the command starts.
+pub fn command_end<R, D>(
+    result: &buck2_error::Result<R>,
+    data: D,
+    resource_tracker: CommandResourceTracker,
+) -> buck2_data::CommandEnd
 where
     D: Into<buck2_data::command_end::Data>,
 {
-    command_end_ext(result, data.into(), |_| true, |_| Vec::new())
+    command_end_ext(
+        result,
+        data.into(),
+        |_| true,
+        |_| Vec::new(),
+        resource_tracker,
+    )
 }
 
 pub fn command_end_ext<R, D, F, G>(
@@ -22,6 +36,7 @@ pub fn command_end_ext<R, D, F, G>(
     data: D,
     is_success: F,
     additional_telemetry_errors: G,
+    resource_tracker: CommandResourceTracker,
 ) -> buck2_data::CommandEnd
 where
     F: FnOnce(&R) -> bool,
@@ -36,5 +51,6 @@ where
         is_success,
         errors,
         data: Some(data.into()),
+        resource_usage: Some(resource_tracker.finish()),
     }
 }
@@ -16,6 +16,7 @@
 use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::sync::Arc;
+use std::time::Duration;
 
 use allocative::Allocative;
 use anyhow::Context;
@@ -31,7 +32,10 @@ use buck2_data::ExclusiveCommandWaitEnd;
 use buck2_data::ExclusiveCommandWaitStart;
 use buck2_data::NoActiveDiceState;
 use buck2_events::dispatch::EventDispatcher;
+use buck2_futures::cancellation::CancellationReason;
+use buck2_futures::cancellation::CancellationReasonKind;
 use buck2_futures::cancellation::ExplicitCancellationContext;
+use buck2_util::late_binding::LateBinding;
 use buck2_util::truncate::truncate;
 use buck2_wrapper_common::invocation_id::TraceId;
 use derive_more::Display;
@@ -43,12 +47,14 @@ use dice::DiceTransactionUpdater;
 use dice::UserComputationData;
 use dupe::Dupe;
 use futures::future::BoxFuture;
+use futures::future::Either;
 use futures::future::Future;
 use futures::future::FutureExt;
 use futures::future::Shared;
 use itertools::Itertools;
 use starlark_map::small_map::SmallMap;
 use starlark_map::small_set::SmallSet;
+use tokio::sync::oneshot;
 use tokio::sync::Mutex;
 use tokio::sync::MutexGuard;
 
@@ -66,8 +72,22 @@ enum ConcurrencyHandlerError {
     #[error("`--exit-when-different-state` was set")]
     #[buck2(tag = DaemonIsBusy)]
     ExitWhenDifferentState,
+    #[error(
+        "Command was preempted by a newer invocation. Trace Id: {0}. Newer invocation command: `{1}`"
+    )]
+    #[buck2(tag = PreemptedByNewerInvocation)]
+    Preempted(TraceId, String),
 }
 
+/// What the command with the given trace id is doing, if it is running. Used to tell commands
+/// that are blocked on it how far along it is.
+pub static CONCURRENT_COMMAND_PROGRESS: LateBinding<
+    fn(&TraceId) -> Option<buck2_data::ConcurrentCommandProgress>,
+> = LateBinding::new("CONCURRENT_COMMAND_PROGRESS");
+
+/// How often a blocked command is told about the progress of the command it is waiting for.
+const CONCURRENT_COMMAND_PROGRESS_INTERVAL: Duration = Duration::from_secs(3);
+
 #[derive(Clone, Dupe, Copy, Debug)]
 pub enum RunState {
     NestedSameState,
@@ -81,6 +101,29 @@ pub enum BypassSemaphore {
     Error,
 }
 
+/// When a running command may be cancelled in favour of a newer command entering the daemon.
+#[derive(Clone, Dupe, Copy, Debug, PartialEq, Eq, Allocative)]
+pub enum PreemptibleWhen {
+    /// The command is never preempted.
+    Never,
+    /// The command is preempted by a newer command that requires a different state.
+    OnDifferentState,
+    /// The command is preempted by any newer command.
+    Always,
+}
+
+impl PreemptibleWhen {
+    /// Whether a running command should be cancelled when a new command is admitted, given
+    /// whether the new command uses the same state as the running one.
+    fn should_preempt(self, is_same_state: bool) -> bool {
+        match self {
+            PreemptibleWhen::Never => false,
+            PreemptibleWhen::OnDifferentState => !is_same_state,
+            PreemptibleWhen::Always => true,
+        }
+    }
+}
+
 /// Manages concurrent commands, blocking when appropriate.
 ///
 /// Currently, we allow concurrency if two `DiceTransactions` are deemed equivalent, such that
@@ -129,16 +172,26 @@ struct CommandData {
     trace_id: TraceId,
     argv: Vec<String>,
     dispatcher: EventDispatcher,
+    preemptible: PreemptibleWhen,
+    /// Used to tell the command that it was preempted by a newer command. Taken once the command
+    /// has been preempted.
+    #[allocative(skip)]
+    preempt: Option<oneshot::Sender<ConcurrencyHandlerError>>,
+}
+
+/// Format a sanitized argv to show it to other commands.
+pub fn format_argv(argv: &[String]) -> String {
+    let mut iter = argv.iter();
+    // Skip the "/path/to/buck2" part so we can just emit "buck2" for the start of the cmd
+    iter.next();
+
+    let cmd = format!("buck2 {}", iter.join(" "));
+    truncate(&cmd, 500)
 }
 
 impl CommandData {
     fn format_argv(&self) -> String {
-        let mut iter = self.argv.iter();
-        // Skip the "/path/to/buck2" part so we can just emit "buck2" for the start of the cmd
-        iter.next();
-
-        let cmd = format!("buck2 {}", iter.join(" "));
-        truncate(&cmd, 500)
+        format_argv(&self.argv)
     }
 
     fn notify_tainted(&self) {
@@ -152,6 +205,11 @@ impl CommandData {
             tags: vec!["concurrency-previously-tainted".to_owned()],
         });
     }
+
+    /// Whether this command is running but will exit soon because it was preempted.
+    fn is_preempted(&self) -> bool {
+        self.preempt.is_none()
+    }
 }
 
 #[derive(Allocative)]
@@ -234,6 +292,31 @@ impl ConcurrencyHandlerData {
             command.notify_tainted()
         }
     }
+
+    /// Cancels the active commands that can be preempted by `new_command`. Returns whether all
+    /// the active commands are now exiting.
+    fn preempt_active_commands(&mut self, new_command: &CommandData, is_same_state: bool) -> bool {
+        for command in self.active_commands.values_mut() {
+            if !command.preemptible.should_preempt(is_same_state) {
+                continue;
+            }
+            if let Some(preempt) = command.preempt.take() {
+                tracing::info!(
+                    "Preempting command {} for {}",
+                    command.trace_id,
+                    new_command.trace_id
+                );
+                // The command may have finished in the meantime, in which case there is nothing
+                // to cancel.
+                let _ignored = preempt.send(ConcurrencyHandlerError::Preempted(
+                    new_command.trace_id.dupe(),
+                    new_command.format_argv(),
+                ));
+            }
+        }
+
+        self.active_commands.values().all(|c| c.is_preempted())
+    }
 }
 
 #[async_trait]
@@ -333,12 +416,20 @@ impl ConcurrencyHandler {
         sanitized_argv: Vec<String>,
         exclusive_cmd: Option<String>,
         exit_when_different_state: bool,
+        preemptible: PreemptibleWhen,
         cancellations: &ExplicitCancellationContext,
     ) -> anyhow::Result<R>
     where
         F: FnOnce(DiceTransaction) -> Fut,
         Fut: Future<Output = R> + Send,
     {
+        // Exclusive commands are not preempted, they must not be interrupted half way through.
+        let preemptible = if exclusive_cmd.is_some() {
+            PreemptibleWhen::Never
+        } else {
+            preemptible
+        };
+
         let _exclusive_command_guard = event_dispatcher
             .span_async(
                 ExclusiveCommandWaitStart {
@@ -358,7 +449,7 @@ impl ConcurrencyHandler {
             .await;
 
         let events = event_dispatcher.dupe();
-        let (_guard, transaction) = event_dispatcher
+        let (_guard, transaction, preempted) = event_dispatcher
             .span_async(DiceSynchronizeSectionStart {}, async move {
                 (
                     cancellations
@@ -370,6 +461,7 @@ impl ConcurrencyHandler {
                                 is_nested_invocation,
                                 sanitized_argv,
                                 exit_when_different_state,
+                                preemptible,
                             )
                         })
                         .await,
@@ -378,7 +470,17 @@ impl ConcurrencyHandler {
             })
             .await?;
 
-        Ok(exec(transaction).await)
+        // When preempted, the command is cancelled like it would be if the client went away: it
+        // is dropped once it's out of any critical section, which releases its transaction and
+        // cancels the computations that only it was waiting on. The newer command waits for them
+        // to be cleaned up before using a different state.
+        Ok(cancellations
+            .cancel_when(
+                exec(transaction),
+                preempted,
+                CancellationReason::new(CancellationReasonKind::Preempted),
+            )
+            .await?)
     }
 
     // this is normally super unsafe, but because we are using an async condvar that takes care
@@ -393,7 +495,12 @@ impl ConcurrencyHandler {
         is_nested_invocation: bool,
         sanitized_argv: Vec<String>,
         exit_when_different_state: bool,
-    ) -> anyhow::Result<(OnExecExit, DiceTransaction)> {
+        preemptible: PreemptibleWhen,
+    ) -> anyhow::Result<(
+        OnExecExit,
+        DiceTransaction,
+        BoxFuture<'static, ConcurrencyHandlerError>,
+    )> {
         // Have to put it on the function unfortunately, https://github.com/rust-lang/rust-clippy/issues/9047
         #![allow(clippy::await_holding_invalid_type)]
 
@@ -408,11 +515,23 @@ impl ConcurrencyHandler {
 
         let command_id = data.next_command_id.increment();
 
+        let (preempt, preempted) = oneshot::channel();
         let command_data = CommandData {
             trace_id: trace.dupe(),
             argv: sanitized_argv,
             dispatcher: event_dispatcher.dupe(),
+            preemptible,
+            preempt: Some(preempt),
         };
+        // The sender is only dropped once the command has exited, so a closed channel means we
+        // were never preempted.
+        let preempted = async move {
+            match preempted.await {
+                Ok(error) => error,
+                Err(_) => futures::future::pending().await,
+            }
+        }
+        .boxed();
 
         let (transaction, tainted) = loop {
             match &data.dice_status {
@@ -483,6 +602,10 @@ impl ConcurrencyHandler {
                             is_equal: is_same_state,
                         });
 
+                        // A nested invocation must not cancel the command that spawned it.
+                        let all_preempted = !is_nested_invocation
+                            && data.preempt_active_commands(&command_data, is_same_state);
+
                         let bypass_semaphore =
                             self.determine_bypass_semaphore(is_same_state, is_nested_invocation);
 
@@ -501,7 +624,9 @@ impl ConcurrencyHandler {
                                 break (transaction, false);
                             }
                             BypassSemaphore::Block => {
-                                if exit_when_different_state {
+                                // Commands that were preempted will exit soon, so no need to
+                                // give up on waiting for them.
+                                if exit_when_different_state && !all_preempted {
                                     return Err(ConcurrencyHandlerError::ExitWhenDifferentState)
                                         .context("Buck daemon is busy processing another command");
                                 }
@@ -523,7 +648,12 @@ impl ConcurrencyHandler {
                                         },
                                         async {
                                             (
-                                                self.cond.wait((data, &*self.data)).await,
+                                                wait_reporting_progress(
+                                                    self.cond.wait((data, &*self.data)),
+                                                    &event_dispatcher,
+                                                    &trace_id,
+                                                )
+                                                .await,
                                                 DiceBlockConcurrentCommandEnd {
                                                     ending_active_trace_id: trace_id.to_string(),
                                                 },
@@ -558,7 +688,7 @@ impl ConcurrencyHandler {
         // create the on exit drop handler, which will take care of notifying tasks.
         let drop_guard = OnExecExit::new(self.dupe(), command_id, command_data, data);
 
-        Ok((drop_guard, transaction))
+        Ok((drop_guard, transaction, preempted))
     }
 
     /// Access dice without locking for dumps.
@@ -609,6 +739,33 @@ impl ConcurrencyHandler {
     }
 }
 
+/// Wait for `wait`, sending the progress of the command with `trace_id` to `event_dispatcher`
+/// while doing so.
+async fn wait_reporting_progress<R>(
+    wait: impl Future<Output = R>,
+    event_dispatcher: &EventDispatcher,
+    trace_id: &TraceId,
+) -> R {
+    let report = async {
+        let Ok(progress) = CONCURRENT_COMMAND_PROGRESS.get() else {
+            return futures::future::pending().await;
+        };
+        let mut interval = tokio::time::interval(CONCURRENT_COMMAND_PROGRESS_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Some(progress) = progress(trace_id) {
+                event_dispatcher.instant_event(progress);
+            }
+        }
+    };
+
+    futures::pin_mut!(wait, report);
+    match futures::future::select(wait, report).await {
+        Either::Left((res, _)) => res,
+        Either::Right(((), _)) => unreachable!("progress reporting never finishes"),
+    }
+}
+
 fn format_traces(
     active_commands: &SmallMap<CommandId, CommandData>,
     current: &CommandData,
@@ -783,6 +940,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
         let fut2 = concurrency.enter(
@@ -799,6 +957,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
         let fut3 = concurrency.enter(
@@ -815,6 +974,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
 
@@ -849,6 +1009,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
 
@@ -866,6 +1027,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
 
@@ -903,6 +1065,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
         let fut2 = concurrency.enter(
@@ -919,6 +1082,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
         let fut3 = concurrency.enter(
@@ -935,6 +1099,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
 
@@ -984,6 +1149,7 @@ mod tests {
                         Vec::new(),
                         None,
                         false,
+                        PreemptibleWhen::Never,
                         ExplicitCancellationContext::testing(),
                     )
                     .await
@@ -1009,6 +1175,7 @@ mod tests {
                         Vec::new(),
                         None,
                         false,
+                        PreemptibleWhen::Never,
                         ExplicitCancellationContext::testing(),
                     )
                     .await
@@ -1036,6 +1203,7 @@ mod tests {
                         Vec::new(),
                         None,
                         false,
+                        PreemptibleWhen::Never,
                         ExplicitCancellationContext::testing(),
                     )
                     .await
@@ -1101,6 +1269,7 @@ mod tests {
                         Vec::new(),
                         None,
                         true,
+                        PreemptibleWhen::Never,
                         ExplicitCancellationContext::testing(),
                     )
                     .await
@@ -1126,6 +1295,7 @@ mod tests {
                         Vec::new(),
                         None,
                         true,
+                        PreemptibleWhen::Never,
                         ExplicitCancellationContext::testing(),
                     )
                     .await
@@ -1153,6 +1323,7 @@ mod tests {
                         Vec::new(),
                         None,
                         true,
+                        PreemptibleWhen::Never,
                         ExplicitCancellationContext::testing(),
                     )
                     .await
@@ -1183,6 +1354,114 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_should_preempt() {
+        assert!(!PreemptibleWhen::Never.should_preempt(true));
+        assert!(!PreemptibleWhen::Never.should_preempt(false));
+        assert!(!PreemptibleWhen::OnDifferentState.should_preempt(true));
+        assert!(PreemptibleWhen::OnDifferentState.should_preempt(false));
+        assert!(PreemptibleWhen::Always.should_preempt(true));
+        assert!(PreemptibleWhen::Always.should_preempt(false));
+    }
+
+    /// Starts a command that never finishes on its own, then runs a second command with `updates`
+    /// to completion. Returns the first command.
+    async fn run_after_never_ending_command(
+        preemptible: PreemptibleWhen,
+        updates: &'static dyn DiceUpdater,
+    ) -> anyhow::Result<tokio::task::JoinHandle<anyhow::Result<()>>> {
+        let dice = Dice::builder().build(DetectCycles::Enabled);
+
+        let concurrency = ConcurrencyHandler::new(dice);
+
+        let running = Arc::new(Barrier::new(2));
+
+        let fut1 = tokio::spawn({
+            let concurrency = concurrency.dupe();
+            let running = running.dupe();
+
+            async move {
+                concurrency
+                    .enter(
+                        EventDispatcher::null_sink_with_trace(TraceId::new()),
+                        &TestDiceDataProvider,
+                        &NoChanges,
+                        |_| async move {
+                            running.wait().await;
+                            futures::future::pending::<()>().await
+                        },
+                        false,
+                        Vec::new(),
+                        None,
+                        false,
+                        preemptible,
+                        ExplicitCancellationContext::testing(),
+                    )
+                    .await
+            }
+        });
+
+        running.wait().await;
+
+        concurrency
+            .enter(
+                EventDispatcher::null_sink_with_trace(TraceId::new()),
+                &TestDiceDataProvider,
+                updates,
+                |_| async move {},
+                false,
+                Vec::new(),
+                None,
+                false,
+                PreemptibleWhen::Never,
+                ExplicitCancellationContext::testing(),
+            )
+            .await?;
+
+        Ok(fut1)
+    }
+
+    fn assert_preempted(result: anyhow::Result<()>) {
+        let error: buck2_error::Error = result.unwrap_err().into();
+        assert!(
+            error
+                .tags()
+                .contains(&buck2_error::ErrorTag::PreemptedByNewerInvocation),
+            "{:?}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preemptible_on_different_state() -> anyhow::Result<()> {
+        let fut1 = run_after_never_ending_command(PreemptibleWhen::OnDifferentState, &CtxDifferent)
+            .await?;
+        assert_preempted(fut1.await?);
+
+        // With the same state, both commands run concurrently and the first one is left alone.
+        let mut fut1 =
+            run_after_never_ending_command(PreemptibleWhen::OnDifferentState, &NoChanges).await?;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), &mut fut1)
+                .await
+                .is_err()
+        );
+        fut1.abort();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_preemptible_always() -> anyhow::Result<()> {
+        let fut1 = run_after_never_ending_command(PreemptibleWhen::Always, &NoChanges).await?;
+        assert_preempted(fut1.await?);
+
+        let fut1 = run_after_never_ending_command(PreemptibleWhen::Always, &CtxDifferent).await?;
+        assert_preempted(fut1.await?);
+
+        Ok(())
+    }
+
     #[derive(Clone, Dupe, Derivative, Allocative, Display)]
     #[derivative(Hash, Eq, PartialEq, Debug)]
     #[display(fmt = "CleanupTestKey")]
@@ -1258,6 +1537,7 @@ mod tests {
                 Vec::new(),
                 None,
                 false,
+                PreemptibleWhen::Never,
                 ExplicitCancellationContext::testing(),
             )
             .await?;
@@ -1277,6 +1557,7 @@ mod tests {
                 Vec::new(),
                 None,
                 false,
+                PreemptibleWhen::Never,
                 ExplicitCancellationContext::testing(),
             )
             .await?;
@@ -1295,6 +1576,7 @@ mod tests {
                 Vec::new(),
                 None,
                 false,
+                PreemptibleWhen::Never,
                 ExplicitCancellationContext::testing(),
             )
             .await?;
@@ -1403,6 +1685,7 @@ mod tests {
                             Vec::new(),
                             exclusive_cmd,
                             false,
+                            PreemptibleWhen::Never,
                             ExplicitCancellationContext::testing(),
                         )
                         .await
@@ -1478,6 +1761,7 @@ mod tests {
                     Vec::new(),
                     None,
                     false,
+                    PreemptibleWhen::Never,
                     ExplicitCancellationContext::testing(),
                 )
                 .await
@@ -1534,6 +1818,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
         pin_mut!(fut1);
@@ -1553,6 +1838,7 @@ mod tests {
             Vec::new(),
             None,
             false,
+            PreemptibleWhen::Never,
             ExplicitCancellationContext::testing(),
         );
         pin_mut!(fut2);
@@ -35,6 +35,7 @@ use dupe::Dupe;
 use crate::concurrency::ConcurrencyHandler;
 use crate::concurrency::DiceDataProvider;
 use crate::concurrency::DiceUpdater;
+use crate::concurrency::PreemptibleWhen;
 use crate::stderr_output_guard::StderrOutputGuard;
 
 #[async_trait]
@@ -87,6 +88,7 @@ pub struct DiceAccessor {
     pub is_nested_invocation: bool,
     pub sanitized_argv: Vec<String>,
     pub exit_when_different_state: bool,
+    pub preemptible: PreemptibleWhen,
     pub build_signals: Box<dyn DeferredBuildSignals>,
 }
 
@@ -138,6 +140,7 @@ impl ServerCommandDiceContext for dyn ServerCommandContextTrait + '_ {
             is_nested_invocation,
             sanitized_argv,
             exit_when_different_state,
+            preemptible,
             build_signals,
         } = self.dice_accessor(PrivateStruct(())).await?;
 
@@ -202,6 +205,7 @@ impl ServerCommandDiceContext for dyn ServerCommandContextTrait + '_ {
                             sanitized_argv,
                             exclusive_cmd,
                             exit_when_different_state,
+                            preemptible,
                             self.cancellation_context(),
                         )
                         .await,
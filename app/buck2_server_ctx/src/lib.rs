@@ -19,6 +19,7 @@ pub mod logging;
 pub mod other_server_commands;
 pub mod partial_result_dispatcher;
 pub mod pattern;
+pub mod resource_usage;
 pub mod stderr_output_guard;
 pub mod stdout_partial_output;
 pub mod streaming_request_handler;
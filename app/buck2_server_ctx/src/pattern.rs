@@ -9,12 +9,18 @@
 
 use buck2_cli_proto::TargetCfg;
 use buck2_common::dice::cells::HasCellResolver;
+use buck2_common::dice::file_ops::DiceFileOps;
 use buck2_common::global_cfg_options::GlobalCfgOptions;
+use buck2_common::legacy_configs::dice::HasLegacyConfigs;
+use buck2_common::legacy_configs::key::BuckconfigKeyRef;
 use buck2_common::pattern::resolve::ResolveTargetPatterns;
 use buck2_common::pattern::resolve::ResolvedPattern;
+use buck2_common::pattern::strict::check_relative_pattern;
 use buck2_common::target_aliases::BuckConfigTargetAliasResolver;
 use buck2_common::target_aliases::HasTargetAliasResolver;
 use buck2_core::cells::cell_path::CellPath;
+use buck2_core::cells::cell_path::CellPathRef;
+use buck2_core::cells::paths::CellRelativePath;
 use buck2_core::cells::CellAliasResolver;
 use buck2_core::cells::CellResolver;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
@@ -35,6 +41,9 @@ pub struct PatternParser {
     cell_alias_resolver: CellAliasResolver,
     cwd: CellPath,
     target_alias_resolver: BuckConfigTargetAliasResolver,
+    /// Reject relative patterns that don't refer to an existing package
+    /// (`buck2.strict_target_patterns`).
+    strict: bool,
 }
 
 impl PatternParser {
@@ -49,24 +58,67 @@ impl PatternParser {
 
         let target_alias_resolver = ctx.target_alias_resolver_for_cell(cell_name).await?;
         let cell_alias_resolver = ctx.get_cell_alias_resolver(cell_name).await?;
+        let strict = ctx
+            .parse_legacy_config_property(
+                cell_name,
+                BuckconfigKeyRef {
+                    section: "buck2",
+                    property: "strict_target_patterns",
+                },
+            )
+            .await?
+            .unwrap_or(false);
 
         Ok(Self {
             cell_resolver,
             cell_alias_resolver,
             cwd,
             target_alias_resolver,
+            strict,
         })
     }
 
     pub fn parse_pattern<T: PatternType>(&self, pattern: &str) -> anyhow::Result<ParsedPattern<T>> {
+        self.parse_pattern_relative_to(self.cwd.as_ref(), pattern)
+    }
+
+    fn parse_pattern_relative_to<T: PatternType>(
+        &self,
+        relative_dir: CellPathRef,
+        pattern: &str,
+    ) -> anyhow::Result<ParsedPattern<T>> {
         ParsedPattern::parse_relaxed(
             &self.target_alias_resolver,
-            self.cwd.as_ref(),
+            relative_dir,
             pattern,
             &self.cell_resolver,
             &self.cell_alias_resolver,
         )
     }
+
+    /// In strict mode, check that relative patterns refer to existing packages, suggesting the
+    /// cell root interpretation when that is the one which exists.
+    async fn check_strict<T: PatternType>(
+        &self,
+        ctx: &mut DiceComputations<'_>,
+        target_patterns: &[buck2_data::TargetPattern],
+        parsed_patterns: &[ParsedPattern<T>],
+    ) -> anyhow::Result<()> {
+        if !self.strict {
+            return Ok(());
+        }
+
+        let cell_root = CellPathRef::new(self.cwd.cell(), CellRelativePath::empty());
+        ctx.with_linear_recompute(|ctx| async move {
+            let file_ops = DiceFileOps(&ctx);
+            for (value, parsed) in target_patterns.iter().zip(parsed_patterns) {
+                let cell_root_parsed = self.parse_pattern_relative_to(cell_root, &value.value)?;
+                check_relative_pattern(&file_ops, &value.value, parsed, &cell_root_parsed).await?;
+            }
+            anyhow::Ok(())
+        })
+        .await
+    }
 }
 
 /// Parse target patterns out of command line arguments.
@@ -81,7 +133,9 @@ pub async fn parse_patterns_from_cli_args<T: PatternType>(
 ) -> anyhow::Result<Vec<ParsedPattern<T>>> {
     let parser = PatternParser::new(ctx, cwd).await?;
 
-    target_patterns.try_map(|value| parser.parse_pattern(&value.value))
+    let patterns = target_patterns.try_map(|value| parser.parse_pattern(&value.value))?;
+    parser.check_strict(ctx, target_patterns, &patterns).await?;
+    Ok(patterns)
 }
 
 pub async fn parse_and_resolve_patterns_from_cli_args<T: PatternType>(
@@ -0,0 +1,270 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Per-command attribution of daemon resource usage.
+//!
+//! Resource counters are process-wide, so when commands overlap there is no way to know which
+//! command used what. Rather than inventing a split, we record raw deltas over the command's
+//! lifetime together with how much of that lifetime was shared with other commands.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use buck2_util::resource_usage::resource_usage_collector;
+use buck2_util::resource_usage::ResourceUsage;
+use parking_lot::const_mutex;
+use parking_lot::Mutex;
+
+static RUNNING_COMMANDS: Mutex<RunningCommands> = const_mutex(RunningCommands::new());
+
+/// Tracks how long a command ran concurrently with other commands.
+#[derive(Debug, Clone)]
+struct CommandOverlap {
+    start: Instant,
+    last_change: Instant,
+    others_running: usize,
+    max_others_running: usize,
+    overlapped: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OverlapSummary {
+    wall_time: Duration,
+    overlapped: Duration,
+    max_others_running: usize,
+}
+
+impl OverlapSummary {
+    /// Fraction of the wall time during which at least one other command was running.
+    fn fraction(&self) -> f64 {
+        if self.wall_time.is_zero() {
+            0.0
+        } else {
+            self.overlapped.as_secs_f64() / self.wall_time.as_secs_f64()
+        }
+    }
+}
+
+impl CommandOverlap {
+    fn new(now: Instant, others_running: usize) -> Self {
+        CommandOverlap {
+            start: now,
+            last_change: now,
+            others_running,
+            max_others_running: others_running,
+            overlapped: Duration::ZERO,
+        }
+    }
+
+    fn advance(&mut self, now: Instant) {
+        if self.others_running > 0 {
+            self.overlapped += now.saturating_duration_since(self.last_change);
+        }
+        self.last_change = now;
+    }
+
+    fn set_others_running(&mut self, now: Instant, others_running: usize) {
+        self.advance(now);
+        self.others_running = others_running;
+        self.max_others_running = self.max_others_running.max(others_running);
+    }
+
+    fn finish(mut self, now: Instant) -> OverlapSummary {
+        self.advance(now);
+        OverlapSummary {
+            wall_time: now.saturating_duration_since(self.start),
+            overlapped: self.overlapped,
+            max_others_running: self.max_others_running,
+        }
+    }
+}
+
+/// Commands currently running in this daemon.
+struct RunningCommands {
+    next_id: u64,
+    commands: BTreeMap<u64, CommandOverlap>,
+}
+
+impl RunningCommands {
+    const fn new() -> Self {
+        RunningCommands {
+            next_id: 0,
+            commands: BTreeMap::new(),
+        }
+    }
+
+    fn start(&mut self, now: Instant) -> u64 {
+        let others_running = self.commands.len();
+        for command in self.commands.values_mut() {
+            let others = command.others_running + 1;
+            command.set_others_running(now, others);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.commands
+            .insert(id, CommandOverlap::new(now, others_running));
+        id
+    }
+
+    fn finish(&mut self, id: u64, now: Instant) -> Option<OverlapSummary> {
+        let command = self.commands.remove(&id)?;
+        for other in self.commands.values_mut() {
+            let others = other.others_running.saturating_sub(1);
+            other.set_others_running(now, others);
+        }
+        Some(command.finish(now))
+    }
+}
+
+/// Records resource usage of the daemon between command start and command end.
+pub struct CommandResourceTracker {
+    id: u64,
+    start: ResourceUsage,
+    finished: bool,
+}
+
+impl CommandResourceTracker {
+    pub fn start() -> Self {
+        let id = RUNNING_COMMANDS.lock().start(Instant::now());
+        CommandResourceTracker {
+            id,
+            start: resource_usage_collector().collect(),
+            finished: false,
+        }
+    }
+
+    pub fn finish(mut self) -> buck2_data::CommandResourceUsage {
+        self.finished = true;
+        let usage = resource_usage_collector()
+            .collect()
+            .delta_since(&self.start);
+        let overlap = RUNNING_COMMANDS.lock().finish(self.id, Instant::now());
+        resource_usage_to_proto(usage, overlap)
+    }
+}
+
+impl Drop for CommandResourceTracker {
+    fn drop(&mut self) {
+        if !self.finished {
+            RUNNING_COMMANDS.lock().finish(self.id, Instant::now());
+        }
+    }
+}
+
+fn resource_usage_to_proto(
+    usage: ResourceUsage,
+    overlap: Option<OverlapSummary>,
+) -> buck2_data::CommandResourceUsage {
+    buck2_data::CommandResourceUsage {
+        user_cpu_us: usage.user_cpu_us,
+        system_cpu_us: usage.system_cpu_us,
+        io_read_bytes: usage.io_read_bytes,
+        io_write_bytes: usage.io_write_bytes,
+        max_rss_bytes: usage.max_rss_bytes,
+        wall_time: overlap.and_then(|o| o.wall_time.try_into().ok()),
+        overlapped_time: overlap.and_then(|o| o.overlapped.try_into().ok()),
+        concurrency_overlap_fraction: overlap.map_or(0.0, |o| o.fraction()),
+        max_concurrent_commands: overlap.map_or(0, |o| o.max_others_running as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn test_no_overlap() {
+        let t0 = Instant::now();
+        let mut commands = RunningCommands::new();
+        let a = commands.start(t0);
+        let summary = commands.finish(a, t0 + Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            OverlapSummary {
+                wall_time: Duration::from_secs(10),
+                overlapped: Duration::ZERO,
+                max_others_running: 0,
+            },
+            summary
+        );
+        assert_eq!(0.0, summary.fraction());
+    }
+
+    #[test]
+    fn test_overlap_bookkeeping() {
+        let t0 = Instant::now();
+        let secs = |s| t0 + Duration::from_secs(s);
+        let mut commands = RunningCommands::new();
+
+        // a: [0, 10), b: [2, 6), c: [4, 12)
+        let a = commands.start(secs(0));
+        let b = commands.start(secs(2));
+        let c = commands.start(secs(4));
+
+        let b_summary = commands.finish(b, secs(6)).unwrap();
+        assert_eq!(
+            OverlapSummary {
+                wall_time: Duration::from_secs(4),
+                overlapped: Duration::from_secs(4),
+                max_others_running: 2,
+            },
+            b_summary
+        );
+        assert_eq!(1.0, b_summary.fraction());
+
+        let a_summary = commands.finish(a, secs(10)).unwrap();
+        assert_eq!(
+            OverlapSummary {
+                wall_time: Duration::from_secs(10),
+                overlapped: Duration::from_secs(8),
+                max_others_running: 2,
+            },
+            a_summary
+        );
+        assert_eq!(0.8, a_summary.fraction());
+
+        let c_summary = commands.finish(c, secs(12)).unwrap();
+        assert_eq!(
+            OverlapSummary {
+                wall_time: Duration::from_secs(8),
+                overlapped: Duration::from_secs(6),
+                max_others_running: 2,
+            },
+            c_summary
+        );
+
+        assert!(commands.commands.is_empty());
+        assert_eq!(None, commands.finish(a, secs(13)));
+    }
+
+    #[test]
+    fn test_to_proto_keeps_raw_deltas() {
+        let usage = ResourceUsage {
+            user_cpu_us: Some(1000),
+            system_cpu_us: Some(200),
+            io_read_bytes: None,
+            io_write_bytes: None,
+            max_rss_bytes: Some(4096),
+        };
+        let overlap = OverlapSummary {
+            wall_time: Duration::from_secs(2),
+            overlapped: Duration::from_secs(1),
+            max_others_running: 1,
+        };
+        let proto = resource_usage_to_proto(usage, Some(overlap));
+        assert_eq!(Some(1000), proto.user_cpu_us);
+        assert_eq!(Some(200), proto.system_cpu_us);
+        assert_eq!(None, proto.io_read_bytes);
+        assert_eq!(0.5, proto.concurrency_overlap_fraction);
+        assert_eq!(1, proto.max_concurrent_commands);
+    }
+}
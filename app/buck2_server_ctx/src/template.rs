@@ -17,6 +17,7 @@ use crate::ctx::ServerCommandContextTrait;
 use crate::ctx::ServerCommandDiceContext;
 use crate::logging::TracingLogFile;
 use crate::partial_result_dispatcher::PartialResultDispatcher;
+use crate::resource_usage::CommandResourceTracker;
 
 /// Typical server command with DICE and span.
 #[async_trait]
@@ -83,6 +84,8 @@ pub async fn run_server_command<T: ServerCommandTemplate>(
     // refresh our tracing log per command
     TracingLogFile::refresh()?;
 
+    let resource_tracker = CommandResourceTracker::start();
+
     span_async(start_event, async {
         let result = server_ctx
             .with_dice_ctx_maybe_exclusive(
@@ -102,6 +105,7 @@ pub async fn run_server_command<T: ServerCommandTemplate>(
             command.end_event(&result),
             |result| command.is_success(result),
             |result| command.additional_telemetry_errors(result),
+            resource_tracker,
         );
         (result.map_err(Into::into), end_event)
     })
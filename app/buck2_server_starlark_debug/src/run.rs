@@ -11,6 +11,7 @@ use buck2_events::dispatch::span_async;
 use buck2_server_ctx::command_end::command_end;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
+use buck2_server_ctx::resource_usage::CommandResourceTracker;
 use buck2_server_ctx::streaming_request_handler::StreamingRequestHandler;
 use debugserver_types as dap;
 use tokio::select;
@@ -51,10 +52,15 @@ pub async fn run_dap_server_command(
         data: Some(buck2_data::StarlarkDebugAttachCommandStart {}.into()),
     };
     span_async(start_event, async move {
+        let resource_tracker = CommandResourceTracker::start();
         let result = run_dap_server(ctx, partial_result_dispatcher, req)
             .await
             .map_err(Into::into);
-        let end_event = command_end(&result, buck2_data::StarlarkDebugAttachCommandEnd {});
+        let end_event = command_end(
+            &result,
+            buck2_data::StarlarkDebugAttachCommandEnd {},
+            resource_tracker,
+        );
         (result.map_err(Into::into), end_event)
     })
     .await
@@ -11,6 +11,7 @@ use buck2_events::dispatch::span_async;
 use buck2_server_ctx::command_end::command_end;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
+use buck2_server_ctx::resource_usage::CommandResourceTracker;
 
 use crate::StarlarkOpaqueCommand;
 
@@ -39,10 +40,11 @@ async fn server_starlark_command_inner(
     anyhow::Result<buck2_cli_proto::GenericResponse>,
     buck2_data::CommandEnd,
 ) {
+    let resource_tracker = CommandResourceTracker::start();
     let result = parse_command_and_execute(context, partial_result_dispatcher, req)
         .await
         .map_err(Into::into);
-    let end_event = command_end(&result, buck2_data::StarlarkCommandEnd {});
+    let end_event = command_end(&result, buck2_data::StarlarkCommandEnd {}, resource_tracker);
 
     let result = result
         .map(|()| buck2_cli_proto::GenericResponse {})
@@ -30,9 +30,10 @@ use buck2_common::events::HasEvents;
 use buck2_common::global_cfg_options::GlobalCfgOptions;
 use buck2_common::legacy_configs::dice::HasLegacyConfigs;
 use buck2_common::legacy_configs::key::BuckconfigKeyRef;
+use buck2_common::liveliness_observer::CompositeLivelinessObserver;
 use buck2_common::liveliness_observer::LivelinessGuard;
 use buck2_common::liveliness_observer::LivelinessObserver;
-use buck2_common::liveliness_observer::LivelinessObserverExt;
+use buck2_common::liveliness_observer::NamedLivelinessObserver;
 use buck2_common::liveliness_observer::TimeoutLivelinessObserver;
 use buck2_common::pattern::resolve::ResolveTargetPatterns;
 use buck2_common::pattern::resolve::ResolvedPattern;
@@ -449,7 +450,10 @@ async fn test_targets(
         Arc::new(TimeoutLivelinessObserver::new(timeout)) as Arc<dyn LivelinessObserver>
     });
     if let Some(timeout_observer) = &timeout_observer {
-        liveliness_observer = Arc::new(liveliness_observer.and(timeout_observer.dupe())) as _;
+        liveliness_observer = Arc::new(CompositeLivelinessObserver::join(vec![
+            NamedLivelinessObserver::create("test_session", liveliness_observer),
+            NamedLivelinessObserver::create("timeout", timeout_observer.dupe()),
+        ])) as _;
     }
 
     let tpx_args = {
@@ -59,6 +59,7 @@ impl LocalResourcesSetupResult {
 mod tests {
     use buck2_common::local_resource_state::EnvironmentVariable;
     use buck2_common::local_resource_state::LocalResource;
+    use buck2_common::local_resource_state::LocalResourceOwner;
     use buck2_core::configuration::data::ConfigurationData;
     use buck2_core::target::configured_target_label::ConfiguredTargetLabel;
     use indexmap::indexmap;
@@ -83,9 +84,15 @@ mod tests {
         };
         let state = setup_result.into_state(target, &provider_env_mapping)?;
         assert_eq!(state.owning_pid(), Some(42));
-        let holder1 = state.acquire_resource().await;
-        let holder2 = state.acquire_resource().await;
-        let holder3 = state.acquire_resource().await;
+        let holder1 = state
+            .acquire_resource(LocalResourceOwner::testing_new("test"), None)
+            .await?;
+        let holder2 = state
+            .acquire_resource(LocalResourceOwner::testing_new("test"), None)
+            .await?;
+        let holder3 = state
+            .acquire_resource(LocalResourceOwner::testing_new("test"), None)
+            .await?;
         assert_eq!(
             holder1.as_ref(),
             &LocalResource(vec![EnvironmentVariable {
@@ -21,6 +21,7 @@ pub mod late_binding;
 pub mod per_thread_instruction_counter;
 pub mod process;
 pub mod process_stats;
+pub mod resource_usage;
 pub mod rtabort;
 pub mod self_ref;
 pub mod system_stats;
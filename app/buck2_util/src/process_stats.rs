@@ -15,17 +15,27 @@ pub struct ProcessStats {
     pub max_rss_bytes: Option<u64>,
     pub user_cpu_us: Option<u64>,
     pub system_cpu_us: Option<u64>,
+    /// Cumulative.
+    pub major_page_faults: Option<u64>,
 }
 
+/// `getrusage` for the current process.
 #[cfg(unix)]
-pub fn process_stats() -> ProcessStats {
-    use crate::process_stats::proc_self_stat::ProcSelfStat;
+pub(crate) struct Rusage {
+    pub(crate) user_cpu_us: u64,
+    pub(crate) system_cpu_us: u64,
+    pub(crate) max_rss_bytes: u64,
+    /// Cumulative.
+    pub(crate) major_page_faults: u64,
+}
 
+#[cfg(unix)]
+pub(crate) fn rusage() -> Option<Rusage> {
     let usage = unsafe {
         let mut usage: libc::rusage = std::mem::zeroed();
         match libc::getrusage(libc::RUSAGE_SELF, &mut usage as *mut _) {
             0 => usage,
-            _ => return ProcessStats::default(),
+            _ => return None,
         }
     };
     // POSIX didn't specify unit of ru_maxrss. Linux uses KB while BSD and
@@ -41,6 +51,22 @@ pub fn process_stats() -> ProcessStats {
         (1_000_000 * tv.tv_sec as u64) + (tv.tv_usec as u64)
     }
 
+    Some(Rusage {
+        user_cpu_us: tv_to_micros(&usage.ru_utime),
+        system_cpu_us: tv_to_micros(&usage.ru_stime),
+        max_rss_bytes: (usage.ru_maxrss as u64) * rss_scale,
+        major_page_faults: usage.ru_majflt as u64,
+    })
+}
+
+#[cfg(unix)]
+pub fn process_stats() -> ProcessStats {
+    use crate::process_stats::proc_self_stat::ProcSelfStat;
+
+    let Some(usage) = rusage() else {
+        return ProcessStats::default();
+    };
+
     let rss_bytes = if cfg!(target_os = "linux") {
         // Buck2 snapshot is made once per second, so this shouldn't be too expensive.
         ProcSelfStat::read().map(|stat| {
@@ -53,9 +79,10 @@ pub fn process_stats() -> ProcessStats {
 
     ProcessStats {
         rss_bytes,
-        max_rss_bytes: Some((usage.ru_maxrss as u64) * rss_scale),
-        user_cpu_us: Some(tv_to_micros(&usage.ru_utime)),
-        system_cpu_us: Some(tv_to_micros(&usage.ru_stime)),
+        max_rss_bytes: Some(usage.max_rss_bytes),
+        user_cpu_us: Some(usage.user_cpu_us),
+        system_cpu_us: Some(usage.system_cpu_us),
+        major_page_faults: Some(usage.major_page_faults),
     }
 }
 
@@ -90,6 +117,8 @@ pub fn process_stats() -> ProcessStats {
         max_rss_bytes: max_wss_bytes,
         user_cpu_us: None,
         system_cpu_us: None,
+        // `PageFaultCount` includes soft faults, so it is not comparable.
+        major_page_faults: None,
     }
 }
 
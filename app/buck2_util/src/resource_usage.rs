@@ -0,0 +1,268 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Process-wide resource counters, used to attribute resource usage to commands.
+
+/// Resource counters of the current process at some point in time.
+///
+/// Fields are `None` when the platform does not expose them.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub user_cpu_us: Option<u64>,
+    pub system_cpu_us: Option<u64>,
+    /// Bytes read from storage.
+    pub io_read_bytes: Option<u64>,
+    /// Bytes written to storage.
+    pub io_write_bytes: Option<u64>,
+    pub max_rss_bytes: Option<u64>,
+}
+
+impl ResourceUsage {
+    /// Difference between this snapshot and an earlier one.
+    ///
+    /// Counters are subtracted. Max RSS is a high watermark rather than a counter, so the later
+    /// value is kept as is.
+    pub fn delta_since(&self, start: &ResourceUsage) -> ResourceUsage {
+        fn sub(end: Option<u64>, start: Option<u64>) -> Option<u64> {
+            Some(end?.saturating_sub(start?))
+        }
+
+        ResourceUsage {
+            user_cpu_us: sub(self.user_cpu_us, start.user_cpu_us),
+            system_cpu_us: sub(self.system_cpu_us, start.system_cpu_us),
+            io_read_bytes: sub(self.io_read_bytes, start.io_read_bytes),
+            io_write_bytes: sub(self.io_write_bytes, start.io_write_bytes),
+            max_rss_bytes: self.max_rss_bytes,
+        }
+    }
+}
+
+/// Platform-specific source of [`ResourceUsage`].
+pub trait ResourceUsageCollector: Send + Sync + 'static {
+    fn collect(&self) -> ResourceUsage;
+}
+
+/// Collector for the current platform.
+pub fn resource_usage_collector() -> &'static dyn ResourceUsageCollector {
+    #[cfg(target_os = "linux")]
+    {
+        &linux::LinuxCollector
+    }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        &unix::RusageCollector
+    }
+    #[cfg(windows)]
+    {
+        &windows::WindowsCollector
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        &UnsupportedCollector
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+struct UnsupportedCollector;
+
+#[cfg(not(any(unix, windows)))]
+impl ResourceUsageCollector for UnsupportedCollector {
+    fn collect(&self) -> ResourceUsage {
+        ResourceUsage::default()
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use crate::resource_usage::ResourceUsage;
+    use crate::resource_usage::ResourceUsageCollector;
+
+    /// Collects CPU times and max RSS using `getrusage`. IO counters are not available.
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    pub(crate) struct RusageCollector;
+
+    impl ResourceUsageCollector for RusageCollector {
+        fn collect(&self) -> ResourceUsage {
+            rusage()
+        }
+    }
+
+    pub(crate) fn rusage() -> ResourceUsage {
+        let Some(usage) = crate::process_stats::rusage() else {
+            return ResourceUsage::default();
+        };
+        ResourceUsage {
+            user_cpu_us: Some(usage.user_cpu_us),
+            system_cpu_us: Some(usage.system_cpu_us),
+            io_read_bytes: None,
+            io_write_bytes: None,
+            max_rss_bytes: Some(usage.max_rss_bytes),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs;
+
+    use crate::resource_usage::unix::rusage;
+    use crate::resource_usage::ResourceUsage;
+    use crate::resource_usage::ResourceUsageCollector;
+
+    /// `getrusage` plus IO counters from `/proc/self/io`.
+    pub(crate) struct LinuxCollector;
+
+    impl ResourceUsageCollector for LinuxCollector {
+        fn collect(&self) -> ResourceUsage {
+            let mut usage = rusage();
+            if let Some((read, write)) = fs::read_to_string("/proc/self/io")
+                .ok()
+                .and_then(|io| parse_proc_self_io(&io))
+            {
+                usage.io_read_bytes = Some(read);
+                usage.io_write_bytes = Some(write);
+            }
+            usage
+        }
+    }
+
+    /// Parse `read_bytes` and `write_bytes` from `/proc/self/io`.
+    pub(crate) fn parse_proc_self_io(io: &str) -> Option<(u64, u64)> {
+        let mut read = None;
+        let mut write = None;
+        for line in io.lines() {
+            let (key, value) = line.split_once(':')?;
+            match key {
+                "read_bytes" => read = Some(value.trim().parse().ok()?),
+                "write_bytes" => write = Some(value.trim().parse().ok()?),
+                _ => {}
+            }
+        }
+        Some((read?, write?))
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use winapi::shared::minwindef::DWORD;
+    use winapi::shared::minwindef::FILETIME;
+    use winapi::um::processthreadsapi::GetCurrentProcess;
+    use winapi::um::processthreadsapi::GetProcessTimes;
+    use winapi::um::psapi::K32GetProcessMemoryInfo;
+    use winapi::um::psapi::PROCESS_MEMORY_COUNTERS;
+    use winapi::um::winbase::GetProcessIoCounters;
+    use winapi::um::winnt::IO_COUNTERS;
+
+    use crate::resource_usage::ResourceUsage;
+    use crate::resource_usage::ResourceUsageCollector;
+
+    /// Collects from `GetProcessTimes`, `GetProcessIoCounters` and `GetProcessMemoryInfo`.
+    pub(crate) struct WindowsCollector;
+
+    impl ResourceUsageCollector for WindowsCollector {
+        fn collect(&self) -> ResourceUsage {
+            let mut usage = ResourceUsage::default();
+
+            unsafe {
+                let process = GetCurrentProcess();
+
+                let mut creation: FILETIME = std::mem::zeroed();
+                let mut exit: FILETIME = std::mem::zeroed();
+                let mut kernel: FILETIME = std::mem::zeroed();
+                let mut user: FILETIME = std::mem::zeroed();
+                if GetProcessTimes(process, &mut creation, &mut exit, &mut kernel, &mut user) != 0 {
+                    // `FILETIME` is in 100ns units.
+                    fn to_micros(t: &FILETIME) -> u64 {
+                        (((t.dwHighDateTime as u64) << 32) | t.dwLowDateTime as u64) / 10
+                    }
+                    usage.user_cpu_us = Some(to_micros(&user));
+                    usage.system_cpu_us = Some(to_micros(&kernel));
+                }
+
+                let mut io: IO_COUNTERS = std::mem::zeroed();
+                if GetProcessIoCounters(process, &mut io) != 0 {
+                    usage.io_read_bytes = Some(io.ReadTransferCount);
+                    usage.io_write_bytes = Some(io.WriteTransferCount);
+                }
+
+                let mut pmc: PROCESS_MEMORY_COUNTERS = std::mem::zeroed();
+                pmc.cb = std::mem::size_of_val(&pmc) as DWORD;
+                if K32GetProcessMemoryInfo(process, &mut pmc, pmc.cb) != 0 {
+                    usage.max_rss_bytes = Some(pmc.PeakWorkingSetSize as u64);
+                }
+            }
+
+            usage
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resource_usage::resource_usage_collector;
+    use crate::resource_usage::ResourceUsage;
+
+    #[test]
+    fn test_delta_since() {
+        let start = ResourceUsage {
+            user_cpu_us: Some(100),
+            system_cpu_us: Some(50),
+            io_read_bytes: Some(1000),
+            io_write_bytes: None,
+            max_rss_bytes: Some(10),
+        };
+        let end = ResourceUsage {
+            user_cpu_us: Some(250),
+            system_cpu_us: Some(40),
+            io_read_bytes: Some(3000),
+            io_write_bytes: Some(7),
+            max_rss_bytes: Some(20),
+        };
+        assert_eq!(
+            ResourceUsage {
+                user_cpu_us: Some(150),
+                // Counters should not go backwards, but never underflow if they do.
+                system_cpu_us: Some(0),
+                io_read_bytes: Some(2000),
+                io_write_bytes: None,
+                max_rss_bytes: Some(20),
+            },
+            end.delta_since(&start)
+        );
+    }
+
+    #[test]
+    fn test_collector_plausible() {
+        let usage = resource_usage_collector().collect();
+        if cfg!(any(unix, windows)) {
+            assert!(usage.max_rss_bytes.unwrap() > 0);
+            assert!(usage.user_cpu_us.is_some());
+            assert!(usage.system_cpu_us.is_some());
+        }
+        if cfg!(windows) {
+            assert!(usage.io_read_bytes.is_some());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_proc_self_io() {
+        let io = "rchar: 2012\n\
+            wchar: 0\n\
+            syscr: 7\n\
+            syscw: 0\n\
+            read_bytes: 4096\n\
+            write_bytes: 8192\n\
+            cancelled_write_bytes: 0\n";
+        assert_eq!(
+            Some((4096, 8192)),
+            crate::resource_usage::linux::parse_proc_self_io(io)
+        );
+    }
+}
@@ -21,7 +21,7 @@ pub enum WhoIsAsking {
     BuckWrapper,
 }
 
-pub(crate) fn is_buck2_exe(path: &Path, who_is_asking: WhoIsAsking) -> bool {
+pub fn is_buck2_exe(path: &Path, who_is_asking: WhoIsAsking) -> bool {
     let Some(file_stem) = path.file_stem() else {
         return false;
     };
@@ -9,7 +9,9 @@
 
 //! Cross-platform process killing.
 
+use std::thread;
 use std::time::Duration;
+use std::time::Instant;
 
 use sysinfo::Process;
 
@@ -27,6 +29,37 @@ pub fn process_exists(pid: Pid) -> anyhow::Result<bool> {
     imp::process_exists(pid)
 }
 
+/// What we know about a running process, from a single process table scan.
+pub struct ProcessInfo {
+    /// Together with the pid, this identifies a process even if its pid is later reused.
+    pub start_time: Option<Duration>,
+    /// Empty if it can't be read, e.g. because the process belongs to another user.
+    pub command_line: Vec<String>,
+}
+
+/// Information about the given process, if it is running.
+pub fn process_info(pid: Pid) -> Option<ProcessInfo> {
+    use sysinfo::PidExt;
+    use sysinfo::ProcessExt;
+    use sysinfo::ProcessRefreshKind;
+    use sysinfo::System;
+    use sysinfo::SystemExt;
+
+    let mut system = System::new();
+    // See `get_sysinfo_status` for why we refresh all processes.
+    system.refresh_processes_specifics(ProcessRefreshKind::new());
+    let process = system.process(sysinfo::Pid::from_u32(pid.to_u32()))?;
+    Some(ProcessInfo {
+        start_time: process_creation_time(process),
+        command_line: process.cmd().to_vec(),
+    })
+}
+
+/// Creation time of the given process, if it is running.
+pub fn process_start_time(pid: Pid) -> Option<Duration> {
+    process_info(pid)?.start_time
+}
+
 /// Send `KILL` or call `TerminateProcess` on the given process.
 ///
 /// Returns a KilledProcessHandle that can be used to observe the termination of the killed process.
@@ -37,6 +70,49 @@ pub fn kill(pid: Pid) -> anyhow::Result<Option<KilledProcessHandle>> {
     }
 }
 
+/// How a process was terminated by `kill_graceful`.
+pub enum GracefulKill {
+    /// The process was not running.
+    NotRunning,
+    /// The process exited after `TERM`.
+    Terminated,
+    /// The process did not exit within the grace period and was sent `KILL`, or it was
+    /// terminated with `TerminateProcess` on Windows.
+    Killed(KilledProcessHandle),
+}
+
+/// Send `TERM` to the given process, and `KILL` if it does not exit within `grace`.
+///
+/// On Windows, there is no graceful termination: this always calls `TerminateProcess`.
+///
+/// This blocks the current thread while waiting.
+#[allow(unused_variables)]
+pub fn kill_graceful(pid: Pid, grace: Duration) -> anyhow::Result<GracefulKill> {
+    #[cfg(unix)]
+    {
+        if !imp::terminate(pid)? {
+            return Ok(GracefulKill::NotRunning);
+        }
+        let start = Instant::now();
+        loop {
+            if !process_exists(pid)? {
+                return Ok(GracefulKill::Terminated);
+            }
+            if start.elapsed() >= grace {
+                break;
+            }
+            thread::sleep(EXIT_POLL_INTERVAL);
+        }
+    }
+
+    match kill(pid)? {
+        Some(handle) => Ok(GracefulKill::Killed(handle)),
+        // The process exited between the last check and `KILL`.
+        None if cfg!(unix) => Ok(GracefulKill::Terminated),
+        None => Ok(GracefulKill::NotRunning),
+    }
+}
+
 pub struct KilledProcessHandle {
     handle: imp::KilledProcessHandleImpl,
 }
@@ -47,6 +123,83 @@ impl KilledProcessHandle {
     }
 }
 
+/// How often `wait_for_exit_or_kill` and `kill_graceful` check whether the process exited.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What happened to a process that was asked to exit, see `wait_for_exit_or_kill`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ProcessExit {
+    /// The process exited without being killed.
+    Exited,
+    /// The process did not exit in time, and exited after `TERM`.
+    Terminated,
+    /// The process did not exit in time, and exited once killed.
+    Killed,
+    /// The process did not exit even after being killed.
+    StillRunning {
+        /// Status of the process according to sysinfo, if it could be read.
+        status: String,
+    },
+}
+
+/// Waits up to `exit_timeout` for a process that was asked to exit to do so, and terminates it
+/// with `kill_graceful` if it doesn't, giving it `term_timeout` to exit after `TERM`. Then waits
+/// up to `kill_timeout` for the killed process to exit.
+///
+/// This blocks the current thread while waiting.
+pub fn wait_for_exit_or_kill(
+    pid: Pid,
+    exit_timeout: Duration,
+    term_timeout: Duration,
+    kill_timeout: Duration,
+) -> anyhow::Result<ProcessExit> {
+    let start = Instant::now();
+    while start.elapsed() < exit_timeout {
+        if !process_exists(pid)? {
+            return Ok(ProcessExit::Exited);
+        }
+        thread::sleep(EXIT_POLL_INTERVAL);
+    }
+    match kill_graceful(pid, term_timeout)? {
+        GracefulKill::NotRunning => Ok(ProcessExit::Exited),
+        GracefulKill::Terminated => Ok(ProcessExit::Terminated),
+        GracefulKill::Killed(handle) => wait_for_killed(pid, handle, kill_timeout),
+    }
+}
+
+/// Kills a process and waits up to `timeout` for it to exit.
+///
+/// This blocks the current thread while waiting.
+pub fn kill_and_wait(pid: Pid, timeout: Duration) -> anyhow::Result<ProcessExit> {
+    let Some(handle) = kill(pid)? else {
+        return Ok(ProcessExit::Exited);
+    };
+    wait_for_killed(pid, handle, timeout)
+}
+
+fn wait_for_killed(
+    pid: Pid,
+    handle: KilledProcessHandle,
+    timeout: Duration,
+) -> anyhow::Result<ProcessExit> {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if handle.has_exited()? {
+            return Ok(ProcessExit::Killed);
+        }
+        thread::sleep(EXIT_POLL_INTERVAL);
+    }
+
+    // Last chance, reading the status first so that it is accurate if the process is still there.
+    let status = get_sysinfo_status(pid).map(|s| s.to_string());
+    if handle.has_exited()? {
+        return Ok(ProcessExit::Killed);
+    }
+    Ok(ProcessExit::StillRunning {
+        status: status.unwrap_or_else(|| "<unknown>".to_owned()),
+    })
+}
+
 /// Get the status of a given process according to sysinfo.
 pub fn get_sysinfo_status(pid: Pid) -> Option<sysinfo::ProcessStatus> {
     use sysinfo::PidExt;
@@ -74,21 +227,30 @@ mod tests {
     use buck2_util::process::background_command;
 
     use crate::kill::kill;
+    #[cfg(unix)]
+    use crate::kill::kill_graceful;
     use crate::kill::process_exists;
+    use crate::kill::wait_for_exit_or_kill;
+    #[cfg(unix)]
+    use crate::kill::GracefulKill;
+    use crate::kill::ProcessExit;
     use crate::pid::Pid;
 
-    #[test]
-    fn test_process_exists_kill() {
-        let mut command = if !cfg!(windows) {
+    fn sleep_command(seconds: u32) -> std::process::Command {
+        if !cfg!(windows) {
             let mut command = background_command("sh");
-            command.args(["-c", "sleep 10000"]);
+            command.args(["-c", &format!("sleep {seconds}")]);
             command
         } else {
             let mut command = background_command("powershell");
-            command.args(["-c", "Start-Sleep -Seconds 10000"]);
+            command.args(["-c", &format!("Start-Sleep -Seconds {seconds}")]);
             command
-        };
-        let mut child = command.spawn().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_process_exists_kill() {
+        let mut child = sleep_command(10000).spawn().unwrap();
         let pid = Pid::from_u32(child.id()).unwrap();
         // TODO T187306095: we only check for existence once, because flakiness
         assert!(
@@ -118,4 +280,114 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_wait_for_exit_or_kill_escalates() {
+        let mut child = sleep_command(10000).spawn().unwrap();
+        let pid = Pid::from_u32(child.id()).unwrap();
+
+        let start = Instant::now();
+        let exit = wait_for_exit_or_kill(
+            pid,
+            Duration::from_millis(500),
+            Duration::from_secs(20),
+            Duration::from_secs(20),
+        )
+        .unwrap();
+        if cfg!(unix) {
+            assert_eq!(ProcessExit::Terminated, exit);
+        } else {
+            assert_eq!(ProcessExit::Killed, exit);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(500));
+
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_wait_for_exit_or_kill_exited() {
+        let mut child = sleep_command(1).spawn().unwrap();
+        let pid = Pid::from_u32(child.id()).unwrap();
+
+        let exit = wait_for_exit_or_kill(
+            pid,
+            Duration::from_secs(60),
+            Duration::from_secs(20),
+            Duration::from_secs(20),
+        )
+        .unwrap();
+        assert_eq!(ProcessExit::Exited, exit);
+
+        child.wait().unwrap();
+    }
+
+    /// Spawn a shell running `script` forever, after it printed a line.
+    #[cfg(unix)]
+    fn spawn_shell_and_wait_ready(script: &str) -> std::process::Child {
+        use std::io::BufRead;
+        use std::io::BufReader;
+        use std::process::Stdio;
+
+        let mut command = background_command("sh");
+        command
+            .args([
+                "-c",
+                &format!("{script}; echo ready; while true; do sleep 0.1; done"),
+            ])
+            .stdout(Stdio::piped());
+        let mut child = command.spawn().unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        assert_eq!("ready\n", line);
+        child
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_graceful_terminated() {
+        let mut child = spawn_shell_and_wait_ready("trap 'exit 0' TERM");
+        let pid = Pid::from_u32(child.id()).unwrap();
+
+        let killed = kill_graceful(pid, Duration::from_secs(20)).unwrap();
+        assert!(matches!(killed, GracefulKill::Terminated));
+
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_graceful_escalates() {
+        let mut child = spawn_shell_and_wait_ready("trap '' TERM");
+        let pid = Pid::from_u32(child.id()).unwrap();
+
+        let start = Instant::now();
+        let killed = kill_graceful(pid, Duration::from_millis(500)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(500));
+        let GracefulKill::Killed(handle) = killed else {
+            panic!("process should have been killed");
+        };
+
+        assert!(!child.wait().unwrap().success());
+        assert!(handle.has_exited().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wait_for_exit_or_kill_escalates_to_kill() {
+        let mut child = spawn_shell_and_wait_ready("trap '' TERM");
+        let pid = Pid::from_u32(child.id()).unwrap();
+
+        let exit = wait_for_exit_or_kill(
+            pid,
+            Duration::ZERO,
+            Duration::from_millis(500),
+            Duration::from_secs(20),
+        )
+        .unwrap();
+        assert_eq!(ProcessExit::Killed, exit);
+
+        child.wait().unwrap();
+    }
 }
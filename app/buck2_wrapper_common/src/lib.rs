@@ -16,11 +16,14 @@
 
 #![feature(once_cell_try)]
 
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::path::PathBuf;
 use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
+use dupe::Dupe;
 use is_buck2::WhoIsAsking;
 use sysinfo::PidExt;
 use sysinfo::ProcessExt;
@@ -45,55 +48,126 @@ pub const BUCK_WRAPPER_UUID_ENV_VAR: &str = "BUCK_WRAPPER_UUID";
 /// Because `sysinfo::Process` is not `Clone`.
 struct ProcessInfo {
     pid: Pid,
+    parent: Option<Pid>,
+    exe: PathBuf,
     name: String,
     cmd: Vec<String>,
+    /// Empty if it could not be obtained.
+    cwd: PathBuf,
 }
 
-/// Find all buck2 processes in the system.
-fn find_buck2_processes(who_is_asking: WhoIsAsking) -> Vec<ProcessInfo> {
+/// Snapshot of all the processes in the system.
+fn process_table() -> Vec<ProcessInfo> {
     let mut system = System::new();
     system.refresh_processes();
 
+    let mut processes = Vec::new();
+    for (pid, process) in system.processes() {
+        let Ok(pid) = Pid::from_u32(pid.as_u32()) else {
+            continue;
+        };
+        processes.push(ProcessInfo {
+            pid,
+            parent: process
+                .parent()
+                .and_then(|parent| Pid::from_u32(parent.as_u32()).ok()),
+            exe: process.exe().to_owned(),
+            name: process.name().to_owned(),
+            cmd: process.cmd().to_vec(),
+            cwd: process.cwd().to_owned(),
+        });
+    }
+    processes
+}
+
+/// Select buck2 processes from the process table, except `current` and its parents.
+fn select_buck2_processes(
+    processes: Vec<ProcessInfo>,
+    current: u32,
+    who_is_asking: WhoIsAsking,
+) -> Vec<ProcessInfo> {
+    let parents: HashMap<u32, u32> = processes
+        .iter()
+        .filter_map(|p| Some((p.pid.to_u32(), p.parent?.to_u32())))
+        .collect();
+
     let mut current_parents = HashSet::new();
-    let mut parent = Some(sysinfo::Pid::from_u32(std::process::id()));
+    let mut parent = Some(current);
     while let Some(pid) = parent {
         // There is a small chance on Windows that the PID of a dead parent
         // was reused by some of its descendants, and this can create a loop.
         if !current_parents.insert(pid) {
             break;
         }
-        parent = system.process(pid).and_then(|p| p.parent());
+        parent = parents.get(&pid).copied();
     }
 
-    let mut buck2_processes = Vec::new();
-    for (pid, process) in system.processes() {
-        if is_buck2_exe(process.exe(), who_is_asking) && !current_parents.contains(pid) {
-            let Ok(pid) = Pid::from_u32(pid.as_u32()) else {
-                continue;
-            };
-            buck2_processes.push(ProcessInfo {
-                pid,
-                name: process.name().to_owned(),
-                cmd: process.cmd().to_vec(),
-            });
-        }
-    }
+    processes
+        .into_iter()
+        .filter(|p| {
+            is_buck2_exe(&p.exe, who_is_asking) && !current_parents.contains(&p.pid.to_u32())
+        })
+        .collect()
+}
 
-    buck2_processes
+/// Find all buck2 processes in the system.
+fn find_buck2_processes(who_is_asking: WhoIsAsking) -> Vec<ProcessInfo> {
+    select_buck2_processes(process_table(), std::process::id(), who_is_asking)
+}
+
+#[derive(Default, Clone, Copy, Dupe)]
+pub struct KillallOptions {
+    /// Only list the processes which would be killed.
+    pub dry_run: bool,
+    /// Write one JSON record per process instead of a human readable message.
+    pub json: bool,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum KillallAction {
+    WouldKill,
+    Killed,
+    FailedToKill,
+}
+
+/// What `killall` did to a process, written in `--json` mode.
+#[derive(serde::Serialize)]
+struct KillallRecord<'a> {
+    pid: u32,
+    name: &'a str,
+    cmd: &'a [String],
+    cwd: Option<String>,
+    action: KillallAction,
+    /// Process status after the action, `None` if the process is gone.
+    status: Option<String>,
+    error: Option<String>,
 }
 
 /// Kills all running Buck2 processes, except this process's hierarchy. Returns whether it
 /// succeeded without errors.
 pub fn killall(who_is_asking: WhoIsAsking, write: impl Fn(String)) -> bool {
+    killall_with_options(who_is_asking, KillallOptions::default(), write)
+}
+
+/// Like [`killall`], but allows to only list the processes, or to report them as JSON.
+pub fn killall_with_options(
+    who_is_asking: WhoIsAsking,
+    options: KillallOptions,
+    write: impl Fn(String),
+) -> bool {
     let buck2_processes = find_buck2_processes(who_is_asking);
 
     if buck2_processes.is_empty() {
-        write("No buck2 processes found".to_owned());
+        if !options.json {
+            write("No buck2 processes found".to_owned());
+        }
         return true;
     }
 
     struct Printer<F> {
         write: F,
+        json: bool,
         /// All processes were killed successfully.
         ok: bool,
     }
@@ -105,24 +179,74 @@ pub fn killall(who_is_asking: WhoIsAsking, write: impl Fn(String)) -> bool {
             format!("{} {} ({}). {}", status, process.name, process.pid, cmd,)
         }
 
+        fn write_record(
+            &mut self,
+            process: &ProcessInfo,
+            action: KillallAction,
+            error: Option<&anyhow::Error>,
+        ) {
+            let record = KillallRecord {
+                pid: process.pid.to_u32(),
+                name: &process.name,
+                cmd: &process.cmd,
+                cwd: (!process.cwd.as_os_str().is_empty())
+                    .then(|| process.cwd.to_string_lossy().into_owned()),
+                action,
+                status: kill::get_sysinfo_status(process.pid).map(|s| s.to_string()),
+                error: error.map(|e| format!("{:#}", e)),
+            };
+            (self.write)(serde_json::to_string(&record).expect("Serialization cannot fail"));
+        }
+
+        fn would_kill(&mut self, process: &ProcessInfo) {
+            if self.json {
+                self.write_record(process, KillallAction::WouldKill, None);
+                return;
+            }
+            let mut message = self.fmt_status(process, "Would kill");
+            if !process.cwd.as_os_str().is_empty() {
+                message.push_str(&format!(" (cwd: {})", process.cwd.display()));
+            }
+            (self.write)(message);
+        }
+
         fn failed_to_kill(&mut self, process: &ProcessInfo, error: anyhow::Error) {
+            self.ok = false;
+
+            if self.json {
+                self.write_record(process, KillallAction::FailedToKill, Some(&error));
+                return;
+            }
             let mut message = self.fmt_status(process, "Failed to kill");
             for line in format!("{:?}", error).lines() {
                 message.push_str("\n  ");
                 message.push_str(line);
             }
             (self.write)(message);
-
-            self.ok = false;
         }
 
         fn killed(&mut self, process: &ProcessInfo) {
+            if self.json {
+                self.write_record(process, KillallAction::Killed, None);
+                return;
+            }
             let message = self.fmt_status(process, "Killed");
             (self.write)(message);
         }
     }
 
-    let mut printer = Printer { write, ok: true };
+    let mut printer = Printer {
+        write,
+        json: options.json,
+        ok: true,
+    };
+
+    if options.dry_run {
+        for process in &buck2_processes {
+            printer.would_kill(process);
+        }
+        return printer.ok;
+    }
 
     // Send a kill signal and collect the processes that are still alive.
 
@@ -168,3 +292,57 @@ pub fn killall(who_is_asking: WhoIsAsking, write: impl Fn(String)) -> bool {
 
     printer.ok
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::is_buck2::WhoIsAsking;
+    use crate::pid::Pid;
+    use crate::select_buck2_processes;
+    use crate::ProcessInfo;
+
+    fn process(pid: u32, parent: u32, exe: &str) -> ProcessInfo {
+        ProcessInfo {
+            pid: Pid::from_u32(pid).unwrap(),
+            parent: Pid::from_u32(parent).ok(),
+            exe: PathBuf::from(exe),
+            name: exe.to_owned(),
+            cmd: vec![exe.to_owned()],
+            cwd: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn test_select_buck2_processes() {
+        let processes = vec![
+            process(1, 0, "/sbin/init"),
+            // The client running `killall`, started by another buck2 (e.g. `buck2 run`).
+            process(10, 5, "/dir/buck2"),
+            process(5, 1, "/dir/buck2"),
+            // A daemon and its forkserver.
+            process(20, 1, "/dir/buck2"),
+            process(21, 20, "/other/dir/buck2"),
+            process(30, 20, "/usr/bin/clang"),
+        ];
+
+        let selected = select_buck2_processes(processes, 10, WhoIsAsking::BuckWrapper);
+        let mut pids: Vec<u32> = selected.iter().map(|p| p.pid.to_u32()).collect();
+        pids.sort();
+        assert_eq!(vec![20, 21], pids);
+    }
+
+    #[test]
+    fn test_select_buck2_processes_parent_loop() {
+        // PIDs reused on Windows can create a loop.
+        let processes = vec![
+            process(10, 11, "/dir/buck2"),
+            process(11, 10, "/dir/buck2"),
+            process(12, 10, "/dir/buck2"),
+        ];
+
+        let selected = select_buck2_processes(processes, 10, WhoIsAsking::BuckWrapper);
+        let pids: Vec<u32> = selected.iter().map(|p| p.pid.to_u32()).collect();
+        assert_eq!(vec![12], pids);
+    }
+}
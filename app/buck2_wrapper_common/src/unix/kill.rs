@@ -34,13 +34,26 @@ pub(crate) fn process_exists(pid: Pid) -> anyhow::Result<bool> {
     })
 }
 
-pub(crate) fn kill(pid: Pid) -> anyhow::Result<Option<KilledProcessHandleImpl>> {
+/// Returns `false` if the process does not exist.
+fn send_signal(pid: Pid, signal: Signal) -> anyhow::Result<bool> {
     let pid_nix = pid.to_nix()?;
 
-    match nix::sys::signal::kill(pid_nix, Signal::SIGKILL) {
-        Ok(()) => Ok(Some(KilledProcessHandleImpl { pid })),
-        Err(nix::errno::Errno::ESRCH) => Ok(None),
-        Err(e) => Err(e).with_context(|| format!("Failed to kill pid {}", pid)),
+    match nix::sys::signal::kill(pid_nix, signal) {
+        Ok(()) => Ok(true),
+        Err(nix::errno::Errno::ESRCH) => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to send {} to pid {}", signal, pid)),
+    }
+}
+
+pub(crate) fn terminate(pid: Pid) -> anyhow::Result<bool> {
+    send_signal(pid, Signal::SIGTERM)
+}
+
+pub(crate) fn kill(pid: Pid) -> anyhow::Result<Option<KilledProcessHandleImpl>> {
+    if send_signal(pid, Signal::SIGKILL)? {
+        Ok(Some(KilledProcessHandleImpl { pid }))
+    } else {
+        Ok(None)
     }
 }
 
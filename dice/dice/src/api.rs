@@ -17,10 +17,13 @@ pub mod dice;
 pub mod error;
 pub mod events;
 pub mod injected;
+pub mod invalidation;
 pub mod key;
 pub mod opaque;
+pub mod pin;
 pub mod projection;
 pub mod storage_type;
 pub mod transaction;
 pub mod user_data;
+pub mod value_size;
 pub mod which;
@@ -8,18 +8,20 @@
  */
 
 use std::any::Any;
+use std::time::Instant;
 
 /// An ActivationTracker can be used to identify which keys were either reused or computed during a
 /// transaction.
 pub trait ActivationTracker: Send + Sync + 'static {
     /// Receives when a key was activated (computed, or reused). The caller will want to downcast
     /// the key and deps to types they care about. The caller also receives whatever the key passed
-    /// to `store_evaluation_data` (if any).
+    /// to `store_evaluation_data` (if any), and when the activation happened.
     fn key_activated(
         &self,
         key: &dyn Any,
         deps: &mut dyn Iterator<Item = &dyn Any>,
         activation_data: ActivationData,
+        timing: ActivationTiming,
     );
 }
 
@@ -31,3 +33,25 @@ pub enum ActivationData {
     /// This key was reused. No data is passed.
     Reused,
 }
+
+/// When a key was activated, and what happened to its previous value.
+#[derive(Clone, Copy, Debug)]
+pub struct ActivationTiming {
+    /// When DICE started checking whether the deps of the key changed, or evaluating the key.
+    pub start: Instant,
+    /// When the value of the key was known.
+    pub end: Instant,
+    pub outcome: ActivationOutcome,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActivationOutcome {
+    /// The deps of the key didn't change, so its previous value was reused without evaluating it.
+    Reused,
+    /// The key was evaluated, to a value equal to its previous one.
+    RecomputedSame,
+    /// The key was evaluated to a new value, or for the first time.
+    RecomputedChanged,
+    /// The key was evaluated by legacy DICE, which doesn't tell whether the value changed.
+    Recomputed,
+}
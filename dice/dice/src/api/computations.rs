@@ -14,11 +14,15 @@ use allocative::Allocative;
 use async_trait::async_trait;
 use buck2_futures::cancellation::CancellationContext;
 use futures::future::BoxFuture;
+use futures::FutureExt;
 
 use crate::api::data::DiceData;
 use crate::api::error::DiceResult;
+use crate::api::invalidation::InvalidationPath;
 use crate::api::key::Key;
 use crate::api::opaque::OpaqueValue;
+use crate::api::pin::PinLifetime;
+use crate::api::transaction::DiceEquality;
 use crate::api::user_data::UserComputationData;
 use crate::ctx::DiceComputationsImpl;
 use crate::ProjectionKey;
@@ -87,6 +91,30 @@ impl<'d> DiceComputations<'d> {
         self.inner().compute(key)
     }
 
+    /// Like `compute`, but additionally pins the key so that its graph node, and those of its
+    /// dependencies, are not dropped when the graph is trimmed. Use this for expensive keys whose
+    /// recomputation would stall every command.
+    ///
+    /// The key is only pinned if its value is cached, i.e. failed or transient computations are
+    /// never pinned.
+    pub fn pin<'a, K>(
+        &'a mut self,
+        key: &K,
+        lifetime: PinLifetime,
+    ) -> impl Future<Output = DiceResult<<K as Key>::Value>> + 'a
+    where
+        K: Key,
+    {
+        self.inner().pin(key, lifetime)
+    }
+
+    /// Releases a pin taken via `pin` with the same `lifetime`. Transaction pins are refcounted,
+    /// so this doesn't release a pin taken by another transaction at the same version. They are
+    /// also released automatically once every transaction at the version exits.
+    pub fn unpin<K: Key>(&self, key: &K, lifetime: PinLifetime) {
+        self.inner().unpin(key, lifetime)
+    }
+
     /// Compute "opaque" value where the value is only accessible via projections.
     /// Projections allow accessing derived results from the "opaque" value,
     /// where the dependency of reading a projection is the projection value rather
@@ -173,6 +201,9 @@ impl<'d> DiceComputations<'d> {
     /// ));
     /// futures::future::join_all(futs).await;
     /// ```
+    ///
+    /// When dice evaluates deterministically (see `DiceDataBuilder::deterministic`), the
+    /// computations are started in the order they are declared.
     pub fn compute_many<'a, T: 'a>(
         &'a mut self,
         computes: impl IntoIterator<
@@ -221,6 +252,21 @@ impl<'d> DiceComputations<'d> {
         futures::future::join_all(futs)
     }
 
+    /// Computes all the given keys in parallel, recording them as dependencies of the current
+    /// computation. The results are in the same order as the keys.
+    ///
+    /// ```ignore
+    /// let values: Vec<DiceResult<Value>> = ctx.compute_join_keys(keys).await;
+    /// ```
+    pub fn compute_join_keys<'a, K: Key>(
+        &'a mut self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> impl Future<Output = Vec<DiceResult<K::Value>>> + 'a {
+        self.compute_join(keys, |ctx, key| {
+            async move { ctx.compute(&key).await }.boxed()
+        })
+    }
+
     /// Maps the items into computations futures and then returns a future which represents either a
     /// collection of the results or an error.
     pub fn try_compute_join<'a, T: Send, R: 'a, E: 'a>(
@@ -252,6 +298,34 @@ impl<'d> DiceComputations<'d> {
         self.inner().compute2(compute1, compute2)
     }
 
+    /// Like `compute2`, but for tasks that can fail: returns the first error as soon as either
+    /// task fails, without waiting for the other one, which is cancelled.
+    ///
+    /// The dependencies computed by the cancelled task before it was cancelled are still recorded.
+    pub fn try_compute2<'a, T: 'a, U: 'a, E: 'a>(
+        &'a mut self,
+        compute1: impl for<'x> FnOnce(&'x mut DiceComputations<'a>) -> BoxFuture<'x, Result<T, E>>
+        + Send,
+        compute2: impl for<'x> FnOnce(&'x mut DiceComputations<'a>) -> BoxFuture<'x, Result<U, E>>
+        + Send,
+    ) -> impl Future<Output = Result<(T, U), E>> + 'a {
+        let (fut1, fut2) = self.compute2(compute1, compute2);
+        futures::future::try_join(fut1, fut2)
+    }
+
+    /// Like `compute_many`, but for tasks that can fail: returns the first error as soon as any
+    /// task fails, cancelling the others. Otherwise, returns the results in the order of the
+    /// tasks.
+    pub fn try_compute_many<'a, T: 'a, E: 'a>(
+        &'a mut self,
+        computes: impl IntoIterator<
+            Item = impl for<'x> FnOnce(&'x mut DiceComputations<'a>) -> BoxFuture<'x, Result<T, E>>
+                   + Send,
+        >,
+    ) -> impl Future<Output = Result<Vec<T>, E>> + 'a {
+        futures::future::try_join_all(self.compute_many(computes))
+    }
+
     /// Computes all the given tasks in parallel, returning an unordered Stream.
     ///
     /// If the closures are defined out of the compute3 call, you need to use declare_closure() to get the right lifetimes.
@@ -305,6 +379,24 @@ impl<'d> DiceComputations<'d> {
         self.inner().per_transaction_data()
     }
 
+    /// Returns a token identifying the state this computation runs against. Computations with
+    /// equal tokens see the same value for every key, regardless of later commits.
+    pub fn equality_token(&self) -> DiceEquality {
+        DiceEquality(self.inner().get_version())
+    }
+
+    /// Explains why `key` was recomputed at the version of this computation: the chain of
+    /// recorded dependencies from a key that was changed at this version to `key`.
+    ///
+    /// Returns `None` if `key` was not affected by the changes of this version, or if it was not
+    /// computed at this version. Only supported by modern DICE; legacy DICE always returns `None`.
+    pub fn compute_reason<'a, K: Key>(
+        &'a self,
+        key: &K,
+    ) -> impl Future<Output = Option<InvalidationPath>> + 'a {
+        self.inner().compute_reason(key)
+    }
+
     /// Gets the current cycle guard if its set. If it's set but a different type, an error will be returned.
     pub fn cycle_guard<T: UserCycleDetectorGuard>(&self) -> DiceResult<Option<Arc<T>>> {
         self.inner().cycle_guard()
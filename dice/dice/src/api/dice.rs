@@ -171,8 +171,10 @@ use futures::future::Future;
 use serde::Serializer;
 
 use crate::api::cycles::DetectCycles;
+use crate::api::error::DiceResult;
 use crate::api::transaction::DiceTransactionUpdater;
 use crate::api::user_data::UserComputationData;
+use crate::api::value_size::ValueSizeConfig;
 use crate::metrics::Metrics;
 use crate::DiceDataBuilderImpl;
 use crate::DiceImplementation;
@@ -259,6 +261,36 @@ impl DiceDataBuilder {
         self.0.set(val);
     }
 
+    /// Debug mode to reproduce bugs that depend on the interleaving of evaluations: all keys are
+    /// evaluated on the current thread tokio runtime in a deterministic order, and the places
+    /// where completion races against cancellation are decided by `seed`. Two runs with the same
+    /// seed produce the same interleaving.
+    ///
+    /// Only supported by modern dice, and must be called from within the current thread tokio
+    /// runtime the dice is built on. Returns an error otherwise.
+    pub fn deterministic(&mut self, seed: u64) -> DiceResult<()> {
+        self.0.deterministic(seed)
+    }
+
+    /// Account for the size of the values dice stores, per key type, and optionally report
+    /// values larger than a limit. The totals are exposed by
+    /// `GraphIntrospectable::value_size_summary`.
+    ///
+    /// Only supported by modern dice: returns an error on legacy dice.
+    pub fn value_size_accounting(&mut self, config: ValueSizeConfig) -> DiceResult<()> {
+        self.0.value_size_accounting(config)
+    }
+
+    /// Caps the total weight of the values of the keys that declare one via `Key::cache_weight`.
+    /// When the cap is exceeded, the least recently used of those keys are dropped from the
+    /// graph, together with the keys depending on them, and recomputed when next requested.
+    /// Pinned keys are never dropped.
+    ///
+    /// Only supported by modern dice: returns an error on legacy dice.
+    pub fn max_cache_weight(&mut self, max_weight: usize) -> DiceResult<()> {
+        self.0.max_cache_weight(max_weight)
+    }
+
     pub fn build(self, detect_cycles: DetectCycles) -> Arc<Dice> {
         self.0.build(detect_cycles)
     }
@@ -15,6 +15,7 @@ use indexmap::IndexSet;
 use itertools::Itertools;
 use thiserror::Error;
 
+use crate::api::value_size::OversizedValue;
 use crate::legacy::cycles::RequestedKey;
 
 #[derive(Clone, Dupe, Debug, Error, Allocative)]
@@ -40,13 +41,33 @@ impl DiceError {
         DiceError(Arc::new(DiceErrorImpl::DuplicateChange(key)))
     }
 
-    pub fn cancelled() -> Self {
-        DiceError(Arc::new(DiceErrorImpl::Cancelled))
+    pub fn cancelled(key_display: String) -> Self {
+        DiceError(Arc::new(DiceErrorImpl::Cancelled { key_display }))
+    }
+
+    /// Whether the requested key was cancelled, e.g. because its transaction was superseded,
+    /// rather than failed.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(&*self.0, DiceErrorImpl::Cancelled { .. })
     }
 
     pub fn duplicate_activation_data() -> Self {
         DiceError(Arc::new(DiceErrorImpl::DuplicateActivationData))
     }
+
+    pub(crate) fn unsupported_by_legacy_dice(feature: &'static str) -> Self {
+        DiceError(Arc::new(DiceErrorImpl::UnsupportedByLegacyDice { feature }))
+    }
+
+    pub(crate) fn oversized_value(oversized: OversizedValue) -> Self {
+        DiceError(Arc::new(DiceErrorImpl::OversizedValue(oversized)))
+    }
+
+    pub(crate) fn deterministic_requires_current_thread_runtime() -> Self {
+        DiceError(Arc::new(
+            DiceErrorImpl::DeterministicRequiresCurrentThreadRuntime,
+        ))
+    }
 }
 
 #[derive(Debug, Error, Allocative)]
@@ -62,8 +83,8 @@ pub(crate) enum DiceErrorImpl {
     ChangedToInvalid(Arc<dyn RequestedKey>),
     /// NOTE: This isn't an error users normally see, since if the user is waiting on a result, the
     /// future doesn't get cancelled.
-    #[error("The evaluation of this key was cancelled")]
-    Cancelled,
+    #[error("The evaluation of key `{key_display}` was cancelled")]
+    Cancelled { key_display: String },
     #[error(
         "Requested cycle_guard of type {}, but current guard has type {}",
         expected_type_name,
@@ -75,6 +96,12 @@ pub(crate) enum DiceErrorImpl {
     },
     #[error("Activation data was already provided for this key")]
     DuplicateActivationData,
+    #[error("{feature} is only supported by modern dice (`buck2.dice = modern`)")]
+    UnsupportedByLegacyDice { feature: &'static str },
+    #[error("Deterministic dice must be created within a current thread tokio runtime")]
+    DeterministicRequiresCurrentThreadRuntime,
+    #[error(transparent)]
+    OversizedValue(#[allocative(skip)] OversizedValue),
 }
 
 pub type DiceResult<T> = Result<T, DiceError>;
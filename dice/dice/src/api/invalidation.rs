@@ -0,0 +1,39 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// Why a key was recomputed at a version, as returned by `DiceComputations::compute_reason`.
+///
+/// This is a chain of dependencies from a key that was changed or invalidated at that version to
+/// the key that was asked about, each key depending on the one before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidationPath {
+    keys: Vec<String>,
+}
+
+impl InvalidationPath {
+    pub(crate) fn new(keys: Vec<String>) -> Self {
+        Self { keys }
+    }
+
+    /// The descriptions of the keys on the path, starting with the changed key, and ending with
+    /// the key that was asked about.
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+}
+
+impl Display for InvalidationPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.keys.join(" -> "))
+    }
+}
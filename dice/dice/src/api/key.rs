@@ -65,4 +65,14 @@ pub trait Key: Allocative + Debug + Display + Clone + Eq + Hash + Send + Sync +
     fn storage_type() -> StorageType {
         StorageType::LastN(1)
     }
+
+    /// The weight of a value of this key towards the max cache weight set with
+    /// `DiceDataBuilder::max_cache_weight`, typically an estimate of its size in bytes. Once the
+    /// cap is exceeded, the least recently used keys with a weight are evicted, and recomputed
+    /// when next requested.
+    ///
+    /// The default of 0 means the key is never evicted.
+    fn cache_weight(&self, _value: &Self::Value) -> usize {
+        0
+    }
 }
@@ -0,0 +1,22 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use allocative::Allocative;
+use dupe::Dupe;
+
+/// How long a key pinned via `DiceComputations::pin` is kept from being dropped when the graph is
+/// trimmed.
+#[derive(Clone, Dupe, Copy, Debug, PartialEq, Eq, Allocative)]
+pub enum PinLifetime {
+    /// The pin is released when the transaction that pinned the key exits.
+    Transaction,
+    /// The pin is held until it is explicitly released, typically for keys pinned at daemon
+    /// startup.
+    Daemon,
+}
@@ -72,6 +72,8 @@ impl DiceTransactionUpdater {
         self.0.commit_with_data(extra)
     }
 
+    /// Drops the cached state of dice. Keys pinned via `DiceComputations::pin` and their
+    /// dependencies are kept.
     pub fn unstable_take(self) -> Self {
         Self(self.0.unstable_take())
     }
@@ -112,7 +114,7 @@ impl DiceTransaction {
 
 #[derive(Allocative, Eq, PartialEq, Copy, Clone, derive_more::Display)]
 #[repr(transparent)]
-pub struct DiceEquality(VersionNumber);
+pub struct DiceEquality(pub(crate) VersionNumber);
 
 mod private {
     use super::*;
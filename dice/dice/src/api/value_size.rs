@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::fmt;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+/// Configures the accounting of the size of the values dice stores in its graph, see
+/// `DiceDataBuilder::value_size_accounting`.
+///
+/// Measuring a value walks all the data it retains, which is too expensive to do for every value.
+/// So values are only measured when their inline size is at least `always_measure_inline_bytes`,
+/// and otherwise one in `sample_rate` values of each key type is measured. Values that aren't
+/// measured are never checked against `max_value_bytes`.
+#[derive(Clone)]
+pub struct ValueSizeConfig {
+    /// Values that take at least this many bytes inline are always measured.
+    pub always_measure_inline_bytes: usize,
+    /// One in this many of the other values of each key type is measured. `0` disables sampling.
+    pub sample_rate: u64,
+    /// Measured values larger than this are reported as errors.
+    pub max_value_bytes: Option<usize>,
+    /// Fail the requests for keys whose value is larger than `max_value_bytes`, with an
+    /// `OversizedValue` error. The value is still stored. Meant for tests and CI.
+    pub strict: bool,
+    /// Called for every value larger than `max_value_bytes`, in addition to logging an error.
+    pub on_oversized: Option<Arc<dyn Fn(&OversizedValue) + Send + Sync>>,
+}
+
+impl Default for ValueSizeConfig {
+    fn default() -> Self {
+        Self {
+            always_measure_inline_bytes: 4096,
+            sample_rate: 64,
+            max_value_bytes: None,
+            strict: false,
+            on_oversized: None,
+        }
+    }
+}
+
+impl Debug for ValueSizeConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValueSizeConfig")
+            .field(
+                "always_measure_inline_bytes",
+                &self.always_measure_inline_bytes,
+            )
+            .field("sample_rate", &self.sample_rate)
+            .field("max_value_bytes", &self.max_value_bytes)
+            .field("strict", &self.strict)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A computed value larger than `ValueSizeConfig::max_value_bytes`.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[error(
+    "DICE value computed for `{key}` (key type `{key_type}`) retains {bytes} bytes, more than the \
+    limit of {max_bytes} bytes"
+)]
+pub struct OversizedValue {
+    pub key: String,
+    pub key_type: &'static str,
+    pub bytes: usize,
+    pub max_bytes: usize,
+}
@@ -20,8 +20,10 @@ use gazebo::variants::UnpackVariants;
 use crate::api::computations::DiceComputations;
 use crate::api::data::DiceData;
 use crate::api::error::DiceResult;
+use crate::api::invalidation::InvalidationPath;
 use crate::api::key::Key;
 use crate::api::opaque::OpaqueValue;
+use crate::api::pin::PinLifetime;
 use crate::api::user_data::UserComputationData;
 use crate::api::user_data::UserCycleDetectorGuard;
 use crate::impls::ctx::ModernComputeCtx;
@@ -53,6 +55,41 @@ impl DiceComputationsImpl {
         }
     }
 
+    /// Computes the given key and pins it against trimming of the graph. Legacy dice does not
+    /// support pinning, so this is equivalent to `compute` there.
+    pub(crate) fn pin<'a, K>(
+        &'a self,
+        key: &K,
+        lifetime: PinLifetime,
+    ) -> impl Future<Output = DiceResult<<K as Key>::Value>> + 'a
+    where
+        K: Key,
+    {
+        match self {
+            DiceComputationsImpl::Legacy(delegate) => delegate.compute(key).left_future(),
+            DiceComputationsImpl::Modern(delegate) => delegate.pin(key, lifetime).right_future(),
+        }
+    }
+
+    pub(crate) fn unpin<K: Key>(&self, key: &K, lifetime: PinLifetime) {
+        match self {
+            DiceComputationsImpl::Legacy(_) => {}
+            DiceComputationsImpl::Modern(delegate) => delegate.unpin(key, lifetime),
+        }
+    }
+
+    /// Explains why the given key was recomputed at this version. Legacy dice does not record the
+    /// history needed for this, so it never has an explanation.
+    pub(crate) fn compute_reason<'a, K: Key>(
+        &'a self,
+        key: &K,
+    ) -> impl Future<Output = Option<InvalidationPath>> + 'a {
+        match self {
+            DiceComputationsImpl::Legacy(_) => futures::future::ready(None).left_future(),
+            DiceComputationsImpl::Modern(delegate) => delegate.compute_reason(key).right_future(),
+        }
+    }
+
     /// Compute "opaque" value where the value is only accessible via projections.
     /// Projections allow accessing derived results from the "opaque" value,
     /// where the dependency of reading a projection is the projection value rather
@@ -11,6 +11,7 @@ pub(crate) mod cache;
 pub(crate) mod core;
 pub(crate) mod ctx;
 mod dep_trackers;
+pub(crate) mod deterministic;
 pub(crate) mod dice;
 pub(crate) mod evaluator;
 pub(crate) mod events;
@@ -25,4 +26,5 @@ mod tests;
 pub(crate) mod transaction;
 pub(crate) mod user_cycle;
 pub(crate) mod value;
+pub(crate) mod value_size;
 pub(crate) mod worker;
@@ -13,6 +13,8 @@ use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 
 use allocative::Allocative;
+use buck2_futures::cancellation::CancellationReason;
+use buck2_futures::cancellation::CancellationReasonKind;
 use dashmap::DashMap;
 use dupe::Dupe;
 use fxhash::FxBuildHasher;
@@ -116,7 +118,7 @@ impl SharedCache {
             dashmap::mapref::entry::Entry::Vacant(e) => DiceTaskRef::Vacant(e),
         };
 
-        if self.data.is_cancelled.load(Ordering::Acquire) {
+        if self.is_cancelled() {
             return DiceTaskRef::TransactionCancelled;
         }
 
@@ -133,6 +135,12 @@ impl SharedCache {
         }
     }
 
+    /// Whether the transaction owning this cache was cancelled, i.e. its version epoch is no
+    /// longer live.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.data.is_cancelled.load(Ordering::Acquire)
+    }
+
     pub(crate) fn active_tasks_count(&self) -> usize {
         self.data.storage.len() + self.data.completed.len()
     }
@@ -146,7 +154,9 @@ impl SharedCache {
             .iter()
             .filter_map(|entry| {
                 if entry.value().is_pending() {
-                    entry.value().cancel();
+                    entry.value().cancel_with_reason(CancellationReason::new(
+                        CancellationReasonKind::DiceTransactionSuperseded,
+                    ));
                     Some(entry.value().clone())
                 } else {
                     None
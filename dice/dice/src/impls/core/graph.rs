@@ -9,9 +9,11 @@
 
 //! The versioned dice graph of dependencies
 mod dependencies;
+pub(crate) mod eviction;
 pub(crate) mod history;
 #[allow(unused)]
 pub(crate) mod introspection;
 mod nodes;
+pub(crate) mod pins;
 pub(crate) mod storage;
 pub(crate) mod types;
@@ -0,0 +1,171 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//!
+//! Evicts the least recently used keys from the graph when the total weight of their values, as
+//! declared by `Key::cache_weight`, exceeds a cap.
+//!
+//! A key is evicted together with its transitive rdeps: once a node is dropped, invalidations of
+//! its deps can no longer propagate through it, so the nodes depending on it would otherwise be
+//! left verified with stale values. Pinned keys, and so their transitive deps, are never evicted.
+//! Evicted keys are simply recomputed when they are requested again.
+
+use std::collections::BTreeMap;
+
+use allocative::Allocative;
+use dupe::Dupe;
+
+use crate::impls::core::graph::storage::VersionedGraph;
+use crate::impls::key::DiceKey;
+use crate::HashMap;
+use crate::HashSet;
+
+#[derive(Allocative, Default)]
+pub(crate) struct EvictionTracker {
+    /// Keys are evicted once the total weight is over this. `None` disables eviction.
+    max_weight: Option<usize>,
+    total_weight: usize,
+    /// The weight of each evictable key, and the tick at which it was last used.
+    weights: HashMap<DiceKey, (usize, u64)>,
+    /// The evictable keys by the tick at which they were last used.
+    lru: BTreeMap<u64, DiceKey>,
+    tick: u64,
+}
+
+impl EvictionTracker {
+    pub(crate) fn new(max_weight: Option<usize>) -> Self {
+        Self {
+            max_weight,
+            ..Default::default()
+        }
+    }
+
+    /// Records the weight of the value just stored for the key. Keys of weight 0 are never
+    /// evicted.
+    pub(crate) fn record(&mut self, key: DiceKey, weight: usize) {
+        if self.max_weight.is_none() {
+            return;
+        }
+
+        self.remove(key);
+        if weight > 0 {
+            self.tick += 1;
+            self.weights.insert(key, (weight, self.tick));
+            self.lru.insert(self.tick, key);
+            self.total_weight += weight;
+        }
+    }
+
+    /// Marks the key as used, making it the last to be evicted.
+    pub(crate) fn touch(&mut self, key: DiceKey) {
+        if let Some((_, last_used)) = self.weights.get_mut(&key) {
+            self.lru.remove(last_used);
+            self.tick += 1;
+            *last_used = self.tick;
+            self.lru.insert(self.tick, key);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: DiceKey) {
+        if let Some((weight, last_used)) = self.weights.remove(&key) {
+            self.lru.remove(&last_used);
+            self.total_weight -= weight;
+        }
+    }
+
+    fn over_max_weight(&self) -> bool {
+        self.max_weight
+            .map_or(false, |max_weight| self.total_weight > max_weight)
+    }
+
+    fn least_recently_used(&self) -> impl Iterator<Item = DiceKey> + '_ {
+        self.lru.values().copied()
+    }
+}
+
+impl VersionedGraph {
+    /// Evicts the least recently used keys, and their transitive rdeps, until the total weight
+    /// of the evictable keys is under the cap. Returns the evicted keys.
+    pub(crate) fn evict_over_max_weight(&mut self) -> HashSet<DiceKey> {
+        let mut evicted = HashSet::default();
+        if !self.eviction.over_max_weight() {
+            return evicted;
+        }
+
+        let retained = self.pinned_closure();
+        let candidates = self
+            .eviction
+            .least_recently_used()
+            .filter(|k| !retained.contains(k))
+            .collect::<Vec<_>>();
+
+        for candidate in candidates {
+            if !self.eviction.over_max_weight() {
+                break;
+            }
+
+            let mut queue = vec![candidate];
+            while let Some(k) = queue.pop() {
+                // rdeps are only ever added, so they may include keys that no longer depend on
+                // this one, which may be pinned
+                if retained.contains(&k) || !evicted.insert(k) {
+                    continue;
+                }
+                self.eviction.remove(k);
+                if let Some(versioned) = self.last_n.remove(&k) {
+                    for (_, node) in versioned.iter() {
+                        if let Some(node) = node.unpack_occupied() {
+                            queue.extend(node.metadata().rdeps.rdeps().keys().map(|r| r.dupe()));
+                        }
+                    }
+                }
+            }
+        }
+
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::impls::core::graph::eviction::EvictionTracker;
+    use crate::impls::key::DiceKey;
+
+    #[test]
+    fn tracks_least_recently_used() {
+        let mut tracker = EvictionTracker::new(Some(10));
+        let k = |index| DiceKey { index };
+
+        tracker.record(k(0), 4);
+        tracker.record(k(1), 4);
+        tracker.record(k(2), 0);
+        assert!(!tracker.over_max_weight());
+
+        tracker.touch(k(0));
+        tracker.record(k(3), 4);
+        assert!(tracker.over_max_weight());
+        assert_eq!(
+            vec![k(1), k(0), k(3)],
+            tracker.least_recently_used().collect::<Vec<_>>()
+        );
+
+        // recording a key again replaces its weight
+        tracker.record(k(3), 1);
+        assert!(!tracker.over_max_weight());
+
+        tracker.remove(k(1));
+        tracker.remove(k(5));
+        assert_eq!(5, tracker.total_weight);
+
+        let mut disabled = EvictionTracker::new(None);
+        disabled.record(k(0), usize::MAX);
+        assert!(!disabled.over_max_weight());
+        assert_eq!(0, disabled.least_recently_used().count());
+    }
+}
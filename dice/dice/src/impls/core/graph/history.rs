@@ -332,6 +332,11 @@ where {
         }
     }
 
+    /// Whether the node was explicitly invalidated at the given version
+    pub(crate) fn is_force_dirtied_at(&self, v: VersionNumber) -> bool {
+        self.dirtied.get(&v).copied().unwrap_or(false)
+    }
+
     pub(crate) fn latest_dirtied(&self) -> Option<VersionNumber> {
         self.dirtied.iter().max().map(|d| *d.0)
     }
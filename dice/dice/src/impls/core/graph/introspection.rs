@@ -21,6 +21,7 @@ use crate::introspection::graph::EngineForIntrospection;
 use crate::introspection::graph::GraphNodeKind;
 use crate::introspection::graph::KeyID;
 use crate::introspection::graph::NodeID;
+use crate::introspection::graph::PinSummary;
 use crate::introspection::graph::SerializedGraphNode;
 use crate::introspection::graph::SerializedGraphNodesForKey;
 use crate::introspection::graph::VersionNumber;
@@ -31,6 +32,7 @@ use crate::HashSet;
 pub struct VersionedGraphIntrospectable {
     nodes: HashMap<DiceKey, GraphNodesForKey>,
     edges: HashMap<DiceKey, Arc<Vec<DiceKey>>>,
+    pins: PinSummary,
 }
 
 pub(crate) struct GraphNodesForKey {
@@ -53,6 +55,9 @@ impl VersionedGraphIntrospectable {
     pub(crate) fn len_for_introspection(&self) -> usize {
         self.nodes.len()
     }
+    pub(crate) fn pin_summary(&self) -> PinSummary {
+        self.pins
+    }
 }
 
 impl VersionedGraph {
@@ -115,6 +120,10 @@ impl VersionedGraph {
 
             res
         }
-        VersionedGraphIntrospectable { nodes, edges }
+        VersionedGraphIntrospectable {
+            nodes,
+            edges,
+            pins: self.pin_summary(),
+        }
     }
 }
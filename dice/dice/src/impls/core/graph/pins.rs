@@ -0,0 +1,224 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//!
+//! Tracks keys that are pinned against trimming of the graph.
+//!
+//! Pinned keys, together with their transitive dependencies, are never dropped when the graph is
+//! trimmed. Dependencies have to be kept as well so that invalidations of them still propagate to
+//! the pinned keys.
+//!
+//! Transactions that are at the same version share their pins: they are tracked per version and
+//! only released once every transaction at that version has exited. This is equivalent to scoping
+//! the pins to each transaction, since a version is active exactly as long as any transaction at it
+//! is. Pins are refcounted, so a transaction releasing its pin via `unpin` doesn't release a pin
+//! taken by another transaction at the same version.
+
+use allocative::Allocative;
+use sorted_vector_map::SortedVectorMap;
+
+use crate::impls::core::graph::nodes::VersionedGraphNode;
+use crate::impls::core::graph::storage::VersionedGraph;
+use crate::impls::core::versions::VersionEpoch;
+use crate::impls::key::DiceKey;
+use crate::introspection::graph::PinSummary;
+use crate::versions::VersionNumber;
+use crate::HashMap;
+use crate::HashSet;
+
+/// Who holds a pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PinScope {
+    /// The transaction at the given version. Pins are released when the version is no longer
+    /// active, i.e. once every transaction at that version has exited.
+    Transaction {
+        v: VersionNumber,
+        epoch: VersionEpoch,
+    },
+    /// The daemon. Pins are only released explicitly.
+    Daemon,
+}
+
+#[derive(Allocative, Default)]
+pub(crate) struct PinnedKeys {
+    daemon: HashSet<DiceKey>,
+    /// The number of times each key was pinned by the transactions at each version.
+    transactions: HashMap<VersionNumber, HashMap<DiceKey, usize>>,
+}
+
+impl PinnedKeys {
+    pub(crate) fn pin(&mut self, key: DiceKey, scope: PinScope) {
+        match scope {
+            PinScope::Transaction { v, .. } => {
+                *self
+                    .transactions
+                    .entry(v)
+                    .or_default()
+                    .entry(key)
+                    .or_default() += 1;
+            }
+            PinScope::Daemon => {
+                self.daemon.insert(key);
+            }
+        }
+    }
+
+    pub(crate) fn unpin(&mut self, key: DiceKey, scope: PinScope) {
+        match scope {
+            PinScope::Transaction { v, .. } => {
+                if let Some(keys) = self.transactions.get_mut(&v) {
+                    if let Some(count) = keys.get_mut(&key) {
+                        *count -= 1;
+                        if *count == 0 {
+                            keys.remove(&key);
+                        }
+                    }
+                    if keys.is_empty() {
+                        self.transactions.remove(&v);
+                    }
+                }
+            }
+            PinScope::Daemon => {
+                self.daemon.remove(&key);
+            }
+        }
+    }
+
+    /// Releases all the pins held by the transaction at the given version.
+    pub(crate) fn release_transaction(&mut self, v: VersionNumber) {
+        self.transactions.remove(&v);
+    }
+
+    pub(crate) fn keys(&self) -> HashSet<DiceKey> {
+        self.daemon
+            .iter()
+            .chain(self.transactions.values().flat_map(|keys| keys.keys()))
+            .copied()
+            .collect()
+    }
+}
+
+impl VersionedGraph {
+    /// The pinned keys and all of their transitive dependencies.
+    pub(super) fn pinned_closure(&self) -> HashSet<DiceKey> {
+        let mut retained = HashSet::default();
+        let mut queue = self.pins.keys().into_iter().collect::<Vec<_>>();
+
+        while let Some(k) = queue.pop() {
+            if !retained.insert(k) {
+                continue;
+            }
+            if let Some(versioned) = self.last_n.get(&k) {
+                for (_, node) in versioned.iter() {
+                    if let Some(node) = node.unpack_occupied() {
+                        queue.extend(node.metadata().deps.deps().iter().copied());
+                    }
+                }
+            }
+        }
+
+        retained
+    }
+
+    /// Drops every node that isn't retained by a pin, returning the dropped nodes.
+    pub(crate) fn trim(
+        &mut self,
+    ) -> HashMap<DiceKey, SortedVectorMap<VersionNumber, VersionedGraphNode>> {
+        let retained = self.pinned_closure();
+
+        let (kept, evicted): (HashMap<_, _>, HashMap<_, _>) = std::mem::take(&mut self.last_n)
+            .into_iter()
+            .partition(|(k, _)| retained.contains(k));
+        self.last_n = kept;
+        for k in evicted.keys() {
+            self.eviction.remove(*k);
+        }
+
+        evicted
+    }
+
+    pub(crate) fn pin_summary(&self) -> PinSummary {
+        let retained = self
+            .pinned_closure()
+            .into_iter()
+            .filter_map(|k| self.last_n.get(&k))
+            .collect::<Vec<_>>();
+
+        PinSummary {
+            pinned_keys: self.pins.keys().len(),
+            retained_keys: retained.len(),
+            retained_bytes: retained
+                .into_iter()
+                .map(|nodes| allocative::size_of_unique_allocated_data(nodes))
+                .sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::impls::core::graph::pins::PinScope;
+    use crate::impls::core::graph::pins::PinnedKeys;
+    use crate::impls::core::versions::VersionEpoch;
+    use crate::impls::key::DiceKey;
+    use crate::versions::VersionNumber;
+    use crate::HashSet;
+
+    #[test]
+    fn transaction_pins_are_released_per_version() {
+        let mut pins = PinnedKeys::default();
+        let scope = |v| PinScope::Transaction {
+            v: VersionNumber::new(v),
+            epoch: VersionEpoch::testing_new(v),
+        };
+
+        pins.pin(DiceKey { index: 0 }, scope(1));
+        pins.pin(DiceKey { index: 1 }, scope(2));
+        pins.pin(DiceKey { index: 2 }, PinScope::Daemon);
+        // the same key can be pinned by several holders
+        pins.pin(DiceKey { index: 2 }, scope(1));
+
+        assert_eq!(
+            pins.keys(),
+            HashSet::from_iter([0, 1, 2].map(|index| DiceKey { index }))
+        );
+
+        pins.release_transaction(VersionNumber::new(1));
+        assert_eq!(
+            pins.keys(),
+            HashSet::from_iter([1, 2].map(|index| DiceKey { index }))
+        );
+
+        pins.unpin(DiceKey { index: 1 }, scope(2));
+        pins.unpin(DiceKey { index: 2 }, PinScope::Daemon);
+        assert!(pins.keys().is_empty());
+
+        // unpinning something that isn't pinned is fine
+        pins.unpin(DiceKey { index: 3 }, scope(5));
+    }
+
+    #[test]
+    fn transaction_pins_are_refcounted() {
+        let mut pins = PinnedKeys::default();
+        let scope = PinScope::Transaction {
+            v: VersionNumber::new(1),
+            epoch: VersionEpoch::testing_new(1),
+        };
+
+        // two transactions at the same version pin the same key
+        pins.pin(DiceKey { index: 0 }, scope);
+        pins.pin(DiceKey { index: 0 }, scope);
+
+        pins.unpin(DiceKey { index: 0 }, scope);
+        assert_eq!(pins.keys(), HashSet::from_iter([DiceKey { index: 0 }]));
+
+        pins.unpin(DiceKey { index: 0 }, scope);
+        assert!(pins.keys().is_empty());
+    }
+}
@@ -27,17 +27,20 @@ use sorted_vector_map::SortedVectorMap;
 use crate::api::storage_type::StorageType;
 use crate::arc::Arc;
 use crate::impls::core::graph::dependencies::VersionedDependencies;
+use crate::impls::core::graph::eviction::EvictionTracker;
 use crate::impls::core::graph::history::CellHistory;
 use crate::impls::core::graph::history::HistoryState;
 use crate::impls::core::graph::nodes::OccupiedGraphNode;
 use crate::impls::core::graph::nodes::VacantGraphNode;
 use crate::impls::core::graph::nodes::VersionedGraphNode;
+use crate::impls::core::graph::pins::PinnedKeys;
 use crate::impls::core::graph::types::VersionedGraphKey;
 use crate::impls::core::graph::types::VersionedGraphResult;
 use crate::impls::core::graph::types::VersionedGraphResultMismatch;
 use crate::impls::key::DiceKey;
 use crate::impls::value::DiceComputedValue;
 use crate::impls::value::DiceValidValue;
+use crate::impls::value::MaybeValidDiceValue;
 use crate::versions::VersionNumber;
 use crate::versions::VersionRanges;
 use crate::HashMap;
@@ -56,12 +59,18 @@ pub(crate) struct VersionedGraph {
     /// VacantGraphEntries can only be present when no other entries are present for the key at
     /// any version.
     pub(crate) last_n: HashMap<DiceKey, SortedVectorMap<VersionNumber, VersionedGraphNode>>,
+    /// keys that are not dropped when the graph is trimmed
+    pub(crate) pins: PinnedKeys,
+    /// the weights of the keys that can be evicted when the graph grows too large
+    pub(crate) eviction: EvictionTracker,
 }
 
 impl VersionedGraph {
     pub(crate) fn new() -> Self {
         Self {
             last_n: Default::default(),
+            pins: Default::default(),
+            eviction: Default::default(),
         }
     }
 
@@ -175,6 +184,24 @@ impl VersionedGraph {
         storage_type: StorageType,
     ) -> (DiceComputedValue, bool) {
         let StorageType::LastN(num_to_keep) = storage_type;
+
+        // A dep may have been evicted from the graph since it was computed. The value can't be
+        // invalidated through that dep anymore, so it isn't stored, and it is recomputed the next
+        // time it is requested instead.
+        for dep in deps.iter() {
+            if !matches!(
+                self.get_internal(VersionedGraphKey::new(key.v, dep.dupe())),
+                Some(VersionedGraphNode::Occupied(_))
+            ) {
+                return (
+                    DiceComputedValue::new(
+                        MaybeValidDiceValue::valid(value),
+                        Arc::new(CellHistory::verified(key.v)),
+                    ),
+                    false,
+                );
+            }
+        }
         // persistent keys, if any changes, are committed at the moment when the version
         // is increased. therefore, it must be the case that the current update for the
         // persistent key is the largest/newest version. it's also the case that they are
@@ -196,7 +223,7 @@ impl VersionedGraph {
         for dep in deps.iter() {
             match self.get_internal(VersionedGraphKey::new(key.v, dep.dupe())) {
                 None => {
-                    unreachable!("dependency should exist")
+                    unreachable!("dependency should exist, checked above")
                 }
                 Some(node) => match node {
                     VersionedGraphNode::Occupied(occ) => {
@@ -213,7 +240,7 @@ impl VersionedGraph {
                         }
                     }
                     VersionedGraphNode::Vacant(_) => {
-                        unreachable!("dependency should exist")
+                        unreachable!("dependency should exist, checked above")
                     }
                 },
             }
@@ -364,6 +391,62 @@ impl VersionedGraph {
         (ret, any_invalidated)
     }
 
+    /// Finds why the given key was recomputed at its version, by following its deps that changed
+    /// at that version down to a key that was changed or invalidated itself at that version.
+    /// Returns the keys on the way, starting from that key, or `None` if nothing the key depends
+    /// on changed at that version.
+    pub(crate) fn invalidation_path(&self, key: VersionedGraphKey) -> Option<Vec<DiceKey>> {
+        let mut path = vec![key.k];
+        let mut current = key.k;
+        // the graph is acyclic and each step follows a dep, so this terminates
+        while let Some(VersionedGraphNode::Occupied(node)) =
+            self.entry_at(current, key.v).map(|(_, e)| e)
+        {
+            match node
+                .metadata()
+                .deps
+                .deps()
+                .iter()
+                .find(|dep| self.changed_at(**dep, key.v))
+            {
+                Some(dep) => {
+                    path.push(*dep);
+                    current = *dep;
+                }
+                None => break,
+            }
+        }
+
+        if path.len() == 1 && !self.changed_at(key.k, key.v) {
+            return None;
+        }
+
+        path.reverse();
+        Some(path)
+    }
+
+    /// The entry in use for the key at the given version, and the version since which it is
+    fn entry_at(
+        &self,
+        k: DiceKey,
+        v: VersionNumber,
+    ) -> Option<(VersionNumber, &VersionedGraphNode)> {
+        self.last_n
+            .get(&k)?
+            .range((Bound::Unbounded, Bound::Included(v)))
+            .next_back()
+            .map(|(since, e)| (*since, e))
+    }
+
+    /// Whether the key got a new value at exactly the given version, or was invalidated then.
+    fn changed_at(&self, k: DiceKey, v: VersionNumber) -> bool {
+        match self.entry_at(k, v) {
+            Some((since, VersionedGraphNode::Occupied(_))) if since == v => true,
+            Some((_, e)) => e.history().is_force_dirtied_at(v),
+            None => false,
+        }
+    }
+
     /// Invalidates an entry and its transitive rdeps. Returning true if this caused any type of
     /// change
     pub(crate) fn invalidate(
@@ -14,7 +14,9 @@ use gazebo::prelude::SliceExt;
 use crate::api::storage_type::StorageType;
 use crate::arc::Arc;
 use crate::impls::cache::SharedCache;
+use crate::impls::core::graph::eviction::EvictionTracker;
 use crate::impls::core::graph::introspection::VersionedGraphIntrospectable;
+use crate::impls::core::graph::pins::PinScope;
 use crate::impls::core::graph::storage::InvalidateKind;
 use crate::impls::core::graph::storage::ValueReusable;
 use crate::impls::core::graph::storage::VersionedGraph;
@@ -42,10 +44,14 @@ pub(super) struct CoreState {
 }
 
 impl CoreState {
-    pub(super) fn new() -> Self {
+    /// `max_cache_weight` caps the total weight of the evictable keys kept in the graph, see
+    /// `Key::cache_weight`.
+    pub(super) fn new(max_cache_weight: Option<usize>) -> Self {
+        let mut graph = VersionedGraph::new();
+        graph.eviction = EvictionTracker::new(max_cache_weight);
         Self {
             version_tracker: VersionTracker::new(),
-            graph: VersionedGraph::new(),
+            graph,
             pending_termination_tasks: Vec::new(),
         }
     }
@@ -86,6 +92,7 @@ impl CoreState {
 
     pub(super) fn drop_ctx_at_version(&mut self, v: VersionNumber) {
         if let Some(evicted_cache) = self.version_tracker.drop_at_version(v) {
+            self.graph.pins.release_transaction(v);
             self.pending_termination_tasks
                 .retain(|task| task.is_pending());
             self.pending_termination_tasks
@@ -93,10 +100,29 @@ impl CoreState {
         }
     }
 
+    pub(super) fn pin(&mut self, key: DiceKey, scope: PinScope) {
+        if let PinScope::Transaction { v, epoch } = scope {
+            if !self.version_tracker.is_relevant(v, epoch) {
+                debug!(msg = "pin is rejected since the transaction has exited", k = ?key, v = %v, v_epoch = %epoch);
+                return;
+            }
+        }
+        self.graph.pins.pin(key, scope)
+    }
+
+    pub(super) fn unpin(&mut self, key: DiceKey, scope: PinScope) {
+        self.graph.pins.unpin(key, scope)
+    }
+
     pub(super) fn lookup_key(&mut self, key: VersionedGraphKey) -> VersionedGraphResult {
+        self.graph.eviction.touch(key.k);
         self.graph.get(key)
     }
 
+    pub(super) fn invalidation_path(&self, key: VersionedGraphKey) -> Option<Vec<DiceKey>> {
+        self.graph.invalidation_path(key)
+    }
+
     pub(super) fn update_computed(
         &mut self,
         key: VersionedGraphKey,
@@ -105,11 +131,29 @@ impl CoreState {
         value: DiceValidValue,
         reusability: ValueReusable,
         deps: Arc<Vec<DiceKey>>,
+        weight: Option<usize>,
     ) -> CancellableResult<DiceComputedValue> {
         if self.version_tracker.is_relevant(key.v, epoch) {
             debug!(msg = "update graph entry", k = ?key.k, v = %key.v, v_epoch = %epoch);
 
-            Ok(self.graph.update(key, value, reusability, deps, storage).0)
+            let res = self.graph.update(key, value, reusability, deps, storage).0;
+
+            // `None` when the previous value is reused, whose weight is already known
+            match weight {
+                Some(weight) if self.graph.last_n.contains_key(&key.k) => {
+                    self.graph.eviction.record(key.k, weight)
+                }
+                _ => self.graph.eviction.touch(key.k),
+            }
+            let evicted = self.graph.evict_over_max_weight();
+            if !evicted.is_empty() {
+                debug!(
+                    msg = "evicted keys over the max cache weight",
+                    count = evicted.len()
+                );
+            }
+
+            Ok(res)
         } else {
             debug!(msg = "update is rejected due to outdated epoch", k = ?key.k, v = %key.v, v_epoch = %epoch);
 
@@ -128,9 +172,9 @@ impl CoreState {
     pub(super) fn unstable_drop_everything(&mut self) {
         self.version_tracker.write().commit();
 
-        // Do the actual drop on a different thread because we may have to drop a lot of stuff
-        // here.
-        let map = std::mem::take(&mut self.graph.last_n);
+        // Pinned keys are kept. Do the actual drop on a different thread because we may have to
+        // drop a lot of stuff here.
+        let map = self.graph.trim();
         thread::Builder::new()
             .name("dice-drop-everything".to_owned())
             .spawn(move || drop(map))
@@ -194,7 +238,7 @@ mod tests {
 
     #[test]
     fn update_state_gets_next_version() {
-        let mut core = CoreState::new();
+        let mut core = CoreState::new(None);
 
         assert_eq!(
             core.update_state([(DiceKey { index: 0 }, ChangeType::Invalidate)]),
@@ -209,7 +253,7 @@ mod tests {
 
     #[test]
     fn state_ctx_at_version() {
-        let mut core = CoreState::new();
+        let mut core = CoreState::new(None);
         let v = VersionNumber::new(0);
 
         let (epoch, ctx) = core.ctx_at_version(v);
@@ -332,7 +376,7 @@ mod tests {
 
     #[tokio::test]
     async fn state_tracks_pending_cancellation() {
-        let mut core = CoreState::new();
+        let mut core = CoreState::new(None);
         let v = VersionNumber::new(0);
 
         let (_epoch, cache) = core.ctx_at_version(v);
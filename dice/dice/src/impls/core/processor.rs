@@ -21,9 +21,9 @@ pub(super) struct StateProcessor {
 }
 
 impl StateProcessor {
-    pub(super) fn spawn() -> CoreStateHandle {
+    pub(super) fn spawn(max_cache_weight: Option<usize>) -> CoreStateHandle {
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-        let state = CoreState::new();
+        let state = CoreState::new(max_cache_weight);
 
         std::thread::Builder::new()
             .name("buck2-dice".to_owned())
@@ -33,6 +33,24 @@ impl StateProcessor {
         CoreStateHandle::new(tx)
     }
 
+    /// Processes the state as a task on the current tokio runtime rather than on a dedicated
+    /// thread, so that it is scheduled deterministically with the dice tasks.
+    pub(super) fn spawn_on_current_runtime(max_cache_weight: Option<usize>) -> CoreStateHandle {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let state = CoreState::new(max_cache_weight);
+
+        tokio::spawn(StateProcessor { state, rx }.async_event_loop());
+
+        CoreStateHandle::new(tx)
+    }
+
+    async fn async_event_loop(mut self) {
+        while let Some(message) = self.rx.recv().await {
+            self.iteration(message);
+        }
+        debug!("Processor terminated");
+    }
+
     fn event_loop(mut self) {
         loop {
             // Skip tokio scheduling.
@@ -70,13 +88,19 @@ impl StateProcessor {
                 // ignore error if the requester dropped it.
                 let _ = resp.send(self.state.current_version());
             }
+            StateRequest::Pin { key, scope } => self.state.pin(key, scope),
+            StateRequest::Unpin { key, scope } => self.state.unpin(key, scope),
             StateRequest::LookupKey { key, resp } => drop(resp.send(self.state.lookup_key(key))),
+            StateRequest::InvalidationPath { key, resp } => {
+                drop(resp.send(self.state.invalidation_path(key)))
+            }
             StateRequest::UpdateComputed {
                 key,
                 epoch,
                 storage,
                 value,
                 deps,
+                weight,
                 resp,
                 ..
             } => {
@@ -88,6 +112,7 @@ impl StateProcessor {
                     value,
                     ValueReusable::EqualityBased,
                     deps,
+                    Some(weight),
                 )));
             }
             StateRequest::UpdateMismatchAsUnchanged {
@@ -106,6 +131,7 @@ impl StateProcessor {
                     previous.entry,
                     ValueReusable::VersionBased(previous.verified_versions),
                     previous.deps_to_validate,
+                    None,
                 )));
             }
             StateRequest::GetTasksPendingCancellation { resp } => {
@@ -16,6 +16,7 @@ use tokio::sync::oneshot::Sender;
 use crate::api::storage_type::StorageType;
 use crate::arc::Arc;
 use crate::impls::core::graph::introspection::VersionedGraphIntrospectable;
+use crate::impls::core::graph::pins::PinScope;
 use crate::impls::core::graph::types::VersionedGraphKey;
 use crate::impls::core::graph::types::VersionedGraphResult;
 use crate::impls::core::graph::types::VersionedGraphResultMismatch;
@@ -53,11 +54,21 @@ pub(crate) enum StateRequest {
     },
     /// Report that a computation context at a version has been dropped
     DropCtxAtVersion { version: VersionNumber },
+    /// Pins a key so that it is not dropped when the graph is trimmed
+    Pin { key: DiceKey, scope: PinScope },
+    /// Releases a pin previously obtained via `Pin`
+    Unpin { key: DiceKey, scope: PinScope },
     /// Lookup the state of a key
     LookupKey {
         key: VersionedGraphKey,
         resp: Sender<VersionedGraphResult>,
     },
+    /// Finds the chain of deps through which a change at the version caused the key to be
+    /// recomputed, starting from the changed key
+    InvalidationPath {
+        key: VersionedGraphKey,
+        resp: Sender<Option<Vec<DiceKey>>>,
+    },
     /// Report that a value has been computed
     UpdateComputed {
         key: VersionedGraphKey,
@@ -68,6 +79,8 @@ pub(crate) enum StateRequest {
         value: DiceValidValue,
         /// The deps accessed during the computation of newly computed value
         deps: Arc<Vec<DiceKey>>,
+        /// The weight of the value towards the max cache weight, see `Key::cache_weight`
+        weight: usize,
         /// Response of the new value to use. This could be a different instance that is `Eq` to the
         /// given computed value if the state already stores an instance of value that is equal.
         resp: Sender<CancellableResult<DiceComputedValue>>,
@@ -121,6 +134,11 @@ impl CoreStateHandle {
 impl Dupe for CoreStateHandle {}
 
 /// Start processing state
-pub(crate) fn init_state() -> CoreStateHandle {
-    StateProcessor::spawn()
+pub(crate) fn init_state(max_cache_weight: Option<usize>) -> CoreStateHandle {
+    StateProcessor::spawn(max_cache_weight)
+}
+
+/// Start processing state on the current tokio runtime, for deterministic evaluation
+pub(crate) fn init_state_on_current_runtime(max_cache_weight: Option<usize>) -> CoreStateHandle {
+    StateProcessor::spawn_on_current_runtime(max_cache_weight)
 }
@@ -19,18 +19,24 @@ use futures::FutureExt;
 use futures::TryFutureExt;
 use parking_lot::Mutex;
 use parking_lot::MutexGuard;
+use tokio::sync::oneshot;
 
 use crate::api::activation_tracker::ActivationData;
 use crate::api::computations::DiceComputations;
 use crate::api::data::DiceData;
 use crate::api::error::DiceResult;
+use crate::api::invalidation::InvalidationPath;
 use crate::api::key::Key;
+use crate::api::pin::PinLifetime;
 use crate::api::projection::ProjectionKey;
 use crate::api::user_data::UserComputationData;
 use crate::ctx::DiceComputationsImpl;
 use crate::impls::cache::DiceTaskRef;
 use crate::impls::cache::SharedCache;
+use crate::impls::core::graph::pins::PinScope;
+use crate::impls::core::graph::types::VersionedGraphKey;
 use crate::impls::core::state::CoreStateHandle;
+use crate::impls::core::state::StateRequest;
 use crate::impls::core::versions::VersionEpoch;
 use crate::impls::dep_trackers::RecordingDepsTracker;
 use crate::impls::dice::DiceModern;
@@ -177,13 +183,70 @@ impl ModernComputeCtx {
     {
         self.ctx_data
             .compute_opaque(key)
-            .map(move |cancellable_result| {
-                let cancellable = cancellable_result.map(move |(dice_key, dice_value)| {
-                    OpaqueValueModern::new(dice_key, dice_value.value().dupe())
-                });
+            .map_ok(move |(dice_key, dice_value)| {
+                OpaqueValueModern::new(dice_key, dice_value.value().dupe())
+            })
+    }
 
-                cancellable.map_err(|_| DiceError::cancelled())
+    /// Computes the given key and pins it so that it is not dropped when the graph is trimmed.
+    /// The pin is only taken if the computed value is cached, so failed computations never stay
+    /// pinned.
+    pub(crate) fn pin<'a, K>(
+        &'a self,
+        key: &K,
+        lifetime: PinLifetime,
+    ) -> impl Future<Output = DiceResult<<K as Key>::Value>> + 'a
+    where
+        K: Key,
+    {
+        self.compute_opaque(key).map(move |r| {
+            r.map(|opaque| {
+                if opaque.derive_from.validity() == DiceValidity::Valid {
+                    self.ctx_data.request(StateRequest::Pin {
+                        key: opaque.derive_from_key,
+                        scope: self.ctx_data.pin_scope(lifetime),
+                    });
+                }
+                self.opaque_into_value(opaque)
             })
+        })
+    }
+
+    /// Releases a pin taken via `pin` with the same lifetime.
+    pub(crate) fn unpin<K: Key>(&self, key: &K, lifetime: PinLifetime) {
+        let dice_key = self
+            .ctx_data
+            .async_evaluator
+            .dice
+            .key_index
+            .index(CowDiceKeyHashed::key_ref(key));
+        self.ctx_data.request(StateRequest::Unpin {
+            key: dice_key,
+            scope: self.ctx_data.pin_scope(lifetime),
+        });
+    }
+
+    /// Walks the recorded deps of the given key down to a key that changed at this version.
+    pub(crate) fn compute_reason<'a, K: Key>(
+        &'a self,
+        key: &K,
+    ) -> impl Future<Output = Option<InvalidationPath>> + 'a {
+        let dice = &self.ctx_data.async_evaluator.dice;
+        let dice_key = dice.key_index.index(CowDiceKeyHashed::key_ref(key));
+        let (tx, rx) = oneshot::channel();
+        self.ctx_data.request(StateRequest::InvalidationPath {
+            key: VersionedGraphKey::new(self.ctx_data.get_version(), dice_key),
+            resp: tx,
+        });
+
+        async move {
+            let path = rx.await.unwrap()?;
+            Some(InvalidationPath::new(
+                path.into_iter()
+                    .map(|k| dice.key_index.get(k).to_string())
+                    .collect(),
+            ))
+        }
     }
 
     pub fn opaque_into_value<'a, K: Key>(&'a self, opaque: OpaqueValueModern<K>) -> K::Value {
@@ -320,7 +383,7 @@ impl CoreCtx {
     pub(crate) fn compute_opaque<'a, K>(
         &'a self,
         key: &K,
-    ) -> impl Future<Output = CancellableResult<(DiceKey, DiceComputedValue)>>
+    ) -> impl Future<Output = DiceResult<(DiceKey, DiceComputedValue)>> + 'a
     where
         K: Key,
     {
@@ -339,7 +402,13 @@ impl CoreCtx {
                 self.cycles
                     .subrequest(dice_key, &self.async_evaluator.dice.key_index),
             )
-            .map_ok(move |res| (dice_key, res))
+            .map(move |res| match res {
+                Ok(res) => {
+                    self.async_evaluator.dice.check_value_size(dice_key)?;
+                    Ok((dice_key, res))
+                }
+                Err(Cancelled) => Err(self.cancelled(dice_key)),
+            })
     }
 
     /// Compute "projection" based on deriving value
@@ -379,10 +448,11 @@ impl CoreCtx {
 
         let r = match r {
             Ok(r) => r,
-            Err(_cancelled) => return Err(DiceError::cancelled()),
+            Err(Cancelled) => return Err(self.cancelled(dice_key)),
         };
 
         dep_trackers.lock().record(dice_key, r.value().validity());
+        self.async_evaluator.dice.check_value_size(dice_key)?;
 
         Ok(r.value()
             .downcast_maybe_transient::<K::Value>()
@@ -390,6 +460,12 @@ impl CoreCtx {
             .dupe())
     }
 
+    /// The error for a requested key whose computation was cancelled, because its transaction
+    /// was superseded.
+    fn cancelled(&self, key: DiceKey) -> DiceError {
+        DiceError::cancelled(self.async_evaluator.dice.key_index.get(key).to_string())
+    }
+
     /// Data that is static per the entire lifetime of Dice. These data are initialized at the
     /// time that Dice is initialized via the constructor.
     pub(crate) fn global_data(&self) -> &DiceData {
@@ -408,6 +484,20 @@ impl CoreCtx {
         self.async_evaluator.per_live_version_ctx.get_version()
     }
 
+    fn pin_scope(&self, lifetime: PinLifetime) -> PinScope {
+        match lifetime {
+            PinLifetime::Transaction => PinScope::Transaction {
+                v: self.async_evaluator.per_live_version_ctx.version,
+                epoch: self.async_evaluator.per_live_version_ctx.version_epoch,
+            },
+            PinLifetime::Daemon => PinScope::Daemon,
+        }
+    }
+
+    fn request(&self, message: StateRequest) {
+        self.async_evaluator.dice.state_handle.request(message)
+    }
+
     pub(crate) fn into_updater(self) -> TransactionUpdater {
         TransactionUpdater::new(
             self.async_evaluator.dice.dupe(),
@@ -588,16 +678,9 @@ impl SharedLiveTransactionCtx {
                     .expect("just created")
             }
             DiceTaskRef::TransactionCancelled => {
-                // for projection keys, these are cheap and synchronous computes that should never
-                // be cancelled
-                let task = unsafe {
-                    // SAFETY: task completed below by `IncrementalEngine::project_for_key`
-                    sync_dice_task(key)
-                };
+                debug!(msg = "projection requested on cancelled transaction", k = ?key, v = ?self.version, v_epoch = ?self.version_epoch);
 
-                task.depended_on_by(parent_key)
-                    .not_cancelled()
-                    .expect("just created")
+                return Err(Cancelled);
             }
         };
 
@@ -609,9 +692,17 @@ impl SharedLiveTransactionCtx {
             self.version_epoch,
             eval,
             events,
+            || self.is_live(),
         )
     }
 
+    /// Whether the version epoch of this ctx is still live. Once the last ctx at this version is
+    /// dropped (or the version is invalidated), the transaction is cancelled and anything computed
+    /// through this ctx must not be recorded into the graph.
+    pub(crate) fn is_live(&self) -> bool {
+        !self.cache.is_cancelled()
+    }
+
     pub(crate) fn get_version(&self) -> VersionNumber {
         self.version
     }
@@ -0,0 +1,79 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Deterministic evaluation mode, used to reproduce bugs that depend on how dice tasks interleave.
+//!
+//! In this mode, dice must be driven from a current thread tokio runtime. All key evaluations, as
+//! well as the core state processing, run as tasks on that runtime, which polls ready tasks in
+//! FIFO order. Since futures returned by `compute_many` are polled in the order they are
+//! declared, their branches are evaluated in declaration order too.
+//!
+//! The remaining source of variation are the places where dice intentionally races the completion
+//! of a task against its cancellation. Before each of those, the worker yields a number of times
+//! chosen by a seeded random number generator, so that the same seed results in the same outcome.
+
+use std::sync::Mutex;
+
+use allocative::Allocative;
+
+use crate::api::error::DiceError;
+use crate::api::error::DiceResult;
+
+/// The maximum number of times a worker yields at a race point.
+const MAX_RACE_POINT_YIELDS: u64 = 2;
+
+#[derive(Allocative)]
+pub(crate) struct DeterministicScheduler {
+    #[allocative(skip)]
+    rng: Mutex<SplitMix64>,
+}
+
+impl DeterministicScheduler {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            rng: Mutex::new(SplitMix64(seed)),
+        }
+    }
+
+    /// Checks that we are running on a current thread runtime, since a multi threaded runtime
+    /// would make the scheduling of tasks nondeterministic.
+    pub(crate) fn check_runtime() -> DiceResult<()> {
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle)
+                if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::CurrentThread =>
+            {
+                Ok(())
+            }
+            _ => Err(DiceError::deterministic_requires_current_thread_runtime()),
+        }
+    }
+
+    /// Called right before the worker checks whether it has been cancelled. Yields to the other
+    /// ready tasks a seed-dependent number of times, which decides whether a cancellation that is
+    /// about to be requested wins over the completion of the task.
+    pub(crate) async fn race_point(&self) {
+        let yields = self.rng.lock().unwrap().next() % (MAX_RACE_POINT_YIELDS + 1);
+        for _ in 0..yields {
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+/// A tiny seedable generator, good enough to pick interleavings.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
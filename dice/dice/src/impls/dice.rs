@@ -16,12 +16,19 @@ use dupe::Dupe;
 
 use crate::api::cycles::DetectCycles;
 use crate::api::data::DiceData;
+use crate::api::error::DiceResult;
 use crate::api::user_data::UserComputationData;
+use crate::api::value_size::ValueSizeConfig;
 use crate::impls::core::state::init_state;
+use crate::impls::core::state::init_state_on_current_runtime;
 use crate::impls::core::state::CoreStateHandle;
 use crate::impls::core::state::StateRequest;
+use crate::impls::deterministic::DeterministicScheduler;
+use crate::impls::key::DiceKey;
 use crate::impls::key_index::DiceKeyIndex;
 use crate::impls::transaction::TransactionUpdater;
+use crate::impls::value::DiceValidValue;
+use crate::impls::value_size::ValueSizeAccounting;
 use crate::introspection::graph::GraphIntrospectable;
 use crate::introspection::graph::ModernIntrospectable;
 use crate::metrics::Metrics;
@@ -31,6 +38,10 @@ pub(crate) struct DiceModern {
     pub(crate) key_index: DiceKeyIndex,
     pub(crate) state_handle: CoreStateHandle,
     pub(crate) global_data: DiceData,
+    /// Set when evaluating in deterministic mode.
+    pub(crate) deterministic: Option<DeterministicScheduler>,
+    /// Set when accounting for the size of the computed values.
+    pub(crate) value_sizes: Option<ValueSizeAccounting>,
 }
 
 impl Debug for DiceModern {
@@ -39,33 +50,120 @@ impl Debug for DiceModern {
     }
 }
 
-pub(crate) struct DiceModernDataBuilder(DiceData);
+pub(crate) struct DiceModernDataBuilder {
+    data: DiceData,
+    deterministic_seed: Option<u64>,
+    value_sizes: Option<ValueSizeConfig>,
+    max_cache_weight: Option<usize>,
+}
 
 impl DiceModernDataBuilder {
     pub(crate) fn new() -> Self {
-        Self(DiceData::new())
+        Self {
+            data: DiceData::new(),
+            deterministic_seed: None,
+            value_sizes: None,
+            max_cache_weight: None,
+        }
     }
 
     pub fn set<K: Send + Sync + 'static>(&mut self, val: K) {
-        self.0.set(val);
+        self.data.set(val);
+    }
+
+    /// Evaluate deterministically, see `crate::impls::deterministic`.
+    pub fn deterministic(&mut self, seed: u64) -> DiceResult<()> {
+        DeterministicScheduler::check_runtime()?;
+        self.deterministic_seed = Some(seed);
+        Ok(())
+    }
+
+    /// Account for the size of the computed values, see `ValueSizeConfig`.
+    pub fn value_size_accounting(&mut self, config: ValueSizeConfig) {
+        self.value_sizes = Some(config);
+    }
+
+    /// Evict the least recently used keys when the total of their `Key::cache_weight` is over
+    /// `max_weight`.
+    pub fn max_cache_weight(&mut self, max_weight: usize) {
+        self.max_cache_weight = Some(max_weight);
     }
 
     pub fn build(self, _detect_cycles: DetectCycles) -> Arc<DiceModern> {
-        DiceModern::new(self.0)
+        let value_sizes = self.value_sizes.map(ValueSizeAccounting::new);
+        match self.deterministic_seed {
+            None => DiceModern::new_with_config(self.data, value_sizes, self.max_cache_weight),
+            Some(seed) => {
+                DiceModern::new_deterministic(self.data, seed, value_sizes, self.max_cache_weight)
+            }
+        }
     }
 }
 
 impl DiceModern {
     pub(crate) fn new(global_data: DiceData) -> Arc<Self> {
-        let state_handle = init_state();
+        Self::new_with_config(global_data, None, None)
+    }
+
+    fn new_with_config(
+        global_data: DiceData,
+        value_sizes: Option<ValueSizeAccounting>,
+        max_cache_weight: Option<usize>,
+    ) -> Arc<Self> {
+        let state_handle = init_state(max_cache_weight);
+
+        Arc::new(DiceModern {
+            key_index: Default::default(),
+            state_handle,
+            global_data,
+            deterministic: None,
+            value_sizes,
+        })
+    }
+
+    /// Must be called from within a current thread tokio runtime (checked by
+    /// `DiceModernDataBuilder::deterministic`), which is then used to run all the evaluations. Since the state is processed on that runtime, `metrics` and
+    /// `to_introspectable`, which block waiting for the state, aren't supported in this mode.
+    pub(crate) fn new_deterministic(
+        global_data: DiceData,
+        seed: u64,
+        value_sizes: Option<ValueSizeAccounting>,
+        max_cache_weight: Option<usize>,
+    ) -> Arc<Self> {
+        let state_handle = init_state_on_current_runtime(max_cache_weight);
 
         Arc::new(DiceModern {
             key_index: Default::default(),
             state_handle,
             global_data,
+            deterministic: Some(DeterministicScheduler::new(seed)),
+            value_sizes,
         })
     }
 
+    /// A point where the completion of a task races against its cancellation. In deterministic
+    /// mode, the seed decides which one wins, otherwise this does nothing.
+    pub(crate) async fn race_point(&self) {
+        if let Some(deterministic) = &self.deterministic {
+            deterministic.race_point().await;
+        }
+    }
+
+    /// Accounts for the size of a value about to be stored in the graph, when enabled.
+    pub(crate) fn record_value_size(&self, k: DiceKey, value: &DiceValidValue) {
+        if let Some(value_sizes) = &self.value_sizes {
+            value_sizes.record(k, self.key_index.get(k), value);
+        }
+    }
+
+    /// Fails the request for `k` if its value is oversized, in strict value size accounting.
+    pub(crate) fn check_value_size(&self, k: DiceKey) -> DiceResult<()> {
+        match &self.value_sizes {
+            Some(value_sizes) => value_sizes.check(k),
+            None => Ok(()),
+        }
+    }
+
     #[cfg(test)]
     pub(crate) fn builder() -> DiceModernDataBuilder {
         DiceModernDataBuilder::new()
@@ -107,6 +205,11 @@ impl DiceModern {
                 graph: graph_introspectable,
                 version_data: version_introspectable,
                 key_map: key_index,
+                value_sizes: self
+                    .value_sizes
+                    .as_ref()
+                    .map(|value_sizes| value_sizes.summary())
+                    .unwrap_or_default(),
             },
         }
     }
@@ -8,6 +8,7 @@
  */
 
 use std::sync::Arc;
+use std::time::Instant;
 
 use allocative::Allocative;
 use dupe::Dupe;
@@ -53,6 +54,7 @@ impl AsyncEvaluator {
         key: DiceKey,
         state: DiceWorkerStateComputing<'a, 'b>,
     ) -> CancellableResult<DiceWorkerStateFinishedEvaluating<'a, 'b>> {
+        let start = Instant::now();
         let key_erased = self.dice.key_index.get(key);
 
         let (cycles, state) = state.evaluating();
@@ -82,12 +84,15 @@ impl AsyncEvaluator {
                         }
                     };
 
+                self.dice.race_point().await;
+
                 let activation = ActivationInfo::new(
                     &self.dice.key_index,
                     &self.user_data.activation_tracker,
                     key,
                     deps.iter(),
                     evaluation_data.into_activation_data(), // Projection keys can't set this.
+                    start,
                 );
 
                 state.finished(
@@ -118,12 +123,15 @@ impl AsyncEvaluator {
 
                 let value = proj.proj().compute(base.value(), &ctx);
 
+                self.dice.race_point().await;
+
                 let activation = ActivationInfo::new(
                     &self.dice.key_index,
                     &self.user_data.activation_tracker,
                     key,
                     [proj.base()].iter(),
                     ActivationData::Evaluated(None), // Projection keys can't set this.
+                    start,
                 );
 
                 state.finished(
@@ -144,7 +152,7 @@ impl AsyncEvaluator {
 #[derive(Clone, Dupe)]
 pub(crate) struct SyncEvaluator {
     user_data: Arc<UserComputationData>,
-    dice: Arc<DiceModern>,
+    pub(super) dice: Arc<DiceModern>,
     base: MaybeValidDiceValue,
 }
 
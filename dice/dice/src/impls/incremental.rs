@@ -16,6 +16,7 @@
 use std::borrow::Cow;
 use std::fmt::Debug;
 use std::future;
+use std::time::Instant;
 
 use allocative::Allocative;
 use dupe::Dupe;
@@ -105,7 +106,7 @@ impl IncrementalEngine {
 
     #[cfg_attr(debug_assertions, instrument(
         level = "debug",
-        skip(state, promise, eval, event_dispatcher),
+        skip(state, promise, eval, event_dispatcher, is_live),
         fields(k = ?k, version = %v),
     ))]
     pub(crate) fn project_for_key(
@@ -116,20 +117,37 @@ impl IncrementalEngine {
         version_epoch: VersionEpoch,
         eval: SyncEvaluator,
         event_dispatcher: DiceEventDispatcher,
+        is_live: impl Fn() -> bool,
     ) -> CancellableResult<DiceComputedValue> {
-        promise.sync_get_or_complete(|| {
+        promise.sync_get_or_try_complete(|| {
+            // The transaction may have been cancelled while a ctx at its version was retained. A
+            // projection computed then must neither be stored in the graph nor be handed back as
+            // a dependency of the parent.
+            if !is_live() {
+                debug!(msg = "projection on obsolete version epoch");
+                return Err(Cancelled);
+            }
+
             event_dispatcher.started(k);
 
             debug!(msg = "running projection");
 
             let eval_result = eval.evaluate(k);
 
+            if !is_live() {
+                debug!(msg = "version epoch became obsolete while running projection");
+                event_dispatcher.finished(k);
+                return Err(Cancelled);
+            }
+
             debug!(msg = "projection finished. updating caches");
 
             let (res, future) = {
                 // send the update but don't wait for it
                 let state_future = match eval_result.value.dupe().into_valid_value() {
                     Ok(value) => {
+                        eval.dice.record_value_size(k, &value);
+                        let weight = eval.dice.key_index.get(k).cache_weight(&value);
                         let (tx, rx) = oneshot::channel();
                         state.request(StateRequest::UpdateComputed {
                             key: VersionedGraphKey::new(v, k),
@@ -137,6 +155,7 @@ impl IncrementalEngine {
                             storage: eval_result.storage,
                             value,
                             deps: Arc::new(eval_result.deps.into_iter().collect()),
+                            weight,
                             resp: tx,
                         });
 
@@ -162,10 +181,10 @@ impl IncrementalEngine {
             let state_future =
                 future.unwrap_or_else(|| future::ready(Ok(computed_value.dupe())).boxed());
 
-            DiceSyncResult {
+            Ok(DiceSyncResult {
                 sync_result: computed_value,
                 state_future,
-            }
+            })
         })
     }
 
@@ -186,13 +205,17 @@ impl IncrementalEngine {
         let state_result = rx.await.unwrap();
 
         match state_result {
-            VersionedGraphResult::Match(entry) => task_state.lookup_matches(entry),
+            VersionedGraphResult::Match(entry) => {
+                eval.dice.race_point().await;
+                task_state.lookup_matches(entry)
+            }
             VersionedGraphResult::Compute => {
                 self.compute(k, eval, &events_dispatcher, task_state.lookup_dirtied(eval))
                     .await
             }
 
             VersionedGraphResult::CheckDeps(mismatch) => {
+                let start = Instant::now();
                 let task_state = task_state.checking_deps(eval);
 
                 let deps_changed = {
@@ -217,12 +240,14 @@ impl IncrementalEngine {
                             .await
                     }
                     DidDepsChange::NoChange => {
+                        eval.dice.race_point().await;
                         let task_state = task_state.deps_match(ActivationInfo::new(
                             &eval.dice.key_index,
                             &eval.user_data.activation_tracker,
                             k,
                             mismatch.deps_to_validate.iter(),
                             ActivationData::Reused,
+                            start,
                         ))?;
 
                         // report reuse
@@ -262,20 +287,30 @@ impl IncrementalEngine {
         let eval_result_state = eval.evaluate(k, task_state).await?;
         let eval_result = eval_result_state.result;
 
+        let mut state = eval_result_state.state;
         let res = {
             match eval_result.value.into_valid_value() {
                 Ok(value) => {
+                    eval.dice.record_value_size(k, &value);
                     let (tx, rx) = oneshot::channel();
                     self.state.request(StateRequest::UpdateComputed {
                         key: VersionedGraphKey::new(v, k),
                         epoch: self.version_epoch,
                         storage: eval_result.storage,
-                        value,
+                        value: value.dupe(),
                         deps: Arc::new(eval_result.deps.into_iter().collect()),
+                        weight: eval.dice.key_index.get(k).cache_weight(&value),
                         resp: tx,
                     });
 
-                    rx.await.unwrap()
+                    rx.await.unwrap().map(|res| {
+                        // the state responds with the instance it already stores if it is equal
+                        // to the new value
+                        if !res.value().instance_equal(&value) {
+                            state.recomputed_same();
+                        }
+                        res
+                    })
                 }
                 Err(value) => Ok(DiceComputedValue::new(
                     value,
@@ -284,7 +319,7 @@ impl IncrementalEngine {
             }
         };
 
-        res.map(|res| eval_result_state.state.cached(res))
+        res.map(|res| state.cached(res))
     }
 
     /// determines if the given 'Dependency' has changed between versions 'last_version' and
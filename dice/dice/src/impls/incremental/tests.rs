@@ -35,6 +35,8 @@ use tokio::sync::Notify;
 use crate::api::computations::DiceComputations;
 use crate::api::data::DiceData;
 use crate::api::key::Key;
+use crate::api::projection::DiceProjectionComputations;
+use crate::api::projection::ProjectionKey;
 use crate::api::storage_type::StorageType;
 use crate::api::user_data::NoOpTracker;
 use crate::api::user_data::UserComputationData;
@@ -47,9 +49,11 @@ use crate::impls::core::versions::VersionEpoch;
 use crate::impls::ctx::SharedLiveTransactionCtx;
 use crate::impls::dice::DiceModern;
 use crate::impls::evaluator::AsyncEvaluator;
+use crate::impls::evaluator::SyncEvaluator;
 use crate::impls::events::DiceEventDispatcher;
 use crate::impls::incremental::testing::DidDepsChangeExt;
 use crate::impls::incremental::IncrementalEngine;
+use crate::impls::key::CowDiceKeyHashed;
 use crate::impls::key::DiceKey;
 use crate::impls::key::ParentKey;
 use crate::impls::task::handle::DiceTaskHandle;
@@ -63,6 +67,7 @@ use crate::impls::value::DiceValidValue;
 use crate::impls::value::MaybeValidDiceValue;
 use crate::impls::worker::state::DiceWorkerStateCheckingDeps;
 use crate::result::CancellableResult;
+use crate::result::Cancelled;
 use crate::versions::testing::VersionRangesExt;
 use crate::versions::VersionNumber;
 use crate::versions::VersionRange;
@@ -88,6 +93,22 @@ impl Key for K {
     }
 }
 
+#[derive(Allocative, Clone, Dupe, Debug, Display, PartialEq, Eq, Hash)]
+struct Doubled;
+
+impl ProjectionKey for Doubled {
+    type DeriveFromKey = K;
+    type Value = usize;
+
+    fn compute(&self, derive_from: &usize, _ctx: &DiceProjectionComputations) -> Self::Value {
+        derive_from * 2
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
 #[derive(Allocative, Clone, Debug, Display)]
 #[display(fmt = "{:?}", self)]
 struct IsRan(Arc<AtomicBool>);
@@ -269,6 +290,7 @@ async fn test_values_gets_reevaluated_when_deps_change() -> anyhow::Result<()> {
         storage: StorageType::LastN(1),
         value: DiceValidValue::testing_new(DiceKeyValue::<K>::new(1)),
         deps: Arc::new(vec![]),
+        weight: 0,
         resp: tx,
     });
     let (tx, _rx) = tokio::sync::oneshot::channel();
@@ -278,6 +300,7 @@ async fn test_values_gets_reevaluated_when_deps_change() -> anyhow::Result<()> {
         storage: StorageType::LastN(1),
         value: DiceValidValue::testing_new(DiceKeyValue::<IsRan>::new(())),
         deps: Arc::new(vec![DiceKey { index: 100 }]),
+        weight: 0,
         resp: tx,
     });
 
@@ -1120,6 +1143,7 @@ fn update_computed_value(
         storage: StorageType::LastN(1),
         value,
         deps,
+        weight: 0,
         resp: tx,
     });
 
@@ -1139,3 +1163,56 @@ async fn get_ctx_at_version(
     let (ctx, guard) = rx.await.unwrap();
     (ctx, guard)
 }
+
+fn project_doubled(
+    dice: &std::sync::Arc<DiceModern>,
+    ctx: &SharedLiveTransactionCtx,
+) -> CancellableResult<DiceComputedValue> {
+    let base_key = dice.key_index.index_key(K);
+    let proj_key = dice
+        .key_index
+        .index(CowDiceKeyHashed::proj_ref(base_key, &Doubled));
+
+    ctx.compute_projection(
+        proj_key,
+        ParentKey::None,
+        dice.state_handle.dupe(),
+        SyncEvaluator::new(
+            std::sync::Arc::new(UserComputationData::new()),
+            dice.dupe(),
+            MaybeValidDiceValue::valid(DiceValidValue::testing_new(DiceKeyValue::<K>::new(21))),
+        ),
+        DiceEventDispatcher::new(std::sync::Arc::new(NoOpTracker), dice.dupe()),
+    )
+}
+
+#[tokio::test]
+async fn projection_is_computed_synchronously() -> anyhow::Result<()> {
+    let dice = DiceModern::new(DiceData::new());
+    let (ctx, _guard) = dice.testing_shared_ctx(VersionNumber::new(1)).await;
+
+    assert!(ctx.is_live());
+
+    let res = project_doubled(&dice, &ctx)?;
+    assert_eq!(res.value().downcast_maybe_transient::<usize>(), Some(&42));
+
+    // the second request is served from the shared cache
+    let res = project_doubled(&dice, &ctx)?;
+    assert_eq!(res.value().downcast_maybe_transient::<usize>(), Some(&42));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn projection_on_cancelled_transaction_is_cancelled() {
+    let dice = DiceModern::new(DiceData::new());
+    let (ctx, guard) = dice.testing_shared_ctx(VersionNumber::new(1)).await;
+
+    // dropping the last guard at the version cancels the transaction while `ctx` is retained
+    drop(guard);
+    while ctx.is_live() {
+        tokio::task::yield_now().await;
+    }
+
+    assert_matches!(project_doubled(&dice, &ctx), Err(Cancelled));
+}
@@ -31,6 +31,7 @@ use crate::impls::hash;
 use crate::impls::hash::key_hash;
 use crate::impls::value::DiceKeyValue;
 use crate::impls::value::DiceProjectValue;
+use crate::impls::value::DiceValidValue;
 use crate::impls::value::DiceValueDyn;
 use crate::impls::value::MaybeValidDiceValue;
 
@@ -78,6 +79,15 @@ impl DiceKeyErased {
         }
     }
 
+    /// See `Key::cache_weight`. Projections are cheap to recompute from their base, so they are
+    /// never evicted on their own.
+    pub(crate) fn cache_weight(&self, value: &DiceValidValue) -> usize {
+        match self {
+            DiceKeyErased::Key(k) => k.cache_weight(value),
+            DiceKeyErased::Projection(_) => 0,
+        }
+    }
+
     pub(crate) fn hash(&self) -> u64 {
         match self {
             DiceKeyErased::Key(k) => k.hash(),
@@ -271,6 +281,8 @@ pub(crate) trait DiceKeyDyn: Allocative + Display + Send + Sync + 'static {
     fn key_type_name(&self) -> &'static str;
 
     fn storage_type(&self) -> StorageType;
+
+    fn cache_weight(&self, value: &DiceValidValue) -> usize;
 }
 
 #[async_trait]
@@ -310,6 +322,15 @@ where
     fn storage_type(&self) -> StorageType {
         K::storage_type()
     }
+
+    fn cache_weight(&self, value: &DiceValidValue) -> usize {
+        <K as Key>::cache_weight(
+            self,
+            value
+                .downcast_ref::<K::Value>()
+                .expect("Key computed to wrong type"),
+        )
+    }
 }
 
 pub(crate) trait DiceProjectionDyn: Allocative + Display + Send + Sync + 'static {
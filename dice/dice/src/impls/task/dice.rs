@@ -18,6 +18,7 @@ use std::task::Poll;
 use allocative::Allocative;
 use allocative::Visitor;
 use buck2_futures::cancellation::future::CancellationHandle;
+use buck2_futures::cancellation::CancellationReason;
 use dupe::Dupe;
 use dupe::OptionDupedExt;
 use futures::task::AtomicWaker;
@@ -214,8 +215,13 @@ impl DiceTask {
     }
 
     pub(crate) fn cancel(&self) {
+        self.cancel_with_reason(CancellationReason::unknown())
+    }
+
+    /// Cancel this task, with the given reason reported to its `CancellationObserver`s.
+    pub(crate) fn cancel_with_reason(&self, reason: CancellationReason) {
         let lock = self.internal.critical.lock();
-        self.cancellations.cancel(&lock);
+        self.cancellations.cancel(&lock, reason);
     }
 
     pub(crate) fn await_termination(&self) -> TerminationObserver {
@@ -260,7 +266,7 @@ impl DiceTaskInternal {
                 Some(ref mut deps) => {
                     deps.remove(*id);
                     if deps.is_empty() {
-                        cancellations.cancel(&critical);
+                        cancellations.cancel(&critical, CancellationReason::unknown());
                     }
                 }
             },
@@ -269,7 +275,7 @@ impl DiceTaskInternal {
                 Some(ref mut deps) => {
                     deps.remove(*id);
                     if deps.is_empty() {
-                        cancellations.cancel(&critical);
+                        cancellations.cancel(&critical, CancellationReason::unknown());
                     }
                 }
             },
@@ -414,7 +420,11 @@ impl Cancellations {
         Self { internal: None }
     }
 
-    pub(super) fn cancel(&self, _lock: &MutexGuard<DiceTaskInternalCritical>) {
+    pub(super) fn cancel(
+        &self,
+        _lock: &MutexGuard<DiceTaskInternalCritical>,
+        reason: CancellationReason,
+    ) {
         if let Some(internal) = self.internal.as_ref() {
             take_mut::take(
                 unsafe {
@@ -423,7 +433,7 @@ impl Cancellations {
                 },
                 |internal| match internal {
                     CancellationsInternal::NotCancelled(handle) => {
-                        handle.cancel();
+                        handle.cancel_with_reason(reason);
                         CancellationsInternal::Cancelled
                     }
                     cancelled => cancelled,
@@ -102,6 +102,15 @@ impl DicePromise {
     pub(crate) fn sync_get_or_complete(
         self,
         f: impl FnOnce() -> DiceSyncResult,
+    ) -> CancellableResult<DiceComputedValue> {
+        self.sync_get_or_try_complete(|| Ok(f()))
+    }
+
+    /// Like `sync_get_or_complete`, but `f` may give up with `Cancelled`, in which case the task
+    /// is terminated so that everyone waiting on it observes the cancellation.
+    pub(crate) fn sync_get_or_try_complete(
+        self,
+        f: impl FnOnce() -> CancellableResult<DiceSyncResult>,
     ) -> CancellableResult<DiceComputedValue> {
         match &self.0 {
             DicePromiseInternal::Ready { result } => Ok(result.dupe()),
@@ -132,7 +141,14 @@ impl DicePromise {
                             return Ok(res.dupe());
                         }
 
-                        let result = f();
+                        let result = match f() {
+                            Ok(result) => result,
+                            Err(Cancelled) => {
+                                drop(locked);
+                                task_internal.report_terminated();
+                                return Err(Cancelled);
+                            }
+                        };
 
                         assert!(
                             locked.replace(result.sync_result.dupe()).is_none(),
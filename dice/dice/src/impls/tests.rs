@@ -9,9 +9,14 @@
 
 mod activation_tracker;
 mod demo;
+mod deterministic;
 mod events;
+mod eviction;
 mod general;
+mod invalidation;
 mod keys;
+mod pins;
 mod spawner;
 mod transients;
 mod user_data;
+mod value_size;
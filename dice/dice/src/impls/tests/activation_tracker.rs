@@ -10,6 +10,7 @@
 use std::any::Any;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Instant;
 
 use allocative::Allocative;
 use async_trait::async_trait;
@@ -23,6 +24,8 @@ use crate::api::dice::Dice;
 use crate::api::key::Key;
 use crate::api::user_data::UserComputationData;
 use crate::ActivationData;
+use crate::ActivationOutcome;
+use crate::ActivationTiming;
 use crate::ActivationTracker;
 use crate::DiceDataBuilder;
 use crate::InjectedKey;
@@ -47,6 +50,7 @@ impl ActivationTracker for Tracker {
         key: &dyn Any,
         deps: &mut dyn Iterator<Item = &dyn Any>,
         activation_data: ActivationData,
+        _timing: ActivationTiming,
     ) {
         let (data, reused) = match activation_data {
             ActivationData::Evaluated(d) => (d.map(|d| *d.downcast::<Data>().unwrap()), false),
@@ -209,3 +213,72 @@ async fn test_events_legacy() -> anyhow::Result<()> {
 async fn test_events_modern() -> anyhow::Result<()> {
     test_events_impl(Dice::modern()).await
 }
+
+/// Key, timing
+#[derive(Default)]
+struct TimingTracker(Mutex<Vec<(Kind, ActivationTiming)>>);
+
+impl ActivationTracker for TimingTracker {
+    fn key_activated(
+        &self,
+        key: &dyn Any,
+        _deps: &mut dyn Iterator<Item = &dyn Any>,
+        _activation_data: ActivationData,
+        timing: ActivationTiming,
+    ) {
+        self.0.lock().unwrap().push((Kind::from_any(key), timing));
+    }
+}
+
+#[tokio::test]
+async fn test_timing_modern() -> anyhow::Result<()> {
+    let dice = Dice::modern().build(DetectCycles::Enabled);
+
+    let mut outcomes = Vec::new();
+    for value in [123, 456] {
+        let activation_tracker = Arc::new(TimingTracker::default());
+
+        let data = UserComputationData {
+            activation_tracker: Some(activation_tracker.dupe()),
+            ..Default::default()
+        };
+
+        let mut updater = dice.updater_with_data(data);
+        updater.changed_to(vec![(Injected, value)])?;
+
+        let mut transaction = updater.commit().await;
+        let before = Instant::now();
+        transaction.compute(&Stage1).await?;
+        let after = Instant::now();
+
+        let activations = activation_tracker.0.lock().unwrap();
+        for (_, timing) in activations.iter() {
+            assert!(before <= timing.start);
+            assert!(timing.start <= timing.end);
+            assert!(timing.end <= after);
+        }
+        outcomes.push(
+            activations
+                .iter()
+                .map(|(kind, timing)| (kind.dupe(), timing.outcome))
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    assert_eq!(
+        outcomes,
+        vec![
+            vec![
+                (Kind::Stage0, ActivationOutcome::RecomputedChanged),
+                (Kind::Stage1, ActivationOutcome::RecomputedChanged),
+            ],
+            // `Stage0` depends on the changed input, but its value is always equal.
+            vec![
+                (Kind::Stage0, ActivationOutcome::RecomputedSame),
+                (Kind::Stage1, ActivationOutcome::Reused),
+            ],
+        ]
+    );
+
+    Ok(())
+}
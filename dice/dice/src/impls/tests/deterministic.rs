@@ -0,0 +1,272 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::any::Any;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use allocative::Allocative;
+use async_trait::async_trait;
+use buck2_futures::cancellation::CancellationContext;
+use derivative::Derivative;
+use derive_more::Display;
+use dupe::Dupe;
+use futures::FutureExt;
+
+use crate::api::computations::DiceComputations;
+use crate::api::cycles::DetectCycles;
+use crate::api::injected::InjectedKey;
+use crate::api::key::Key;
+use crate::api::user_data::UserComputationData;
+use crate::impls::dice::DiceModern;
+use crate::ActivationData;
+use crate::ActivationTiming;
+use crate::ActivationTracker;
+use crate::Dice;
+
+/// Number of children of each non leaf node.
+const BRANCHING: u32 = 3;
+/// Number of distinct nodes at each depth, so that nodes share some of their children.
+const WIDTH: u32 = 5;
+
+#[derive(Clone, Dupe, Copy, Debug, Display, PartialEq, Eq, Hash, Allocative)]
+#[display(fmt = "{:?}", self)]
+struct Leaf(u32);
+
+impl InjectedKey for Leaf {
+    type Value = u64;
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+#[derive(Clone, Dupe, Copy, Debug, Display, PartialEq, Eq, Hash, Allocative)]
+#[display(fmt = "{:?}", self)]
+struct Node {
+    depth: u32,
+    index: u32,
+}
+
+#[async_trait]
+impl Key for Node {
+    type Value = u64;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        if self.depth == 0 {
+            return ctx.compute(&Leaf(self.index)).await.unwrap();
+        }
+
+        let children = (0..BRANCHING).map(|i| Node {
+            depth: self.depth - 1,
+            index: (self.index * BRANCHING + i) % WIDTH,
+        });
+        let values = ctx
+            .compute_join(children, |ctx, child| {
+                async move { ctx.compute(&child).await.unwrap() }.boxed()
+            })
+            .await;
+
+        values.into_iter().sum::<u64>() * 2 + self.index as u64
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+/// Records the evaluated keys along with their deps, in activation order.
+#[derive(Default)]
+struct Tracker(Mutex<Vec<(String, Vec<String>)>>);
+
+impl ActivationTracker for Tracker {
+    fn key_activated(
+        &self,
+        key: &dyn Any,
+        deps: &mut dyn Iterator<Item = &dyn Any>,
+        _activation_data: ActivationData,
+        _timing: ActivationTiming,
+    ) {
+        fn describe(key: &dyn Any) -> String {
+            if let Some(node) = key.downcast_ref::<Node>() {
+                node.to_string()
+            } else if let Some(leaf) = key.downcast_ref::<Leaf>() {
+                leaf.to_string()
+            } else {
+                panic!("Unexpected key: {:?}", key)
+            }
+        }
+
+        self.0
+            .lock()
+            .unwrap()
+            .push((describe(key), deps.map(describe).collect()));
+    }
+}
+
+fn current_thread_runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    value: u64,
+    activations: Vec<(String, Vec<String>)>,
+}
+
+impl Outcome {
+    fn sorted(mut self) -> Self {
+        for (_, deps) in &mut self.activations {
+            deps.sort();
+        }
+        self.activations.sort();
+        self
+    }
+}
+
+async fn compute_tree(deterministic_seed: Option<u64>) -> anyhow::Result<Outcome> {
+    let mut builder = DiceModern::builder();
+    if let Some(seed) = deterministic_seed {
+        builder.deterministic(seed)?;
+    }
+    let dice = builder.build(DetectCycles::Disabled);
+
+    let tracker = Arc::new(Tracker::default());
+    let data = UserComputationData {
+        activation_tracker: Some(tracker.dupe()),
+        ..Default::default()
+    };
+    let mut updater = dice.updater_with_data(data);
+    updater.changed_to((0..WIDTH).map(|i| (Leaf(i), (i * 7 + 1) as u64)))?;
+    let ctx = updater.commit().await;
+
+    let value = ctx.compute(&Node { depth: 4, index: 0 }).await?;
+
+    let activations = std::mem::take(&mut *tracker.0.lock().unwrap());
+    Ok(Outcome { value, activations })
+}
+
+#[test]
+fn deterministic_mode_computes_same_results_and_deps() {
+    let default_mode = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(compute_tree(None))
+        .unwrap();
+    let deterministic = current_thread_runtime()
+        .block_on(compute_tree(Some(17)))
+        .unwrap();
+    let deterministic_again = current_thread_runtime()
+        .block_on(compute_tree(Some(17)))
+        .unwrap();
+
+    // the same seed evaluates the keys in exactly the same order
+    assert_eq!(deterministic, deterministic_again);
+    assert_eq!(default_mode.sorted(), deterministic.sorted());
+}
+
+#[derive(Clone, Dupe, Debug, Display, Derivative, Allocative)]
+#[derivative(PartialEq, Eq, Hash)]
+#[display(fmt = "{:?}", self)]
+struct RacingKey {
+    #[derivative(Hash = "ignore", PartialEq = "ignore")]
+    computed: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl Key for RacingKey {
+    type Value = ();
+
+    async fn compute(
+        &self,
+        _ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        self.computed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn equality(_x: &Self::Value, _y: &Self::Value) -> bool {
+        true
+    }
+}
+
+/// Drops the only request for a key right after its evaluation finished, so that the cancellation
+/// races against the completion of the task. Returns whether the completed value was kept.
+async fn cancellation_race(seed: u64) -> anyhow::Result<bool> {
+    let mut builder = DiceModern::builder();
+    builder.deterministic(seed)?;
+    let dice = builder.build(DetectCycles::Disabled);
+    let ctx = dice.updater().commit().await;
+
+    let key = RacingKey {
+        computed: Arc::new(AtomicUsize::new(0)),
+    };
+
+    let mut request = ctx.compute(&key).boxed();
+    assert!(futures::poll!(&mut request).is_pending());
+    while key.computed.load(Ordering::SeqCst) == 0 {
+        tokio::task::yield_now().await;
+    }
+    drop(request);
+
+    // if the task was cancelled, requesting the key again evaluates it again
+    ctx.compute(&key).await?;
+    Ok(key.computed.load(Ordering::SeqCst) == 1)
+}
+
+#[test]
+fn seed_decides_cancellation_race() {
+    let outcomes: Vec<bool> = (0..16)
+        .map(|seed| {
+            current_thread_runtime()
+                .block_on(cancellation_race(seed))
+                .unwrap()
+        })
+        .collect();
+
+    for (seed, outcome) in outcomes.iter().enumerate() {
+        assert_eq!(
+            *outcome,
+            current_thread_runtime()
+                .block_on(cancellation_race(seed as u64))
+                .unwrap(),
+            "seed {} should always produce the same outcome",
+            seed
+        );
+    }
+
+    assert!(outcomes.contains(&true), "no seed kept the value");
+    assert!(outcomes.contains(&false), "no seed cancelled the task");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deterministic_mode_requires_current_thread_runtime() {
+    let err = DiceModern::builder().deterministic(1).unwrap_err();
+    assert!(err.to_string().contains("current thread"), "{}", err);
+}
+
+#[test]
+fn deterministic_mode_is_rejected_by_legacy_dice() {
+    let err = Dice::builder().deterministic(1).unwrap_err();
+    assert!(
+        err.to_string().contains("only supported by modern dice"),
+        "{}",
+        err
+    );
+}
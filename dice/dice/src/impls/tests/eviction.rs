@@ -0,0 +1,179 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use allocative::Allocative;
+use async_trait::async_trait;
+use buck2_futures::cancellation::CancellationContext;
+use derivative::Derivative;
+use derive_more::Display;
+use dupe::Dupe;
+
+use crate::api::computations::DiceComputations;
+use crate::api::cycles::DetectCycles;
+use crate::api::injected::InjectedKey;
+use crate::api::key::Key;
+use crate::impls::dice::DiceModern;
+use crate::Dice;
+
+#[derive(Clone, Copy, Dupe, Debug, Display, PartialEq, Eq, Hash, Allocative)]
+#[display(fmt = "{:?}", self)]
+enum Input {
+    Value,
+    /// Not depended on, changed only to start new versions.
+    Unrelated,
+}
+
+impl InjectedKey for Input {
+    type Value = usize;
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+/// Computes to `10 * Input + offset`, or to the value of its dep plus one, counting how many times
+/// it was computed.
+#[derive(Clone, Dupe, Debug, Display, Derivative, Allocative)]
+#[derivative(Hash, PartialEq, Eq)]
+#[display(fmt = "{:?}", self)]
+#[allocative(skip)]
+struct Weighted {
+    offset: usize,
+    weight: usize,
+    dep: Option<Arc<Weighted>>,
+    #[derivative(PartialEq = "ignore", Hash = "ignore")]
+    runs: Arc<AtomicUsize>,
+}
+
+impl Weighted {
+    fn new(offset: usize, weight: usize, dep: Option<&Weighted>) -> Self {
+        Self {
+            offset,
+            weight,
+            dep: dep.map(|dep| Arc::new(dep.dupe())),
+            runs: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn runs(&self) -> usize {
+        self.runs.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Key for Weighted {
+    type Value = usize;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        match &self.dep {
+            Some(dep) => ctx.compute(&**dep).await.unwrap() + 1,
+            None => 10 * ctx.compute(&Input::Value).await.unwrap() + self.offset,
+        }
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+
+    fn cache_weight(&self, _value: &Self::Value) -> usize {
+        self.weight
+    }
+}
+
+fn dice_with_max_cache_weight(max_weight: usize) -> Arc<DiceModern> {
+    let mut builder = DiceModern::builder();
+    builder.max_cache_weight(max_weight);
+    builder.build(DetectCycles::Disabled)
+}
+
+#[tokio::test]
+async fn least_recently_used_keys_are_evicted() -> anyhow::Result<()> {
+    let dice = dice_with_max_cache_weight(5);
+    let keys = (0..10)
+        .map(|offset| Weighted::new(offset, 1, None))
+        .collect::<Vec<_>>();
+    let unweighted = Weighted::new(10, 0, None);
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input::Value, 1), (Input::Unrelated, 0)])?;
+    let mut ctx = updater.commit().await;
+    ctx.compute(&unweighted).await?;
+    for key in &keys {
+        ctx.compute(key).await?;
+    }
+    drop(ctx);
+
+    // Only the last keys within the max weight, and keys without weight, are kept.
+    let mut updater = dice.updater();
+    updater.changed_to([(Input::Unrelated, 1)])?;
+    let mut ctx = updater.commit().await;
+    for key in keys[5..].iter().rev() {
+        assert_eq!(10 + key.offset, ctx.compute(key).await?);
+    }
+    assert_eq!(20, ctx.compute(&unweighted).await?);
+    assert!(keys[5..].iter().all(|k| k.runs() == 1));
+    assert_eq!(1, unweighted.runs());
+
+    assert_eq!(10, ctx.compute(&keys[0]).await?);
+    assert_eq!(2, keys[0].runs());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn evicted_keys_are_invalidated() -> anyhow::Result<()> {
+    let dice = dice_with_max_cache_weight(1);
+    let leaf = Weighted::new(0, 1, None);
+    let root = Weighted::new(0, 0, Some(&leaf));
+    let other = Weighted::new(5, 1, None);
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input::Value, 1), (Input::Unrelated, 0)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(11, ctx.compute(&root).await?);
+    // evicts the leaf, and the root depending on it
+    assert_eq!(15, ctx.compute(&other).await?);
+    drop(ctx);
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input::Unrelated, 1)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(11, ctx.compute(&root).await?);
+    assert_eq!((2, 2), (root.runs(), leaf.runs()));
+    drop(ctx);
+
+    // the evicted leaf doesn't keep the root from seeing changes to its deps
+    let mut updater = dice.updater();
+    updater.changed_to([(Input::Value, 2)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(21, ctx.compute(&root).await?);
+    assert_eq!(25, ctx.compute(&other).await?);
+    assert_eq!((3, 3), (root.runs(), leaf.runs()));
+
+    Ok(())
+}
+
+#[test]
+fn max_cache_weight_is_rejected_by_legacy_dice() {
+    let err = Dice::builder().max_cache_weight(5).unwrap_err();
+    assert!(
+        err.to_string().contains("only supported by modern dice"),
+        "{}",
+        err
+    );
+}
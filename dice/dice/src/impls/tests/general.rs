@@ -26,6 +26,7 @@ use tokio::sync::oneshot;
 use crate::api::computations::DiceComputations;
 use crate::api::cycles::DetectCycles;
 use crate::api::error::DiceErrorImpl;
+use crate::api::error::DiceResult;
 use crate::api::injected::InjectedKey;
 use crate::api::key::Key;
 use crate::api::user_data::UserComputationData;
@@ -132,6 +133,31 @@ async fn set_injected_with_no_change_no_new_ctx() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn computations_keep_their_version_across_commits() -> anyhow::Result<()> {
+    let dice = DiceModern::builder().build(DetectCycles::Disabled);
+
+    let mut updater = dice.updater();
+    updater.changed_to(vec![(Foo(0), 0)])?;
+    let mut before = updater.commit().await;
+    let token = DiceComputations::equality_token(&before);
+    assert_eq!(before.compute(&Foo(0)).await?, 0);
+
+    let mut updater = dice.updater();
+    updater.changed_to(vec![(Foo(0), 1)])?;
+    let mut after = updater.commit().await;
+
+    // The computation that started before the commit still reads the old state.
+    assert_eq!(before.compute(&Foo(0)).await?, 0);
+    assert!(token == DiceComputations::equality_token(&before));
+    assert!(token == before.equality_token());
+
+    assert_eq!(after.compute(&Foo(0)).await?, 1);
+    assert!(token != DiceComputations::equality_token(&after));
+
+    Ok(())
+}
+
 #[derive(Clone, Dupe, Display, Debug, Eq, PartialEq, Hash, Allocative)]
 #[display(fmt = "{:?}", self)]
 struct K(i32);
@@ -695,3 +721,97 @@ async fn test_dice_usable_after_cancellations() {
 
     assert!(is_ran.load(Ordering::SeqCst));
 }
+
+#[tokio::test]
+async fn compute_on_cancelled_transaction_reports_cancelled_key() -> anyhow::Result<()> {
+    #[derive(Clone, Dupe, Debug, Display, Eq, Hash, PartialEq, Allocative)]
+    #[display(fmt = "{:?}", self)]
+    struct Inner;
+
+    #[async_trait]
+    impl Key for Inner {
+        type Value = ();
+
+        async fn compute(
+            &self,
+            _ctx: &mut DiceComputations,
+            _cancellations: &CancellationContext,
+        ) -> Self::Value {
+        }
+
+        fn equality(_x: &Self::Value, _y: &Self::Value) -> bool {
+            true
+        }
+    }
+
+    /// Keeps running through its cancellation, and computes `Inner` once resumed.
+    #[derive(Clone, Dupe, Debug, Derivative, Allocative, Display)]
+    #[derivative(PartialEq, Eq, Hash)]
+    #[display(fmt = "{:?}", self)]
+    #[allocative(skip)]
+    struct Outer {
+        #[derivative(Hash = "ignore", PartialEq = "ignore")]
+        started: Arc<tokio::sync::Notify>,
+        #[derivative(Hash = "ignore", PartialEq = "ignore")]
+        resume: Arc<tokio::sync::Notify>,
+        #[derivative(Hash = "ignore", PartialEq = "ignore")]
+        result: Arc<Mutex<Option<oneshot::Sender<DiceResult<()>>>>>,
+    }
+
+    #[async_trait]
+    impl Key for Outer {
+        type Value = ();
+
+        async fn compute(
+            &self,
+            ctx: &mut DiceComputations,
+            cancellations: &CancellationContext,
+        ) -> Self::Value {
+            cancellations
+                .critical_section(|| async move {
+                    self.started.notify_one();
+                    self.resume.notified().await;
+                    let res = ctx.compute(&Inner).await;
+                    let _ignored = self.result.lock().unwrap().take().unwrap().send(res);
+                })
+                .await
+        }
+
+        fn equality(_x: &Self::Value, _y: &Self::Value) -> bool {
+            true
+        }
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let key = Outer {
+        started: Arc::new(tokio::sync::Notify::new()),
+        resume: Arc::new(tokio::sync::Notify::new()),
+        result: Arc::new(Mutex::new(Some(tx))),
+    };
+
+    let dice = DiceModern::builder().build(DetectCycles::Disabled);
+    let ctx = dice.updater().commit().await;
+    let req = tokio::spawn({
+        let key = key.dupe();
+        async move {
+            let _ignored = ctx.compute(&key).await;
+        }
+    });
+    key.started.notified().await;
+
+    // dropping the only transaction at the version cancels it while `Outer` is still running
+    req.abort();
+    let _ignored = req.await;
+    // requests are processed in order, so the transaction has been cancelled once this returns
+    drop(dice.updater().commit().await);
+
+    key.resume.notify_one();
+    let err = rx.await?.unwrap_err();
+    assert!(err.is_cancelled());
+    assert_matches!(
+        &*err.0,
+        DiceErrorImpl::Cancelled { key_display } if key_display == "Inner"
+    );
+
+    Ok(())
+}
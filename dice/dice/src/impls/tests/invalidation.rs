@@ -0,0 +1,133 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use allocative::Allocative;
+use async_trait::async_trait;
+use buck2_futures::cancellation::CancellationContext;
+use derive_more::Display;
+use dupe::Dupe;
+
+use crate::api::computations::DiceComputations;
+use crate::api::data::DiceData;
+use crate::api::injected::InjectedKey;
+use crate::api::key::Key;
+use crate::impls::dice::DiceModern;
+
+#[derive(Allocative, Clone, Copy, Dupe, Debug, Display, PartialEq, Eq, Hash)]
+#[display(fmt = "{:?}", self)]
+enum Input {
+    Leaf,
+    Other,
+}
+
+impl InjectedKey for Input {
+    type Value = usize;
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+#[derive(Allocative, Clone, Copy, Dupe, Debug, Display, PartialEq, Eq, Hash)]
+#[display(fmt = "{:?}", self)]
+struct Mid;
+
+#[async_trait]
+impl Key for Mid {
+    type Value = usize;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        ctx.compute(&Input::Other).await.unwrap() + ctx.compute(&Input::Leaf).await.unwrap()
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+#[derive(Allocative, Clone, Copy, Dupe, Debug, Display, PartialEq, Eq, Hash)]
+#[display(fmt = "{:?}", self)]
+struct Root;
+
+#[async_trait]
+impl Key for Root {
+    type Value = usize;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        ctx.compute(&Mid).await.unwrap() * 2
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+#[derive(Allocative, Clone, Copy, Dupe, Debug, Display, PartialEq, Eq, Hash)]
+#[display(fmt = "{:?}", self)]
+struct Unrelated;
+
+#[async_trait]
+impl Key for Unrelated {
+    type Value = usize;
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        ctx.compute(&Input::Other).await.unwrap()
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+#[tokio::test]
+async fn compute_reason_reports_path_from_changed_key() -> anyhow::Result<()> {
+    let dice = DiceModern::new(DiceData::new());
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input::Leaf, 1), (Input::Other, 10)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(22, ctx.compute(&Root).await?);
+    assert_eq!(10, ctx.compute(&Unrelated).await?);
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input::Leaf, 2)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(24, ctx.compute(&Root).await?);
+    assert_eq!(10, ctx.compute(&Unrelated).await?);
+
+    let path = ctx
+        .compute_reason(&Root)
+        .await
+        .expect("root was recomputed");
+    assert_eq!(&["Leaf", "Mid", "Root"], path.keys());
+    assert_eq!("Leaf -> Mid -> Root", path.to_string());
+
+    let path = ctx
+        .compute_reason(&Input::Leaf)
+        .await
+        .expect("leaf changed");
+    assert_eq!(&["Leaf"], path.keys());
+
+    assert_eq!(None, ctx.compute_reason(&Unrelated).await);
+    assert_eq!(None, ctx.compute_reason(&Input::Other).await);
+
+    Ok(())
+}
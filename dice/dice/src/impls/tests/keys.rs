@@ -17,10 +17,14 @@ use buck2_futures::cancellation::CancellationContext;
 use derive_more::Display;
 use dupe::Dupe;
 use futures::future::join3;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::sync::Barrier;
 use tokio::sync::Mutex;
 
 use crate::api::computations::DiceComputations;
 use crate::api::data::DiceData;
+use crate::api::injected::InjectedKey;
 use crate::api::key::Key;
 use crate::impls::dice::DiceModern;
 
@@ -157,3 +161,259 @@ fn different_requests_are_spawned_in_parallel() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn compute_join_keys_computes_in_parallel_in_order() -> anyhow::Result<()> {
+    /// Only finishes once all the keys sharing its barrier are being computed.
+    #[derive(Allocative, Clone, Debug, Display)]
+    #[display(fmt = "{:?}", self)]
+    struct WaitForAll(usize, #[allocative(skip)] Arc<Barrier>);
+
+    impl PartialEq for WaitForAll {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl Eq for WaitForAll {}
+
+    impl Hash for WaitForAll {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.0.hash(state)
+        }
+    }
+
+    #[async_trait]
+    impl Key for WaitForAll {
+        type Value = usize;
+
+        async fn compute(
+            &self,
+            _ctx: &mut DiceComputations,
+            _cancellations: &CancellationContext,
+        ) -> Self::Value {
+            self.1.wait().await;
+            self.0
+        }
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    #[derive(Allocative, Clone, Debug, Display)]
+    #[display(fmt = "{:?}", self)]
+    struct JoinAll(usize, #[allocative(skip)] Arc<Barrier>);
+
+    impl PartialEq for JoinAll {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl Eq for JoinAll {}
+
+    impl Hash for JoinAll {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.0.hash(state)
+        }
+    }
+
+    #[async_trait]
+    impl Key for JoinAll {
+        type Value = Vec<usize>;
+
+        async fn compute(
+            &self,
+            ctx: &mut DiceComputations,
+            _cancellations: &CancellationContext,
+        ) -> Self::Value {
+            ctx.compute_join_keys((0..self.0).rev().map(|i| WaitForAll(i, self.1.dupe())))
+                .await
+                .into_iter()
+                .map(|v| v.unwrap())
+                .collect()
+        }
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    let n = 10;
+    let dice = DiceModern::new(DiceData::new());
+    let mut ctx = dice.updater().commit().await;
+
+    // Computing the keys one after the other would never get past the barrier.
+    let values = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        ctx.compute(&JoinAll(n, Arc::new(Barrier::new(n)))),
+    )
+    .await??;
+    assert_eq!((0..n).rev().collect::<Vec<_>>(), values);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn compute_join_keys_records_deps() -> anyhow::Result<()> {
+    #[derive(Allocative, Clone, Dupe, Debug, Display, PartialEq, Eq, Hash)]
+    #[display(fmt = "{:?}", self)]
+    struct Input(usize);
+
+    impl InjectedKey for Input {
+        type Value = usize;
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    #[derive(Allocative, Clone, Dupe, Debug, Display, PartialEq, Eq, Hash)]
+    #[display(fmt = "{:?}", self)]
+    struct SumOfInputs(usize);
+
+    #[async_trait]
+    impl Key for SumOfInputs {
+        type Value = usize;
+
+        async fn compute(
+            &self,
+            ctx: &mut DiceComputations,
+            _cancellations: &CancellationContext,
+        ) -> Self::Value {
+            ctx.compute_join_keys((0..self.0).map(Input))
+                .await
+                .into_iter()
+                .map(|v| v.unwrap())
+                .sum()
+        }
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    let dice = DiceModern::new(DiceData::new());
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input(0), 1), (Input(1), 2), (Input(2), 3)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(6, ctx.compute(&SumOfInputs(3)).await?);
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input(1), 20)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(24, ctx.compute(&SumOfInputs(3)).await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn try_compute2_returns_first_error_without_waiting() -> anyhow::Result<()> {
+    let dice = DiceModern::new(DiceData::new());
+    let mut ctx = dice.updater().commit().await;
+
+    let res = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        ctx.try_compute2(
+            |_| futures::future::pending::<Result<(), &str>>().boxed(),
+            |_| async { Err::<(), _>("failed") }.boxed(),
+        ),
+    )
+    .await?;
+    assert_eq!(Err("failed"), res);
+
+    let res = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        ctx.try_compute_many((0..2).map(|i| {
+            DiceComputations::declare_closure(
+                move |_: &mut DiceComputations| -> BoxFuture<Result<(), &'static str>> {
+                    if i == 0 {
+                        futures::future::pending().boxed()
+                    } else {
+                        async { Err("failed") }.boxed()
+                    }
+                },
+            )
+        })),
+    )
+    .await?;
+    assert_eq!(Err("failed"), res);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn try_compute2_records_deps() -> anyhow::Result<()> {
+    #[derive(Allocative, Clone, Dupe, Debug, Display, PartialEq, Eq, Hash)]
+    #[display(fmt = "{:?}", self)]
+    struct Input(usize);
+
+    impl InjectedKey for Input {
+        type Value = usize;
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    /// The sum of the two inputs, if the second one is small.
+    #[derive(Allocative, Clone, Dupe, Debug, Display, PartialEq, Eq, Hash)]
+    #[display(fmt = "{:?}", self)]
+    struct SumIfSmall;
+
+    #[async_trait]
+    impl Key for SumIfSmall {
+        type Value = Option<usize>;
+
+        async fn compute(
+            &self,
+            ctx: &mut DiceComputations,
+            _cancellations: &CancellationContext,
+        ) -> Self::Value {
+            let (a, b) = ctx
+                .try_compute2(
+                    |ctx| async move { Ok(ctx.compute(&Input(0)).await.unwrap()) }.boxed(),
+                    |ctx| {
+                        async move {
+                            let v = ctx.compute(&Input(1)).await.unwrap();
+                            if v < 10 { Ok(v) } else { Err(()) }
+                        }
+                        .boxed()
+                    },
+                )
+                .await
+                .ok()?;
+            Some(a + b)
+        }
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+    }
+
+    let dice = DiceModern::new(DiceData::new());
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input(0), 1), (Input(1), 2)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(Some(3), ctx.compute(&SumIfSmall).await?);
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input(0), 5)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(Some(7), ctx.compute(&SumIfSmall).await?);
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input(1), 20)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(None, ctx.compute(&SumIfSmall).await?);
+
+    let mut updater = dice.updater();
+    updater.changed_to([(Input(1), 3)])?;
+    let mut ctx = updater.commit().await;
+    assert_eq!(Some(8), ctx.compute(&SumIfSmall).await?);
+
+    Ok(())
+}
@@ -0,0 +1,239 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use allocative::Allocative;
+use async_trait::async_trait;
+use buck2_futures::cancellation::CancellationContext;
+use derivative::Derivative;
+use derive_more::Display;
+use dupe::Dupe;
+
+use crate::api::computations::DiceComputations;
+use crate::api::cycles::DetectCycles;
+use crate::api::key::Key;
+use crate::api::pin::PinLifetime;
+use crate::impls::dice::DiceModern;
+use crate::introspection::graph::PinSummary;
+
+/// Counts how many times it was computed.
+#[derive(Clone, Dupe, Debug, Display, Derivative, Allocative)]
+#[derivative(Hash, PartialEq, Eq)]
+#[display(fmt = "{:?}", self)]
+#[allocative(skip)]
+struct Counted {
+    name: &'static str,
+    dep: Option<Arc<Counted>>,
+    #[derivative(PartialEq = "ignore", Hash = "ignore")]
+    runs: Arc<AtomicUsize>,
+}
+
+impl Counted {
+    fn new(name: &'static str, dep: Option<&Counted>) -> Self {
+        Self {
+            name,
+            dep: dep.map(|dep| Arc::new(dep.dupe())),
+            runs: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn runs(&self) -> usize {
+        self.runs.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Key for Counted {
+    type Value = ();
+
+    async fn compute(
+        &self,
+        ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        if let Some(dep) = &self.dep {
+            ctx.compute(&**dep).await.unwrap();
+        }
+    }
+
+    fn equality(_x: &Self::Value, _y: &Self::Value) -> bool {
+        true
+    }
+}
+
+async fn pin_summary(dice: &Arc<DiceModern>) -> PinSummary {
+    let dice = dice.dupe();
+    tokio::task::spawn_blocking(move || dice.to_introspectable().pin_summary())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn pinned_key_survives_trim() -> anyhow::Result<()> {
+    let dice = DiceModern::builder().build(DetectCycles::Disabled);
+
+    let dep = Counted::new("dep", None);
+    let pinned = Counted::new("pinned", Some(&dep));
+    let sibling = Counted::new("sibling", None);
+
+    let ctx = dice.updater().commit().await;
+    ctx.pin(&pinned, PinLifetime::Transaction).await?;
+    ctx.compute(&sibling).await?;
+
+    let summary = pin_summary(&dice).await;
+    assert_eq!(summary.pinned_keys, 1);
+    // the dependency of the pinned key is retained as well
+    assert_eq!(summary.retained_keys, 2);
+    assert!(summary.retained_bytes > 0);
+
+    dice.updater().unstable_take();
+
+    let ctx1 = dice.updater().commit().await;
+    ctx1.compute(&pinned).await?;
+    ctx1.compute(&dep).await?;
+    ctx1.compute(&sibling).await?;
+
+    assert_eq!(pinned.runs(), 1);
+    assert_eq!(dep.runs(), 1);
+    assert_eq!(sibling.runs(), 2);
+
+    drop(ctx);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn transaction_pin_released_on_drop() -> anyhow::Result<()> {
+    let dice = DiceModern::builder().build(DetectCycles::Disabled);
+
+    let transaction_pinned = Counted::new("transaction", None);
+    let daemon_pinned = Counted::new("daemon", None);
+
+    {
+        let ctx = dice.updater().commit().await;
+        ctx.pin(&transaction_pinned, PinLifetime::Transaction)
+            .await?;
+        ctx.pin(&daemon_pinned, PinLifetime::Daemon).await?;
+        assert_eq!(pin_summary(&dice).await.pinned_keys, 2);
+    }
+
+    // only the daemon pin outlives the transaction
+    assert_eq!(pin_summary(&dice).await.pinned_keys, 1);
+
+    dice.updater().unstable_take();
+
+    {
+        let ctx = dice.updater().commit().await;
+        ctx.compute(&transaction_pinned).await?;
+        ctx.compute(&daemon_pinned).await?;
+        assert_eq!(transaction_pinned.runs(), 2);
+        assert_eq!(daemon_pinned.runs(), 1);
+
+        ctx.unpin(&daemon_pinned, PinLifetime::Daemon);
+    }
+
+    assert_eq!(pin_summary(&dice).await.pinned_keys, 0);
+
+    dice.updater().unstable_take();
+
+    let ctx = dice.updater().commit().await;
+    ctx.compute(&daemon_pinned).await?;
+    assert_eq!(daemon_pinned.runs(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn overlapping_transactions_keep_their_own_pins() -> anyhow::Result<()> {
+    let dice = DiceModern::builder().build(DetectCycles::Disabled);
+
+    let pinned = Counted::new("pinned", None);
+
+    let ctx1 = dice.updater().commit().await;
+    let ctx2 = dice.updater().commit().await;
+    assert_eq!(ctx1.get_version(), ctx2.get_version());
+
+    ctx1.pin(&pinned, PinLifetime::Transaction).await?;
+    ctx2.pin(&pinned, PinLifetime::Transaction).await?;
+    assert_eq!(pin_summary(&dice).await.pinned_keys, 1);
+
+    // releasing the pin of one transaction keeps the pin of the other
+    ctx1.unpin(&pinned, PinLifetime::Transaction);
+    assert_eq!(pin_summary(&dice).await.pinned_keys, 1);
+
+    // as does one of the transactions exiting
+    drop(ctx1);
+    assert_eq!(pin_summary(&dice).await.pinned_keys, 1);
+
+    dice.updater().unstable_take();
+
+    let ctx3 = dice.updater().commit().await;
+    ctx3.compute(&pinned).await?;
+    assert_eq!(pinned.runs(), 1);
+
+    drop(ctx2);
+    drop(ctx3);
+    assert_eq!(pin_summary(&dice).await.pinned_keys, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pin_on_error_does_not_stick() -> anyhow::Result<()> {
+    /// Succeeds unless `fail` is set. Failures are not cached.
+    #[derive(Clone, Dupe, Debug, Display, Derivative, Allocative)]
+    #[derivative(Hash, PartialEq, Eq)]
+    #[display(fmt = "{:?}", self)]
+    struct Flaky(#[derivative(PartialEq = "ignore", Hash = "ignore")] Arc<AtomicBool>);
+
+    #[async_trait]
+    impl Key for Flaky {
+        type Value = bool;
+
+        async fn compute(
+            &self,
+            _ctx: &mut DiceComputations,
+            _cancellations: &CancellationContext,
+        ) -> Self::Value {
+            !self.0.load(Ordering::SeqCst)
+        }
+
+        fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+            x == y
+        }
+
+        fn validity(x: &Self::Value) -> bool {
+            *x
+        }
+    }
+
+    let dice = DiceModern::builder().build(DetectCycles::Disabled);
+    let fail = Arc::new(AtomicBool::new(true));
+
+    {
+        let ctx = dice.updater().commit().await;
+        assert!(!ctx.pin(&Flaky(fail.dupe()), PinLifetime::Daemon).await?);
+        assert_eq!(pin_summary(&dice).await, PinSummary::default());
+    }
+
+    fail.store(false, Ordering::SeqCst);
+
+    let ctx = dice.updater().commit().await;
+    assert!(ctx.pin(&Flaky(fail.dupe()), PinLifetime::Daemon).await?);
+    let summary = pin_summary(&dice).await;
+    assert_eq!(summary.pinned_keys, 1);
+    assert_eq!(summary.retained_keys, 1);
+
+    Ok(())
+}
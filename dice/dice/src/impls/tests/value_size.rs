@@ -0,0 +1,286 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use allocative::Allocative;
+use allocative::Visitor;
+use async_trait::async_trait;
+use buck2_futures::cancellation::CancellationContext;
+use derivative::Derivative;
+use derive_more::Display;
+use dupe::Dupe;
+
+use crate::api::computations::DiceComputations;
+use crate::api::cycles::DetectCycles;
+use crate::api::key::Key;
+use crate::api::value_size::OversizedValue;
+use crate::api::value_size::ValueSizeConfig;
+use crate::impls::dice::DiceModern;
+use crate::introspection::graph::ValueSizeSummary;
+use crate::introspection::graph::ValueSizeTotals;
+use crate::Dice;
+
+/// Computes a value retaining the given number of bytes.
+#[derive(Clone, Dupe, Debug, Display, PartialEq, Eq, Hash, Allocative)]
+#[display(fmt = "{:?}", self)]
+struct Bytes(usize);
+
+#[async_trait]
+impl Key for Bytes {
+    type Value = Arc<Vec<u8>>;
+
+    async fn compute(
+        &self,
+        _ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        Arc::new(vec![0; self.0])
+    }
+
+    fn equality(x: &Self::Value, y: &Self::Value) -> bool {
+        x == y
+    }
+}
+
+/// A value taking `N` bytes inline that counts how many times it was measured.
+#[derive(Clone)]
+struct InstrumentedValue<const N: usize> {
+    visits: Arc<AtomicUsize>,
+    _padding: [u8; N],
+}
+
+impl<const N: usize> Dupe for InstrumentedValue<N> {}
+
+impl<const N: usize> Allocative for InstrumentedValue<N> {
+    fn visit<'a, 'b: 'a>(&self, visitor: &'a mut Visitor<'b>) {
+        self.visits.fetch_add(1, Ordering::SeqCst);
+        visitor.visit_simple_sized::<Self>();
+    }
+}
+
+#[derive(Clone, Dupe, Debug, Display, Derivative, Allocative)]
+#[derivative(Hash, PartialEq, Eq)]
+#[display(fmt = "Instrumented({})", index)]
+#[allocative(skip)]
+struct Instrumented<const N: usize> {
+    index: usize,
+    #[derivative(PartialEq = "ignore", Hash = "ignore")]
+    visits: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl<const N: usize> Key for Instrumented<N> {
+    type Value = InstrumentedValue<N>;
+
+    async fn compute(
+        &self,
+        _ctx: &mut DiceComputations,
+        _cancellations: &CancellationContext,
+    ) -> Self::Value {
+        InstrumentedValue {
+            visits: self.visits.dupe(),
+            _padding: [0; N],
+        }
+    }
+
+    fn equality(_x: &Self::Value, _y: &Self::Value) -> bool {
+        false
+    }
+}
+
+fn dice_with(config: ValueSizeConfig) -> Arc<DiceModern> {
+    let mut builder = DiceModern::builder();
+    builder.value_size_accounting(config);
+    builder.build(DetectCycles::Disabled)
+}
+
+async fn value_size_summary(dice: &Arc<DiceModern>) -> ValueSizeSummary {
+    let dice = dice.dupe();
+    tokio::task::spawn_blocking(move || dice.to_introspectable().value_size_summary())
+        .await
+        .unwrap()
+}
+
+fn totals<K: Key>(summary: &ValueSizeSummary) -> ValueSizeTotals {
+    summary
+        .by_key_type
+        .get(K::key_type_name())
+        .copied()
+        .unwrap_or_default()
+}
+
+#[tokio::test]
+async fn large_value_is_attributed_to_key_type() -> anyhow::Result<()> {
+    let dice = dice_with(ValueSizeConfig {
+        sample_rate: 1,
+        ..Default::default()
+    });
+
+    let ctx = dice.updater().commit().await;
+    ctx.compute(&Bytes(1 << 20)).await?;
+    ctx.compute(&Bytes(10)).await?;
+    ctx.compute(&Instrumented::<8> {
+        index: 0,
+        visits: Default::default(),
+    })
+    .await?;
+
+    let summary = value_size_summary(&dice).await;
+    let bytes = totals::<Bytes>(&summary);
+    assert_eq!(bytes.values, 2);
+    assert_eq!(bytes.measured_values, 2);
+    assert!(bytes.measured_bytes >= (1 << 20) + 10);
+    assert!(bytes.max_value_bytes >= 1 << 20);
+    assert_eq!(bytes.estimated_bytes(), bytes.measured_bytes);
+    assert_eq!(bytes.oversized_values, 0);
+
+    let instrumented = totals::<Instrumented<8>>(&summary);
+    assert_eq!(instrumented.values, 1);
+    assert!(instrumented.measured_bytes < 1 << 10);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn oversized_value_is_reported_with_key() -> anyhow::Result<()> {
+    let reported = Arc::new(Mutex::new(Vec::<OversizedValue>::new()));
+    let on_oversized: Arc<dyn Fn(&OversizedValue) + Send + Sync> = {
+        let reported = reported.dupe();
+        Arc::new(move |oversized: &OversizedValue| reported.lock().unwrap().push(oversized.clone()))
+    };
+    let dice = dice_with(ValueSizeConfig {
+        sample_rate: 1,
+        max_value_bytes: Some(1 << 16),
+        on_oversized: Some(on_oversized),
+        ..Default::default()
+    });
+
+    let ctx = dice.updater().commit().await;
+    ctx.compute(&Bytes(1 << 20)).await?;
+    ctx.compute(&Bytes(10)).await?;
+
+    let reported = reported.lock().unwrap().clone();
+    assert_eq!(reported.len(), 1);
+    assert_eq!(reported[0].key, "Bytes(1048576)");
+    assert_eq!(reported[0].key_type, Bytes::key_type_name());
+    assert_eq!(reported[0].max_bytes, 1 << 16);
+    assert!(reported[0].bytes >= 1 << 20);
+    assert!(reported[0].to_string().contains("`Bytes(1048576)`"));
+
+    let summary = value_size_summary(&dice).await;
+    assert_eq!(totals::<Bytes>(&summary).oversized_values, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn strict_mode_fails_requests_for_oversized_values() -> anyhow::Result<()> {
+    let dice = dice_with(ValueSizeConfig {
+        sample_rate: 1,
+        max_value_bytes: Some(1 << 16),
+        strict: true,
+        ..Default::default()
+    });
+
+    let ctx = dice.updater().commit().await;
+    ctx.compute(&Bytes(10)).await?;
+    let err = ctx.compute(&Bytes(1 << 20)).await.unwrap_err();
+    assert!(err.to_string().contains("`Bytes(1048576)`"), "{}", err);
+    // The value is cached, and requesting it again still fails.
+    assert!(ctx.compute(&Bytes(1 << 20)).await.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn value_size_accounting_is_rejected_by_legacy_dice() {
+    let err = Dice::builder()
+        .value_size_accounting(ValueSizeConfig::default())
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("only supported by modern dice"),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn small_values_skip_measurement() -> anyhow::Result<()> {
+    let dice = dice_with(ValueSizeConfig {
+        always_measure_inline_bytes: 256,
+        sample_rate: 0,
+        ..Default::default()
+    });
+
+    let small_visits = Arc::new(AtomicUsize::new(0));
+    let wide_visits = Arc::new(AtomicUsize::new(0));
+
+    let ctx = dice.updater().commit().await;
+    for index in 0..10 {
+        ctx.compute(&Instrumented::<8> {
+            index,
+            visits: small_visits.dupe(),
+        })
+        .await?;
+        ctx.compute(&Instrumented::<512> {
+            index,
+            visits: wide_visits.dupe(),
+        })
+        .await?;
+    }
+
+    assert_eq!(small_visits.load(Ordering::SeqCst), 0);
+    assert_eq!(wide_visits.load(Ordering::SeqCst), 10);
+
+    let summary = value_size_summary(&dice).await;
+    let small = totals::<Instrumented<8>>(&summary);
+    assert_eq!(small.values, 10);
+    assert_eq!(small.measured_values, 0);
+    assert_eq!(small.estimated_bytes(), 0);
+    let wide = totals::<Instrumented<512>>(&summary);
+    assert_eq!(wide.values, 10);
+    assert_eq!(wide.measured_values, 10);
+    assert!(wide.measured_bytes >= 10 * 512);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn small_values_are_sampled() -> anyhow::Result<()> {
+    let dice = dice_with(ValueSizeConfig {
+        sample_rate: 4,
+        ..Default::default()
+    });
+
+    let visits = Arc::new(AtomicUsize::new(0));
+
+    let ctx = dice.updater().commit().await;
+    for index in 0..10 {
+        ctx.compute(&Instrumented::<8> {
+            index,
+            visits: visits.dupe(),
+        })
+        .await?;
+    }
+
+    // the 1st, 5th and 9th values
+    assert_eq!(visits.load(Ordering::SeqCst), 3);
+
+    let summary = value_size_summary(&dice).await;
+    let sampled = totals::<Instrumented<8>>(&summary);
+    assert_eq!(sampled.values, 10);
+    assert_eq!(sampled.measured_values, 3);
+    assert_eq!(sampled.estimated_bytes(), sampled.measured_bytes * 10 / 3);
+
+    Ok(())
+}
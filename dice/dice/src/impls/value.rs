@@ -31,7 +31,6 @@ impl Debug for DiceValidValue {
 }
 
 impl DiceValidValue {
-    #[cfg(test)]
     pub(crate) fn downcast_ref<V: Any>(&self) -> Option<&V> {
         self.0.downcast_ref()
     }
@@ -40,6 +39,15 @@ impl DiceValidValue {
     pub(crate) fn equality(&self, other: &DiceValidValue) -> bool {
         self.0.equality(&*other.0)
     }
+
+    /// Size of the value itself, excluding any data it points to.
+    pub(crate) fn inline_size(&self) -> usize {
+        std::mem::size_of_val(&*self.0)
+    }
+
+    pub(crate) fn as_allocative(&self) -> &dyn Allocative {
+        self.0.as_allocative()
+    }
 }
 
 /// Type erased value that may be transient, or whose dependencies are transient
@@ -92,7 +100,6 @@ impl MaybeValidDiceValue {
         self.value.equality(&*other.0)
     }
 
-    #[cfg(test)]
     pub(crate) fn instance_equal(&self, other: &DiceValidValue) -> bool {
         #[allow(ambiguous_wide_pointer_comparisons)]
         // we literally just want to compare the exact pointer
@@ -150,6 +157,7 @@ pub(crate) trait DiceValueDyn: Allocative + Any + Send + Sync + 'static {
     /// Panics if called with incompatible values.
     fn equality(&self, other: &dyn DiceValueDyn) -> bool;
     fn validity(&self) -> bool;
+    fn as_allocative(&self) -> &dyn Allocative;
 }
 
 impl dyn DiceValueDyn {
@@ -187,6 +195,10 @@ where
     fn validity(&self) -> bool {
         K::validity(&self.value)
     }
+
+    fn as_allocative(&self) -> &dyn Allocative {
+        self
+    }
 }
 
 #[derive(Allocative)]
@@ -218,6 +230,10 @@ where
     fn validity(&self) -> bool {
         K::validity(&self.value)
     }
+
+    fn as_allocative(&self) -> &dyn Allocative {
+        self
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,138 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//!
+//! Accounts for the size of the values stored in the graph, per key type.
+//!
+//! Values are measured by the worker that computed them, right before they are sent to the core
+//! state, so that the single threaded state processing never pays for it.
+
+use allocative::Allocative;
+use allocative::FlameGraphBuilder;
+use dashmap::DashMap;
+use fxhash::FxBuildHasher;
+
+use crate::api::error::DiceError;
+use crate::api::error::DiceResult;
+use crate::api::value_size::OversizedValue;
+use crate::api::value_size::ValueSizeConfig;
+use crate::impls::key::DiceKey;
+use crate::impls::key::DiceKeyErased;
+use crate::impls::value::DiceValidValue;
+use crate::introspection::graph::ValueSizeSummary;
+use crate::introspection::graph::ValueSizeTotals;
+
+#[derive(Allocative)]
+pub(crate) struct ValueSizeAccounting {
+    #[allocative(skip)]
+    config: ValueSizeConfig,
+    #[allocative(skip)]
+    totals: DashMap<&'static str, ValueSizeTotals, FxBuildHasher>,
+    /// In strict mode, the keys whose last measured value was oversized.
+    #[allocative(skip)]
+    oversized: DashMap<DiceKey, OversizedValue, FxBuildHasher>,
+}
+
+impl ValueSizeAccounting {
+    pub(crate) fn new(config: ValueSizeConfig) -> Self {
+        Self {
+            config,
+            totals: DashMap::default(),
+            oversized: DashMap::default(),
+        }
+    }
+
+    /// Records a value about to be stored for `key`, measuring it if the config says so.
+    pub(crate) fn record(&self, k: DiceKey, key: &DiceKeyErased, value: &DiceValidValue) {
+        let key_type = key.key_type_name();
+
+        let measure = {
+            let mut totals = self.totals.entry(key_type).or_default();
+            let sampled =
+                self.config.sample_rate != 0 && totals.values % self.config.sample_rate == 0;
+            totals.values += 1;
+            sampled || value.inline_size() >= self.config.always_measure_inline_bytes
+        };
+        if !measure {
+            return;
+        }
+
+        // Measured without holding the entry, since this may take a while for large values.
+        let bytes = retained_size(value.as_allocative());
+        let oversized = self
+            .config
+            .max_value_bytes
+            .filter(|max_bytes| bytes > *max_bytes);
+
+        {
+            let mut totals = self.totals.entry(key_type).or_default();
+            totals.measured_values += 1;
+            totals.measured_bytes += bytes as u64;
+            totals.max_value_bytes = totals.max_value_bytes.max(bytes as u64);
+            if oversized.is_some() {
+                totals.oversized_values += 1;
+            }
+        }
+
+        match oversized {
+            Some(max_bytes) => self.report_oversized(
+                k,
+                OversizedValue {
+                    key: key.to_string(),
+                    key_type,
+                    bytes,
+                    max_bytes,
+                },
+            ),
+            None => {
+                if self.config.strict {
+                    self.oversized.remove(&k);
+                }
+            }
+        }
+    }
+
+    fn report_oversized(&self, k: DiceKey, oversized: OversizedValue) {
+        tracing::error!("{}", oversized);
+        if let Some(on_oversized) = &self.config.on_oversized {
+            on_oversized(&oversized);
+        }
+        if self.config.strict {
+            self.oversized.insert(k, oversized);
+        }
+    }
+
+    /// In strict mode, fails the requests for keys whose value is oversized.
+    pub(crate) fn check(&self, k: DiceKey) -> DiceResult<()> {
+        if !self.config.strict {
+            return Ok(());
+        }
+        match self.oversized.get(&k) {
+            Some(oversized) => Err(DiceError::oversized_value(oversized.clone())),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn summary(&self) -> ValueSizeSummary {
+        ValueSizeSummary {
+            by_key_type: self
+                .totals
+                .iter()
+                .map(|entry| (*entry.key(), *entry.value()))
+                .collect(),
+        }
+    }
+}
+
+/// Size of the value and of everything it retains, including data behind shared pointers.
+fn retained_size(value: &dyn Allocative) -> usize {
+    let mut builder = FlameGraphBuilder::default();
+    builder.visit_root(value);
+    builder.finish().flamegraph().total_size()
+}
@@ -10,6 +10,7 @@
 //! The main worker thread for the dice task
 
 use std::sync::Arc;
+use std::time::Instant;
 
 use buck2_futures::cancellable_future::DisableCancellationGuard;
 use buck2_futures::cancellation::ExplicitCancellationContext;
@@ -28,6 +29,8 @@ use crate::impls::value::DiceComputedValue;
 use crate::result::CancellableResult;
 use crate::result::Cancelled;
 use crate::ActivationData;
+use crate::ActivationOutcome;
+use crate::ActivationTiming;
 use crate::ActivationTracker;
 
 /// Represents when we are in a spawned dice task worker and are currently waiting for the previous
@@ -317,6 +320,14 @@ pub(crate) struct DiceWorkerStateFinished<'a, 'b> {
 }
 
 impl<'a, 'b> DiceWorkerStateFinished<'a, 'b> {
+    /// Records that the evaluated value is equal to the previous value of the key, which the core
+    /// state kept instead.
+    pub(crate) fn recomputed_same(&mut self) {
+        if let Some(activation_info) = &mut self.activation_info {
+            activation_info.outcome = ActivationOutcome::RecomputedSame;
+        }
+    }
+
     pub(crate) fn cached(mut self, value: DiceComputedValue) -> DiceWorkerStateFinishedAndCached {
         debug!(msg = "Update caches complete");
 
@@ -325,6 +336,11 @@ impl<'a, 'b> DiceWorkerStateFinished<'a, 'b> {
                 activation_info.key.as_any(),
                 &mut activation_info.deps.iter().map(|k| k.as_any()),
                 activation_info.activation_data,
+                ActivationTiming {
+                    start: activation_info.start,
+                    end: Instant::now(),
+                    outcome: activation_info.outcome,
+                },
             )
         }
         self.internals.finished(value);
@@ -340,6 +356,8 @@ pub(crate) struct ActivationInfo {
     key: DiceKeyErased,
     deps: Vec<DiceKeyErased>,
     activation_data: ActivationData,
+    start: Instant,
+    outcome: ActivationOutcome,
 }
 
 impl ActivationInfo {
@@ -349,16 +367,24 @@ impl ActivationInfo {
         key: DiceKey,
         deps: impl Iterator<Item = &'a DiceKey>,
         activation_data: ActivationData,
+        start: Instant,
     ) -> Option<ActivationInfo> {
         if let Some(activation_tracker) = activation_tracker {
             let key = key_index.get(key).dupe();
             let deps = deps.map(|dep| key_index.get(*dep).dupe()).collect();
 
+            let outcome = match activation_data {
+                ActivationData::Evaluated(_) => ActivationOutcome::RecomputedChanged,
+                ActivationData::Reused => ActivationOutcome::Reused,
+            };
+
             Some(ActivationInfo {
                 activation_tracker: activation_tracker.dupe(),
                 key,
                 deps,
                 activation_data,
+                start,
+                outcome,
             })
         } else {
             None
@@ -51,6 +51,50 @@ pub enum GraphIntrospectable {
     },
 }
 
+/// How much of the graph is kept alive by pinned keys.
+#[derive(Debug, Default, Clone, Copy, Dupe, PartialEq, Eq, Serialize)]
+pub struct PinSummary {
+    /// Number of distinct pinned keys.
+    pub pinned_keys: usize,
+    /// Number of keys in the graph retained by pins, including dependencies of pinned keys.
+    pub retained_keys: usize,
+    /// Approximate size of the retained keys.
+    pub retained_bytes: usize,
+}
+
+/// Sizes of the values computed since dice was created, per key type. Only populated when value
+/// size accounting is enabled, see `DiceDataBuilder::value_size_accounting`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct ValueSizeSummary {
+    pub by_key_type: BTreeMap<&'static str, ValueSizeTotals>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Dupe, PartialEq, Eq, Serialize)]
+pub struct ValueSizeTotals {
+    /// Number of values stored in the graph.
+    pub values: u64,
+    /// Number of those values that were measured.
+    pub measured_values: u64,
+    /// Total size of the measured values.
+    pub measured_bytes: u64,
+    /// Size of the largest measured value.
+    pub max_value_bytes: u64,
+    /// Number of measured values larger than the configured limit.
+    pub oversized_values: u64,
+}
+
+impl ValueSizeTotals {
+    /// Total size of all the values, extrapolated from the measured ones.
+    pub fn estimated_bytes(&self) -> u64 {
+        if self.measured_values == 0 {
+            0
+        } else {
+            (self.measured_bytes as u128 * self.values as u128 / self.measured_values as u128)
+                as u64
+        }
+    }
+}
+
 pub struct LegacyIntrospectable(pub(crate) Vec<Arc<dyn ErasedEngine + Send + Sync + 'static>>);
 
 impl GraphIntrospectable {
@@ -64,12 +108,31 @@ impl GraphIntrospectable {
             }
         }
     }
+
+    /// Summary of the keys pinned against trimming of the graph. Legacy dice does not support
+    /// pinning.
+    pub fn pin_summary(&self) -> PinSummary {
+        match self {
+            GraphIntrospectable::Legacy { .. } => PinSummary::default(),
+            GraphIntrospectable::Modern { introspection } => introspection.graph.pin_summary(),
+        }
+    }
+
+    /// Sizes of the computed values per key type. Legacy dice does not support value size
+    /// accounting.
+    pub fn value_size_summary(&self) -> ValueSizeSummary {
+        match self {
+            GraphIntrospectable::Legacy { .. } => ValueSizeSummary::default(),
+            GraphIntrospectable::Modern { introspection } => introspection.value_sizes.clone(),
+        }
+    }
 }
 
 pub struct ModernIntrospectable {
     pub(crate) graph: VersionedGraphIntrospectable,
     pub(crate) version_data: VersionIntrospectable,
     pub(crate) key_map: HashMap<DiceKey, AnyKey>,
+    pub(crate) value_sizes: ValueSizeSummary,
 }
 
 impl EngineForIntrospection for ModernIntrospectable {
@@ -10,6 +10,7 @@
 use std::any::Any;
 use std::future::Future;
 use std::sync::Arc;
+use std::time::Instant;
 
 use allocative::Allocative;
 use dupe::Dupe;
@@ -17,6 +18,8 @@ use futures::FutureExt;
 use parking_lot::Mutex;
 
 use crate::api::activation_tracker::ActivationData;
+use crate::api::activation_tracker::ActivationOutcome;
+use crate::api::activation_tracker::ActivationTiming;
 use crate::api::cycles::DetectCycles;
 use crate::api::data::DiceData;
 use crate::api::error::DiceErrorImpl;
@@ -57,6 +60,9 @@ pub(crate) struct ComputationData {
     /// user_data's ActivationTracker when the key evaluation finishes.
     #[allocative(skip)]
     pub(crate) evaluation_data: Mutex<Option<Box<dyn Any + Send + Sync + 'static>>>,
+    /// When the computation of the key started, passed to the ActivationTracker.
+    #[allocative(skip)]
+    computing_started: Option<Instant>,
 }
 
 impl ComputationData {
@@ -69,6 +75,7 @@ impl ComputationData {
             },
             user_cycle_detector_guard: None,
             evaluation_data: Mutex::new(None),
+            computing_started: None,
         }
     }
 
@@ -90,11 +97,13 @@ impl ComputationData {
                 .transpose()?,
             user_cycle_detector_guard: None,
             evaluation_data: Mutex::new(None),
+            computing_started: None,
         })
     }
 
     pub(crate) fn start_computing_key<K: StorageProperties>(&mut self, k: &K::Key) {
         assert!(self.user_cycle_detector_guard.is_none());
+        self.computing_started = Some(Instant::now());
         self.user_cycle_detector_guard = self
             .user_data
             .cycle_detector
@@ -115,13 +124,22 @@ impl ComputationData {
         if let Some(v) = &self.user_data.activation_tracker {
             let mut iter = deps.deps.iter().map(|d| d.to_key_any());
 
-            let activation_data = if reused {
-                ActivationData::Reused
+            let (activation_data, outcome) = if reused {
+                (ActivationData::Reused, ActivationOutcome::Reused)
             } else {
-                ActivationData::Evaluated(self.evaluation_data.lock().take())
+                (
+                    ActivationData::Evaluated(self.evaluation_data.lock().take()),
+                    ActivationOutcome::Recomputed,
+                )
+            };
+            let end = Instant::now();
+            let timing = ActivationTiming {
+                start: self.computing_started.unwrap_or(end),
+                end,
+                outcome,
             };
 
-            v.key_activated(K::to_key_any(k), &mut iter, activation_data);
+            v.key_activated(K::to_key_any(k), &mut iter, activation_data, timing);
         }
     }
 }
@@ -365,6 +383,7 @@ impl DiceComputationsImplLegacy {
             cycle_detector: this.extra.cycle_detector.take(),
             user_cycle_detector_guard: None,
             evaluation_data: Mutex::new(None),
+            computing_started: None,
         })
     }
 
@@ -213,6 +213,8 @@ use metrics::Metrics;
 use serde::Serializer;
 
 pub use crate::api::activation_tracker::ActivationData;
+pub use crate::api::activation_tracker::ActivationOutcome;
+pub use crate::api::activation_tracker::ActivationTiming;
 pub use crate::api::activation_tracker::ActivationTracker;
 pub use crate::api::computations::DiceComputations;
 pub use crate::api::computations::LinearRecomputeDiceComputations;
@@ -225,8 +227,10 @@ pub use crate::api::error::DiceResult;
 pub use crate::api::events::DiceEvent;
 pub use crate::api::events::DiceEventListener;
 pub use crate::api::injected::InjectedKey;
+pub use crate::api::invalidation::InvalidationPath;
 pub use crate::api::key::Key;
 pub use crate::api::opaque::OpaqueValue;
+pub use crate::api::pin::PinLifetime;
 pub use crate::api::projection::DiceProjectionComputations;
 pub use crate::api::projection::ProjectionKey;
 pub use crate::api::transaction::DiceEquality;
@@ -235,6 +239,8 @@ pub use crate::api::transaction::DiceTransactionUpdater;
 pub use crate::api::user_data::UserComputationData;
 pub use crate::api::user_data::UserCycleDetector;
 pub use crate::api::user_data::UserCycleDetectorGuard;
+pub use crate::api::value_size::OversizedValue;
+pub use crate::api::value_size::ValueSizeConfig;
 pub use crate::api::which::WhichDice;
 use crate::impls::dice::DiceModern;
 use crate::impls::dice::DiceModernDataBuilder;
@@ -350,6 +356,39 @@ impl DiceDataBuilderImpl {
         }
     }
 
+    pub fn deterministic(&mut self, seed: u64) -> DiceResult<()> {
+        match self {
+            DiceDataBuilderImpl::Legacy(_) => Err(DiceError::unsupported_by_legacy_dice(
+                "Deterministic evaluation",
+            )),
+            DiceDataBuilderImpl::Modern(d) => d.deterministic(seed),
+        }
+    }
+
+    pub fn value_size_accounting(&mut self, config: ValueSizeConfig) -> DiceResult<()> {
+        match self {
+            DiceDataBuilderImpl::Legacy(_) => Err(DiceError::unsupported_by_legacy_dice(
+                "Value size accounting",
+            )),
+            DiceDataBuilderImpl::Modern(d) => {
+                d.value_size_accounting(config);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn max_cache_weight(&mut self, max_weight: usize) -> DiceResult<()> {
+        match self {
+            DiceDataBuilderImpl::Legacy(_) => {
+                Err(DiceError::unsupported_by_legacy_dice("Cache eviction"))
+            }
+            DiceDataBuilderImpl::Modern(d) => {
+                d.max_cache_weight(max_weight);
+                Ok(())
+            }
+        }
+    }
+
     pub fn build(self, detect_cycles: DetectCycles) -> Arc<Dice> {
         Dice::new(match self {
             DiceDataBuilderImpl::Legacy(d) => DiceImplementation::Legacy(d.build(detect_cycles)),
@@ -17,6 +17,11 @@ use std::process::Output;
 use std::process::Stdio;
 
 use anyhow::Context;
+use buck2_client_api::proto::CqueryRequest;
+use buck2_client_api::proto::TargetCfg;
+use buck2_client_api::Buck2Client;
+use buck2_client_api::ClientOptions;
+use buck2_client_api::IgnoreEvents;
 use rustc_hash::FxHashMap;
 use rustc_hash::FxHashSet;
 use serde::Deserialize;
@@ -546,7 +551,6 @@ impl Buck {
         targets: &[Target],
     ) -> Result<FxHashMap<Target, AliasedTargetInfo>, anyhow::Error> {
         // FIXME: Do this in bxl as well instead of manually writing a separate query
-        let mut command = self.command(["cquery"]);
 
         // Fetch all aliases used by transitive deps. This is so we
         // can translate an apparent dependency of e.g.
@@ -554,15 +558,25 @@ impl Buck {
         // name of fbsource//third-party/rust:once_cell-1.15. This
         // query also fetches non-Rust aliases, but they shouldn't
         // hurt anything.
-        if let Some(mode) = &self.mode {
-            command.arg(mode);
-        }
-        command.args(["--output-all-attributes", "kind('^alias$', deps(%Ss))"]);
-        command.args(targets);
-
         info!("resolving aliased libraries");
-        let raw: FxHashMap<Target, AliasedTargetInfo> =
-            deserialize_output(command.output(), &command)?;
+        let raw: FxHashMap<Target, AliasedTargetInfo> = match &self.mode {
+            // Modes are argfiles, which only the `buck2` binary expands.
+            Some(mode) => {
+                let mut command = self.command(["cquery"]);
+                command.arg(mode);
+                command.args(["--output-all-attributes", "kind('^alias$', deps(%Ss))"]);
+                command.args(targets);
+                deserialize_output(command.output(), &command)?
+            }
+            None => {
+                let targets = targets
+                    .iter()
+                    .map(|target| format!("'{}'", target))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                self.cquery_all_attributes(&format!("kind('^alias$', deps(set({})))", targets))?
+            }
+        };
 
         if enabled!(Level::TRACE) {
             for (target, info) in &raw {
@@ -572,6 +586,41 @@ impl Buck {
         Ok(raw)
     }
 
+    /// Run a cquery outputting all attributes as JSON through the daemon, like
+    /// `buck2 cquery --output-all-attributes` run with [`Buck::command`].
+    fn cquery_all_attributes<T>(&self, query: &str) -> Result<T, anyhow::Error>
+    where
+        T: for<'a> Deserialize<'a>,
+    {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let output = runtime.block_on(async {
+            let mut options = ClientOptions::new(std::env::current_dir()?);
+            options.isolation_dir = ".rust-analyzer".to_owned();
+            options.config_overrides = vec![
+                "client.id=rust-project".to_owned(),
+                "rust.failure_filter=true".to_owned(),
+            ];
+            options.oncall = Some("rust_devx".to_owned());
+
+            let mut client = Buck2Client::connect(options).await?;
+            let request = CqueryRequest {
+                query: query.to_owned(),
+                output_attributes: vec![String::new()],
+                target_cfg: Some(TargetCfg::default()),
+                correct_owner: true,
+                ..Default::default()
+            };
+            client.run(request, &mut IgnoreEvents).await
+        })?;
+
+        tracing::debug!(%query, "parsing cquery output");
+        serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("cquery `{}`", query))
+            .context("failed to deserialize cquery output")
+    }
+
     #[instrument(skip_all)]
     pub(crate) fn query_owner(
         &self,
@@ -8,10 +8,14 @@
  */
 
 mod check;
+mod clean_up;
 mod develop;
 mod new;
 
 pub(crate) use check::Check;
+pub(crate) use clean_up::record_artifacts;
+pub(crate) use clean_up::record_generated_file;
+pub(crate) use clean_up::CleanUp;
 pub(crate) use develop::Develop;
 pub(crate) use new::New;
 pub(crate) use new::ProjectKind;
@@ -10,6 +10,9 @@
 use std::path::PathBuf;
 use std::str::FromStr;
 
+use serde::Deserialize;
+use serde::Serialize;
+
 use crate::buck;
 use crate::buck::select_mode;
 use crate::diagnostics;
@@ -18,27 +21,105 @@ pub(crate) struct Check {
     pub(crate) buck: buck::Buck,
     pub(crate) use_clippy: bool,
     pub(crate) saved_file: PathBuf,
+    pub(crate) json: bool,
+}
+
+/// The result of `check --json`, printed as a single JSON object on stdout.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub(crate) struct CheckOutput {
+    pub(crate) exit_reason: ExitReason,
+    /// Number of distinct diagnostics, of any level.
+    pub(crate) diagnostics: usize,
+    /// The files the diagnostics were read from, as produced by `check.bxl`.
+    pub(crate) diagnostics_files: Vec<PathBuf>,
+    /// Why the build could not be run, if it couldn't.
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ExitReason {
+    /// The build ran and reported no errors.
+    Success,
+    /// The build ran and reported at least one error.
+    Errors,
+    /// The build could not be run.
+    BuildFailed,
+}
+
+impl CheckOutput {
+    fn new(result: &Result<(Vec<serde_json::Value>, Vec<PathBuf>), anyhow::Error>) -> Self {
+        match result {
+            Ok((diagnostics, diagnostics_files)) => {
+                let has_errors = diagnostics
+                    .iter()
+                    .any(|d| d.get("level").and_then(|l| l.as_str()) == Some("error"));
+                CheckOutput {
+                    exit_reason: if has_errors {
+                        ExitReason::Errors
+                    } else {
+                        ExitReason::Success
+                    },
+                    diagnostics: diagnostics.len(),
+                    diagnostics_files: diagnostics_files.clone(),
+                    error: None,
+                }
+            }
+            Err(e) => CheckOutput {
+                exit_reason: ExitReason::BuildFailed,
+                diagnostics: 0,
+                diagnostics_files: vec![],
+                error: Some(format!("{:#}", e)),
+            },
+        }
+    }
 }
 
 impl Check {
-    pub(crate) fn new(mode: Option<String>, use_clippy: bool, saved_file: PathBuf) -> Self {
+    pub(crate) fn new(
+        mode: Option<String>,
+        use_clippy: bool,
+        saved_file: PathBuf,
+        json: bool,
+    ) -> Self {
         let mode = select_mode(mode.as_deref());
         let buck = buck::Buck::new(mode);
         Self {
             buck,
             use_clippy,
             saved_file,
+            json,
         }
     }
 
     pub(crate) fn run(&self) -> Result<(), anyhow::Error> {
+        let result = self.diagnostics();
+
+        if self.json {
+            let out = serde_json::to_string(&CheckOutput::new(&result))?;
+            println!("{}", out);
+            return result.map(|_| ());
+        }
+
+        let (diagnostics, _) = result?;
+        for diagnostic in diagnostics {
+            let out = serde_json::to_string(&diagnostic)?;
+            println!("{}", out);
+        }
+
+        Ok(())
+    }
+
+    /// Builds the saved file and returns its deduplicated diagnostics, together with the files
+    /// they were read from.
+    fn diagnostics(&self) -> Result<(Vec<serde_json::Value>, Vec<PathBuf>), anyhow::Error> {
         let buck = &self.buck;
 
         let cell_root = buck.resolve_root_of_file(&self.saved_file)?;
         let diagnostic_files = buck.check_saved_file(self.use_clippy, &self.saved_file)?;
 
         let mut diagnostics = vec![];
-        for path in diagnostic_files {
+        for path in &diagnostic_files {
             let contents = std::fs::read_to_string(path)?;
             for l in contents.lines() {
                 // rustc (and with greater relevance, the underlying build.bxl script) emits diagnostics as newline-delimited JSON.
@@ -76,11 +157,41 @@ impl Check {
             }
         }
 
-        for diagnostic in diagnostics {
-            let out = serde_json::to_string(&diagnostic)?;
-            println!("{}", out);
-        }
-
-        Ok(())
+        Ok((diagnostics, diagnostic_files))
     }
 }
+
+#[test]
+fn test_check_output_schema() {
+    let diagnostics = vec![
+        serde_json::json!({"message": "unused variable", "level": "warning"}),
+        serde_json::json!({"message": "mismatched types", "level": "error"}),
+    ];
+    let output = CheckOutput::new(&Ok((
+        diagnostics,
+        vec![PathBuf::from("/tmp/diagnostics.json")],
+    )));
+    assert_eq!(
+        serde_json::json!({
+            "exit_reason": "errors",
+            "diagnostics": 2,
+            "diagnostics_files": ["/tmp/diagnostics.json"],
+            "error": null,
+        }),
+        serde_json::to_value(&output).unwrap()
+    );
+
+    let output = CheckOutput::new(&Ok((vec![], vec![])));
+    assert_eq!(ExitReason::Success, output.exit_reason);
+
+    let output = CheckOutput::new(&Err(anyhow::anyhow!("buck2 failed")));
+    assert_eq!(
+        serde_json::json!({
+            "exit_reason": "build_failed",
+            "diagnostics": 0,
+            "diagnostics_files": [],
+            "error": "buck2 failed",
+        }),
+        serde_json::to_value(&output).unwrap()
+    );
+}
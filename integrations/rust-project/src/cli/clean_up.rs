@@ -0,0 +1,287 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::warn;
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+struct Artifacts {
+    files: Vec<PathBuf>,
+    /// Only removed if they are empty once `files` are removed, so that anything the user added
+    /// to a generated project is kept.
+    dirs: Vec<PathBuf>,
+}
+
+/// Where `new` and `develop` record the files they generate, so that `clean-up` can find them
+/// again without leaving anything in the source tree. There is one manifest per directory that
+/// files were generated in.
+struct ArtifactStore {
+    root: PathBuf,
+}
+
+impl ArtifactStore {
+    /// `$XDG_STATE_HOME/rust-project/artifacts`, or `~/.local/state/rust-project/artifacts`.
+    #[cfg(not(windows))]
+    fn from_env() -> Result<Self, anyhow::Error> {
+        let state_home = match std::env::var_os("XDG_STATE_HOME") {
+            Some(dir) if Path::new(&dir).is_absolute() => PathBuf::from(dir),
+            _ => {
+                let home = std::env::var_os("HOME").context("`HOME` is not set")?;
+                PathBuf::from(home).join(".local").join("state")
+            }
+        };
+        Ok(ArtifactStore {
+            root: state_home.join("rust-project").join("artifacts"),
+        })
+    }
+
+    /// `%LOCALAPPDATA%\rust-project\artifacts`.
+    #[cfg(windows)]
+    fn from_env() -> Result<Self, anyhow::Error> {
+        let local_app_data =
+            std::env::var_os("LOCALAPPDATA").context("`LOCALAPPDATA` is not set")?;
+        Ok(ArtifactStore {
+            root: PathBuf::from(local_app_data)
+                .join("rust-project")
+                .join("artifacts"),
+        })
+    }
+
+    /// The manifest for `dir`, which should be canonical.
+    fn manifest(&self, dir: &Path) -> PathBuf {
+        // FNV-1a, which unlike `DefaultHasher` is the same across releases.
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in dir.to_string_lossy().bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        self.root.join(format!("{:016x}.json", hash))
+    }
+
+    /// Records files and directories that were generated in `dir`. Paths should be absolute.
+    fn record(&self, dir: &Path, files: &[PathBuf], dirs: &[PathBuf]) -> Result<(), anyhow::Error> {
+        let manifest = self.manifest(dir);
+        let mut artifacts = read_artifacts(&manifest)?.unwrap_or_default();
+        for file in files {
+            if !artifacts.files.contains(file) {
+                artifacts.files.push(file.clone());
+            }
+        }
+        for generated in dirs {
+            if !artifacts.dirs.contains(generated) {
+                artifacts.dirs.push(generated.clone());
+            }
+        }
+
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("Unable to create `{}`", self.root.display()))?;
+        let contents = serde_json::to_string_pretty(&artifacts)?;
+        fs::write(&manifest, contents)
+            .with_context(|| format!("Unable to write `{}`", manifest.display()))
+    }
+
+    /// Records a single generated file, in the directory that contains it.
+    fn record_file(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Unable to canonicalize `{}`", path.display()))?;
+        let Some(dir) = path.parent() else {
+            return Ok(());
+        };
+        self.record(dir, &[path.clone()], &[])
+    }
+
+    /// Removes the artifacts recorded in `dir` and returns the paths that were removed.
+    /// Directories that still contain other files stay recorded, so that a later run can remove
+    /// them once they are empty.
+    fn clean_up(&self, dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let manifest = self.manifest(dir);
+        let Some(artifacts) = read_artifacts(&manifest)? else {
+            return Ok(vec![]);
+        };
+
+        let mut removed = vec![];
+        let mut kept_dirs = vec![];
+        for file in artifacts.files {
+            match fs::remove_file(&file) {
+                Ok(()) => removed.push(file),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Unable to remove `{}`", file.display()));
+                }
+            }
+        }
+
+        // Deepest first, so that generated subdirectories are removed before their parents.
+        let mut dirs = artifacts.dirs;
+        dirs.sort_by_key(|dir| Reverse(dir.components().count()));
+        for dir in dirs {
+            let is_empty = match fs::read_dir(&dir) {
+                Ok(mut entries) => entries.next().is_none(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Unable to read `{}`", dir.display()));
+                }
+            };
+            if !is_empty {
+                warn!(?dir, "not removing directory that contains other files");
+                kept_dirs.push(dir);
+                continue;
+            }
+            fs::remove_dir(&dir)
+                .with_context(|| format!("Unable to remove `{}`", dir.display()))?;
+            removed.push(dir);
+        }
+
+        if kept_dirs.is_empty() {
+            fs::remove_file(&manifest)
+                .with_context(|| format!("Unable to remove `{}`", manifest.display()))?;
+        } else {
+            let artifacts = Artifacts {
+                files: vec![],
+                dirs: kept_dirs,
+            };
+            fs::write(&manifest, serde_json::to_string_pretty(&artifacts)?)
+                .with_context(|| format!("Unable to write `{}`", manifest.display()))?;
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Records files and directories that were generated in `dir`, which should be canonical. Paths
+/// should be absolute.
+pub(crate) fn record_artifacts(
+    dir: &Path,
+    files: &[PathBuf],
+    dirs: &[PathBuf],
+) -> Result<(), anyhow::Error> {
+    ArtifactStore::from_env()?.record(dir, files, dirs)
+}
+
+/// Records a single generated file, in the directory that contains it.
+pub(crate) fn record_generated_file(path: &Path) -> Result<(), anyhow::Error> {
+    ArtifactStore::from_env()?.record_file(path)
+}
+
+fn read_artifacts(manifest: &Path) -> Result<Option<Artifacts>, anyhow::Error> {
+    let contents = match fs::read_to_string(manifest) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Unable to read `{}`", manifest.display()));
+        }
+    };
+    let artifacts = serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid artifacts file `{}`", manifest.display()))?;
+    Ok(Some(artifacts))
+}
+
+pub(crate) struct CleanUp {
+    pub(crate) path: Option<PathBuf>,
+}
+
+impl CleanUp {
+    pub(crate) fn run(self) -> Result<(), anyhow::Error> {
+        let dir = match self.path {
+            Some(path) => path,
+            None => std::env::current_dir()?,
+        };
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("Unable to canonicalize `{}`", dir.display()))?;
+
+        for path in ArtifactStore::from_env()?.clean_up(&dir)? {
+            println!("removed {}", path.display());
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_clean_up_is_idempotent() {
+    let state = tempfile::tempdir().unwrap();
+    let store = ArtifactStore {
+        root: state.path().join("artifacts"),
+    };
+    let workspace = tempfile::tempdir().unwrap();
+    let dir = workspace.path().canonicalize().unwrap();
+
+    let project = dir.join("project");
+    let src = project.join("src");
+    fs::create_dir_all(&src).unwrap();
+    let targets = project.join("TARGETS");
+    let main = src.join("main.rs");
+    let json = dir.join("rust-project.json");
+    for file in [&targets, &main, &json] {
+        fs::write(file, "").unwrap();
+    }
+
+    store
+        .record(&dir, &[targets, main], &[project.clone(), src.clone()])
+        .unwrap();
+    store.record_file(&json).unwrap();
+    // Recording the same file twice, as repeated `develop` runs do, only lists it once.
+    store.record_file(&json).unwrap();
+    // Nothing is written next to the generated files.
+    assert_eq!(vec![project.clone(), json.clone()], sorted_entries(&dir));
+
+    // A file the user added to the generated project.
+    let user_file = src.join("lib.rs");
+    fs::write(&user_file, "").unwrap();
+
+    let removed = store.clean_up(&dir).unwrap();
+    assert_eq!(3, removed.len(), "{:?}", removed);
+    assert!(!json.exists());
+    assert!(user_file.exists());
+
+    // Nothing else can be removed yet.
+    assert_eq!(Vec::<PathBuf>::new(), store.clean_up(&dir).unwrap());
+
+    // Once the user's file is gone, the directories are removed too.
+    fs::remove_file(&user_file).unwrap();
+    assert_eq!(vec![src, project.clone()], store.clean_up(&dir).unwrap());
+    assert!(!project.exists());
+    assert!(!store.manifest(&dir).exists());
+
+    // Nothing left to do.
+    assert_eq!(Vec::<PathBuf>::new(), store.clean_up(&dir).unwrap());
+}
+
+#[test]
+fn test_manifest_name_is_stable() {
+    // Manifests written by earlier releases must still be found.
+    let store = ArtifactStore {
+        root: PathBuf::from("artifacts"),
+    };
+    assert_eq!(
+        Path::new("artifacts").join("f8a71a04e8340307.json"),
+        store.manifest(Path::new("/home/user/project"))
+    );
+}
+
+#[cfg(test)]
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    entries
+}
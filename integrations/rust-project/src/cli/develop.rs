@@ -7,11 +7,16 @@
  * of this source tree.
  */
 
-use std::io::BufWriter;
+use std::ffi::OsString;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Instant;
 
+use anyhow::Context;
+use notify::RecommendedWatcher;
+use notify::RecursiveMode;
+use notify::Watcher;
 use rustc_hash::FxHashMap;
 use rustc_hash::FxHashSet;
 use tracing::info;
@@ -27,6 +32,12 @@ use crate::sysroot::resolve_buckconfig_sysroot;
 use crate::sysroot::resolve_rustup_sysroot;
 use crate::sysroot::SysrootConfig;
 use crate::target::Target;
+use crate::watch::is_relevant;
+use crate::watch::watched_dirs;
+use crate::watch::ChangeKind;
+use crate::watch::Debouncer;
+use crate::watch::DEBOUNCE_DELAY;
+use crate::watch::MAX_DEBOUNCE_DELAY;
 use crate::Command;
 
 #[derive(Debug)]
@@ -79,6 +90,7 @@ impl Develop {
             relative_paths,
             mode,
             check_cycles,
+            watch: _,
         } = command
         {
             let out = if stdout {
@@ -210,10 +222,87 @@ impl Develop {
     }
 
     pub(crate) fn run_as_cli(self, input: Input, cfg: OutputCfg) -> Result<(), anyhow::Error> {
+        self.generate(&input, &cfg)?;
+        Ok(())
+    }
+
+    /// Like [`Develop::run_as_cli`], but then keeps regenerating the project whenever the build
+    /// files of its workspace crates, or the set of their sources, change. Returns on ctrl-c.
+    ///
+    /// Only the directories of the workspace crates are watched, so changes to `.bzl` files
+    /// elsewhere (e.g. in the prelude) are not noticed.
+    pub(crate) fn run_watch(self, input: Input, cfg: OutputCfg) -> Result<(), anyhow::Error> {
+        let project_root = self.buck.resolve_project_root()?;
+
+        let (change_tx, change_rx) = crossbeam::channel::unbounded();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) => {
+                    let kind = ChangeKind::new(&event.kind);
+                    if event.paths.iter().any(|path| is_relevant(path, kind)) {
+                        // Only fails once we stopped watching.
+                        let _ = change_tx.send(());
+                    }
+                }
+                Err(e) => warn!("error watching files: {}", e),
+            })?;
+        let interrupt_rx = ctrl_c()?;
+
+        let project = self.generate(&input, &cfg)?;
+        let mut watched = FxHashSet::default();
+        update_watched_dirs(
+            &mut watcher,
+            &mut watched,
+            watched_dirs(&project, &project_root),
+        );
+        info!("watching for changes, press ctrl-c to stop");
+
+        let mut debouncer = Debouncer::new(DEBOUNCE_DELAY, MAX_DEBOUNCE_DELAY);
+        loop {
+            let timeout = match debouncer.deadline() {
+                Some(deadline) => {
+                    crossbeam::channel::after(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => crossbeam::channel::never(),
+            };
+            crossbeam::select! {
+                recv(change_rx) -> _ => debouncer.on_change(Instant::now()),
+                recv(interrupt_rx) -> _ => {
+                    info!("stopped watching");
+                    return Ok(());
+                }
+                recv(timeout) -> _ => {}
+            }
+
+            if !debouncer.should_regenerate(Instant::now()) {
+                continue;
+            }
+            let start = Instant::now();
+            match self.generate(&input, &cfg) {
+                Ok(project) => {
+                    info!(duration = ?start.elapsed(), "regenerated rust-project.json");
+                    update_watched_dirs(
+                        &mut watcher,
+                        &mut watched,
+                        watched_dirs(&project, &project_root),
+                    );
+                }
+                // Likely a build file that is being edited, so wait for the next change.
+                Err(e) => warn!(
+                    duration = ?start.elapsed(),
+                    "failed to regenerate rust-project.json: {:#}", e
+                ),
+            }
+        }
+    }
+
+    /// Generates the project for `input` and writes it out.
+    fn generate(&self, input: &Input, cfg: &OutputCfg) -> Result<JsonProject, anyhow::Error> {
         let targets = match input {
-            Input::Targets(targets) => targets,
+            Input::Targets(targets) => targets.clone(),
             Input::Files(files) => {
-                let targets: FxHashMap<String, Vec<Target>> = self.resolve_file_owners(files)?;
+                let targets: FxHashMap<String, Vec<Target>> =
+                    self.resolve_file_owners(files.clone())?;
                 targets
                     .values()
                     .into_iter()
@@ -225,28 +314,84 @@ impl Develop {
 
         let rust_project = self.run(targets)?;
 
-        let mut writer: BufWriter<Box<dyn Write>> = match cfg.out {
+        let mut contents = if cfg.pretty {
+            serde_json::to_vec_pretty(&rust_project)?
+        } else {
+            serde_json::to_vec(&rust_project)?
+        };
+        contents.push(b'\n');
+
+        match cfg.out {
             Output::Path(ref p) => {
-                let out = std::fs::File::create(p)?;
-                BufWriter::new(Box::new(out))
+                write_atomically(p, &contents)?;
+                info!(file = ?p, "wrote rust-project.json");
+                if let Err(e) = crate::cli::record_generated_file(p) {
+                    warn!(file = ?p, "unable to record the generated file for `clean-up`: {:#}", e);
+                }
             }
-            Output::Stdout => BufWriter::new(Box::new(std::io::stdout())),
-        };
+            Output::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(&contents)?;
+                stdout.flush()?;
+                info!("wrote rust-project.json to stdout");
+            }
+        }
 
-        if cfg.pretty {
-            serde_json::to_writer_pretty(&mut writer, &rust_project)?;
-        } else {
-            serde_json::to_writer(&mut writer, &rust_project)?;
+        Ok(rust_project)
+    }
+}
+
+fn update_watched_dirs(
+    watcher: &mut RecommendedWatcher,
+    watched: &mut FxHashSet<PathBuf>,
+    dirs: Vec<PathBuf>,
+) {
+    let dirs: FxHashSet<PathBuf> = dirs.into_iter().collect();
+    for dir in watched.difference(&dirs) {
+        if let Err(e) = watcher.unwatch(dir) {
+            warn!(?dir, "failed to stop watching directory: {}", e);
         }
-        writeln!(writer)?;
+    }
+    for dir in dirs.difference(watched) {
+        if let Err(e) = watcher.watch(dir, RecursiveMode::Recursive) {
+            warn!(?dir, "failed to watch directory: {}", e);
+        }
+    }
+    *watched = dirs;
+}
 
-        match cfg.out {
-            Output::Path(p) => info!(file = ?p, "wrote rust-project.json"),
-            Output::Stdout => info!("wrote rust-project.json to stdout"),
+/// Receives a message on ctrl-c. Once this is called, ctrl-c no longer terminates the process.
+fn ctrl_c() -> Result<crossbeam::channel::Receiver<()>, anyhow::Error> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let (tx, rx) = crossbeam::channel::bounded(1);
+    std::thread::spawn(move || {
+        if runtime.block_on(tokio::signal::ctrl_c()).is_ok() {
+            let _ = tx.send(());
         }
+    });
+    Ok(rx)
+}
 
-        Ok(())
+/// Writes `contents` to a temporary file next to `path` and renames it over `path`, so that
+/// rust-analyzer never reads a partially written file.
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), anyhow::Error> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("`{}` is not a file path", path.display()))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    std::fs::write(&tmp, contents)
+        .with_context(|| format!("Unable to write `{}`", tmp.display()))?;
+    if let Err(e) = std::fs::rename(&tmp, path) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e).with_context(|| format!("Unable to write `{}`", path.display()));
     }
+    Ok(())
 }
 
 fn expand_tilde(path: &Path) -> Result<PathBuf, anyhow::Error> {
@@ -15,6 +15,7 @@ use std::path::PathBuf;
 use anyhow::Context;
 use clap::ValueEnum;
 use tracing::info;
+use tracing::warn;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub(crate) enum ProjectKind {
@@ -54,8 +55,9 @@ impl New {
             .canonicalize()
             .context("Unable to canonicalize current directory")?;
 
+        let parent = path;
         let project_dir = Path::new(&name);
-        let path = path.join(project_dir);
+        let path = parent.join(project_dir);
 
         fs::create_dir(&path).context("Unable to create project directory")?;
 
@@ -64,7 +66,7 @@ impl New {
         // create the `TARGETS` file
         let targets_path = path.join("TARGETS");
         let mut targets_file =
-            fs::File::create(targets_path).context("Unable to create `TARGETS` file")?;
+            fs::File::create(&targets_path).context("Unable to create `TARGETS` file")?;
         targets_file
             .write_all(target.render().as_bytes())
             .context("Unable to write generated template to `TARGETS` file")?;
@@ -73,23 +75,36 @@ impl New {
         let src_dur = path.join(Path::new("src"));
         fs::create_dir(&src_dur).context("Unable to create `src/` directory")?;
 
-        match kind {
+        let entry_path = match kind {
             EntryFile::Main(main) => {
                 let path = src_dur.join(Path::new("main.rs"));
-                let mut main_file = fs::File::create(path).context("Unable to create main file")?;
+                let mut main_file =
+                    fs::File::create(&path).context("Unable to create main file")?;
 
                 main_file
                     .write_all(main.render().as_bytes())
                     .context("Unable to write contents of main.rs")?;
+                path
             }
             EntryFile::Lib(lib) => {
                 let path = src_dur.join(Path::new("lib.rs"));
-                let mut lib_file = fs::File::create(path).context("Unable to create main file")?;
+                let mut lib_file = fs::File::create(&path).context("Unable to create main file")?;
 
                 lib_file
                     .write_all(lib.render().as_bytes())
                     .context("Unable to write contents of lib.rs")?;
+                path
             }
+        };
+
+        // The project was created, so only `clean-up` is affected if this fails.
+        if let Err(e) =
+            crate::cli::record_artifacts(&parent, &[targets_path, entry_path], &[path, src_dur])
+        {
+            warn!(
+                "unable to record the generated files for `clean-up`: {:#}",
+                e
+            );
         }
 
         Ok(())
@@ -15,6 +15,7 @@ mod progress;
 mod server;
 mod sysroot;
 mod target;
+mod watch;
 
 use std::io;
 use std::io::IsTerminal as _;
@@ -102,6 +103,11 @@ enum Command {
         /// Optional argument specifying build mode.
         #[clap(short = 'm', long)]
         mode: Option<String>,
+
+        /// Keep running and regenerate `rust-project.json` whenever the build files of the
+        /// workspace crates change, or sources are added or removed.
+        #[clap(long, conflicts_with = "stdout")]
+        watch: bool,
     },
     /// Build the saved file's owning target. This is meant to be used by IDEs to provide diagnostics on save.
     Check {
@@ -110,9 +116,17 @@ enum Command {
         mode: Option<String>,
         #[clap(short = 'c', long, default_value = "true", action = ArgAction::Set)]
         use_clippy: bool,
+        /// Print a single JSON object summarizing the result instead of the diagnostics.
+        #[clap(long)]
+        json: bool,
         /// The file saved by the user. `rust-project` will infer the owning target(s) of the saved file and build them.
         saved_file: PathBuf,
     },
+    /// Remove the files that `new` and `develop` generated in a directory.
+    CleanUp {
+        /// Directory to clean up. Defaults to the current working directory.
+        path: Option<PathBuf>,
+    },
     /// Start an LSP server whose functionality is similar to [Command::Develop].
     #[clap(hide = true)]
     LspServer,
@@ -139,11 +153,17 @@ fn main() -> Result<(), anyhow::Error> {
         Command::Check {
             mode,
             use_clippy,
+            json,
             saved_file,
-        } => cli::Check::new(mode, use_clippy, saved_file).run(),
-        c @ Command::Develop { .. } => {
+        } => cli::Check::new(mode, use_clippy, saved_file, json).run(),
+        Command::CleanUp { path } => cli::CleanUp { path }.run(),
+        c @ Command::Develop { watch, .. } => {
             let (develop, input, out) = cli::Develop::from_command(c);
-            develop.run_as_cli(input, out)
+            if watch {
+                develop.run_watch(input, out)
+            } else {
+                develop.run_as_cli(input, out)
+            }
         }
         Command::LspServer => {
             let state = server::State::new(reload_handle)?;
@@ -0,0 +1,313 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Deciding when `develop --watch` should regenerate `rust-project.json`.
+//!
+//! The decisions are kept separate from the file watcher so that they can be tested without
+//! touching the filesystem.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use notify::event::ModifyKind;
+use notify::EventKind;
+
+use crate::json_project::JsonProject;
+
+/// How long the watched files must be left alone before regenerating.
+pub(crate) const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+
+/// Regenerate after this long even if files keep changing, e.g. during a long rebase.
+pub(crate) const MAX_DEBOUNCE_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ChangeKind {
+    /// The contents of an existing file changed.
+    Contents,
+    /// A file was created, removed or renamed.
+    Existence,
+    /// Nothing that can affect the project, e.g. a file was read.
+    None,
+}
+
+impl ChangeKind {
+    pub(crate) fn new(kind: &EventKind) -> Self {
+        match kind {
+            EventKind::Access(_) => ChangeKind::None,
+            EventKind::Modify(ModifyKind::Metadata(_)) => ChangeKind::None,
+            EventKind::Modify(ModifyKind::Data(_)) => ChangeKind::Contents,
+            EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(_) => {
+                ChangeKind::Existence
+            }
+            // Unknown, so assume the worst.
+            EventKind::Any | EventKind::Other => ChangeKind::Existence,
+        }
+    }
+}
+
+/// Whether a change to `path` can change the generated project.
+///
+/// Build files and Starlark files can change the targets and their dependencies. Sources only
+/// matter when they are added or removed, since the set of sources is part of the project, but
+/// their contents are not.
+pub(crate) fn is_relevant(path: &Path, kind: ChangeKind) -> bool {
+    if kind == ChangeKind::None {
+        return false;
+    }
+    if path.components().any(|c| c.as_os_str() == "buck-out") {
+        return false;
+    }
+
+    if matches!(
+        path.file_name().and_then(|n| n.to_str()),
+        Some("BUCK" | "BUCK.v2" | "TARGETS" | "TARGETS.v2")
+    ) {
+        return true;
+    }
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("bzl") => true,
+        Some("rs") => kind == ChangeKind::Existence,
+        _ => false,
+    }
+}
+
+/// The directories to watch for `project`: those containing the build files of the workspace
+/// crates. Relative paths are resolved against `project_root`.
+pub(crate) fn watched_dirs(project: &JsonProject, project_root: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = project
+        .crates
+        .iter()
+        .filter(|krate| krate.is_workspace_member)
+        .filter_map(|krate| krate.buck_extensions.build_file.parent())
+        .map(|dir| project_root.join(dir))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+
+    // Directories are watched recursively, so nested ones are redundant. Sorting puts parents
+    // right before their children.
+    let mut out: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        if !out.last().is_some_and(|parent| dir.starts_with(parent)) {
+            out.push(dir);
+        }
+    }
+    out
+}
+
+/// Coalesces bursts of changes (an editor saving several files, a VCS checkout) into a single
+/// regeneration.
+#[derive(Debug)]
+pub(crate) struct Debouncer {
+    delay: Duration,
+    max_delay: Duration,
+    /// When the first and the last change since the previous regeneration happened.
+    pending: Option<(Instant, Instant)>,
+}
+
+impl Debouncer {
+    pub(crate) fn new(delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            delay,
+            max_delay,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn on_change(&mut self, now: Instant) {
+        self.pending = Some(match self.pending {
+            Some((first, _)) => (first, now),
+            None => (now, now),
+        });
+    }
+
+    /// When the next regeneration is due, if any change is pending.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        let (first, last) = self.pending?;
+        Some(std::cmp::min(last + self.delay, first + self.max_delay))
+    }
+
+    /// Returns whether to regenerate now. If so, the pending changes are considered handled.
+    pub(crate) fn should_regenerate(&mut self, now: Instant) -> bool {
+        match self.deadline() {
+            Some(deadline) if now >= deadline => {
+                self.pending = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::AccessKind;
+    use notify::event::CreateKind;
+    use notify::event::DataChange;
+    use notify::event::MetadataKind;
+    use notify::event::RenameMode;
+
+    use super::*;
+    use crate::json_project::BuckExtensions;
+    use crate::json_project::Crate;
+    use crate::json_project::Sysroot;
+    use crate::target::Target;
+
+    const DELAY: Duration = Duration::from_millis(500);
+    const MAX_DELAY: Duration = Duration::from_secs(10);
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_change_kind() {
+        assert_eq!(
+            ChangeKind::None,
+            ChangeKind::new(&EventKind::Access(AccessKind::Any))
+        );
+        assert_eq!(
+            ChangeKind::None,
+            ChangeKind::new(&EventKind::Modify(ModifyKind::Metadata(
+                MetadataKind::WriteTime
+            )))
+        );
+        assert_eq!(
+            ChangeKind::Contents,
+            ChangeKind::new(&EventKind::Modify(ModifyKind::Data(DataChange::Content)))
+        );
+        assert_eq!(
+            ChangeKind::Existence,
+            ChangeKind::new(&EventKind::Create(CreateKind::File))
+        );
+        assert_eq!(
+            ChangeKind::Existence,
+            ChangeKind::new(&EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+        );
+    }
+
+    #[test]
+    fn test_is_relevant() {
+        for path in ["foo/BUCK", "foo/TARGETS", "foo/BUCK.v2", "defs/rust.bzl"] {
+            assert!(
+                is_relevant(Path::new(path), ChangeKind::Contents),
+                "{}",
+                path
+            );
+            assert!(
+                is_relevant(Path::new(path), ChangeKind::Existence),
+                "{}",
+                path
+            );
+            assert!(!is_relevant(Path::new(path), ChangeKind::None), "{}", path);
+        }
+
+        // Editing a source doesn't change the project, adding one does.
+        assert!(!is_relevant(
+            Path::new("foo/src/lib.rs"),
+            ChangeKind::Contents
+        ));
+        assert!(is_relevant(
+            Path::new("foo/src/lib.rs"),
+            ChangeKind::Existence
+        ));
+
+        assert!(!is_relevant(
+            Path::new("foo/rust-project.json"),
+            ChangeKind::Existence
+        ));
+        assert!(!is_relevant(
+            Path::new("foo/README.md"),
+            ChangeKind::Contents
+        ));
+        assert!(!is_relevant(
+            Path::new("root/buck-out/v2/gen/foo/BUCK"),
+            ChangeKind::Existence
+        ));
+    }
+
+    #[test]
+    fn test_debounce_waits_for_quiet() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(DELAY, MAX_DELAY);
+        assert_eq!(None, debouncer.deadline());
+        assert!(!debouncer.should_regenerate(start));
+
+        debouncer.on_change(start);
+        debouncer.on_change(start + ms(100));
+        debouncer.on_change(start + ms(300));
+        assert_eq!(Some(start + ms(800)), debouncer.deadline());
+        assert!(!debouncer.should_regenerate(start + ms(500)));
+        assert!(!debouncer.should_regenerate(start + ms(799)));
+        assert!(debouncer.should_regenerate(start + ms(800)));
+
+        // The changes were handled.
+        assert_eq!(None, debouncer.deadline());
+        assert!(!debouncer.should_regenerate(start + ms(2000)));
+    }
+
+    #[test]
+    fn test_debounce_max_delay() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(DELAY, MAX_DELAY);
+
+        let mut now = start;
+        let mut regenerated_at = None;
+        while now < start + 2 * MAX_DELAY {
+            debouncer.on_change(now);
+            if debouncer.should_regenerate(now) {
+                regenerated_at = Some(now);
+                break;
+            }
+            now += ms(100);
+        }
+        // Changes that never stop still regenerate eventually.
+        assert_eq!(Some(start + MAX_DELAY), regenerated_at);
+    }
+
+    fn krate(build_file: &str, is_workspace_member: bool) -> Crate {
+        Crate {
+            buck_extensions: BuckExtensions {
+                build_file: PathBuf::from(build_file),
+                label: Target::new("cell//foo:bar"),
+            },
+            is_workspace_member,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_watched_dirs() {
+        let project = JsonProject {
+            sysroot: Sysroot {
+                sysroot: PathBuf::from("/sysroot"),
+                sysroot_src: None,
+            },
+            crates: vec![
+                krate("/repo/foo/BUCK", true),
+                krate("/repo/foo/bar/BUCK", true),
+                krate("/repo/foobar/BUCK", true),
+                krate("baz/BUCK", true),
+                krate("/repo/foo/BUCK", true),
+                krate("/repo/third-party/BUCK", false),
+            ],
+            generated: String::new(),
+        };
+        assert_eq!(
+            vec![
+                PathBuf::from("/repo/baz"),
+                PathBuf::from("/repo/foo"),
+                PathBuf::from("/repo/foobar"),
+            ],
+            watched_dirs(&project, Path::new("/repo"))
+        );
+    }
+}
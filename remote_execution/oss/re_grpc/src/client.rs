@@ -207,6 +207,8 @@ pub struct RECapabilities {
     max_msg_size: usize,
     /// Does the remote server support execution.
     exec_enabled: bool,
+    /// The `DigestFunction` the remote server executes with. `UNKNOWN` (0) if it didn't say.
+    digest_function: i32,
 }
 
 /// Contains runtime options for the remote execution client as set under `buck2_re_client`
@@ -305,6 +307,7 @@ impl REClientBuilder {
             RECapabilities {
                 exec_enabled: true,
                 max_msg_size: DEFAULT_MAX_MSG_SIZE,
+                digest_function: 0,
             }
         };
 
@@ -340,6 +343,7 @@ impl REClientBuilder {
         // with enough room for headers.
         let mut max_msg_size = DEFAULT_MAX_MSG_SIZE;
         let mut exec_enabled = true;
+        let mut digest_function = 0;
 
         if let Some(cache_cap) = resp.cache_capabilities {
             let size = cache_cap.max_batch_total_size_bytes as usize;
@@ -351,11 +355,13 @@ impl REClientBuilder {
 
         if let Some(exec_cap) = resp.execution_capabilities {
             exec_enabled = exec_cap.exec_enabled;
+            digest_function = exec_cap.digest_function;
         }
 
         Ok(RECapabilities {
             max_msg_size,
             exec_enabled,
+            digest_function,
         })
     }
 }
@@ -818,6 +824,11 @@ impl REClient {
     pub fn get_experiment_name(&self) -> anyhow::Result<Option<String>> {
         Ok(None)
     }
+
+    /// The `DigestFunction` of the digests in the action results returned by the server.
+    pub fn get_digest_function(&self) -> i32 {
+        self.capabilities.digest_function
+    }
 }
 
 fn convert_action_result(action_result: ActionResult) -> anyhow::Result<TActionResult2> {
@@ -8,6 +8,7 @@
  */
 
 pub use re_grpc_proto::build::bazel::remote::execution::v2::command::EnvironmentVariable;
+pub use re_grpc_proto::build::bazel::remote::execution::v2::digest_function;
 pub use re_grpc_proto::build::bazel::remote::execution::v2::platform::Property;
 pub use re_grpc_proto::build::bazel::remote::execution::v2::Action;
 pub use re_grpc_proto::build::bazel::remote::execution::v2::Command;
@@ -0,0 +1,52 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+# pyre-strict
+
+import json
+
+import pytest
+
+from buck2.tests.e2e_util.api.buck import Buck
+from buck2.tests.e2e_util.buck_workspace import buck_test
+
+# Every aquery method that takes targets accepts `keep_incompatible`.
+METHODS = ["deps", "all_actions", "all_outputs", "all_inputs", "attrfilter"]
+
+# Each target has a single action, writing its default output. `all_inputs` ignores target
+# literals, so it finds no action.
+EXPECTED_OWNERS = {
+    "deps": ["root//:compatible"],
+    "all_actions": ["root//:compatible"],
+    "all_outputs": ["root//:compatible"],
+    "all_inputs": [],
+    "attrfilter": ["root//:compatible"],
+}
+
+
+@buck_test(inplace=False)
+@pytest.mark.parametrize("method", METHODS)
+async def test_aquery_incompatible_reported_on_stderr(buck: Buck, method: str) -> None:
+    result = await buck.bxl("//aquery.bxl:main", "--", "--method", method)
+
+    assert json.loads(result.stdout) == {"owners": EXPECTED_OWNERS[method]}
+    assert "Skipped 1 incompatible targets" in result.stderr
+    assert "root//:incompatible" in result.stderr
+
+
+@buck_test(inplace=False)
+@pytest.mark.parametrize("method", METHODS)
+async def test_aquery_keep_incompatible(buck: Buck, method: str) -> None:
+    result = await buck.bxl(
+        "//aquery.bxl:main", "--", "--method", method, "--keep", "true"
+    )
+
+    assert json.loads(result.stdout) == {
+        "incompatible_targets": ["root//:incompatible"],
+        "owners": EXPECTED_OWNERS[method],
+    }
+    assert "root//:incompatible" not in result.stderr
@@ -0,0 +1,58 @@
+def _constraint_setting_impl(ctx):
+    return [DefaultInfo(), ConstraintSettingInfo(label = ctx.label.raw_target())]
+
+constraint_setting = rule(
+    impl = _constraint_setting_impl,
+    is_configuration_rule = True,
+    attrs = {},
+)
+
+def _constraint_value_impl(ctx):
+    constraint_value = ConstraintValueInfo(
+        setting = ctx.attrs.constraint_setting[ConstraintSettingInfo],
+        label = ctx.label.raw_target(),
+    )
+    return [
+        DefaultInfo(),
+        constraint_value,
+        ConfigurationInfo(constraints = {
+            constraint_value.setting.label: constraint_value,
+        }, values = {}),
+    ]
+
+constraint_value = rule(
+    impl = _constraint_value_impl,
+    is_configuration_rule = True,
+    attrs = {
+        "constraint_setting": attrs.dep(providers = [ConstraintSettingInfo]),
+    },
+)
+
+def _platform_impl(ctx):
+    constraint_value = ctx.attrs.constraint_value[ConstraintValueInfo]
+    return [
+        DefaultInfo(),
+        PlatformInfo(
+            label = str(ctx.label.raw_target()),
+            configuration = ConfigurationInfo(constraints = {
+                constraint_value.setting.label: constraint_value,
+            }, values = {}),
+        ),
+    ]
+
+platform = rule(
+    impl = _platform_impl,
+    is_configuration_rule = True,
+    attrs = {
+        "constraint_value": attrs.dep(providers = [ConstraintValueInfo]),
+    },
+)
+
+def _write_impl(ctx):
+    out = ctx.actions.write(ctx.label.name + ".txt", ctx.label.name)
+    return [DefaultInfo(default_output = out)]
+
+write = rule(
+    impl = _write_impl,
+    attrs = {},
+)
@@ -0,0 +1,48 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+# pyre-strict
+
+import asyncio
+import json
+
+from buck2.tests.e2e_util.api.buck import Buck
+from buck2.tests.e2e_util.buck_workspace import buck_test
+
+BEFORE = {"cquery": ["root//:a"], "uquery": ["root//:a"]}
+AFTER = {"cquery": ["root//:a", "root//:b"], "uquery": ["root//:a", "root//:b"]}
+
+
+def _add_target(buck: Buck) -> None:
+    with open(buck.cwd / "TARGETS.fixture", "a") as f:
+        f.write('\nstub(\n    name = "b",\n)\n')
+
+
+@buck_test(inplace=False)
+async def test_queries_of_an_evaluation_agree(buck: Buck) -> None:
+    result = await buck.bxl("//snapshot.bxl:main", "--", "--rounds", "3")
+    assert json.loads(result.stdout) == BEFORE
+
+
+@buck_test(inplace=False)
+async def test_file_change_during_evaluation(buck: Buck) -> None:
+    # Start the daemon so the evaluation below begins right away.
+    await buck.uquery("root//...")
+
+    # The script fails if any of its queries sees a different universe than the first one.
+    evaluation = asyncio.ensure_future(
+        buck.bxl("//snapshot.bxl:main", "--", "--rounds", "50")
+    )
+    _add_target(buck)
+    await buck.uquery("root//...")
+
+    result = await evaluation
+    assert json.loads(result.stdout) in (BEFORE, AFTER)
+
+    # The next evaluation sees the change.
+    result = await buck.bxl("//snapshot.bxl:main")
+    assert json.loads(result.stdout) == AFTER
@@ -0,0 +1,66 @@
+# Copyright (c) Meta Platforms, Inc. and affiliates.
+#
+# This source code is licensed under both the MIT license found in the
+# LICENSE-MIT file in the root directory of this source tree and the Apache
+# License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+# of this source tree.
+
+# pyre-strict
+
+from typing import List
+
+import pytest
+
+from buck2.tests.e2e_util.api.buck import Buck
+from buck2.tests.e2e_util.api.buck_result import BuckResult
+from buck2.tests.e2e_util.asserts import expect_failure
+from buck2.tests.e2e_util.buck_workspace import buck_test
+
+LIB = [
+    "root//lib:private",
+    "root//lib:public",
+    "root//lib:restricted",
+    "root//lib:sibling",
+]
+
+EXPECTED = {
+    "root//lib:public": sorted(
+        LIB + ["root//app:app", "root//app/sub:sub", "root//other:other"]
+    ),
+    # Targets in the same package can always depend on it.
+    "root//lib:private": LIB,
+    "root//lib:restricted": sorted(LIB + ["root//app:app", "root//app/sub:sub"]),
+}
+
+
+def _targets(result: BuckResult) -> List[str]:
+    # cquery prints the configuration after the label.
+    return sorted(line.split(" ")[0] for line in result.stdout.splitlines())
+
+
+@buck_test(inplace=False)
+@pytest.mark.parametrize("target", list(EXPECTED))
+async def test_uquery_visible_to(buck: Buck, target: str) -> None:
+    result = await buck.uquery(f"visible_to({target}, root//...)")
+    assert _targets(result) == EXPECTED[target]
+
+
+@buck_test(inplace=False)
+@pytest.mark.parametrize("target", list(EXPECTED))
+async def test_cquery_visible_to(buck: Buck, target: str) -> None:
+    result = await buck.cquery(f"visible_to({target}, root//...)")
+    assert _targets(result) == EXPECTED[target]
+
+
+@buck_test(inplace=False)
+async def test_visible_to_only_filters_candidates(buck: Buck) -> None:
+    result = await buck.uquery("visible_to(root//lib:restricted, root//other/...)")
+    assert _targets(result) == []
+
+
+@buck_test(inplace=False)
+async def test_visible_to_requires_single_target(buck: Buck) -> None:
+    await expect_failure(
+        buck.uquery("visible_to(root//lib:, root//...)"),
+        stderr_regex="function `visible_to` expects a single target, got 4",
+    )